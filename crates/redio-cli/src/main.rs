@@ -0,0 +1,1733 @@
+//! Command-line tool for merchants operating redio-contract pools.
+//!
+//! Signers are resolved the same way as the Solana CLI, so `--keypair` takes
+//! a keypair file or a hardware wallet URL such as `usb://ledger`. The RPC URL
+//! and default keypair fall back to the Solana CLI config.
+
+use std::{error::Error, fs, path::PathBuf, rc::Rc, str::FromStr};
+
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand};
+use redio_client::{
+    args::{
+        validate_pool_id, validate_ref_id, AddAffiliatesBatchArgs, AffiliateImport,
+        CampaignSaleArgs, InitializePoolArgs, ProcessSaleArgs, Validate,
+    },
+    instructions, pda, ConfigChange, FeeTier, PayoutSplit, RedioClient, RewardMode,
+};
+use solana_clap_v3_utils::keypair::signer_from_path;
+use solana_remote_wallet::remote_wallet::RemoteWalletManager;
+use solana_sdk::{
+    hash::hash,
+    instruction::Instruction,
+    pubkey::Pubkey,
+    signature::{Signature, Signer},
+    transaction::Transaction,
+};
+use solana_system_interface::instruction as system_instruction;
+
+type CliResult<T> = Result<T, Box<dyn Error>>;
+
+/// Affiliates per `affiliate import` transaction; with three accounts each,
+/// more wouldn't fit a legacy transaction
+const IMPORT_BATCH_SIZE: usize = 4;
+
+#[derive(Parser)]
+#[clap(
+    name = "redio-cli",
+    version,
+    about = "Operate redio-contract merchant pools"
+)]
+struct Cli {
+    /// RPC URL, defaults to the Solana CLI config
+    #[clap(long, short = 'u', global = true)]
+    url: Option<String>,
+
+    /// Keypair file or hardware wallet URL (e.g. usb://ledger)
+    #[clap(long, short = 'k', global = true)]
+    keypair: Option<String>,
+
+    /// Keypair that pays transaction fees and rent, so the merchant key only
+    /// authorizes. Defaults to `--keypair`
+    #[clap(long, global = true)]
+    fee_payer: Option<String>,
+
+    /// Durable nonce account to use instead of a recent blockhash, advanced
+    /// by `--keypair` as its authority
+    #[clap(long, global = true)]
+    nonce: Option<Pubkey>,
+
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Create and fund merchant pools
+    #[clap(subcommand)]
+    Pool(PoolCommand),
+    /// Manage a pool's affiliates
+    #[clap(subcommand)]
+    Affiliate(AffiliateCommand),
+    /// Record sales and pay commissions
+    #[clap(subcommand)]
+    Sale(SaleCommand),
+    /// Show pool statistics, escrow balance and affiliates
+    Stats {
+        #[clap(long)]
+        pool: Pubkey,
+    },
+    /// Program-wide controls for the config admin
+    #[clap(subcommand)]
+    Admin(AdminCommand),
+}
+
+#[derive(Subcommand)]
+enum AdminCommand {
+    /// Create the program config; the signer must be the upgrade authority
+    Init,
+    /// Halt everything but escrow withdrawals and commission claims
+    Pause,
+    /// Resume normal operation
+    Unpause,
+    /// Lift an affiliate flag after review, re-enabling withdrawals
+    ClearFlag {
+        #[clap(long)]
+        pool: Pubkey,
+        /// The review found for the affiliates; counts a dispute lost
+        /// against the merchant's reputation
+        #[clap(long)]
+        upheld: bool,
+    },
+    /// Replace the protocol fee schedule; no tiers charges no fee
+    FeeTiers {
+        /// `MIN_MONTHLY_VOLUME:BPS`, ascending from zero volume
+        #[clap(long = "tier", value_parser = parse_fee_tier)]
+        tiers: Vec<FeeTier>,
+    },
+    /// Send collected protocol fees to this wallet's token accounts
+    SetTreasury {
+        #[clap(long)]
+        treasury: Pubkey,
+    },
+    /// Bound the commission rates pools may be created with or changed to
+    RateBand {
+        #[clap(long)]
+        min_bps: u16,
+        #[clap(long)]
+        max_bps: u16,
+    },
+    /// Sweep a pool's accrued protocol fees to the treasury's ATA
+    CollectFees {
+        #[clap(long)]
+        pool: Pubkey,
+    },
+    /// Waive the protocol fee for a pool, or end the waiver with --revoke
+    FeeExempt {
+        #[clap(long)]
+        pool: Pubkey,
+        #[clap(long)]
+        revoke: bool,
+    },
+    /// Give notice that a long-inactive pool's escrow will be recovered to
+    /// its merchant
+    NoticeAbandoned {
+        #[clap(long)]
+        pool: Pubkey,
+    },
+    /// Send a noticed, abandoned pool's unreserved escrow in every mint to
+    /// its merchant
+    RecoverAbandoned {
+        #[clap(long)]
+        pool: Pubkey,
+    },
+    /// Put fee schedule, rate band and treasury changes behind a timelock,
+    /// recording the hash of the program build you intend to run
+    Guard {
+        /// Program binary (`.so`) whose hash to record
+        #[clap(long)]
+        build: PathBuf,
+        #[clap(long)]
+        timelock_secs: i64,
+    },
+    /// Queue recording the hash of the next program build to deploy
+    ProgramHash {
+        #[clap(long)]
+        build: PathBuf,
+    },
+    /// Queue a new timelock for config changes
+    Timelock {
+        #[clap(long)]
+        secs: i64,
+    },
+    /// Apply the queued config change once its timelock has passed
+    ExecuteChange,
+    /// Drop the queued config change
+    CancelChange,
+}
+
+#[derive(Subcommand)]
+enum PoolCommand {
+    /// Create a pool and fund its escrow
+    Init {
+        #[clap(long)]
+        pool_id: String,
+        /// Settlement mint, e.g. USDC
+        #[clap(long)]
+        mint: Pubkey,
+        /// Commission rate in basis points
+        #[clap(long)]
+        commission_rate: u16,
+        /// Initial escrow deposit in base units
+        #[clap(long)]
+        deposit: u64,
+    },
+    /// Create a pool with the settings of one of your pools, without its funds
+    Clone {
+        /// Pool to copy settings from
+        #[clap(long)]
+        source: Pubkey,
+        #[clap(long)]
+        pool_id: String,
+    },
+    /// Deposit into a pool's escrow
+    Deposit {
+        #[clap(long)]
+        pool: Pubkey,
+        /// Amount in base units
+        #[clap(long)]
+        amount: u64,
+    },
+    /// Deposit from the merchant's token account as its approved delegate
+    DepositDelegated {
+        #[clap(long)]
+        pool: Pubkey,
+        /// Amount in base units
+        #[clap(long)]
+        amount: u64,
+    },
+    /// Fund any pool's escrow from your own token account
+    Donate {
+        #[clap(long)]
+        pool: Pubkey,
+        /// Amount in base units
+        #[clap(long)]
+        amount: u64,
+    },
+    /// Execute the pool's scheduled escrow top-up if it is due
+    Topup {
+        #[clap(long)]
+        pool: Pubkey,
+    },
+    /// Record the interest an interest-bearing mint has added to the escrow
+    SyncInterest {
+        #[clap(long)]
+        pool: Pubkey,
+    },
+    /// Archive the current reporting period's stats and start a new one
+    RotatePeriod {
+        #[clap(long)]
+        pool: Pubkey,
+    },
+    /// Have the pool's sales reject transactions with token instructions on
+    /// their escrow or commission accounts
+    GuardSales {
+        #[clap(long)]
+        pool: Pubkey,
+        /// Stop guarding sales
+        #[clap(long)]
+        disable: bool,
+    },
+    /// Only pay commissions on sales whose buyer payment is proven in the
+    /// same transaction
+    RequirePaymentProof {
+        #[clap(long)]
+        pool: Pubkey,
+        /// Accept sales without a payment proof again
+        #[clap(long)]
+        disable: bool,
+    },
+    /// Let the pool's affiliates opt into confidential payouts; the escrow
+    /// must already be configured for confidential transfers
+    ConfidentialPayouts {
+        #[clap(long)]
+        pool: Pubkey,
+        /// Stop offering confidential payouts
+        #[clap(long)]
+        disable: bool,
+    },
+    /// Move escrow between two of the merchant's pools
+    Transfer {
+        #[clap(long)]
+        from: Pubkey,
+        #[clap(long)]
+        to: Pubkey,
+        /// Amount in base units of the source pool's mint
+        #[clap(long)]
+        amount: u64,
+        /// Last slot the transfer may execute in
+        #[clap(long)]
+        expiry_slot: Option<u64>,
+        /// Keypair of the source pool's withdrawal co-signer, needed above
+        /// its co-sign threshold
+        #[clap(long)]
+        cosigner: Option<String>,
+    },
+    /// Move the pool's escrow to a fresh escrow authority under the next
+    /// seed version
+    MigrateEscrow {
+        #[clap(long)]
+        pool: Pubkey,
+    },
+    /// Create or update a regional partition with its own commission budget
+    /// and rate modifier, paid from the pool's escrow
+    Region {
+        #[clap(long)]
+        pool: Pubkey,
+        /// Two-letter region code, e.g. DE
+        #[clap(long, value_parser = parse_region)]
+        region: [u8; 2],
+        /// Most commission the region may pay in total, in base units
+        #[clap(long)]
+        budget: u64,
+        /// Added to the rate of the region's sales, in basis points
+        #[clap(long, default_value_t = 0, allow_hyphen_values = true)]
+        rate_modifier_bps: i16,
+    },
+    /// Replace the keys that can jointly recover the pool to a new merchant
+    /// key; no guardians disables recovery
+    Guardians {
+        #[clap(long)]
+        pool: Pubkey,
+        /// Guardian wallet, repeatable
+        #[clap(long = "guardian")]
+        guardians: Vec<Pubkey>,
+        /// Guardian signatures a recovery needs
+        #[clap(long, default_value_t = 0)]
+        threshold: u8,
+    },
+    /// Reward affiliates with store credit redeemed by a storefront program,
+    /// or with token commissions again when no rate is given
+    RewardMode {
+        #[clap(long)]
+        pool: Pubkey,
+        /// Store credit granted per unit of commission, in basis points
+        #[clap(long, requires = "storefront")]
+        store_credit_rate_bps: Option<u16>,
+        /// Program allowed to redeem the credit via CPI
+        #[clap(long)]
+        storefront: Option<Pubkey>,
+    },
+    /// Veto the guardians' pending recovery of the pool to a new merchant key
+    VetoRecovery {
+        #[clap(long)]
+        pool: Pubkey,
+    },
+}
+
+#[derive(Subcommand)]
+enum AffiliateCommand {
+    /// Register an affiliate wallet in a pool
+    Add {
+        #[clap(long)]
+        pool: Pubkey,
+        #[clap(long)]
+        wallet: Pubkey,
+        #[clap(long)]
+        ref_id: String,
+        /// Wallet of the affiliate that referred this one
+        #[clap(long)]
+        referrer: Option<Pubkey>,
+    },
+    /// Add affiliates in bulk from a file of `wallet,ref_id` lines
+    Import {
+        #[clap(long)]
+        pool: Pubkey,
+        #[clap(long)]
+        file: PathBuf,
+    },
+    /// Consent, as the signing affiliate wallet, to the account a merchant
+    /// created for it; sales don't pay it until then
+    Activate {
+        #[clap(long)]
+        pool: Pubkey,
+    },
+    /// Have the signing affiliate's commissions paid to a token account other
+    /// than its ATA, such as an exchange deposit address
+    SetPayoutAccount {
+        #[clap(long)]
+        pool: Pubkey,
+        /// Token account in the pool's mint; omit to pay the ATA again
+        #[clap(long)]
+        account: Option<Pubkey>,
+    },
+    /// Have the signing affiliate's commissions accrue and be paid out as
+    /// compressed tokens, which need no token account
+    CompressedPayouts {
+        #[clap(long)]
+        pool: Pubkey,
+        /// Go back to token account payouts
+        #[clap(long)]
+        disable: bool,
+    },
+    /// Have the signing affiliate's commissions accrue and be paid into its
+    /// token account's encrypted balance by confidential transfer
+    ConfidentialPayouts {
+        #[clap(long)]
+        pool: Pubkey,
+        /// Hex ElGamal public key the wallet's token account is configured
+        /// with; omit to go back to public payouts
+        #[clap(long, value_parser = parse_elgamal_pubkey)]
+        elgamal_pubkey: Option<[u8; 32]>,
+    },
+    /// Split the signing affiliate's payouts between token accounts, e.g.
+    /// 80% personal and 20% to a tax wallet
+    SplitPayouts {
+        #[clap(long)]
+        pool: Pubkey,
+        /// `TOKEN_ACCOUNT:BPS` in the pool's mint, repeatable, totalling
+        /// 10000; omit to pay the payout account whole again
+        #[clap(long = "split", value_parser = parse_payout_split)]
+        splits: Vec<PayoutSplit>,
+    },
+    /// Replace an affiliate's tag bits, which campaigns can require; signed
+    /// by the merchant
+    Tag {
+        #[clap(long)]
+        pool: Pubkey,
+        #[clap(long)]
+        wallet: Pubkey,
+        /// Bitfield of merchant-defined labels, e.g. 1 for VIP
+        #[clap(long)]
+        tags: u32,
+    },
+    /// Checkpoint an affiliate's stats for a period; signed by the merchant
+    /// or the pool's processor
+    Snapshot {
+        #[clap(long)]
+        pool: Pubkey,
+        #[clap(long)]
+        wallet: Pubkey,
+        /// Period the snapshot is keyed by, e.g. 202610
+        #[clap(long)]
+        period: u32,
+    },
+    /// Submit the signing affiliate's application for the pool's approvers
+    Apply {
+        #[clap(long)]
+        pool: Pubkey,
+        /// Website or profile describing the affiliate's audience
+        #[clap(long)]
+        profile_uri: String,
+        /// Contact details shared with the merchant; only their hash is stored
+        #[clap(long)]
+        contact: String,
+    },
+    /// Leave a pool as the signing affiliate
+    Leave {
+        #[clap(long)]
+        pool: Pubkey,
+        /// Also close the affiliate account; requires nothing left to claim
+        #[clap(long)]
+        close: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum SaleCommand {
+    /// Process a sale and pay the affiliate's commission
+    Process {
+        #[clap(long)]
+        pool: Pubkey,
+        /// Affiliate wallet
+        #[clap(long, required_unless_present = "ref_id", conflicts_with = "ref_id")]
+        affiliate: Option<Pubkey>,
+        /// Attribute the sale through the affiliate's ref_id instead,
+        /// counting a redemption of it
+        #[clap(long)]
+        ref_id: Option<String>,
+        /// Sale amount in base units
+        #[clap(long)]
+        amount: u64,
+        /// Merchant order id the sale receipt is recorded under
+        #[clap(long)]
+        order_id: String,
+        /// Negotiated commission rate in basis points, within the pool's bounds
+        #[clap(long)]
+        commission_override_bps: Option<u16>,
+        /// Free-form order reference stored on the receipt (max 128 chars)
+        #[clap(long)]
+        memo: Option<String>,
+        /// Succeed without paying again if the order was already processed
+        #[clap(long)]
+        idempotent: bool,
+        /// Credit the sale to this campaign, at its rate and against its budget
+        #[clap(long, conflicts_with = "commission_override_bps")]
+        campaign: Option<String>,
+        /// Process the sale in this region, with its rate modifier and
+        /// against its budget
+        #[clap(long, value_parser = parse_region, conflicts_with = "campaign")]
+        region: Option<[u8; 2]>,
+        /// The signer is a session key issued for the pool
+        #[clap(long)]
+        session_key: bool,
+    },
+    /// Pay out what affiliates opted into compressed payouts have accrued
+    PayOutCompressed {
+        #[clap(long)]
+        pool: Pubkey,
+        /// Light state tree the compressed token accounts are appended to
+        #[clap(long)]
+        state_tree: Pubkey,
+        /// Affiliate wallet to pay, repeatable up to 8 times
+        #[clap(long = "affiliate", required = true)]
+        affiliates: Vec<Pubkey>,
+    },
+    /// Release held-back commissions whose holdback period has passed
+    Release {
+        #[clap(long)]
+        pool: Pubkey,
+        /// Order id of a sale to release, repeatable; ones not yet due are skipped
+        #[clap(long = "order-id", required = true)]
+        order_ids: Vec<String>,
+    },
+}
+
+struct Context<'a> {
+    client: RedioClient,
+    matches: &'a ArgMatches,
+    keypair_path: String,
+    fee_payer_path: Option<String>,
+    nonce: Option<Pubkey>,
+}
+
+impl Context<'_> {
+    fn signer(&self) -> CliResult<Box<dyn Signer>> {
+        let mut wallet_manager: Option<Rc<RemoteWalletManager>> = None;
+        signer_from_path(
+            self.matches,
+            &self.keypair_path,
+            "keypair",
+            &mut wallet_manager,
+        )
+    }
+
+    /// Separate fee and rent payer, if `--fee-payer` was given
+    fn fee_payer(&self) -> CliResult<Option<Box<dyn Signer>>> {
+        let Some(path) = &self.fee_payer_path else {
+            return Ok(None);
+        };
+        let mut wallet_manager: Option<Rc<RemoteWalletManager>> = None;
+        signer_from_path(self.matches, path, "fee_payer", &mut wallet_manager).map(Some)
+    }
+
+    /// Pool's withdrawal co-signer, if `--cosigner` was given
+    fn cosigner(&self, path: Option<&str>) -> CliResult<Option<Box<dyn Signer>>> {
+        let Some(path) = path else {
+            return Ok(None);
+        };
+        let mut wallet_manager: Option<Rc<RemoteWalletManager>> = None;
+        signer_from_path(self.matches, path, "cosigner", &mut wallet_manager).map(Some)
+    }
+
+    /// Token program owning `mint`, so Token-2022 mints work transparently
+    fn token_program_for(&self, mint: &Pubkey) -> CliResult<Pubkey> {
+        Ok(self.client.rpc().get_account(mint)?.owner)
+    }
+
+    fn send(&self, signer: &dyn Signer, instruction: Instruction) -> CliResult<Signature> {
+        self.send_with_payer(signer, None, instruction)
+    }
+
+    /// Sends `instruction` with `fee_payer` covering fees, falling back to
+    /// `signer`. With `--nonce` the transaction advances that nonce account
+    /// first and is signed against its durable blockhash
+    fn send_with_payer(
+        &self,
+        signer: &dyn Signer,
+        fee_payer: Option<&dyn Signer>,
+        instruction: Instruction,
+    ) -> CliResult<Signature> {
+        self.send_with_cosigner(signer, fee_payer, None, instruction)
+    }
+
+    /// Like `send_with_payer`, with `cosigner` signing as well
+    fn send_with_cosigner(
+        &self,
+        signer: &dyn Signer,
+        fee_payer: Option<&dyn Signer>,
+        cosigner: Option<&dyn Signer>,
+        instruction: Instruction,
+    ) -> CliResult<Signature> {
+        let rpc = self.client.rpc();
+        let payer = fee_payer.unwrap_or(signer);
+        let mut signers: Vec<&dyn Signer> = vec![signer];
+        if payer.pubkey() != signer.pubkey() {
+            signers.push(payer);
+        }
+        signers.extend(cosigner);
+        let (instructions, blockhash) = match &self.nonce {
+            Some(nonce) => (
+                vec![
+                    system_instruction::advance_nonce_account(nonce, &signer.pubkey()),
+                    instruction,
+                ],
+                self.client.fetch_durable_nonce(nonce)?,
+            ),
+            None => (vec![instruction], rpc.get_latest_blockhash()?),
+        };
+        let transaction = Transaction::new_signed_with_payer(
+            &instructions,
+            Some(&payer.pubkey()),
+            &signers,
+            blockhash,
+        );
+        Ok(rpc.send_and_confirm_transaction_with_spinner(&transaction)?)
+    }
+}
+
+fn main() -> CliResult<()> {
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&matches)?;
+
+    let config = solana_cli_config::CONFIG_FILE
+        .as_ref()
+        .and_then(|path| solana_cli_config::Config::load(path).ok())
+        .unwrap_or_default();
+
+    let ctx = Context {
+        client: RedioClient::new(cli.url.unwrap_or(config.json_rpc_url)),
+        matches: &matches,
+        keypair_path: cli.keypair.unwrap_or(config.keypair_path),
+        fee_payer_path: cli.fee_payer,
+        nonce: cli.nonce,
+    };
+
+    match cli.command {
+        Command::Pool(PoolCommand::Init {
+            pool_id,
+            mint,
+            commission_rate,
+            deposit,
+        }) => {
+            let args = InitializePoolArgs {
+                pool_id,
+                commission_rate,
+                initial_deposit: deposit,
+            };
+            args.validate()?;
+            let signer = ctx.signer()?;
+            let fee_payer = ctx.fee_payer()?;
+            let rent_payer = fee_payer.as_ref().unwrap_or(&signer).pubkey();
+            let token_program = ctx.token_program_for(&mint)?;
+            let (pool, _) = pda::find_pool_address(&signer.pubkey(), &args.pool_id);
+            let pool_index = ctx.client.next_pool_index(&signer.pubkey())?;
+            let signature = ctx.send_with_payer(
+                signer.as_ref(),
+                fee_payer.as_deref(),
+                instructions::initialize_pool(
+                    &signer.pubkey(),
+                    &rent_payer,
+                    pool_index,
+                    &mint,
+                    &token_program,
+                    args,
+                ),
+            )?;
+            println!("Pool {pool} initialized: {signature}");
+        }
+        Command::Pool(PoolCommand::Clone { source, pool_id }) => {
+            validate_pool_id(&pool_id)?;
+            let signer = ctx.signer()?;
+            let fee_payer = ctx.fee_payer()?;
+            let rent_payer = fee_payer.as_ref().unwrap_or(&signer).pubkey();
+            let mint = ctx.client.fetch_pool(&source)?.usdc_mint;
+            let token_program = ctx.token_program_for(&mint)?;
+            let (pool, _) = pda::find_pool_address(&signer.pubkey(), &pool_id);
+            let pool_index = ctx.client.next_pool_index(&signer.pubkey())?;
+            let signature = ctx.send_with_payer(
+                signer.as_ref(),
+                fee_payer.as_deref(),
+                instructions::clone_pool(
+                    &source,
+                    &signer.pubkey(),
+                    &rent_payer,
+                    pool_index,
+                    &mint,
+                    &token_program,
+                    &pool_id,
+                ),
+            )?;
+            println!("Pool {pool} cloned from {source}: {signature}");
+        }
+        Command::Pool(PoolCommand::Deposit { pool, amount }) => {
+            let signer = ctx.signer()?;
+            let merchant_pool = ctx.client.fetch_pool(&pool)?;
+            let mint = merchant_pool.usdc_mint;
+            let token_program = ctx.token_program_for(&mint)?;
+            let signature = ctx.send(
+                signer.as_ref(),
+                instructions::at_escrow_version(
+                    instructions::deposit_escrow(
+                        &pool,
+                        &signer.pubkey(),
+                        &mint,
+                        &token_program,
+                        amount,
+                    ),
+                    &pool,
+                    merchant_pool.escrow_version,
+                ),
+            )?;
+            println!("Deposited {amount} into {pool}: {signature}");
+        }
+        Command::Pool(PoolCommand::DepositDelegated { pool, amount }) => {
+            let signer = ctx.signer()?;
+            let merchant_pool = ctx.client.fetch_pool(&pool)?;
+            let mint = merchant_pool.usdc_mint;
+            let token_program = ctx.token_program_for(&mint)?;
+            let signature = ctx.send(
+                signer.as_ref(),
+                instructions::at_escrow_version(
+                    instructions::deposit_escrow_delegated(
+                        &pool,
+                        &merchant_pool.merchant,
+                        &signer.pubkey(),
+                        &mint,
+                        &token_program,
+                        amount,
+                    ),
+                    &pool,
+                    merchant_pool.escrow_version,
+                ),
+            )?;
+            println!("Deposited {amount} into {pool} as delegate: {signature}");
+        }
+        Command::Pool(PoolCommand::Donate { pool, amount }) => {
+            let signer = ctx.signer()?;
+            let merchant_pool = ctx.client.fetch_pool(&pool)?;
+            let mint = merchant_pool.usdc_mint;
+            let token_program = ctx.token_program_for(&mint)?;
+            let signature = ctx.send(
+                signer.as_ref(),
+                instructions::at_escrow_version(
+                    instructions::donate_to_escrow(
+                        &pool,
+                        &signer.pubkey(),
+                        &mint,
+                        &token_program,
+                        amount,
+                    ),
+                    &pool,
+                    merchant_pool.escrow_version,
+                ),
+            )?;
+            println!("Donated {amount} to {pool}: {signature}");
+        }
+        Command::Pool(PoolCommand::Transfer {
+            from,
+            to,
+            amount,
+            expiry_slot,
+            cosigner,
+        }) => {
+            let signer = ctx.signer()?;
+            let cosigner = ctx.cosigner(cosigner.as_deref())?;
+            let from_pool = ctx.client.fetch_pool(&from)?;
+            let to_pool = ctx.client.fetch_pool(&to)?;
+            let mint = from_pool.usdc_mint;
+            let token_program = ctx.token_program_for(&mint)?;
+            let instruction = instructions::transfer_escrow(
+                &from,
+                &to,
+                &signer.pubkey(),
+                &mint,
+                &token_program,
+                amount,
+                cosigner.as_ref().map(|cosigner| cosigner.pubkey()).as_ref(),
+                expiry_slot,
+            );
+            let instruction =
+                instructions::at_escrow_version(instruction, &from, from_pool.escrow_version);
+            let signature = ctx.send_with_cosigner(
+                signer.as_ref(),
+                None,
+                cosigner.as_deref(),
+                instructions::at_escrow_version(instruction, &to, to_pool.escrow_version),
+            )?;
+            println!("Transferred {amount} from {from} to {to}: {signature}");
+        }
+        Command::Pool(PoolCommand::SyncInterest { pool }) => {
+            let signer = ctx.signer()?;
+            let merchant_pool = ctx.client.fetch_pool(&pool)?;
+            let mint = merchant_pool.usdc_mint;
+            let token_program = ctx.token_program_for(&mint)?;
+            let signature = ctx.send(
+                signer.as_ref(),
+                instructions::at_escrow_version(
+                    instructions::sync_interest(&pool, &mint, &token_program),
+                    &pool,
+                    merchant_pool.escrow_version,
+                ),
+            )?;
+            let interest_earned = ctx.client.fetch_pool(&pool)?.interest_earned;
+            println!("Escrow interest of {pool} synced, {interest_earned} earned: {signature}");
+        }
+        Command::Pool(PoolCommand::MigrateEscrow { pool }) => {
+            let signer = ctx.signer()?;
+            let fee_payer = ctx.fee_payer()?;
+            let rent_payer = fee_payer.as_ref().unwrap_or(&signer).pubkey();
+            let merchant_pool = ctx.client.fetch_pool(&pool)?;
+            let mint = merchant_pool.usdc_mint;
+            let token_program = ctx.token_program_for(&mint)?;
+            let new_version = merchant_pool
+                .escrow_version
+                .checked_add(1)
+                .ok_or("pool is on the last escrow seed version")?;
+            let signature = ctx.send_with_payer(
+                signer.as_ref(),
+                fee_payer.as_deref(),
+                instructions::migrate_escrow_authority(
+                    &pool,
+                    &signer.pubkey(),
+                    &rent_payer,
+                    &mint,
+                    &token_program,
+                    merchant_pool.settlement_mints(),
+                    merchant_pool.escrow_version,
+                    new_version,
+                ),
+            )?;
+            let (escrow_authority, _) = pda::find_versioned_escrow_authority(&pool, new_version);
+            println!("Escrow of {pool} moved to {escrow_authority}: {signature}");
+        }
+        Command::Pool(PoolCommand::Region {
+            pool,
+            region,
+            budget,
+            rate_modifier_bps,
+        }) => {
+            let signer = ctx.signer()?;
+            let fee_payer = ctx.fee_payer()?;
+            let rent_payer = fee_payer.as_ref().unwrap_or(&signer).pubkey();
+            let instruction = match ctx.client.fetch_region(&pool, &region)? {
+                Some(_) => instructions::update_region(
+                    &pool,
+                    &signer.pubkey(),
+                    &region,
+                    budget,
+                    rate_modifier_bps,
+                ),
+                None => instructions::create_region(
+                    &pool,
+                    &signer.pubkey(),
+                    &rent_payer,
+                    region,
+                    budget,
+                    rate_modifier_bps,
+                ),
+            };
+            let signature =
+                ctx.send_with_payer(signer.as_ref(), fee_payer.as_deref(), instruction)?;
+            println!(
+                "Region {} of {pool} budgeted at {budget}: {signature}",
+                String::from_utf8_lossy(&region)
+            );
+        }
+        Command::Pool(PoolCommand::Guardians {
+            pool,
+            guardians,
+            threshold,
+        }) => {
+            let signer = ctx.signer()?;
+            let count = guardians.len();
+            let signature = ctx.send(
+                signer.as_ref(),
+                instructions::set_guardians(&pool, &signer.pubkey(), guardians, threshold),
+            )?;
+            println!("Guardians of {pool} set to {threshold} of {count}: {signature}");
+        }
+        Command::Pool(PoolCommand::RewardMode {
+            pool,
+            store_credit_rate_bps,
+            storefront,
+        }) => {
+            let signer = ctx.signer()?;
+            let reward_mode = match store_credit_rate_bps {
+                Some(_) => RewardMode::StoreCredit,
+                None => RewardMode::Token,
+            };
+            let signature = ctx.send(
+                signer.as_ref(),
+                instructions::set_reward_mode(
+                    &pool,
+                    &signer.pubkey(),
+                    reward_mode,
+                    store_credit_rate_bps.unwrap_or(0),
+                    &storefront.unwrap_or_default(),
+                ),
+            )?;
+            println!("Pool {pool} now rewards in {reward_mode:?}: {signature}");
+        }
+        Command::Pool(PoolCommand::VetoRecovery { pool }) => {
+            let signer = ctx.signer()?;
+            let signature = ctx.send(
+                signer.as_ref(),
+                instructions::veto_merchant_recovery(&pool, &signer.pubkey()),
+            )?;
+            println!("Merchant recovery of {pool} vetoed: {signature}");
+        }
+        Command::Pool(PoolCommand::RotatePeriod { pool }) => {
+            let signer = ctx.signer()?;
+            let fee_payer = ctx.fee_payer()?;
+            let rent_payer = fee_payer.as_ref().unwrap_or(&signer).pubkey();
+            let period_index = ctx.client.fetch_pool(&pool)?.period_index;
+            let signature = ctx.send_with_payer(
+                signer.as_ref(),
+                fee_payer.as_deref(),
+                instructions::rotate_period(&pool, &signer.pubkey(), &rent_payer, period_index),
+            )?;
+            println!("Period {period_index} of {pool} archived: {signature}");
+        }
+        Command::Pool(PoolCommand::GuardSales { pool, disable }) => {
+            let signer = ctx.signer()?;
+            let signature = ctx.send(
+                signer.as_ref(),
+                instructions::set_sale_guard(&pool, &signer.pubkey(), !disable),
+            )?;
+            if disable {
+                println!("Sales of {pool} are no longer guarded: {signature}");
+            } else {
+                println!("Sales of {pool} are now guarded: {signature}");
+            }
+        }
+        Command::Pool(PoolCommand::RequirePaymentProof { pool, disable }) => {
+            let signer = ctx.signer()?;
+            let signature = ctx.send(
+                signer.as_ref(),
+                instructions::set_payment_proof_required(&pool, &signer.pubkey(), !disable),
+            )?;
+            if disable {
+                println!("Sales of {pool} no longer need a payment proof: {signature}");
+            } else {
+                println!("Sales of {pool} now need a payment proof: {signature}");
+            }
+        }
+        Command::Pool(PoolCommand::ConfidentialPayouts { pool, disable }) => {
+            let signer = ctx.signer()?;
+            let state = ctx.client.fetch_pool(&pool)?;
+            let token_program = ctx.token_program_for(&state.usdc_mint)?;
+            let signature = ctx.send(
+                signer.as_ref(),
+                instructions::at_escrow_version(
+                    instructions::set_confidential_payouts(
+                        &pool,
+                        &signer.pubkey(),
+                        &state.usdc_mint,
+                        &token_program,
+                        !disable,
+                    ),
+                    &pool,
+                    state.escrow_version,
+                ),
+            )?;
+            if disable {
+                println!(
+                    "Affiliates of {pool} can no longer opt into confidential payouts: {signature}"
+                );
+            } else {
+                println!("Affiliates of {pool} can now opt into confidential payouts: {signature}");
+            }
+        }
+        Command::Pool(PoolCommand::Topup { pool }) => {
+            let signer = ctx.signer()?;
+            let schedule = ctx
+                .client
+                .fetch_topup_schedule(&pool)?
+                .ok_or("pool has no top-up schedule")?;
+            let token_program = ctx.token_program_for(&schedule.mint)?;
+            let escrow_version = ctx.client.fetch_pool(&pool)?.escrow_version;
+            let signature = ctx.send(
+                signer.as_ref(),
+                instructions::at_escrow_version(
+                    instructions::execute_topup(
+                        &pool,
+                        &schedule.source,
+                        &schedule.mint,
+                        &token_program,
+                    ),
+                    &pool,
+                    escrow_version,
+                ),
+            )?;
+            println!("Topped up {pool} with {}: {signature}", schedule.amount);
+        }
+        Command::Affiliate(AffiliateCommand::Add {
+            pool,
+            wallet,
+            ref_id,
+            referrer,
+        }) => {
+            validate_ref_id(&ref_id)?;
+            let signer = ctx.signer()?;
+            let fee_payer = ctx.fee_payer()?;
+            let rent_payer = fee_payer.as_ref().unwrap_or(&signer).pubkey();
+            let pool_account = ctx.client.fetch_pool(&pool)?;
+            let signature = ctx.send_with_payer(
+                signer.as_ref(),
+                fee_payer.as_deref(),
+                instructions::add_affiliate(
+                    &pool,
+                    &signer.pubkey(),
+                    &rent_payer,
+                    &wallet,
+                    pool_account.affiliate_count,
+                    &ref_id,
+                    referrer.as_ref(),
+                    pool_account.approval_threshold > 0,
+                ),
+            )?;
+            println!("Affiliate {wallet} ({ref_id}) added: {signature}");
+        }
+        Command::Affiliate(AffiliateCommand::Import { pool, file }) => {
+            let mut affiliates = Vec::new();
+            for line in fs::read_to_string(&file)?.lines() {
+                let line = line.trim();
+                if line.is_empty() {
+                    continue;
+                }
+                let Some((wallet, ref_id)) = line.split_once(',') else {
+                    return Err(format!("expected `wallet,ref_id`, got `{line}`").into());
+                };
+                affiliates.push(AffiliateImport {
+                    wallet: Pubkey::from_str(wallet.trim())?,
+                    ref_id: ref_id.trim().to_string(),
+                });
+            }
+            let signer = ctx.signer()?;
+            let fee_payer = ctx.fee_payer()?;
+            let rent_payer = fee_payer.as_ref().unwrap_or(&signer).pubkey();
+            for batch in affiliates.chunks(IMPORT_BATCH_SIZE) {
+                let args = AddAffiliatesBatchArgs {
+                    affiliates: batch.to_vec(),
+                };
+                args.validate()?;
+                let first_index = ctx.client.fetch_pool(&pool)?.affiliate_count;
+                let signature = ctx.send_with_payer(
+                    signer.as_ref(),
+                    fee_payer.as_deref(),
+                    instructions::add_affiliates_batch(
+                        &pool,
+                        &signer.pubkey(),
+                        &rent_payer,
+                        first_index,
+                        args,
+                    ),
+                )?;
+                println!("Imported {} affiliates: {signature}", batch.len());
+            }
+        }
+        Command::Affiliate(AffiliateCommand::Tag { pool, wallet, tags }) => {
+            let signer = ctx.signer()?;
+            let signature = ctx.send(
+                signer.as_ref(),
+                instructions::set_affiliate_tags(&pool, &signer.pubkey(), &wallet, tags),
+            )?;
+            println!("Tags of {wallet} set to {tags:#b}: {signature}");
+        }
+        Command::Affiliate(AffiliateCommand::Snapshot {
+            pool,
+            wallet,
+            period,
+        }) => {
+            let signer = ctx.signer()?;
+            let fee_payer = ctx.fee_payer()?;
+            let rent_payer = fee_payer.as_ref().unwrap_or(&signer).pubkey();
+            let signature = ctx.send_with_payer(
+                signer.as_ref(),
+                fee_payer.as_deref(),
+                instructions::snapshot_affiliate(
+                    &pool,
+                    &wallet,
+                    &signer.pubkey(),
+                    &rent_payer,
+                    period,
+                ),
+            )?;
+            println!("Snapshot of {wallet} for {period} taken: {signature}");
+        }
+        Command::Affiliate(AffiliateCommand::Leave { pool, close }) => {
+            let signer = ctx.signer()?;
+            let merchant_pool = ctx.client.fetch_pool(&pool)?;
+            let (affiliate_address, _) = pda::find_affiliate_address(&pool, &signer.pubkey());
+            let affiliate_account = ctx.client.fetch_affiliate(&affiliate_address)?;
+            let signature = ctx.send(
+                signer.as_ref(),
+                instructions::leave_pool(
+                    &pool,
+                    &merchant_pool.merchant,
+                    &signer.pubkey(),
+                    &affiliate_account.ref_id,
+                    close,
+                ),
+            )?;
+            println!("Left pool {pool}: {signature}");
+        }
+        Command::Affiliate(AffiliateCommand::Apply {
+            pool,
+            profile_uri,
+            contact,
+        }) => {
+            let signer = ctx.signer()?;
+            let signature = ctx.send(
+                signer.as_ref(),
+                instructions::submit_affiliate_application(
+                    &pool,
+                    &signer.pubkey(),
+                    &profile_uri,
+                    hash(contact.as_bytes()).to_bytes(),
+                ),
+            )?;
+            println!("Application submitted to pool {pool}: {signature}");
+        }
+        Command::Affiliate(AffiliateCommand::Activate { pool }) => {
+            let signer = ctx.signer()?;
+            let signature = ctx.send(
+                signer.as_ref(),
+                instructions::activate_self(&pool, &signer.pubkey()),
+            )?;
+            println!("Affiliate activated: {signature}");
+        }
+        Command::Affiliate(AffiliateCommand::SetPayoutAccount { pool, account }) => {
+            let signer = ctx.signer()?;
+            let escrow_version = ctx.client.fetch_pool(&pool)?.escrow_version;
+            let signature = ctx.send(
+                signer.as_ref(),
+                instructions::at_escrow_version(
+                    instructions::set_payout_account(&pool, &signer.pubkey(), account.as_ref()),
+                    &pool,
+                    escrow_version,
+                ),
+            )?;
+            match account {
+                Some(account) => println!("Payouts now go to {account}: {signature}"),
+                None => println!("Payouts now go to the wallet's ATA: {signature}"),
+            }
+        }
+        Command::Affiliate(AffiliateCommand::CompressedPayouts { pool, disable }) => {
+            let signer = ctx.signer()?;
+            let signature = ctx.send(
+                signer.as_ref(),
+                instructions::set_compressed_payouts(&pool, &signer.pubkey(), !disable),
+            )?;
+            if disable {
+                println!("Payouts now go to token accounts: {signature}");
+            } else {
+                println!("Payouts now accrue for compressed payout: {signature}");
+            }
+        }
+        Command::Affiliate(AffiliateCommand::ConfidentialPayouts {
+            pool,
+            elgamal_pubkey,
+        }) => {
+            let signer = ctx.signer()?;
+            let signature = ctx.send(
+                signer.as_ref(),
+                instructions::set_confidential_payout_key(&pool, &signer.pubkey(), elgamal_pubkey),
+            )?;
+            match elgamal_pubkey {
+                Some(_) => println!("Payouts now accrue for confidential payout: {signature}"),
+                None => println!("Payouts are public again: {signature}"),
+            }
+        }
+        Command::Affiliate(AffiliateCommand::SplitPayouts { pool, splits }) => {
+            let signer = ctx.signer()?;
+            let escrow_version = ctx.client.fetch_pool(&pool)?.escrow_version;
+            let signature = ctx.send(
+                signer.as_ref(),
+                instructions::at_escrow_version(
+                    instructions::set_payout_splits(&pool, &signer.pubkey(), &splits),
+                    &pool,
+                    escrow_version,
+                ),
+            )?;
+            println!("Payouts split {} ways: {signature}", splits.len().max(1));
+        }
+        Command::Sale(SaleCommand::Process {
+            pool,
+            affiliate,
+            ref_id,
+            amount,
+            order_id,
+            commission_override_bps,
+            memo,
+            idempotent,
+            campaign,
+            region,
+            session_key,
+        }) => {
+            let args = ProcessSaleArgs {
+                sale_amount: amount,
+                order_id: order_id.clone(),
+                commission_override_bps,
+                memo,
+                metadata_hash: None,
+                idempotent,
+            };
+            args.validate()?;
+            let signer = ctx.signer()?;
+            let merchant_pool = ctx.client.fetch_pool(&pool)?;
+            let mint = merchant_pool.usdc_mint;
+            let token_program = ctx.token_program_for(&mint)?;
+            let affiliate_account = match (&ref_id, affiliate) {
+                (Some(ref_id), _) => ctx.client.fetch_affiliate_by_ref_id(&pool, ref_id)?.1,
+                (None, Some(affiliate)) => {
+                    let (affiliate_address, _) = pda::find_affiliate_address(&pool, &affiliate);
+                    ctx.client.fetch_affiliate(&affiliate_address)?
+                }
+                (None, None) => return Err("--affiliate or --ref-id is required".into()),
+            };
+            let affiliate = affiliate_account.wallet;
+            let manager = affiliate_account.manager;
+            let referrer = affiliate_account
+                .referred_by
+                .filter(|_| !affiliate_account.referral_bounty_paid);
+            let payout_account = ctx.client.fetch_payout_account(&affiliate_account)?;
+            let instruction = match (campaign, region) {
+                (Some(campaign_id), _) => instructions::process_sale_for_campaign(
+                    &pool,
+                    &affiliate,
+                    &mint,
+                    &token_program,
+                    &signer.pubkey(),
+                    &campaign_id,
+                    CampaignSaleArgs {
+                        sale_amount: args.sale_amount,
+                        order_id: args.order_id,
+                        memo: args.memo,
+                        metadata_hash: args.metadata_hash,
+                        idempotent: args.idempotent,
+                    },
+                    merchant_pool.has_leaderboard(),
+                    manager.as_ref(),
+                    referrer.as_ref(),
+                    merchant_pool.revenue_account().as_ref(),
+                    payout_account.as_ref(),
+                    ref_id.as_deref(),
+                    session_key,
+                    affiliate_account.group.as_ref(),
+                    merchant_pool.guards_sales(),
+                    &affiliate_account.payout_splits,
+                ),
+                (None, Some(region)) => instructions::process_sale_in_region(
+                    &pool,
+                    &affiliate,
+                    &mint,
+                    &token_program,
+                    &signer.pubkey(),
+                    &region,
+                    args,
+                    merchant_pool.has_leaderboard(),
+                    manager.as_ref(),
+                    referrer.as_ref(),
+                    merchant_pool.revenue_account().as_ref(),
+                    payout_account.as_ref(),
+                    ref_id.as_deref(),
+                    session_key,
+                    affiliate_account.group.as_ref(),
+                    merchant_pool.guards_sales(),
+                    &affiliate_account.payout_splits,
+                ),
+                (None, None) => instructions::process_sale(
+                    &pool,
+                    &affiliate,
+                    &mint,
+                    &token_program,
+                    &signer.pubkey(),
+                    args,
+                    merchant_pool.has_leaderboard(),
+                    manager.as_ref(),
+                    referrer.as_ref(),
+                    merchant_pool.revenue_account().as_ref(),
+                    payout_account.as_ref(),
+                    ref_id.as_deref(),
+                    session_key,
+                    affiliate_account.group.as_ref(),
+                    merchant_pool.guards_sales(),
+                    &affiliate_account.payout_splits,
+                ),
+            };
+            let signature = ctx.send(
+                signer.as_ref(),
+                instructions::at_escrow_version(instruction, &pool, merchant_pool.escrow_version),
+            )?;
+            println!("Sale {order_id} of {amount} processed for {affiliate}: {signature}");
+        }
+        Command::Sale(SaleCommand::PayOutCompressed {
+            pool,
+            state_tree,
+            affiliates,
+        }) => {
+            let signer = ctx.signer()?;
+            let merchant_pool = ctx.client.fetch_pool(&pool)?;
+            let mint = merchant_pool.usdc_mint;
+            let token_program = ctx.token_program_for(&mint)?;
+            let signature = ctx.send(
+                signer.as_ref(),
+                instructions::at_escrow_version(
+                    instructions::pay_out_compressed(
+                        &pool,
+                        &mint,
+                        &token_program,
+                        &signer.pubkey(),
+                        &state_tree,
+                        &affiliates,
+                    ),
+                    &pool,
+                    merchant_pool.escrow_version,
+                ),
+            )?;
+            println!(
+                "Paid {} affiliates in compressed tokens: {signature}",
+                affiliates.len()
+            );
+        }
+        Command::Sale(SaleCommand::Release { pool, order_ids }) => {
+            let signer = ctx.signer()?;
+            let merchant_pool = ctx.client.fetch_pool(&pool)?;
+            let mint = merchant_pool.usdc_mint;
+            let token_program = ctx.token_program_for(&mint)?;
+            let mut holdbacks = Vec::with_capacity(order_ids.len());
+            for order_id in order_ids {
+                let receipt = ctx.client.fetch_sale_receipt(&pool, &order_id)?;
+                let affiliate = ctx.client.fetch_affiliate(&receipt.affiliate)?;
+                holdbacks.push((order_id, affiliate.wallet, affiliate.payout_account));
+            }
+            let signature = ctx.send(
+                signer.as_ref(),
+                instructions::at_escrow_version(
+                    instructions::release_matured_holdbacks(
+                        &pool,
+                        &mint,
+                        &token_program,
+                        &holdbacks,
+                    ),
+                    &pool,
+                    merchant_pool.escrow_version,
+                ),
+            )?;
+            println!("Released matured holdbacks: {signature}");
+        }
+        Command::Stats { pool } => stats(&ctx, &pool)?,
+        Command::Admin(AdminCommand::Init) => {
+            let signer = ctx.signer()?;
+            let signature = ctx.send(
+                signer.as_ref(),
+                instructions::initialize_config(&signer.pubkey()),
+            )?;
+            println!("Program config initialized: {signature}");
+        }
+        Command::Admin(command @ (AdminCommand::Pause | AdminCommand::Unpause)) => {
+            let paused = matches!(command, AdminCommand::Pause);
+            let signer = ctx.signer()?;
+            let signature = ctx.send(
+                signer.as_ref(),
+                instructions::set_paused(&signer.pubkey(), paused),
+            )?;
+            println!("Program paused={paused}: {signature}");
+        }
+        Command::Admin(AdminCommand::ClearFlag { pool, upheld }) => {
+            let creator = ctx.client.fetch_pool(&pool)?.creator;
+            let signer = ctx.signer()?;
+            let signature = ctx.send(
+                signer.as_ref(),
+                instructions::clear_pool_flag(&pool, &creator, &signer.pubkey(), upheld),
+            )?;
+            println!("Flag on {pool} cleared: {signature}");
+        }
+        Command::Admin(AdminCommand::FeeTiers { tiers }) => {
+            let signer = ctx.signer()?;
+            let change = ConfigChange::FeeTiers { fee_tiers: tiers };
+            match send_config_change(&ctx, signer.as_ref(), change)? {
+                (signature, Some(executable_at)) => {
+                    println!("Fee schedule change queued until {executable_at}: {signature}")
+                }
+                (signature, None) => println!("Protocol fee schedule updated: {signature}"),
+            }
+        }
+        Command::Admin(AdminCommand::SetTreasury { treasury }) => {
+            let signer = ctx.signer()?;
+            let change = ConfigChange::Treasury { treasury };
+            match send_config_change(&ctx, signer.as_ref(), change)? {
+                (signature, Some(executable_at)) => println!(
+                    "Treasury change to {treasury} queued until {executable_at}: {signature}"
+                ),
+                (signature, None) => println!("Treasury set to {treasury}: {signature}"),
+            }
+        }
+        Command::Admin(AdminCommand::RateBand { min_bps, max_bps }) => {
+            let signer = ctx.signer()?;
+            let change = ConfigChange::CommissionRateBand {
+                min_commission_rate_bps: min_bps,
+                max_commission_rate_bps: max_bps,
+            };
+            match send_config_change(&ctx, signer.as_ref(), change)? {
+                (signature, Some(executable_at)) => println!(
+                    "Rate band change to {min_bps}-{max_bps} bps queued until {executable_at}: {signature}"
+                ),
+                (signature, None) => {
+                    println!("Commission rates bounded to {min_bps}-{max_bps} bps: {signature}")
+                }
+            }
+        }
+        Command::Admin(AdminCommand::Guard {
+            build,
+            timelock_secs,
+        }) => {
+            let signer = ctx.signer()?;
+            let program_hash = hash(&fs::read(&build)?).to_bytes();
+            let signature = ctx.send(
+                signer.as_ref(),
+                instructions::init_upgrade_guard(&signer.pubkey(), program_hash, timelock_secs),
+            )?;
+            println!("Config changes now wait {timelock_secs}s: {signature}");
+        }
+        Command::Admin(AdminCommand::ProgramHash { build }) => {
+            let signer = ctx.signer()?;
+            let program_hash = hash(&fs::read(&build)?).to_bytes();
+            let change = ConfigChange::ProgramHash { program_hash };
+            if let (signature, Some(executable_at)) =
+                send_config_change(&ctx, signer.as_ref(), change)?
+            {
+                println!("Program hash change queued until {executable_at}: {signature}");
+            }
+        }
+        Command::Admin(AdminCommand::Timelock { secs }) => {
+            let signer = ctx.signer()?;
+            let change = ConfigChange::Timelock {
+                timelock_secs: secs,
+            };
+            if let (signature, Some(executable_at)) =
+                send_config_change(&ctx, signer.as_ref(), change)?
+            {
+                println!("Timelock change to {secs}s queued until {executable_at}: {signature}");
+            }
+        }
+        Command::Admin(AdminCommand::ExecuteChange) => {
+            let signer = ctx.signer()?;
+            let signature = ctx.send(
+                signer.as_ref(),
+                instructions::execute_config_change(&signer.pubkey()),
+            )?;
+            println!("Queued config change executed: {signature}");
+        }
+        Command::Admin(AdminCommand::CancelChange) => {
+            let signer = ctx.signer()?;
+            let signature = ctx.send(
+                signer.as_ref(),
+                instructions::cancel_config_change(&signer.pubkey()),
+            )?;
+            println!("Queued config change cancelled: {signature}");
+        }
+        Command::Admin(AdminCommand::CollectFees { pool }) => {
+            let signer = ctx.signer()?;
+            let merchant_pool = ctx.client.fetch_pool(&pool)?;
+            let treasury = ctx.client.fetch_config()?.treasury;
+            let token_program = ctx.token_program_for(&merchant_pool.usdc_mint)?;
+            let signature = ctx.send(
+                signer.as_ref(),
+                instructions::at_escrow_version(
+                    instructions::collect_protocol_fees(
+                        &pool,
+                        &signer.pubkey(),
+                        &treasury,
+                        &merchant_pool.usdc_mint,
+                        &token_program,
+                    ),
+                    &pool,
+                    merchant_pool.escrow_version,
+                ),
+            )?;
+            println!(
+                "Collected {} in protocol fees from {pool}: {signature}",
+                merchant_pool.protocol_fees_accrued
+            );
+        }
+        Command::Admin(AdminCommand::FeeExempt { pool, revoke }) => {
+            let signer = ctx.signer()?;
+            let signature = ctx.send(
+                signer.as_ref(),
+                instructions::set_fee_exempt(&pool, &signer.pubkey(), !revoke),
+            )?;
+            println!("Pool {pool} fee_exempt={}: {signature}", !revoke);
+        }
+        Command::Admin(AdminCommand::NoticeAbandoned { pool }) => {
+            let signer = ctx.signer()?;
+            let signature = ctx.send(
+                signer.as_ref(),
+                instructions::notice_abandoned_pool(&pool, &signer.pubkey()),
+            )?;
+            println!("Abandonment of {pool} noticed: {signature}");
+        }
+        Command::Admin(AdminCommand::RecoverAbandoned { pool }) => {
+            let signer = ctx.signer()?;
+            let merchant_pool = ctx.client.fetch_pool(&pool)?;
+            let mints =
+                std::iter::once(&merchant_pool.usdc_mint).chain(merchant_pool.settlement_mints());
+            for mint in mints {
+                let token_program = ctx.token_program_for(mint)?;
+                let signature = ctx.send(
+                    signer.as_ref(),
+                    instructions::at_escrow_version(
+                        instructions::recover_abandoned_pool(
+                            &pool,
+                            &merchant_pool.merchant,
+                            &signer.pubkey(),
+                            mint,
+                            &token_program,
+                        ),
+                        &pool,
+                        merchant_pool.escrow_version,
+                    ),
+                )?;
+                println!(
+                    "Escrow of {pool} in {mint} recovered to {}: {signature}",
+                    merchant_pool.merchant
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Send `change` as the admin: applied directly, or queued behind the
+/// timelock once the program has an upgrade guard, in which case the time
+/// it becomes executable is returned too
+fn send_config_change(
+    ctx: &Context,
+    signer: &dyn Signer,
+    change: ConfigChange,
+) -> CliResult<(Signature, Option<i64>)> {
+    let admin = signer.pubkey();
+    if ctx.client.fetch_upgrade_guard()?.is_some() {
+        let signature = ctx.send(signer, instructions::queue_config_change(&admin, change))?;
+        let guard = ctx
+            .client
+            .fetch_upgrade_guard()?
+            .ok_or("upgrade guard disappeared")?;
+        return Ok((signature, Some(guard.executable_at)));
+    }
+    let instruction = match change {
+        ConfigChange::FeeTiers { fee_tiers } => instructions::set_fee_tiers(&admin, fee_tiers),
+        ConfigChange::CommissionRateBand {
+            min_commission_rate_bps,
+            max_commission_rate_bps,
+        } => instructions::set_commission_rate_band(
+            &admin,
+            min_commission_rate_bps,
+            max_commission_rate_bps,
+        ),
+        ConfigChange::Treasury { treasury } => instructions::set_treasury(&admin, &treasury),
+        ConfigChange::ProgramHash { .. } | ConfigChange::Timelock { .. } => {
+            return Err("the program has no upgrade guard; create one with `admin guard`".into())
+        }
+    };
+    Ok((ctx.send(signer, instruction)?, None))
+}
+
+fn parse_fee_tier(tier: &str) -> Result<FeeTier, String> {
+    let (volume, bps) = tier
+        .split_once(':')
+        .ok_or_else(|| format!("expected MIN_MONTHLY_VOLUME:BPS, got {tier}"))?;
+    Ok(FeeTier {
+        min_monthly_volume: volume.parse().map_err(|e| format!("{volume}: {e}"))?,
+        fee_bps: bps.parse().map_err(|e| format!("{bps}: {e}"))?,
+    })
+}
+
+fn parse_payout_split(split: &str) -> Result<PayoutSplit, String> {
+    let (token_account, bps) = split
+        .split_once(':')
+        .ok_or_else(|| format!("expected TOKEN_ACCOUNT:BPS, got {split}"))?;
+    Ok(PayoutSplit {
+        token_account: token_account
+            .parse()
+            .map_err(|e| format!("{token_account}: {e}"))?,
+        weight_bps: bps.parse().map_err(|e| format!("{bps}: {e}"))?,
+    })
+}
+
+fn parse_elgamal_pubkey(key: &str) -> Result<[u8; 32], String> {
+    let bytes = (0..key.len())
+        .step_by(2)
+        .map(|i| {
+            key.get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+        })
+        .collect::<Option<Vec<u8>>>();
+    bytes
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| format!("expected a 64-digit hex ElGamal public key, got {key}"))
+}
+
+fn parse_region(region: &str) -> Result<[u8; 2], String> {
+    region
+        .to_ascii_uppercase()
+        .into_bytes()
+        .try_into()
+        .map_err(|_| format!("expected a two-letter region code, got {region}"))
+}
+
+fn stats(ctx: &Context, address: &Pubkey) -> CliResult<()> {
+    let pool = ctx.client.fetch_pool(address)?;
+    let token_program = ctx.token_program_for(&pool.usdc_mint)?;
+    let escrow = pda::get_versioned_escrow_token_address(
+        address,
+        pool.escrow_version,
+        &pool.usdc_mint,
+        &token_program,
+    );
+    let balance = ctx.client.rpc().get_token_account_balance(&escrow)?;
+
+    println!("Pool:              {address}");
+    println!("Pool ID:           {}", pool.pool_id());
+    if !pool.display_name().is_empty() {
+        println!("Name:              {}", pool.display_name());
+    }
+    if !pool.metadata_uri().is_empty() {
+        println!("Metadata URI:      {}", pool.metadata_uri());
+    }
+    println!("Merchant:          {}", pool.merchant);
+    if let Some(reputation) = ctx.client.fetch_reputation(&pool.creator)? {
+        println!(
+            "Reputation:        {} pools, {} paid, {} freezes, {} disputes lost",
+            reputation.pools_created,
+            reputation.total_paid,
+            reputation.freezes,
+            reputation.disputes_lost
+        );
+    }
+    println!("Mint:              {}", pool.usdc_mint);
+    println!("Commission rate:   {} bps", pool.commission_rate);
+    println!("Active:            {}", pool.is_active());
+    println!("Total volume:      {}", pool.total_volume);
+    println!("Commissions paid:  {}", pool.total_commissions_paid);
+    if !pool.settlement_mints().is_empty() {
+        let mints = std::iter::once(&pool.usdc_mint).chain(pool.settlement_mints());
+        for (slot, mint) in mints.enumerate() {
+            println!(
+                "  {mint} volume={} commissions={}",
+                pool.mint_volumes[slot], pool.mint_commissions_paid[slot]
+            );
+        }
+    }
+    println!(
+        "Current period:    #{} volume={} commissions={} since {}",
+        pool.period_index, pool.period_volume, pool.period_commissions_paid, pool.period_started_at
+    );
+    println!("Escrow balance:    {}", balance.ui_amount_string);
+    println!("Payout mode:       {:?}", pool.payout_mode());
+    if pool.reward_mode() == RewardMode::StoreCredit {
+        println!(
+            "Rewards:           store credit at {} bps, redeemed by {}",
+            pool.store_credit_rate_bps, pool.storefront_program
+        );
+    }
+    if pool.is_flagged() {
+        println!(
+            "Flagged at:        {} (withdrawals frozen)",
+            pool.flagged_at
+        );
+    }
+    if pool.max_commission_per_sale > 0 {
+        println!(
+            "Commission cap:    {} per sale ({:?})",
+            pool.max_commission_per_sale,
+            pool.commission_cap_mode()
+        );
+    }
+    if pool.first_sale_bounty > 0 {
+        println!("Referral bounty:   {}", pool.first_sale_bounty);
+    }
+    if pool.rate_grace_period_secs > 0 {
+        println!(
+            "Rate grace period: {}s (previous rate {} bps)",
+            pool.rate_grace_period_secs, pool.previous_commission_rate
+        );
+    }
+    if pool.fee_exempt() {
+        println!("Protocol fee:      exempt");
+    } else if pool.protocol_fees_accrued > 0 {
+        println!(
+            "Protocol fees:     {} owed ({} monthly volume)",
+            pool.protocol_fees_accrued, pool.monthly_volume
+        );
+    }
+    if let Some(governance) = pool.governance() {
+        println!(
+            "Governance:        {} (program {})",
+            governance, pool.governance_program
+        );
+    }
+    if pool.guardian_count > 0 {
+        println!(
+            "Guardians:         {} of {}",
+            pool.guardian_threshold, pool.guardian_count
+        );
+    }
+    if pool.merchant_recovery_at != 0 {
+        println!(
+            "Recovery pending:  to {} from {} (veto with `pool veto-recovery`)",
+            pool.pending_merchant, pool.merchant_recovery_at
+        );
+    }
+    if pool.holdback_secs > 0 {
+        println!(
+            "Holdback:          {}s ({} held)",
+            pool.holdback_secs, pool.total_held
+        );
+    }
+    println!("Unclaimed:         {}", pool.total_claimable);
+
+    let affiliates = ctx.client.fetch_affiliates_for_pool(address)?;
+    if let Some(leaderboard) = ctx.client.fetch_leaderboard(address)? {
+        println!("Leaderboard:");
+        let ranked = leaderboard
+            .entries
+            .iter()
+            .filter(|entry| entry.volume > 0)
+            .enumerate();
+        for (rank, entry) in ranked {
+            let ref_id = affiliates
+                .iter()
+                .find(|(key, _)| *key == entry.affiliate)
+                .map_or("", |(_, affiliate)| affiliate.ref_id.as_str());
+            println!("  {:>2}. {:<32} volume={}", rank + 1, ref_id, entry.volume);
+        }
+    }
+    println!("Affiliates ({}):", affiliates.len());
+    for (_, affiliate) in affiliates {
+        println!(
+            "  {} {:<32} earned={} manager={} claimable={} sales={} clicks={} conversion={}bps largest={} avg={}{}",
+            affiliate.wallet,
+            affiliate.ref_id,
+            affiliate.total_earned,
+            affiliate.manager_earned,
+            affiliate.claimable,
+            affiliate.sales_count,
+            affiliate.clicks,
+            affiliate.conversion_rate_bps(),
+            affiliate.largest_sale,
+            affiliate.average_sale(),
+            if !affiliate.is_active {
+                " (inactive)"
+            } else if !affiliate.activated_by_affiliate {
+                " (awaiting activation)"
+            } else {
+                ""
+            },
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,317 @@
+use anchor_lang::{AccountDeserialize, Discriminator};
+use solana_client::{nonce_utils, rpc_client::RpcClient};
+use solana_sdk::{commitment_config::CommitmentConfig, hash::Hash, pubkey::Pubkey};
+
+use crate::{
+    pda, AffiliateAccount, AffiliateEntry, AffiliateGroup, AffiliateSnapshot, Campaign, Error,
+    Leaderboard, MerchantPool, MerchantRegistry, MerchantReputation, PoolEntry, PoolPeriod,
+    ProgramConfig, RefIdIndex, RegionPartition, Result, SaleReceipt, TopUpSchedule, UpgradeGuard,
+};
+
+/// `getMultipleAccounts` limit per request
+const MAX_MULTIPLE_ACCOUNTS: usize = 100;
+
+/// Typed account access over a blocking RPC client
+pub struct RedioClient {
+    rpc: RpcClient,
+}
+
+impl RedioClient {
+    pub fn new(rpc_url: impl ToString) -> Self {
+        Self::with_rpc(RpcClient::new_with_commitment(
+            rpc_url.to_string(),
+            CommitmentConfig::confirmed(),
+        ))
+    }
+
+    pub fn with_rpc(rpc: RpcClient) -> Self {
+        Self { rpc }
+    }
+
+    pub fn rpc(&self) -> &RpcClient {
+        &self.rpc
+    }
+
+    /// The program's config, including whether it is paused
+    pub fn fetch_config(&self) -> Result<ProgramConfig> {
+        let (address, _) = pda::find_program_config();
+        self.fetch(&address, "ProgramConfig")
+    }
+
+    /// The program's upgrade guard, `None` until the admin creates one
+    pub fn fetch_upgrade_guard(&self) -> Result<Option<UpgradeGuard>> {
+        let (address, _) = pda::find_upgrade_guard();
+        match self.fetch(&address, "UpgradeGuard") {
+            Ok(guard) => Ok(Some(guard)),
+            Err(Error::AccountNotFound(_)) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// The pool's `region` partition, `None` until the merchant creates it
+    pub fn fetch_region(&self, pool: &Pubkey, region: &[u8; 2]) -> Result<Option<RegionPartition>> {
+        let (address, _) = pda::find_region(pool, region);
+        match self.fetch(&address, "RegionPartition") {
+            Ok(partition) => Ok(Some(partition)),
+            Err(Error::AccountNotFound(_)) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Fetch a single merchant pool
+    pub fn fetch_pool(&self, address: &Pubkey) -> Result<MerchantPool> {
+        let account = self
+            .rpc
+            .get_account_with_commitment(address, self.rpc.commitment())?
+            .value
+            .ok_or(Error::AccountNotFound(*address))?;
+        decode_zero_copy(address, &account.data, "MerchantPool")
+    }
+
+    /// Blockhash stored in `nonce_account`, for signing a transaction
+    /// offline that stays valid until the nonce is advanced
+    pub fn fetch_durable_nonce(&self, nonce_account: &Pubkey) -> Result<Hash> {
+        let account = nonce_utils::get_account_with_commitment(
+            &self.rpc,
+            nonce_account,
+            self.rpc.commitment(),
+        )?;
+        Ok(nonce_utils::data_from_account(&account)?.blockhash())
+    }
+
+    /// Fetch a single affiliate account
+    pub fn fetch_affiliate(&self, address: &Pubkey) -> Result<AffiliateAccount> {
+        self.fetch(address, "AffiliateAccount")
+    }
+
+    /// Receipt of the sale recorded under `order_id` in `pool`
+    pub fn fetch_sale_receipt(&self, pool: &Pubkey, order_id: &str) -> Result<SaleReceipt> {
+        let (address, _) = pda::find_sale_receipt(pool, order_id);
+        self.fetch(&address, "SaleReceipt")
+    }
+
+    pub fn fetch_pool_period(&self, pool: &Pubkey, period_index: u32) -> Result<PoolPeriod> {
+        let (address, _) = pda::find_pool_period(pool, period_index);
+        self.fetch(&address, "PoolPeriod")
+    }
+
+    pub fn fetch_affiliate_snapshot(
+        &self,
+        affiliate: &Pubkey,
+        period: u32,
+    ) -> Result<AffiliateSnapshot> {
+        let (address, _) = pda::find_affiliate_snapshot(affiliate, period);
+        self.fetch(&address, "AffiliateSnapshot")
+    }
+
+    /// The pool's recurring escrow top-up, if one is scheduled
+    pub fn fetch_topup_schedule(&self, pool: &Pubkey) -> Result<Option<TopUpSchedule>> {
+        let (address, _) = pda::find_topup_schedule(pool);
+        match self.fetch(&address, "TopUpSchedule") {
+            Ok(schedule) => Ok(Some(schedule)),
+            Err(Error::AccountNotFound(_)) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Campaign `campaign_id` of `pool`
+    pub fn fetch_campaign(&self, pool: &Pubkey, campaign_id: &str) -> Result<Campaign> {
+        let (address, _) = pda::find_campaign(pool, campaign_id);
+        self.fetch(&address, "Campaign")
+    }
+
+    /// Affiliate group `group_id` of `pool`
+    pub fn fetch_affiliate_group(&self, pool: &Pubkey, group_id: &str) -> Result<AffiliateGroup> {
+        let (address, _) = pda::find_affiliate_group(pool, group_id);
+        self.fetch(&address, "AffiliateGroup")
+    }
+
+    /// Payout account the affiliate's commissions are routed to, its group's
+    /// taking precedence over its own; `None` pays the wallet's ATA
+    pub fn fetch_payout_account(&self, affiliate: &AffiliateAccount) -> Result<Option<Pubkey>> {
+        let group_payout = match affiliate.group {
+            Some(group) => {
+                self.fetch::<AffiliateGroup>(&group, "AffiliateGroup")?
+                    .payout_account
+            }
+            None => None,
+        };
+        Ok(group_payout.or(affiliate.payout_account))
+    }
+
+    /// The pool's leaderboard, if it keeps one
+    pub fn fetch_leaderboard(&self, pool: &Pubkey) -> Result<Option<Leaderboard>> {
+        let (address, _) = pda::find_leaderboard(pool);
+        let Some(account) = self
+            .rpc
+            .get_account_with_commitment(&address, self.rpc.commitment())?
+            .value
+        else {
+            return Ok(None);
+        };
+        decode_zero_copy(&address, &account.data, "Leaderboard").map(Some)
+    }
+
+    /// Resolve a ref_id to its affiliate account within `pool`
+    pub fn fetch_affiliate_by_ref_id(
+        &self,
+        pool: &Pubkey,
+        ref_id: &str,
+    ) -> Result<(Pubkey, AffiliateAccount)> {
+        let (address, _) = pda::find_ref_id_index(pool, ref_id);
+        let index: RefIdIndex = self.fetch(&address, "RefIdIndex")?;
+        Ok((index.affiliate, self.fetch_affiliate(&index.affiliate)?))
+    }
+
+    /// The merchant's pool registry, if they have created a pool
+    pub fn fetch_registry(&self, merchant: &Pubkey) -> Result<Option<MerchantRegistry>> {
+        let (address, _) = pda::find_merchant_registry(merchant);
+        match self.fetch(&address, "MerchantRegistry") {
+            Ok(registry) => Ok(Some(registry)),
+            Err(Error::AccountNotFound(_)) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// The merchant's cross-pool reputation, if they have created a pool
+    pub fn fetch_reputation(&self, merchant: &Pubkey) -> Result<Option<MerchantReputation>> {
+        let (address, _) = pda::find_merchant_reputation(merchant);
+        match self.fetch(&address, "MerchantReputation") {
+            Ok(reputation) => Ok(Some(reputation)),
+            Err(Error::AccountNotFound(_)) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Registry index the merchant's next pool will be listed under
+    pub fn next_pool_index(&self, merchant: &Pubkey) -> Result<u64> {
+        Ok(self
+            .fetch_registry(merchant)?
+            .map_or(0, |registry| registry.pool_count))
+    }
+
+    /// All pools created by `merchant`, in creation order, resolved through
+    /// the merchant's registry entries
+    pub fn fetch_pools_for_merchant(
+        &self,
+        merchant: &Pubkey,
+    ) -> Result<Vec<(Pubkey, MerchantPool)>> {
+        let entries: Vec<Pubkey> = (0..self.next_pool_index(merchant)?)
+            .map(|index| pda::find_pool_entry(merchant, index).0)
+            .collect();
+        let pools: Vec<Pubkey> = self
+            .fetch_multiple::<PoolEntry>(&entries, "PoolEntry")?
+            .into_iter()
+            .map(|entry| entry.pool)
+            .collect();
+        let mut accounts = Vec::with_capacity(pools.len());
+        for chunk in pools.chunks(MAX_MULTIPLE_ACCOUNTS) {
+            let fetched = self
+                .rpc
+                .get_multiple_accounts_with_commitment(chunk, self.rpc.commitment())?
+                .value;
+            for (address, account) in chunk.iter().zip(fetched) {
+                let account = account.ok_or(Error::AccountNotFound(*address))?;
+                accounts.push(decode_zero_copy(address, &account.data, "MerchantPool")?);
+            }
+        }
+        Ok(pools.into_iter().zip(accounts).collect())
+    }
+
+    /// All affiliates ever added to `pool`, active or not, in creation order;
+    /// affiliates that closed their account on leaving are skipped
+    pub fn fetch_affiliates_for_pool(
+        &self,
+        pool: &Pubkey,
+    ) -> Result<Vec<(Pubkey, AffiliateAccount)>> {
+        let count = self.fetch_pool(pool)?.affiliate_count;
+        self.fetch_affiliates_page(pool, 0, count)
+    }
+
+    /// Affiliates at listing indexes `start..start + limit` of `pool`; the
+    /// range must lie within the pool's `affiliate_count`
+    pub fn fetch_affiliates_page(
+        &self,
+        pool: &Pubkey,
+        start: u64,
+        limit: u64,
+    ) -> Result<Vec<(Pubkey, AffiliateAccount)>> {
+        let entries: Vec<Pubkey> = (start..start.saturating_add(limit))
+            .map(|index| pda::find_affiliate_entry(pool, index).0)
+            .collect();
+        let affiliates: Vec<Pubkey> = self
+            .fetch_multiple::<AffiliateEntry>(&entries, "AffiliateEntry")?
+            .into_iter()
+            .map(|entry| entry.affiliate)
+            .collect();
+        let accounts = self.fetch_multiple_optional(&affiliates, "AffiliateAccount")?;
+        Ok(affiliates
+            .into_iter()
+            .zip(accounts)
+            .filter_map(|(address, account)| Some((address, account?)))
+            .collect())
+    }
+
+    fn fetch<T: AccountDeserialize>(&self, address: &Pubkey, name: &'static str) -> Result<T> {
+        let account = self
+            .rpc
+            .get_account_with_commitment(address, self.rpc.commitment())?
+            .value
+            .ok_or(Error::AccountNotFound(*address))?;
+        decode(address, &account.data, name)
+    }
+
+    /// Batched fetch preserving the order of `addresses`
+    fn fetch_multiple<T: AccountDeserialize>(
+        &self,
+        addresses: &[Pubkey],
+        name: &'static str,
+    ) -> Result<Vec<T>> {
+        self.fetch_multiple_optional(addresses, name)?
+            .into_iter()
+            .zip(addresses)
+            .map(|(account, address)| account.ok_or(Error::AccountNotFound(*address)))
+            .collect()
+    }
+
+    /// Same as `fetch_multiple`, with `None` for accounts that don't exist
+    fn fetch_multiple_optional<T: AccountDeserialize>(
+        &self,
+        addresses: &[Pubkey],
+        name: &'static str,
+    ) -> Result<Vec<Option<T>>> {
+        let mut decoded = Vec::with_capacity(addresses.len());
+        for chunk in addresses.chunks(MAX_MULTIPLE_ACCOUNTS) {
+            let accounts = self
+                .rpc
+                .get_multiple_accounts_with_commitment(chunk, self.rpc.commitment())?
+                .value;
+            for (address, account) in chunk.iter().zip(accounts) {
+                decoded.push(match account {
+                    Some(account) => Some(decode(address, &account.data, name)?),
+                    None => None,
+                });
+            }
+        }
+        Ok(decoded)
+    }
+}
+
+fn decode<T: AccountDeserialize>(address: &Pubkey, data: &[u8], name: &'static str) -> Result<T> {
+    T::try_deserialize(&mut &data[..]).map_err(|_| Error::InvalidAccount(*address, name))
+}
+
+/// Zero-copy accounts are read as raw bytes rather than Borsh
+fn decode_zero_copy<T: bytemuck::Pod + Discriminator>(
+    address: &Pubkey,
+    data: &[u8],
+    name: &'static str,
+) -> Result<T> {
+    let invalid = || Error::InvalidAccount(*address, name);
+    let (discriminator, data) = data.split_at_checked(8).ok_or_else(invalid)?;
+    if discriminator != T::DISCRIMINATOR {
+        return Err(invalid());
+    }
+    let data = data.get(..std::mem::size_of::<T>()).ok_or_else(invalid)?;
+    Ok(bytemuck::pod_read_unaligned(data))
+}
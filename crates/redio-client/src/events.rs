@@ -0,0 +1,47 @@
+//! Events emitted by the program, parsed out of transaction logs. Decoding
+//! itself is [`redio_contract::events`], re-exported here.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+pub use redio_contract::events::{decode_event, RedioEvent, EVENT_DISCRIMINATORS};
+
+use crate::PROGRAM_ID;
+
+/// Decode every redio event in a transaction's log messages.
+///
+/// `Program data:` lines are only decoded while the program is at the top of
+/// the invocation stack, so events from other programs are skipped.
+pub fn parse_logs(logs: &[String]) -> Vec<RedioEvent> {
+    let program_id = PROGRAM_ID.to_string();
+    let mut stack: Vec<&str> = Vec::new();
+    let mut events = Vec::new();
+
+    for log in logs {
+        let Some(rest) = log.strip_prefix("Program ") else {
+            continue;
+        };
+
+        if let Some(data) = rest.strip_prefix("data: ") {
+            if stack.last() == Some(&program_id.as_str()) {
+                if let Some(event) = STANDARD
+                    .decode(data)
+                    .ok()
+                    .and_then(|bytes| decode_event(&bytes))
+                {
+                    events.push(event);
+                }
+            }
+            continue;
+        }
+
+        let mut parts = rest.split_whitespace();
+        match (parts.next(), parts.next()) {
+            (Some(id), Some("invoke")) => stack.push(id),
+            (Some(_), Some("success")) | (Some(_), Some("failed:")) => {
+                stack.pop();
+            }
+            _ => {}
+        }
+    }
+
+    events
+}
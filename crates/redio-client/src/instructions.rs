@@ -0,0 +1,2777 @@
+//! Instruction builders for every program instruction.
+//!
+//! PDAs and associated token accounts are derived from the inputs, so
+//! callers only pass the keys they actually choose.
+
+// Builders mirror the instruction arguments one-to-one
+#![allow(clippy::too_many_arguments)]
+
+use std::collections::HashMap;
+
+use anchor_lang::{system_program, InstructionData, ToAccountMetas};
+use anchor_spl::{
+    associated_token::{self, get_associated_token_address_with_program_id},
+    memo, token,
+    token_2022::{self, spl_token_2022::instruction::TokenInstruction},
+};
+use redio_contract::{
+    accounts,
+    args::{
+        AddAffiliatesBatchArgs, CampaignSaleArgs, CompressedSaleArgs, CreateCampaignArgs,
+        InitializePoolArgs, MultiSaleArgs, ProcessSaleArgs,
+    },
+    compressed_token, compression, instruction, CommissionCapMode, CompressedReceipt, ConfigChange,
+    FeeTier, PayoutMode, PayoutSplit, RatePolicy, RateStep, RewardMode, SessionScope,
+};
+use solana_loader_v3_interface::get_program_data_address;
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    sysvar,
+};
+
+use crate::{pda, remaining, PROGRAM_ID};
+
+fn ata(owner: &Pubkey, mint: &Pubkey, token_program: &Pubkey) -> Pubkey {
+    get_associated_token_address_with_program_id(owner, mint, token_program)
+}
+
+fn config() -> Pubkey {
+    pda::find_program_config().0
+}
+
+fn upgrade_guard() -> Pubkey {
+    pda::find_upgrade_guard().0
+}
+
+fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+/// `admin` must be the program's upgrade authority
+pub fn initialize_config(admin: &Pubkey) -> Instruction {
+    build(
+        accounts::InitializeConfig {
+            config: config(),
+            admin: *admin,
+            program: PROGRAM_ID,
+            program_data: get_program_data_address(&PROGRAM_ID),
+            system_program: system_program::ID,
+        },
+        instruction::InitializeConfig {},
+    )
+}
+
+pub fn set_paused(admin: &Pubkey, paused: bool) -> Instruction {
+    build(
+        accounts::SetPaused {
+            config: config(),
+            admin: *admin,
+        },
+        instruction::SetPaused { paused },
+    )
+}
+
+pub fn set_fee_tiers(admin: &Pubkey, fee_tiers: Vec<FeeTier>) -> Instruction {
+    build(
+        accounts::SetFeeTiers {
+            config: config(),
+            admin: *admin,
+            upgrade_guard: upgrade_guard(),
+        },
+        instruction::SetFeeTiers { fee_tiers },
+    )
+}
+
+pub fn set_treasury(admin: &Pubkey, treasury: &Pubkey) -> Instruction {
+    build(
+        accounts::SetTreasury {
+            config: config(),
+            admin: *admin,
+            upgrade_guard: upgrade_guard(),
+        },
+        instruction::SetTreasury {
+            treasury: *treasury,
+        },
+    )
+}
+
+pub fn set_commission_rate_band(
+    admin: &Pubkey,
+    min_commission_rate_bps: u16,
+    max_commission_rate_bps: u16,
+) -> Instruction {
+    build(
+        accounts::SetCommissionRateBand {
+            config: config(),
+            admin: *admin,
+            upgrade_guard: upgrade_guard(),
+        },
+        instruction::SetCommissionRateBand {
+            min_commission_rate_bps,
+            max_commission_rate_bps,
+        },
+    )
+}
+
+/// `program_hash` is the SHA-256 of the build the admin intends to run
+pub fn init_upgrade_guard(
+    admin: &Pubkey,
+    program_hash: [u8; 32],
+    timelock_secs: i64,
+) -> Instruction {
+    build(
+        accounts::InitUpgradeGuard {
+            config: config(),
+            upgrade_guard: upgrade_guard(),
+            admin: *admin,
+            system_program: system_program::ID,
+        },
+        instruction::InitUpgradeGuard {
+            program_hash,
+            timelock_secs,
+        },
+    )
+}
+
+pub fn queue_config_change(admin: &Pubkey, change: ConfigChange) -> Instruction {
+    build(
+        accounts::QueueConfigChange {
+            config: config(),
+            upgrade_guard: upgrade_guard(),
+            admin: *admin,
+        },
+        instruction::QueueConfigChange { change },
+    )
+}
+
+pub fn execute_config_change(admin: &Pubkey) -> Instruction {
+    build(
+        accounts::ExecuteConfigChange {
+            config: config(),
+            upgrade_guard: upgrade_guard(),
+            admin: *admin,
+        },
+        instruction::ExecuteConfigChange {},
+    )
+}
+
+pub fn cancel_config_change(admin: &Pubkey) -> Instruction {
+    build(
+        accounts::CancelConfigChange {
+            config: config(),
+            upgrade_guard: upgrade_guard(),
+            admin: *admin,
+        },
+        instruction::CancelConfigChange {},
+    )
+}
+
+/// `treasury` is the config's treasury wallet; fees go to its ATA
+pub fn collect_protocol_fees(
+    merchant_pool: &Pubkey,
+    admin: &Pubkey,
+    treasury: &Pubkey,
+    usdc_mint: &Pubkey,
+    token_program: &Pubkey,
+) -> Instruction {
+    let (escrow_authority, _) = pda::find_escrow_authority(merchant_pool);
+    build(
+        accounts::CollectProtocolFees {
+            config: config(),
+            admin: *admin,
+            merchant_pool: *merchant_pool,
+            escrow_authority,
+            escrow_usdc: ata(&escrow_authority, usdc_mint, token_program),
+            treasury_usdc: ata(treasury, usdc_mint, token_program),
+            usdc_mint: *usdc_mint,
+            token_program: *token_program,
+        },
+        instruction::CollectProtocolFees {},
+    )
+}
+
+/// `pool_index` is the merchant's current registry `pool_count`, see
+/// [`RedioClient::next_pool_index`](crate::RedioClient::next_pool_index)
+pub fn initialize_pool(
+    merchant: &Pubkey,
+    rent_payer: &Pubkey,
+    pool_index: u64,
+    usdc_mint: &Pubkey,
+    token_program: &Pubkey,
+    args: InitializePoolArgs,
+) -> Instruction {
+    let (merchant_pool, _) = pda::find_pool_address(merchant, &args.pool_id);
+    let (escrow_authority, _) = pda::find_escrow_authority(&merchant_pool);
+    let (merchant_registry, _) = pda::find_merchant_registry(merchant);
+    let (merchant_reputation, _) = pda::find_merchant_reputation(merchant);
+    let (pool_entry, _) = pda::find_pool_entry(merchant, pool_index);
+
+    build(
+        accounts::InitializePool {
+            config: config(),
+            merchant_pool,
+            escrow_authority,
+            merchant: *merchant,
+            rent_payer: *rent_payer,
+            merchant_usdc: ata(merchant, usdc_mint, token_program),
+            escrow_usdc: ata(&escrow_authority, usdc_mint, token_program),
+            merchant_registry,
+            merchant_reputation,
+            pool_entry,
+            usdc_mint: *usdc_mint,
+            token_program: *token_program,
+            associated_token_program: associated_token::ID,
+            system_program: system_program::ID,
+        },
+        instruction::InitializePool { args },
+    )
+}
+
+/// `pool_index` is the merchant registry's current `pool_count`;
+/// `usdc_mint` is the source pool's primary mint
+pub fn clone_pool(
+    source_pool: &Pubkey,
+    merchant: &Pubkey,
+    rent_payer: &Pubkey,
+    pool_index: u64,
+    usdc_mint: &Pubkey,
+    token_program: &Pubkey,
+    new_pool_id: &str,
+) -> Instruction {
+    let (merchant_pool, _) = pda::find_pool_address(merchant, new_pool_id);
+    let (escrow_authority, _) = pda::find_escrow_authority(&merchant_pool);
+
+    build(
+        accounts::ClonePool {
+            config: config(),
+            source_pool: *source_pool,
+            merchant_pool,
+            escrow_authority,
+            merchant: *merchant,
+            rent_payer: *rent_payer,
+            escrow_usdc: ata(&escrow_authority, usdc_mint, token_program),
+            merchant_registry: pda::find_merchant_registry(merchant).0,
+            merchant_reputation: pda::find_merchant_reputation(merchant).0,
+            pool_entry: pda::find_pool_entry(merchant, pool_index).0,
+            usdc_mint: *usdc_mint,
+            token_program: *token_program,
+            associated_token_program: associated_token::ID,
+            system_program: system_program::ID,
+        },
+        instruction::ClonePool {
+            new_pool_id: new_pool_id.to_string(),
+        },
+    )
+}
+
+pub fn update_pool_commission(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    new_commission_rate: u16,
+    expiry_slot: Option<u64>,
+    valid_until: Option<i64>,
+) -> Instruction {
+    build(
+        accounts::UpdatePoolCommission {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            merchant: *merchant,
+        },
+        instruction::UpdatePoolCommission {
+            new_commission_rate,
+            expiry_slot,
+            valid_until,
+        },
+    )
+}
+
+pub fn update_pool_metadata(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    display_name: &str,
+    metadata_uri: &str,
+) -> Instruction {
+    build(
+        accounts::UpdatePoolMetadata {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            merchant: *merchant,
+        },
+        instruction::UpdatePoolMetadata {
+            display_name: display_name.to_string(),
+            metadata_uri: metadata_uri.to_string(),
+        },
+    )
+}
+
+pub fn set_pool_terms(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    terms_hash: [u8; 32],
+) -> Instruction {
+    build(
+        accounts::SetPoolTerms {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            merchant: *merchant,
+        },
+        instruction::SetPoolTerms { terms_hash },
+    )
+}
+
+/// `storefront_program` redeems the store credit that
+/// `RewardMode::StoreCredit` grants at `store_credit_rate_bps`
+pub fn set_reward_mode(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    reward_mode: RewardMode,
+    store_credit_rate_bps: u16,
+    storefront_program: &Pubkey,
+) -> Instruction {
+    build(
+        accounts::SetRewardMode {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            merchant: *merchant,
+        },
+        instruction::SetRewardMode {
+            reward_mode,
+            store_credit_rate_bps,
+            storefront_program: *storefront_program,
+        },
+    )
+}
+
+/// For the storefront program to CPI, signing as its storefront authority
+/// with the affiliate wallet's signature passed through
+pub fn redeem_store_credit(
+    merchant_pool: &Pubkey,
+    affiliate_wallet: &Pubkey,
+    storefront_program: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    let (affiliate_account, _) = pda::find_affiliate_address(merchant_pool, affiliate_wallet);
+
+    build(
+        accounts::RedeemStoreCredit {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            affiliate_account,
+            affiliate_wallet: *affiliate_wallet,
+            storefront_authority: pda::find_storefront_authority(merchant_pool, storefront_program)
+                .0,
+        },
+        instruction::RedeemStoreCredit { amount },
+    )
+}
+
+pub fn set_payout_mode(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    payout_mode: PayoutMode,
+) -> Instruction {
+    build(
+        accounts::SetPayoutMode {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            merchant: *merchant,
+        },
+        instruction::SetPayoutMode { payout_mode },
+    )
+}
+
+pub fn set_sale_guard(merchant_pool: &Pubkey, merchant: &Pubkey, enabled: bool) -> Instruction {
+    build(
+        accounts::SetSaleGuard {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            merchant: *merchant,
+        },
+        instruction::SetSaleGuard { enabled },
+    )
+}
+
+pub fn set_payment_proof_required(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    required: bool,
+) -> Instruction {
+    build(
+        accounts::SetPaymentProofRequired {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            merchant: *merchant,
+        },
+        instruction::SetPaymentProofRequired { required },
+    )
+}
+
+/// `usdc_mint` is a Token-2022 mint with confidential transfers;
+/// `proof_context` holds the verified validity proof of the processor's
+/// ElGamal key for the escrow and `decryptable_zero_balance` is zero under
+/// its AE key
+pub fn configure_confidential_escrow(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    usdc_mint: &Pubkey,
+    proof_context: &Pubkey,
+    decryptable_zero_balance: [u8; 36],
+    maximum_pending_balance_credit_counter: u64,
+) -> Instruction {
+    let (escrow_authority, _) = pda::find_escrow_authority(merchant_pool);
+
+    build(
+        accounts::ConfigureConfidentialEscrow {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            escrow_authority,
+            escrow_usdc: ata(&escrow_authority, usdc_mint, &token_2022::ID),
+            usdc_mint: *usdc_mint,
+            proof_context: *proof_context,
+            merchant: *merchant,
+            token_program: token_2022::ID,
+            system_program: system_program::ID,
+        },
+        instruction::ConfigureConfidentialEscrow {
+            decryptable_zero_balance,
+            maximum_pending_balance_credit_counter,
+        },
+    )
+}
+
+pub fn set_confidential_payouts(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    usdc_mint: &Pubkey,
+    token_program: &Pubkey,
+    enabled: bool,
+) -> Instruction {
+    let (escrow_authority, _) = pda::find_escrow_authority(merchant_pool);
+
+    build(
+        accounts::SetConfidentialPayouts {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            escrow_authority,
+            escrow_usdc: ata(&escrow_authority, usdc_mint, token_program),
+            merchant: *merchant,
+        },
+        instruction::SetConfidentialPayouts { enabled },
+    )
+}
+
+pub fn set_pool_processor(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    processor: &Pubkey,
+) -> Instruction {
+    build(
+        accounts::SetPoolProcessor {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            merchant: *merchant,
+        },
+        instruction::SetPoolProcessor {
+            processor: *processor,
+        },
+    )
+}
+
+pub fn set_commission_override_bounds(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    min_override_bps: u16,
+    max_override_bps: u16,
+) -> Instruction {
+    build(
+        accounts::SetCommissionOverrideBounds {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            merchant: *merchant,
+        },
+        instruction::SetCommissionOverrideBounds {
+            min_override_bps,
+            max_override_bps,
+        },
+    )
+}
+
+pub fn set_claim_window(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    claim_window_secs: i64,
+) -> Instruction {
+    build(
+        accounts::SetClaimWindow {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            merchant: *merchant,
+        },
+        instruction::SetClaimWindow { claim_window_secs },
+    )
+}
+
+pub fn set_inactivity_period(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    inactivity_period_secs: i64,
+) -> Instruction {
+    build(
+        accounts::SetInactivityPeriod {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            merchant: *merchant,
+        },
+        instruction::SetInactivityPeriod {
+            inactivity_period_secs,
+        },
+    )
+}
+
+pub fn set_attribution_window(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    attribution_window_secs: i64,
+) -> Instruction {
+    build(
+        accounts::SetAttributionWindow {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            merchant: *merchant,
+        },
+        instruction::SetAttributionWindow {
+            attribution_window_secs,
+        },
+    )
+}
+
+/// Signed by the pool's merchant or processor
+pub fn register_click(merchant_pool: &Pubkey, authority: &Pubkey, ref_id: &str) -> Instruction {
+    build(
+        accounts::RegisterClick {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            ref_id_index: pda::find_ref_id_index(merchant_pool, ref_id).0,
+            authority: *authority,
+        },
+        instruction::RegisterClick {
+            ref_id: ref_id.to_string(),
+        },
+    )
+}
+
+pub fn set_rate_policy(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    rate_policy: RatePolicy,
+    rate_steps: Vec<RateStep>,
+) -> Instruction {
+    build(
+        accounts::SetRatePolicy {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            merchant: *merchant,
+        },
+        instruction::SetRatePolicy {
+            rate_policy,
+            rate_steps,
+        },
+    )
+}
+
+/// A zero `idle_days` turns rate decay off
+pub fn set_rate_decay(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    idle_days: u16,
+    restore_days: u16,
+    decay_bps: u16,
+) -> Instruction {
+    build(
+        accounts::SetRateDecay {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            merchant: *merchant,
+        },
+        instruction::SetRateDecay {
+            idle_days,
+            restore_days,
+            decay_bps,
+        },
+    )
+}
+
+/// An empty `approvers` list with a zero threshold turns approvals off
+pub fn set_affiliate_approvers(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    approvers: Vec<Pubkey>,
+    approval_threshold: u8,
+) -> Instruction {
+    build(
+        accounts::SetAffiliateApprovers {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            merchant: *merchant,
+        },
+        instruction::SetAffiliateApprovers {
+            approvers,
+            approval_threshold,
+        },
+    )
+}
+
+pub fn approve_affiliate(
+    merchant_pool: &Pubkey,
+    affiliate_wallet: &Pubkey,
+    approver: &Pubkey,
+) -> Instruction {
+    let (affiliate_account, _) = pda::find_affiliate_address(merchant_pool, affiliate_wallet);
+    build(
+        accounts::ApproveAffiliate {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            affiliate_account,
+            pending_affiliate: pda::find_pending_affiliate(&affiliate_account).0,
+            approver: *approver,
+        },
+        instruction::ApproveAffiliate {},
+    )
+}
+
+/// Signed by the pending affiliate's wallet
+pub fn submit_affiliate_application(
+    merchant_pool: &Pubkey,
+    affiliate_wallet: &Pubkey,
+    profile_uri: &str,
+    contact_hash: [u8; 32],
+) -> Instruction {
+    let (affiliate_account, _) = pda::find_affiliate_address(merchant_pool, affiliate_wallet);
+    build(
+        accounts::SubmitAffiliateApplication {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            affiliate_account,
+            pending_affiliate: pda::find_pending_affiliate(&affiliate_account).0,
+            affiliate_wallet: *affiliate_wallet,
+        },
+        instruction::SubmitAffiliateApplication {
+            profile_uri: profile_uri.to_string(),
+            contact_hash,
+        },
+    )
+}
+
+/// Signed by the pool's merchant or processor, which pays the rent
+pub fn create_session_key(
+    merchant_pool: &Pubkey,
+    authority: &Pubkey,
+    session: &Pubkey,
+    expiry: i64,
+    scope: SessionScope,
+) -> Instruction {
+    build(
+        accounts::CreateSessionKey {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            session_key: pda::find_session_key(merchant_pool, session).0,
+            authority: *authority,
+            system_program: system_program::ID,
+        },
+        instruction::CreateSessionKey {
+            session: *session,
+            expiry,
+            scope,
+        },
+    )
+}
+
+pub fn revoke_session_key(
+    merchant_pool: &Pubkey,
+    authority: &Pubkey,
+    session: &Pubkey,
+) -> Instruction {
+    build(
+        accounts::RevokeSessionKey {
+            merchant_pool: *merchant_pool,
+            session_key: pda::find_session_key(merchant_pool, session).0,
+            authority: *authority,
+        },
+        instruction::RevokeSessionKey {},
+    )
+}
+
+/// Signed by the pool's merchant or processor
+pub fn record_click(
+    merchant_pool: &Pubkey,
+    affiliate_wallet: &Pubkey,
+    authority: &Pubkey,
+    count: u64,
+) -> Instruction {
+    build(
+        accounts::RecordClick {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            affiliate_account: pda::find_affiliate_address(merchant_pool, affiliate_wallet).0,
+            authority: *authority,
+        },
+        instruction::RecordClick { count },
+    )
+}
+
+pub fn set_commission_cap(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    max_commission_per_sale: u64,
+    commission_cap_mode: CommissionCapMode,
+) -> Instruction {
+    build(
+        accounts::SetCommissionCap {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            merchant: *merchant,
+        },
+        instruction::SetCommissionCap {
+            max_commission_per_sale,
+            commission_cap_mode,
+        },
+    )
+}
+
+/// `revenue_usdc` must approve the pool's escrow authority as delegate;
+/// `None` returns the pool to escrow mode
+pub fn set_revenue_account(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    revenue_usdc: Option<&Pubkey>,
+) -> Instruction {
+    build(
+        accounts::SetRevenueAccount {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            merchant: *merchant,
+            revenue_usdc: revenue_usdc.copied(),
+        },
+        instruction::SetRevenueAccount {},
+    )
+}
+
+/// Applies at once for the first destination, otherwise after the timelock
+pub fn set_withdrawal_destination(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    destination: &Pubkey,
+) -> Instruction {
+    build(
+        accounts::SetWithdrawalDestination {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            merchant: *merchant,
+        },
+        instruction::SetWithdrawalDestination {
+            destination: *destination,
+        },
+    )
+}
+
+pub fn apply_withdrawal_destination(merchant_pool: &Pubkey, merchant: &Pubkey) -> Instruction {
+    build(
+        accounts::SetWithdrawalDestination {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            merchant: *merchant,
+        },
+        instruction::ApplyWithdrawalDestination {},
+    )
+}
+
+/// `cosigner` must sign to raise or remove the limit while the pool has one
+pub fn set_daily_withdrawal_limit(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    cosigner: Option<&Pubkey>,
+    daily_withdrawal_limit: u64,
+) -> Instruction {
+    build(
+        accounts::SetDailyWithdrawalLimit {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            merchant: *merchant,
+            cosigner: cosigner.copied(),
+        },
+        instruction::SetDailyWithdrawalLimit {
+            daily_withdrawal_limit,
+        },
+    )
+}
+
+/// `current_cosigner` must sign when the pool already has a co-signer
+pub fn set_withdrawal_cosigner(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    current_cosigner: Option<&Pubkey>,
+    cosigner: Option<Pubkey>,
+    cosign_threshold: u64,
+) -> Instruction {
+    build(
+        accounts::SetWithdrawalCosigner {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            merchant: *merchant,
+            current_cosigner: current_cosigner.copied(),
+        },
+        instruction::SetWithdrawalCosigner {
+            cosigner,
+            cosign_threshold,
+        },
+    )
+}
+
+pub fn set_first_sale_bounty(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    build(
+        accounts::SetFirstSaleBounty {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            merchant: *merchant,
+        },
+        instruction::SetFirstSaleBounty { amount },
+    )
+}
+
+pub fn set_rate_grace_period(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    rate_grace_period_secs: i64,
+) -> Instruction {
+    build(
+        accounts::SetRateGracePeriod {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            merchant: *merchant,
+        },
+        instruction::SetRateGracePeriod {
+            rate_grace_period_secs,
+        },
+    )
+}
+
+pub fn set_pool_governance(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    governance_program: &Pubkey,
+    governance: Option<&Pubkey>,
+) -> Instruction {
+    build(
+        accounts::SetPoolGovernance {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            merchant: *merchant,
+        },
+        instruction::SetPoolGovernance {
+            governance_program: *governance_program,
+            governance: governance.copied(),
+        },
+    )
+}
+
+pub fn set_holdback_period(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    holdback_secs: i64,
+) -> Instruction {
+    build(
+        accounts::SetHoldbackPeriod {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            merchant: *merchant,
+        },
+        instruction::SetHoldbackPeriod { holdback_secs },
+    )
+}
+
+/// `holdbacks` are the (order id, affiliate wallet, payout account) of the
+/// sales to release, the payout account being the affiliate account's when
+/// registered
+pub fn release_matured_holdbacks(
+    merchant_pool: &Pubkey,
+    usdc_mint: &Pubkey,
+    token_program: &Pubkey,
+    holdbacks: &[(String, Pubkey, Option<Pubkey>)],
+) -> Instruction {
+    let (escrow_authority, _) = pda::find_escrow_authority(merchant_pool);
+    let mut instruction = build(
+        accounts::ReleaseMaturedHoldbacks {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            escrow_authority,
+            escrow_usdc: ata(&escrow_authority, usdc_mint, token_program),
+            usdc_mint: *usdc_mint,
+            token_program: *token_program,
+            memo_program: memo::ID,
+        },
+        instruction::ReleaseMaturedHoldbacks {},
+    );
+    instruction.accounts.extend(remaining::holdback_accounts(
+        merchant_pool,
+        usdc_mint,
+        token_program,
+        holdbacks,
+    ));
+    instruction
+}
+
+pub fn init_leaderboard(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    rent_payer: &Pubkey,
+) -> Instruction {
+    let (leaderboard, _) = pda::find_leaderboard(merchant_pool);
+
+    build(
+        accounts::InitLeaderboard {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            leaderboard,
+            merchant: *merchant,
+            rent_payer: *rent_payer,
+            system_program: system_program::ID,
+        },
+        instruction::InitLeaderboard {},
+    )
+}
+
+pub fn deactivate_pool(merchant_pool: &Pubkey, merchant: &Pubkey) -> Instruction {
+    build(
+        accounts::DeactivatePool {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            merchant: *merchant,
+        },
+        instruction::DeactivatePool {},
+    )
+}
+
+/// `period_index` is the pool's current `period_index`
+pub fn rotate_period(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    rent_payer: &Pubkey,
+    period_index: u32,
+) -> Instruction {
+    let (pool_period, _) = pda::find_pool_period(merchant_pool, period_index);
+    build(
+        accounts::RotatePeriod {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            pool_period,
+            merchant: *merchant,
+            rent_payer: *rent_payer,
+            system_program: system_program::ID,
+        },
+        instruction::RotatePeriod {},
+    )
+}
+
+/// Every wallet in `affiliate_wallets` must sign; `creator` is the pool's
+/// `creator`
+pub fn flag_pool(
+    merchant_pool: &Pubkey,
+    creator: &Pubkey,
+    affiliate_wallets: &[Pubkey],
+) -> Instruction {
+    let mut instruction = build(
+        accounts::FlagPool {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            merchant_reputation: pda::find_merchant_reputation(creator).0,
+        },
+        instruction::FlagPool {},
+    );
+    instruction
+        .accounts
+        .extend(remaining::flag_accounts(merchant_pool, affiliate_wallets));
+    instruction
+}
+
+pub fn clear_pool_flag(
+    merchant_pool: &Pubkey,
+    creator: &Pubkey,
+    admin: &Pubkey,
+    upheld: bool,
+) -> Instruction {
+    build(
+        accounts::ClearPoolFlag {
+            config: config(),
+            admin: *admin,
+            merchant_pool: *merchant_pool,
+            merchant_reputation: pda::find_merchant_reputation(creator).0,
+        },
+        instruction::ClearPoolFlag { upheld },
+    )
+}
+
+/// Permissionless; `payer` funds the reputation account if the merchant's
+/// pools predate it. `creator` is the pool's `creator`
+pub fn sync_merchant_reputation(
+    merchant_pool: &Pubkey,
+    creator: &Pubkey,
+    payer: &Pubkey,
+) -> Instruction {
+    build(
+        accounts::SyncMerchantReputation {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            merchant_registry: pda::find_merchant_registry(creator).0,
+            merchant_reputation: pda::find_merchant_reputation(creator).0,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::SyncMerchantReputation {},
+    )
+}
+
+pub fn set_fee_exempt(merchant_pool: &Pubkey, admin: &Pubkey, fee_exempt: bool) -> Instruction {
+    build(
+        accounts::SetFeeExempt {
+            config: config(),
+            admin: *admin,
+            merchant_pool: *merchant_pool,
+        },
+        instruction::SetFeeExempt { fee_exempt },
+    )
+}
+
+pub fn notice_abandoned_pool(merchant_pool: &Pubkey, admin: &Pubkey) -> Instruction {
+    build(
+        accounts::NoticeAbandonedPool {
+            config: config(),
+            admin: *admin,
+            merchant_pool: *merchant_pool,
+        },
+        instruction::NoticeAbandonedPool {},
+    )
+}
+
+/// Recovers `usdc_mint`'s escrow to the ATA of the pool's `merchant`, which must exist
+pub fn recover_abandoned_pool(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    admin: &Pubkey,
+    usdc_mint: &Pubkey,
+    token_program: &Pubkey,
+) -> Instruction {
+    let (escrow_authority, _) = pda::find_escrow_authority(merchant_pool);
+
+    build(
+        accounts::RecoverAbandonedPool {
+            config: config(),
+            admin: *admin,
+            merchant_pool: *merchant_pool,
+            merchant_usdc: ata(merchant, usdc_mint, token_program),
+            escrow_authority,
+            escrow_usdc: ata(&escrow_authority, usdc_mint, token_program),
+            usdc_mint: *usdc_mint,
+            token_program: *token_program,
+            memo_program: memo::ID,
+        },
+        instruction::RecoverAbandonedPool {},
+    )
+}
+
+pub fn set_guardians(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    guardians: Vec<Pubkey>,
+    guardian_threshold: u8,
+) -> Instruction {
+    build(
+        accounts::SetGuardians {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            merchant: *merchant,
+        },
+        instruction::SetGuardians {
+            guardians,
+            guardian_threshold,
+        },
+    )
+}
+
+/// Every wallet in `guardians` must sign
+pub fn propose_merchant_recovery(
+    merchant_pool: &Pubkey,
+    new_merchant: &Pubkey,
+    guardians: &[Pubkey],
+) -> Instruction {
+    let mut instruction = build(
+        accounts::ProposeMerchantRecovery {
+            config: config(),
+            merchant_pool: *merchant_pool,
+        },
+        instruction::ProposeMerchantRecovery {
+            new_merchant: *new_merchant,
+        },
+    );
+    instruction
+        .accounts
+        .extend(remaining::guardian_signers(guardians));
+    instruction
+}
+
+pub fn veto_merchant_recovery(merchant_pool: &Pubkey, merchant: &Pubkey) -> Instruction {
+    build(
+        accounts::VetoMerchantRecovery {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            merchant: *merchant,
+        },
+        instruction::VetoMerchantRecovery {},
+    )
+}
+
+/// Every wallet in `guardians` must sign
+pub fn recover_merchant(
+    merchant_pool: &Pubkey,
+    new_merchant: &Pubkey,
+    guardians: &[Pubkey],
+) -> Instruction {
+    let mut instruction = build(
+        accounts::RecoverMerchant {
+            config: config(),
+            merchant_pool: *merchant_pool,
+        },
+        instruction::RecoverMerchant {
+            new_merchant: *new_merchant,
+        },
+    );
+    instruction
+        .accounts
+        .extend(remaining::guardian_signers(guardians));
+    instruction
+}
+
+/// `affiliate_index` is the pool's current `affiliate_count`; `referrer` is
+/// the wallet of the affiliate that referred this one
+pub fn add_affiliate(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    rent_payer: &Pubkey,
+    affiliate_wallet: &Pubkey,
+    affiliate_index: u64,
+    ref_id: &str,
+    referrer: Option<&Pubkey>,
+    needs_approval: bool,
+) -> Instruction {
+    let (affiliate_account, _) = pda::find_affiliate_address(merchant_pool, affiliate_wallet);
+    let (affiliate_entry, _) = pda::find_affiliate_entry(merchant_pool, affiliate_index);
+    let (ref_id_index, _) = pda::find_ref_id_index(merchant_pool, ref_id);
+
+    build(
+        accounts::AddAffiliate {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            affiliate_account,
+            affiliate_entry,
+            ref_id_index,
+            affiliate_wallet: *affiliate_wallet,
+            merchant: *merchant,
+            rent_payer: *rent_payer,
+            system_program: system_program::ID,
+            referrer: referrer.map(|wallet| pda::find_affiliate_address(merchant_pool, wallet).0),
+            pending_affiliate: needs_approval
+                .then(|| pda::find_pending_affiliate(&affiliate_account).0),
+        },
+        instruction::AddAffiliate {
+            ref_id: ref_id.to_string(),
+        },
+    )
+}
+
+/// `first_index` is the pool's current `affiliate_count`; each affiliate's
+/// account, entry and ref_id index PDAs are appended as remaining accounts
+pub fn add_affiliates_batch(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    rent_payer: &Pubkey,
+    first_index: u64,
+    args: AddAffiliatesBatchArgs,
+) -> Instruction {
+    let batch_accounts =
+        remaining::affiliate_batch_accounts(merchant_pool, first_index, &args.affiliates);
+    let mut instruction = build(
+        accounts::AddAffiliatesBatch {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            merchant: *merchant,
+            rent_payer: *rent_payer,
+            system_program: system_program::ID,
+        },
+        instruction::AddAffiliatesBatch { args },
+    );
+    instruction.accounts.extend(batch_accounts);
+    instruction
+}
+
+/// `merkle_tree` must already be allocated for the account-compression
+/// program at the size `max_depth` and `max_buffer_size` need, usually by a
+/// `create_account` earlier in the same transaction
+pub fn init_affiliate_tree(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    merkle_tree: &Pubkey,
+    max_depth: u32,
+    max_buffer_size: u32,
+) -> Instruction {
+    build(
+        accounts::InitAffiliateTree {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            merchant: *merchant,
+            tree_authority: pda::find_affiliate_tree_authority(merchant_pool).0,
+            merkle_tree: *merkle_tree,
+            compression_program: compression::ACCOUNT_COMPRESSION_ID,
+            noop_program: compression::NOOP_ID,
+        },
+        instruction::InitAffiliateTree {
+            max_depth,
+            max_buffer_size,
+        },
+    )
+}
+
+pub fn add_compressed_affiliate(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    merkle_tree: &Pubkey,
+    affiliate_wallet: &Pubkey,
+    ref_id: &str,
+) -> Instruction {
+    build(
+        accounts::AddCompressedAffiliate {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            merchant: *merchant,
+            affiliate_wallet: *affiliate_wallet,
+            tree_authority: pda::find_affiliate_tree_authority(merchant_pool).0,
+            merkle_tree: *merkle_tree,
+            compression_program: compression::ACCOUNT_COMPRESSION_ID,
+            noop_program: compression::NOOP_ID,
+        },
+        instruction::AddCompressedAffiliate {
+            ref_id: ref_id.to_string(),
+        },
+    )
+}
+
+/// `proof` is the leaf's Merkle proof against `args.root`, as served by an
+/// indexer tracking the tree; commissions go to the wallet's ATA. Pass the
+/// pool's `receipt_tree` once it has one, which replaces the receipt PDA
+pub fn process_compressed_sale(
+    merchant_pool: &Pubkey,
+    merkle_tree: &Pubkey,
+    receipt_tree: Option<&Pubkey>,
+    usdc_mint: &Pubkey,
+    token_program: &Pubkey,
+    authority: &Pubkey,
+    args: CompressedSaleArgs,
+    proof: &[Pubkey],
+) -> Instruction {
+    let (escrow_authority, _) = pda::find_escrow_authority(merchant_pool);
+    let sale_receipt = match receipt_tree {
+        Some(_) => None,
+        None => Some(pda::find_sale_receipt(merchant_pool, &args.order_id).0),
+    };
+    let affiliate_usdc = ata(&args.affiliate.wallet, usdc_mint, token_program);
+    let mut instruction = build(
+        accounts::ProcessCompressedSale {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            tree_authority: pda::find_affiliate_tree_authority(merchant_pool).0,
+            merkle_tree: *merkle_tree,
+            escrow_authority,
+            escrow_usdc: ata(&escrow_authority, usdc_mint, token_program),
+            affiliate_usdc,
+            sale_receipt,
+            usdc_mint: *usdc_mint,
+            authority: *authority,
+            token_program: *token_program,
+            system_program: system_program::ID,
+            memo_program: memo::ID,
+            compression_program: compression::ACCOUNT_COMPRESSION_ID,
+            noop_program: compression::NOOP_ID,
+            receipt_tree_authority: receipt_tree
+                .map(|_| pda::find_receipt_tree_authority(merchant_pool).0),
+            receipt_tree: receipt_tree.copied(),
+        },
+        instruction::ProcessCompressedSale { args },
+    );
+    instruction.accounts.extend(
+        proof
+            .iter()
+            .map(|node| AccountMeta::new_readonly(*node, false)),
+    );
+    instruction
+}
+
+/// `merkle_tree` is allocated by the caller, as for `init_affiliate_tree`
+pub fn init_receipt_tree(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    merkle_tree: &Pubkey,
+    max_depth: u32,
+    max_buffer_size: u32,
+) -> Instruction {
+    build(
+        accounts::InitReceiptTree {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            merchant: *merchant,
+            tree_authority: pda::find_receipt_tree_authority(merchant_pool).0,
+            merkle_tree: *merkle_tree,
+            compression_program: compression::ACCOUNT_COMPRESSION_ID,
+            noop_program: compression::NOOP_ID,
+        },
+        instruction::InitReceiptTree {
+            max_depth,
+            max_buffer_size,
+        },
+    )
+}
+
+/// `proof` is the receipt leaf's Merkle proof against `root`
+pub fn verify_sale_receipt(
+    merchant_pool: &Pubkey,
+    receipt_tree: &Pubkey,
+    root: [u8; 32],
+    receipt: CompressedReceipt,
+    proof: &[Pubkey],
+) -> Instruction {
+    let mut instruction = build(
+        accounts::VerifySaleReceipt {
+            merchant_pool: *merchant_pool,
+            receipt_tree: *receipt_tree,
+            compression_program: compression::ACCOUNT_COMPRESSION_ID,
+        },
+        instruction::VerifySaleReceipt { root, receipt },
+    );
+    instruction.accounts.extend(
+        proof
+            .iter()
+            .map(|node| AccountMeta::new_readonly(*node, false)),
+    );
+    instruction
+}
+
+/// Signed by the pool's processor. `splits` lists each affiliate's wallet
+/// and its account's `payout_account`, when registered, in the order of
+/// `args.weights_bps`; set `leaderboard` when the pool has one
+pub fn process_sale_multi(
+    merchant_pool: &Pubkey,
+    usdc_mint: &Pubkey,
+    token_program: &Pubkey,
+    authority: &Pubkey,
+    args: MultiSaleArgs,
+    splits: &[(Pubkey, Option<Pubkey>)],
+    leaderboard: bool,
+) -> Instruction {
+    let (escrow_authority, _) = pda::find_escrow_authority(merchant_pool);
+    let mut instruction = build(
+        accounts::ProcessSaleMulti {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            escrow_authority,
+            escrow_usdc: ata(&escrow_authority, usdc_mint, token_program),
+            sale_receipt: pda::find_sale_receipt(merchant_pool, &args.order_id).0,
+            usdc_mint: *usdc_mint,
+            authority: *authority,
+            token_program: *token_program,
+            system_program: system_program::ID,
+            memo_program: memo::ID,
+            leaderboard: leaderboard.then(|| pda::find_leaderboard(merchant_pool).0),
+        },
+        instruction::ProcessSaleMulti { args },
+    );
+    instruction.accounts.extend(remaining::split_accounts(
+        merchant_pool,
+        usdc_mint,
+        token_program,
+        splits,
+    ));
+    instruction
+}
+
+/// Signed by the pool's processor or merchant, or by a session key with
+/// `session_key` set. Set `leaderboard` when the pool's `has_leaderboard`
+/// is set, and pass the affiliate account's `manager` so its share can be paid. `referrer` is the
+/// account's `referred_by` until its referral bounty has been paid,
+/// `revenue_account` is the pool's, set in escrowless mode,
+/// `payout_account` is the affiliate account's, when registered,
+/// `ref_id` is the affiliate's code when the sale was attributed through it,
+/// `group` is the affiliate account's, while it is in one, `guarded` is
+/// the pool's `guards_sales()` and `payout_splits` are the affiliate
+/// account's
+pub fn process_sale(
+    merchant_pool: &Pubkey,
+    affiliate_wallet: &Pubkey,
+    usdc_mint: &Pubkey,
+    token_program: &Pubkey,
+    authority: &Pubkey,
+    args: ProcessSaleArgs,
+    leaderboard: bool,
+    manager: Option<&Pubkey>,
+    referrer: Option<&Pubkey>,
+    revenue_account: Option<&Pubkey>,
+    payout_account: Option<&Pubkey>,
+    ref_id: Option<&str>,
+    session_key: bool,
+    group: Option<&Pubkey>,
+    guarded: bool,
+    payout_splits: &[PayoutSplit],
+) -> Instruction {
+    let mut instruction = build(
+        sale_accounts(
+            merchant_pool,
+            affiliate_wallet,
+            usdc_mint,
+            token_program,
+            authority,
+            &args.order_id,
+            leaderboard,
+            manager,
+            referrer,
+            revenue_account,
+            payout_account,
+            ref_id,
+            session_key,
+            group,
+            guarded,
+        ),
+        instruction::ProcessSale { args },
+    );
+    instruction
+        .accounts
+        .extend(remaining::payout_split_accounts(payout_splits));
+    instruction
+}
+
+/// Same as [`process_sale`] at the rate of the pool's `campaign_id` campaign
+pub fn process_sale_for_campaign(
+    merchant_pool: &Pubkey,
+    affiliate_wallet: &Pubkey,
+    usdc_mint: &Pubkey,
+    token_program: &Pubkey,
+    authority: &Pubkey,
+    campaign_id: &str,
+    args: CampaignSaleArgs,
+    leaderboard: bool,
+    manager: Option<&Pubkey>,
+    referrer: Option<&Pubkey>,
+    revenue_account: Option<&Pubkey>,
+    payout_account: Option<&Pubkey>,
+    ref_id: Option<&str>,
+    session_key: bool,
+    group: Option<&Pubkey>,
+    guarded: bool,
+    payout_splits: &[PayoutSplit],
+) -> Instruction {
+    let (campaign, _) = pda::find_campaign(merchant_pool, campaign_id);
+
+    let mut instruction = build(
+        accounts::ProcessSaleForCampaign {
+            sale: sale_accounts(
+                merchant_pool,
+                affiliate_wallet,
+                usdc_mint,
+                token_program,
+                authority,
+                &args.order_id,
+                leaderboard,
+                manager,
+                referrer,
+                revenue_account,
+                payout_account,
+                ref_id,
+                session_key,
+                group,
+                guarded,
+            ),
+            campaign,
+        },
+        instruction::ProcessSaleForCampaign { args },
+    );
+    instruction
+        .accounts
+        .extend(remaining::payout_split_accounts(payout_splits));
+    instruction
+}
+
+/// Same as [`process_sale`] within the pool's `region` partition
+pub fn process_sale_in_region(
+    merchant_pool: &Pubkey,
+    affiliate_wallet: &Pubkey,
+    usdc_mint: &Pubkey,
+    token_program: &Pubkey,
+    authority: &Pubkey,
+    region: &[u8; 2],
+    args: ProcessSaleArgs,
+    leaderboard: bool,
+    manager: Option<&Pubkey>,
+    referrer: Option<&Pubkey>,
+    revenue_account: Option<&Pubkey>,
+    payout_account: Option<&Pubkey>,
+    ref_id: Option<&str>,
+    session_key: bool,
+    group: Option<&Pubkey>,
+    guarded: bool,
+    payout_splits: &[PayoutSplit],
+) -> Instruction {
+    let (region, _) = pda::find_region(merchant_pool, region);
+
+    let mut instruction = build(
+        accounts::ProcessSaleInRegion {
+            sale: sale_accounts(
+                merchant_pool,
+                affiliate_wallet,
+                usdc_mint,
+                token_program,
+                authority,
+                &args.order_id,
+                leaderboard,
+                manager,
+                referrer,
+                revenue_account,
+                payout_account,
+                ref_id,
+                session_key,
+                group,
+                guarded,
+            ),
+            region,
+        },
+        instruction::ProcessSaleInRegion { args },
+    );
+    instruction
+        .accounts
+        .extend(remaining::payout_split_accounts(payout_splits));
+    instruction
+}
+
+/// Same as [`process_sale`], verifying the buyer's payment of
+/// `args.sale_amount` to `merchant`. Place [`payment_transfer`] with the same
+/// `reference` and the pool's current `payment_nonce` right before it in the
+/// transaction
+pub fn process_sale_with_payment_proof(
+    merchant_pool: &Pubkey,
+    affiliate_wallet: &Pubkey,
+    usdc_mint: &Pubkey,
+    token_program: &Pubkey,
+    authority: &Pubkey,
+    merchant: &Pubkey,
+    reference: &Pubkey,
+    args: ProcessSaleArgs,
+    leaderboard: bool,
+    manager: Option<&Pubkey>,
+    referrer: Option<&Pubkey>,
+    revenue_account: Option<&Pubkey>,
+    payout_account: Option<&Pubkey>,
+    ref_id: Option<&str>,
+    session_key: bool,
+    group: Option<&Pubkey>,
+    payout_splits: &[PayoutSplit],
+) -> Instruction {
+    let mut instruction = build(
+        accounts::ProcessSaleWithPaymentProof {
+            sale: sale_accounts(
+                merchant_pool,
+                affiliate_wallet,
+                usdc_mint,
+                token_program,
+                authority,
+                &args.order_id,
+                leaderboard,
+                manager,
+                referrer,
+                revenue_account,
+                payout_account,
+                ref_id,
+                session_key,
+                group,
+                true,
+            ),
+            merchant_usdc: ata(merchant, usdc_mint, token_program),
+        },
+        instruction::ProcessSaleWithPaymentProof {
+            args,
+            reference: *reference,
+        },
+    );
+    instruction
+        .accounts
+        .extend(remaining::payout_split_accounts(payout_splits));
+    instruction
+}
+
+/// The buyer's Solana Pay payment of `amount` (raw units) to `merchant`,
+/// tagged with `reference` and the reference of `merchant_pool`'s payment
+/// `nonce`, as [`process_sale_with_payment_proof`] expects it
+pub fn payment_transfer(
+    merchant_pool: &Pubkey,
+    buyer: &Pubkey,
+    merchant: &Pubkey,
+    usdc_mint: &Pubkey,
+    token_program: &Pubkey,
+    amount: u64,
+    decimals: u8,
+    reference: &Pubkey,
+    nonce: u64,
+) -> Instruction {
+    Instruction {
+        program_id: *token_program,
+        accounts: vec![
+            AccountMeta::new(ata(buyer, usdc_mint, token_program), false),
+            AccountMeta::new_readonly(*usdc_mint, false),
+            AccountMeta::new(ata(merchant, usdc_mint, token_program), false),
+            AccountMeta::new_readonly(*buyer, true),
+            AccountMeta::new_readonly(*reference, false),
+            AccountMeta::new_readonly(
+                pda::find_payment_nonce_reference(merchant_pool, nonce).0,
+                false,
+            ),
+        ],
+        data: TokenInstruction::TransferChecked { amount, decimals }.pack(),
+    }
+}
+
+fn sale_accounts(
+    merchant_pool: &Pubkey,
+    affiliate_wallet: &Pubkey,
+    usdc_mint: &Pubkey,
+    token_program: &Pubkey,
+    authority: &Pubkey,
+    order_id: &str,
+    leaderboard: bool,
+    manager: Option<&Pubkey>,
+    referrer: Option<&Pubkey>,
+    revenue_account: Option<&Pubkey>,
+    payout_account: Option<&Pubkey>,
+    ref_id: Option<&str>,
+    session_key: bool,
+    group: Option<&Pubkey>,
+    guarded: bool,
+) -> accounts::ProcessSale {
+    let (affiliate_account, _) = pda::find_affiliate_address(merchant_pool, affiliate_wallet);
+    let (escrow_authority, _) = pda::find_escrow_authority(merchant_pool);
+    let (sale_receipt, _) = pda::find_sale_receipt(merchant_pool, order_id);
+    let leaderboard = leaderboard.then(|| pda::find_leaderboard(merchant_pool).0);
+
+    accounts::ProcessSale {
+        config: config(),
+        merchant_pool: *merchant_pool,
+        affiliate_account,
+        affiliate_wallet: *affiliate_wallet,
+        escrow_authority,
+        escrow_usdc: ata(&escrow_authority, usdc_mint, token_program),
+        affiliate_usdc: ata(affiliate_wallet, usdc_mint, token_program),
+        sale_receipt,
+        usdc_mint: *usdc_mint,
+        authority: *authority,
+        token_program: *token_program,
+        associated_token_program: associated_token::ID,
+        system_program: system_program::ID,
+        memo_program: memo::ID,
+        leaderboard,
+        manager_usdc: manager.map(|manager| ata(manager, usdc_mint, token_program)),
+        referrer_usdc: referrer.map(|referrer| ata(referrer, usdc_mint, token_program)),
+        revenue_usdc: revenue_account.copied(),
+        payout_usdc: payout_account.copied(),
+        ref_id_index: ref_id.map(|ref_id| pda::find_ref_id_index(merchant_pool, ref_id).0),
+        session_key: session_key.then(|| pda::find_session_key(merchant_pool, authority).0),
+        affiliate_group: group.copied(),
+        instructions: guarded.then_some(sysvar::instructions::ID),
+    }
+}
+
+pub fn create_campaign(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    rent_payer: &Pubkey,
+    args: CreateCampaignArgs,
+) -> Instruction {
+    let (campaign, _) = pda::find_campaign(merchant_pool, &args.campaign_id);
+
+    build(
+        accounts::CreateCampaign {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            campaign,
+            merchant: *merchant,
+            rent_payer: *rent_payer,
+            system_program: system_program::ID,
+        },
+        instruction::CreateCampaign { args },
+    )
+}
+
+pub fn create_affiliate_group(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    rent_payer: &Pubkey,
+    group_id: &str,
+    rate_modifier_bps: i16,
+) -> Instruction {
+    build(
+        accounts::CreateAffiliateGroup {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            affiliate_group: pda::find_affiliate_group(merchant_pool, group_id).0,
+            merchant: *merchant,
+            rent_payer: *rent_payer,
+            system_program: system_program::ID,
+        },
+        instruction::CreateAffiliateGroup {
+            group_id: group_id.to_string(),
+            rate_modifier_bps,
+        },
+    )
+}
+
+pub fn set_group_rate_modifier(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    group_id: &str,
+    rate_modifier_bps: i16,
+) -> Instruction {
+    build(
+        accounts::UpdateAffiliateGroup {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            affiliate_group: pda::find_affiliate_group(merchant_pool, group_id).0,
+            merchant: *merchant,
+        },
+        instruction::SetGroupRateModifier { rate_modifier_bps },
+    )
+}
+
+pub fn create_region(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    rent_payer: &Pubkey,
+    region: [u8; 2],
+    budget: u64,
+    rate_modifier_bps: i16,
+) -> Instruction {
+    build(
+        accounts::CreateRegion {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            region: pda::find_region(merchant_pool, &region).0,
+            merchant: *merchant,
+            rent_payer: *rent_payer,
+            system_program: system_program::ID,
+        },
+        instruction::CreateRegion {
+            region_code: region,
+            budget,
+            rate_modifier_bps,
+        },
+    )
+}
+
+pub fn update_region(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    region: &[u8; 2],
+    budget: u64,
+    rate_modifier_bps: i16,
+) -> Instruction {
+    build(
+        accounts::UpdateRegion {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            region: pda::find_region(merchant_pool, region).0,
+            merchant: *merchant,
+        },
+        instruction::UpdateRegion {
+            budget,
+            rate_modifier_bps,
+        },
+    )
+}
+
+/// `payout_account` is a token account in the pool's primary mint that
+/// receives every member's commissions; `None` pays members directly again
+pub fn set_group_payout_account(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    group_id: &str,
+    payout_account: Option<&Pubkey>,
+) -> Instruction {
+    build(
+        accounts::SetGroupPayoutAccount {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            affiliate_group: pda::find_affiliate_group(merchant_pool, group_id).0,
+            merchant: *merchant,
+            escrow_authority: pda::find_escrow_authority(merchant_pool).0,
+            payout_usdc: payout_account.copied(),
+        },
+        instruction::SetGroupPayoutAccount {},
+    )
+}
+
+/// Adds the affiliate to the group, or takes it out when `join` is false
+pub fn assign_affiliate_group(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    affiliate_wallet: &Pubkey,
+    group_id: &str,
+    join: bool,
+) -> Instruction {
+    let accounts = accounts::AssignAffiliateGroup {
+        config: config(),
+        merchant_pool: *merchant_pool,
+        affiliate_account: pda::find_affiliate_address(merchant_pool, affiliate_wallet).0,
+        affiliate_group: pda::find_affiliate_group(merchant_pool, group_id).0,
+        merchant: *merchant,
+    };
+    if join {
+        build(accounts, instruction::AssignAffiliateGroup {})
+    } else {
+        build(accounts, instruction::RemoveAffiliateFromGroup {})
+    }
+}
+
+pub fn end_campaign(merchant_pool: &Pubkey, merchant: &Pubkey, campaign_id: &str) -> Instruction {
+    let (campaign, _) = pda::find_campaign(merchant_pool, campaign_id);
+
+    build(
+        accounts::EndCampaign {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            campaign,
+            merchant: *merchant,
+        },
+        instruction::EndCampaign {},
+    )
+}
+
+/// `affiliate_account` is the affiliate recorded on the sale's receipt
+pub fn refund_partial(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    affiliate_account: &Pubkey,
+    order_id: &str,
+    refund_amount: u64,
+) -> Instruction {
+    let (sale_receipt, _) = pda::find_sale_receipt(merchant_pool, order_id);
+
+    build(
+        accounts::RefundPartial {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            sale_receipt,
+            affiliate_account: *affiliate_account,
+            merchant: *merchant,
+        },
+        instruction::RefundPartial {
+            order_id: order_id.to_string(),
+            refund_amount,
+        },
+    )
+}
+
+/// Pass the pool's `usdc_mint` when it is interest-bearing, so the quote
+/// converts `sale_amount` the way a sale would, and the affiliate's group
+/// while it is in one
+pub fn quote_commission(
+    merchant_pool: &Pubkey,
+    affiliate_wallet: &Pubkey,
+    sale_amount: u64,
+    usdc_mint: Option<&Pubkey>,
+    affiliate_group: Option<&Pubkey>,
+) -> Instruction {
+    let (affiliate_account, _) = pda::find_affiliate_address(merchant_pool, affiliate_wallet);
+
+    build(
+        accounts::QuoteCommission {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            affiliate_account,
+            usdc_mint: usdc_mint.copied(),
+            affiliate_group: affiliate_group.copied(),
+        },
+        instruction::QuoteCommission { sale_amount },
+    )
+}
+
+pub fn update_affiliate_ref_id(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    rent_payer: &Pubkey,
+    affiliate_wallet: &Pubkey,
+    old_ref_id: &str,
+    new_ref_id: &str,
+) -> Instruction {
+    let (affiliate_account, _) = pda::find_affiliate_address(merchant_pool, affiliate_wallet);
+
+    build(
+        accounts::UpdateAffiliateRefId {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            affiliate_account,
+            old_ref_id_index: pda::find_ref_id_index(merchant_pool, old_ref_id).0,
+            new_ref_id_index: pda::find_ref_id_index(merchant_pool, new_ref_id).0,
+            merchant: *merchant,
+            rent_payer: *rent_payer,
+            system_program: system_program::ID,
+        },
+        instruction::UpdateAffiliateRefId {
+            new_ref_id: new_ref_id.to_string(),
+        },
+    )
+}
+
+/// `authority` is the affiliate wallet or the pool's merchant
+pub fn set_payout_threshold(
+    merchant_pool: &Pubkey,
+    affiliate_wallet: &Pubkey,
+    authority: &Pubkey,
+    min_payout_amount: u64,
+    min_payout_interval_secs: i64,
+) -> Instruction {
+    let (affiliate_account, _) = pda::find_affiliate_address(merchant_pool, affiliate_wallet);
+
+    build(
+        accounts::SetPayoutThreshold {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            affiliate_account,
+            authority: *authority,
+        },
+        instruction::SetPayoutThreshold {
+            min_payout_amount,
+            min_payout_interval_secs,
+        },
+    )
+}
+
+/// `authority` is the pool's merchant or processor
+pub fn snapshot_affiliate(
+    merchant_pool: &Pubkey,
+    affiliate_wallet: &Pubkey,
+    authority: &Pubkey,
+    rent_payer: &Pubkey,
+    period: u32,
+) -> Instruction {
+    let (affiliate_account, _) = pda::find_affiliate_address(merchant_pool, affiliate_wallet);
+    let (snapshot, _) = pda::find_affiliate_snapshot(&affiliate_account, period);
+
+    build(
+        accounts::SnapshotAffiliate {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            affiliate_account,
+            snapshot,
+            authority: *authority,
+            rent_payer: *rent_payer,
+            system_program: system_program::ID,
+        },
+        instruction::SnapshotAffiliate { period },
+    )
+}
+
+/// Permissionless; only emits a `PoolHeartbeat`
+pub fn ping_pool(
+    merchant_pool: &Pubkey,
+    usdc_mint: &Pubkey,
+    token_program: &Pubkey,
+) -> Instruction {
+    let (escrow_authority, _) = pda::find_escrow_authority(merchant_pool);
+    build(
+        accounts::PingPool {
+            merchant_pool: *merchant_pool,
+            escrow_authority,
+            escrow_usdc: ata(&escrow_authority, usdc_mint, token_program),
+        },
+        instruction::PingPool {},
+    )
+}
+
+pub fn sync_interest(
+    merchant_pool: &Pubkey,
+    usdc_mint: &Pubkey,
+    token_program: &Pubkey,
+) -> Instruction {
+    let (escrow_authority, _) = pda::find_escrow_authority(merchant_pool);
+    build(
+        accounts::SyncInterest {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            escrow_authority,
+            escrow_usdc: ata(&escrow_authority, usdc_mint, token_program),
+            usdc_mint: *usdc_mint,
+        },
+        instruction::SyncInterest {},
+    )
+}
+
+pub fn generate_statement(
+    merchant_pool: &Pubkey,
+    affiliate_wallet: &Pubkey,
+    authority: &Pubkey,
+    rent_payer: &Pubkey,
+    period: u32,
+) -> Instruction {
+    let (affiliate_account, _) = pda::find_affiliate_address(merchant_pool, affiliate_wallet);
+    let (statement, _) = pda::find_statement(&affiliate_account, period);
+
+    build(
+        accounts::GenerateStatement {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            affiliate_account,
+            statement,
+            authority: *authority,
+            rent_payer: *rent_payer,
+            system_program: system_program::ID,
+        },
+        instruction::GenerateStatement { period },
+    )
+}
+
+/// Signed by the affiliate wallet, consenting to the account the merchant
+/// created for it
+pub fn activate_self(merchant_pool: &Pubkey, affiliate_wallet: &Pubkey) -> Instruction {
+    let (affiliate_account, _) = pda::find_affiliate_address(merchant_pool, affiliate_wallet);
+
+    build(
+        accounts::ActivateSelf {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            affiliate_account,
+            affiliate_wallet: *affiliate_wallet,
+        },
+        instruction::ActivateSelf {},
+    )
+}
+
+pub fn accept_terms(
+    merchant_pool: &Pubkey,
+    affiliate_wallet: &Pubkey,
+    terms_hash: [u8; 32],
+) -> Instruction {
+    let (affiliate_account, _) = pda::find_affiliate_address(merchant_pool, affiliate_wallet);
+
+    build(
+        accounts::AcceptTerms {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            affiliate_account,
+            affiliate_wallet: *affiliate_wallet,
+        },
+        instruction::AcceptTerms { terms_hash },
+    )
+}
+
+/// Signed by the affiliate wallet
+pub fn set_manager(
+    merchant_pool: &Pubkey,
+    affiliate_wallet: &Pubkey,
+    manager: Option<Pubkey>,
+    manager_share_bps: u16,
+) -> Instruction {
+    let (affiliate_account, _) = pda::find_affiliate_address(merchant_pool, affiliate_wallet);
+
+    build(
+        accounts::SetManager {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            affiliate_account,
+            affiliate_wallet: *affiliate_wallet,
+        },
+        instruction::SetManager {
+            manager,
+            manager_share_bps,
+        },
+    )
+}
+
+/// `splits` are (token account in the pool's primary mint, weight) pairs;
+/// none goes back to paying the payout account whole
+pub fn set_payout_splits(
+    merchant_pool: &Pubkey,
+    affiliate_wallet: &Pubkey,
+    splits: &[PayoutSplit],
+) -> Instruction {
+    let (affiliate_account, _) = pda::find_affiliate_address(merchant_pool, affiliate_wallet);
+    let (escrow_authority, _) = pda::find_escrow_authority(merchant_pool);
+
+    let mut instruction = build(
+        accounts::SetPayoutSplits {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            affiliate_account,
+            affiliate_wallet: *affiliate_wallet,
+            escrow_authority,
+        },
+        instruction::SetPayoutSplits {
+            weights_bps: splits.iter().map(|split| split.weight_bps).collect(),
+        },
+    );
+    instruction.accounts.extend(
+        splits
+            .iter()
+            .map(|split| AccountMeta::new_readonly(split.token_account, false)),
+    );
+    instruction
+}
+
+/// `payout_account` is a token account in the pool's primary mint; `None`
+/// goes back to paying the wallet's ATA
+pub fn set_payout_account(
+    merchant_pool: &Pubkey,
+    affiliate_wallet: &Pubkey,
+    payout_account: Option<&Pubkey>,
+) -> Instruction {
+    let (affiliate_account, _) = pda::find_affiliate_address(merchant_pool, affiliate_wallet);
+    let (escrow_authority, _) = pda::find_escrow_authority(merchant_pool);
+
+    build(
+        accounts::SetPayoutAccount {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            affiliate_account,
+            affiliate_wallet: *affiliate_wallet,
+            escrow_authority,
+            payout_usdc: payout_account.copied(),
+        },
+        instruction::SetPayoutAccount {},
+    )
+}
+
+pub fn set_compressed_payouts(
+    merchant_pool: &Pubkey,
+    affiliate_wallet: &Pubkey,
+    enabled: bool,
+) -> Instruction {
+    let (affiliate_account, _) = pda::find_affiliate_address(merchant_pool, affiliate_wallet);
+
+    build(
+        accounts::SetCompressedPayouts {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            affiliate_account,
+            affiliate_wallet: *affiliate_wallet,
+        },
+        instruction::SetCompressedPayouts { enabled },
+    )
+}
+
+/// `authority` is the pool's processor or merchant; `usdc_mint` must be
+/// registered with the compressed-token program and `output_state_tree` is
+/// the Light state tree the payouts are appended to
+pub fn pay_out_compressed(
+    merchant_pool: &Pubkey,
+    usdc_mint: &Pubkey,
+    token_program: &Pubkey,
+    authority: &Pubkey,
+    output_state_tree: &Pubkey,
+    affiliate_wallets: &[Pubkey],
+) -> Instruction {
+    let (escrow_authority, _) = pda::find_escrow_authority(merchant_pool);
+    let mut instruction = build(
+        accounts::PayOutCompressed {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            escrow_authority,
+            escrow_usdc: ata(&escrow_authority, usdc_mint, token_program),
+            authority: *authority,
+            compressed_token_program: compressed_token::COMPRESSED_TOKEN_ID,
+            light_cpi_authority: compressed_token::find_cpi_authority().0,
+            light_system_program: compressed_token::LIGHT_SYSTEM_ID,
+            registered_program: compressed_token::find_registered_program().0,
+            noop_program: compression::NOOP_ID,
+            account_compression_authority: compressed_token::find_account_compression_authority().0,
+            account_compression_program: compressed_token::LIGHT_ACCOUNT_COMPRESSION_ID,
+            token_pool: compressed_token::find_token_pool(usdc_mint).0,
+            output_state_tree: *output_state_tree,
+            token_program: *token_program,
+            system_program: system_program::ID,
+        },
+        instruction::PayOutCompressed {},
+    );
+    instruction
+        .accounts
+        .extend(remaining::compressed_payout_accounts(
+            merchant_pool,
+            affiliate_wallets,
+        ));
+    instruction
+}
+
+pub fn set_confidential_payout_key(
+    merchant_pool: &Pubkey,
+    affiliate_wallet: &Pubkey,
+    elgamal_pubkey: Option<[u8; 32]>,
+) -> Instruction {
+    let (affiliate_account, _) = pda::find_affiliate_address(merchant_pool, affiliate_wallet);
+
+    build(
+        accounts::SetConfidentialPayoutKey {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            affiliate_account,
+            affiliate_wallet: *affiliate_wallet,
+        },
+        instruction::SetConfidentialPayoutKey { elgamal_pubkey },
+    )
+}
+
+/// `authority` is the pool's processor or merchant; `affiliate_usdc` is the
+/// affiliate wallet's token account configured with its ElGamal key, and
+/// the proofs are the transfer's verified proof context accounts, in the
+/// order `[equality, ciphertext_validity, range]`
+pub fn pay_out_confidential(
+    merchant_pool: &Pubkey,
+    usdc_mint: &Pubkey,
+    authority: &Pubkey,
+    affiliate_wallet: &Pubkey,
+    affiliate_usdc: &Pubkey,
+    proofs: [Pubkey; 3],
+    new_decryptable_available_balance: [u8; 36],
+    new_source_decryptable_available_balance: [u8; 36],
+) -> Instruction {
+    let (affiliate_account, _) = pda::find_affiliate_address(merchant_pool, affiliate_wallet);
+    let (escrow_authority, _) = pda::find_escrow_authority(merchant_pool);
+    let [equality_proof, ciphertext_validity_proof, range_proof] = proofs;
+
+    build(
+        accounts::PayOutConfidential {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            affiliate_account,
+            affiliate_usdc: *affiliate_usdc,
+            escrow_authority,
+            escrow_usdc: ata(&escrow_authority, usdc_mint, &token_2022::ID),
+            usdc_mint: *usdc_mint,
+            equality_proof,
+            ciphertext_validity_proof,
+            range_proof,
+            authority: *authority,
+            token_program: token_2022::ID,
+        },
+        instruction::PayOutConfidential {
+            new_decryptable_available_balance,
+            new_source_decryptable_available_balance,
+        },
+    )
+}
+
+/// `authority` is the affiliate wallet or the pool's merchant;
+/// `payout_account` is the group's or the affiliate account's, when
+/// registered, `group` the affiliate's group and `payout_splits` the
+/// affiliate account's
+pub fn claim_commissions(
+    merchant_pool: &Pubkey,
+    affiliate_wallet: &Pubkey,
+    usdc_mint: &Pubkey,
+    token_program: &Pubkey,
+    authority: &Pubkey,
+    payout_account: Option<&Pubkey>,
+    group: Option<&Pubkey>,
+    payout_splits: &[PayoutSplit],
+) -> Instruction {
+    let (affiliate_account, _) = pda::find_affiliate_address(merchant_pool, affiliate_wallet);
+    let (escrow_authority, _) = pda::find_escrow_authority(merchant_pool);
+
+    let mut instruction = build(
+        accounts::ClaimCommissions {
+            merchant_pool: *merchant_pool,
+            affiliate_account,
+            affiliate_wallet: *affiliate_wallet,
+            escrow_authority,
+            escrow_usdc: ata(&escrow_authority, usdc_mint, token_program),
+            affiliate_usdc: ata(affiliate_wallet, usdc_mint, token_program),
+            usdc_mint: *usdc_mint,
+            authority: *authority,
+            token_program: *token_program,
+            associated_token_program: associated_token::ID,
+            system_program: system_program::ID,
+            memo_program: memo::ID,
+            payout_usdc: payout_account.copied(),
+            affiliate_group: group.copied(),
+        },
+        instruction::ClaimCommissions {},
+    );
+    instruction
+        .accounts
+        .extend(remaining::payout_split_accounts(payout_splits));
+    instruction
+}
+
+/// `relayer` pays fees and rent; the affiliate wallet co-signs as its permit
+pub fn claim_commissions_relayed(
+    merchant_pool: &Pubkey,
+    affiliate_wallet: &Pubkey,
+    usdc_mint: &Pubkey,
+    token_program: &Pubkey,
+    relayer: &Pubkey,
+    relayer_fee: u64,
+    payout_account: Option<&Pubkey>,
+    group: Option<&Pubkey>,
+    payout_splits: &[PayoutSplit],
+) -> Instruction {
+    let (affiliate_account, _) = pda::find_affiliate_address(merchant_pool, affiliate_wallet);
+    let (escrow_authority, _) = pda::find_escrow_authority(merchant_pool);
+
+    let mut instruction = build(
+        accounts::ClaimCommissionsRelayed {
+            merchant_pool: *merchant_pool,
+            affiliate_account,
+            affiliate_wallet: *affiliate_wallet,
+            escrow_authority,
+            escrow_usdc: ata(&escrow_authority, usdc_mint, token_program),
+            affiliate_usdc: ata(affiliate_wallet, usdc_mint, token_program),
+            relayer_usdc: ata(relayer, usdc_mint, token_program),
+            usdc_mint: *usdc_mint,
+            relayer: *relayer,
+            token_program: *token_program,
+            associated_token_program: associated_token::ID,
+            system_program: system_program::ID,
+            memo_program: memo::ID,
+            payout_usdc: payout_account.copied(),
+            affiliate_group: group.copied(),
+        },
+        instruction::ClaimCommissionsRelayed { relayer_fee },
+    );
+    instruction
+        .accounts
+        .extend(remaining::payout_split_accounts(payout_splits));
+    instruction
+}
+
+pub fn sweep_expired(merchant_pool: &Pubkey, affiliate_wallet: &Pubkey) -> Instruction {
+    let (affiliate_account, _) = pda::find_affiliate_address(merchant_pool, affiliate_wallet);
+
+    build(
+        accounts::SweepExpired {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            affiliate_account,
+        },
+        instruction::SweepExpired {},
+    )
+}
+
+pub fn deactivate_stale_affiliate(
+    merchant_pool: &Pubkey,
+    affiliate_wallet: &Pubkey,
+) -> Instruction {
+    let (affiliate_account, _) = pda::find_affiliate_address(merchant_pool, affiliate_wallet);
+
+    build(
+        accounts::DeactivateStaleAffiliate {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            affiliate_account,
+        },
+        instruction::DeactivateStaleAffiliate {},
+    )
+}
+
+pub fn remove_affiliate(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    affiliate_wallet: &Pubkey,
+) -> Instruction {
+    let (affiliate_account, _) = pda::find_affiliate_address(merchant_pool, affiliate_wallet);
+
+    build(
+        accounts::RemoveAffiliate {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            affiliate_account,
+            affiliate_wallet: *affiliate_wallet,
+            merchant: *merchant,
+        },
+        instruction::RemoveAffiliate {},
+    )
+}
+
+/// `tags` replaces the affiliate's tag bits
+pub fn set_affiliate_tags(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    affiliate_wallet: &Pubkey,
+    tags: u32,
+) -> Instruction {
+    let (affiliate_account, _) = pda::find_affiliate_address(merchant_pool, affiliate_wallet);
+
+    build(
+        accounts::SetAffiliateTags {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            affiliate_account,
+            affiliate_wallet: *affiliate_wallet,
+            merchant: *merchant,
+        },
+        instruction::SetAffiliateTags { tags },
+    )
+}
+
+/// `ref_id` is the affiliate account's; `merchant` is the pool's, refunded
+/// the rent when `close` is set
+pub fn leave_pool(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    affiliate_wallet: &Pubkey,
+    ref_id: &str,
+    close: bool,
+) -> Instruction {
+    let (affiliate_account, _) = pda::find_affiliate_address(merchant_pool, affiliate_wallet);
+    let (ref_id_index, _) = pda::find_ref_id_index(merchant_pool, ref_id);
+
+    build(
+        accounts::LeavePool {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            affiliate_account,
+            affiliate_wallet: *affiliate_wallet,
+            ref_id_index,
+            merchant: *merchant,
+        },
+        instruction::LeavePool { close },
+    )
+}
+
+pub fn deposit_escrow(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    usdc_mint: &Pubkey,
+    token_program: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    let (escrow_authority, _) = pda::find_escrow_authority(merchant_pool);
+
+    build(
+        accounts::DepositEscrow {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            merchant: *merchant,
+            merchant_usdc: ata(merchant, usdc_mint, token_program),
+            escrow_authority,
+            escrow_usdc: ata(&escrow_authority, usdc_mint, token_program),
+            usdc_mint: *usdc_mint,
+            token_program: *token_program,
+        },
+        instruction::DepositEscrow { amount },
+    )
+}
+
+/// `merchant_usdc` must have approved `delegate` for at least `amount`
+pub fn deposit_escrow_delegated(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    delegate: &Pubkey,
+    usdc_mint: &Pubkey,
+    token_program: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    let (escrow_authority, _) = pda::find_escrow_authority(merchant_pool);
+
+    build(
+        accounts::DepositEscrowDelegated {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            delegate: *delegate,
+            merchant_usdc: ata(merchant, usdc_mint, token_program),
+            escrow_authority,
+            escrow_usdc: ata(&escrow_authority, usdc_mint, token_program),
+            usdc_mint: *usdc_mint,
+            token_program: *token_program,
+        },
+        instruction::DepositEscrowDelegated { amount },
+    )
+}
+
+/// Funds the escrow from `donor`'s associated token account
+pub fn donate_to_escrow(
+    merchant_pool: &Pubkey,
+    donor: &Pubkey,
+    usdc_mint: &Pubkey,
+    token_program: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    let (escrow_authority, _) = pda::find_escrow_authority(merchant_pool);
+
+    build(
+        accounts::DonateToEscrow {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            donor: *donor,
+            donor_usdc: ata(donor, usdc_mint, token_program),
+            escrow_authority,
+            escrow_usdc: ata(&escrow_authority, usdc_mint, token_program),
+            usdc_mint: *usdc_mint,
+            token_program: *token_program,
+        },
+        instruction::DonateToEscrow { amount },
+    )
+}
+
+/// Pass the pool's `withdrawal_cosigner` when `amount` exceeds its threshold,
+/// and its `withdrawal_destination` once one is set
+pub fn withdraw_escrow(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    usdc_mint: &Pubkey,
+    token_program: &Pubkey,
+    amount: u64,
+    cosigner: Option<&Pubkey>,
+    destination: Option<&Pubkey>,
+    expiry_slot: Option<u64>,
+    valid_until: Option<i64>,
+) -> Instruction {
+    let (escrow_authority, _) = pda::find_escrow_authority(merchant_pool);
+
+    build(
+        accounts::WithdrawEscrow {
+            merchant_pool: *merchant_pool,
+            merchant: *merchant,
+            merchant_usdc: ata(destination.unwrap_or(merchant), usdc_mint, token_program),
+            escrow_authority,
+            escrow_usdc: ata(&escrow_authority, usdc_mint, token_program),
+            usdc_mint: *usdc_mint,
+            token_program: *token_program,
+            memo_program: memo::ID,
+            cosigner: cosigner.copied(),
+        },
+        instruction::WithdrawEscrow {
+            amount,
+            expiry_slot,
+            valid_until,
+        },
+    )
+}
+
+pub fn transfer_escrow(
+    from_pool: &Pubkey,
+    to_pool: &Pubkey,
+    merchant: &Pubkey,
+    mint: &Pubkey,
+    token_program: &Pubkey,
+    amount: u64,
+    cosigner: Option<&Pubkey>,
+    expiry_slot: Option<u64>,
+) -> Instruction {
+    let (from_escrow_authority, _) = pda::find_escrow_authority(from_pool);
+    let (to_escrow_authority, _) = pda::find_escrow_authority(to_pool);
+
+    build(
+        accounts::TransferEscrow {
+            config: config(),
+            from_pool: *from_pool,
+            to_pool: *to_pool,
+            merchant: *merchant,
+            from_escrow_authority,
+            from_escrow: ata(&from_escrow_authority, mint, token_program),
+            to_escrow_authority,
+            to_escrow: ata(&to_escrow_authority, mint, token_program),
+            mint: *mint,
+            token_program: *token_program,
+            cosigner: cosigner.copied(),
+        },
+        instruction::TransferEscrow {
+            amount,
+            expiry_slot,
+        },
+    )
+}
+
+/// `escrow_version` is the pool's current one; every `settlement_mints`
+/// escrow moves along and must be under `token_program` like the primary
+/// mint's
+pub fn migrate_escrow_authority(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    rent_payer: &Pubkey,
+    usdc_mint: &Pubkey,
+    token_program: &Pubkey,
+    settlement_mints: &[Pubkey],
+    escrow_version: u8,
+    new_version: u8,
+) -> Instruction {
+    let (escrow_authority, _) = pda::find_versioned_escrow_authority(merchant_pool, escrow_version);
+    let (new_escrow_authority, _) =
+        pda::find_versioned_escrow_authority(merchant_pool, new_version);
+    let mut instruction = build(
+        accounts::MigrateEscrowAuthority {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            merchant: *merchant,
+            rent_payer: *rent_payer,
+            escrow_authority,
+            escrow_usdc: ata(&escrow_authority, usdc_mint, token_program),
+            new_escrow_authority,
+            new_escrow_usdc: ata(&new_escrow_authority, usdc_mint, token_program),
+            usdc_mint: *usdc_mint,
+            token_program: *token_program,
+            associated_token_program: associated_token::ID,
+            system_program: system_program::ID,
+        },
+        instruction::MigrateEscrowAuthority { new_version },
+    );
+    instruction
+        .accounts
+        .extend(remaining::escrow_migration_accounts(
+            merchant_pool,
+            escrow_version,
+            new_version,
+            token_program,
+            settlement_mints,
+        ));
+    instruction
+}
+
+/// `instruction` retargeted at the escrow of a pool migrated to escrow seed
+/// `version`. Builders derive the original escrow authority and its token
+/// accounts, which this swaps for their counterparts under `version`
+pub fn at_escrow_version(
+    mut instruction: Instruction,
+    merchant_pool: &Pubkey,
+    version: u8,
+) -> Instruction {
+    if version == 0 {
+        return instruction;
+    }
+    let (escrow_authority, _) = pda::find_escrow_authority(merchant_pool);
+    let (versioned_authority, _) = pda::find_versioned_escrow_authority(merchant_pool, version);
+    let keys: Vec<Pubkey> = instruction
+        .accounts
+        .iter()
+        .map(|meta| meta.pubkey)
+        .collect();
+    let token_programs = keys
+        .iter()
+        .filter(|key| **key == token::ID || **key == token_2022::ID);
+    let escrows: HashMap<Pubkey, Pubkey> = token_programs
+        .flat_map(|token_program| {
+            keys.iter().map(move |mint| {
+                (
+                    ata(&escrow_authority, mint, token_program),
+                    ata(&versioned_authority, mint, token_program),
+                )
+            })
+        })
+        .chain([(escrow_authority, versioned_authority)])
+        .collect();
+    for meta in &mut instruction.accounts {
+        if let Some(versioned) = escrows.get(&meta.pubkey) {
+            meta.pubkey = *versioned;
+        }
+    }
+    instruction
+}
+
+pub fn add_pool_mint(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    rent_payer: &Pubkey,
+    mint: &Pubkey,
+    token_program: &Pubkey,
+) -> Instruction {
+    let (escrow_authority, _) = pda::find_escrow_authority(merchant_pool);
+
+    build(
+        accounts::AddPoolMint {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            merchant: *merchant,
+            rent_payer: *rent_payer,
+            escrow_authority,
+            escrow_token: ata(&escrow_authority, mint, token_program),
+            mint: *mint,
+            token_program: *token_program,
+            associated_token_program: associated_token::ID,
+            system_program: system_program::ID,
+        },
+        instruction::AddPoolMint {},
+    )
+}
+
+/// Schedule recurring top-ups from `source`. Approve the
+/// [`find_topup_schedule`](pda::find_topup_schedule) PDA as delegate of
+/// `source` for the top-ups to execute
+pub fn set_topup_schedule(
+    merchant_pool: &Pubkey,
+    merchant: &Pubkey,
+    rent_payer: &Pubkey,
+    source: &Pubkey,
+    amount: u64,
+    interval_secs: i64,
+) -> Instruction {
+    let (topup_schedule, _) = pda::find_topup_schedule(merchant_pool);
+
+    build(
+        accounts::SetTopUpSchedule {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            topup_schedule,
+            source: *source,
+            merchant: *merchant,
+            rent_payer: *rent_payer,
+            system_program: system_program::ID,
+        },
+        instruction::SetTopupSchedule {
+            amount,
+            interval_secs,
+        },
+    )
+}
+
+pub fn cancel_topup_schedule(merchant_pool: &Pubkey, merchant: &Pubkey) -> Instruction {
+    let (topup_schedule, _) = pda::find_topup_schedule(merchant_pool);
+
+    build(
+        accounts::CancelTopUpSchedule {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            topup_schedule,
+            merchant: *merchant,
+        },
+        instruction::CancelTopupSchedule {},
+    )
+}
+
+pub fn execute_topup(
+    merchant_pool: &Pubkey,
+    source: &Pubkey,
+    mint: &Pubkey,
+    token_program: &Pubkey,
+) -> Instruction {
+    let (topup_schedule, _) = pda::find_topup_schedule(merchant_pool);
+    let (escrow_authority, _) = pda::find_escrow_authority(merchant_pool);
+
+    build(
+        accounts::ExecuteTopUp {
+            config: config(),
+            merchant_pool: *merchant_pool,
+            topup_schedule,
+            source: *source,
+            escrow_authority,
+            escrow_token: ata(&escrow_authority, mint, token_program),
+            mint: *mint,
+            token_program: *token_program,
+        },
+        instruction::ExecuteTopup {},
+    )
+}
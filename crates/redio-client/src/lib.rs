@@ -0,0 +1,42 @@
+//! Rust client SDK for the redio-contract program.
+//!
+//! Typed account fetchers, instruction builders and event decoding built on
+//! the program crate itself, so backend services can talk to the program
+//! without going through the TypeScript tooling.
+
+pub mod accounts;
+pub mod events;
+pub mod instructions;
+pub mod remaining;
+
+pub use accounts::RedioClient;
+pub use redio_contract::{
+    self, args, pda, AffiliateAccount, AffiliateEntry, AffiliateGroup, AffiliateSnapshot, Campaign,
+    CommissionCapMode, CommissionQuote, CompressedAffiliate, CompressedReceipt, ConfigChange,
+    FeeTier, Leaderboard, LeaderboardEntry, MerchantPool, MerchantRegistry, MerchantReputation,
+    PayoutMode, PayoutSplit, PendingAffiliate, PoolEntry, PoolPeriod, ProgramConfig, RatePolicy,
+    RateStep, RefIdIndex, RegionPartition, RewardMode, SaleReceipt, SaleSplit, SessionKey,
+    SessionScope, Statement, StatementTotals, TopUpSchedule, UpgradeGuard, ID as PROGRAM_ID,
+};
+
+use solana_sdk::pubkey::Pubkey;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Rpc(Box<solana_client::client_error::ClientError>),
+    #[error("account {0} not found")]
+    AccountNotFound(Pubkey),
+    #[error("account {0} is not a valid {1}")]
+    InvalidAccount(Pubkey, &'static str),
+    #[error(transparent)]
+    Nonce(#[from] solana_client::nonce_utils::Error),
+}
+
+impl From<solana_client::client_error::ClientError> for Error {
+    fn from(err: solana_client::client_error::ClientError) -> Self {
+        Error::Rpc(Box::new(err))
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,172 @@
+//! Remaining-account packing for the instructions that take a variable
+//! number of accounts, in the group layouts `redio_contract::remaining`
+//! resolves on-chain.
+//!
+//! Builders in [`crate::instructions`] append these already; they're public
+//! for callers composing the instructions into their own transactions or
+//! CPIs.
+
+use anchor_spl::associated_token::get_associated_token_address_with_program_id;
+use redio_contract::{args::AffiliateImport, PayoutSplit};
+use solana_sdk::{instruction::AccountMeta, pubkey::Pubkey};
+
+use crate::pda;
+
+/// Token account an affiliate's commissions in `mint` are paid to: its
+/// registered `payout_account`, otherwise the wallet's ATA
+pub fn commission_account(
+    wallet: &Pubkey,
+    payout_account: Option<&Pubkey>,
+    mint: &Pubkey,
+    token_program: &Pubkey,
+) -> Pubkey {
+    payout_account.copied().unwrap_or_else(|| {
+        get_associated_token_address_with_program_id(wallet, mint, token_program)
+    })
+}
+
+/// `release_matured_holdbacks`: (sale receipt, affiliate account, commission
+/// token account) per (order id, affiliate wallet, payout account)
+pub fn holdback_accounts(
+    merchant_pool: &Pubkey,
+    usdc_mint: &Pubkey,
+    token_program: &Pubkey,
+    holdbacks: &[(String, Pubkey, Option<Pubkey>)],
+) -> Vec<AccountMeta> {
+    holdbacks
+        .iter()
+        .flat_map(|(order_id, wallet, payout_account)| {
+            [
+                AccountMeta::new(pda::find_sale_receipt(merchant_pool, order_id).0, false),
+                AccountMeta::new(pda::find_affiliate_address(merchant_pool, wallet).0, false),
+                AccountMeta::new(
+                    commission_account(wallet, payout_account.as_ref(), usdc_mint, token_program),
+                    false,
+                ),
+            ]
+        })
+        .collect()
+}
+
+/// `flag_pool`: (affiliate account, signing wallet) per affiliate wallet
+pub fn flag_accounts(merchant_pool: &Pubkey, affiliate_wallets: &[Pubkey]) -> Vec<AccountMeta> {
+    affiliate_wallets
+        .iter()
+        .flat_map(|wallet| {
+            [
+                AccountMeta::new_readonly(
+                    pda::find_affiliate_address(merchant_pool, wallet).0,
+                    false,
+                ),
+                AccountMeta::new_readonly(*wallet, true),
+            ]
+        })
+        .collect()
+}
+
+/// `pay_out_compressed`: the affiliate account of each affiliate wallet
+pub fn compressed_payout_accounts(
+    merchant_pool: &Pubkey,
+    affiliate_wallets: &[Pubkey],
+) -> Vec<AccountMeta> {
+    affiliate_wallets
+        .iter()
+        .map(|wallet| AccountMeta::new(pda::find_affiliate_address(merchant_pool, wallet).0, false))
+        .collect()
+}
+
+/// Sales and claims: the affiliate's payout split token accounts, in order
+pub fn payout_split_accounts(payout_splits: &[PayoutSplit]) -> Vec<AccountMeta> {
+    payout_splits
+        .iter()
+        .map(|split| AccountMeta::new(split.token_account, false))
+        .collect()
+}
+
+/// `propose_merchant_recovery` and `recover_merchant`: each signing guardian
+pub fn guardian_signers(guardians: &[Pubkey]) -> Vec<AccountMeta> {
+    guardians
+        .iter()
+        .map(|guardian| AccountMeta::new_readonly(*guardian, true))
+        .collect()
+}
+
+/// `process_sale_multi`: (affiliate account, commission token account) per
+/// (affiliate wallet, payout account), in weight order
+pub fn split_accounts(
+    merchant_pool: &Pubkey,
+    usdc_mint: &Pubkey,
+    token_program: &Pubkey,
+    splits: &[(Pubkey, Option<Pubkey>)],
+) -> Vec<AccountMeta> {
+    splits
+        .iter()
+        .flat_map(|(wallet, payout_account)| {
+            [
+                AccountMeta::new(pda::find_affiliate_address(merchant_pool, wallet).0, false),
+                AccountMeta::new(
+                    commission_account(wallet, payout_account.as_ref(), usdc_mint, token_program),
+                    false,
+                ),
+            ]
+        })
+        .collect()
+}
+
+/// `add_affiliates_batch`: (affiliate account, affiliate entry, ref_id
+/// index) per imported affiliate, the first taking entry `first_index`
+pub fn affiliate_batch_accounts(
+    merchant_pool: &Pubkey,
+    first_index: u64,
+    affiliates: &[AffiliateImport],
+) -> Vec<AccountMeta> {
+    affiliates
+        .iter()
+        .zip(first_index..)
+        .flat_map(|(affiliate, index)| {
+            [
+                pda::find_affiliate_address(merchant_pool, &affiliate.wallet).0,
+                pda::find_affiliate_entry(merchant_pool, index).0,
+                pda::find_ref_id_index(merchant_pool, &affiliate.ref_id).0,
+            ]
+        })
+        .map(|pda| AccountMeta::new(pda, false))
+        .collect()
+}
+
+/// `migrate_escrow_authority`: (settlement mint, escrow, new escrow ATA) per
+/// settlement mint of the pool, in registration order
+pub fn escrow_migration_accounts(
+    merchant_pool: &Pubkey,
+    escrow_version: u8,
+    new_version: u8,
+    token_program: &Pubkey,
+    settlement_mints: &[Pubkey],
+) -> Vec<AccountMeta> {
+    settlement_mints
+        .iter()
+        .flat_map(|mint| {
+            [
+                AccountMeta::new_readonly(*mint, false),
+                AccountMeta::new(
+                    pda::get_versioned_escrow_token_address(
+                        merchant_pool,
+                        escrow_version,
+                        mint,
+                        token_program,
+                    ),
+                    false,
+                ),
+                AccountMeta::new(
+                    pda::get_versioned_escrow_token_address(
+                        merchant_pool,
+                        new_version,
+                        mint,
+                        token_program,
+                    ),
+                    false,
+                ),
+            ]
+        })
+        .collect()
+}
@@ -0,0 +1,73 @@
+//! Helpers shared by the tests that run against a local validator
+
+#![allow(dead_code)]
+
+use std::{thread::sleep, time::Duration};
+
+use anchor_spl::{
+    associated_token::get_associated_token_address_with_program_id, token_2022::spl_token_2022,
+};
+use redio_client::events::{parse_logs, RedioEvent};
+use solana_client::{rpc_client::RpcClient, rpc_config::RpcTransactionConfig};
+use solana_sdk::{
+    instruction::Instruction,
+    native_token::LAMPORTS_PER_SOL,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature, Signer},
+    transaction::Transaction,
+};
+
+pub const RPC_URL: &str = "http://127.0.0.1:8899";
+
+pub fn send(
+    rpc: &RpcClient,
+    payer: &Keypair,
+    instructions: &[Instruction],
+    signers: &[&Keypair],
+) -> Signature {
+    let mut all_signers = vec![payer];
+    all_signers.extend_from_slice(signers);
+    let transaction = Transaction::new_signed_with_payer(
+        instructions,
+        Some(&payer.pubkey()),
+        &all_signers,
+        rpc.get_latest_blockhash().unwrap(),
+    );
+    rpc.send_and_confirm_transaction(&transaction).unwrap()
+}
+
+pub fn fund(rpc: &RpcClient, wallet: &Keypair) {
+    let signature = rpc
+        .request_airdrop(&wallet.pubkey(), 10 * LAMPORTS_PER_SOL)
+        .unwrap();
+    while !rpc.confirm_transaction(&signature).unwrap() {
+        sleep(Duration::from_millis(200));
+    }
+}
+
+/// Log messages of a confirmed transaction
+pub fn logs(rpc: &RpcClient, signature: &Signature) -> Vec<String> {
+    rpc.get_transaction_with_config(
+        signature,
+        RpcTransactionConfig {
+            commitment: Some(rpc.commitment()),
+            max_supported_transaction_version: Some(0),
+            ..RpcTransactionConfig::default()
+        },
+    )
+    .unwrap()
+    .transaction
+    .meta
+    .unwrap()
+    .log_messages
+    .expect("transaction has log messages")
+}
+
+/// Redio events a confirmed transaction emitted
+pub fn events(rpc: &RpcClient, signature: &Signature) -> Vec<RedioEvent> {
+    parse_logs(&logs(rpc, signature))
+}
+
+pub fn token_account(wallet: &Pubkey, mint: &Pubkey) -> Pubkey {
+    get_associated_token_address_with_program_id(wallet, mint, &spl_token_2022::ID)
+}
@@ -0,0 +1,356 @@
+//! Processes compressed affiliates' sales against real SPL Account
+//! Compression trees, appending their receipts to a receipt tree once the
+//! pool has one, on a local validator running the program, e.g. the one
+//! `anchor test` starts, which runs it after the TypeScript suite. Run by
+//! hand with `cargo test -p redio-client --test compressed_sale -- --ignored`
+
+mod common;
+
+use anchor_lang::solana_program::{keccak, program_pack::Pack};
+use anchor_spl::{
+    associated_token::spl_associated_token_account::instruction::create_associated_token_account,
+    token_2022::spl_token_2022::{
+        self,
+        instruction::{initialize_mint2, mint_to},
+        state::{Account as TokenAccountState, Mint},
+    },
+};
+use common::{events, fund, logs, send, token_account, RPC_URL};
+use redio_client::{
+    args::{CompressedSaleArgs, InitializePoolArgs},
+    events::RedioEvent,
+    instructions, pda,
+    redio_contract::compression::ACCOUNT_COMPRESSION_ID,
+    CompressedAffiliate, CompressedReceipt, RedioClient,
+};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{
+    instruction::Instruction,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+};
+use solana_system_interface::instruction::create_account;
+
+const POOL_ID: &str = "compressed-pool";
+const MAX_DEPTH: u32 = 5;
+const MAX_BUFFER_SIZE: u32 = 8;
+/// Account type, version and header ahead of the tree itself
+const HEADER_SIZE: usize = 56;
+/// The tree's active index follows its sequence number
+const ACTIVE_INDEX_OFFSET: usize = HEADER_SIZE + 8;
+/// Changelogs follow the active index and buffer size
+const CHANGELOGS_OFFSET: usize = ACTIVE_INDEX_OFFSET + 16;
+/// A changelog's root, path, leaf index and padding
+const CHANGELOG_SIZE: usize = 32 + 32 * MAX_DEPTH as usize + 8;
+/// Changelogs, then the rightmost path and leaf with its index and padding;
+/// the tree has no canopy
+const TREE_SPACE: usize = CHANGELOGS_OFFSET
+    + MAX_BUFFER_SIZE as usize * CHANGELOG_SIZE
+    + 32 * MAX_DEPTH as usize
+    + 32
+    + 8;
+
+/// Mirror of a tree's leaves, for the roots and proofs an indexer would
+/// serve
+struct LocalTree {
+    leaves: Vec<[u8; 32]>,
+}
+
+impl LocalTree {
+    fn new() -> Self {
+        Self {
+            leaves: vec![[0; 32]; 1 << MAX_DEPTH],
+        }
+    }
+
+    /// Every level of the tree, from the leaves up to the root
+    fn levels(&self) -> Vec<Vec<[u8; 32]>> {
+        let mut levels = vec![self.leaves.clone()];
+        while levels.last().unwrap().len() > 1 {
+            let parents = levels
+                .last()
+                .unwrap()
+                .chunks(2)
+                .map(|pair| keccak::hashv(&[&pair[0], &pair[1]]).to_bytes())
+                .collect();
+            levels.push(parents);
+        }
+        levels
+    }
+
+    fn root(&self) -> [u8; 32] {
+        self.levels().last().unwrap()[0]
+    }
+
+    fn proof(&self, index: u32) -> Vec<Pubkey> {
+        let index = index as usize;
+        self.levels()[..MAX_DEPTH as usize]
+            .iter()
+            .enumerate()
+            .map(|(level, nodes)| Pubkey::new_from_array(nodes[(index >> level) ^ 1]))
+            .collect()
+    }
+}
+
+/// Root of `tree` as of its latest change
+fn onchain_root(rpc: &RpcClient, tree: &Pubkey) -> [u8; 32] {
+    let data = rpc.get_account_data(tree).unwrap();
+    let active_index = u64::from_le_bytes(
+        data[ACTIVE_INDEX_OFFSET..ACTIVE_INDEX_OFFSET + 8]
+            .try_into()
+            .unwrap(),
+    ) as usize;
+    let offset = CHANGELOGS_OFFSET + active_index * CHANGELOG_SIZE;
+    data[offset..offset + 32].try_into().unwrap()
+}
+
+/// Allocate a tree account owned by SPL Account Compression and initialize
+/// it with `init`
+fn create_tree(
+    rpc: &RpcClient,
+    merchant: &Keypair,
+    init: impl FnOnce(&Pubkey) -> Instruction,
+) -> Pubkey {
+    let tree = Keypair::new();
+    send(
+        rpc,
+        merchant,
+        &[
+            create_account(
+                &merchant.pubkey(),
+                &tree.pubkey(),
+                rpc.get_minimum_balance_for_rent_exemption(TREE_SPACE)
+                    .unwrap(),
+                TREE_SPACE as u64,
+                &ACCOUNT_COMPRESSION_ID,
+            ),
+            init(&tree.pubkey()),
+        ],
+        &[&tree],
+    );
+    tree.pubkey()
+}
+
+fn token_balance(rpc: &RpcClient, account: &Pubkey) -> u64 {
+    TokenAccountState::unpack(&rpc.get_account_data(account).unwrap()[..TokenAccountState::LEN])
+        .unwrap()
+        .amount
+}
+
+#[test]
+#[ignore = "needs a local validator running the program"]
+fn processes_compressed_affiliate_sales() {
+    let client = RedioClient::new(RPC_URL);
+    let rpc = client.rpc();
+    let token_program = spl_token_2022::ID;
+    let merchant = Keypair::new();
+    let wallet = Keypair::new().pubkey();
+    fund(rpc, &merchant);
+
+    let mint = Keypair::new();
+    let merchant_usdc = token_account(&merchant.pubkey(), &mint.pubkey());
+    let affiliate_usdc = token_account(&wallet, &mint.pubkey());
+    send(
+        rpc,
+        &merchant,
+        &[
+            create_account(
+                &merchant.pubkey(),
+                &mint.pubkey(),
+                rpc.get_minimum_balance_for_rent_exemption(Mint::LEN)
+                    .unwrap(),
+                Mint::LEN as u64,
+                &token_program,
+            ),
+            initialize_mint2(&token_program, &mint.pubkey(), &merchant.pubkey(), None, 6).unwrap(),
+            create_associated_token_account(
+                &merchant.pubkey(),
+                &merchant.pubkey(),
+                &mint.pubkey(),
+                &token_program,
+            ),
+            create_associated_token_account(
+                &merchant.pubkey(),
+                &wallet,
+                &mint.pubkey(),
+                &token_program,
+            ),
+            mint_to(
+                &token_program,
+                &mint.pubkey(),
+                &merchant_usdc,
+                &merchant.pubkey(),
+                &[],
+                1_000_000_000,
+            )
+            .unwrap(),
+        ],
+        &[&mint],
+    );
+
+    let (merchant_pool, _) = pda::find_pool_address(&merchant.pubkey(), POOL_ID);
+    send(
+        rpc,
+        &merchant,
+        &[instructions::initialize_pool(
+            &merchant.pubkey(),
+            &merchant.pubkey(),
+            client.next_pool_index(&merchant.pubkey()).unwrap(),
+            &mint.pubkey(),
+            &token_program,
+            InitializePoolArgs {
+                pool_id: POOL_ID.to_string(),
+                commission_rate: 500,
+                initial_deposit: 100_000_000,
+            },
+        )],
+        &[],
+    );
+
+    let affiliate_tree = create_tree(rpc, &merchant, |tree| {
+        instructions::init_affiliate_tree(
+            &merchant_pool,
+            &merchant.pubkey(),
+            tree,
+            MAX_DEPTH,
+            MAX_BUFFER_SIZE,
+        )
+    });
+    let mut affiliates = LocalTree::new();
+    assert_eq!(onchain_root(rpc, &affiliate_tree), affiliates.root());
+
+    // The record's creation time is only known from the event
+    let signature = send(
+        rpc,
+        &merchant,
+        &[instructions::add_compressed_affiliate(
+            &merchant_pool,
+            &merchant.pubkey(),
+            &affiliate_tree,
+            &wallet,
+            "TREE01",
+        )],
+        &[],
+    );
+    let affiliate: CompressedAffiliate = events(rpc, &signature)
+        .into_iter()
+        .find_map(|event| match event {
+            RedioEvent::CompressedAffiliateAdded(added) => Some(added.affiliate),
+            _ => None,
+        })
+        .unwrap();
+    affiliates.leaves[affiliate.leaf_index as usize] = affiliate.leaf(&merchant_pool);
+    assert_eq!(onchain_root(rpc, &affiliate_tree), affiliates.root());
+
+    let signature = send(
+        rpc,
+        &merchant,
+        &[instructions::process_compressed_sale(
+            &merchant_pool,
+            &affiliate_tree,
+            None,
+            &mint.pubkey(),
+            &token_program,
+            &merchant.pubkey(),
+            CompressedSaleArgs {
+                sale_amount: 10_000_000,
+                order_id: "compressed-sale-1".to_string(),
+                root: affiliates.root(),
+                affiliate: affiliate.clone(),
+            },
+            &affiliates.proof(affiliate.leaf_index),
+        )],
+        &[],
+    );
+
+    let updated = events(rpc, &signature)
+        .into_iter()
+        .find_map(|event| match event {
+            RedioEvent::CompressedSaleProcessed(sale) => Some(sale.affiliate),
+            _ => None,
+        })
+        .unwrap();
+    assert_eq!(updated.total_earned, 500_000);
+    assert_eq!(updated.sales_count, 1);
+    assert_eq!(updated.total_sale_volume, 10_000_000);
+    assert_eq!(token_balance(rpc, &affiliate_usdc), 500_000);
+    client
+        .fetch_sale_receipt(&merchant_pool, "compressed-sale-1")
+        .unwrap();
+    assert!(logs(rpc, &signature)
+        .iter()
+        .any(|log| log.contains("Memo") && log.contains("compressed-sale-1")));
+
+    // The affiliate's leaf was replaced with the updated record
+    affiliates.leaves[updated.leaf_index as usize] = updated.leaf(&merchant_pool);
+    assert_eq!(onchain_root(rpc, &affiliate_tree), affiliates.root());
+
+    // Once the pool has a receipt tree, receipts are appended to it instead
+    let receipt_tree = create_tree(rpc, &merchant, |tree| {
+        instructions::init_receipt_tree(
+            &merchant_pool,
+            &merchant.pubkey(),
+            tree,
+            MAX_DEPTH,
+            MAX_BUFFER_SIZE,
+        )
+    });
+    let mut receipts = LocalTree::new();
+    let empty_root = onchain_root(rpc, &receipt_tree);
+    assert_eq!(empty_root, receipts.root());
+
+    let signature = send(
+        rpc,
+        &merchant,
+        &[instructions::process_compressed_sale(
+            &merchant_pool,
+            &affiliate_tree,
+            Some(&receipt_tree),
+            &mint.pubkey(),
+            &token_program,
+            &merchant.pubkey(),
+            CompressedSaleArgs {
+                sale_amount: 10_000_000,
+                order_id: "compressed-sale-2".to_string(),
+                root: affiliates.root(),
+                affiliate: updated.clone(),
+            },
+            &affiliates.proof(updated.leaf_index),
+        )],
+        &[],
+    );
+
+    let receipt: CompressedReceipt = events(rpc, &signature)
+        .into_iter()
+        .find_map(|event| match event {
+            RedioEvent::SaleReceiptCompressed(compressed) => Some(compressed.receipt),
+            _ => None,
+        })
+        .unwrap();
+    assert_eq!(receipt.affiliate, wallet);
+    assert_eq!(receipt.order_id, "compressed-sale-2");
+    assert_eq!(receipt.commission, 500_000);
+    assert_eq!(receipt.leaf_index, 0);
+    assert_eq!(token_balance(rpc, &affiliate_usdc), 1_000_000);
+    assert!(client
+        .fetch_sale_receipt(&merchant_pool, "compressed-sale-2")
+        .is_err());
+
+    let root = onchain_root(rpc, &receipt_tree);
+    assert_ne!(root, empty_root);
+    receipts.leaves[receipt.leaf_index as usize] = receipt.leaf();
+    assert_eq!(root, receipts.root());
+
+    send(
+        rpc,
+        &merchant,
+        &[instructions::verify_sale_receipt(
+            &merchant_pool,
+            &receipt_tree,
+            root,
+            receipt.clone(),
+            &receipts.proof(receipt.leaf_index),
+        )],
+        &[],
+    );
+}
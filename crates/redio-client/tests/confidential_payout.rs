@@ -0,0 +1,395 @@
+//! Pays a commission out confidentially against a local validator running
+//! the program, e.g. the one `anchor test` starts, which runs it after the
+//! TypeScript suite. Run by hand with
+//! `cargo test -p redio-client --test confidential_payout -- --ignored`
+
+mod common;
+
+use std::mem::size_of;
+
+use anchor_spl::{
+    associated_token::spl_associated_token_account::instruction::create_associated_token_account,
+    token_2022::spl_token_2022::{
+        self,
+        extension::{
+            confidential_transfer::{self, ConfidentialTransferAccount},
+            BaseStateWithExtensions, ExtensionType, StateWithExtensions,
+        },
+        instruction::{initialize_mint2, mint_to, reallocate},
+        solana_zk_sdk::{
+            encryption::{
+                auth_encryption::AeKey,
+                elgamal::{ElGamalCiphertext, ElGamalKeypair},
+                pedersen::PedersenOpening,
+                pod::{auth_encryption::PodAeCiphertext, elgamal::PodElGamalPubkey},
+            },
+            zk_elgamal_proof_program::{
+                self,
+                instruction::{ContextStateInfo, ProofInstruction},
+                proof_data::{PubkeyValidityProofData, ZkProofData},
+                state::ProofContextState,
+            },
+        },
+        state::{Account as TokenAccountState, Mint},
+    },
+};
+use bytemuck::Pod;
+use common::{fund, send, token_account, RPC_URL};
+use redio_client::{
+    args::{InitializePoolArgs, ProcessSaleArgs},
+    instructions, pda, PayoutMode, RedioClient,
+};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{
+    compute_budget::ComputeBudgetInstruction,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+};
+use solana_system_interface::instruction::create_account;
+use spl_token_confidential_transfer_proof_extraction::instruction::{ProofData, ProofLocation};
+use spl_token_confidential_transfer_proof_generation::transfer::transfer_split_proof_data;
+
+const POOL_ID: &str = "confidential-pool";
+const MAXIMUM_PENDING_BALANCE_CREDIT_COUNTER: u64 = 65_536;
+const PROOF_COMPUTE_UNITS: u32 = 400_000;
+
+/// Verify `proof_data` into a fresh context state account. Creation and
+/// verification go in separate transactions since range proofs leave no
+/// room for anything else
+fn verify_proof<T, U>(
+    rpc: &RpcClient,
+    payer: &Keypair,
+    proof_instruction: ProofInstruction,
+    proof_data: &T,
+) -> Pubkey
+where
+    T: Pod + ZkProofData<U>,
+    U: Pod,
+{
+    let context_state = Keypair::new();
+    let space = size_of::<ProofContextState<U>>();
+    send(
+        rpc,
+        payer,
+        &[create_account(
+            &payer.pubkey(),
+            &context_state.pubkey(),
+            rpc.get_minimum_balance_for_rent_exemption(space).unwrap(),
+            space as u64,
+            &zk_elgamal_proof_program::id(),
+        )],
+        &[&context_state],
+    );
+    send(
+        rpc,
+        payer,
+        &[
+            ComputeBudgetInstruction::set_compute_unit_limit(PROOF_COMPUTE_UNITS),
+            proof_instruction.encode_verify_proof(
+                Some(ContextStateInfo {
+                    context_state_account: &context_state.pubkey(),
+                    context_state_authority: &payer.pubkey(),
+                }),
+                proof_data,
+            ),
+        ],
+        &[],
+    );
+    context_state.pubkey()
+}
+
+fn ae_bytes(key: &AeKey, amount: u64) -> [u8; 36] {
+    bytemuck::cast(PodAeCiphertext::from(key.encrypt(amount)))
+}
+
+#[test]
+#[ignore = "needs a local validator running the program"]
+fn pays_out_commissions_confidentially() {
+    let client = RedioClient::new(RPC_URL);
+    let rpc = client.rpc();
+    let token_program = spl_token_2022::ID;
+
+    let merchant = Keypair::new();
+    let affiliate = Keypair::new();
+    fund(rpc, &merchant);
+    fund(rpc, &affiliate);
+
+    let mint = Keypair::new();
+    let mint_space = ExtensionType::try_calculate_account_len::<Mint>(&[
+        ExtensionType::ConfidentialTransferMint,
+    ])
+    .unwrap();
+    let merchant_usdc = token_account(&merchant.pubkey(), &mint.pubkey());
+    send(
+        rpc,
+        &merchant,
+        &[
+            create_account(
+                &merchant.pubkey(),
+                &mint.pubkey(),
+                rpc.get_minimum_balance_for_rent_exemption(mint_space)
+                    .unwrap(),
+                mint_space as u64,
+                &token_program,
+            ),
+            confidential_transfer::instruction::initialize_mint(
+                &token_program,
+                &mint.pubkey(),
+                None,
+                true,
+                None,
+            )
+            .unwrap(),
+            initialize_mint2(&token_program, &mint.pubkey(), &merchant.pubkey(), None, 6).unwrap(),
+            create_associated_token_account(
+                &merchant.pubkey(),
+                &merchant.pubkey(),
+                &mint.pubkey(),
+                &token_program,
+            ),
+            mint_to(
+                &token_program,
+                &mint.pubkey(),
+                &merchant_usdc,
+                &merchant.pubkey(),
+                &[],
+                1_000_000_000,
+            )
+            .unwrap(),
+        ],
+        &[&mint],
+    );
+
+    let (merchant_pool, _) = pda::find_pool_address(&merchant.pubkey(), POOL_ID);
+    send(
+        rpc,
+        &merchant,
+        &[
+            instructions::initialize_pool(
+                &merchant.pubkey(),
+                &merchant.pubkey(),
+                client.next_pool_index(&merchant.pubkey()).unwrap(),
+                &mint.pubkey(),
+                &token_program,
+                InitializePoolArgs {
+                    pool_id: POOL_ID.to_string(),
+                    commission_rate: 500,
+                    initial_deposit: 100_000_000,
+                },
+            ),
+            instructions::set_payout_mode(&merchant_pool, &merchant.pubkey(), PayoutMode::Claim),
+        ],
+        &[],
+    );
+
+    // The processor's keys, which the escrow's confidential balance is under
+    let escrow_elgamal = ElGamalKeypair::new_rand();
+    let escrow_aes = AeKey::new_rand();
+    let escrow_pubkey_proof = verify_proof(
+        rpc,
+        &merchant,
+        ProofInstruction::VerifyPubkeyValidity,
+        &PubkeyValidityProofData::new(&escrow_elgamal).unwrap(),
+    );
+    send(
+        rpc,
+        &merchant,
+        &[
+            instructions::configure_confidential_escrow(
+                &merchant_pool,
+                &merchant.pubkey(),
+                &mint.pubkey(),
+                &escrow_pubkey_proof,
+                ae_bytes(&escrow_aes, 0),
+                MAXIMUM_PENDING_BALANCE_CREDIT_COUNTER,
+            ),
+            instructions::set_confidential_payouts(
+                &merchant_pool,
+                &merchant.pubkey(),
+                &mint.pubkey(),
+                &token_program,
+                true,
+            ),
+        ],
+        &[],
+    );
+
+    let affiliate_elgamal = ElGamalKeypair::new_rand();
+    let affiliate_aes = AeKey::new_rand();
+    let affiliate_elgamal_pubkey: [u8; 32] =
+        bytemuck::cast(PodElGamalPubkey::from(affiliate_elgamal.pubkey_owned()));
+    let affiliate_usdc = token_account(&affiliate.pubkey(), &mint.pubkey());
+    let affiliate_pubkey_proof = PubkeyValidityProofData::new(&affiliate_elgamal).unwrap();
+    send(
+        rpc,
+        &merchant,
+        &[
+            instructions::add_affiliate(
+                &merchant_pool,
+                &merchant.pubkey(),
+                &merchant.pubkey(),
+                &affiliate.pubkey(),
+                0,
+                "CONF01",
+                None,
+                false,
+            ),
+            instructions::activate_self(&merchant_pool, &affiliate.pubkey()),
+            instructions::set_confidential_payout_key(
+                &merchant_pool,
+                &affiliate.pubkey(),
+                Some(affiliate_elgamal_pubkey),
+            ),
+        ],
+        &[&affiliate],
+    );
+    let mut affiliate_setup = vec![
+        ComputeBudgetInstruction::set_compute_unit_limit(PROOF_COMPUTE_UNITS),
+        create_associated_token_account(
+            &merchant.pubkey(),
+            &affiliate.pubkey(),
+            &mint.pubkey(),
+            &token_program,
+        ),
+        reallocate(
+            &token_program,
+            &affiliate_usdc,
+            &merchant.pubkey(),
+            &affiliate.pubkey(),
+            &[],
+            &[ExtensionType::ConfidentialTransferAccount],
+        )
+        .unwrap(),
+    ];
+    affiliate_setup.extend(
+        confidential_transfer::instruction::configure_account(
+            &token_program,
+            &affiliate_usdc,
+            &mint.pubkey(),
+            affiliate_aes.encrypt(0).into(),
+            MAXIMUM_PENDING_BALANCE_CREDIT_COUNTER,
+            &affiliate.pubkey(),
+            &[],
+            ProofLocation::InstructionOffset(
+                1.try_into().unwrap(),
+                ProofData::InstructionData(&affiliate_pubkey_proof),
+            ),
+        )
+        .unwrap(),
+    );
+    send(rpc, &merchant, &affiliate_setup, &[&affiliate]);
+
+    send(
+        rpc,
+        &merchant,
+        &[instructions::process_sale(
+            &merchant_pool,
+            &affiliate.pubkey(),
+            &mint.pubkey(),
+            &token_program,
+            &merchant.pubkey(),
+            ProcessSaleArgs {
+                sale_amount: 10_000_000,
+                order_id: "confidential-1".to_string(),
+                commission_override_bps: None,
+                memo: None,
+                metadata_hash: None,
+                idempotent: false,
+            },
+            false,
+            None,
+            None,
+            None,
+            None,
+            None,
+            false,
+            None,
+            false,
+            &[],
+        )],
+        &[],
+    );
+    let (affiliate_account, _) = pda::find_affiliate_address(&merchant_pool, &affiliate.pubkey());
+    let amount = client
+        .fetch_affiliate(&affiliate_account)
+        .unwrap()
+        .claimable;
+    assert!(amount > 0);
+
+    // The payout deposits `amount` into the escrow's zeroed confidential
+    // balance and applies it, which leaves it encrypted under a zero opening
+    let available_balance: ElGamalCiphertext = escrow_elgamal
+        .pubkey()
+        .encrypt_with(amount, &PedersenOpening::default());
+    let proofs = transfer_split_proof_data(
+        &available_balance,
+        &escrow_aes.encrypt(amount),
+        amount,
+        &escrow_elgamal,
+        &escrow_aes,
+        affiliate_elgamal.pubkey(),
+        None,
+    )
+    .unwrap();
+    let equality_proof = verify_proof(
+        rpc,
+        &merchant,
+        ProofInstruction::VerifyCiphertextCommitmentEquality,
+        &proofs.equality_proof_data,
+    );
+    let ciphertext_validity_proof = verify_proof(
+        rpc,
+        &merchant,
+        ProofInstruction::VerifyBatchedGroupedCiphertext3HandlesValidity,
+        &proofs.ciphertext_validity_proof_data,
+    );
+    let range_proof = verify_proof(
+        rpc,
+        &merchant,
+        ProofInstruction::VerifyBatchedRangeProofU128,
+        &proofs.range_proof_data,
+    );
+
+    send(
+        rpc,
+        &merchant,
+        &[
+            ComputeBudgetInstruction::set_compute_unit_limit(PROOF_COMPUTE_UNITS),
+            instructions::pay_out_confidential(
+                &merchant_pool,
+                &mint.pubkey(),
+                &merchant.pubkey(),
+                &affiliate.pubkey(),
+                &affiliate_usdc,
+                [equality_proof, ciphertext_validity_proof, range_proof],
+                ae_bytes(&escrow_aes, amount),
+                ae_bytes(&escrow_aes, 0),
+            ),
+        ],
+        &[],
+    );
+
+    assert_eq!(
+        client
+            .fetch_affiliate(&affiliate_account)
+            .unwrap()
+            .claimable,
+        0
+    );
+    let data = rpc.get_account_data(&affiliate_usdc).unwrap();
+    let state = StateWithExtensions::<TokenAccountState>::unpack(&data).unwrap();
+    let extension = state
+        .get_extension::<ConfidentialTransferAccount>()
+        .unwrap();
+    assert_eq!(u64::from(extension.pending_balance_credit_counter), 1);
+    let decrypt = |ciphertext| {
+        affiliate_elgamal
+            .secret()
+            .decrypt_u32(&ElGamalCiphertext::try_from(ciphertext).unwrap())
+            .unwrap()
+    };
+    let pending_lo = decrypt(extension.pending_balance_lo);
+    let pending_hi = decrypt(extension.pending_balance_hi);
+    assert_eq!(pending_lo + (pending_hi << 16), amount);
+    assert_eq!(state.base.amount, 0);
+}
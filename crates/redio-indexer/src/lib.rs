@@ -0,0 +1,50 @@
+//! Event indexer for redio-contract.
+//!
+//! Subscribes to the program's logs over websocket, decodes the events it
+//! emits and stores them in SQLite or Postgres behind [`Store`], which also
+//! provides the query API.
+
+pub mod store;
+
+pub use store::{EventRow, SaleRow, Store};
+
+use futures_util::StreamExt;
+use redio_client::{events::parse_logs, PROGRAM_ID};
+use solana_client::{
+    nonblocking::pubsub_client::PubsubClient,
+    rpc_config::{RpcTransactionLogsConfig, RpcTransactionLogsFilter},
+};
+use solana_sdk::commitment_config::CommitmentConfig;
+
+pub type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// Index events from `ws_url` into `store` until the subscription closes.
+/// Failed transactions are skipped; re-delivered events are ignored by the
+/// store, so callers can simply reconnect.
+pub async fn run(ws_url: &str, store: &Store) -> Result<(), Error> {
+    let client = PubsubClient::new(ws_url).await?;
+    let (mut logs, unsubscribe) = client
+        .logs_subscribe(
+            RpcTransactionLogsFilter::Mentions(vec![PROGRAM_ID.to_string()]),
+            RpcTransactionLogsConfig {
+                commitment: Some(CommitmentConfig::confirmed()),
+            },
+        )
+        .await?;
+
+    while let Some(response) = logs.next().await {
+        if response.value.err.is_some() {
+            continue;
+        }
+
+        let slot = response.context.slot;
+        for (index, event) in parse_logs(&response.value.logs).iter().enumerate() {
+            store
+                .insert(&response.value.signature, index, slot, event)
+                .await?;
+        }
+    }
+
+    unsubscribe().await;
+    Ok(())
+}
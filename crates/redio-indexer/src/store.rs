@@ -0,0 +1,270 @@
+use redio_client::events::RedioEvent;
+use solana_sdk::pubkey::Pubkey;
+use sqlx::{any::install_default_drivers, AnyPool, FromRow};
+
+use crate::Error;
+
+/// Statements are kept to the SQL subset shared by SQLite and Postgres
+const SCHEMA: &[&str] = &[
+    "CREATE TABLE IF NOT EXISTS events (
+        signature TEXT NOT NULL,
+        event_index BIGINT NOT NULL,
+        slot BIGINT NOT NULL,
+        name TEXT NOT NULL,
+        pool TEXT,
+        PRIMARY KEY (signature, event_index)
+    )",
+    "CREATE TABLE IF NOT EXISTS sales (
+        signature TEXT NOT NULL,
+        event_index BIGINT NOT NULL,
+        slot BIGINT NOT NULL,
+        pool TEXT NOT NULL,
+        affiliate TEXT NOT NULL,
+        affiliate_wallet TEXT NOT NULL,
+        mint TEXT NOT NULL,
+        sale_amount BIGINT NOT NULL,
+        commission BIGINT NOT NULL,
+        timestamp BIGINT NOT NULL,
+        PRIMARY KEY (signature, event_index)
+    )",
+    "CREATE INDEX IF NOT EXISTS events_pool ON events (pool)",
+    "CREATE INDEX IF NOT EXISTS sales_pool ON sales (pool)",
+    "CREATE INDEX IF NOT EXISTS sales_affiliate ON sales (affiliate)",
+];
+
+#[derive(Debug, Clone, FromRow)]
+pub struct EventRow {
+    pub signature: String,
+    pub event_index: i64,
+    pub slot: i64,
+    pub name: String,
+    pub pool: Option<String>,
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct SaleRow {
+    pub signature: String,
+    pub event_index: i64,
+    pub slot: i64,
+    pub pool: String,
+    pub affiliate: String,
+    pub affiliate_wallet: String,
+    pub mint: String,
+    pub sale_amount: i64,
+    pub commission: i64,
+    pub timestamp: i64,
+}
+
+/// Event storage and query API over SQLite (`sqlite://...`) or Postgres
+/// (`postgres://...`)
+pub struct Store {
+    pool: AnyPool,
+}
+
+impl Store {
+    pub async fn connect(database_url: &str) -> Result<Self, Error> {
+        install_default_drivers();
+        let pool = AnyPool::connect(database_url).await?;
+        for statement in SCHEMA {
+            sqlx::query(statement).execute(&pool).await?;
+        }
+        Ok(Self { pool })
+    }
+
+    /// Store a decoded event; events already stored are ignored
+    pub async fn insert(
+        &self,
+        signature: &str,
+        index: usize,
+        slot: u64,
+        event: &RedioEvent,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            "INSERT INTO events (signature, event_index, slot, name, pool)
+             VALUES ($1, $2, $3, $4, $5)
+             ON CONFLICT DO NOTHING",
+        )
+        .bind(signature.to_string())
+        .bind(index as i64)
+        .bind(slot as i64)
+        .bind(event.name().to_string())
+        .bind(event_pool(event).map(|pool| pool.to_string()))
+        .execute(&self.pool)
+        .await?;
+
+        if let RedioEvent::SaleProcessed(sale) = event {
+            sqlx::query(
+                "INSERT INTO sales (signature, event_index, slot, pool, affiliate,
+                     affiliate_wallet, mint, sale_amount, commission, timestamp)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+                 ON CONFLICT DO NOTHING",
+            )
+            .bind(signature.to_string())
+            .bind(index as i64)
+            .bind(slot as i64)
+            .bind(sale.pool.to_string())
+            .bind(sale.affiliate.to_string())
+            .bind(sale.affiliate_wallet.to_string())
+            .bind(sale.mint.to_string())
+            .bind(sale.sale_amount as i64)
+            .bind(sale.commission as i64)
+            .bind(sale.timestamp)
+            .execute(&self.pool)
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Most recent events for `pool`, newest first
+    pub async fn events_for_pool(&self, pool: &Pubkey, limit: i64) -> Result<Vec<EventRow>, Error> {
+        Ok(sqlx::query_as(
+            "SELECT signature, event_index, slot, name, pool FROM events
+             WHERE pool = $1 ORDER BY slot DESC, event_index DESC LIMIT $2",
+        )
+        .bind(pool.to_string())
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    /// Most recent sales in `pool`, newest first
+    pub async fn sales_for_pool(&self, pool: &Pubkey, limit: i64) -> Result<Vec<SaleRow>, Error> {
+        Ok(sqlx::query_as(
+            "SELECT * FROM sales WHERE pool = $1
+             ORDER BY slot DESC, event_index DESC LIMIT $2",
+        )
+        .bind(pool.to_string())
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    /// Most recent sales attributed to the affiliate account `affiliate`
+    pub async fn sales_for_affiliate(
+        &self,
+        affiliate: &Pubkey,
+        limit: i64,
+    ) -> Result<Vec<SaleRow>, Error> {
+        Ok(sqlx::query_as(
+            "SELECT * FROM sales WHERE affiliate = $1
+             ORDER BY slot DESC, event_index DESC LIMIT $2",
+        )
+        .bind(affiliate.to_string())
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?)
+    }
+}
+
+fn event_pool(event: &RedioEvent) -> Option<Pubkey> {
+    match event {
+        RedioEvent::PoolInitialized(e) => Some(e.pool),
+        RedioEvent::PoolCommissionUpdated(e) => Some(e.pool),
+        RedioEvent::PoolDeactivated(e) => Some(e.pool),
+        RedioEvent::AffiliateAdded(e) => Some(e.pool),
+        RedioEvent::SaleProcessed(e) => Some(e.pool),
+        RedioEvent::AffiliateRemoved(e) => Some(e.pool),
+        RedioEvent::EscrowDeposited(e) => Some(e.pool),
+        RedioEvent::EscrowWithdrawn(e) => Some(e.pool),
+        RedioEvent::PoolMintAdded(e) => Some(e.pool),
+        RedioEvent::PoolMetadataUpdated(e) => Some(e.pool),
+        RedioEvent::PoolTermsUpdated(e) => Some(e.pool),
+        RedioEvent::TermsAccepted(e) => Some(e.pool),
+        RedioEvent::AffiliateRefIdUpdated(e) => Some(e.pool),
+        RedioEvent::PayoutModeUpdated(e) => Some(e.pool),
+        RedioEvent::CommissionsClaimed(e) => Some(e.pool),
+        RedioEvent::SaleRefunded(e) => Some(e.pool),
+        RedioEvent::PoolProcessorUpdated(e) => Some(e.pool),
+        RedioEvent::CommissionOverrideBoundsUpdated(e) => Some(e.pool),
+        RedioEvent::ClaimWindowUpdated(e) => Some(e.pool),
+        RedioEvent::UnclaimedSwept(e) => Some(e.pool),
+        RedioEvent::InactivityPeriodUpdated(e) => Some(e.pool),
+        RedioEvent::StaleAffiliateDeactivated(e) => Some(e.pool),
+        RedioEvent::TopUpScheduleUpdated(e) => Some(e.pool),
+        RedioEvent::TopUpScheduleCancelled(e) => Some(e.pool),
+        RedioEvent::TopUpExecuted(e) => Some(e.pool),
+        RedioEvent::CommissionCapUpdated(e) => Some(e.pool),
+        RedioEvent::AlreadyProcessed(e) => Some(e.pool),
+        RedioEvent::LeaderboardInitialized(e) => Some(e.pool),
+        RedioEvent::CampaignCreated(e) => Some(e.pool),
+        RedioEvent::CampaignEnded(e) => Some(e.pool),
+        RedioEvent::EscrowTransferred(e) => Some(e.pool),
+        RedioEvent::PayoutThresholdUpdated(e) => Some(e.pool),
+        RedioEvent::ManagerUpdated(e) => Some(e.pool),
+        RedioEvent::FirstSaleBountyUpdated(e) => Some(e.pool),
+        RedioEvent::ReferralBountyPaid(e) => Some(e.pool),
+        RedioEvent::ProgramPauseUpdated(_) => None,
+        RedioEvent::PoolFlagged(e) => Some(e.pool),
+        RedioEvent::PoolFlagCleared(e) => Some(e.pool),
+        RedioEvent::WithdrawalCosignerUpdated(e) => Some(e.pool),
+        RedioEvent::DailyWithdrawalLimitUpdated(e) => Some(e.pool),
+        RedioEvent::WithdrawalDestinationProposed(e) => Some(e.pool),
+        RedioEvent::WithdrawalDestinationUpdated(e) => Some(e.pool),
+        RedioEvent::RevenueAccountUpdated(e) => Some(e.pool),
+        RedioEvent::HoldbackPeriodUpdated(e) => Some(e.pool),
+        RedioEvent::HoldbackReleased(e) => Some(e.pool),
+        RedioEvent::PayoutAccountUpdated(e) => Some(e.pool),
+        RedioEvent::AffiliateLeft(e) => Some(e.pool),
+        RedioEvent::RateGracePeriodUpdated(e) => Some(e.pool),
+        RedioEvent::PoolGovernanceUpdated(e) => Some(e.pool),
+        RedioEvent::FeeExemptionUpdated(e) => Some(e.pool),
+        RedioEvent::FeeTiersUpdated(_) => None,
+        RedioEvent::TreasuryUpdated(_) => None,
+        RedioEvent::ProtocolFeesCollected(e) => Some(e.pool),
+        RedioEvent::AffiliateSnapshotTaken(e) => Some(e.pool),
+        RedioEvent::PeriodRotated(e) => Some(e.pool),
+        RedioEvent::AffiliateTreeInitialized(e) => Some(e.pool),
+        RedioEvent::CompressedAffiliateAdded(e) => Some(e.pool),
+        RedioEvent::CompressedSaleProcessed(e) => Some(e.pool),
+        RedioEvent::ReceiptTreeInitialized(e) => Some(e.pool),
+        RedioEvent::SaleReceiptCompressed(e) => Some(e.pool),
+        RedioEvent::AttributionWindowUpdated(e) => Some(e.pool),
+        RedioEvent::ClickRegistered(e) => Some(e.pool),
+        RedioEvent::ClicksRecorded(e) => Some(e.pool),
+        RedioEvent::RatePolicyUpdated(e) => Some(e.pool),
+        RedioEvent::AffiliateApproversUpdated(e) => Some(e.pool),
+        RedioEvent::AffiliateApproved(e) => Some(e.pool),
+        RedioEvent::SessionKeyCreated(e) => Some(e.pool),
+        RedioEvent::SessionKeyRevoked(e) => Some(e.pool),
+        RedioEvent::StatementGenerated(e) => Some(e.pool),
+        RedioEvent::PoolHeartbeat(e) => Some(e.pool),
+        RedioEvent::MerchantReputationSynced(e) => Some(e.pool),
+        RedioEvent::AffiliateApplicationSubmitted(e) => Some(e.pool),
+        RedioEvent::CommissionRateBandUpdated(_) => None,
+        RedioEvent::SplitSaleProcessed(e) => Some(e.pool),
+        RedioEvent::AffiliateGroupCreated(e) => Some(e.pool),
+        RedioEvent::GroupRateModifierUpdated(e) => Some(e.pool),
+        RedioEvent::AffiliateGroupChanged(e) => Some(e.pool),
+        RedioEvent::GroupPayoutAccountUpdated(e) => Some(e.pool),
+        RedioEvent::PoolCloned(e) => Some(e.pool),
+        RedioEvent::SaleGuardUpdated(e) => Some(e.pool),
+        RedioEvent::RateDecayUpdated(e) => Some(e.pool),
+        RedioEvent::InterestSynced(e) => Some(e.pool),
+        RedioEvent::EscrowAuthorityMigrated(e) => Some(e.pool),
+        RedioEvent::UpgradeGuardInitialized(_) => None,
+        RedioEvent::ConfigChangeQueued(_) => None,
+        RedioEvent::ConfigChangeCancelled(_) => None,
+        RedioEvent::ProgramHashUpdated(_) => None,
+        RedioEvent::ConfigTimelockUpdated(_) => None,
+        RedioEvent::AffiliateSelfActivated(e) => Some(e.pool),
+        RedioEvent::AffiliateTagsSet(e) => Some(e.pool),
+        RedioEvent::RegionUpdated(e) => Some(e.pool),
+        RedioEvent::PoolAbandonmentNoticed(e) => Some(e.pool),
+        RedioEvent::AbandonedPoolRecovered(e) => Some(e.pool),
+        RedioEvent::GuardiansUpdated(e) => Some(e.pool),
+        RedioEvent::MerchantRecoveryProposed(e) => Some(e.pool),
+        RedioEvent::MerchantRecoveryVetoed(e) => Some(e.pool),
+        RedioEvent::MerchantRecovered(e) => Some(e.pool),
+        RedioEvent::PayoutSplitsUpdated(e) => Some(e.pool),
+        RedioEvent::RewardModeUpdated(e) => Some(e.pool),
+        RedioEvent::StoreCreditRedeemed(e) => Some(e.pool),
+        RedioEvent::PaymentVerified(e) => Some(e.pool),
+        RedioEvent::CompressedPayoutsUpdated(e) => Some(e.pool),
+        RedioEvent::CompressedCommissionsPaid(e) => Some(e.pool),
+        RedioEvent::ConfidentialPayoutsUpdated(e) => Some(e.pool),
+        RedioEvent::ConfidentialPayoutKeyUpdated(e) => Some(e.pool),
+        RedioEvent::ConfidentialCommissionsPaid(e) => Some(e.pool),
+        RedioEvent::PaymentProofRequirementUpdated(e) => Some(e.pool),
+    }
+}
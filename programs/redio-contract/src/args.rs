@@ -0,0 +1,223 @@
+//! Typed instruction arguments.
+//!
+//! Each struct serializes exactly like the positional arguments it replaced,
+//! and its [`Validate::validate`] is the same check the program runs, so
+//! clients can reject bad input before sending a transaction.
+
+use anchor_lang::prelude::*;
+
+use crate::{
+    CompressedAffiliate, ErrorCode, MAX_AFFILIATE_BATCH, MAX_SALE_MEMO_LEN, MAX_SALE_SPLITS,
+};
+
+/// Longest ref, order and campaign id
+pub const MAX_ID_LEN: usize = 32;
+
+/// Longest pool id. Pool seeds use its hash, so it isn't bound by the
+/// 32-byte seed limit
+pub const MAX_POOL_ID_LEN: usize = 128;
+
+/// Checks an instruction runs on its arguments before touching any account
+pub trait Validate {
+    fn validate(&self) -> Result<()>;
+}
+
+/// A non-empty id of at most `MAX_ID_LEN` bytes, failing with `error`
+pub fn validate_id(id: &str, error: ErrorCode) -> Result<()> {
+    if id.is_empty() || id.len() > MAX_ID_LEN {
+        return Err(error.into());
+    }
+    Ok(())
+}
+
+/// A non-empty pool id of at most `MAX_POOL_ID_LEN` id characters
+pub fn validate_pool_id(pool_id: &str) -> Result<()> {
+    require!(
+        !pool_id.is_empty() && pool_id.len() <= MAX_POOL_ID_LEN,
+        ErrorCode::InvalidPoolId
+    );
+    require!(is_id_charset(pool_id), ErrorCode::InvalidPoolIdCharset);
+    Ok(())
+}
+
+/// A ref id: a valid id made only of id characters
+pub fn validate_ref_id(ref_id: &str) -> Result<()> {
+    validate_id(ref_id, ErrorCode::InvalidRefId)?;
+    require!(is_id_charset(ref_id), ErrorCode::InvalidRefIdCharset);
+    Ok(())
+}
+
+/// ASCII letters, digits and `-`. Keeps lookalike Unicode and differently
+/// normalized spellings of the same code out of ids and seeds
+pub fn is_id_charset(id: &str) -> bool {
+    id.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-')
+}
+
+/// A rate in basis points, at most 100%
+pub fn validate_bps(bps: u16) -> Result<()> {
+    require!(bps <= 10000, ErrorCode::InvalidCommissionRate);
+    Ok(())
+}
+
+/// A non-zero token amount
+pub fn validate_amount(amount: u64) -> Result<()> {
+    require!(amount > 0, ErrorCode::InvalidAmount);
+    Ok(())
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq)]
+pub struct InitializePoolArgs {
+    pub pool_id: String,
+    pub commission_rate: u16,
+    pub initial_deposit: u64,
+}
+
+impl Validate for InitializePoolArgs {
+    fn validate(&self) -> Result<()> {
+        validate_pool_id(&self.pool_id)?;
+        validate_bps(self.commission_rate)?;
+        validate_amount(self.initial_deposit)
+    }
+}
+
+/// `commission_override_bps` is also checked against the pool's override
+/// bounds on-chain
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq)]
+pub struct ProcessSaleArgs {
+    pub sale_amount: u64,
+    pub order_id: String,
+    pub commission_override_bps: Option<u16>,
+    pub memo: Option<String>,
+    pub metadata_hash: Option<[u8; 32]>,
+    pub idempotent: bool,
+}
+
+impl Validate for ProcessSaleArgs {
+    fn validate(&self) -> Result<()> {
+        validate_sale(self.sale_amount, &self.order_id, self.memo.as_deref())?;
+        if let Some(rate) = self.commission_override_bps {
+            validate_bps(rate)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq)]
+pub struct CampaignSaleArgs {
+    pub sale_amount: u64,
+    pub order_id: String,
+    pub memo: Option<String>,
+    pub metadata_hash: Option<[u8; 32]>,
+    pub idempotent: bool,
+}
+
+impl Validate for CampaignSaleArgs {
+    fn validate(&self) -> Result<()> {
+        validate_sale(self.sale_amount, &self.order_id, self.memo.as_deref())
+    }
+}
+
+/// `ends_at` of zero is open-ended
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq)]
+pub struct CreateCampaignArgs {
+    pub campaign_id: String,
+    pub commission_rate: u16,
+    pub budget: u64,
+    pub starts_at: i64,
+    pub ends_at: i64,
+    /// Tag bits an affiliate must all carry for its sales to count
+    pub required_tags: u32,
+}
+
+impl Validate for CreateCampaignArgs {
+    fn validate(&self) -> Result<()> {
+        validate_id(&self.campaign_id, ErrorCode::InvalidCampaignId)?;
+        validate_bps(self.commission_rate)?;
+        validate_amount(self.budget)?;
+        require!(
+            self.ends_at == 0 || self.ends_at > self.starts_at,
+            ErrorCode::InvalidCampaignWindow
+        );
+        Ok(())
+    }
+}
+
+/// Sale by a compressed affiliate. `affiliate` is its current record and
+/// `root` the tree root it was proven against
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq)]
+pub struct CompressedSaleArgs {
+    pub sale_amount: u64,
+    pub order_id: String,
+    pub root: [u8; 32],
+    pub affiliate: CompressedAffiliate,
+}
+
+impl Validate for CompressedSaleArgs {
+    fn validate(&self) -> Result<()> {
+        validate_sale(self.sale_amount, &self.order_id, None)
+    }
+}
+
+/// Sale split between affiliates. `weights_bps[i]` is the share of the
+/// commission owed to the `i`-th affiliate passed, and the weights add up
+/// to 10000
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq)]
+pub struct MultiSaleArgs {
+    pub sale_amount: u64,
+    pub order_id: String,
+    pub weights_bps: Vec<u16>,
+}
+
+impl Validate for MultiSaleArgs {
+    fn validate(&self) -> Result<()> {
+        validate_sale(self.sale_amount, &self.order_id, None)?;
+        require!(
+            !self.weights_bps.is_empty()
+                && self.weights_bps.len() <= MAX_SALE_SPLITS
+                && self.weights_bps.iter().all(|&weight| weight > 0)
+                && self
+                    .weights_bps
+                    .iter()
+                    .map(|&weight| u32::from(weight))
+                    .sum::<u32>()
+                    == 10000,
+            ErrorCode::InvalidSplitWeights
+        );
+        Ok(())
+    }
+}
+
+/// An affiliate carried over from an existing program
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq)]
+pub struct AffiliateImport {
+    pub wallet: Pubkey,
+    pub ref_id: String,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq)]
+pub struct AddAffiliatesBatchArgs {
+    pub affiliates: Vec<AffiliateImport>,
+}
+
+impl Validate for AddAffiliatesBatchArgs {
+    fn validate(&self) -> Result<()> {
+        require!(
+            !self.affiliates.is_empty() && self.affiliates.len() <= MAX_AFFILIATE_BATCH,
+            ErrorCode::InvalidAffiliateBatch
+        );
+        for affiliate in &self.affiliates {
+            validate_ref_id(&affiliate.ref_id)?;
+        }
+        Ok(())
+    }
+}
+
+fn validate_sale(sale_amount: u64, order_id: &str, memo: Option<&str>) -> Result<()> {
+    validate_amount(sale_amount)?;
+    validate_id(order_id, ErrorCode::InvalidOrderId)?;
+    require!(
+        memo.map_or(0, str::len) <= MAX_SALE_MEMO_LEN,
+        ErrorCode::InvalidMemo
+    );
+    Ok(())
+}
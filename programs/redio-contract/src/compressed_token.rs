@@ -0,0 +1,155 @@
+//! CPIs into Light Protocol's compressed-token program, which pays
+//! commissions as compressed token accounts in a Light state tree instead of
+//! rent-paying token accounts.
+//!
+//! The instruction is built by hand, like those in [`crate::compression`]:
+//! `light-compressed-token` is the program crate itself and doesn't build
+//! as a CPI dependency without its heap allocator and IDL build. The data is
+//! its `CompressedTokenInstructionDataTransfer` as of 2.0, and the test suite
+//! runs it against the mainnet programs Anchor.toml loads. Only compression
+//! out of a token account is used: no input compressed accounts, so no
+//! validity proof.
+
+use anchor_lang::{
+    prelude::*,
+    solana_program::{
+        instruction::{AccountMeta, Instruction},
+        program::invoke_signed,
+        pubkey,
+    },
+};
+
+pub const COMPRESSED_TOKEN_ID: Pubkey = pubkey!("cTokenmWW8bLPjZEBAUgYy3zKxQZW6VKi7bqNFEVv3m");
+pub const LIGHT_SYSTEM_ID: Pubkey = pubkey!("SySTEM1eSU2p4BGQfQpimFEWWSC1XDFeun3Nqzz3rT7");
+/// Light's account compression program, distinct from SPL Account Compression
+pub const LIGHT_ACCOUNT_COMPRESSION_ID: Pubkey =
+    pubkey!("compr6CUsB5m2jS4Y3831ztGSTnDpnKJTKS95d64XVq");
+
+const CPI_AUTHORITY_SEED: &[u8] = b"cpi_authority";
+const TOKEN_POOL_SEED: &[u8] = b"pool";
+
+// Anchor sighash of the compressed-token program's `transfer`
+const TRANSFER: [u8; 8] = [163, 52, 200, 231, 140, 3, 69, 186];
+
+/// Signer the compressed-token program calls the Light system program with
+pub fn find_cpi_authority() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[CPI_AUTHORITY_SEED], &COMPRESSED_TOKEN_ID)
+}
+
+/// Signer the Light system program calls account compression with
+pub fn find_account_compression_authority() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[CPI_AUTHORITY_SEED], &LIGHT_SYSTEM_ID)
+}
+
+/// Registration of the Light system program with account compression
+pub fn find_registered_program() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[LIGHT_SYSTEM_ID.as_ref()], &LIGHT_ACCOUNT_COMPRESSION_ID)
+}
+
+/// Token account holding `mint`'s compressed supply, created when the mint
+/// is registered with the compressed-token program
+pub fn find_token_pool(mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[TOKEN_POOL_SEED, mint.as_ref()], &COMPRESSED_TOKEN_ID)
+}
+
+/// Accounts of a compressing transfer; `authority` owns `source` and signs
+/// with `signer_seeds`
+pub struct CompressCpi<'a, 'info> {
+    pub compressed_token_program: &'a AccountInfo<'info>,
+    pub fee_payer: &'a AccountInfo<'info>,
+    pub authority: &'a AccountInfo<'info>,
+    pub cpi_authority: &'a AccountInfo<'info>,
+    pub light_system_program: &'a AccountInfo<'info>,
+    pub registered_program: &'a AccountInfo<'info>,
+    pub noop_program: &'a AccountInfo<'info>,
+    pub account_compression_authority: &'a AccountInfo<'info>,
+    pub account_compression_program: &'a AccountInfo<'info>,
+    pub token_pool: &'a AccountInfo<'info>,
+    pub source: &'a AccountInfo<'info>,
+    pub token_program: &'a AccountInfo<'info>,
+    pub system_program: &'a AccountInfo<'info>,
+    /// State tree the compressed token accounts are appended to
+    pub output_state_tree: &'a AccountInfo<'info>,
+    pub signer_seeds: &'a [&'a [&'a [u8]]],
+}
+
+impl CompressCpi<'_, '_> {
+    /// Move the sum of `outputs` from `source` into the token pool and
+    /// append a compressed token account of `mint` for each
+    /// `(owner, amount)`
+    pub fn compress(&self, mint: &Pubkey, outputs: &[(Pubkey, u64)]) -> Result<()> {
+        let total = outputs
+            .iter()
+            .try_fold(0u64, |total, (_, amount)| total.checked_add(*amount))
+            .ok_or(crate::ErrorCode::ArithmeticOverflow)?;
+
+        // Borsh `CompressedTokenInstructionDataTransfer`, less the trailing
+        // `with_transaction_hash` the program appends itself
+        let mut inputs = vec![0]; // proof: None
+        inputs.extend_from_slice(mint.as_ref());
+        inputs.push(0); // delegated_transfer: None
+        inputs.extend_from_slice(&0u32.to_le_bytes()); // no input accounts
+        inputs.extend_from_slice(&(outputs.len() as u32).to_le_bytes());
+        for (owner, amount) in outputs {
+            inputs.extend_from_slice(owner.as_ref());
+            inputs.extend_from_slice(&amount.to_le_bytes());
+            inputs.push(0); // lamports: None
+            inputs.push(0); // merkle_tree_index: the output state tree
+            inputs.push(0); // tlv: None
+        }
+        inputs.push(1); // is_compress
+        inputs.push(1);
+        inputs.extend_from_slice(&total.to_le_bytes());
+        inputs.push(0); // cpi_context: None
+        inputs.push(0); // lamports_change_account_merkle_tree_index: None
+
+        // `transfer` takes the serialized inputs as its one `Vec<u8>` arg
+        let mut data = TRANSFER.to_vec();
+        data.extend_from_slice(&(inputs.len() as u32).to_le_bytes());
+        data.extend_from_slice(&inputs);
+
+        let accounts = vec![
+            AccountMeta::new(self.fee_payer.key(), true),
+            AccountMeta::new_readonly(self.authority.key(), true),
+            AccountMeta::new_readonly(self.cpi_authority.key(), false),
+            AccountMeta::new_readonly(self.light_system_program.key(), false),
+            AccountMeta::new_readonly(self.registered_program.key(), false),
+            AccountMeta::new_readonly(self.noop_program.key(), false),
+            AccountMeta::new_readonly(self.account_compression_authority.key(), false),
+            AccountMeta::new_readonly(self.account_compression_program.key(), false),
+            AccountMeta::new_readonly(self.compressed_token_program.key(), false),
+            AccountMeta::new(self.token_pool.key(), false),
+            AccountMeta::new(self.source.key(), false),
+            AccountMeta::new_readonly(self.token_program.key(), false),
+            AccountMeta::new_readonly(self.system_program.key(), false),
+            AccountMeta::new(self.output_state_tree.key(), false),
+        ];
+        let infos = [
+            self.fee_payer.clone(),
+            self.authority.clone(),
+            self.cpi_authority.clone(),
+            self.light_system_program.clone(),
+            self.registered_program.clone(),
+            self.noop_program.clone(),
+            self.account_compression_authority.clone(),
+            self.account_compression_program.clone(),
+            self.compressed_token_program.clone(),
+            self.token_pool.clone(),
+            self.source.clone(),
+            self.token_program.clone(),
+            self.system_program.clone(),
+            self.output_state_tree.clone(),
+        ];
+
+        invoke_signed(
+            &Instruction {
+                program_id: self.compressed_token_program.key(),
+                accounts,
+                data,
+            },
+            &infos,
+            self.signer_seeds,
+        )?;
+        Ok(())
+    }
+}
@@ -0,0 +1,134 @@
+//! CPIs into SPL Account Compression, whose concurrent Merkle trees hold
+//! compressed affiliates and sale receipts as leaves.
+//!
+//! The instructions are built by hand: the `spl-account-compression` crate
+//! pins an older Anchor than this program.
+
+use anchor_lang::{
+    prelude::*,
+    solana_program::{
+        instruction::{AccountMeta, Instruction},
+        program::{invoke, invoke_signed},
+        pubkey,
+    },
+};
+
+pub const ACCOUNT_COMPRESSION_ID: Pubkey = pubkey!("cmtDvXumGCrqC1Age74AVPhSRVXJMd8PJS91L8KbNCK");
+/// Program the compression program logs changelogs through, for indexers
+pub const NOOP_ID: Pubkey = pubkey!("noopb9bkMVfRPU8AsbpTUg8AQkHtKwMYZiFUjNRtMmV");
+
+// Anchor sighashes of the compression program's instructions
+const INIT_EMPTY_MERKLE_TREE: [u8; 8] = [191, 11, 119, 7, 180, 107, 220, 110];
+const APPEND: [u8; 8] = [149, 120, 18, 222, 236, 225, 88, 203];
+const REPLACE_LEAF: [u8; 8] = [204, 165, 76, 100, 73, 147, 0, 128];
+const VERIFY_LEAF: [u8; 8] = [124, 220, 22, 223, 104, 10, 250, 224];
+
+/// Accounts every tree-modifying CPI takes; `authority` is the PDA the tree
+/// was created under and signs with `signer_seeds`
+pub struct TreeCpi<'a, 'info> {
+    pub compression_program: &'a AccountInfo<'info>,
+    pub merkle_tree: &'a AccountInfo<'info>,
+    pub authority: &'a AccountInfo<'info>,
+    pub noop_program: &'a AccountInfo<'info>,
+    pub signer_seeds: &'a [&'a [&'a [u8]]],
+}
+
+impl<'info> TreeCpi<'_, 'info> {
+    /// Initialize a tree account allocated by the caller for `max_depth`
+    /// and `max_buffer_size`
+    pub fn init_empty_merkle_tree(&self, max_depth: u32, max_buffer_size: u32) -> Result<()> {
+        let mut data = INIT_EMPTY_MERKLE_TREE.to_vec();
+        data.extend_from_slice(&max_depth.to_le_bytes());
+        data.extend_from_slice(&max_buffer_size.to_le_bytes());
+        self.invoke(data, &[])
+    }
+
+    pub fn append(&self, leaf: [u8; 32]) -> Result<()> {
+        let mut data = APPEND.to_vec();
+        data.extend_from_slice(&leaf);
+        self.invoke(data, &[])
+    }
+
+    /// Swap `previous_leaf` at `index` for `new_leaf`; the compression
+    /// program rejects it unless `proof` proves `previous_leaf` against `root`
+    pub fn replace_leaf(
+        &self,
+        root: [u8; 32],
+        previous_leaf: [u8; 32],
+        new_leaf: [u8; 32],
+        index: u32,
+        proof: &[AccountInfo<'info>],
+    ) -> Result<()> {
+        let mut data = REPLACE_LEAF.to_vec();
+        data.extend_from_slice(&root);
+        data.extend_from_slice(&previous_leaf);
+        data.extend_from_slice(&new_leaf);
+        data.extend_from_slice(&index.to_le_bytes());
+        self.invoke(data, proof)
+    }
+
+    fn invoke(&self, data: Vec<u8>, proof: &[AccountInfo<'info>]) -> Result<()> {
+        let mut accounts = vec![
+            AccountMeta::new(self.merkle_tree.key(), false),
+            AccountMeta::new_readonly(self.authority.key(), true),
+            AccountMeta::new_readonly(self.noop_program.key(), false),
+        ];
+        accounts.extend(
+            proof
+                .iter()
+                .map(|node| AccountMeta::new_readonly(node.key(), false)),
+        );
+        let mut infos = vec![
+            self.merkle_tree.clone(),
+            self.authority.clone(),
+            self.noop_program.clone(),
+        ];
+        infos.extend_from_slice(proof);
+
+        invoke_signed(
+            &Instruction {
+                program_id: self.compression_program.key(),
+                accounts,
+                data,
+            },
+            &infos,
+            self.signer_seeds,
+        )?;
+        Ok(())
+    }
+}
+
+/// Fail unless `proof` proves `leaf` at `index` against `root`, a root
+/// still in `merkle_tree`'s changelog. Needs no signer
+pub fn verify_leaf<'info>(
+    compression_program: &AccountInfo<'info>,
+    merkle_tree: &AccountInfo<'info>,
+    root: [u8; 32],
+    leaf: [u8; 32],
+    index: u32,
+    proof: &[AccountInfo<'info>],
+) -> Result<()> {
+    let mut data = VERIFY_LEAF.to_vec();
+    data.extend_from_slice(&root);
+    data.extend_from_slice(&leaf);
+    data.extend_from_slice(&index.to_le_bytes());
+
+    let mut accounts = vec![AccountMeta::new_readonly(merkle_tree.key(), false)];
+    accounts.extend(
+        proof
+            .iter()
+            .map(|node| AccountMeta::new_readonly(node.key(), false)),
+    );
+    let mut infos = vec![merkle_tree.clone()];
+    infos.extend_from_slice(proof);
+
+    invoke(
+        &Instruction {
+            program_id: compression_program.key(),
+            accounts,
+            data,
+        },
+        &infos,
+    )?;
+    Ok(())
+}
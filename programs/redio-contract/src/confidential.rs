@@ -0,0 +1,200 @@
+//! CPIs into Token-2022's confidential transfer extension, which pays
+//! commissions into an affiliate token account's encrypted balance.
+//!
+//! The escrow is configured for confidential transfers under ElGamal and AE
+//! keys the pool's processor holds. A payout deposits the commission from
+//! the escrow's public balance into its pending balance, applies it and
+//! transfers it out confidentially, all in one instruction, so the escrow's
+//! encrypted balance is back at zero afterwards.
+//!
+//! `ConfigureAccount` and `Transfer` read their proofs from context state
+//! accounts the processor verified them into beforehand, so they're built
+//! with spl-token-2022's `inner_*` builders, which don't append proof
+//! instructions.
+
+use anchor_lang::{prelude::*, solana_program::program::invoke_signed};
+use anchor_spl::token_2022::spl_token_2022::{
+    self,
+    extension::{
+        confidential_transfer::{
+            instruction::{
+                deposit, inner_apply_pending_balance, inner_configure_account, inner_transfer,
+            },
+            ConfidentialTransferAccount, ConfidentialTransferMint,
+        },
+        BaseStateWithExtensions, ExtensionType, StateWithExtensions,
+    },
+    instruction::reallocate,
+    state::{Account as TokenAccountState, Mint as MintState},
+};
+use spl_token_confidential_transfer_proof_extraction::instruction::ProofLocation;
+
+/// Whether `mint` is a Token-2022 mint with confidential transfers
+pub fn is_confidential_mint(mint: &AccountInfo) -> Result<bool> {
+    if *mint.owner != spl_token_2022::ID {
+        return Ok(false);
+    }
+    let data = mint.try_borrow_data()?;
+    let state = StateWithExtensions::<MintState>::unpack(&data)?;
+    Ok(state.get_extension::<ConfidentialTransferMint>().is_ok())
+}
+
+/// ElGamal public key `token_account` receives confidential transfers
+/// under, if it's configured for them
+pub fn elgamal_pubkey(token_account: &AccountInfo) -> Result<Option<[u8; 32]>> {
+    if *token_account.owner != spl_token_2022::ID {
+        return Ok(None);
+    }
+    let data = token_account.try_borrow_data()?;
+    let state = StateWithExtensions::<TokenAccountState>::unpack(&data)?;
+    Ok(state
+        .get_extension::<ConfidentialTransferAccount>()
+        .ok()
+        .map(|extension| bytemuck::cast(extension.elgamal_pubkey)))
+}
+
+fn pending_balance_credit_counter(token_account: &AccountInfo) -> Result<u64> {
+    let data = token_account.try_borrow_data()?;
+    let state = StateWithExtensions::<TokenAccountState>::unpack(&data)?;
+    let extension = state.get_extension::<ConfidentialTransferAccount>()?;
+    Ok(extension.pending_balance_credit_counter.into())
+}
+
+/// The escrow's side of confidential payouts; `authority` owns `escrow`
+/// and signs with `signer_seeds`
+pub struct ConfidentialCpi<'a, 'info> {
+    pub token_program: &'a AccountInfo<'info>,
+    pub escrow: &'a AccountInfo<'info>,
+    pub mint: &'a AccountInfo<'info>,
+    pub authority: &'a AccountInfo<'info>,
+    pub signer_seeds: &'a [&'a [&'a [u8]]],
+}
+
+impl<'info> ConfidentialCpi<'_, 'info> {
+    /// Grow the escrow for the confidential transfer extension, paid by
+    /// `payer`, and configure it under the ElGamal key `proof_context`
+    /// proves valid
+    pub fn configure(
+        &self,
+        payer: &AccountInfo<'info>,
+        system_program: &AccountInfo<'info>,
+        proof_context: &AccountInfo<'info>,
+        decryptable_zero_balance: [u8; 36],
+        maximum_pending_balance_credit_counter: u64,
+    ) -> Result<()> {
+        invoke_signed(
+            &reallocate(
+                self.token_program.key,
+                self.escrow.key,
+                payer.key,
+                self.authority.key,
+                &[],
+                &[ExtensionType::ConfidentialTransferAccount],
+            )?,
+            &[
+                self.escrow.clone(),
+                payer.clone(),
+                system_program.clone(),
+                self.authority.clone(),
+            ],
+            self.signer_seeds,
+        )?;
+
+        invoke_signed(
+            &inner_configure_account(
+                self.token_program.key,
+                self.escrow.key,
+                self.mint.key,
+                decryptable_zero_balance.into(),
+                maximum_pending_balance_credit_counter,
+                self.authority.key,
+                &[],
+                ProofLocation::ContextStateAccount(proof_context.key),
+            )?,
+            &[
+                self.escrow.clone(),
+                self.mint.clone(),
+                proof_context.clone(),
+                self.authority.clone(),
+            ],
+            self.signer_seeds,
+        )?;
+        Ok(())
+    }
+
+    /// Pay `amount` from the escrow's public balance into `destination`'s
+    /// pending balance. `new_decryptable_available_balance` is the escrow's
+    /// AE-encrypted balance once the deposit is applied and
+    /// `new_source_decryptable_available_balance` once the transfer is out;
+    /// the proofs are the transfer's equality, ciphertext validity and range
+    /// proof context accounts
+    #[allow(clippy::too_many_arguments)]
+    pub fn pay(
+        &self,
+        destination: &AccountInfo<'info>,
+        equality_proof: &AccountInfo<'info>,
+        ciphertext_validity_proof: &AccountInfo<'info>,
+        range_proof: &AccountInfo<'info>,
+        amount: u64,
+        decimals: u8,
+        new_decryptable_available_balance: [u8; 36],
+        new_source_decryptable_available_balance: [u8; 36],
+    ) -> Result<()> {
+        invoke_signed(
+            &deposit(
+                self.token_program.key,
+                self.escrow.key,
+                self.mint.key,
+                amount,
+                decimals,
+                self.authority.key,
+                &[],
+            )?,
+            &[
+                self.escrow.clone(),
+                self.mint.clone(),
+                self.authority.clone(),
+            ],
+            self.signer_seeds,
+        )?;
+
+        invoke_signed(
+            &inner_apply_pending_balance(
+                self.token_program.key,
+                self.escrow.key,
+                pending_balance_credit_counter(self.escrow)?,
+                new_decryptable_available_balance.into(),
+                self.authority.key,
+                &[],
+            )?,
+            &[self.escrow.clone(), self.authority.clone()],
+            self.signer_seeds,
+        )?;
+
+        invoke_signed(
+            &inner_transfer(
+                self.token_program.key,
+                self.escrow.key,
+                self.mint.key,
+                destination.key,
+                new_source_decryptable_available_balance.into(),
+                self.authority.key,
+                &[],
+                ProofLocation::ContextStateAccount(equality_proof.key),
+                ProofLocation::ContextStateAccount(ciphertext_validity_proof.key),
+                ProofLocation::ContextStateAccount(range_proof.key),
+            )?,
+            &[
+                self.escrow.clone(),
+                self.mint.clone(),
+                destination.clone(),
+                equality_proof.clone(),
+                ciphertext_validity_proof.clone(),
+                range_proof.clone(),
+                self.authority.clone(),
+            ],
+            self.signer_seeds,
+        )?;
+        Ok(())
+    }
+}
@@ -0,0 +1,298 @@
+//! Decoding of the events the program emits via `emit!`.
+//!
+//! [`decode_event`] turns an event's bytes, the base64-decoded payload of a
+//! `Program data:` log line, into a [`RedioEvent`]. It has no off-chain
+//! dependencies, so indexers and other programs inspecting redio's event
+//! data decode it the same way. Discriminators are Anchor's, the first 8
+//! bytes of `sha256("event:<Name>")`, and payloads are Borsh, so an event's
+//! layout only changes if its fields do.
+
+use anchor_lang::{AnchorDeserialize, Discriminator, Event};
+
+use crate::{
+    AbandonedPoolRecovered, AffiliateAdded, AffiliateApplicationSubmitted, AffiliateApproved,
+    AffiliateApproversUpdated, AffiliateGroupChanged, AffiliateGroupCreated, AffiliateLeft,
+    AffiliateRefIdUpdated, AffiliateRemoved, AffiliateSelfActivated, AffiliateSnapshotTaken,
+    AffiliateTagsSet, AffiliateTreeInitialized, AlreadyProcessed, AttributionWindowUpdated,
+    CampaignCreated, CampaignEnded, ClaimWindowUpdated, ClickRegistered, ClicksRecorded,
+    CommissionCapUpdated, CommissionOverrideBoundsUpdated, CommissionRateBandUpdated,
+    CommissionsClaimed, CompressedAffiliateAdded, CompressedCommissionsPaid,
+    CompressedPayoutsUpdated, CompressedSaleProcessed, ConfidentialCommissionsPaid,
+    ConfidentialPayoutKeyUpdated, ConfidentialPayoutsUpdated, ConfigChangeCancelled,
+    ConfigChangeQueued, ConfigTimelockUpdated, DailyWithdrawalLimitUpdated,
+    EscrowAuthorityMigrated, EscrowDeposited, EscrowTransferred, EscrowWithdrawn,
+    FeeExemptionUpdated, FeeTiersUpdated, FirstSaleBountyUpdated, GroupPayoutAccountUpdated,
+    GroupRateModifierUpdated, GuardiansUpdated, HoldbackPeriodUpdated, HoldbackReleased,
+    InactivityPeriodUpdated, InterestSynced, LeaderboardInitialized, ManagerUpdated,
+    MerchantRecovered, MerchantRecoveryProposed, MerchantRecoveryVetoed, MerchantReputationSynced,
+    PaymentProofRequirementUpdated, PaymentVerified, PayoutAccountUpdated, PayoutModeUpdated,
+    PayoutSplitsUpdated, PayoutThresholdUpdated, PeriodRotated, PoolAbandonmentNoticed, PoolCloned,
+    PoolCommissionUpdated, PoolDeactivated, PoolFlagCleared, PoolFlagged, PoolGovernanceUpdated,
+    PoolHeartbeat, PoolInitialized, PoolMetadataUpdated, PoolMintAdded, PoolProcessorUpdated,
+    PoolTermsUpdated, ProgramHashUpdated, ProgramPauseUpdated, ProtocolFeesCollected,
+    RateDecayUpdated, RateGracePeriodUpdated, RatePolicyUpdated, ReceiptTreeInitialized,
+    ReferralBountyPaid, RegionUpdated, RevenueAccountUpdated, RewardModeUpdated, SaleGuardUpdated,
+    SaleProcessed, SaleReceiptCompressed, SaleRefunded, SessionKeyCreated, SessionKeyRevoked,
+    SplitSaleProcessed, StaleAffiliateDeactivated, StatementGenerated, StoreCreditRedeemed,
+    TermsAccepted, TopUpExecuted, TopUpScheduleCancelled, TopUpScheduleUpdated, TreasuryUpdated,
+    UnclaimedSwept, UpgradeGuardInitialized, WithdrawalCosignerUpdated,
+    WithdrawalDestinationProposed, WithdrawalDestinationUpdated,
+};
+
+macro_rules! redio_events {
+    ($($event:ident),+ $(,)?) => {
+        /// Any event emitted by the program
+        pub enum RedioEvent {
+            $($event($event),)+
+        }
+
+        impl RedioEvent {
+            /// Event type name as declared in the program
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $(RedioEvent::$event(_) => stringify!($event),)+
+                }
+            }
+
+            /// The event's discriminator-prefixed bytes, as emitted
+            pub fn data(&self) -> Vec<u8> {
+                match self {
+                    $(RedioEvent::$event(event) => event.data(),)+
+                }
+            }
+        }
+
+        /// Name and discriminator of every event
+        pub const EVENT_DISCRIMINATORS: &[(&str, &[u8])] = &[
+            $((stringify!($event), $event::DISCRIMINATOR),)+
+        ];
+
+        /// Decode a single event from its discriminator-prefixed bytes; `None`
+        /// if they aren't a redio event
+        pub fn decode_event(data: &[u8]) -> Option<RedioEvent> {
+            if data.len() < 8 {
+                return None;
+            }
+            let (discriminator, mut payload) = data.split_at(8);
+            $(
+                if discriminator == $event::DISCRIMINATOR {
+                    return $event::deserialize(&mut payload).ok().map(RedioEvent::$event);
+                }
+            )+
+            None
+        }
+    };
+}
+
+redio_events!(
+    PoolInitialized,
+    PoolCommissionUpdated,
+    PoolDeactivated,
+    AffiliateAdded,
+    SaleProcessed,
+    AffiliateRemoved,
+    EscrowDeposited,
+    EscrowWithdrawn,
+    PoolMintAdded,
+    PoolMetadataUpdated,
+    PoolTermsUpdated,
+    TermsAccepted,
+    AffiliateRefIdUpdated,
+    PayoutModeUpdated,
+    CommissionsClaimed,
+    SaleRefunded,
+    PoolProcessorUpdated,
+    CommissionOverrideBoundsUpdated,
+    ClaimWindowUpdated,
+    UnclaimedSwept,
+    InactivityPeriodUpdated,
+    StaleAffiliateDeactivated,
+    TopUpScheduleUpdated,
+    TopUpScheduleCancelled,
+    TopUpExecuted,
+    CommissionCapUpdated,
+    AlreadyProcessed,
+    LeaderboardInitialized,
+    CampaignCreated,
+    CampaignEnded,
+    EscrowTransferred,
+    PayoutThresholdUpdated,
+    ManagerUpdated,
+    FirstSaleBountyUpdated,
+    ReferralBountyPaid,
+    ProgramPauseUpdated,
+    PoolFlagged,
+    PoolFlagCleared,
+    WithdrawalCosignerUpdated,
+    DailyWithdrawalLimitUpdated,
+    WithdrawalDestinationProposed,
+    WithdrawalDestinationUpdated,
+    RevenueAccountUpdated,
+    HoldbackPeriodUpdated,
+    HoldbackReleased,
+    PayoutAccountUpdated,
+    AffiliateLeft,
+    RateGracePeriodUpdated,
+    PoolGovernanceUpdated,
+    FeeExemptionUpdated,
+    FeeTiersUpdated,
+    TreasuryUpdated,
+    ProtocolFeesCollected,
+    AffiliateSnapshotTaken,
+    PeriodRotated,
+    AffiliateTreeInitialized,
+    CompressedAffiliateAdded,
+    CompressedSaleProcessed,
+    ReceiptTreeInitialized,
+    SaleReceiptCompressed,
+    AttributionWindowUpdated,
+    ClickRegistered,
+    ClicksRecorded,
+    RatePolicyUpdated,
+    AffiliateApproversUpdated,
+    AffiliateApproved,
+    SessionKeyCreated,
+    SessionKeyRevoked,
+    StatementGenerated,
+    PoolHeartbeat,
+    MerchantReputationSynced,
+    AffiliateApplicationSubmitted,
+    CommissionRateBandUpdated,
+    SplitSaleProcessed,
+    AffiliateGroupCreated,
+    GroupRateModifierUpdated,
+    AffiliateGroupChanged,
+    GroupPayoutAccountUpdated,
+    PoolCloned,
+    SaleGuardUpdated,
+    RateDecayUpdated,
+    InterestSynced,
+    EscrowAuthorityMigrated,
+    UpgradeGuardInitialized,
+    ConfigChangeQueued,
+    ConfigChangeCancelled,
+    ProgramHashUpdated,
+    ConfigTimelockUpdated,
+    AffiliateSelfActivated,
+    AffiliateTagsSet,
+    RegionUpdated,
+    PoolAbandonmentNoticed,
+    AbandonedPoolRecovered,
+    GuardiansUpdated,
+    MerchantRecoveryProposed,
+    MerchantRecoveryVetoed,
+    MerchantRecovered,
+    PayoutSplitsUpdated,
+    RewardModeUpdated,
+    StoreCreditRedeemed,
+    PaymentVerified,
+    CompressedPayoutsUpdated,
+    CompressedCommissionsPaid,
+    ConfidentialPayoutsUpdated,
+    ConfidentialPayoutKeyUpdated,
+    ConfidentialCommissionsPaid,
+    PaymentProofRequirementUpdated,
+);
+
+#[cfg(test)]
+mod tests {
+    use anchor_lang::{prelude::Pubkey, solana_program::hash::hash};
+
+    use super::*;
+
+    /// Zero bytes enough for every event's zero-valued fields, which Borsh
+    /// reads as empty strings and vecs, `None`, `false` and zero numbers
+    const ZERO_PAYLOAD: [u8; 4096] = [0; 4096];
+
+    fn zero_event(discriminator: &[u8]) -> Vec<u8> {
+        [discriminator, &ZERO_PAYLOAD[..]].concat()
+    }
+
+    #[test]
+    fn discriminators_are_anchor_event_hashes() {
+        for (name, discriminator) in EVENT_DISCRIMINATORS {
+            let preimage = format!("event:{name}");
+            assert_eq!(
+                *discriminator,
+                &hash(preimage.as_bytes()).to_bytes()[..8],
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn discriminators_are_unique() {
+        for (index, (name, discriminator)) in EVENT_DISCRIMINATORS.iter().enumerate() {
+            assert!(
+                EVENT_DISCRIMINATORS[index + 1..]
+                    .iter()
+                    .all(|(_, other)| other != discriminator),
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn every_event_decodes_and_reencodes() {
+        for (name, discriminator) in EVENT_DISCRIMINATORS {
+            let data = zero_event(discriminator);
+            let event = decode_event(&data).unwrap_or_else(|| panic!("{name} didn't decode"));
+            assert_eq!(event.name(), *name);
+
+            let encoded = event.data();
+            assert_eq!(encoded[..], data[..encoded.len()], "{name}");
+        }
+    }
+
+    #[test]
+    fn truncated_events_are_rejected() {
+        for (name, discriminator) in EVENT_DISCRIMINATORS {
+            assert!(decode_event(&discriminator[..7]).is_none(), "{name}");
+
+            let encoded = decode_event(&zero_event(discriminator)).unwrap().data();
+            if encoded.len() > 8 {
+                assert!(
+                    decode_event(&encoded[..encoded.len() - 1]).is_none(),
+                    "{name}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_discriminators_are_rejected() {
+        assert!(decode_event(&[0; 64]).is_none());
+        assert!(decode_event(&[]).is_none());
+    }
+
+    #[test]
+    fn pool_initialized_layout() {
+        let pool = Pubkey::new_from_array([1; 32]);
+        let merchant = Pubkey::new_from_array([2; 32]);
+        let event = PoolInitialized {
+            pool,
+            merchant,
+            pool_id: "pool".to_string(),
+            commission_rate: 500,
+            initial_deposit: 1_000_000,
+            timestamp: 1_700_000_000,
+        };
+
+        let mut expected = PoolInitialized::DISCRIMINATOR.to_vec();
+        expected.extend_from_slice(pool.as_ref());
+        expected.extend_from_slice(merchant.as_ref());
+        expected.extend_from_slice(&4u32.to_le_bytes());
+        expected.extend_from_slice(b"pool");
+        expected.extend_from_slice(&500u16.to_le_bytes());
+        expected.extend_from_slice(&1_000_000u64.to_le_bytes());
+        expected.extend_from_slice(&1_700_000_000i64.to_le_bytes());
+        assert_eq!(event.data(), expected);
+
+        let Some(RedioEvent::PoolInitialized(decoded)) = decode_event(&expected) else {
+            panic!("PoolInitialized didn't decode");
+        };
+        assert_eq!(decoded.pool, pool);
+        assert_eq!(decoded.pool_id, "pool");
+        assert_eq!(decoded.initial_deposit, 1_000_000);
+    }
+}
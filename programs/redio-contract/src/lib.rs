@@ -1,40 +1,245 @@
-use anchor_lang::prelude::*;
+//! USDC-based affiliate escrow payment system.
+//!
+//! Other Anchor programs can compose with redio-contract by depending on it
+//! with the `cpi` feature, which disables the entrypoint and exposes typed
+//! instruction builders under [`cpi`] and their account structs under
+//! [`cpi::accounts`]:
+//!
+//! ```ignore
+//! redio-contract = { version = "0.1.0", features = ["cpi"] }
+//! ```
+//!
+//! ```ignore
+//! use redio_contract::{
+//!     args::ProcessSaleArgs,
+//!     cpi::{self, accounts::ProcessSale},
+//! };
+//!
+//! let cpi_ctx = CpiContext::new(
+//!     ctx.accounts.redio_program.to_account_info(),
+//!     ProcessSale {
+//!         merchant_pool: ctx.accounts.merchant_pool.to_account_info(),
+//!         affiliate_account: ctx.accounts.affiliate_account.to_account_info(),
+//!         // ...
+//!     },
+//! );
+//! cpi::process_sale(
+//!     cpi_ctx,
+//!     ProcessSaleArgs {
+//!         sale_amount,
+//!         order_id,
+//!         commission_override_bps: None,
+//!         memo: None,
+//!         metadata_hash: None,
+//!         idempotent: false,
+//!     },
+//! )?;
+//! ```
+//!
+//! The on-chain account types ([`MerchantPool`], [`AffiliateAccount`]) and
+//! events are exported so callers can deserialize them directly. Instructions
+//! that take a variable number of accounts document their layouts in
+//! [`remaining`].
+
+use anchor_lang::{
+    prelude::*,
+    solana_program::{
+        keccak,
+        program_option::COption,
+        sysvar::instructions::{
+            self as instructions_sysvar, load_current_index_checked, load_instruction_at_checked,
+        },
+    },
+};
 use anchor_spl::{
-    associated_token::AssociatedToken,
+    associated_token::{self, AssociatedToken},
+    memo::{self, BuildMemo, Memo},
+    token_2022::{
+        spl_token_2022::{
+            extension::{
+                interest_bearing_mint::{BasisPoints, InterestBearingConfig},
+                BaseStateWithExtensions, StateWithExtensions,
+            },
+            instruction::TokenInstruction,
+            state::Mint as MintState,
+        },
+        Token2022,
+    },
     token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked},
 };
 
+pub mod args;
+pub mod compressed_token;
+pub mod compression;
+pub mod confidential;
+pub mod events;
+pub mod pda;
+pub mod remaining;
+
+use args::{
+    validate_amount, validate_id, validate_pool_id, validate_ref_id, AddAffiliatesBatchArgs,
+    AffiliateImport, CampaignSaleArgs, CompressedSaleArgs, CreateCampaignArgs, InitializePoolArgs,
+    MultiSaleArgs, ProcessSaleArgs, Validate, MAX_POOL_ID_LEN,
+};
+
+use pda::{
+    escrow_version_seed, AFFILIATE_ENTRY_SEED, AFFILIATE_SEED, AFFILIATE_SNAPSHOT_SEED,
+    AFFILIATE_TREE_SEED, CAMPAIGN_SEED, CONFIG_SEED, ESCROW_AUTHORITY_SEED, GROUP_SEED,
+    LEADERBOARD_SEED, PENDING_AFFILIATE_SEED, POOL_ENTRY_SEED, POOL_PERIOD_SEED, POOL_SEED,
+    RECEIPT_SEED, RECEIPT_TREE_SEED, REF_ID_SEED, REGION_SEED, REGISTRY_SEED, REPUTATION_SEED,
+    SESSION_KEY_SEED, STATEMENT_SEED, TOPUP_SEED, UPGRADE_GUARD_SEED,
+};
+
 declare_id!("CFQoHeX28aKhpgsLCSGM2zpou6RkRrwRoHVToWS2B6tQ");
 
+/// Settlement mints a pool can register on top of its primary mint
+pub const MAX_SETTLEMENT_MINTS: usize = 3;
+/// Slots in a pool's per-mint totals: the primary mint, then each settlement mint
+pub const MAX_POOL_MINTS: usize = MAX_SETTLEMENT_MINTS + 1;
+pub const MAX_DISPLAY_NAME_LEN: usize = 64;
+pub const MAX_METADATA_URI_LEN: usize = 200;
+pub const MAX_SALE_MEMO_LEN: usize = 128;
+/// Affiliates ranked on a pool's leaderboard
+pub const LEADERBOARD_SIZE: usize = 10;
+/// Largest share of a relayed claim a relayer may keep as its fee
+pub const MAX_RELAYER_FEE_BPS: u16 = 500;
+/// Length of the window `daily_withdrawal_limit` applies to
+pub const WITHDRAWAL_WINDOW_SECS: i64 = 86_400;
+/// Delay before a replacement withdrawal destination takes effect
+pub const DESTINATION_CHANGE_DELAY_SECS: i64 = 172_800;
+/// Share of a pool's sale volume its affiliates must exceed to flag it
+pub const FLAG_VOLUME_THRESHOLD_BPS: u16 = 5000;
+/// Tiers in the protocol fee schedule
+pub const MAX_FEE_TIERS: usize = 4;
+/// Highest protocol fee any tier may charge
+pub const MAX_PROTOCOL_FEE_BPS: u16 = 1000;
+/// Longest timelock an `UpgradeGuard` can put on config changes
+pub const MAX_CONFIG_TIMELOCK_SECS: i64 = 2_592_000;
+/// Length of the window a pool's volume is measured over for its fee tier
+pub const FEE_VOLUME_WINDOW_SECS: i64 = 2_592_000;
+/// Steps in a pool's dynamic rate schedule
+pub const MAX_RATE_STEPS: usize = 4;
+/// Operators a pool can require affiliate approvals from
+pub const MAX_APPROVERS: usize = 5;
+/// Inactivity after which the admin may give notice of recovering a pool's escrow
+pub const ABANDONMENT_PERIOD_SECS: i64 = 2 * 365 * SECS_PER_DAY;
+/// Wait between an abandonment notice and the recovery it announces
+pub const ABANDONMENT_NOTICE_SECS: i64 = 90 * SECS_PER_DAY;
+/// Token accounts an affiliate can split its payouts between
+pub const MAX_PAYOUT_SPLITS: usize = 4;
+/// Guardians a merchant can entrust with recovering its key
+pub const MAX_GUARDIANS: usize = 3;
+/// Wait between guardians proposing a new merchant key and recovering to
+/// it, during which the current key can veto
+pub const MERCHANT_RECOVERY_CHALLENGE_SECS: i64 = 14 * SECS_PER_DAY;
+/// Longest a session key can stay valid
+pub const MAX_SESSION_KEY_SECS: i64 = 86_400;
+/// Length of the days rate decay schedules count in
+pub const SECS_PER_DAY: i64 = 86_400;
+/// Year length Token-2022's interest-bearing extension compounds over,
+/// 365.24 days
+pub const SECS_PER_INTEREST_YEAR: u128 = 31_556_736;
+/// Fixed-point denominator of `MerchantPool::synced_interest_scale`
+pub const INTEREST_SCALE_ONE: u64 = 1_000_000_000;
+/// Finer fixed-point denominator interest growth is compounded over before
+/// it's rounded to `INTEREST_SCALE_ONE`
+const INTEREST_GROWTH_ONE: u128 = 1_000_000_000_000;
+/// Affiliates a single sale's commission can be split between
+pub const MAX_SALE_SPLITS: usize = 4;
+/// Affiliates `add_affiliates_batch` creates at once; with three accounts
+/// each it fits a transaction using an address lookup table
+pub const MAX_AFFILIATE_BATCH: usize = 10;
+/// Affiliates `pay_out_compressed` pays at once, in one compressing transfer
+pub const MAX_COMPRESSED_PAYOUTS: usize = 8;
+
 #[program]
 pub mod redio_contract {
     use super::*;
 
+    /// Create the program config with the upgrade authority as its admin
+    pub fn initialize_config(ctx: Context<InitializeConfig>) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        config.admin = ctx.accounts.admin.key();
+        config.paused = false;
+        config.fee_tiers = Vec::new();
+        config.treasury = config.admin;
+        config.min_commission_rate_bps = 0;
+        config.max_commission_rate_bps = 10000;
+        config.bump = ctx.bumps.config;
+
+        Ok(())
+    }
+
+    /// Halt or resume every state-changing instruction except escrow
+    /// withdrawals and commission claims. Incident-response brake
+    pub fn set_paused(ctx: Context<SetPaused>, paused: bool) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        config.paused = paused;
+
+        emit!(ProgramPauseUpdated {
+            paused,
+            admin: config.admin,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
     /// Initialize a merchant pool with escrow account
-    pub fn initialize_pool(
-        ctx: Context<InitializePool>,
-        pool_id: String,
-        commission_rate: u16,
-        initial_deposit: u64,
-    ) -> Result<()> {
+    pub fn initialize_pool(ctx: Context<InitializePool>, args: InitializePoolArgs) -> Result<()> {
+        args.validate()?;
+        let InitializePoolArgs {
+            pool_id,
+            commission_rate,
+            initial_deposit,
+        } = args;
         require!(
-            pool_id.len() > 0 && pool_id.len() <= 32,
-            ErrorCode::InvalidPoolId
+            ctx.accounts.config.allows_commission_rate(commission_rate),
+            ErrorCode::CommissionRateOutOfBand
         );
-        require!(commission_rate <= 10000, ErrorCode::InvalidCommissionRate);
-        require!(initial_deposit > 0, ErrorCode::InvalidAmount);
 
-        let pool = &mut ctx.accounts.merchant_pool;
+        let pool_key = ctx.accounts.merchant_pool.key();
+        // Zero-copy accounts start zeroed, so only non-default fields are set
+        let pool = &mut ctx.accounts.merchant_pool.load_init()?;
         pool.merchant = ctx.accounts.merchant.key();
-        pool.pool_id = pool_id.clone();
+        pool.creator = pool.merchant;
+        pool.pool_id_hash = pda::pool_id_hash(&pool_id);
+        pool.set_pool_id(&pool_id);
+        pool.processor = pool.merchant;
         pool.usdc_mint = ctx.accounts.usdc_mint.key();
         pool.commission_rate = commission_rate;
-        pool.total_volume = 0;
-        pool.total_commissions_paid = 0;
-        pool.is_active = true;
+        pool.payout_mode = PayoutMode::Push as u8;
+        pool.commission_cap_mode = CommissionCapMode::Reject as u8;
+        pool.previous_commission_rate = commission_rate;
+        pool.is_active = u8::from(true);
         pool.bump = ctx.bumps.merchant_pool;
         pool.escrow_bump = ctx.bumps.escrow_authority;
         pool.created_at = Clock::get()?.unix_timestamp;
+        pool.period_started_at = pool.created_at;
+
+        let registry = &mut ctx.accounts.merchant_registry;
+        registry.merchant = pool.merchant;
+        registry.bump = ctx.bumps.merchant_registry;
+
+        let entry = &mut ctx.accounts.pool_entry;
+        entry.merchant = pool.merchant;
+        entry.index = registry.pool_count;
+        entry.pool = pool_key;
+        entry.bump = ctx.bumps.pool_entry;
+
+        registry.pool_count = registry
+            .pool_count
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        let reputation = &mut ctx.accounts.merchant_reputation;
+        reputation.merchant = pool.merchant;
+        reputation.pools_created = registry.pool_count;
+        if reputation.first_pool_at == 0 {
+            reputation.first_pool_at = pool.created_at;
+        }
+        reputation.updated_at = pool.created_at;
+        reputation.bump = ctx.bumps.merchant_reputation;
 
         if initial_deposit > 0 {
             let decimals = ctx.accounts.usdc_mint.decimals;
@@ -54,7 +259,7 @@ pub mod redio_contract {
         }
 
         emit!(PoolInitialized {
-            pool: pool.key(),
+            pool: pool_key,
             merchant: pool.merchant,
             pool_id,
             commission_rate,
@@ -65,612 +270,12509 @@ pub mod redio_contract {
         Ok(())
     }
 
-    /// Update commission rate for a specific pool
+    /// Create a pool with the settings of one of the merchant's existing
+    /// pools, for recurring campaigns that shouldn't be set up from scratch.
+    /// Rates, rate steps, override bounds, caps and limits, windows, payout
+    /// and cap modes, terms, metadata, the processor, approvers, withdrawal
+    /// cosigner and destination, and governance carry over. Escrow funds,
+    /// stats, affiliates, settlement mints, the revenue account, leaderboard
+    /// and compression trees don't
+    pub fn clone_pool(ctx: Context<ClonePool>, new_pool_id: String) -> Result<()> {
+        validate_pool_id(&new_pool_id)?;
+
+        let source_key = ctx.accounts.source_pool.key();
+        let source = &ctx.accounts.source_pool.load()?;
+        require!(
+            ctx.accounts
+                .config
+                .allows_commission_rate(source.commission_rate),
+            ErrorCode::CommissionRateOutOfBand
+        );
+
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_init()?;
+        pool.merchant = source.merchant;
+        pool.creator = source.merchant;
+        pool.pool_id_hash = pda::pool_id_hash(&new_pool_id);
+        pool.set_pool_id(&new_pool_id);
+        pool.usdc_mint = source.usdc_mint;
+        pool.commission_rate = source.commission_rate;
+        pool.previous_commission_rate = source.commission_rate;
+        pool.min_override_bps = source.min_override_bps;
+        pool.max_override_bps = source.max_override_bps;
+        pool.rate_policy = source.rate_policy;
+        pool.rate_step_count = source.rate_step_count;
+        pool.rate_step_thresholds = source.rate_step_thresholds;
+        pool.rate_step_bps = source.rate_step_bps;
+        pool.rate_decay_idle_days = source.rate_decay_idle_days;
+        pool.rate_decay_restore_days = source.rate_decay_restore_days;
+        pool.rate_decay_bps = source.rate_decay_bps;
+        pool.rate_grace_period_secs = source.rate_grace_period_secs;
+        pool.max_commission_per_sale = source.max_commission_per_sale;
+        pool.commission_cap_mode = source.commission_cap_mode;
+        pool.first_sale_bounty = source.first_sale_bounty;
+        pool.payout_mode = source.payout_mode;
+        pool.holdback_secs = source.holdback_secs;
+        pool.claim_window_secs = source.claim_window_secs;
+        pool.inactivity_period_secs = source.inactivity_period_secs;
+        pool.attribution_window_secs = source.attribution_window_secs;
+        pool.cosign_threshold = source.cosign_threshold;
+        pool.daily_withdrawal_limit = source.daily_withdrawal_limit;
+        pool.withdrawal_cosigner = source.withdrawal_cosigner;
+        pool.withdrawal_destination = source.withdrawal_destination;
+        pool.processor = source.processor;
+        pool.approvers = source.approvers;
+        pool.approver_count = source.approver_count;
+        pool.approval_threshold = source.approval_threshold;
+        pool.governance = source.governance;
+        pool.governance_program = source.governance_program;
+        pool.terms_hash = source.terms_hash;
+        pool.display_name = source.display_name;
+        pool.display_name_len = source.display_name_len;
+        pool.metadata_uri = source.metadata_uri;
+        pool.metadata_uri_len = source.metadata_uri_len;
+        pool.is_active = u8::from(true);
+        pool.bump = ctx.bumps.merchant_pool;
+        pool.escrow_bump = ctx.bumps.escrow_authority;
+        pool.created_at = Clock::get()?.unix_timestamp;
+        pool.period_started_at = pool.created_at;
+
+        let registry = &mut ctx.accounts.merchant_registry;
+        let entry = &mut ctx.accounts.pool_entry;
+        entry.merchant = pool.merchant;
+        entry.index = registry.pool_count;
+        entry.pool = pool_key;
+        entry.bump = ctx.bumps.pool_entry;
+
+        registry.pool_count = registry
+            .pool_count
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        let reputation = &mut ctx.accounts.merchant_reputation;
+        reputation.merchant = pool.merchant;
+        reputation.pools_created = registry.pool_count;
+        if reputation.first_pool_at == 0 {
+            reputation.first_pool_at = pool.created_at;
+        }
+        reputation.updated_at = pool.created_at;
+        reputation.bump = ctx.bumps.merchant_reputation;
+
+        emit!(PoolCloned {
+            pool: pool_key,
+            merchant: pool.merchant,
+            pool_id: new_pool_id,
+            source_pool: source_key,
+            commission_rate: pool.commission_rate,
+            timestamp: pool.created_at,
+        });
+
+        Ok(())
+    }
+
+    /// Update commission rate for a specific pool. With `expiry_slot` or
+    /// `valid_until` set the change fails once that slot or time has passed,
+    /// so a transaction signed offline against a durable nonce or queued in
+    /// a multisig can't be executed long after it was approved
     pub fn update_pool_commission(
         ctx: Context<UpdatePoolCommission>,
         new_commission_rate: u16,
+        expiry_slot: Option<u64>,
+        valid_until: Option<i64>,
     ) -> Result<()> {
+        require_unexpired(expiry_slot, valid_until)?;
         require!(
             new_commission_rate <= 10000,
             ErrorCode::InvalidCommissionRate
         );
+        require!(
+            ctx.accounts
+                .config
+                .allows_commission_rate(new_commission_rate),
+            ErrorCode::CommissionRateOutOfBand
+        );
 
-        let pool = &mut ctx.accounts.merchant_pool;
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
         let old_rate = pool.commission_rate;
         pool.commission_rate = new_commission_rate;
 
+        // A rate cut opens a grace window in which affiliates added before
+        // it keep being paid at their snapshotted rate
+        let now = Clock::get()?.unix_timestamp;
+        let mut grace_ends_at = 0;
+        if new_commission_rate < old_rate {
+            pool.previous_commission_rate = old_rate;
+            pool.rate_changed_at = now;
+            if pool.rate_grace_period_secs > 0 {
+                grace_ends_at = now
+                    .checked_add(pool.rate_grace_period_secs)
+                    .ok_or(ErrorCode::ArithmeticOverflow)?;
+            }
+        }
+
         emit!(PoolCommissionUpdated {
-            pool: pool.key(),
+            pool: pool_key,
             merchant: pool.merchant,
-            pool_id: pool.pool_id.clone(),
+            pool_id: pool.pool_id().to_string(),
             old_rate,
             new_rate: new_commission_rate,
+            grace_ends_at,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Set how long affiliates keep their previous rate after a rate cut.
+    /// Zero applies cuts right away
+    pub fn set_rate_grace_period(
+        ctx: Context<SetRateGracePeriod>,
+        rate_grace_period_secs: i64,
+    ) -> Result<()> {
+        require!(
+            rate_grace_period_secs >= 0,
+            ErrorCode::InvalidRateGracePeriod
+        );
+
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        pool.rate_grace_period_secs = rate_grace_period_secs;
+
+        emit!(RateGracePeriodUpdated {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            rate_grace_period_secs,
             timestamp: Clock::get()?.unix_timestamp,
         });
 
         Ok(())
     }
 
-    /// Deactivate a pool
-    pub fn deactivate_pool(ctx: Context<DeactivatePool>) -> Result<()> {
-        let pool = &mut ctx.accounts.merchant_pool;
-        pool.is_active = false;
+    /// Mark the pool as DAO-owned: its merchant must be the SPL Governance
+    /// native treasury of `governance`, so rate changes, withdrawals and
+    /// every other merchant-signed instruction only go through proposals
+    /// executed by `governance_program`. `None` clears the mode
+    pub fn set_pool_governance(
+        ctx: Context<SetPoolGovernance>,
+        governance_program: Pubkey,
+        governance: Option<Pubkey>,
+    ) -> Result<()> {
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        if let Some(governance) = governance {
+            let (treasury, _) = pda::find_governance_treasury(&governance_program, &governance);
+            require!(treasury == pool.merchant, ErrorCode::NotGovernanceTreasury);
+            pool.governance_program = governance_program;
+        } else {
+            pool.governance_program = Pubkey::default();
+        }
+        pool.governance = governance.unwrap_or_default();
 
-        emit!(PoolDeactivated {
-            pool: pool.key(),
-            merchant: pool.merchant,
-            pool_id: pool.pool_id.clone(),
+        emit!(PoolGovernanceUpdated {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            governance_program: pool.governance_program,
+            governance,
             timestamp: Clock::get()?.unix_timestamp,
         });
 
         Ok(())
     }
 
-    /// Add an affiliate to the merchant's pool
-    pub fn add_affiliate(ctx: Context<AddAffiliate>, ref_id: String) -> Result<()> {
+    /// Set the pool's human-readable name and off-chain metadata URI
+    pub fn update_pool_metadata(
+        ctx: Context<UpdatePoolMetadata>,
+        display_name: String,
+        metadata_uri: String,
+    ) -> Result<()> {
+        require!(
+            display_name.len() <= MAX_DISPLAY_NAME_LEN,
+            ErrorCode::InvalidDisplayName
+        );
+        require!(
+            metadata_uri.len() <= MAX_METADATA_URI_LEN,
+            ErrorCode::InvalidMetadataUri
+        );
+
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        pool.set_display_name(&display_name);
+        pool.set_metadata_uri(&metadata_uri);
+
+        emit!(PoolMetadataUpdated {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            display_name,
+            metadata_uri,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Publish the hash of the pool's terms of service. Affiliates must
+    /// accept the current hash before they can be paid; an all-zero hash
+    /// removes the requirement.
+    pub fn set_pool_terms(ctx: Context<SetPoolTerms>, terms_hash: [u8; 32]) -> Result<()> {
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        pool.terms_hash = terms_hash;
+
+        emit!(PoolTermsUpdated {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            terms_hash,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Switch between paying commissions out on each sale and accruing
+    /// them for the affiliate to claim
+    pub fn set_payout_mode(ctx: Context<SetPayoutMode>, payout_mode: PayoutMode) -> Result<()> {
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        pool.payout_mode = payout_mode as u8;
+
+        emit!(PayoutModeUpdated {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            payout_mode,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Choose how sales in the primary mint reward affiliates: commissions in
+    /// tokens, or store credit worth `store_credit_rate_bps` of the commission
+    /// that `storefront_program` redeems via CPI
+    pub fn set_reward_mode(
+        ctx: Context<SetRewardMode>,
+        reward_mode: RewardMode,
+        store_credit_rate_bps: u16,
+        storefront_program: Pubkey,
+    ) -> Result<()> {
         require!(
-            ref_id.len() > 0 && ref_id.len() <= 32,
-            ErrorCode::InvalidRefId
+            reward_mode == RewardMode::Token
+                || (store_credit_rate_bps > 0 && storefront_program != Pubkey::default()),
+            ErrorCode::InvalidStoreCreditConfig
         );
 
-        let pool = &ctx.accounts.merchant_pool;
-        require!(pool.is_active, ErrorCode::PoolInactive);
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        pool.reward_mode = reward_mode as u8;
+        pool.store_credit_rate_bps = store_credit_rate_bps;
+        pool.storefront_program = storefront_program;
+
+        emit!(RewardModeUpdated {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            reward_mode,
+            store_credit_rate_bps,
+            storefront_program,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
 
+    /// Spend `amount` of the affiliate's store credit. Called by the pool's
+    /// storefront program via CPI, signing with its storefront authority,
+    /// with the affiliate wallet's signature passed through
+    pub fn redeem_store_credit(ctx: Context<RedeemStoreCredit>, amount: u64) -> Result<()> {
         let affiliate = &mut ctx.accounts.affiliate_account;
-        affiliate.pool = ctx.accounts.merchant_pool.key();
-        affiliate.wallet = ctx.accounts.affiliate_wallet.key();
-        affiliate.ref_id = ref_id.clone();
-        affiliate.total_earned = 0;
-        affiliate.sales_count = 0;
-        affiliate.is_active = true;
-        affiliate.bump = ctx.bumps.affiliate_account;
-        affiliate.created_at = Clock::get()?.unix_timestamp;
+        require!(
+            amount > 0 && amount <= affiliate.store_credit,
+            ErrorCode::InsufficientStoreCredit
+        );
+        affiliate.store_credit -= amount;
 
-        emit!(AffiliateAdded {
+        let pool = &ctx.accounts.merchant_pool.load()?;
+        emit!(StoreCreditRedeemed {
             pool: affiliate.pool,
-            pool_id: pool.pool_id.clone(),
+            pool_id: pool.pool_id().to_string(),
             affiliate: affiliate.key(),
             wallet: affiliate.wallet,
-            ref_id,
-            timestamp: affiliate.created_at,
+            amount,
+            store_credit: affiliate.store_credit,
+            storefront_program: pool.storefront_program,
+            timestamp: Clock::get()?.unix_timestamp,
         });
 
         Ok(())
     }
 
-    /// Process a sale and pay commission to affiliate
-    pub fn process_sale(ctx: Context<ProcessSale>, sale_amount: u64) -> Result<()> {
-        require!(sale_amount > 0, ErrorCode::InvalidAmount);
+    /// Have sales reject transactions whose other instructions call a token
+    /// program on the sale's escrow or commission accounts, so a third-party
+    /// frontend composing the sale can't sandwich it with transfers in or
+    /// out of them. Guarded sales must pass the instructions sysvar
+    pub fn set_sale_guard(ctx: Context<SetSaleGuard>, enabled: bool) -> Result<()> {
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        pool.guard_sales = u8::from(enabled);
 
-        let pool = &mut ctx.accounts.merchant_pool;
-        require!(pool.is_active, ErrorCode::PoolInactive);
+        emit!(SaleGuardUpdated {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            enabled,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
 
-        let affiliate = &mut ctx.accounts.affiliate_account;
-        require!(affiliate.is_active, ErrorCode::AffiliateInactive);
+        Ok(())
+    }
 
-        // Calculate commission with checked arithmetic
-        let commission_rate_u64 = pool.commission_rate as u64;
-        let commission = sale_amount
-            .checked_mul(commission_rate_u64)
-            .ok_or(ErrorCode::ArithmeticOverflow)?
-            .checked_div(10000)
-            .ok_or(ErrorCode::ArithmeticOverflow)?;
+    /// Have the pool only pay commissions on sales whose buyer payment
+    /// `process_sale_with_payment_proof` verifies; every other sale
+    /// instruction is refused
+    pub fn set_payment_proof_required(
+        ctx: Context<SetPaymentProofRequired>,
+        required: bool,
+    ) -> Result<()> {
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        pool.require_payment_proof = u8::from(required);
 
-        require!(commission > 0, ErrorCode::CommissionTooSmall);
+        emit!(PaymentProofRequirementUpdated {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            required,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
 
-        // Check escrow balance
-        ctx.accounts.escrow_usdc.reload()?;
+        Ok(())
+    }
+
+    /// Configure the escrow for Token-2022 confidential transfers, growing
+    /// it for the extension at the merchant's expense. The ElGamal key
+    /// `proof_context` proves valid and the AE key `decryptable_zero_balance`
+    /// is encrypted under are the processor's, which it later builds
+    /// `pay_out_confidential`'s balances and proofs with. Mints that don't
+    /// auto-approve new accounts need the escrow approved afterwards
+    pub fn configure_confidential_escrow(
+        ctx: Context<ConfigureConfidentialEscrow>,
+        decryptable_zero_balance: [u8; 36],
+        maximum_pending_balance_credit_counter: u64,
+    ) -> Result<()> {
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &ctx.accounts.merchant_pool.load()?;
         require!(
-            ctx.accounts.escrow_usdc.amount >= commission,
-            ErrorCode::InsufficientEscrowBalance
+            confidential::is_confidential_mint(&ctx.accounts.usdc_mint.to_account_info())?,
+            ErrorCode::MintNotConfidential
         );
 
-        // Transfer commission to affiliate
-        let decimals = ctx.accounts.usdc_mint.decimals;
-        let pool_key = pool.key();
-        let seeds = &[b"escrow_authority", pool_key.as_ref(), &[pool.escrow_bump]];
-        let signer_seeds = &[&seeds[..]];
+        let seeds = &[
+            ESCROW_AUTHORITY_SEED,
+            pool_key.as_ref(),
+            pool.escrow_version_seed(),
+            &[pool.escrow_bump],
+        ];
+        confidential::ConfidentialCpi {
+            token_program: &ctx.accounts.token_program.to_account_info(),
+            escrow: &ctx.accounts.escrow_usdc.to_account_info(),
+            mint: &ctx.accounts.usdc_mint.to_account_info(),
+            authority: &ctx.accounts.escrow_authority.to_account_info(),
+            signer_seeds: &[&seeds[..]],
+        }
+        .configure(
+            &ctx.accounts.merchant.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            &ctx.accounts.proof_context,
+            decryptable_zero_balance,
+            maximum_pending_balance_credit_counter,
+        )
+    }
 
-        token_interface::transfer_checked(
-            CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
-                TransferChecked {
-                    from: ctx.accounts.escrow_usdc.to_account_info(),
-                    mint: ctx.accounts.usdc_mint.to_account_info(),
-                    to: ctx.accounts.affiliate_usdc.to_account_info(),
-                    authority: ctx.accounts.escrow_authority.to_account_info(),
-                },
-                signer_seeds,
-            ),
-            commission,
-            decimals,
-        )?;
+    /// Let affiliates opt into confidential payouts. Turning them on needs
+    /// the escrow configured by `configure_confidential_escrow`; turning
+    /// them off leaves opted-in affiliates' commissions to
+    /// `claim_commissions`
+    pub fn set_confidential_payouts(
+        ctx: Context<SetConfidentialPayouts>,
+        enabled: bool,
+    ) -> Result<()> {
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        require!(
+            !enabled
+                || confidential::elgamal_pubkey(&ctx.accounts.escrow_usdc.to_account_info())?
+                    .is_some(),
+            ErrorCode::EscrowNotConfidential
+        );
+        pool.confidential_payouts = u8::from(enabled);
 
-        // Update statistics
-        affiliate.total_earned = affiliate
-            .total_earned
-            .checked_add(commission)
-            .ok_or(ErrorCode::ArithmeticOverflow)?;
-        affiliate.sales_count = affiliate
-            .sales_count
-            .checked_add(1)
-            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        emit!(ConfidentialPayoutsUpdated {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            enabled,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
 
-        pool.total_volume = pool
-            .total_volume
-            .checked_add(sale_amount)
-            .ok_or(ErrorCode::ArithmeticOverflow)?;
-        pool.total_commissions_paid = pool
-            .total_commissions_paid
-            .checked_add(commission)
-            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(())
+    }
 
-        emit!(SaleProcessed {
-            pool: pool.key(),
-            pool_id: pool.pool_id.clone(),
-            affiliate: affiliate.key(),
-            affiliate_wallet: affiliate.wallet,
-            sale_amount,
-            commission,
+    /// Delegate sale processing (and commission overrides) to a backend key
+    pub fn set_pool_processor(ctx: Context<SetPoolProcessor>, processor: Pubkey) -> Result<()> {
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        pool.processor = processor;
+
+        emit!(PoolProcessorUpdated {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            processor,
             timestamp: Clock::get()?.unix_timestamp,
         });
 
         Ok(())
     }
 
-    /// Remove (deactivate) an affiliate
-    pub fn remove_affiliate(ctx: Context<RemoveAffiliate>) -> Result<()> {
-        let affiliate = &mut ctx.accounts.affiliate_account;
-        affiliate.is_active = false;
+    /// Bound the per-sale commission overrides the processor may apply.
+    /// With both bounds at zero, overrides are effectively disabled.
+    pub fn set_commission_override_bounds(
+        ctx: Context<SetCommissionOverrideBounds>,
+        min_override_bps: u16,
+        max_override_bps: u16,
+    ) -> Result<()> {
+        require!(
+            min_override_bps <= max_override_bps && max_override_bps <= 10000,
+            ErrorCode::InvalidCommissionRate
+        );
 
-        let pool = &ctx.accounts.merchant_pool;
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        pool.min_override_bps = min_override_bps;
+        pool.max_override_bps = max_override_bps;
 
-        emit!(AffiliateRemoved {
-            pool: pool.key(),
-            pool_id: pool.pool_id.clone(),
-            affiliate: affiliate.key(),
-            wallet: affiliate.wallet,
+        emit!(CommissionOverrideBoundsUpdated {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            min_override_bps,
+            max_override_bps,
             timestamp: Clock::get()?.unix_timestamp,
         });
 
         Ok(())
     }
 
-    /// Deposit additional USDC to escrow
-    pub fn deposit_escrow(ctx: Context<DepositEscrow>, amount: u64) -> Result<()> {
-        require!(amount > 0, ErrorCode::InvalidAmount);
-
-        let pool = &ctx.accounts.merchant_pool;
-        require!(pool.is_active, ErrorCode::PoolInactive);
+    /// Set how long accrued commissions stay claimable before they can be
+    /// swept back to escrow; zero keeps them claimable forever
+    pub fn set_claim_window(ctx: Context<SetClaimWindow>, claim_window_secs: i64) -> Result<()> {
+        require!(claim_window_secs >= 0, ErrorCode::InvalidClaimWindow);
 
-        let decimals = ctx.accounts.usdc_mint.decimals;
-        token_interface::transfer_checked(
-            CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                TransferChecked {
-                    from: ctx.accounts.merchant_usdc.to_account_info(),
-                    mint: ctx.accounts.usdc_mint.to_account_info(),
-                    to: ctx.accounts.escrow_usdc.to_account_info(),
-                    authority: ctx.accounts.merchant.to_account_info(),
-                },
-            ),
-            amount,
-            decimals,
-        )?;
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        pool.claim_window_secs = claim_window_secs;
 
-        emit!(EscrowDeposited {
-            pool: pool.key(),
-            pool_id: pool.pool_id.clone(),
-            amount,
+        emit!(ClaimWindowUpdated {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            claim_window_secs,
             timestamp: Clock::get()?.unix_timestamp,
         });
 
         Ok(())
     }
 
-    /// Withdraw unused USDC from escrow
-    pub fn withdraw_escrow(ctx: Context<WithdrawEscrow>, amount: u64) -> Result<()> {
-        require!(amount > 0, ErrorCode::InvalidAmount);
+    /// Set how long an affiliate may go without a sale before anyone can
+    /// deactivate them; zero disables stale deactivation
+    pub fn set_inactivity_period(
+        ctx: Context<SetInactivityPeriod>,
+        inactivity_period_secs: i64,
+    ) -> Result<()> {
+        require!(
+            inactivity_period_secs >= 0,
+            ErrorCode::InvalidInactivityPeriod
+        );
 
-        let pool = &ctx.accounts.merchant_pool;
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        pool.inactivity_period_secs = inactivity_period_secs;
 
-        ctx.accounts.escrow_usdc.reload()?;
+        emit!(InactivityPeriodUpdated {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            inactivity_period_secs,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Set how long after a registered click a sale can still be
+    /// attributed to the clicked ref_id; zero stops requiring clicks
+    pub fn set_attribution_window(
+        ctx: Context<SetAttributionWindow>,
+        attribution_window_secs: i64,
+    ) -> Result<()> {
         require!(
-            ctx.accounts.escrow_usdc.amount >= amount,
-            ErrorCode::InsufficientEscrowBalance
+            attribution_window_secs >= 0,
+            ErrorCode::InvalidAttributionWindow
         );
 
-        let decimals = ctx.accounts.usdc_mint.decimals;
-        let pool_key = pool.key();
-        let seeds = &[b"escrow_authority", pool_key.as_ref(), &[pool.escrow_bump]];
-        let signer_seeds = &[&seeds[..]];
-
-        token_interface::transfer_checked(
-            CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
-                TransferChecked {
-                    from: ctx.accounts.escrow_usdc.to_account_info(),
-                    mint: ctx.accounts.usdc_mint.to_account_info(),
-                    to: ctx.accounts.merchant_usdc.to_account_info(),
-                    authority: ctx.accounts.escrow_authority.to_account_info(),
-                },
-                signer_seeds,
-            ),
-            amount,
-            decimals,
-        )?;
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        pool.attribution_window_secs = attribution_window_secs;
 
-        emit!(EscrowWithdrawn {
-            pool: pool.key(),
-            pool_id: pool.pool_id.clone(),
-            amount,
+        emit!(AttributionWindowUpdated {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            attribution_window_secs,
             timestamp: Clock::get()?.unix_timestamp,
         });
 
         Ok(())
     }
-}
 
-#[account]
-#[derive(InitSpace)]
-pub struct MerchantPool {
-    pub merchant: Pubkey,
-    #[max_len(32)]
-    pub pool_id: String,
-    pub usdc_mint: Pubkey,
-    pub commission_rate: u16,
-    pub total_volume: u64,
-    pub total_commissions_paid: u64,
-    pub is_active: bool,
-    pub bump: u8,
-    pub escrow_bump: u8,
-    pub created_at: i64,
-}
+    /// Record a click on `ref_id`, opening its attribution window
+    pub fn register_click(ctx: Context<RegisterClick>, ref_id: String) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let index = &mut ctx.accounts.ref_id_index;
+        index.click_registered_at = now;
 
+        emit!(ClickRegistered {
+            pool: index.pool,
+            pool_id: ctx.accounts.merchant_pool.load()?.pool_id().to_string(),
+            affiliate: index.affiliate,
+            ref_id,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Scale the rate of sales without an override with each affiliate's
+    /// conversion rate or trailing volume: an affiliate earns the rate of
+    /// the last step its metric reaches, or the pool rate below the first.
+    /// `Flat` with no steps turns the policy off
+    pub fn set_rate_policy(
+        ctx: Context<SetRatePolicy>,
+        rate_policy: RatePolicy,
+        rate_steps: Vec<RateStep>,
+    ) -> Result<()> {
+        require!(
+            rate_steps.len() <= MAX_RATE_STEPS
+                && (rate_policy == RatePolicy::Flat) == rate_steps.is_empty()
+                && rate_steps
+                    .windows(2)
+                    .all(|pair| pair[0].threshold < pair[1].threshold)
+                && rate_steps.iter().all(|step| step.rate_bps <= 10000),
+            ErrorCode::InvalidRateSteps
+        );
+
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        pool.rate_policy = rate_policy as u8;
+        pool.rate_step_count = rate_steps.len() as u8;
+        pool.rate_step_thresholds = [0; MAX_RATE_STEPS];
+        pool.rate_step_bps = [0; MAX_RATE_STEPS];
+        for (i, step) in rate_steps.iter().enumerate() {
+            pool.rate_step_thresholds[i] = step.threshold;
+            pool.rate_step_bps[i] = step.rate_bps;
+        }
+
+        emit!(RatePolicyUpdated {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            rate_policy,
+            rate_steps,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Decay the rate of affiliates who go `idle_days` without a sale by
+    /// `decay_bps` of it, until they make sales on `restore_days`
+    /// consecutive days. Zero `idle_days` turns decay off
+    pub fn set_rate_decay(
+        ctx: Context<SetRateDecay>,
+        idle_days: u16,
+        restore_days: u16,
+        decay_bps: u16,
+    ) -> Result<()> {
+        require!(
+            idle_days == 0 || (restore_days > 0 && decay_bps > 0 && decay_bps <= 10000),
+            ErrorCode::InvalidRateDecay
+        );
+
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        pool.rate_decay_idle_days = idle_days;
+        pool.rate_decay_restore_days = restore_days;
+        pool.rate_decay_bps = decay_bps;
+
+        emit!(RateDecayUpdated {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            idle_days,
+            restore_days,
+            decay_bps,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Require `approval_threshold` of `approvers` to sign off on each new
+    /// affiliate before it becomes active; a zero threshold with no
+    /// approvers activates affiliates right away. Affiliates already
+    /// pending are judged against the new list
+    pub fn set_affiliate_approvers(
+        ctx: Context<SetAffiliateApprovers>,
+        approvers: Vec<Pubkey>,
+        approval_threshold: u8,
+    ) -> Result<()> {
+        require!(
+            approvers.len() <= MAX_APPROVERS
+                && usize::from(approval_threshold) <= approvers.len()
+                && (approval_threshold == 0) == approvers.is_empty()
+                && approvers
+                    .iter()
+                    .enumerate()
+                    .all(|(i, approver)| !approvers[..i].contains(approver)),
+            ErrorCode::InvalidApprovers
+        );
+
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        pool.approvers = [Pubkey::default(); MAX_APPROVERS];
+        pool.approvers[..approvers.len()].copy_from_slice(&approvers);
+        pool.approver_count = approvers.len() as u8;
+        pool.approval_threshold = approval_threshold;
+
+        emit!(AffiliateApproversUpdated {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            approvers,
+            approval_threshold,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Record `approver`'s sign-off on a pending affiliate, activating it
+    /// once the pool's threshold of current approvers is reached
+    pub fn approve_affiliate(ctx: Context<ApproveAffiliate>) -> Result<()> {
+        let pool = &ctx.accounts.merchant_pool.load()?;
+        let approver = ctx.accounts.approver.key();
+        require!(pool.is_approver(&approver), ErrorCode::Unauthorized);
+
+        let pending = &mut ctx.accounts.pending_affiliate;
+        require!(
+            pending.approved_at == 0,
+            ErrorCode::AffiliateAlreadyApproved
+        );
+        require!(
+            !pending.approvals.contains(&approver),
+            ErrorCode::AffiliateAlreadyApproved
+        );
+        // Approvers removed since they signed no longer count
+        pending.approvals.retain(|key| pool.is_approver(key));
+        pending.approvals.push(approver);
+
+        let now = Clock::get()?.unix_timestamp;
+        let activated = pending.approvals.len() >= usize::from(pool.approval_threshold);
+        if activated {
+            pending.approved_at = now;
+            ctx.accounts.affiliate_account.is_active = true;
+        }
+
+        emit!(AffiliateApproved {
+            pool: pending.pool,
+            pool_id: pool.pool_id().to_string(),
+            affiliate: pending.affiliate,
+            approver,
+            approvals: pending.approvals.len() as u8,
+            approval_threshold: pool.approval_threshold,
+            activated,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Attach application details to the signing affiliate's pending record
+    /// for approvers to review: a `profile_uri` (website, audience size) and
+    /// the hash of contact details shared off-chain. Resubmitting clears
+    /// approvals given to the earlier application
+    pub fn submit_affiliate_application(
+        ctx: Context<SubmitAffiliateApplication>,
+        profile_uri: String,
+        contact_hash: [u8; 32],
+    ) -> Result<()> {
+        require!(
+            profile_uri.len() <= MAX_METADATA_URI_LEN,
+            ErrorCode::InvalidMetadataUri
+        );
+
+        let pending = &mut ctx.accounts.pending_affiliate;
+        require!(
+            pending.approved_at == 0,
+            ErrorCode::AffiliateAlreadyApproved
+        );
+        let now = Clock::get()?.unix_timestamp;
+        pending.profile_uri = profile_uri.clone();
+        pending.contact_hash = contact_hash;
+        pending.submitted_at = now;
+        pending.approvals.clear();
+
+        emit!(AffiliateApplicationSubmitted {
+            pool: pending.pool,
+            pool_id: ctx.accounts.merchant_pool.load()?.pool_id().to_string(),
+            affiliate: pending.affiliate,
+            profile_uri,
+            contact_hash,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Let `session` sign sales for the pool in the processor's place until
+    /// `expiry`, within `scope`'s limits. A leaked session key stops
+    /// working on its own, without rotating the processor
+    pub fn create_session_key(
+        ctx: Context<CreateSessionKey>,
+        session: Pubkey,
+        expiry: i64,
+        scope: SessionScope,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        require!(
+            expiry > now
+                && expiry
+                    <= now
+                        .checked_add(MAX_SESSION_KEY_SECS)
+                        .ok_or(ErrorCode::ArithmeticOverflow)?,
+            ErrorCode::InvalidSessionExpiry
+        );
+        require!(
+            scope.max_sale_amount > 0 && scope.commission_budget > 0,
+            ErrorCode::InvalidSessionScope
+        );
+
+        let session_key = &mut ctx.accounts.session_key;
+        session_key.pool = ctx.accounts.merchant_pool.key();
+        session_key.session = session;
+        session_key.issued_by = ctx.accounts.authority.key();
+        session_key.scope = scope;
+        session_key.commission_spent = 0;
+        session_key.expires_at = expiry;
+        session_key.bump = ctx.bumps.session_key;
+        session_key.created_at = now;
+
+        emit!(SessionKeyCreated {
+            pool: session_key.pool,
+            pool_id: ctx.accounts.merchant_pool.load()?.pool_id().to_string(),
+            session,
+            issued_by: session_key.issued_by,
+            scope,
+            expires_at: expiry,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Withdraw a session key before it expires, refunding its rent
+    pub fn revoke_session_key(ctx: Context<RevokeSessionKey>) -> Result<()> {
+        emit!(SessionKeyRevoked {
+            pool: ctx.accounts.session_key.pool,
+            pool_id: ctx.accounts.merchant_pool.load()?.pool_id().to_string(),
+            session: ctx.accounts.session_key.session,
+            revoked_by: ctx.accounts.authority.key(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Add `count` clicks to the affiliate's counter. Processors batch
+    /// clicks off-chain and flush them here rather than sending one per click
+    pub fn record_click(ctx: Context<RecordClick>, count: u64) -> Result<()> {
+        require!(count > 0, ErrorCode::InvalidAmount);
+
+        let affiliate = &mut ctx.accounts.affiliate_account;
+        affiliate.clicks = affiliate
+            .clicks
+            .checked_add(count)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        emit!(ClicksRecorded {
+            pool: affiliate.pool,
+            pool_id: ctx.accounts.merchant_pool.load()?.pool_id().to_string(),
+            affiliate: affiliate.key(),
+            count,
+            clicks: affiliate.clicks,
+            conversion_rate_bps: affiliate.conversion_rate_bps(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Cap the commission a single sale can pay, so a mistyped sale amount
+    /// can't drain escrow; zero removes the cap
+    pub fn set_commission_cap(
+        ctx: Context<SetCommissionCap>,
+        max_commission_per_sale: u64,
+        commission_cap_mode: CommissionCapMode,
+    ) -> Result<()> {
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        pool.max_commission_per_sale = max_commission_per_sale;
+        pool.commission_cap_mode = commission_cap_mode as u8;
+
+        emit!(CommissionCapUpdated {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            max_commission_per_sale,
+            commission_cap_mode,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Require `cosigner` to co-sign escrow withdrawals above `cosign_threshold`,
+    /// or drop the requirement with `None`. Once set, changing it needs the
+    /// current co-signer too
+    pub fn set_withdrawal_cosigner(
+        ctx: Context<SetWithdrawalCosigner>,
+        cosigner: Option<Pubkey>,
+        cosign_threshold: u64,
+    ) -> Result<()> {
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        if let Some(current) = pool.withdrawal_cosigner() {
+            require!(
+                ctx.accounts
+                    .current_cosigner
+                    .as_ref()
+                    .is_some_and(|signer| signer.key() == current),
+                ErrorCode::CosignerRequired
+            );
+        }
+        pool.withdrawal_cosigner = cosigner.unwrap_or_default();
+        pool.cosign_threshold = cosign_threshold;
+
+        emit!(WithdrawalCosignerUpdated {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            cosigner,
+            cosign_threshold,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Lock withdrawals to token accounts owned by `destination`, e.g. the
+    /// treasury. The first destination applies immediately; replacing it
+    /// is queued for `DESTINATION_CHANGE_DELAY_SECS`
+    pub fn set_withdrawal_destination(
+        ctx: Context<SetWithdrawalDestination>,
+        destination: Pubkey,
+    ) -> Result<()> {
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        let now = Clock::get()?.unix_timestamp;
+
+        if pool.withdrawal_destination().is_none() {
+            pool.withdrawal_destination = destination;
+            emit!(WithdrawalDestinationUpdated {
+                pool: pool_key,
+                pool_id: pool.pool_id().to_string(),
+                destination,
+                timestamp: now,
+            });
+            return Ok(());
+        }
+
+        let effective_at = now
+            .checked_add(DESTINATION_CHANGE_DELAY_SECS)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        pool.pending_withdrawal_destination = destination;
+        pool.destination_change_at = effective_at;
+
+        emit!(WithdrawalDestinationProposed {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            destination,
+            effective_at,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Switch to the queued withdrawal destination once its delay has passed
+    pub fn apply_withdrawal_destination(ctx: Context<SetWithdrawalDestination>) -> Result<()> {
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        let destination = pool
+            .pending_withdrawal_destination()
+            .ok_or(ErrorCode::NoPendingDestination)?;
+        let now = Clock::get()?.unix_timestamp;
+        require!(
+            now >= pool.destination_change_at,
+            ErrorCode::DestinationChangePending
+        );
+
+        pool.withdrawal_destination = destination;
+        pool.pending_withdrawal_destination = Pubkey::default();
+        pool.destination_change_at = 0;
+
+        emit!(WithdrawalDestinationUpdated {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            destination,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Switch the pool to escrowless mode, funding each primary-mint payout
+    /// from `revenue_usdc` at sale time, or back to escrow with `None`. The
+    /// merchant approves the pool's escrow authority as its SPL delegate
+    pub fn set_revenue_account(ctx: Context<SetRevenueAccount>) -> Result<()> {
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        let revenue_account = ctx
+            .accounts
+            .revenue_usdc
+            .as_ref()
+            .map(|account| account.key());
+        pool.revenue_account = revenue_account.unwrap_or_default();
+
+        emit!(RevenueAccountUpdated {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            revenue_account,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Cap escrow withdrawals per rolling day; zero removes the cap. Raising
+    /// or removing it needs the pool's withdrawal co-signer when one is set
+    pub fn set_daily_withdrawal_limit(
+        ctx: Context<SetDailyWithdrawalLimit>,
+        daily_withdrawal_limit: u64,
+    ) -> Result<()> {
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        let loosened = daily_withdrawal_limit == 0
+            || (pool.daily_withdrawal_limit != 0
+                && daily_withdrawal_limit > pool.daily_withdrawal_limit);
+        if let (true, Some(cosigner)) = (loosened, pool.withdrawal_cosigner()) {
+            require!(
+                ctx.accounts
+                    .cosigner
+                    .as_ref()
+                    .is_some_and(|signer| signer.key() == cosigner),
+                ErrorCode::CosignerRequired
+            );
+        }
+        pool.daily_withdrawal_limit = daily_withdrawal_limit;
+
+        emit!(DailyWithdrawalLimitUpdated {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            daily_withdrawal_limit,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Set the one-time bounty paid to an affiliate's referrer on the
+    /// referred affiliate's first sale. Zero disables
+    pub fn set_first_sale_bounty(ctx: Context<SetFirstSaleBounty>, amount: u64) -> Result<()> {
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        pool.first_sale_bounty = amount;
+
+        emit!(FirstSaleBountyUpdated {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Hold primary-mint commissions in escrow for `holdback_secs` after each
+    /// sale so refunds can still claw them back. Zero pays out right away
+    pub fn set_holdback_period(ctx: Context<SetHoldbackPeriod>, holdback_secs: i64) -> Result<()> {
+        require!(holdback_secs >= 0, ErrorCode::InvalidHoldbackPeriod);
+
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        pool.holdback_secs = holdback_secs;
+
+        emit!(HoldbackPeriodUpdated {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            holdback_secs,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Pay out held-back commissions whose settlement window has elapsed.
+    /// Permissionless; remaining accounts are (sale receipt, affiliate
+    /// account, affiliate token account) triples, and receipts that aren't
+    /// due yet are skipped so a crank can pass a whole batch. Affiliates
+    /// with payout splits, or in a group that may route their
+    /// commissions, have theirs released to claim instead
+    pub fn release_matured_holdbacks<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ReleaseMaturedHoldbacks<'info>>,
+    ) -> Result<()> {
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        let seeds = &[
+            ESCROW_AUTHORITY_SEED,
+            pool_key.as_ref(),
+            pool.escrow_version_seed(),
+            &[pool.escrow_bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+        let now = Clock::get()?.unix_timestamp;
+
+        let error = ErrorCode::InvalidHoldbackAccounts;
+        for [receipt_info, affiliate_info, affiliate_usdc_info] in
+            remaining::groups::<3>(ctx.remaining_accounts, None, error)?
+        {
+            let mut receipt = Account::<SaleReceipt>::try_from(receipt_info)?;
+            let mut affiliate = remaining::affiliate_of(affiliate_info, &pool_key, error)?;
+            let affiliate_usdc = remaining::commission_account_of(
+                affiliate_usdc_info,
+                &affiliate,
+                &pool.usdc_mint,
+                error,
+            )?;
+            if receipt.pool != pool_key || receipt.affiliate != affiliate.key() {
+                return Err(error.into());
+            }
+            if receipt.held_amount == 0 || now < receipt.releasable_at {
+                continue;
+            }
+
+            let amount = receipt.held_amount;
+            if affiliate.payout_splits.is_empty()
+                && affiliate.group.is_none()
+                && !affiliate.compressed_payouts
+                && affiliate.elgamal_pubkey.is_none()
+            {
+                transfer_memo(&ctx.accounts.memo_program, &receipt.order_id)?;
+                token_interface::transfer_checked(
+                    CpiContext::new_with_signer(
+                        ctx.accounts.token_program.to_account_info(),
+                        TransferChecked {
+                            from: ctx.accounts.escrow_usdc.to_account_info(),
+                            mint: ctx.accounts.usdc_mint.to_account_info(),
+                            to: affiliate_usdc.to_account_info(),
+                            authority: ctx.accounts.escrow_authority.to_account_info(),
+                        },
+                        signer_seeds,
+                    ),
+                    amount,
+                    ctx.accounts.usdc_mint.decimals,
+                )?;
+            } else {
+                // Released to claim, where the affiliate's split, group,
+                // compressed or confidential payout applies
+                if affiliate.claimable == 0 {
+                    affiliate.claimable_since = now;
+                }
+                affiliate.claimable = affiliate
+                    .claimable
+                    .checked_add(amount)
+                    .ok_or(ErrorCode::ArithmeticOverflow)?;
+                pool.total_claimable = pool
+                    .total_claimable
+                    .checked_add(amount)
+                    .ok_or(ErrorCode::ArithmeticOverflow)?;
+            }
+
+            receipt.held_amount = 0;
+            receipt.exit(&crate::ID)?;
+            affiliate.total_held = affiliate
+                .total_held
+                .checked_sub(amount)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+            affiliate.exit(&crate::ID)?;
+            pool.total_held = pool
+                .total_held
+                .checked_sub(amount)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+            emit!(HoldbackReleased {
+                pool: pool_key,
+                pool_id: pool.pool_id().to_string(),
+                affiliate: affiliate.key(),
+                order_id: receipt.order_id.clone(),
+                amount,
+                timestamp: now,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Create the pool's on-chain leaderboard; from then on every
+    /// `process_sale` must pass it so the ranking can't be skipped
+    pub fn init_leaderboard(ctx: Context<InitLeaderboard>) -> Result<()> {
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        pool.has_leaderboard = u8::from(true);
+
+        let mut leaderboard = ctx.accounts.leaderboard.load_init()?;
+        leaderboard.pool = pool_key;
+        leaderboard.bump = ctx.bumps.leaderboard;
+
+        emit!(LeaderboardInitialized {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            leaderboard: ctx.accounts.leaderboard.key(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Close the pool's current reporting period: archive its volume and
+    /// commissions in a `PoolPeriod` and start the next one from zero.
+    /// Lifetime totals are untouched
+    pub fn rotate_period(ctx: Context<RotatePeriod>) -> Result<()> {
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        let now = Clock::get()?.unix_timestamp;
+
+        let period = &mut ctx.accounts.pool_period;
+        period.pool = pool_key;
+        period.period_index = pool.period_index;
+        period.volume = pool.period_volume;
+        period.commissions_paid = pool.period_commissions_paid;
+        period.total_volume = pool.total_volume;
+        period.total_commissions_paid = pool.total_commissions_paid;
+        period.started_at = pool.period_started_at;
+        period.ended_at = now;
+        period.bump = ctx.bumps.pool_period;
+
+        emit!(PeriodRotated {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            period_index: pool.period_index,
+            volume: pool.period_volume,
+            commissions_paid: pool.period_commissions_paid,
+            started_at: pool.period_started_at,
+            timestamp: now,
+        });
+
+        pool.period_index = pool
+            .period_index
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        pool.period_volume = 0;
+        pool.period_commissions_paid = 0;
+        pool.period_started_at = now;
+
+        Ok(())
+    }
+
+    /// Deactivate a pool
+    pub fn deactivate_pool(ctx: Context<DeactivatePool>) -> Result<()> {
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        pool.is_active = u8::from(false);
+
+        emit!(PoolDeactivated {
+            pool: pool_key,
+            merchant: pool.merchant,
+            pool_id: pool.pool_id().to_string(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Freeze the merchant's escrow withdrawals until the admin reviews the
+    /// pool. Co-signed by affiliates holding more than
+    /// `FLAG_VOLUME_THRESHOLD_BPS` of the pool's sale volume, passed as
+    /// (affiliate account, signing wallet) pairs in the remaining accounts
+    pub fn flag_pool<'info>(ctx: Context<'_, '_, 'info, 'info, FlagPool<'info>>) -> Result<()> {
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        require!(!pool.is_flagged(), ErrorCode::PoolFlagged);
+
+        let mut flagged_volume: u64 = 0;
+        let mut counted: Vec<Pubkey> = Vec::new();
+        let error = ErrorCode::InvalidFlagAccounts;
+        for [account, wallet] in remaining::groups::<2>(ctx.remaining_accounts, None, error)? {
+            let affiliate = remaining::affiliate_of(account, &pool_key, error)?;
+            if affiliate.wallet != wallet.key()
+                || !wallet.is_signer
+                || counted.contains(&account.key())
+            {
+                return Err(error.into());
+            }
+            counted.push(account.key());
+            flagged_volume = flagged_volume
+                .checked_add(affiliate.total_sale_volume)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+        }
+        require!(
+            flagged_volume as u128 * 10000
+                > pool.total_volume as u128 * FLAG_VOLUME_THRESHOLD_BPS as u128,
+            ErrorCode::InsufficientFlagVolume
+        );
+
+        let now = Clock::get()?.unix_timestamp;
+        pool.flagged_at = now;
+
+        let reputation = &mut ctx.accounts.merchant_reputation;
+        reputation.freezes = reputation
+            .freezes
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        reputation.updated_at = now;
+
+        emit!(PoolFlagged {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            affiliates: counted.len() as u32,
+            flagged_volume,
+            total_volume: pool.total_volume,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Lift a flag after review, re-enabling the merchant's withdrawals.
+    /// `upheld` records that the review found for the affiliates, counting
+    /// a dispute lost against the merchant's reputation
+    pub fn clear_pool_flag(ctx: Context<ClearPoolFlag>, upheld: bool) -> Result<()> {
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        require!(pool.is_flagged(), ErrorCode::PoolNotFlagged);
+        pool.flagged_at = 0;
+
+        let now = Clock::get()?.unix_timestamp;
+        if upheld {
+            let reputation = &mut ctx.accounts.merchant_reputation;
+            reputation.disputes_lost = reputation
+                .disputes_lost
+                .checked_add(1)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+            reputation.updated_at = now;
+        }
+
+        emit!(PoolFlagCleared {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            admin: ctx.accounts.admin.key(),
+            upheld,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Bring the merchant's reputation up to date with a pool's commissions
+    /// paid. Permissionless so affiliates can refresh it before joining;
+    /// creates the account for merchants whose pools predate it
+    pub fn sync_merchant_reputation(ctx: Context<SyncMerchantReputation>) -> Result<()> {
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        let now = Clock::get()?.unix_timestamp;
+
+        // Refunds and expired claims lower a pool's total, so the delta
+        // since the last sync can go either way
+        let reputation = &mut ctx.accounts.merchant_reputation;
+        reputation.total_paid = if pool.total_commissions_paid >= pool.reputation_synced_paid {
+            reputation
+                .total_paid
+                .checked_add(pool.total_commissions_paid - pool.reputation_synced_paid)
+                .ok_or(ErrorCode::ArithmeticOverflow)?
+        } else {
+            reputation
+                .total_paid
+                .saturating_sub(pool.reputation_synced_paid - pool.total_commissions_paid)
+        };
+        pool.reputation_synced_paid = pool.total_commissions_paid;
+
+        reputation.merchant = pool.creator;
+        reputation.pools_created = ctx.accounts.merchant_registry.pool_count;
+        if reputation.first_pool_at == 0 || pool.created_at < reputation.first_pool_at {
+            reputation.first_pool_at = pool.created_at;
+        }
+        reputation.updated_at = now;
+        reputation.bump = ctx.bumps.merchant_reputation;
+
+        emit!(MerchantReputationSynced {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            merchant: reputation.merchant,
+            pools_created: reputation.pools_created,
+            total_paid: reputation.total_paid,
+            disputes_lost: reputation.disputes_lost,
+            freezes: reputation.freezes,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Replace the protocol fee schedule. Each tier applies from its
+    /// `min_monthly_volume` up to the next tier's; an empty schedule charges
+    /// no fee
+    pub fn set_fee_tiers(ctx: Context<SetFeeTiers>, fee_tiers: Vec<FeeTier>) -> Result<()> {
+        let change = ConfigChange::FeeTiers { fee_tiers };
+        change.validate()?;
+        change.apply(&mut ctx.accounts.config, None)
+    }
+
+    /// Bound the commission rate pools may be created with or changed to.
+    /// Pools already outside a new band keep their rate until their next
+    /// change
+    pub fn set_commission_rate_band(
+        ctx: Context<SetCommissionRateBand>,
+        min_commission_rate_bps: u16,
+        max_commission_rate_bps: u16,
+    ) -> Result<()> {
+        let change = ConfigChange::CommissionRateBand {
+            min_commission_rate_bps,
+            max_commission_rate_bps,
+        };
+        change.validate()?;
+        change.apply(&mut ctx.accounts.config, None)
+    }
+
+    /// Send future protocol fee collections to `treasury`'s token accounts
+    pub fn set_treasury(ctx: Context<SetTreasury>, treasury: Pubkey) -> Result<()> {
+        ConfigChange::Treasury { treasury }.apply(&mut ctx.accounts.config, None)
+    }
+
+    /// Record the hash of the program build the admin intends to run and put
+    /// the admin's config changes behind `timelock_secs`. Permanent: from
+    /// then on fee schedule, rate band and treasury changes are announced
+    /// with `queue_config_change` and only take effect after the timelock,
+    /// so integrators can react to them. Pausing stays immediate
+    pub fn init_upgrade_guard(
+        ctx: Context<InitUpgradeGuard>,
+        program_hash: [u8; 32],
+        timelock_secs: i64,
+    ) -> Result<()> {
+        ConfigChange::Timelock { timelock_secs }.validate()?;
+
+        let guard = &mut ctx.accounts.upgrade_guard;
+        guard.program_hash = program_hash;
+        guard.timelock_secs = timelock_secs;
+        guard.pending_change = None;
+        guard.executable_at = 0;
+        guard.bump = ctx.bumps.upgrade_guard;
+
+        emit!(UpgradeGuardInitialized {
+            program_hash,
+            timelock_secs,
+            admin: ctx.accounts.admin.key(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Announce `change`, which `execute_config_change` applies once the
+    /// guard's timelock has passed. One change is pending at a time
+    pub fn queue_config_change(
+        ctx: Context<QueueConfigChange>,
+        change: ConfigChange,
+    ) -> Result<()> {
+        change.validate()?;
+
+        let guard = &mut ctx.accounts.upgrade_guard;
+        require!(
+            guard.pending_change.is_none(),
+            ErrorCode::ConfigChangePending
+        );
+        let now = Clock::get()?.unix_timestamp;
+        guard.executable_at = now
+            .checked_add(guard.timelock_secs)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        guard.pending_change = Some(change.clone());
+
+        emit!(ConfigChangeQueued {
+            change,
+            executable_at: guard.executable_at,
+            admin: ctx.accounts.admin.key(),
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Apply the pending config change once its timelock has passed
+    pub fn execute_config_change(ctx: Context<ExecuteConfigChange>) -> Result<()> {
+        let guard = &mut ctx.accounts.upgrade_guard;
+        let change = guard
+            .pending_change
+            .take()
+            .ok_or(ErrorCode::NoPendingConfigChange)?;
+        require!(
+            Clock::get()?.unix_timestamp >= guard.executable_at,
+            ErrorCode::TimelockNotElapsed
+        );
+        guard.executable_at = 0;
+
+        change.apply(&mut ctx.accounts.config, Some(guard))
+    }
+
+    /// Drop the pending config change
+    pub fn cancel_config_change(ctx: Context<CancelConfigChange>) -> Result<()> {
+        let guard = &mut ctx.accounts.upgrade_guard;
+        let change = guard
+            .pending_change
+            .take()
+            .ok_or(ErrorCode::NoPendingConfigChange)?;
+        guard.executable_at = 0;
+
+        emit!(ConfigChangeCancelled {
+            change,
+            admin: ctx.accounts.admin.key(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Sweep a pool's accrued protocol fees from its escrow to the treasury.
+    /// Sales only reserve the fee, so this one transfer covers many of them
+    pub fn collect_protocol_fees(ctx: Context<CollectProtocolFees>) -> Result<()> {
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        let amount = pool.protocol_fees_accrued;
+        require!(amount > 0, ErrorCode::NoProtocolFees);
+        pool.protocol_fees_accrued = 0;
+
+        let seeds = &[
+            ESCROW_AUTHORITY_SEED,
+            pool_key.as_ref(),
+            pool.escrow_version_seed(),
+            &[pool.escrow_bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.escrow_usdc.to_account_info(),
+                    mint: ctx.accounts.usdc_mint.to_account_info(),
+                    to: ctx.accounts.treasury_usdc.to_account_info(),
+                    authority: ctx.accounts.escrow_authority.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            amount,
+            ctx.accounts.usdc_mint.decimals,
+        )?;
+
+        emit!(ProtocolFeesCollected {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            amount,
+            treasury_account: ctx.accounts.treasury_usdc.key(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Exempt a pool from the protocol fee, e.g. for a launch partner, or
+    /// end its exemption
+    pub fn set_fee_exempt(ctx: Context<SetFeeExempt>, fee_exempt: bool) -> Result<()> {
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        pool.fee_exempt = u8::from(fee_exempt);
+
+        emit!(FeeExemptionUpdated {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            fee_exempt,
+            admin: ctx.accounts.admin.key(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Give public notice that a pool without activity for
+    /// `ABANDONMENT_PERIOD_SECS` will have its escrow recovered to the
+    /// merchant after `ABANDONMENT_NOTICE_SECS`. Any sale, deposit or
+    /// withdrawal in the meantime withdraws the notice
+    pub fn notice_abandoned_pool(ctx: Context<NoticeAbandonedPool>) -> Result<()> {
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        let now = Clock::get()?.unix_timestamp;
+        require!(now >= pool.abandoned_at(), ErrorCode::PoolNotAbandoned);
+        require!(
+            pool.abandonment_noticed_at == 0,
+            ErrorCode::AbandonmentAlreadyNoticed
+        );
+        pool.abandonment_noticed_at = now;
+
+        emit!(PoolAbandonmentNoticed {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            merchant: pool.merchant,
+            last_activity_at: pool.last_activity_at.max(pool.created_at),
+            recoverable_at: now.saturating_add(ABANDONMENT_NOTICE_SECS),
+            admin: ctx.accounts.admin.key(),
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Send an abandoned pool's unreserved escrow in `usdc_mint` to the
+    /// merchant's own token account once its notice period has passed,
+    /// deactivating the pool. Commissions owed to affiliates stay in escrow
+    /// for them to claim. Call once per settlement mint
+    pub fn recover_abandoned_pool(ctx: Context<RecoverAbandonedPool>) -> Result<()> {
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        let now = Clock::get()?.unix_timestamp;
+        require!(
+            pool.abandonment_noticed_at != 0,
+            ErrorCode::PoolNotAbandoned
+        );
+        require!(!pool.is_flagged(), ErrorCode::PoolFlagged);
+        require!(
+            now >= pool
+                .abandonment_noticed_at
+                .saturating_add(ABANDONMENT_NOTICE_SECS),
+            ErrorCode::AbandonmentNoticePending
+        );
+
+        let mint = ctx.accounts.usdc_mint.key();
+        let amount = ctx
+            .accounts
+            .escrow_usdc
+            .amount
+            .saturating_sub(pool.reserved_for(&mint));
+        pool.is_active = u8::from(false);
+
+        if amount > 0 {
+            let seeds = &[
+                ESCROW_AUTHORITY_SEED,
+                pool_key.as_ref(),
+                pool.escrow_version_seed(),
+                &[pool.escrow_bump],
+            ];
+            let signer_seeds = &[&seeds[..]];
+
+            transfer_memo(&ctx.accounts.memo_program, pool.pool_id())?;
+            token_interface::transfer_checked(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    TransferChecked {
+                        from: ctx.accounts.escrow_usdc.to_account_info(),
+                        mint: ctx.accounts.usdc_mint.to_account_info(),
+                        to: ctx.accounts.merchant_usdc.to_account_info(),
+                        authority: ctx.accounts.escrow_authority.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                amount,
+                ctx.accounts.usdc_mint.decimals,
+            )?;
+        }
+
+        emit!(AbandonedPoolRecovered {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            merchant: pool.merchant,
+            mint,
+            amount,
+            admin: ctx.accounts.admin.key(),
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Replace the keys that can jointly recover the pool's merchant to a
+    /// new key, and how many of them must sign
+    pub fn set_guardians(
+        ctx: Context<SetGuardians>,
+        guardians: Vec<Pubkey>,
+        guardian_threshold: u8,
+    ) -> Result<()> {
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        require!(
+            guardians.len() <= MAX_GUARDIANS
+                && usize::from(guardian_threshold) <= guardians.len()
+                && (guardian_threshold == 0) == guardians.is_empty()
+                && !guardians.contains(&pool.merchant)
+                && guardians
+                    .iter()
+                    .enumerate()
+                    .all(|(i, guardian)| !guardians[..i].contains(guardian)),
+            ErrorCode::InvalidGuardians
+        );
+
+        pool.guardians = [Pubkey::default(); MAX_GUARDIANS];
+        pool.guardians[..guardians.len()].copy_from_slice(&guardians);
+        pool.guardian_count = guardians.len() as u8;
+        pool.guardian_threshold = guardian_threshold;
+
+        emit!(GuardiansUpdated {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            guardians,
+            guardian_threshold,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Propose recovering the pool's merchant to `new_merchant`, co-signed by
+    /// the pool's threshold of guardians passed as signing remaining
+    /// accounts. Starts `MERCHANT_RECOVERY_CHALLENGE_SECS` during which the
+    /// current merchant can veto
+    pub fn propose_merchant_recovery(
+        ctx: Context<ProposeMerchantRecovery>,
+        new_merchant: Pubkey,
+    ) -> Result<()> {
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        pool.require_guardians(ctx.remaining_accounts)?;
+        require!(
+            pool.merchant_recovery_at == 0,
+            ErrorCode::MerchantRecoveryPending
+        );
+        require!(
+            new_merchant != Pubkey::default() && new_merchant != pool.merchant,
+            ErrorCode::InvalidRecoveryKey
+        );
+
+        let now = Clock::get()?.unix_timestamp;
+        pool.pending_merchant = new_merchant;
+        pool.merchant_recovery_at = now.saturating_add(MERCHANT_RECOVERY_CHALLENGE_SECS);
+
+        emit!(MerchantRecoveryProposed {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            merchant: pool.merchant,
+            new_merchant,
+            recoverable_at: pool.merchant_recovery_at,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Cancel the pending merchant recovery with the current merchant key
+    pub fn veto_merchant_recovery(ctx: Context<VetoMerchantRecovery>) -> Result<()> {
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        require!(
+            pool.merchant_recovery_at != 0,
+            ErrorCode::NoPendingMerchantRecovery
+        );
+
+        let new_merchant = pool.pending_merchant;
+        pool.pending_merchant = Pubkey::default();
+        pool.merchant_recovery_at = 0;
+
+        emit!(MerchantRecoveryVetoed {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            merchant: pool.merchant,
+            new_merchant,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Hand the pool to the proposed `new_merchant` once the challenge
+    /// window has passed unvetoed, co-signed again by the pool's threshold
+    /// of guardians. A processor left at the old key moves with it
+    pub fn recover_merchant(ctx: Context<RecoverMerchant>, new_merchant: Pubkey) -> Result<()> {
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        pool.require_guardians(ctx.remaining_accounts)?;
+        require!(
+            pool.merchant_recovery_at != 0,
+            ErrorCode::NoPendingMerchantRecovery
+        );
+        require!(
+            pool.pending_merchant == new_merchant,
+            ErrorCode::InvalidRecoveryKey
+        );
+        let now = Clock::get()?.unix_timestamp;
+        require!(
+            now >= pool.merchant_recovery_at,
+            ErrorCode::MerchantRecoveryChallengeActive
+        );
+
+        let previous_merchant = pool.merchant;
+        pool.merchant = new_merchant;
+        if pool.processor == previous_merchant {
+            pool.processor = new_merchant;
+        }
+        pool.pending_merchant = Pubkey::default();
+        pool.merchant_recovery_at = 0;
+
+        emit!(MerchantRecovered {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            previous_merchant,
+            merchant: new_merchant,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Add an affiliate to the merchant's pool
+    pub fn add_affiliate(ctx: Context<AddAffiliate>, ref_id: String) -> Result<()> {
+        validate_ref_id(&ref_id)?;
+
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        require!(pool.is_active(), ErrorCode::PoolInactive);
+
+        let affiliate = &mut ctx.accounts.affiliate_account;
+        affiliate.init(
+            pool_key,
+            pool,
+            ctx.accounts.affiliate_wallet.key(),
+            ref_id.clone(),
+            ctx.bumps.affiliate_account,
+            Clock::get()?.unix_timestamp,
+        );
+        affiliate.referred_by = ctx
+            .accounts
+            .referrer
+            .as_ref()
+            .map(|referrer| referrer.wallet);
+
+        // Pools with an approval threshold start affiliates inactive until
+        // enough operators sign off on their pending record
+        match (
+            pool.approval_threshold,
+            ctx.accounts.pending_affiliate.as_mut(),
+            ctx.bumps.pending_affiliate,
+        ) {
+            (0, _, _) => {}
+            (_, Some(pending), Some(bump)) => {
+                affiliate.is_active = false;
+                pending.pool = pool_key;
+                pending.affiliate = affiliate.key();
+                pending.approvals = Vec::new();
+                pending.approved_at = 0;
+                pending.bump = bump;
+                pending.created_at = affiliate.created_at;
+                pending.profile_uri = String::new();
+                pending.contact_hash = [0; 32];
+                pending.submitted_at = 0;
+            }
+            _ => return err!(ErrorCode::PendingAffiliateRequired),
+        }
+
+        let entry = &mut ctx.accounts.affiliate_entry;
+        entry.pool = affiliate.pool;
+        entry.index = affiliate.affiliate_index;
+        entry.affiliate = affiliate.key();
+        entry.bump = ctx.bumps.affiliate_entry;
+
+        let ref_index = &mut ctx.accounts.ref_id_index;
+        ref_index.pool = affiliate.pool;
+        ref_index.affiliate = affiliate.key();
+        ref_index.redemptions = 0;
+        ref_index.click_registered_at = 0;
+        ref_index.bump = ctx.bumps.ref_id_index;
+
+        pool.affiliate_count = pool
+            .affiliate_count
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        emit!(AffiliateAdded {
+            pool: affiliate.pool,
+            pool_id: pool.pool_id().to_string(),
+            affiliate: affiliate.key(),
+            wallet: affiliate.wallet,
+            ref_id,
+            timestamp: affiliate.created_at,
+        });
+
+        Ok(())
+    }
+
+    /// Add up to `MAX_AFFILIATE_BATCH` affiliates in one transaction, for
+    /// migrating an existing affiliate program. The remaining accounts are
+    /// the uninitialized (affiliate account, affiliate entry, ref_id index)
+    /// PDAs of each affiliate, in order. Imported affiliates have no
+    /// referrer, and pools with an approval threshold add them one at a time
+    pub fn add_affiliates_batch<'info>(
+        ctx: Context<'_, '_, 'info, 'info, AddAffiliatesBatch<'info>>,
+        args: AddAffiliatesBatchArgs,
+    ) -> Result<()> {
+        args.validate()?;
+        let error = ErrorCode::InvalidAffiliateBatchAccounts;
+        let groups =
+            remaining::groups::<3>(ctx.remaining_accounts, Some(args.affiliates.len()), error)?;
+
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        require!(pool.is_active(), ErrorCode::PoolInactive);
+        require!(
+            pool.approval_threshold == 0,
+            ErrorCode::PendingAffiliateRequired
+        );
+        let payer = ctx.accounts.rent_payer.to_account_info();
+        let system_program = ctx.accounts.system_program.to_account_info();
+        let now = Clock::get()?.unix_timestamp;
+
+        for (import, [affiliate_info, entry_info, ref_index_info]) in
+            args.affiliates.into_iter().zip(groups)
+        {
+            let AffiliateImport { wallet, ref_id } = import;
+
+            let bump = remaining::create_pda_account::<AffiliateAccount>(
+                affiliate_info,
+                &payer,
+                &system_program,
+                &[AFFILIATE_SEED, pool_key.as_ref(), wallet.as_ref()],
+                error,
+            )?;
+            let mut affiliate = Account::<AffiliateAccount>::try_from(affiliate_info)?;
+            affiliate.init(pool_key, pool, wallet, ref_id.clone(), bump, now);
+            affiliate.exit(&crate::ID)?;
+
+            let bump = remaining::create_pda_account::<AffiliateEntry>(
+                entry_info,
+                &payer,
+                &system_program,
+                &[
+                    AFFILIATE_ENTRY_SEED,
+                    pool_key.as_ref(),
+                    &pool.affiliate_count.to_le_bytes(),
+                ],
+                error,
+            )?;
+            let mut entry = Account::<AffiliateEntry>::try_from(entry_info)?;
+            entry.pool = pool_key;
+            entry.index = affiliate.affiliate_index;
+            entry.affiliate = affiliate.key();
+            entry.bump = bump;
+            entry.exit(&crate::ID)?;
+
+            let bump = remaining::create_pda_account::<RefIdIndex>(
+                ref_index_info,
+                &payer,
+                &system_program,
+                &[REF_ID_SEED, pool_key.as_ref(), ref_id.as_bytes()],
+                error,
+            )?;
+            let mut ref_index = Account::<RefIdIndex>::try_from(ref_index_info)?;
+            ref_index.pool = pool_key;
+            ref_index.affiliate = affiliate.key();
+            ref_index.redemptions = 0;
+            ref_index.click_registered_at = 0;
+            ref_index.bump = bump;
+            ref_index.exit(&crate::ID)?;
+
+            pool.affiliate_count = pool
+                .affiliate_count
+                .checked_add(1)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+            emit!(AffiliateAdded {
+                pool: pool_key,
+                pool_id: pool.pool_id().to_string(),
+                affiliate: affiliate.key(),
+                wallet,
+                ref_id,
+                timestamp: now,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Create the pool's canonical affiliate tree in `merkle_tree`, which the
+    /// caller allocated for the account-compression program at the size
+    /// `max_depth` and `max_buffer_size` need. Each pool gets one tree
+    pub fn init_affiliate_tree(
+        ctx: Context<InitAffiliateTree>,
+        max_depth: u32,
+        max_buffer_size: u32,
+    ) -> Result<()> {
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        require!(
+            pool.affiliate_tree().is_none(),
+            ErrorCode::AffiliateTreeExists
+        );
+
+        let seeds = &[
+            AFFILIATE_TREE_SEED,
+            pool_key.as_ref(),
+            &[ctx.bumps.tree_authority],
+        ];
+        compression::TreeCpi {
+            compression_program: &ctx.accounts.compression_program,
+            merkle_tree: &ctx.accounts.merkle_tree,
+            authority: &ctx.accounts.tree_authority,
+            noop_program: &ctx.accounts.noop_program,
+            signer_seeds: &[&seeds[..]],
+        }
+        .init_empty_merkle_tree(max_depth, max_buffer_size)?;
+        pool.affiliate_tree = ctx.accounts.merkle_tree.key();
+
+        emit!(AffiliateTreeInitialized {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            tree: pool.affiliate_tree,
+            max_depth,
+            max_buffer_size,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Add an affiliate as a leaf of the pool's affiliate tree instead of
+    /// an `AffiliateAccount`, for programs with too many affiliates to pay
+    /// rent on each. Indexers rebuild the record from the emitted event.
+    /// Ref ids aren't indexed on-chain, so their uniqueness is up to the merchant
+    pub fn add_compressed_affiliate(
+        ctx: Context<AddCompressedAffiliate>,
+        ref_id: String,
+    ) -> Result<()> {
+        validate_ref_id(&ref_id)?;
+
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        require!(pool.is_active(), ErrorCode::PoolInactive);
+
+        let now = Clock::get()?.unix_timestamp;
+        let affiliate = CompressedAffiliate {
+            wallet: ctx.accounts.affiliate_wallet.key(),
+            ref_id,
+            leaf_index: u32::try_from(pool.compressed_affiliate_count)
+                .map_err(|_| ErrorCode::ArithmeticOverflow)?,
+            total_earned: 0,
+            sales_count: 0,
+            total_sale_volume: 0,
+            last_sale_at: 0,
+            created_at: now,
+        };
+        let leaf = affiliate.leaf(&pool_key);
+
+        let seeds = &[
+            AFFILIATE_TREE_SEED,
+            pool_key.as_ref(),
+            &[ctx.bumps.tree_authority],
+        ];
+        compression::TreeCpi {
+            compression_program: &ctx.accounts.compression_program,
+            merkle_tree: &ctx.accounts.merkle_tree,
+            authority: &ctx.accounts.tree_authority,
+            noop_program: &ctx.accounts.noop_program,
+            signer_seeds: &[&seeds[..]],
+        }
+        .append(leaf)?;
+        pool.compressed_affiliate_count = pool
+            .compressed_affiliate_count
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        emit!(CompressedAffiliateAdded {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            tree: pool.affiliate_tree,
+            affiliate,
+            leaf,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Create the pool's compressed sale receipt tree in `merkle_tree`,
+    /// allocated by the caller like the affiliate tree. From then on
+    /// compressed sales append their receipt to it instead of writing a
+    /// `SaleReceipt`
+    pub fn init_receipt_tree(
+        ctx: Context<InitReceiptTree>,
+        max_depth: u32,
+        max_buffer_size: u32,
+    ) -> Result<()> {
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        require!(pool.receipt_tree().is_none(), ErrorCode::ReceiptTreeExists);
+
+        let seeds = &[
+            RECEIPT_TREE_SEED,
+            pool_key.as_ref(),
+            &[ctx.bumps.tree_authority],
+        ];
+        compression::TreeCpi {
+            compression_program: &ctx.accounts.compression_program,
+            merkle_tree: &ctx.accounts.merkle_tree,
+            authority: &ctx.accounts.tree_authority,
+            noop_program: &ctx.accounts.noop_program,
+            signer_seeds: &[&seeds[..]],
+        }
+        .init_empty_merkle_tree(max_depth, max_buffer_size)?;
+        pool.receipt_tree = ctx.accounts.merkle_tree.key();
+
+        emit!(ReceiptTreeInitialized {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            tree: pool.receipt_tree,
+            max_depth,
+            max_buffer_size,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Check that `receipt` is in the pool's receipt tree, with its proof
+    /// passed as the remaining accounts. Fails if it isn't, so other
+    /// programs can CPI into it to verify a sale
+    pub fn verify_sale_receipt<'info>(
+        ctx: Context<'_, '_, 'info, 'info, VerifySaleReceipt<'info>>,
+        root: [u8; 32],
+        receipt: CompressedReceipt,
+    ) -> Result<()> {
+        require!(
+            receipt.pool == ctx.accounts.merchant_pool.key(),
+            ErrorCode::ReceiptTreeMismatch
+        );
+        compression::verify_leaf(
+            &ctx.accounts.compression_program,
+            &ctx.accounts.receipt_tree,
+            root,
+            receipt.leaf(),
+            receipt.leaf_index,
+            ctx.remaining_accounts,
+        )
+    }
+
+    /// Process a sale and pay commission to affiliate
+    pub fn process_sale<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ProcessSale<'info>>,
+        args: ProcessSaleArgs,
+    ) -> Result<()> {
+        args.validate()?;
+        let ProcessSaleArgs {
+            sale_amount,
+            order_id,
+            commission_override_bps,
+            memo,
+            metadata_hash,
+            idempotent,
+        } = args;
+        let now = Clock::get()?.unix_timestamp;
+        let sale_amount = raw_amount(&ctx.accounts.usdc_mint, sale_amount, now)?;
+        let applied_rate_bps = sale_rate(ctx.accounts, commission_override_bps, now)?;
+
+        settle_sale(
+            ctx.accounts,
+            &ctx.bumps,
+            ctx.remaining_accounts,
+            SaleParams {
+                sale_amount,
+                order_id,
+                applied_rate_bps,
+                memo,
+                metadata_hash,
+                idempotent,
+                campaign: None,
+                campaign_redemptions: 0,
+                payment_verified: false,
+                now,
+            },
+        )?;
+
+        Ok(())
+    }
+
+    /// Process a sale in one of the pool's regions: its modifier adjusts the
+    /// rate and the commission is charged against its budget, while paying
+    /// from the pool's escrow
+    pub fn process_sale_in_region<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ProcessSaleInRegion<'info>>,
+        args: ProcessSaleArgs,
+    ) -> Result<()> {
+        args.validate()?;
+        let ProcessSaleArgs {
+            sale_amount,
+            order_id,
+            commission_override_bps,
+            memo,
+            metadata_hash,
+            idempotent,
+        } = args;
+        let now = Clock::get()?.unix_timestamp;
+        let sale_amount = raw_amount(&ctx.accounts.sale.usdc_mint, sale_amount, now)?;
+        let rate = sale_rate(&mut ctx.accounts.sale, commission_override_bps, now)?;
+        let applied_rate_bps = ctx.accounts.region.modified_rate(rate);
+
+        let Some(commission) = settle_sale(
+            &mut ctx.accounts.sale,
+            &ctx.bumps.sale,
+            ctx.remaining_accounts,
+            SaleParams {
+                sale_amount,
+                order_id,
+                applied_rate_bps,
+                memo,
+                metadata_hash,
+                idempotent,
+                campaign: None,
+                campaign_redemptions: 0,
+                payment_verified: false,
+                now,
+            },
+        )?
+        else {
+            return Ok(());
+        };
+
+        let region = &mut ctx.accounts.region;
+        region.spent = region
+            .spent
+            .checked_add(commission)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        require!(
+            region.spent <= region.budget,
+            ErrorCode::RegionBudgetExceeded
+        );
+        region.total_volume = region
+            .total_volume
+            .checked_add(sale_amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        region.sales_count = region
+            .sales_count
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        Ok(())
+    }
+
+    /// Process a sale only once the buyer's payment is proven: the
+    /// instruction right before this one must transfer exactly `sale_amount`
+    /// of the sale's mint into `merchant_usdc`, tagged with the Solana Pay
+    /// `reference`. Requiring the directly preceding instruction keeps one
+    /// payment from backing two sales in the same transaction, and the
+    /// payment must also carry the reference of the pool's `payment_nonce`,
+    /// which each sale advances, so a signed payment can't back a later one
+    pub fn process_sale_with_payment_proof<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ProcessSaleWithPaymentProof<'info>>,
+        args: ProcessSaleArgs,
+        reference: Pubkey,
+    ) -> Result<()> {
+        args.validate()?;
+        let ProcessSaleArgs {
+            sale_amount,
+            order_id,
+            commission_override_bps,
+            memo,
+            metadata_hash,
+            idempotent,
+        } = args;
+        let now = Clock::get()?.unix_timestamp;
+        let sale_amount = raw_amount(&ctx.accounts.sale.usdc_mint, sale_amount, now)?;
+        let instructions = ctx
+            .accounts
+            .sale
+            .instructions
+            .as_ref()
+            .ok_or(ErrorCode::InstructionsSysvarRequired)?;
+        let pool_key = ctx.accounts.sale.merchant_pool.key();
+        let nonce = {
+            let pool = &mut ctx.accounts.sale.merchant_pool.load_mut()?;
+            let nonce = pool.payment_nonce;
+            pool.payment_nonce = nonce.checked_add(1).ok_or(ErrorCode::ArithmeticOverflow)?;
+            nonce
+        };
+        let buyer = verify_payment(
+            instructions,
+            &ctx.accounts.sale.usdc_mint.key(),
+            &ctx.accounts.merchant_usdc.key(),
+            sale_amount,
+            &reference,
+            &pda::find_payment_nonce_reference(&pool_key, nonce).0,
+        )?;
+        let applied_rate_bps = sale_rate(&mut ctx.accounts.sale, commission_override_bps, now)?;
+
+        let Some(_) = settle_sale(
+            &mut ctx.accounts.sale,
+            &ctx.bumps.sale,
+            ctx.remaining_accounts,
+            SaleParams {
+                sale_amount,
+                order_id: order_id.clone(),
+                applied_rate_bps,
+                memo,
+                metadata_hash,
+                idempotent,
+                campaign: None,
+                campaign_redemptions: 0,
+                payment_verified: true,
+                now,
+            },
+        )?
+        else {
+            return Ok(());
+        };
+
+        emit!(PaymentVerified {
+            pool: ctx.accounts.sale.merchant_pool.key(),
+            pool_id: ctx
+                .accounts
+                .sale
+                .merchant_pool
+                .load()?
+                .pool_id()
+                .to_string(),
+            order_id,
+            reference,
+            nonce,
+            buyer,
+            amount: sale_amount,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Process a sale at a campaign's rate, charging the commission against
+    /// the campaign's budget while paying from the pool's escrow
+    pub fn process_sale_for_campaign<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ProcessSaleForCampaign<'info>>,
+        args: CampaignSaleArgs,
+    ) -> Result<()> {
+        args.validate()?;
+        let CampaignSaleArgs {
+            sale_amount,
+            order_id,
+            memo,
+            metadata_hash,
+            idempotent,
+        } = args;
+        let campaign = &ctx.accounts.campaign;
+        let now = Clock::get()?.unix_timestamp;
+        require!(campaign.is_live(now), ErrorCode::CampaignNotLive);
+        require!(
+            ctx.accounts
+                .sale
+                .affiliate_account
+                .has_tags(campaign.required_tags),
+            ErrorCode::MissingAffiliateTags
+        );
+        let sale_amount = raw_amount(&ctx.accounts.sale.usdc_mint, sale_amount, now)?;
+        let campaign_key = campaign.key();
+        let campaign_redemptions = campaign
+            .sales_count
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        let Some(commission) = settle_sale(
+            &mut ctx.accounts.sale,
+            &ctx.bumps.sale,
+            ctx.remaining_accounts,
+            SaleParams {
+                sale_amount,
+                order_id,
+                applied_rate_bps: campaign.commission_rate,
+                memo,
+                metadata_hash,
+                idempotent,
+                campaign: Some(campaign_key),
+                campaign_redemptions,
+                payment_verified: false,
+                now,
+            },
+        )?
+        else {
+            return Ok(());
+        };
+
+        let campaign = &mut ctx.accounts.campaign;
+        campaign.spent = campaign
+            .spent
+            .checked_add(commission)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        require!(
+            campaign.spent <= campaign.budget,
+            ErrorCode::CampaignBudgetExceeded
+        );
+        campaign.total_volume = campaign
+            .total_volume
+            .checked_add(sale_amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        campaign.sales_count = campaign_redemptions;
+
+        Ok(())
+    }
+
+    /// Process a sale credited to several affiliates at once, such as a
+    /// co-marketing deal or first-touch/last-touch attribution. Each
+    /// affiliate earns commission on its `weights_bps` share of the sale at
+    /// its own base rate, see `MerchantPool::rate_for`, the last affiliate
+    /// taking the rounding remainder, and its volume stats grow by that
+    /// share. Only the processor can
+    /// split sales. Pools and affiliates using what only `process_sale`
+    /// supports are refused rather than paid without it; see
+    /// `MerchantPool::supports_split_sales` and
+    /// `AffiliateAccount::supports_split_sales`. The receipt names the first
+    /// affiliate and is marked `split`, so `refund_partial` refuses it. The
+    /// remaining accounts are
+    /// (affiliate account, commission token account) pairs in weight order
+    pub fn process_sale_multi<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ProcessSaleMulti<'info>>,
+        args: MultiSaleArgs,
+    ) -> Result<()> {
+        args.validate()?;
+        let MultiSaleArgs {
+            sale_amount,
+            order_id,
+            weights_bps,
+        } = args;
+        let error = ErrorCode::InvalidSplitAccounts;
+        let groups =
+            remaining::groups::<2>(ctx.remaining_accounts, Some(weights_bps.len()), error)?;
+        require!(
+            ctx.accounts.sale_receipt.pool == Pubkey::default(),
+            ErrorCode::OrderAlreadyProcessed
+        );
+
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        require!(pool.is_active(), ErrorCode::PoolInactive);
+        require!(
+            !pool.requires_payment_proof(),
+            ErrorCode::PaymentProofRequired
+        );
+        require!(
+            pool.supports_split_sales(),
+            ErrorCode::PoolSplitSaleUnsupported
+        );
+        let now = Clock::get()?.unix_timestamp;
+        let sale_amount = raw_amount(&ctx.accounts.usdc_mint, sale_amount, now)?;
+
+        // Each affiliate earns its weighted share of the sale at its own base
+        // rate, which a rate cut's grace window may still hold above the
+        // pool rate
+        let mut shares: Vec<SplitShare> = Vec::with_capacity(weights_bps.len());
+        let mut volume_left = sale_amount;
+        let mut uncapped_commission: u64 = 0;
+        let mut weighted_rate_bps: u64 = 0;
+        for (index, (&weight_bps, [affiliate_info, affiliate_usdc_info])) in
+            weights_bps.iter().zip(groups).enumerate()
+        {
+            let mut affiliate = remaining::affiliate_of(affiliate_info, &pool_key, error)?;
+            let affiliate_usdc = remaining::commission_account_of(
+                affiliate_usdc_info,
+                &affiliate,
+                &pool.usdc_mint,
+                error,
+            )?;
+            if shares
+                .iter()
+                .any(|share| share.affiliate.key() == affiliate.key())
+            {
+                return Err(error.into());
+            }
+            require!(affiliate.is_active, ErrorCode::AffiliateInactive);
+            require!(
+                affiliate.activated_by_affiliate,
+                ErrorCode::AffiliateNotActivated
+            );
+            require!(
+                !pool.requires_terms() || affiliate.accepted_terms_hash == pool.terms_hash,
+                ErrorCode::TermsNotAccepted
+            );
+            require!(
+                affiliate.supports_split_sales(pool),
+                ErrorCode::AffiliateSplitSaleUnsupported
+            );
+
+            let volume = if index + 1 == weights_bps.len() {
+                volume_left
+            } else {
+                calculate_commission(sale_amount, weight_bps)?
+            };
+            volume_left -= volume;
+            let rate_bps = pool.rate_for(&affiliate, now);
+            // As in `sale_rate`, a sale at the pool rate moves the snapshot
+            if rate_bps == pool.commission_rate {
+                affiliate.rate_snapshot_bps = pool.commission_rate;
+                affiliate.rate_snapshot_at = now;
+            }
+            let uncapped = calculate_commission(volume, rate_bps)?;
+            uncapped_commission = uncapped_commission
+                .checked_add(uncapped)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+            weighted_rate_bps += u64::from(weight_bps) * u64::from(rate_bps);
+            shares.push(SplitShare {
+                affiliate,
+                affiliate_usdc,
+                weight_bps,
+                volume,
+                uncapped,
+            });
+        }
+        // Weights sum to 10000, so this is the affiliates' mean rate
+        let applied_rate_bps =
+            u16::try_from(weighted_rate_bps / 10000).map_err(|_| ErrorCode::ArithmeticOverflow)?;
+
+        // The pool's cap applies to the sale's whole commission, scaling
+        // every share down alike
+        let commission = pool.cap_commission(uncapped_commission)?;
+        require!(commission > 0, ErrorCode::CommissionTooSmall);
+        let (fee_tier, protocol_fee_bps) = pool.protocol_fee_tier(&ctx.accounts.config, now);
+        let protocol_fee = calculate_commission(sale_amount, protocol_fee_bps)?;
+
+        require!(
+            ctx.accounts.escrow_usdc.amount
+                >= pool
+                    .reserved_for(&pool.usdc_mint)
+                    .checked_add(commission)
+                    .and_then(|required| required.checked_add(protocol_fee))
+                    .ok_or(ErrorCode::ArithmeticOverflow)?,
+            ErrorCode::InsufficientEscrowBalance
+        );
+        pool.protocol_fees_accrued = pool
+            .protocol_fees_accrued
+            .checked_add(protocol_fee)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        let seeds = &[
+            ESCROW_AUTHORITY_SEED,
+            pool_key.as_ref(),
+            pool.escrow_version_seed(),
+            &[pool.escrow_bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+        let receipt_key = ctx.accounts.sale_receipt.key();
+
+        let mut splits: Vec<SaleSplit> = Vec::with_capacity(weights_bps.len());
+        let mut commission_left = commission;
+        let count = shares.len();
+        for (index, split_share) in shares.into_iter().enumerate() {
+            let SplitShare {
+                mut affiliate,
+                affiliate_usdc,
+                weight_bps,
+                volume,
+                uncapped,
+            } = split_share;
+            let share = if index + 1 == count {
+                commission_left
+            } else {
+                prorate(uncapped, commission, uncapped_commission)?
+            };
+            commission_left -= share;
+
+            // Outstanding clawbacks are recovered before anything is paid out
+            let debt_offset = affiliate.commission_debt.min(share);
+            let payout = share - debt_offset;
+            affiliate.commission_debt -= debt_offset;
+
+            let mut paid_out = 0;
+            if pool.payout_mode() == PayoutMode::Claim || !affiliate.payout_due(payout, now) {
+                if affiliate.claimable == 0 {
+                    affiliate.claimable_since = now;
+                }
+                affiliate.claimable = affiliate
+                    .claimable
+                    .checked_add(payout)
+                    .ok_or(ErrorCode::ArithmeticOverflow)?;
+                pool.total_claimable = pool
+                    .total_claimable
+                    .checked_add(payout)
+                    .ok_or(ErrorCode::ArithmeticOverflow)?;
+            } else {
+                let released = affiliate.claimable;
+                paid_out = payout
+                    .checked_add(released)
+                    .ok_or(ErrorCode::ArithmeticOverflow)?;
+                affiliate.claimable = 0;
+                affiliate.claimable_since = 0;
+                pool.total_claimable = pool
+                    .total_claimable
+                    .checked_sub(released)
+                    .ok_or(ErrorCode::ArithmeticOverflow)?;
+                affiliate.last_payout_at = now;
+                transfer_memo(&ctx.accounts.memo_program, &order_id)?;
+                token_interface::transfer_checked(
+                    CpiContext::new_with_signer(
+                        ctx.accounts.token_program.to_account_info(),
+                        TransferChecked {
+                            from: ctx.accounts.escrow_usdc.to_account_info(),
+                            mint: ctx.accounts.usdc_mint.to_account_info(),
+                            to: affiliate_usdc.to_account_info(),
+                            authority: ctx.accounts.escrow_authority.to_account_info(),
+                        },
+                        signer_seeds,
+                    ),
+                    paid_out,
+                    ctx.accounts.usdc_mint.decimals,
+                )?;
+            }
+
+            affiliate.total_earned = affiliate
+                .total_earned
+                .checked_add(share)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+            affiliate.sales_count = affiliate
+                .sales_count
+                .checked_add(1)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+            affiliate.last_sale_at = now;
+            affiliate.largest_sale = affiliate.largest_sale.max(volume);
+            affiliate.total_sale_volume = affiliate
+                .total_sale_volume
+                .checked_add(volume)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+            affiliate.record_period_volume(pool.period_index, volume)?;
+            affiliate
+                .open_statement
+                .record_sale(&receipt_key, volume, share, 0)?;
+
+            match &ctx.accounts.leaderboard {
+                Some(leaderboard) => leaderboard
+                    .load_mut()?
+                    .record(affiliate.key(), affiliate.total_sale_volume),
+                None => require!(!pool.has_leaderboard(), ErrorCode::LeaderboardRequired),
+            }
+            affiliate.exit(&crate::ID)?;
+
+            splits.push(SaleSplit {
+                affiliate: affiliate.key(),
+                weight_bps,
+                commission: share,
+                paid_out,
+            });
+        }
+
+        pool.record_sale(sale_amount, commission, &ctx.accounts.usdc_mint.key())?;
+        pool.record_activity(now);
+
+        let receipt = &mut ctx.accounts.sale_receipt;
+        receipt.pool = pool_key;
+        receipt.affiliate = splits[0].affiliate;
+        receipt.order_id = order_id.clone();
+        receipt.memo = String::new();
+        receipt.metadata_hash = [0; 32];
+        receipt.mint = pool.usdc_mint;
+        receipt.sale_amount = sale_amount;
+        receipt.commission = commission;
+        receipt.applied_rate_bps = applied_rate_bps;
+        receipt.refunded_amount = 0;
+        receipt.clawed_back = 0;
+        receipt.held_amount = 0;
+        receipt.releasable_at = 0;
+        receipt.bump = ctx.bumps.sale_receipt;
+        receipt.created_at = now;
+        receipt.split = true;
+
+        emit!(SplitSaleProcessed {
+            pool: pool_key,
+            pool_id_hash: pool.pool_id_hash,
+            order_id,
+            sale_amount,
+            commission,
+            applied_rate_bps,
+            protocol_fee,
+            fee_tier,
+            splits,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Process a sale by a compressed affiliate at the pool rate. The
+    /// commission is always paid out right away to the wallet's token
+    /// account: claim mode, holdbacks, managers and referral bounties only
+    /// apply to `AffiliateAccount`s. The leaf's proof is passed as the
+    /// remaining accounts, and the leaf is replaced with the updated stats.
+    /// Once the pool has a receipt tree the receipt is appended to it and
+    /// `sale_receipt` is left out; order ids are then only unique as far
+    /// as the processor keeps them so
+    pub fn process_compressed_sale<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ProcessCompressedSale<'info>>,
+        args: CompressedSaleArgs,
+    ) -> Result<()> {
+        args.validate()?;
+        let CompressedSaleArgs {
+            sale_amount,
+            order_id,
+            root,
+            affiliate,
+        } = args;
+        require!(
+            ctx.accounts.affiliate_usdc.owner == affiliate.wallet,
+            ErrorCode::AffiliateWalletMismatch
+        );
+
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        require!(pool.is_active(), ErrorCode::PoolInactive);
+        require!(
+            !pool.requires_payment_proof(),
+            ErrorCode::PaymentProofRequired
+        );
+        let now = Clock::get()?.unix_timestamp;
+        let sale_amount = raw_amount(&ctx.accounts.usdc_mint, sale_amount, now)?;
+
+        let commission =
+            pool.cap_commission(calculate_commission(sale_amount, pool.commission_rate)?)?;
+        require!(commission > 0, ErrorCode::CommissionTooSmall);
+        let (fee_tier, protocol_fee_bps) = pool.protocol_fee_tier(&ctx.accounts.config, now);
+        let protocol_fee = calculate_commission(sale_amount, protocol_fee_bps)?;
+
+        require!(
+            ctx.accounts.escrow_usdc.amount
+                >= pool
+                    .reserved_for(&pool.usdc_mint)
+                    .checked_add(commission)
+                    .and_then(|required| required.checked_add(protocol_fee))
+                    .ok_or(ErrorCode::ArithmeticOverflow)?,
+            ErrorCode::InsufficientEscrowBalance
+        );
+
+        let seeds = &[
+            ESCROW_AUTHORITY_SEED,
+            pool_key.as_ref(),
+            pool.escrow_version_seed(),
+            &[pool.escrow_bump],
+        ];
+        transfer_memo(&ctx.accounts.memo_program, &order_id)?;
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.escrow_usdc.to_account_info(),
+                    mint: ctx.accounts.usdc_mint.to_account_info(),
+                    to: ctx.accounts.affiliate_usdc.to_account_info(),
+                    authority: ctx.accounts.escrow_authority.to_account_info(),
+                },
+                &[&seeds[..]],
+            ),
+            commission,
+            ctx.accounts.usdc_mint.decimals,
+        )?;
+        pool.protocol_fees_accrued = pool
+            .protocol_fees_accrued
+            .checked_add(protocol_fee)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        pool.record_sale(sale_amount, commission, &ctx.accounts.usdc_mint.key())?;
+        pool.record_activity(now);
+
+        let previous_leaf = affiliate.leaf(&pool_key);
+        let updated = CompressedAffiliate {
+            total_earned: affiliate
+                .total_earned
+                .checked_add(commission)
+                .ok_or(ErrorCode::ArithmeticOverflow)?,
+            sales_count: affiliate
+                .sales_count
+                .checked_add(1)
+                .ok_or(ErrorCode::ArithmeticOverflow)?,
+            total_sale_volume: affiliate
+                .total_sale_volume
+                .checked_add(sale_amount)
+                .ok_or(ErrorCode::ArithmeticOverflow)?,
+            last_sale_at: now,
+            ..affiliate
+        };
+        let leaf = updated.leaf(&pool_key);
+
+        let tree_seeds = &[
+            AFFILIATE_TREE_SEED,
+            pool_key.as_ref(),
+            &[ctx.bumps.tree_authority],
+        ];
+        compression::TreeCpi {
+            compression_program: &ctx.accounts.compression_program,
+            merkle_tree: &ctx.accounts.merkle_tree,
+            authority: &ctx.accounts.tree_authority,
+            noop_program: &ctx.accounts.noop_program,
+            signer_seeds: &[&tree_seeds[..]],
+        }
+        .replace_leaf(
+            root,
+            previous_leaf,
+            leaf,
+            updated.leaf_index,
+            ctx.remaining_accounts,
+        )?;
+
+        if pool.receipt_tree().is_some() {
+            let (Some(tree_authority), Some(receipt_tree), Some(bump)) = (
+                ctx.accounts.receipt_tree_authority.as_ref(),
+                ctx.accounts.receipt_tree.as_ref(),
+                ctx.bumps.receipt_tree_authority,
+            ) else {
+                return err!(ErrorCode::ReceiptTreeRequired);
+            };
+            let receipt = CompressedReceipt {
+                pool: pool_key,
+                affiliate: updated.wallet,
+                order_id: order_id.clone(),
+                mint: pool.usdc_mint,
+                sale_amount,
+                commission,
+                applied_rate_bps: pool.commission_rate,
+                leaf_index: u32::try_from(pool.compressed_receipt_count)
+                    .map_err(|_| ErrorCode::ArithmeticOverflow)?,
+                created_at: now,
+            };
+            let receipt_leaf = receipt.leaf();
+
+            let receipt_seeds = &[RECEIPT_TREE_SEED, pool_key.as_ref(), &[bump]];
+            compression::TreeCpi {
+                compression_program: &ctx.accounts.compression_program,
+                merkle_tree: receipt_tree,
+                authority: tree_authority,
+                noop_program: &ctx.accounts.noop_program,
+                signer_seeds: &[&receipt_seeds[..]],
+            }
+            .append(receipt_leaf)?;
+            pool.compressed_receipt_count = pool
+                .compressed_receipt_count
+                .checked_add(1)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+            emit!(SaleReceiptCompressed {
+                pool: pool_key,
+                tree: pool.receipt_tree,
+                receipt,
+                leaf: receipt_leaf,
+                timestamp: now,
+            });
+        } else {
+            // The receipt keeps order ids unique across both kinds of affiliate
+            let (Some(receipt), Some(bump)) =
+                (ctx.accounts.sale_receipt.as_mut(), ctx.bumps.sale_receipt)
+            else {
+                return err!(ErrorCode::SaleReceiptRequired);
+            };
+            receipt.pool = pool_key;
+            receipt.affiliate = updated.wallet;
+            receipt.order_id = order_id.clone();
+            receipt.mint = pool.usdc_mint;
+            receipt.sale_amount = sale_amount;
+            receipt.commission = commission;
+            receipt.applied_rate_bps = pool.commission_rate;
+            receipt.bump = bump;
+            receipt.created_at = now;
+            receipt.split = false;
+        }
+
+        emit!(CompressedSaleProcessed {
+            pool: pool_key,
+            pool_id_hash: pool.pool_id_hash,
+            tree: pool.affiliate_tree,
+            order_id,
+            sale_amount,
+            commission,
+            protocol_fee,
+            fee_tier,
+            affiliate: updated,
+            leaf,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Start a promotion within the pool with its own commission rate,
+    /// commission budget and time window; `ends_at` of zero is open-ended
+    pub fn create_campaign(ctx: Context<CreateCampaign>, args: CreateCampaignArgs) -> Result<()> {
+        args.validate()?;
+        let CreateCampaignArgs {
+            campaign_id,
+            commission_rate,
+            budget,
+            starts_at,
+            ends_at,
+            required_tags,
+        } = args;
+
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &ctx.accounts.merchant_pool.load()?;
+        require!(pool.is_active(), ErrorCode::PoolInactive);
+
+        let campaign = &mut ctx.accounts.campaign;
+        campaign.pool = pool_key;
+        campaign.campaign_id = campaign_id.clone();
+        campaign.commission_rate = commission_rate;
+        campaign.budget = budget;
+        campaign.spent = 0;
+        campaign.total_volume = 0;
+        campaign.sales_count = 0;
+        campaign.starts_at = starts_at;
+        campaign.ends_at = ends_at;
+        campaign.required_tags = required_tags;
+        campaign.is_active = true;
+        campaign.bump = ctx.bumps.campaign;
+        campaign.created_at = Clock::get()?.unix_timestamp;
+
+        emit!(CampaignCreated {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            campaign: campaign.key(),
+            campaign_id,
+            commission_rate,
+            budget,
+            starts_at,
+            ends_at,
+            required_tags,
+            timestamp: campaign.created_at,
+        });
+
+        Ok(())
+    }
+
+    /// Partition the pool by market: sales in `region`, a two-letter code
+    /// such as `*b"DE"`, have `rate_modifier_bps` added to their rate and may
+    /// pay at most `budget` in commissions, all from the pool's escrow
+    pub fn create_region(
+        ctx: Context<CreateRegion>,
+        region_code: [u8; 2],
+        budget: u64,
+        rate_modifier_bps: i16,
+    ) -> Result<()> {
+        require!(
+            region_code.iter().all(u8::is_ascii_uppercase),
+            ErrorCode::InvalidRegion
+        );
+        validate_amount(budget)?;
+        validate_rate_modifier(rate_modifier_bps)?;
+
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &ctx.accounts.merchant_pool.load()?;
+        require!(pool.is_active(), ErrorCode::PoolInactive);
+
+        let partition = &mut ctx.accounts.region;
+        partition.pool = pool_key;
+        partition.region = region_code;
+        partition.rate_modifier_bps = rate_modifier_bps;
+        partition.budget = budget;
+        partition.spent = 0;
+        partition.total_volume = 0;
+        partition.sales_count = 0;
+        partition.bump = ctx.bumps.region;
+        partition.created_at = Clock::get()?.unix_timestamp;
+
+        emit!(RegionUpdated {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            region_account: partition.key(),
+            region: region_code,
+            budget,
+            rate_modifier_bps,
+            timestamp: partition.created_at,
+        });
+
+        Ok(())
+    }
+
+    /// Change a region's commission budget and rate modifier. A budget at or
+    /// below what it has spent stops its sales
+    pub fn update_region(
+        ctx: Context<UpdateRegion>,
+        budget: u64,
+        rate_modifier_bps: i16,
+    ) -> Result<()> {
+        validate_rate_modifier(rate_modifier_bps)?;
+
+        let partition = &mut ctx.accounts.region;
+        partition.budget = budget;
+        partition.rate_modifier_bps = rate_modifier_bps;
+
+        emit!(RegionUpdated {
+            pool: partition.pool,
+            pool_id: ctx.accounts.merchant_pool.load()?.pool_id().to_string(),
+            region_account: partition.key(),
+            region: partition.region,
+            budget,
+            rate_modifier_bps,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Create a group the merchant can assign affiliates to, such as the
+    /// creators an agency manages. Members' sales roll up into the group,
+    /// and `rate_modifier_bps` is added to their rate in `process_sale`
+    pub fn create_affiliate_group(
+        ctx: Context<CreateAffiliateGroup>,
+        group_id: String,
+        rate_modifier_bps: i16,
+    ) -> Result<()> {
+        validate_id(&group_id, ErrorCode::InvalidGroupId)?;
+        validate_rate_modifier(rate_modifier_bps)?;
+
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &ctx.accounts.merchant_pool.load()?;
+        require!(pool.is_active(), ErrorCode::PoolInactive);
+
+        let group = &mut ctx.accounts.affiliate_group;
+        group.pool = pool_key;
+        group.group_id = group_id.clone();
+        group.rate_modifier_bps = rate_modifier_bps;
+        group.member_count = 0;
+        group.total_sale_volume = 0;
+        group.total_earned = 0;
+        group.sales_count = 0;
+        group.bump = ctx.bumps.affiliate_group;
+        group.created_at = Clock::get()?.unix_timestamp;
+        group.payout_account = None;
+
+        emit!(AffiliateGroupCreated {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            group: group.key(),
+            group_id,
+            rate_modifier_bps,
+            timestamp: group.created_at,
+        });
+
+        Ok(())
+    }
+
+    /// Change the rate modifier applied to the group's members' sales
+    pub fn set_group_rate_modifier(
+        ctx: Context<UpdateAffiliateGroup>,
+        rate_modifier_bps: i16,
+    ) -> Result<()> {
+        validate_rate_modifier(rate_modifier_bps)?;
+
+        let group = &mut ctx.accounts.affiliate_group;
+        let old_modifier_bps = group.rate_modifier_bps;
+        group.rate_modifier_bps = rate_modifier_bps;
+
+        emit!(GroupRateModifierUpdated {
+            pool: group.pool,
+            pool_id: ctx.accounts.merchant_pool.load()?.pool_id().to_string(),
+            group: group.key(),
+            old_modifier_bps,
+            new_modifier_bps: rate_modifier_bps,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Route every member's primary-mint commissions, pushed or claimed, to
+    /// `payout_usdc`, such as the agency's account, ahead of the members' own
+    /// payout accounts. Members' stats still accrue individually. Omitting it
+    /// pays members directly again; `process_sale_multi` and holdback
+    /// releases always pay members directly
+    pub fn set_group_payout_account(ctx: Context<SetGroupPayoutAccount>) -> Result<()> {
+        let payout_usdc = ctx.accounts.payout_usdc.as_ref();
+        let group = &mut ctx.accounts.affiliate_group;
+        group.payout_account = payout_usdc.map(|account| account.key());
+
+        emit!(GroupPayoutAccountUpdated {
+            pool: group.pool,
+            pool_id: ctx.accounts.merchant_pool.load()?.pool_id().to_string(),
+            group: group.key(),
+            payout_account: group.payout_account,
+            owner: payout_usdc.map(|account| account.owner),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Make an ungrouped affiliate a member of the group. Its earlier sales
+    /// stay out of the group's stats
+    pub fn assign_affiliate_group(ctx: Context<AssignAffiliateGroup>) -> Result<()> {
+        let affiliate = &mut ctx.accounts.affiliate_account;
+        require!(
+            affiliate.group.is_none(),
+            ErrorCode::AffiliateAlreadyGrouped
+        );
+        let group = &mut ctx.accounts.affiliate_group;
+        affiliate.group = Some(group.key());
+        group.member_count = group
+            .member_count
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        emit!(AffiliateGroupChanged {
+            pool: group.pool,
+            pool_id: ctx.accounts.merchant_pool.load()?.pool_id().to_string(),
+            group: group.key(),
+            affiliate: affiliate.key(),
+            joined: true,
+            member_count: group.member_count,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Take an affiliate out of its group; the group keeps the stats its
+    /// sales contributed
+    pub fn remove_affiliate_from_group(ctx: Context<AssignAffiliateGroup>) -> Result<()> {
+        let affiliate = &mut ctx.accounts.affiliate_account;
+        let group = &mut ctx.accounts.affiliate_group;
+        require!(
+            affiliate.group == Some(group.key()),
+            ErrorCode::AffiliateGroupMismatch
+        );
+        affiliate.group = None;
+        group.member_count = group
+            .member_count
+            .checked_sub(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        emit!(AffiliateGroupChanged {
+            pool: group.pool,
+            pool_id: ctx.accounts.merchant_pool.load()?.pool_id().to_string(),
+            group: group.key(),
+            affiliate: affiliate.key(),
+            joined: false,
+            member_count: group.member_count,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// End a campaign early; its sales history stays on the account
+    pub fn end_campaign(ctx: Context<EndCampaign>) -> Result<()> {
+        let campaign = &mut ctx.accounts.campaign;
+        campaign.is_active = false;
+
+        emit!(CampaignEnded {
+            pool: campaign.pool,
+            pool_id: ctx.accounts.merchant_pool.load()?.pool_id().to_string(),
+            campaign: campaign.key(),
+            spent: campaign.spent,
+            total_volume: campaign.total_volume,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Quote the commission a sale would pay without moving any funds, at
+    /// the rate `process_sale` would apply without an override. The
+    /// breakdown, worked out as the sale would settle it, is returned via
+    /// return data.
+    pub fn quote_commission(
+        ctx: Context<QuoteCommission>,
+        sale_amount: u64,
+    ) -> Result<CommissionQuote> {
+        require!(sale_amount > 0, ErrorCode::InvalidAmount);
+
+        let pool = &ctx.accounts.merchant_pool.load()?;
+        require!(pool.is_active(), ErrorCode::PoolInactive);
+
+        let affiliate = &ctx.accounts.affiliate_account;
+        require!(affiliate.is_active, ErrorCode::AffiliateInactive);
+
+        let now = Clock::get()?.unix_timestamp;
+        let sale_amount = match &ctx.accounts.usdc_mint {
+            Some(mint) => raw_amount(mint, sale_amount, now)?,
+            None => sale_amount,
+        };
+        let group = ctx.accounts.affiliate_group.as_deref();
+        require!(
+            affiliate.group.is_none() || group.is_some(),
+            ErrorCode::AffiliateGroupRequired
+        );
+        let commission_rate = pool.affiliate_rate(affiliate, group, now);
+        let commission =
+            pool.cap_commission(calculate_commission(sale_amount, commission_rate)?)?;
+        require!(commission > 0, ErrorCode::CommissionTooSmall);
+        let breakdown = CommissionBreakdown::new(
+            pool,
+            &ctx.accounts.config,
+            affiliate,
+            &pool.usdc_mint,
+            sale_amount,
+            commission,
+            now,
+        )?;
+
+        Ok(CommissionQuote {
+            sale_amount,
+            commission_rate,
+            commission,
+            affiliate_payout: breakdown.payout,
+            debt_offset: breakdown.debt_offset,
+            protocol_fee: breakdown.protocol_fee,
+            manager_cut: breakdown.manager_cut,
+            holdback: breakdown.holdback,
+            store_credit: breakdown.store_credit,
+        })
+    }
+
+    /// Emit a `PoolHeartbeat` with the pool's escrow balance, liabilities
+    /// and counters. Permissionless and read-only, so monitors can crank it
+    /// on a schedule and follow pool health from the event stream alone
+    pub fn ping_pool(ctx: Context<PingPool>) -> Result<()> {
+        let pool = &ctx.accounts.merchant_pool.load()?;
+        let escrow_balance = ctx.accounts.escrow_usdc.amount;
+        let reserved = pool.reserved_for(&pool.usdc_mint);
+
+        emit!(PoolHeartbeat {
+            pool: ctx.accounts.merchant_pool.key(),
+            pool_id: pool.pool_id().to_string(),
+            escrow_balance,
+            reserved,
+            available: escrow_balance.saturating_sub(reserved),
+            total_claimable: pool.total_claimable,
+            total_held: pool.total_held,
+            protocol_fees_accrued: pool.protocol_fees_accrued,
+            affiliate_count: pool.affiliate_count,
+            total_volume: pool.total_volume,
+            total_commissions_paid: pool.total_commissions_paid,
+            period_index: pool.period_index,
+            period_volume: pool.period_volume,
+            is_active: pool.is_active(),
+            flagged: pool.flagged_at != 0,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Track the interest an interest-bearing primary mint has added to the
+    /// escrow's value since the last sync, as the raw escrow balance times
+    /// the growth of the mint's scale. Permissionless, so anyone can crank
+    /// it; syncing often keeps deposits and withdrawals between syncs from
+    /// skewing the figure
+    pub fn sync_interest(ctx: Context<SyncInterest>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let scale = interest_scale(&ctx.accounts.usdc_mint, now)?
+            .ok_or(ErrorCode::MintNotInterestBearing)?;
+        let escrow_balance = ctx.accounts.escrow_usdc.amount;
+
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        let interest = match pool.synced_interest_scale {
+            0 => 0,
+            synced => prorate(
+                escrow_balance,
+                scale.saturating_sub(synced),
+                INTEREST_SCALE_ONE,
+            )?,
+        };
+        pool.interest_earned = pool
+            .interest_earned
+            .checked_add(interest)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        pool.synced_interest_scale = scale;
+        pool.interest_synced_at = now;
+
+        emit!(InterestSynced {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            escrow_balance,
+            escrow_ui_balance: prorate(escrow_balance, scale, INTEREST_SCALE_ONE)?,
+            interest,
+            interest_earned: pool.interest_earned,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Refund part of a recorded sale and claw back the matching share of
+    /// its commission, first from the affiliate's unclaimed balance and
+    /// otherwise as debt netted against future commissions
+    pub fn refund_partial(
+        ctx: Context<RefundPartial>,
+        order_id: String,
+        refund_amount: u64,
+    ) -> Result<()> {
+        let receipt = &mut ctx.accounts.sale_receipt;
+        require!(!receipt.split, ErrorCode::SplitSaleRefundUnsupported);
+        let refundable = receipt
+            .sale_amount
+            .checked_sub(receipt.refunded_amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        require!(
+            refund_amount > 0 && refund_amount <= refundable,
+            ErrorCode::InvalidRefundAmount
+        );
+
+        receipt.refunded_amount = receipt
+            .refunded_amount
+            .checked_add(refund_amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        // Prorate cumulatively so repeated partial refunds don't drift
+        let clawed_back = prorate(
+            receipt.commission,
+            receipt.refunded_amount,
+            receipt.sale_amount,
+        )?;
+        let clawback = clawed_back
+            .checked_sub(receipt.clawed_back)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        receipt.clawed_back = clawed_back;
+
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        let affiliate = &mut ctx.accounts.affiliate_account;
+
+        // Commission still held back on this sale is clawed back first.
+        // Only primary-mint commissions are held back or accrue, so a
+        // settlement-mint sale's clawback becomes debt in that mint
+        let slot = pool
+            .mint_slot(&receipt.mint)
+            .ok_or(ErrorCode::MintMismatch)?;
+        let (from_held, from_claimable) = if slot == 0 {
+            let from_held = receipt.held_amount.min(clawback);
+            (from_held, affiliate.claimable.min(clawback - from_held))
+        } else {
+            (0, 0)
+        };
+        receipt.held_amount -= from_held;
+        let debt_added = clawback - from_held - from_claimable;
+        affiliate.total_held -= from_held;
+        affiliate.claimable -= from_claimable;
+        if affiliate.claimable == 0 {
+            affiliate.claimable_since = 0;
+        }
+        let debt = affiliate.debt_mut(slot);
+        *debt = debt
+            .checked_add(debt_added)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        affiliate.total_earned = affiliate
+            .total_earned
+            .checked_sub(clawback)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        affiliate.total_sale_volume = affiliate
+            .total_sale_volume
+            .checked_sub(refund_amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        affiliate
+            .open_statement
+            .record_refund(refund_amount, clawback)?;
+
+        pool.total_claimable = pool
+            .total_claimable
+            .checked_sub(from_claimable)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        pool.total_held = pool
+            .total_held
+            .checked_sub(from_held)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        pool.total_volume = pool
+            .total_volume
+            .checked_sub(refund_amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        pool.total_commissions_paid = pool
+            .total_commissions_paid
+            .checked_sub(clawback)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        pool.unrecord_mint_sale(&receipt.mint, refund_amount, clawback);
+        // A refund of an earlier period's sale nets against the current one
+        pool.period_volume = pool.period_volume.saturating_sub(refund_amount);
+        pool.period_commissions_paid = pool.period_commissions_paid.saturating_sub(clawback);
+
+        emit!(SaleRefunded {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            affiliate: affiliate.key(),
+            order_id,
+            refund_amount,
+            clawback,
+            from_held,
+            from_claimable,
+            debt_added,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Rebrand an affiliate's ref_id, keeping its stats and earnings
+    pub fn update_affiliate_ref_id(
+        ctx: Context<UpdateAffiliateRefId>,
+        new_ref_id: String,
+    ) -> Result<()> {
+        validate_ref_id(&new_ref_id)?;
+
+        let affiliate = &mut ctx.accounts.affiliate_account;
+        let old_ref_id = std::mem::replace(&mut affiliate.ref_id, new_ref_id.clone());
+
+        let ref_index = &mut ctx.accounts.new_ref_id_index;
+        ref_index.pool = affiliate.pool;
+        ref_index.affiliate = affiliate.key();
+        ref_index.redemptions = 0;
+        ref_index.click_registered_at = 0;
+        ref_index.bump = ctx.bumps.new_ref_id_index;
+
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &ctx.accounts.merchant_pool.load()?;
+
+        emit!(AffiliateRefIdUpdated {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            affiliate: affiliate.key(),
+            old_ref_id,
+            new_ref_id,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Batch small push payouts: commissions accrue until the affiliate is
+    /// owed `min_payout_amount` and `min_payout_interval_secs` have passed
+    /// since the last payout. Set by the affiliate wallet or the merchant
+    pub fn set_payout_threshold(
+        ctx: Context<SetPayoutThreshold>,
+        min_payout_amount: u64,
+        min_payout_interval_secs: i64,
+    ) -> Result<()> {
+        require!(
+            min_payout_interval_secs >= 0,
+            ErrorCode::InvalidPayoutInterval
+        );
+
+        let affiliate = &mut ctx.accounts.affiliate_account;
+        affiliate.min_payout_amount = min_payout_amount;
+        affiliate.min_payout_interval_secs = min_payout_interval_secs;
+
+        emit!(PayoutThresholdUpdated {
+            pool: affiliate.pool,
+            pool_id: ctx.accounts.merchant_pool.load()?.pool_id().to_string(),
+            affiliate: affiliate.key(),
+            min_payout_amount,
+            min_payout_interval_secs,
+            updated_by: ctx.accounts.authority.key(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Checkpoint an affiliate's running stats for `period` (e.g. `202610`
+    /// for October 2026) in a write-once `AffiliateSnapshot`, so statements
+    /// can be proven after the affiliate account moves on. Signed by the
+    /// merchant or the pool's processor
+    pub fn snapshot_affiliate(ctx: Context<SnapshotAffiliate>, period: u32) -> Result<()> {
+        let affiliate = &ctx.accounts.affiliate_account;
+        let now = Clock::get()?.unix_timestamp;
+
+        let snapshot = &mut ctx.accounts.snapshot;
+        snapshot.pool = affiliate.pool;
+        snapshot.affiliate = affiliate.key();
+        snapshot.period = period;
+        snapshot.total_earned = affiliate.total_earned;
+        snapshot.manager_earned = affiliate.manager_earned;
+        snapshot.sales_count = affiliate.sales_count;
+        snapshot.total_sale_volume = affiliate.total_sale_volume;
+        snapshot.largest_sale = affiliate.largest_sale;
+        snapshot.claimable = affiliate.claimable;
+        snapshot.total_held = affiliate.total_held;
+        snapshot.commission_debt = affiliate.commission_debt;
+        snapshot.taken_at = now;
+        snapshot.bump = ctx.bumps.snapshot;
+
+        emit!(AffiliateSnapshotTaken {
+            pool: affiliate.pool,
+            pool_id: ctx.accounts.merchant_pool.load()?.pool_id().to_string(),
+            affiliate: affiliate.key(),
+            period,
+            total_earned: affiliate.total_earned,
+            sales_count: affiliate.sales_count,
+            total_sale_volume: affiliate.total_sale_volume,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Close the affiliate's open statement into an immutable `Statement`
+    /// for `period`, covering every sale and refund since its previous one.
+    /// Periods must increase, so each activity lands in exactly one
+    /// statement. Signed by the merchant or the pool's processor
+    pub fn generate_statement(ctx: Context<GenerateStatement>, period: u32) -> Result<()> {
+        let affiliate = &mut ctx.accounts.affiliate_account;
+        require!(
+            period > affiliate.last_statement_period,
+            ErrorCode::StatementPeriodNotAfterLast
+        );
+        let now = Clock::get()?.unix_timestamp;
+
+        let statement = &mut ctx.accounts.statement;
+        statement.pool = affiliate.pool;
+        statement.affiliate = affiliate.key();
+        statement.period = period;
+        statement.previous_period = affiliate.last_statement_period;
+        statement.totals = affiliate.open_statement;
+        statement.total_earned = affiliate.total_earned;
+        statement.total_sale_volume = affiliate.total_sale_volume;
+        statement.generated_at = now;
+        statement.bump = ctx.bumps.statement;
+
+        affiliate.open_statement = StatementTotals::default();
+        affiliate.last_statement_period = period;
+
+        emit!(StatementGenerated {
+            pool: statement.pool,
+            pool_id: ctx.accounts.merchant_pool.load()?.pool_id().to_string(),
+            affiliate: statement.affiliate,
+            statement: statement.key(),
+            period,
+            totals: statement.totals,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Route `manager_share_bps` of every future commission to a manager or
+    /// agency wallet, or stop doing so with `None`. Signed by the affiliate
+    pub fn set_manager(
+        ctx: Context<SetManager>,
+        manager: Option<Pubkey>,
+        manager_share_bps: u16,
+    ) -> Result<()> {
+        require!(
+            manager_share_bps <= 10000 && (manager.is_some() || manager_share_bps == 0),
+            ErrorCode::InvalidManagerShare
+        );
+
+        let affiliate = &mut ctx.accounts.affiliate_account;
+        affiliate.manager = manager;
+        affiliate.manager_share_bps = manager_share_bps;
+
+        emit!(ManagerUpdated {
+            pool: affiliate.pool,
+            pool_id: ctx.accounts.merchant_pool.load()?.pool_id().to_string(),
+            affiliate: affiliate.key(),
+            manager,
+            manager_share_bps,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Split the affiliate's primary-mint payouts and claims between up to
+    /// `MAX_PAYOUT_SPLITS` token accounts, e.g. a personal and a tax wallet,
+    /// passed as remaining accounts in `weights_bps` order. Weights total
+    /// 10000; none pays the payout account whole again
+    pub fn set_payout_splits<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SetPayoutSplits<'info>>,
+        weights_bps: Vec<u16>,
+    ) -> Result<()> {
+        require!(
+            weights_bps.len() <= MAX_PAYOUT_SPLITS
+                && weights_bps.iter().all(|&weight_bps| weight_bps > 0)
+                && (weights_bps.is_empty()
+                    || weights_bps
+                        .iter()
+                        .map(|&weight_bps| u32::from(weight_bps))
+                        .sum::<u32>()
+                        == 10000),
+            ErrorCode::InvalidPayoutSplits
+        );
+        let groups = remaining::groups::<1>(
+            ctx.remaining_accounts,
+            Some(weights_bps.len()),
+            ErrorCode::InvalidPayoutSplits,
+        )?;
+        let usdc_mint = ctx.accounts.merchant_pool.load()?.usdc_mint;
+        let escrow_authority = ctx.accounts.escrow_authority.key();
+
+        let mut splits: Vec<PayoutSplit> = Vec::with_capacity(weights_bps.len());
+        for (&weight_bps, [info]) in weights_bps.iter().zip(groups) {
+            let token_account = InterfaceAccount::<TokenAccount>::try_from(info)?;
+            require!(token_account.mint == usdc_mint, ErrorCode::MintMismatch);
+            require!(
+                token_account.owner != escrow_authority,
+                ErrorCode::InvalidPayoutAccount
+            );
+            require!(
+                !splits.iter().any(|split| split.token_account == info.key()),
+                ErrorCode::InvalidPayoutSplits
+            );
+            splits.push(PayoutSplit {
+                token_account: info.key(),
+                weight_bps,
+            });
+        }
+
+        let affiliate = &mut ctx.accounts.affiliate_account;
+        require!(
+            splits.is_empty() || !affiliate.compressed_payouts,
+            ErrorCode::CompressedPayoutsRouted
+        );
+        require!(
+            splits.is_empty() || affiliate.elgamal_pubkey.is_none(),
+            ErrorCode::ConfidentialPayoutsRouted
+        );
+        affiliate.payout_splits = splits.clone();
+
+        emit!(PayoutSplitsUpdated {
+            pool: affiliate.pool,
+            pool_id: ctx.accounts.merchant_pool.load()?.pool_id().to_string(),
+            affiliate: affiliate.key(),
+            splits,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Route the affiliate's primary-mint commissions to `payout_usdc`, such
+    /// as a custodial or exchange deposit account, instead of the wallet's
+    /// ATA. Omitting it goes back to the ATA
+    pub fn set_payout_account(ctx: Context<SetPayoutAccount>) -> Result<()> {
+        let payout_usdc = ctx.accounts.payout_usdc.as_ref();
+        let affiliate = &mut ctx.accounts.affiliate_account;
+        require!(
+            payout_usdc.is_none() || !affiliate.compressed_payouts,
+            ErrorCode::CompressedPayoutsRouted
+        );
+        require!(
+            payout_usdc.is_none() || affiliate.elgamal_pubkey.is_none(),
+            ErrorCode::ConfidentialPayoutsRouted
+        );
+        affiliate.payout_account = payout_usdc.map(|account| account.key());
+
+        emit!(PayoutAccountUpdated {
+            pool: affiliate.pool,
+            pool_id: ctx.accounts.merchant_pool.load()?.pool_id().to_string(),
+            affiliate: affiliate.key(),
+            payout_account: affiliate.payout_account,
+            owner: payout_usdc.map(|account| account.owner),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Opt into commissions paid as Light compressed tokens owned by the
+    /// wallet, which need no token account: primary-mint commissions accrue
+    /// until the pool's processor pays them out with `pay_out_compressed`.
+    /// Unavailable while payouts go to a payout account or splits
+    pub fn set_compressed_payouts(ctx: Context<SetCompressedPayouts>, enabled: bool) -> Result<()> {
+        let affiliate = &mut ctx.accounts.affiliate_account;
+        require!(
+            !enabled || (affiliate.payout_account.is_none() && affiliate.payout_splits.is_empty()),
+            ErrorCode::CompressedPayoutsRouted
+        );
+        require!(
+            !enabled || affiliate.elgamal_pubkey.is_none(),
+            ErrorCode::ConfidentialPayoutsRouted
+        );
+        affiliate.compressed_payouts = enabled;
+
+        emit!(CompressedPayoutsUpdated {
+            pool: affiliate.pool,
+            pool_id: ctx.accounts.merchant_pool.load()?.pool_id().to_string(),
+            affiliate: affiliate.key(),
+            wallet: affiliate.wallet,
+            enabled,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Opt into confidential payouts under `elgamal_pubkey`, the key the
+    /// wallet's primary-mint token account is configured for confidential
+    /// transfers with, or opt out with `None`. Primary-mint commissions then
+    /// accrue until the pool's processor pays them into that account's
+    /// encrypted balance with `pay_out_confidential`. Needs the pool's
+    /// confidential payouts on, and is unavailable while payouts go to a
+    /// payout account, splits or compressed payouts
+    pub fn set_confidential_payout_key(
+        ctx: Context<SetConfidentialPayoutKey>,
+        elgamal_pubkey: Option<[u8; 32]>,
+    ) -> Result<()> {
+        let pool = &ctx.accounts.merchant_pool.load()?;
+        let affiliate = &mut ctx.accounts.affiliate_account;
+        if elgamal_pubkey.is_some() {
+            require!(
+                pool.confidential_payouts(),
+                ErrorCode::ConfidentialPayoutsDisabled
+            );
+            require!(
+                affiliate.payout_account.is_none()
+                    && affiliate.payout_splits.is_empty()
+                    && !affiliate.compressed_payouts,
+                ErrorCode::ConfidentialPayoutsRouted
+            );
+        }
+        affiliate.elgamal_pubkey = elgamal_pubkey;
+
+        emit!(ConfidentialPayoutKeyUpdated {
+            pool: affiliate.pool,
+            pool_id: pool.pool_id().to_string(),
+            affiliate: affiliate.key(),
+            wallet: affiliate.wallet,
+            elgamal_pubkey,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Accept the pool's current terms of service as the affiliate wallet
+    pub fn accept_terms(ctx: Context<AcceptTerms>, terms_hash: [u8; 32]) -> Result<()> {
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &ctx.accounts.merchant_pool.load()?;
+        require!(
+            pool.requires_terms() && terms_hash == pool.terms_hash,
+            ErrorCode::TermsHashMismatch
+        );
+
+        let affiliate = &mut ctx.accounts.affiliate_account;
+        affiliate.accepted_terms_hash = terms_hash;
+        affiliate.terms_accepted_at = Clock::get()?.unix_timestamp;
+
+        emit!(TermsAccepted {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            affiliate: affiliate.key(),
+            wallet: affiliate.wallet,
+            terms_hash,
+            timestamp: affiliate.terms_accepted_at,
+        });
+
+        Ok(())
+    }
+
+    /// Consent, as the affiliate wallet, to the account the merchant created
+    /// for it. Sales can't pay the affiliate until it has
+    pub fn activate_self(ctx: Context<ActivateSelf>) -> Result<()> {
+        let affiliate = &mut ctx.accounts.affiliate_account;
+        require!(
+            !affiliate.activated_by_affiliate,
+            ErrorCode::AffiliateAlreadyActivated
+        );
+        affiliate.activated_by_affiliate = true;
+
+        emit!(AffiliateSelfActivated {
+            pool: affiliate.pool,
+            pool_id: ctx.accounts.merchant_pool.load()?.pool_id().to_string(),
+            affiliate: affiliate.key(),
+            wallet: affiliate.wallet,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Pay out an affiliate's accrued commissions. Callable by the affiliate,
+    /// or by the merchant to force settlement before removing them.
+    /// Remaining accounts are the affiliate's payout split token accounts
+    pub fn claim_commissions<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ClaimCommissions<'info>>,
+    ) -> Result<()> {
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        let affiliate = &mut ctx.accounts.affiliate_account;
+        let amount = affiliate.claimable;
+        require!(amount > 0, ErrorCode::NothingToClaim);
+
+        let decimals = ctx.accounts.usdc_mint.decimals;
+        let seeds = &[
+            ESCROW_AUTHORITY_SEED,
+            pool_key.as_ref(),
+            pool.escrow_version_seed(),
+            &[pool.escrow_bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        let memo = claim_memo(pool, affiliate);
+        let transfers = payout_transfers(
+            affiliate,
+            ctx.accounts.affiliate_group.as_deref(),
+            &ctx.accounts.affiliate_usdc,
+            ctx.accounts.payout_usdc.as_ref(),
+            ctx.remaining_accounts,
+            amount,
+        )?;
+        for (to, share) in transfers {
+            transfer_memo(&ctx.accounts.memo_program, &memo)?;
+            token_interface::transfer_checked(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    TransferChecked {
+                        from: ctx.accounts.escrow_usdc.to_account_info(),
+                        mint: ctx.accounts.usdc_mint.to_account_info(),
+                        to,
+                        authority: ctx.accounts.escrow_authority.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                share,
+                decimals,
+            )?;
+        }
+
+        affiliate.claimable = 0;
+        affiliate.claimable_since = 0;
+        pool.total_claimable = pool
+            .total_claimable
+            .checked_sub(amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        emit!(CommissionsClaimed {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            affiliate: affiliate.key(),
+            wallet: affiliate.wallet,
+            amount,
+            claimed_by: ctx.accounts.authority.key(),
+            relayer_fee: 0,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Claim on the affiliate's behalf. The affiliate co-signs the transaction
+    /// as its permit, while the relayer pays the network fees and any rent and
+    /// keeps `relayer_fee` (capped at `MAX_RELAYER_FEE_BPS` of the claim) out
+    /// of the payout. Remaining accounts are the affiliate's payout split
+    /// token accounts, which share the payout net of the fee
+    pub fn claim_commissions_relayed<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ClaimCommissionsRelayed<'info>>,
+        relayer_fee: u64,
+    ) -> Result<()> {
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        let affiliate = &mut ctx.accounts.affiliate_account;
+        let amount = affiliate.claimable;
+        require!(amount > 0, ErrorCode::NothingToClaim);
+        require!(
+            relayer_fee <= calculate_commission(amount, MAX_RELAYER_FEE_BPS)?,
+            ErrorCode::RelayerFeeTooHigh
+        );
+        let net_amount = amount
+            .checked_sub(relayer_fee)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        let decimals = ctx.accounts.usdc_mint.decimals;
+        let seeds = &[
+            ESCROW_AUTHORITY_SEED,
+            pool_key.as_ref(),
+            pool.escrow_version_seed(),
+            &[pool.escrow_bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+        let memo = claim_memo(pool, affiliate);
+
+        let transfers = payout_transfers(
+            affiliate,
+            ctx.accounts.affiliate_group.as_deref(),
+            &ctx.accounts.affiliate_usdc,
+            ctx.accounts.payout_usdc.as_ref(),
+            ctx.remaining_accounts,
+            net_amount,
+        )?;
+        for (to, share) in transfers {
+            transfer_memo(&ctx.accounts.memo_program, &memo)?;
+            token_interface::transfer_checked(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    TransferChecked {
+                        from: ctx.accounts.escrow_usdc.to_account_info(),
+                        mint: ctx.accounts.usdc_mint.to_account_info(),
+                        to,
+                        authority: ctx.accounts.escrow_authority.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                share,
+                decimals,
+            )?;
+        }
+
+        if relayer_fee > 0 {
+            transfer_memo(&ctx.accounts.memo_program, &memo)?;
+            token_interface::transfer_checked(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    TransferChecked {
+                        from: ctx.accounts.escrow_usdc.to_account_info(),
+                        mint: ctx.accounts.usdc_mint.to_account_info(),
+                        to: ctx.accounts.relayer_usdc.to_account_info(),
+                        authority: ctx.accounts.escrow_authority.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                relayer_fee,
+                decimals,
+            )?;
+        }
+
+        affiliate.claimable = 0;
+        affiliate.claimable_since = 0;
+        pool.total_claimable = pool
+            .total_claimable
+            .checked_sub(amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        emit!(CommissionsClaimed {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            affiliate: affiliate.key(),
+            wallet: affiliate.wallet,
+            amount,
+            claimed_by: ctx.accounts.relayer.key(),
+            relayer_fee,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Pay out what up to `MAX_COMPRESSED_PAYOUTS` affiliates opted into
+    /// compressed payouts have accrued, compressing it out of escrow into
+    /// compressed token accounts owned by their wallets in one transfer.
+    /// Remaining accounts are the affiliate accounts. Signed by the pool's
+    /// processor, which pays the fees
+    pub fn pay_out_compressed<'info>(
+        ctx: Context<'_, '_, 'info, 'info, PayOutCompressed<'info>>,
+    ) -> Result<()> {
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        let now = Clock::get()?.unix_timestamp;
+
+        let error = ErrorCode::InvalidCompressedPayoutAccounts;
+        let groups = remaining::groups::<1>(ctx.remaining_accounts, None, error)?;
+        require!(
+            !groups.is_empty() && groups.len() <= MAX_COMPRESSED_PAYOUTS,
+            ErrorCode::InvalidCompressedPayoutAccounts
+        );
+
+        let mut affiliates: Vec<Account<AffiliateAccount>> = Vec::with_capacity(groups.len());
+        let mut outputs = Vec::with_capacity(groups.len());
+        for [affiliate_info] in groups {
+            let affiliate = remaining::affiliate_of(affiliate_info, &pool_key, error)?;
+            require!(
+                !affiliates
+                    .iter()
+                    .any(|other| other.key() == affiliate.key()),
+                ErrorCode::InvalidCompressedPayoutAccounts
+            );
+            require!(
+                affiliate.compressed_payouts,
+                ErrorCode::CompressedPayoutsDisabled
+            );
+            require!(affiliate.claimable > 0, ErrorCode::NothingToClaim);
+            outputs.push((affiliate.wallet, affiliate.claimable));
+            affiliates.push(affiliate);
+        }
+
+        let seeds = &[
+            ESCROW_AUTHORITY_SEED,
+            pool_key.as_ref(),
+            pool.escrow_version_seed(),
+            &[pool.escrow_bump],
+        ];
+        let accounts = &ctx.accounts;
+        compressed_token::CompressCpi {
+            compressed_token_program: &accounts.compressed_token_program,
+            fee_payer: &accounts.authority,
+            authority: &accounts.escrow_authority,
+            cpi_authority: &accounts.light_cpi_authority,
+            light_system_program: &accounts.light_system_program,
+            registered_program: &accounts.registered_program,
+            noop_program: &accounts.noop_program,
+            account_compression_authority: &accounts.account_compression_authority,
+            account_compression_program: &accounts.account_compression_program,
+            token_pool: &accounts.token_pool,
+            source: &accounts.escrow_usdc.to_account_info(),
+            token_program: &accounts.token_program,
+            system_program: &accounts.system_program,
+            output_state_tree: &accounts.output_state_tree,
+            signer_seeds: &[&seeds[..]],
+        }
+        .compress(&pool.usdc_mint, &outputs)?;
+
+        for mut affiliate in affiliates {
+            let amount = affiliate.claimable;
+            affiliate.claimable = 0;
+            affiliate.claimable_since = 0;
+            affiliate.last_payout_at = now;
+            affiliate.exit(&crate::ID)?;
+            pool.total_claimable = pool
+                .total_claimable
+                .checked_sub(amount)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+            emit!(CompressedCommissionsPaid {
+                pool: pool_key,
+                pool_id: pool.pool_id().to_string(),
+                affiliate: affiliate.key(),
+                wallet: affiliate.wallet,
+                amount,
+                output_state_tree: accounts.output_state_tree.key(),
+                timestamp: now,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Pay what an affiliate opted into confidential payouts has accrued
+    /// into its token account's encrypted balance. The amount is as public
+    /// as any other commission, but the account's balance stays encrypted.
+    /// The escrow's AE-encrypted balances after the deposit is applied and
+    /// after the transfer, and the transfer's proof context accounts, are
+    /// computed by the processor, which holds the escrow's keys and signs
+    pub fn pay_out_confidential(
+        ctx: Context<PayOutConfidential>,
+        new_decryptable_available_balance: [u8; 36],
+        new_source_decryptable_available_balance: [u8; 36],
+    ) -> Result<()> {
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        let affiliate = &mut ctx.accounts.affiliate_account;
+        require!(
+            pool.confidential_payouts() && affiliate.elgamal_pubkey.is_some(),
+            ErrorCode::ConfidentialPayoutsDisabled
+        );
+        let amount = affiliate.claimable;
+        require!(amount > 0, ErrorCode::NothingToClaim);
+        // Token-2022 checks the proofs encrypt to the destination's key;
+        // this checks that key is the one the affiliate opted in with
+        require!(
+            confidential::elgamal_pubkey(&ctx.accounts.affiliate_usdc.to_account_info())?
+                == affiliate.elgamal_pubkey,
+            ErrorCode::ConfidentialDestinationMismatch
+        );
+
+        let seeds = &[
+            ESCROW_AUTHORITY_SEED,
+            pool_key.as_ref(),
+            pool.escrow_version_seed(),
+            &[pool.escrow_bump],
+        ];
+        confidential::ConfidentialCpi {
+            token_program: &ctx.accounts.token_program.to_account_info(),
+            escrow: &ctx.accounts.escrow_usdc.to_account_info(),
+            mint: &ctx.accounts.usdc_mint.to_account_info(),
+            authority: &ctx.accounts.escrow_authority.to_account_info(),
+            signer_seeds: &[&seeds[..]],
+        }
+        .pay(
+            &ctx.accounts.affiliate_usdc.to_account_info(),
+            &ctx.accounts.equality_proof,
+            &ctx.accounts.ciphertext_validity_proof,
+            &ctx.accounts.range_proof,
+            amount,
+            ctx.accounts.usdc_mint.decimals,
+            new_decryptable_available_balance,
+            new_source_decryptable_available_balance,
+        )?;
+
+        let now = Clock::get()?.unix_timestamp;
+        affiliate.claimable = 0;
+        affiliate.claimable_since = 0;
+        affiliate.last_payout_at = now;
+        pool.total_claimable = pool
+            .total_claimable
+            .checked_sub(amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        emit!(ConfidentialCommissionsPaid {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            affiliate: affiliate.key(),
+            wallet: affiliate.wallet,
+            destination: ctx.accounts.affiliate_usdc.key(),
+            amount,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Release commissions left unclaimed past the pool's claim window back
+    /// to the merchant's spendable escrow. Permissionless crank.
+    pub fn sweep_expired(ctx: Context<SweepExpired>) -> Result<()> {
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        let affiliate = &mut ctx.accounts.affiliate_account;
+        let amount = affiliate.claimable;
+        require!(amount > 0, ErrorCode::NothingToClaim);
+        require!(pool.claim_window_secs > 0, ErrorCode::ClaimWindowNotElapsed);
+
+        let now = Clock::get()?.unix_timestamp;
+        let expires_at = affiliate
+            .claimable_since
+            .checked_add(pool.claim_window_secs)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        require!(now >= expires_at, ErrorCode::ClaimWindowNotElapsed);
+
+        affiliate.claimable = 0;
+        affiliate.claimable_since = 0;
+        affiliate.total_earned = affiliate
+            .total_earned
+            .checked_sub(amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        pool.total_claimable = pool
+            .total_claimable
+            .checked_sub(amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        pool.total_commissions_paid = pool
+            .total_commissions_paid
+            .checked_sub(amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        pool.period_commissions_paid = pool.period_commissions_paid.saturating_sub(amount);
+        let mint = pool.usdc_mint;
+        pool.unrecord_mint_sale(&mint, 0, amount);
+
+        emit!(UnclaimedSwept {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            affiliate: affiliate.key(),
+            wallet: affiliate.wallet,
+            amount,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Deactivate an affiliate with no sales for the pool's inactivity
+    /// period. Permissionless crank; accrued commissions stay claimable.
+    pub fn deactivate_stale_affiliate(ctx: Context<DeactivateStaleAffiliate>) -> Result<()> {
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &ctx.accounts.merchant_pool.load()?;
+        require!(
+            pool.inactivity_period_secs > 0,
+            ErrorCode::AffiliateNotStale
+        );
+
+        let affiliate = &mut ctx.accounts.affiliate_account;
+        require!(affiliate.is_active, ErrorCode::AffiliateInactive);
+
+        let now = Clock::get()?.unix_timestamp;
+        let last_active_at = affiliate.last_sale_at.max(affiliate.created_at);
+        let stale_at = last_active_at
+            .checked_add(pool.inactivity_period_secs)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        require!(now >= stale_at, ErrorCode::AffiliateNotStale);
+
+        affiliate.is_active = false;
+
+        emit!(StaleAffiliateDeactivated {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            affiliate: affiliate.key(),
+            wallet: affiliate.wallet,
+            last_active_at,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Remove (deactivate) an affiliate. Accrued commissions must be
+    /// claimed first so they can't be stranded.
+    pub fn remove_affiliate(ctx: Context<RemoveAffiliate>) -> Result<()> {
+        let affiliate = &mut ctx.accounts.affiliate_account;
+        require!(affiliate.claimable == 0, ErrorCode::PendingCommissions);
+        affiliate.is_active = false;
+
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &ctx.accounts.merchant_pool.load()?;
+
+        emit!(AffiliateRemoved {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            affiliate: affiliate.key(),
+            wallet: affiliate.wallet,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Replace an affiliate's merchant-defined tag bits
+    pub fn set_affiliate_tags(ctx: Context<SetAffiliateTags>, tags: u32) -> Result<()> {
+        let affiliate = &mut ctx.accounts.affiliate_account;
+        affiliate.tags = tags;
+
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &ctx.accounts.merchant_pool.load()?;
+
+        emit!(AffiliateTagsSet {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            affiliate: affiliate.key(),
+            wallet: affiliate.wallet,
+            tags,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Leave the pool as the affiliate wallet, deactivating the account.
+    /// With `close`, the affiliate and ref id accounts are closed too, once
+    /// nothing is left to claim or held back; their rent goes back to the
+    /// merchant, who paid it
+    pub fn leave_pool(ctx: Context<LeavePool>, close: bool) -> Result<()> {
+        let affiliate = &mut ctx.accounts.affiliate_account;
+        require!(
+            !close || (affiliate.claimable == 0 && affiliate.total_held == 0),
+            ErrorCode::PendingCommissions
+        );
+        affiliate.is_active = false;
+
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &ctx.accounts.merchant_pool.load()?;
+
+        emit!(AffiliateLeft {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            affiliate: affiliate.key(),
+            wallet: affiliate.wallet,
+            closed: close,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        if close {
+            let merchant = ctx.accounts.merchant.to_account_info();
+            ctx.accounts.ref_id_index.close(merchant.clone())?;
+            ctx.accounts.affiliate_account.close(merchant)?;
+        }
+
+        Ok(())
+    }
+
+    /// Deposit additional USDC to escrow
+    pub fn deposit_escrow(ctx: Context<DepositEscrow>, amount: u64) -> Result<()> {
+        require!(amount > 0, ErrorCode::InvalidAmount);
+
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        require!(pool.is_active(), ErrorCode::PoolInactive);
+        pool.record_activity(Clock::get()?.unix_timestamp);
+
+        let decimals = ctx.accounts.usdc_mint.decimals;
+        token_interface::transfer_checked(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.merchant_usdc.to_account_info(),
+                    mint: ctx.accounts.usdc_mint.to_account_info(),
+                    to: ctx.accounts.escrow_usdc.to_account_info(),
+                    authority: ctx.accounts.merchant.to_account_info(),
+                },
+            ),
+            amount,
+            decimals,
+        )?;
+
+        emit!(EscrowDeposited {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            depositor: ctx.accounts.merchant.key(),
+            source: ctx.accounts.merchant_usdc.key(),
+            amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Top up escrow from the merchant's token account through an SPL delegate
+    /// approval, so a treasury bot can deposit without the merchant signing
+    pub fn deposit_escrow_delegated(
+        ctx: Context<DepositEscrowDelegated>,
+        amount: u64,
+    ) -> Result<()> {
+        require!(amount > 0, ErrorCode::InvalidAmount);
+
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &ctx.accounts.merchant_pool.load()?;
+        require!(pool.is_active(), ErrorCode::PoolInactive);
+
+        let decimals = ctx.accounts.usdc_mint.decimals;
+        token_interface::transfer_checked(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.merchant_usdc.to_account_info(),
+                    mint: ctx.accounts.usdc_mint.to_account_info(),
+                    to: ctx.accounts.escrow_usdc.to_account_info(),
+                    authority: ctx.accounts.delegate.to_account_info(),
+                },
+            ),
+            amount,
+            decimals,
+        )?;
+
+        emit!(EscrowDeposited {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            depositor: ctx.accounts.delegate.key(),
+            source: ctx.accounts.merchant_usdc.key(),
+            amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Fund a pool's escrow from any wallet, e.g. a sponsor backing a
+    /// campaign. Donations can only be withdrawn by the pool's merchant
+    pub fn donate_to_escrow(ctx: Context<DonateToEscrow>, amount: u64) -> Result<()> {
+        require!(amount > 0, ErrorCode::InvalidAmount);
+
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &ctx.accounts.merchant_pool.load()?;
+        require!(pool.is_active(), ErrorCode::PoolInactive);
+
+        let decimals = ctx.accounts.usdc_mint.decimals;
+        token_interface::transfer_checked(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.donor_usdc.to_account_info(),
+                    mint: ctx.accounts.usdc_mint.to_account_info(),
+                    to: ctx.accounts.escrow_usdc.to_account_info(),
+                    authority: ctx.accounts.donor.to_account_info(),
+                },
+            ),
+            amount,
+            decimals,
+        )?;
+
+        emit!(EscrowDeposited {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            depositor: ctx.accounts.donor.key(),
+            source: ctx.accounts.donor_usdc.key(),
+            amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Withdraw unused USDC from escrow. With `expiry_slot` or `valid_until`
+    /// set the withdrawal fails once that slot or time has passed
+    pub fn withdraw_escrow(
+        ctx: Context<WithdrawEscrow>,
+        amount: u64,
+        expiry_slot: Option<u64>,
+        valid_until: Option<i64>,
+    ) -> Result<()> {
+        require_unexpired(expiry_slot, valid_until)?;
+        require!(amount > 0, ErrorCode::InvalidAmount);
+
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        require!(!pool.is_flagged(), ErrorCode::PoolFlagged);
+
+        // Large withdrawals need the pool's second key as well
+        pool.require_cosigner(
+            amount,
+            ctx.accounts.cosigner.as_ref().map(|signer| signer.key()),
+        )?;
+        let now = Clock::get()?.unix_timestamp;
+        pool.record_withdrawal(amount, now)?;
+        pool.record_activity(now);
+
+        // Accrued claims stay in escrow
+        ctx.accounts.escrow_usdc.reload()?;
+        require!(
+            ctx.accounts.escrow_usdc.amount
+                >= pool
+                    .reserved_for(&ctx.accounts.usdc_mint.key())
+                    .checked_add(amount)
+                    .ok_or(ErrorCode::ArithmeticOverflow)?,
+            ErrorCode::InsufficientEscrowBalance
+        );
+
+        let decimals = ctx.accounts.usdc_mint.decimals;
+        let seeds = &[
+            ESCROW_AUTHORITY_SEED,
+            pool_key.as_ref(),
+            pool.escrow_version_seed(),
+            &[pool.escrow_bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        transfer_memo(&ctx.accounts.memo_program, pool.pool_id())?;
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.escrow_usdc.to_account_info(),
+                    mint: ctx.accounts.usdc_mint.to_account_info(),
+                    to: ctx.accounts.merchant_usdc.to_account_info(),
+                    authority: ctx.accounts.escrow_authority.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            amount,
+            decimals,
+        )?;
+
+        emit!(EscrowWithdrawn {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Move unreserved escrow between two pools of the same merchant without
+    /// routing it through the merchant's wallet. It counts as a withdrawal
+    /// from the source pool for its co-signer and daily limit. With
+    /// `expiry_slot` set the transfer fails once that slot has passed
+    pub fn transfer_escrow(
+        ctx: Context<TransferEscrow>,
+        amount: u64,
+        expiry_slot: Option<u64>,
+    ) -> Result<()> {
+        require_unexpired(expiry_slot, None)?;
+        require!(amount > 0, ErrorCode::InvalidAmount);
+
+        let from_key = ctx.accounts.from_pool.key();
+        let to_key = ctx.accounts.to_pool.key();
+        require!(from_key != to_key, ErrorCode::SameEscrowTransfer);
+        let from_pool = &mut ctx.accounts.from_pool.load_mut()?;
+        let to_pool = &ctx.accounts.to_pool.load()?;
+        require!(!from_pool.is_flagged(), ErrorCode::PoolFlagged);
+        require!(to_pool.is_active(), ErrorCode::PoolInactive);
+
+        // Escrow locked to a destination may only move to a pool locked to
+        // the same one
+        if let Some(destination) = from_pool.withdrawal_destination() {
+            require!(
+                to_pool.withdrawal_destination() == Some(destination),
+                ErrorCode::WithdrawalDestinationMismatch
+            );
+        }
+        from_pool.require_cosigner(
+            amount,
+            ctx.accounts.cosigner.as_ref().map(|signer| signer.key()),
+        )?;
+        let now = Clock::get()?.unix_timestamp;
+        from_pool.record_withdrawal(amount, now)?;
+        from_pool.record_activity(now);
+
+        // Accrued claims stay in the source escrow
+        let mint = ctx.accounts.mint.key();
+        ctx.accounts.from_escrow.reload()?;
+        require!(
+            ctx.accounts.from_escrow.amount
+                >= from_pool
+                    .reserved_for(&mint)
+                    .checked_add(amount)
+                    .ok_or(ErrorCode::ArithmeticOverflow)?,
+            ErrorCode::InsufficientEscrowBalance
+        );
+
+        let decimals = ctx.accounts.mint.decimals;
+        let seeds = &[
+            ESCROW_AUTHORITY_SEED,
+            from_key.as_ref(),
+            from_pool.escrow_version_seed(),
+            &[from_pool.escrow_bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.from_escrow.to_account_info(),
+                    mint: ctx.accounts.mint.to_account_info(),
+                    to: ctx.accounts.to_escrow.to_account_info(),
+                    authority: ctx.accounts.from_escrow_authority.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            amount,
+            decimals,
+        )?;
+
+        emit!(EscrowTransferred {
+            pool: from_key,
+            pool_id: from_pool.pool_id().to_string(),
+            to_pool: to_key,
+            to_pool_id: to_pool.pool_id().to_string(),
+            mint,
+            amount,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Move all of the pool's escrow to a fresh authority PDA derived under
+    /// escrow seed `new_version`, above the pool's current one, so a seed
+    /// scheme can change without stranding funds. Every settlement mint
+    /// moves along as a remaining (mint, escrow, new escrow ATA) group; new
+    /// escrows are created as needed. An escrowless pool's revenue account
+    /// must approve the new authority afterwards
+    pub fn migrate_escrow_authority<'info>(
+        ctx: Context<'_, '_, 'info, 'info, MigrateEscrowAuthority<'info>>,
+        new_version: u8,
+    ) -> Result<()> {
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        require!(!pool.is_flagged(), ErrorCode::PoolFlagged);
+        require!(
+            new_version > pool.escrow_version,
+            ErrorCode::InvalidEscrowVersion
+        );
+        let groups = remaining::groups::<3>(
+            ctx.remaining_accounts,
+            Some(pool.settlement_mints().len()),
+            ErrorCode::InvalidEscrowMigrationAccounts,
+        )?;
+
+        let seeds = &[
+            ESCROW_AUTHORITY_SEED,
+            pool_key.as_ref(),
+            pool.escrow_version_seed(),
+            &[pool.escrow_bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+        let escrow_authority = ctx.accounts.escrow_authority.to_account_info();
+        let new_escrow_authority = ctx.accounts.new_escrow_authority.to_account_info();
+        let token_program = &ctx.accounts.token_program;
+
+        migrate_escrow(
+            token_program,
+            &escrow_authority,
+            &ctx.accounts.usdc_mint,
+            &ctx.accounts.escrow_usdc,
+            &mut ctx.accounts.new_escrow_usdc,
+            pool.reserved_for(&pool.usdc_mint),
+            signer_seeds,
+        )?;
+
+        for (&mint, [mint_info, escrow_info, new_escrow_info]) in
+            pool.settlement_mints().iter().zip(groups)
+        {
+            let mint_account = InterfaceAccount::<Mint>::try_from(mint_info)?;
+            let escrow = InterfaceAccount::<TokenAccount>::try_from(escrow_info)?;
+            require!(
+                mint_account.key() == mint
+                    && escrow.owner == escrow_authority.key()
+                    && escrow.mint == mint,
+                ErrorCode::InvalidEscrowMigrationAccounts
+            );
+            associated_token::create_idempotent(CpiContext::new(
+                ctx.accounts.associated_token_program.to_account_info(),
+                associated_token::Create {
+                    payer: ctx.accounts.rent_payer.to_account_info(),
+                    associated_token: new_escrow_info.clone(),
+                    authority: new_escrow_authority.clone(),
+                    mint: mint_info.clone(),
+                    system_program: ctx.accounts.system_program.to_account_info(),
+                    token_program: token_program.to_account_info(),
+                },
+            ))?;
+            let mut new_escrow = InterfaceAccount::<TokenAccount>::try_from(new_escrow_info)?;
+            migrate_escrow(
+                token_program,
+                &escrow_authority,
+                &mint_account,
+                &escrow,
+                &mut new_escrow,
+                pool.reserved_for(&mint),
+                signer_seeds,
+            )?;
+        }
+
+        pool.escrow_version = new_version;
+        pool.escrow_bump = ctx.bumps.new_escrow_authority;
+
+        emit!(EscrowAuthorityMigrated {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            escrow_authority: escrow_authority.key(),
+            new_escrow_authority: new_escrow_authority.key(),
+            escrow_version: new_version,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Register an additional settlement mint and create its escrow account
+    pub fn add_pool_mint(ctx: Context<AddPoolMint>) -> Result<()> {
+        let mint = ctx.accounts.mint.key();
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &mut ctx.accounts.merchant_pool.load_mut()?;
+        require!(!pool.accepts_mint(&mint), ErrorCode::MintAlreadyAdded);
+        let count = usize::from(pool.settlement_mint_count);
+        require!(
+            count < MAX_SETTLEMENT_MINTS,
+            ErrorCode::TooManySettlementMints
+        );
+
+        pool.settlement_mints[count] = mint;
+        pool.settlement_mint_count += 1;
+
+        emit!(PoolMintAdded {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            mint,
+            escrow: ctx.accounts.escrow_token.key(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Schedule recurring escrow top-ups of `amount` from `source` every
+    /// `interval_secs`. The merchant must `approve` the schedule PDA as the
+    /// delegate of `source`; the first top-up is due immediately
+    pub fn set_topup_schedule(
+        ctx: Context<SetTopUpSchedule>,
+        amount: u64,
+        interval_secs: i64,
+    ) -> Result<()> {
+        require!(amount > 0, ErrorCode::InvalidAmount);
+        require!(interval_secs > 0, ErrorCode::InvalidTopUpInterval);
+
+        let now = Clock::get()?.unix_timestamp;
+        let schedule = &mut ctx.accounts.topup_schedule;
+        schedule.pool = ctx.accounts.merchant_pool.key();
+        schedule.source = ctx.accounts.source.key();
+        schedule.mint = ctx.accounts.source.mint;
+        schedule.amount = amount;
+        schedule.interval_secs = interval_secs;
+        schedule.next_topup_at = now;
+        schedule.bump = ctx.bumps.topup_schedule;
+
+        emit!(TopUpScheduleUpdated {
+            pool: schedule.pool,
+            pool_id: ctx.accounts.merchant_pool.load()?.pool_id().to_string(),
+            source: schedule.source,
+            amount,
+            interval_secs,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Stop recurring top-ups and reclaim the schedule's rent
+    pub fn cancel_topup_schedule(ctx: Context<CancelTopUpSchedule>) -> Result<()> {
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &ctx.accounts.merchant_pool.load()?;
+
+        emit!(TopUpScheduleCancelled {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Permissionless crank moving the scheduled amount into escrow once due
+    pub fn execute_topup(ctx: Context<ExecuteTopUp>) -> Result<()> {
+        let pool_key = ctx.accounts.merchant_pool.key();
+        let pool = &ctx.accounts.merchant_pool.load()?;
+        require!(pool.is_active(), ErrorCode::PoolInactive);
+
+        let now = Clock::get()?.unix_timestamp;
+        let schedule = &ctx.accounts.topup_schedule;
+        require!(now >= schedule.next_topup_at, ErrorCode::TopUpNotDue);
+
+        let amount = schedule.amount;
+        let schedule_seeds = &[TOPUP_SEED, pool_key.as_ref(), &[schedule.bump]];
+        let signer = &[&schedule_seeds[..]];
+
+        let decimals = ctx.accounts.mint.decimals;
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.source.to_account_info(),
+                    mint: ctx.accounts.mint.to_account_info(),
+                    to: ctx.accounts.escrow_token.to_account_info(),
+                    authority: ctx.accounts.topup_schedule.to_account_info(),
+                },
+                signer,
+            ),
+            amount,
+            decimals,
+        )?;
+
+        // Late cranks don't accumulate missed top-ups
+        let next_topup_at = now
+            .checked_add(ctx.accounts.topup_schedule.interval_secs)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        ctx.accounts.topup_schedule.next_topup_at = next_topup_at;
+
+        emit!(TopUpExecuted {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            mint: ctx.accounts.mint.key(),
+            amount,
+            next_topup_at,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+}
+
+/// An affiliate's part of a `process_sale_multi` sale
+struct SplitShare<'info> {
+    affiliate: Account<'info, AffiliateAccount>,
+    affiliate_usdc: InterfaceAccount<'info, TokenAccount>,
+    weight_bps: u16,
+    /// The affiliate's weighted share of the sale amount
+    volume: u64,
+    /// Commission on `volume` at the affiliate's rate, before the pool's cap
+    uncapped: u64,
+}
+
+/// How a sale's commission divides up. Shared by `settle_sale` and
+/// `quote_commission`, so a quote is what the sale would settle
+struct CommissionBreakdown {
+    /// Recovered against the affiliate's debt in the sale's mint
+    debt_offset: u64,
+    /// Credited in kind by store-credit pools instead of a payout
+    store_credit: u64,
+    fee_tier: u8,
+    protocol_fee_bps: u16,
+    /// Owed to the protocol on top of the commission
+    protocol_fee: u64,
+    manager_cut: u64,
+    /// Kept in escrow until `release_matured_holdbacks`
+    holdback: u64,
+    /// The affiliate's share, after everything above
+    payout: u64,
+}
+
+impl CommissionBreakdown {
+    /// Break down `commission` on a `sale_amount` sale in `mint`; a
+    /// primary-mint sale is charged the fee tier of `pool`'s monthly
+    /// volume at `now`
+    fn new(
+        pool: &MerchantPool,
+        config: &ProgramConfig,
+        affiliate: &AffiliateAccount,
+        mint: &Pubkey,
+        sale_amount: u64,
+        commission: u64,
+        now: i64,
+    ) -> Result<Self> {
+        let primary = *mint == pool.usdc_mint;
+
+        // Outstanding clawbacks in the sale's mint are recovered before
+        // anything is paid out
+        let slot = pool.mint_slot(mint).ok_or(ErrorCode::MintMismatch)?;
+        let debt_offset = affiliate.debt(slot).min(commission);
+        let payout = commission - debt_offset;
+
+        // Store-credit pools reward primary-mint sales in kind: the payout
+        // is credited at the pool's conversion rate and never leaves escrow
+        let in_kind = pool.reward_mode() == RewardMode::StoreCredit && primary;
+        let store_credit = if in_kind {
+            calculate_commission(payout, pool.store_credit_rate_bps)?
+        } else {
+            0
+        };
+        let payout = if in_kind { 0 } else { payout };
+
+        // Primary-mint sales owe the protocol the fee of the tier the
+        // pool's monthly volume is in; it stays reserved in escrow for the
+        // treasury
+        let (fee_tier, protocol_fee_bps) = if primary {
+            pool.fee_tier_at(config, now)
+        } else {
+            (0, 0)
+        };
+        let protocol_fee = calculate_commission(sale_amount, protocol_fee_bps)?;
+
+        // The manager's share is paid out right away; the rest is the
+        // affiliate's
+        let manager_cut = match affiliate.manager {
+            Some(_) => calculate_commission(payout, affiliate.manager_share_bps)?,
+            None => 0,
+        };
+        let payout = payout - manager_cut;
+
+        // With a holdback period, the affiliate's share of a primary-mint
+        // sale stays in escrow until release_matured_holdbacks pays it out
+        let holdback = if pool.holdback_secs > 0 && primary {
+            payout
+        } else {
+            0
+        };
+
+        Ok(Self {
+            debt_offset,
+            store_credit,
+            fee_tier,
+            protocol_fee_bps,
+            protocol_fee,
+            manager_cut,
+            holdback,
+            payout: payout - holdback,
+        })
+    }
+}
+
+/// Sale details shared by `process_sale` and `process_sale_for_campaign`
+struct SaleParams {
+    sale_amount: u64,
+    order_id: String,
+    applied_rate_bps: u16,
+    memo: Option<String>,
+    metadata_hash: Option<[u8; 32]>,
+    idempotent: bool,
+    campaign: Option<Pubkey>,
+    /// The campaign's sales count including this one
+    campaign_redemptions: u64,
+    /// Whether the caller verified the buyer's payment
+    payment_verified: bool,
+    /// Read from the clock once by the caller
+    now: i64,
+}
+
+/// Pay or accrue a sale's commission, write its receipt and update stats.
+/// Returns the commission, or `None` when the order was already processed
+/// `split_accounts` are the affiliate's payout split token accounts, in order
+fn settle_sale<'info>(
+    accounts: &mut ProcessSale<'info>,
+    bumps: &ProcessSaleBumps,
+    split_accounts: &[AccountInfo<'info>],
+    sale: SaleParams,
+) -> Result<Option<u64>> {
+    let SaleParams {
+        sale_amount,
+        order_id,
+        applied_rate_bps,
+        memo,
+        metadata_hash,
+        idempotent,
+        campaign,
+        campaign_redemptions,
+        payment_verified,
+        now,
+    } = sale;
+
+    // A written receipt means this order_id was already settled; retries
+    // can opt into a successful no-op instead of an error
+    if accounts.sale_receipt.pool != Pubkey::default() {
+        require!(idempotent, ErrorCode::OrderAlreadyProcessed);
+        let receipt = &accounts.sale_receipt;
+        emit!(AlreadyProcessed {
+            pool: receipt.pool,
+            pool_id: accounts.merchant_pool.load()?.pool_id().to_string(),
+            affiliate: receipt.affiliate,
+            order_id,
+            timestamp: now,
+        });
+        return Ok(None);
+    }
+    let memo = memo.unwrap_or_default();
+    let metadata_hash = metadata_hash.unwrap_or_default();
+
+    let pool_key = accounts.merchant_pool.key();
+    let pool = &mut accounts.merchant_pool.load_mut()?;
+    require!(pool.is_active(), ErrorCode::PoolInactive);
+    require!(
+        payment_verified || !pool.requires_payment_proof(),
+        ErrorCode::PaymentProofRequired
+    );
+
+    if pool.guards_sales() {
+        let instructions = accounts
+            .instructions
+            .as_ref()
+            .ok_or(ErrorCode::InstructionsSysvarRequired)?;
+        let mut guarded = vec![accounts.escrow_usdc.key(), accounts.affiliate_usdc.key()];
+        guarded.extend(
+            accounts
+                .payout_usdc
+                .as_ref()
+                .map(|payout_usdc| payout_usdc.key()),
+        );
+        guarded.extend(
+            accounts
+                .affiliate_account
+                .payout_splits
+                .iter()
+                .map(|split| split.token_account),
+        );
+        require_no_token_instructions(instructions, &guarded)?;
+    }
+
+    let affiliate = &mut accounts.affiliate_account;
+    require!(affiliate.is_active, ErrorCode::AffiliateInactive);
+    require!(
+        affiliate.activated_by_affiliate,
+        ErrorCode::AffiliateNotActivated
+    );
+    require!(
+        !pool.requires_terms() || affiliate.accepted_terms_hash == pool.terms_hash,
+        ErrorCode::TermsNotAccepted
+    );
+
+    // With an attribution window the sale must come through a ref_id
+    // whose latest click is recent enough
+    if pool.attribution_window_secs > 0 {
+        let index = accounts
+            .ref_id_index
+            .as_ref()
+            .ok_or(ErrorCode::ClickNotRegistered)?;
+        require!(index.click_registered_at > 0, ErrorCode::ClickNotRegistered);
+        require!(
+            now.checked_sub(index.click_registered_at)
+                .ok_or(ErrorCode::ArithmeticOverflow)?
+                <= pool.attribution_window_secs,
+            ErrorCode::AttributionWindowExpired
+        );
+    }
+
+    let commission = pool.cap_commission(calculate_commission(sale_amount, applied_rate_bps)?)?;
+    require!(commission > 0, ErrorCode::CommissionTooSmall);
+
+    // Sales signed with a session key stay within its grant
+    if let Some(session_key) = accounts.session_key.as_mut() {
+        require!(now < session_key.expires_at, ErrorCode::SessionKeyExpired);
+        session_key.commission_spent = session_key
+            .commission_spent
+            .checked_add(commission)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        require!(
+            sale_amount <= session_key.scope.max_sale_amount
+                && session_key.commission_spent <= session_key.scope.commission_budget,
+            ErrorCode::SessionLimitExceeded
+        );
+    }
+
+    // Grouped affiliates roll their sales up into the group's stats
+    match (affiliate.group, accounts.affiliate_group.as_mut()) {
+        (None, _) => {}
+        (Some(_), Some(group)) => group.record_sale(sale_amount, commission)?,
+        (Some(_), None) => return err!(ErrorCode::AffiliateGroupRequired),
+    }
+
+    let mint = accounts.usdc_mint.key();
+    if mint == pool.usdc_mint {
+        pool.roll_monthly_volume(now);
+    }
+    let CommissionBreakdown {
+        debt_offset,
+        store_credit,
+        fee_tier,
+        protocol_fee_bps,
+        protocol_fee,
+        manager_cut,
+        holdback,
+        payout,
+    } = CommissionBreakdown::new(
+        pool,
+        &accounts.config,
+        affiliate,
+        &mint,
+        sale_amount,
+        commission,
+        now,
+    )?;
+    let escrowed = payout + manager_cut + holdback;
+    *affiliate.debt_mut(pool.mint_slot(&mint).ok_or(ErrorCode::MintMismatch)?) -= debt_offset;
+    affiliate.store_credit = affiliate
+        .store_credit
+        .checked_add(store_credit)
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+    // A referred affiliate's first sale in the primary mint earns its
+    // referrer the pool's bounty
+    let bounty = match affiliate.referred_by {
+        Some(_) if !affiliate.referral_bounty_paid && mint == pool.usdc_mint => {
+            pool.first_sale_bounty
+        }
+        _ => 0,
+    };
+
+    let decimals = accounts.usdc_mint.decimals;
+    let seeds = &[
+        ESCROW_AUTHORITY_SEED,
+        pool_key.as_ref(),
+        pool.escrow_version_seed(),
+        &[pool.escrow_bump],
+    ];
+    let signer_seeds = &[&seeds[..]];
+
+    // Escrowless pools pull what the sale pays out from the merchant's
+    // revenue account, where the escrow authority is an approved delegate
+    if let (Some(revenue_account), true) = (pool.revenue_account(), mint == pool.usdc_mint) {
+        let revenue_usdc = accounts
+            .revenue_usdc
+            .as_ref()
+            .ok_or(ErrorCode::RevenueAccountRequired)?;
+        let amount = escrowed
+            .checked_add(bounty)
+            .and_then(|amount| amount.checked_add(protocol_fee))
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        transfer_memo(&accounts.memo_program, &order_id)?;
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: revenue_usdc.to_account_info(),
+                    mint: accounts.usdc_mint.to_account_info(),
+                    to: accounts.escrow_usdc.to_account_info(),
+                    authority: accounts.escrow_authority.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            amount,
+            decimals,
+        )?;
+
+        emit!(EscrowDeposited {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            depositor: accounts.escrow_authority.key(),
+            source: revenue_account,
+            amount,
+            timestamp: now,
+        });
+
+        // The pull changed the balance read when the accounts were loaded
+        accounts.escrow_usdc.reload()?;
+    }
+
+    // Check escrow balance, leaving accrued claims untouched
+    require!(
+        accounts.escrow_usdc.amount
+            >= pool
+                .reserved_for(&mint)
+                .checked_add(escrowed)
+                .and_then(|required| required.checked_add(bounty))
+                .and_then(|required| required.checked_add(protocol_fee))
+                .ok_or(ErrorCode::ArithmeticOverflow)?,
+        ErrorCode::InsufficientEscrowBalance
+    );
+    pool.protocol_fees_accrued = pool
+        .protocol_fees_accrued
+        .checked_add(protocol_fee)
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+    pool.total_held = pool
+        .total_held
+        .checked_add(holdback)
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+    affiliate.total_held = affiliate
+        .total_held
+        .checked_add(holdback)
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+    // Compressed and confidential payouts go to the wallet, so they can't
+    // honour a group's payout account
+    let confidential = pool.confidential_payouts() && affiliate.elgamal_pubkey.is_some();
+    if mint == pool.usdc_mint
+        && payout_account_for(affiliate, accounts.affiliate_group.as_deref()).is_some()
+    {
+        require!(
+            !affiliate.compressed_payouts,
+            ErrorCode::CompressedPayoutsRouted
+        );
+        require!(!confidential, ErrorCode::ConfidentialPayoutsRouted);
+    }
+
+    // Push payouts in the primary mint wait for the affiliate's threshold,
+    // compressed ones for `pay_out_compressed` and confidential ones for
+    // `pay_out_confidential`
+    let held_back = mint == pool.usdc_mint
+        && (affiliate.compressed_payouts
+            || confidential
+            || (pool.payout_mode() == PayoutMode::Push && !affiliate.payout_due(payout, now)));
+
+    let mut paid_out = 0;
+    if pool.payout_mode() == PayoutMode::Claim || held_back {
+        // Accrue for a later payout or claim_commissions
+        require!(mint == pool.usdc_mint, ErrorCode::MintMismatch);
+        if affiliate.claimable == 0 {
+            affiliate.claimable_since = now;
+        }
+        affiliate.claimable = affiliate
+            .claimable
+            .checked_add(payout)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        pool.total_claimable = pool
+            .total_claimable
+            .checked_add(payout)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+    } else {
+        // Anything held back in this mint is released with the payout
+        let released = if mint == pool.usdc_mint {
+            affiliate.claimable
+        } else {
+            0
+        };
+        paid_out = payout
+            .checked_add(released)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        affiliate.claimable -= released;
+        if released > 0 {
+            affiliate.claimable_since = 0;
+        }
+        pool.total_claimable = pool
+            .total_claimable
+            .checked_sub(released)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+    }
+
+    if manager_cut > 0 {
+        let manager_usdc = accounts
+            .manager_usdc
+            .as_ref()
+            .ok_or(ErrorCode::ManagerAccountRequired)?;
+        transfer_memo(&accounts.memo_program, &order_id)?;
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: accounts.escrow_usdc.to_account_info(),
+                    mint: accounts.usdc_mint.to_account_info(),
+                    to: manager_usdc.to_account_info(),
+                    authority: accounts.escrow_authority.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            manager_cut,
+            decimals,
+        )?;
+        affiliate.manager_earned = affiliate
+            .manager_earned
+            .checked_add(manager_cut)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+    }
+
+    if bounty > 0 {
+        let referrer_usdc = accounts
+            .referrer_usdc
+            .as_ref()
+            .ok_or(ErrorCode::ReferrerAccountRequired)?;
+        transfer_memo(&accounts.memo_program, &order_id)?;
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: accounts.escrow_usdc.to_account_info(),
+                    mint: accounts.usdc_mint.to_account_info(),
+                    to: referrer_usdc.to_account_info(),
+                    authority: accounts.escrow_authority.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            bounty,
+            decimals,
+        )?;
+        affiliate.referral_bounty_paid = true;
+
+        emit!(ReferralBountyPaid {
+            pool: pool_key,
+            pool_id: pool.pool_id().to_string(),
+            affiliate: affiliate.key(),
+            referrer: referrer_usdc.owner,
+            amount: bounty,
+            timestamp: now,
+        });
+    }
+
+    if paid_out > 0 {
+        // Transfer commission to affiliate
+        affiliate.last_payout_at = now;
+        let transfers = if mint == pool.usdc_mint {
+            payout_transfers(
+                affiliate,
+                accounts.affiliate_group.as_deref(),
+                &accounts.affiliate_usdc,
+                accounts.payout_usdc.as_deref(),
+                split_accounts,
+                paid_out,
+            )?
+        } else {
+            vec![(accounts.affiliate_usdc.to_account_info(), paid_out)]
+        };
+        for (to, share) in transfers {
+            transfer_memo(&accounts.memo_program, &order_id)?;
+            token_interface::transfer_checked(
+                CpiContext::new_with_signer(
+                    accounts.token_program.to_account_info(),
+                    TransferChecked {
+                        from: accounts.escrow_usdc.to_account_info(),
+                        mint: accounts.usdc_mint.to_account_info(),
+                        to,
+                        authority: accounts.escrow_authority.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                share,
+                decimals,
+            )?;
+        }
+    }
+
+    let receipt = &mut accounts.sale_receipt;
+    receipt.pool = pool_key;
+    receipt.affiliate = affiliate.key();
+    receipt.order_id = order_id.clone();
+    receipt.memo = memo.clone();
+    receipt.metadata_hash = metadata_hash;
+    receipt.mint = mint;
+    receipt.sale_amount = sale_amount;
+    receipt.commission = commission;
+    receipt.applied_rate_bps = applied_rate_bps;
+    receipt.refunded_amount = 0;
+    receipt.clawed_back = 0;
+    receipt.held_amount = holdback;
+    receipt.releasable_at = if holdback > 0 {
+        now.checked_add(pool.holdback_secs)
+            .ok_or(ErrorCode::ArithmeticOverflow)?
+    } else {
+        0
+    };
+    receipt.bump = bumps.sale_receipt;
+    receipt.created_at = now;
+    receipt.split = false;
+
+    // Update statistics
+    affiliate.total_earned = affiliate
+        .total_earned
+        .checked_add(commission)
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+    affiliate.sales_count = affiliate
+        .sales_count
+        .checked_add(1)
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+    (affiliate.rate_decayed, affiliate.sale_streak_days) = pool.rate_decay(affiliate, now);
+    affiliate.last_sale_at = now;
+    affiliate.largest_sale = affiliate.largest_sale.max(sale_amount);
+    affiliate.total_sale_volume = affiliate
+        .total_sale_volume
+        .checked_add(sale_amount)
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+    affiliate.record_period_volume(pool.period_index, sale_amount)?;
+    affiliate.open_statement.record_sale(
+        &accounts.sale_receipt.key(),
+        sale_amount,
+        commission,
+        manager_cut,
+    )?;
+
+    // Sales attributed through a ref_id lookup count as redemptions of it
+    let ref_id_redemptions = match accounts.ref_id_index.as_mut() {
+        Some(index) => {
+            index.redemptions = index
+                .redemptions
+                .checked_add(1)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+            index.redemptions
+        }
+        None => 0,
+    };
+
+    match &accounts.leaderboard {
+        Some(leaderboard) => leaderboard
+            .load_mut()?
+            .record(affiliate.key(), affiliate.total_sale_volume),
+        None => require!(!pool.has_leaderboard(), ErrorCode::LeaderboardRequired),
+    }
+
+    pool.record_sale(sale_amount, commission, &mint)?;
+    pool.record_activity(now);
+
+    let escrow_remaining = accounts
+        .escrow_usdc
+        .amount
+        .checked_sub(paid_out)
+        .and_then(|remaining| remaining.checked_sub(manager_cut))
+        .and_then(|remaining| remaining.checked_sub(bounty))
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+    emit!(SaleProcessed {
+        pool: pool_key,
+        pool_id_hash: pool.pool_id_hash,
+        affiliate: affiliate.key(),
+        affiliate_wallet: affiliate.wallet,
+        order_id,
+        mint,
+        sale_amount,
+        commission,
+        applied_rate_bps,
+        protocol_fee,
+        fee_tier,
+        protocol_fee_bps,
+        escrow_remaining,
+        memo,
+        metadata_hash,
+        sales_count: affiliate.sales_count,
+        largest_sale: affiliate.largest_sale,
+        average_sale: affiliate.average_sale(),
+        last_sale_at: affiliate.last_sale_at,
+        paid_out,
+        manager_cut,
+        held: holdback,
+        store_credit,
+        campaign,
+        campaign_redemptions,
+        ref_id_index: accounts.ref_id_index.as_ref().map(|index| index.key()),
+        ref_id_redemptions,
+        timestamp: now,
+    });
+
+    Ok(Some(commission))
+}
+
+/// Fails once the current slot is past `expiry_slot` or the clock is past
+/// `valid_until`. Transactions signed against a durable nonce never expire
+/// on their own, so sensitive merchant instructions take an expiry to keep
+/// stale offline signatures unusable
+fn require_unexpired(expiry_slot: Option<u64>, valid_until: Option<i64>) -> Result<()> {
+    let clock = Clock::get()?;
+    if let Some(expiry_slot) = expiry_slot {
+        require!(clock.slot <= expiry_slot, ErrorCode::TransactionExpired);
+    }
+    if let Some(valid_until) = valid_until {
+        require!(
+            clock.unix_timestamp <= valid_until,
+            ErrorCode::TransactionExpired
+        );
+    }
+    Ok(())
+}
+
+/// Fails if any top-level instruction of the transaction calls a token
+/// program with one of `guarded` among its accounts. Token CPIs made by
+/// other programs aren't visible in the instructions sysvar
+fn require_no_token_instructions(instructions: &AccountInfo, guarded: &[Pubkey]) -> Result<()> {
+    let count = {
+        let data = instructions.try_borrow_data()?;
+        let (Some(&low), Some(&high)) = (data.first(), data.get(1)) else {
+            return Err(ProgramError::InvalidAccountData.into());
+        };
+        u16::from_le_bytes([low, high])
+    };
+    for index in 0..count {
+        let instruction = load_instruction_at_checked(index.into(), instructions)?;
+        let is_token_program = instruction.program_id == anchor_spl::token::ID
+            || instruction.program_id == anchor_spl::token_2022::ID;
+        require!(
+            !is_token_program
+                || !instruction
+                    .accounts
+                    .iter()
+                    .any(|meta| guarded.contains(&meta.pubkey)),
+            ErrorCode::UnexpectedTokenInstruction
+        );
+    }
+    Ok(())
+}
+
+/// Fails unless the instruction right before the current one is a token
+/// `TransferChecked` of `amount` of `mint` into `destination` carrying
+/// `reference` and `nonce_reference` among its trailing accounts, as Solana
+/// Pay tags payments. Returns the paying authority
+fn verify_payment(
+    instructions: &AccountInfo,
+    mint: &Pubkey,
+    destination: &Pubkey,
+    amount: u64,
+    reference: &Pubkey,
+    nonce_reference: &Pubkey,
+) -> Result<Pubkey> {
+    let current = load_current_index_checked(instructions)?;
+    require!(current > 0, ErrorCode::PaymentNotFound);
+    let payment = load_instruction_at_checked(usize::from(current - 1), instructions)?;
+    let is_token_program = payment.program_id == anchor_spl::token::ID
+        || payment.program_id == anchor_spl::token_2022::ID;
+    require!(is_token_program, ErrorCode::PaymentNotFound);
+    let Ok(TokenInstruction::TransferChecked { amount: paid, .. }) =
+        TokenInstruction::unpack(&payment.data)
+    else {
+        return err!(ErrorCode::PaymentNotFound);
+    };
+    // TransferChecked takes source, mint, destination and authority, then
+    // any multisig signers; Solana Pay appends references after those
+    let [_, payment_mint, payment_destination, authority, extra @ ..] = payment.accounts.as_slice()
+    else {
+        return err!(ErrorCode::PaymentNotFound);
+    };
+    require!(
+        paid == amount
+            && payment_mint.pubkey == *mint
+            && payment_destination.pubkey == *destination
+            && extra.iter().any(|meta| meta.pubkey == *reference),
+        ErrorCode::PaymentMismatch
+    );
+    require!(
+        extra.iter().any(|meta| meta.pubkey == *nonce_reference),
+        ErrorCode::PaymentNonceMismatch
+    );
+    Ok(authority.pubkey)
+}
+
+/// The rate a sale is paid at: the processor's negotiated override, or the
+/// affiliate's policy or pool rate after decay and its group's modifier
+fn sale_rate(
+    accounts: &mut ProcessSale,
+    commission_override_bps: Option<u16>,
+    now: i64,
+) -> Result<u16> {
+    // Only the processor or its session keys sign sales, so negotiated
+    // one-off rates just need to stay within bounds. The pool is only
+    // borrowed here so `settle_sale` can borrow it mutably
+    let pool = accounts.merchant_pool.load()?;
+    let rate = match commission_override_bps {
+        Some(rate) => {
+            require!(
+                rate >= pool.min_override_bps && rate <= pool.max_override_bps,
+                ErrorCode::CommissionOverrideOutOfBounds
+            );
+            rate
+        }
+        None => {
+            let affiliate = &mut accounts.affiliate_account;
+            let rate = pool.affiliate_rate(affiliate, accounts.affiliate_group.as_deref(), now);
+            // Sales at the pool rate, outside a grace window, move the
+            // snapshot to the current rate, which later cuts are measured
+            // against
+            if pool.policy_rate(affiliate).is_none()
+                && pool.rate_for(affiliate, now) == pool.commission_rate
+            {
+                affiliate.rate_snapshot_bps = pool.commission_rate;
+                affiliate.rate_snapshot_at = now;
+            }
+            rate
+        }
+    };
+    Ok(rate)
+}
+
+/// A group rate modifier within +/-100%
+fn validate_rate_modifier(rate_modifier_bps: i16) -> Result<()> {
+    require!(
+        (-10000..=10000).contains(&rate_modifier_bps),
+        ErrorCode::InvalidGroupRateModifier
+    );
+    Ok(())
+}
+
+/// Commission owed on `sale_amount` at `commission_rate` basis points
+fn calculate_commission(sale_amount: u64, commission_rate: u16) -> Result<u64> {
+    let commission = sale_amount
+        .checked_mul(commission_rate as u64)
+        .ok_or(ErrorCode::ArithmeticOverflow)?
+        .checked_div(10000)
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+    Ok(commission)
+}
+
+/// Token account an affiliate's primary-mint commissions go to: its
+/// group's payout account, else its own registered one, otherwise the
+/// wallet's ATA
+fn payout_target<'info>(
+    affiliate: &AffiliateAccount,
+    group: Option<&AffiliateGroup>,
+    affiliate_usdc: &InterfaceAccount<'info, TokenAccount>,
+    payout_usdc: Option<&InterfaceAccount<'info, TokenAccount>>,
+) -> Result<AccountInfo<'info>> {
+    require!(
+        affiliate.group.is_none() || group.is_some(),
+        ErrorCode::AffiliateGroupRequired
+    );
+    match (payout_account_for(affiliate, group), payout_usdc) {
+        (None, _) => Ok(affiliate_usdc.to_account_info()),
+        (Some(_), Some(payout_usdc)) => Ok(payout_usdc.to_account_info()),
+        (Some(_), None) => err!(ErrorCode::PayoutAccountRequired),
+    }
+}
+
+/// Token accounts a primary-mint payout of `amount` goes to, with each
+/// one's share: split across the affiliate's `payout_splits`, passed in
+/// order as `split_accounts`, unless its group routes its commissions;
+/// otherwise whole to `payout_target`
+fn payout_transfers<'info>(
+    affiliate: &AffiliateAccount,
+    group: Option<&AffiliateGroup>,
+    affiliate_usdc: &InterfaceAccount<'info, TokenAccount>,
+    payout_usdc: Option<&InterfaceAccount<'info, TokenAccount>>,
+    split_accounts: &[AccountInfo<'info>],
+    amount: u64,
+) -> Result<Vec<(AccountInfo<'info>, u64)>> {
+    let group_routed = group.is_some_and(|group| group.payout_account.is_some());
+    if affiliate.payout_splits.is_empty() || group_routed {
+        let to = payout_target(affiliate, group, affiliate_usdc, payout_usdc)?;
+        return Ok(vec![(to, amount)]);
+    }
+    require!(
+        split_accounts.len() >= affiliate.payout_splits.len(),
+        ErrorCode::PayoutSplitAccountsMismatch
+    );
+
+    let mut transfers = Vec::with_capacity(affiliate.payout_splits.len());
+    let mut left = amount;
+    for (index, (split, info)) in affiliate
+        .payout_splits
+        .iter()
+        .zip(split_accounts)
+        .enumerate()
+    {
+        require_keys_eq!(
+            info.key(),
+            split.token_account,
+            ErrorCode::PayoutSplitAccountsMismatch
+        );
+        // The last split takes the rounding remainder
+        let share = if index + 1 == affiliate.payout_splits.len() {
+            left
+        } else {
+            calculate_commission(amount, split.weight_bps)?
+        };
+        left -= share;
+        if share > 0 {
+            transfers.push((info.clone(), share));
+        }
+    }
+    Ok(transfers)
+}
+
+/// Payout account an affiliate's commissions are routed to, if any;
+/// `group` is the affiliate's group when it's in one
+fn payout_account_for(
+    affiliate: &AffiliateAccount,
+    group: Option<&AffiliateGroup>,
+) -> Option<Pubkey> {
+    group
+        .and_then(|group| group.payout_account)
+        .or(affiliate.payout_account)
+}
+
+/// Factor a Token-2022 interest-bearing `mint`'s UI amounts have grown to
+/// over its raw amounts by `now`, over `INTEREST_SCALE_ONE`, compounded
+/// continuously like the extension does; `None` for mints without the
+/// extension
+fn interest_scale(mint: &InterfaceAccount<Mint>, now: i64) -> Result<Option<u64>> {
+    let info = mint.to_account_info();
+    if *info.owner != anchor_spl::token_2022::ID {
+        return Ok(None);
+    }
+    let data = info.try_borrow_data()?;
+    let state = StateWithExtensions::<MintState>::unpack(&data)?;
+    let Ok(config) = state.get_extension::<InterestBearingConfig>() else {
+        return Ok(None);
+    };
+    let initialized_at = i64::from(config.initialization_timestamp);
+    let updated_at = i64::from(config.last_update_timestamp);
+    let growth = interest_growth(config.pre_update_average_rate, updated_at - initialized_at)?
+        .checked_mul(interest_growth(config.current_rate, now - updated_at)?)
+        .ok_or(ErrorCode::ArithmeticOverflow)?
+        / INTEREST_GROWTH_ONE;
+    let scale = growth / (INTEREST_GROWTH_ONE / u128::from(INTEREST_SCALE_ONE));
+    u64::try_from(scale)
+        .map(Some)
+        .map_err(|_| ErrorCode::ArithmeticOverflow.into())
+}
+
+/// `e^(rate / 10_000 * secs / SECS_PER_INTEREST_YEAR)` over
+/// `INTEREST_GROWTH_ONE`: the exponent is halved below one half, where the
+/// Taylor series converges quickly, and the sum squared back up
+fn interest_growth(rate: BasisPoints, secs: i64) -> Result<u128> {
+    let exponent = i128::from(i16::from(rate)) * i128::from(secs);
+    let mut x = exponent.unsigned_abs() * INTEREST_GROWTH_ONE / (10_000 * SECS_PER_INTEREST_YEAR);
+    let mut halvings = 0;
+    while x > INTEREST_GROWTH_ONE / 2 {
+        x /= 2;
+        halvings += 1;
+    }
+
+    let mut growth = INTEREST_GROWTH_ONE;
+    let mut term = INTEREST_GROWTH_ONE;
+    for n in 1.. {
+        term = term * x / INTEREST_GROWTH_ONE / n;
+        if term == 0 {
+            break;
+        }
+        growth += term;
+    }
+    for _ in 0..halvings {
+        growth = growth
+            .checked_mul(growth)
+            .ok_or(ErrorCode::ArithmeticOverflow)?
+            / INTEREST_GROWTH_ONE;
+    }
+
+    if exponent < 0 {
+        growth = INTEREST_GROWTH_ONE * INTEREST_GROWTH_ONE / growth;
+    }
+    Ok(growth)
+}
+
+/// Raw amount of `mint` worth `amount` at `now`. Sale amounts are what the
+/// customer paid, so for interest-bearing mints they're in UI terms, while
+/// commissions, reserves and stats are kept in raw amounts like the escrow
+/// balance they're paid from. Other mints' amounts pass through unchanged
+fn raw_amount(mint: &InterfaceAccount<Mint>, amount: u64, now: i64) -> Result<u64> {
+    match interest_scale(mint, now)? {
+        Some(scale) => prorate(amount, INTEREST_SCALE_ONE, scale),
+        None => Ok(amount),
+    }
+}
+
+/// Log `memo` through the SPL Memo program. Every escrow transfer of a sale,
+/// withdrawal or claim comes right after one, so exchanges and custodians
+/// can credit it and Token-2022 accounts requiring memos on incoming
+/// transfers accept it
+fn transfer_memo(memo_program: &Program<Memo>, memo: &str) -> Result<()> {
+    memo::build_memo(
+        CpiContext::new(memo_program.to_account_info(), BuildMemo {}),
+        memo.as_bytes(),
+    )
+}
+
+/// Memo of a claim's transfers, referencing the pool and affiliate the
+/// commissions were earned in
+fn claim_memo(pool: &MerchantPool, affiliate: &AffiliateAccount) -> String {
+    format!("{}/{}", pool.pool_id(), affiliate.ref_id)
+}
+
+/// Move the whole balance of `escrow` to `new_escrow`, which must then
+/// still cover the `reserved` claims on it
+fn migrate_escrow<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    escrow_authority: &AccountInfo<'info>,
+    mint: &InterfaceAccount<'info, Mint>,
+    escrow: &InterfaceAccount<'info, TokenAccount>,
+    new_escrow: &mut InterfaceAccount<'info, TokenAccount>,
+    reserved: u64,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    if escrow.amount > 0 {
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                token_program.to_account_info(),
+                TransferChecked {
+                    from: escrow.to_account_info(),
+                    mint: mint.to_account_info(),
+                    to: new_escrow.to_account_info(),
+                    authority: escrow_authority.clone(),
+                },
+                signer_seeds,
+            ),
+            escrow.amount,
+            mint.decimals,
+        )?;
+    }
+    new_escrow.reload()?;
+    require!(
+        new_escrow.amount >= reserved,
+        ErrorCode::InsufficientEscrowBalance
+    );
+    Ok(())
+}
+
+/// `amount * numerator / denominator`, rounded down
+fn prorate(amount: u64, numerator: u64, denominator: u64) -> Result<u64> {
+    let prorated = (amount as u128)
+        .checked_mul(numerator as u128)
+        .ok_or(ErrorCode::ArithmeticOverflow)?
+        .checked_div(denominator as u128)
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+    u64::try_from(prorated).map_err(|_| ErrorCode::ArithmeticOverflow.into())
+}
+
+/// Program-wide settings, held in a single PDA
+#[account]
+#[derive(InitSpace)]
+pub struct ProgramConfig {
+    pub admin: Pubkey,
+    /// Blocks everything but escrow withdrawals and commission claims
+    pub paused: bool,
+    /// Protocol fee schedule by pool volume, ascending; empty charges no fee
+    #[max_len(MAX_FEE_TIERS)]
+    pub fee_tiers: Vec<FeeTier>,
+    /// Wallet whose token accounts collected protocol fees go to
+    pub treasury: Pubkey,
+    /// Range pool commission rates must fall in when set
+    pub min_commission_rate_bps: u16,
+    pub max_commission_rate_bps: u16,
+    pub bump: u8,
+}
+
+impl ProgramConfig {
+    pub fn allows_commission_rate(&self, rate_bps: u16) -> bool {
+        (self.min_commission_rate_bps..=self.max_commission_rate_bps).contains(&rate_bps)
+    }
+
+    /// Index and rate of the tier a pool with `monthly_volume` pays;
+    /// `None` while there is no fee schedule
+    pub fn fee_tier_for(&self, monthly_volume: u64) -> Option<(u8, u16)> {
+        self.fee_tiers
+            .iter()
+            .rposition(|tier| monthly_volume >= tier.min_monthly_volume)
+            .map(|index| (index as u8, self.fee_tiers[index].fee_bps))
+    }
+}
+
+/// Share of an affiliate's primary-mint payouts sent to `token_account`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub struct PayoutSplit {
+    pub token_account: Pubkey,
+    pub weight_bps: u16,
+}
+
+/// Protocol fee charged on sales once a pool's primary-mint volume over the
+/// last `FEE_VOLUME_WINDOW_SECS` reaches `min_monthly_volume`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub struct FeeTier {
+    pub min_monthly_volume: u64,
+    pub fee_bps: u16,
+}
+
+/// Timelock on the admin's sensitive config changes, held in a single PDA.
+/// Integrators watch its queued change and compare the deployed program
+/// against `program_hash`
+#[account]
+#[derive(InitSpace)]
+pub struct UpgradeGuard {
+    /// SHA-256 of the program build the admin intends to run
+    pub program_hash: [u8; 32],
+    pub timelock_secs: i64,
+    /// Change announced by `queue_config_change`
+    pub pending_change: Option<ConfigChange>,
+    /// When `pending_change` may execute
+    pub executable_at: i64,
+    pub bump: u8,
+}
+
+/// An admin config change; queued behind the `UpgradeGuard` timelock once
+/// the program has one
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq, InitSpace)]
+pub enum ConfigChange {
+    FeeTiers {
+        #[max_len(MAX_FEE_TIERS)]
+        fee_tiers: Vec<FeeTier>,
+    },
+    CommissionRateBand {
+        min_commission_rate_bps: u16,
+        max_commission_rate_bps: u16,
+    },
+    Treasury {
+        treasury: Pubkey,
+    },
+    /// Record the build the admin intends to deploy next
+    ProgramHash {
+        program_hash: [u8; 32],
+    },
+    Timelock {
+        timelock_secs: i64,
+    },
+}
+
+impl ConfigChange {
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            ConfigChange::FeeTiers { fee_tiers } => require!(
+                fee_tiers.len() <= MAX_FEE_TIERS
+                    && fee_tiers
+                        .first()
+                        .is_none_or(|tier| tier.min_monthly_volume == 0)
+                    && fee_tiers
+                        .windows(2)
+                        .all(|pair| pair[0].min_monthly_volume < pair[1].min_monthly_volume)
+                    && fee_tiers
+                        .iter()
+                        .all(|tier| tier.fee_bps <= MAX_PROTOCOL_FEE_BPS),
+                ErrorCode::InvalidFeeTiers
+            ),
+            ConfigChange::CommissionRateBand {
+                min_commission_rate_bps,
+                max_commission_rate_bps,
+            } => require!(
+                min_commission_rate_bps <= max_commission_rate_bps
+                    && *max_commission_rate_bps <= 10000,
+                ErrorCode::InvalidCommissionRateBand
+            ),
+            ConfigChange::Timelock { timelock_secs } => require!(
+                (1..=MAX_CONFIG_TIMELOCK_SECS).contains(timelock_secs),
+                ErrorCode::InvalidTimelock
+            ),
+            ConfigChange::Treasury { .. } | ConfigChange::ProgramHash { .. } => {}
+        }
+        Ok(())
+    }
+
+    /// Apply the change to `config`, or to `guard` for the guard's own
+    /// settings, and emit the updated setting's event
+    pub fn apply(self, config: &mut ProgramConfig, guard: Option<&mut UpgradeGuard>) -> Result<()> {
+        let admin = config.admin;
+        let timestamp = Clock::get()?.unix_timestamp;
+        match self {
+            ConfigChange::FeeTiers { fee_tiers } => {
+                config.fee_tiers = fee_tiers.clone();
+                emit!(FeeTiersUpdated {
+                    fee_tiers,
+                    admin,
+                    timestamp,
+                });
+            }
+            ConfigChange::CommissionRateBand {
+                min_commission_rate_bps,
+                max_commission_rate_bps,
+            } => {
+                config.min_commission_rate_bps = min_commission_rate_bps;
+                config.max_commission_rate_bps = max_commission_rate_bps;
+                emit!(CommissionRateBandUpdated {
+                    min_commission_rate_bps,
+                    max_commission_rate_bps,
+                    admin,
+                    timestamp,
+                });
+            }
+            ConfigChange::Treasury { treasury } => {
+                config.treasury = treasury;
+                emit!(TreasuryUpdated {
+                    treasury,
+                    admin,
+                    timestamp,
+                });
+            }
+            ConfigChange::ProgramHash { program_hash } => {
+                let guard = guard.ok_or(ErrorCode::UpgradeGuardRequired)?;
+                guard.program_hash = program_hash;
+                emit!(ProgramHashUpdated {
+                    program_hash,
+                    admin,
+                    timestamp,
+                });
+            }
+            ConfigChange::Timelock { timelock_secs } => {
+                let guard = guard.ok_or(ErrorCode::UpgradeGuardRequired)?;
+                guard.timelock_secs = timelock_secs;
+                emit!(ConfigTimelockUpdated {
+                    timelock_secs,
+                    admin,
+                    timestamp,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Zero-copy so instructions borrow the pool in place instead of
+/// deserializing it; ids are fixed-size, optional keys are
+/// `Pubkey::default()` when unset and flags are `0`/`1`. Fields are laid out
+/// by alignment, so the struct has no implicit padding
+#[account(zero_copy)]
+pub struct MerchantPool {
+    pub total_volume: u64,
+    pub total_commissions_paid: u64,
+    /// Affiliates ever added; affiliate `i` is listed in the `AffiliateEntry` at index `i`
+    pub affiliate_count: u64,
+    /// Accrued commissions owed to affiliates, reserved in the primary mint's escrow
+    pub total_claimable: u64,
+    /// Seconds accrued commissions stay claimable; zero means no expiry
+    pub claim_window_secs: i64,
+    /// Seconds without a sale after which an affiliate can be deactivated; zero disables
+    pub inactivity_period_secs: i64,
+    /// Seconds a sale may follow the click registered on its ref_id; zero
+    /// doesn't require clicks
+    pub attribution_window_secs: i64,
+    /// Most a single sale can pay in commission; zero means uncapped
+    pub max_commission_per_sale: u64,
+    /// Paid once to the referrer of an affiliate when it makes its first
+    /// sale in the primary mint; zero disables
+    pub first_sale_bounty: u64,
+    /// When affiliates flagged the pool, freezing withdrawals until the
+    /// admin clears it; zero when not flagged
+    pub flagged_at: i64,
+    pub cosign_threshold: u64,
+    /// Most the merchant can withdraw per `WITHDRAWAL_WINDOW_SECS`, in base
+    /// units of any mint; zero means unlimited
+    pub daily_withdrawal_limit: u64,
+    pub withdrawn_in_window: u64,
+    pub withdrawal_window_start: i64,
+    pub destination_change_at: i64,
+    /// Seconds primary-mint commissions are held in escrow after a sale;
+    /// zero pays out right away
+    pub holdback_secs: i64,
+    /// Held-back commissions not yet released, reserved in the primary mint's escrow
+    pub total_held: u64,
+    /// Seconds after a rate cut that earlier affiliates keep their
+    /// snapshotted rate; zero applies cuts right away
+    pub rate_grace_period_secs: i64,
+    pub rate_changed_at: i64,
+    /// Primary-mint volume since `monthly_volume_start`, which picks the
+    /// pool's protocol fee tier
+    pub monthly_volume: u64,
+    pub monthly_volume_start: i64,
+    /// Protocol fees owed to the treasury, reserved in the primary mint's
+    /// escrow until `collect_protocol_fees` sweeps them
+    pub protocol_fees_accrued: u64,
+    pub period_volume: u64,
+    pub period_commissions_paid: u64,
+    pub period_started_at: i64,
+    pub created_at: i64,
+    /// Leaves appended to `affiliate_tree`; the next compressed affiliate's index
+    pub compressed_affiliate_count: u64,
+    /// Receipts appended to `receipt_tree`
+    pub compressed_receipt_count: u64,
+    /// `total_commissions_paid` as of the last `sync_merchant_reputation`
+    pub reputation_synced_paid: u64,
+    /// Interest-bearing primary mint's scale as of the last `sync_interest`,
+    /// over `INTEREST_SCALE_ONE`; zero before the first sync
+    pub synced_interest_scale: u64,
+    /// Value the escrow has gained from interest, in the mint's UI amounts,
+    /// as tracked by `sync_interest`
+    pub interest_earned: u64,
+    pub interest_synced_at: i64,
+    /// Latest sale, deposit or withdrawal; zero before the first
+    pub last_activity_at: i64,
+    /// When the admin gave notice of recovering the abandoned pool's
+    /// escrow; zero without a pending notice
+    pub abandonment_noticed_at: i64,
+    /// When guardians can complete the pending merchant recovery; zero
+    /// without one
+    pub merchant_recovery_at: i64,
+    /// Nonce whose reference the next payment-proven sale's payment must
+    /// carry; advanced by each one
+    pub payment_nonce: u64,
+    /// `total_volume` and `total_commissions_paid` split by mint, in
+    /// `mint_slot` order
+    pub mint_volumes: [u64; MAX_POOL_MINTS],
+    pub mint_commissions_paid: [u64; MAX_POOL_MINTS],
+    /// Metric thresholds of the dynamic rate schedule, ascending; the
+    /// first `rate_step_count` are in use
+    pub rate_step_thresholds: [u64; MAX_RATE_STEPS],
+    pub merchant: Pubkey,
+    /// Merchant that created the pool. Its key seeds the pool and the
+    /// registry and reputation it counts toward, so they survive
+    /// `recover_merchant`
+    pub creator: Pubkey,
+    /// Backend key allowed to apply commission overrides; defaults to the merchant
+    pub processor: Pubkey,
+    pub usdc_mint: Pubkey,
+    /// Second key that must also sign withdrawals above `cosign_threshold`
+    pub withdrawal_cosigner: Pubkey,
+    /// Wallet whose token accounts alone may receive withdrawals; the
+    /// merchant's own accounts when unset
+    pub withdrawal_destination: Pubkey,
+    /// Replacement destination, applicable from `destination_change_at`
+    pub pending_withdrawal_destination: Pubkey,
+    /// Escrowless mode: primary-mint sales pull their payout from this
+    /// merchant token account, which must approve the escrow authority
+    pub revenue_account: Pubkey,
+    /// SPL Governance account whose native treasury is the merchant, making
+    /// the pool DAO-owned; unset for a regular merchant
+    pub governance: Pubkey,
+    /// Governance program `governance` belongs to; default when unset
+    pub governance_program: Pubkey,
+    /// Account-compression tree holding the pool's compressed affiliates;
+    /// unset until `init_affiliate_tree`
+    pub affiliate_tree: Pubkey,
+    /// Account-compression tree compressed sales log their receipts to;
+    /// unset until `init_receipt_tree`
+    pub receipt_tree: Pubkey,
+    /// Program that redeems affiliates' store credit via CPI; unset until
+    /// `set_reward_mode`
+    pub storefront_program: Pubkey,
+    /// Operators whose sign-off activates new affiliates; the first
+    /// `approver_count` are in use
+    pub approvers: [Pubkey; MAX_APPROVERS],
+    /// Keys that can jointly recover the merchant to a new key; the first
+    /// `guardian_count` are in use
+    pub guardians: [Pubkey; MAX_GUARDIANS],
+    /// Key guardians proposed to recover the merchant to; default without a
+    /// pending recovery
+    pub pending_merchant: Pubkey,
+    /// Additional settlement mints, each with its own escrow ATA; the first
+    /// `settlement_mint_count` are in use
+    pub settlement_mints: [Pubkey; MAX_SETTLEMENT_MINTS],
+    /// SHA-256 of the pool id, used in the pool's seeds so ids can outgrow a seed
+    pub pool_id_hash: [u8; 32],
+    /// Hash of the terms of service affiliates must accept; zero when unset
+    pub terms_hash: [u8; 32],
+    /// The first `pool_id_len` bytes hold the pool id
+    pub pool_id: [u8; MAX_POOL_ID_LEN],
+    pub display_name: [u8; MAX_DISPLAY_NAME_LEN],
+    /// Off-chain JSON with logo, terms and contact details
+    pub metadata_uri: [u8; MAX_METADATA_URI_LEN],
+    /// Reporting period in progress, closed out by `rotate_period`
+    pub period_index: u32,
+    pub min_override_bps: u16,
+    pub max_override_bps: u16,
+    pub commission_rate: u16,
+    /// Rate before the latest cut, and when that cut happened
+    pub previous_commission_rate: u16,
+    /// Rates paired with `rate_step_thresholds`
+    pub rate_step_bps: [u16; MAX_RATE_STEPS],
+    /// Days without a sale after which an affiliate's rate decays; zero
+    /// disables decay
+    pub rate_decay_idle_days: u16,
+    /// Consecutive days with sales that restore a decayed rate
+    pub rate_decay_restore_days: u16,
+    /// Share of its rate a decayed affiliate loses
+    pub rate_decay_bps: u16,
+    /// Store credit granted per unit of commission in store-credit mode
+    pub store_credit_rate_bps: u16,
+    pub pool_id_len: u8,
+    pub display_name_len: u8,
+    pub metadata_uri_len: u8,
+    pub settlement_mint_count: u8,
+    /// A `PayoutMode` discriminant
+    pub payout_mode: u8,
+    /// A `CommissionCapMode` discriminant
+    pub commission_cap_mode: u8,
+    /// Whether sales must update the pool's `Leaderboard`
+    pub has_leaderboard: u8,
+    /// Set by the admin to waive the protocol fee for this pool
+    pub fee_exempt: u8,
+    pub is_active: u8,
+    pub bump: u8,
+    pub escrow_bump: u8,
+    /// A `RatePolicy` discriminant
+    pub rate_policy: u8,
+    pub rate_step_count: u8,
+    pub approver_count: u8,
+    /// Approvals a new affiliate needs to become active; zero adds
+    /// affiliates active
+    pub approval_threshold: u8,
+    /// Whether sales check their transaction for token instructions on the
+    /// sale's accounts
+    pub guard_sales: u8,
+    /// Seed version `escrow_bump` and the escrow authority are derived
+    /// under, raised by `migrate_escrow_authority`
+    pub escrow_version: u8,
+    pub guardian_count: u8,
+    /// Guardian signatures proposing and completing a recovery take
+    pub guardian_threshold: u8,
+    /// A `RewardMode` discriminant
+    pub reward_mode: u8,
+    /// Whether affiliates can opt into payouts by confidential transfer
+    pub confidential_payouts: u8,
+    /// Whether sales must go through `process_sale_with_payment_proof`
+    pub require_payment_proof: u8,
+    pub _padding: [u8; 6],
+}
+
+impl MerchantPool {
+    pub fn pool_id(&self) -> &str {
+        read_fixed(&self.pool_id, self.pool_id_len)
+    }
+
+    pub fn set_pool_id(&mut self, pool_id: &str) {
+        self.pool_id_len = write_fixed(&mut self.pool_id, pool_id);
+    }
+
+    pub fn display_name(&self) -> &str {
+        read_fixed(&self.display_name, self.display_name_len)
+    }
+
+    pub fn set_display_name(&mut self, display_name: &str) {
+        self.display_name_len = write_fixed(&mut self.display_name, display_name);
+    }
+
+    pub fn metadata_uri(&self) -> &str {
+        read_fixed(&self.metadata_uri, self.metadata_uri_len)
+    }
+
+    pub fn set_metadata_uri(&mut self, metadata_uri: &str) {
+        self.metadata_uri_len = write_fixed(&mut self.metadata_uri, metadata_uri);
+    }
+
+    /// Trailing seed of the pool's escrow authority
+    pub fn escrow_version_seed(&self) -> &'static [u8] {
+        escrow_version_seed(self.escrow_version)
+    }
+
+    /// Settlement mints registered on top of the primary mint
+    pub fn settlement_mints(&self) -> &[Pubkey] {
+        &self.settlement_mints[..usize::from(self.settlement_mint_count)]
+    }
+
+    pub fn approvers(&self) -> &[Pubkey] {
+        &self.approvers[..usize::from(self.approver_count)]
+    }
+
+    pub fn is_approver(&self, key: &Pubkey) -> bool {
+        self.approvers().contains(key)
+    }
+
+    pub fn guardians(&self) -> &[Pubkey] {
+        &self.guardians[..usize::from(self.guardian_count)]
+    }
+
+    /// Fails unless distinct guardians among the signers of `accounts`
+    /// reach the pool's threshold
+    pub fn require_guardians(&self, accounts: &[AccountInfo]) -> Result<()> {
+        let mut signed: Vec<Pubkey> = Vec::new();
+        for info in accounts {
+            if info.is_signer && self.guardians().contains(info.key) && !signed.contains(info.key) {
+                signed.push(info.key());
+            }
+        }
+        require!(
+            self.guardian_threshold > 0 && signed.len() >= usize::from(self.guardian_threshold),
+            ErrorCode::NotEnoughGuardians
+        );
+        Ok(())
+    }
+
+    pub fn reward_mode(&self) -> RewardMode {
+        if self.reward_mode == RewardMode::StoreCredit as u8 {
+            RewardMode::StoreCredit
+        } else {
+            RewardMode::Token
+        }
+    }
+
+    pub fn payout_mode(&self) -> PayoutMode {
+        if self.payout_mode == PayoutMode::Claim as u8 {
+            PayoutMode::Claim
+        } else {
+            PayoutMode::Push
+        }
+    }
+
+    pub fn rate_policy(&self) -> RatePolicy {
+        match self.rate_policy {
+            1 => RatePolicy::ConversionRate,
+            2 => RatePolicy::TrailingVolume,
+            _ => RatePolicy::Flat,
+        }
+    }
+
+    pub fn rate_steps(&self) -> impl Iterator<Item = RateStep> + '_ {
+        self.rate_step_thresholds
+            .iter()
+            .zip(self.rate_step_bps.iter())
+            .take(self.rate_step_count as usize)
+            .map(|(&threshold, &rate_bps)| RateStep {
+                threshold,
+                rate_bps,
+            })
+    }
+
+    /// Rate the pool's dynamic rate policy gives `affiliate`; `None` for a
+    /// flat pool or below the first step
+    pub fn policy_rate(&self, affiliate: &AffiliateAccount) -> Option<u16> {
+        let metric = match self.rate_policy() {
+            RatePolicy::Flat => return None,
+            RatePolicy::ConversionRate => affiliate.conversion_rate_bps() as u64,
+            RatePolicy::TrailingVolume => affiliate.trailing_volume(self.period_index),
+        };
+        self.rate_steps()
+            .take_while(|step| step.threshold <= metric)
+            .last()
+            .map(|step| step.rate_bps)
+    }
+
+    /// Whether a sale by `affiliate` at `now` is paid a decayed rate, and
+    /// the affiliate's streak of consecutive sale days counting that sale
+    pub fn rate_decay(&self, affiliate: &AffiliateAccount, now: i64) -> (bool, u16) {
+        let days_since_sale =
+            now.div_euclid(SECS_PER_DAY) - affiliate.last_sale_at.div_euclid(SECS_PER_DAY);
+        let streak = match days_since_sale {
+            _ if affiliate.last_sale_at == 0 => 1,
+            0 => affiliate.sale_streak_days.max(1),
+            1 => affiliate.sale_streak_days.saturating_add(1),
+            _ => 1,
+        };
+        if self.rate_decay_idle_days == 0 {
+            return (false, streak);
+        }
+        let last_active_at = affiliate.last_sale_at.max(affiliate.created_at);
+        let idle = now.saturating_sub(last_active_at)
+            >= i64::from(self.rate_decay_idle_days) * SECS_PER_DAY;
+        let decayed = (affiliate.rate_decayed || idle) && streak < self.rate_decay_restore_days;
+        (decayed, streak)
+    }
+
+    /// `rate` less the pool's decay when `affiliate` is decayed at `now`
+    pub fn decayed_rate(&self, affiliate: &AffiliateAccount, rate: u16, now: i64) -> u16 {
+        match self.rate_decay(affiliate, now) {
+            (true, _) => (u32::from(rate) * u32::from(10000 - self.rate_decay_bps) / 10000) as u16,
+            (false, _) => rate,
+        }
+    }
+
+    /// Rate a sale by `affiliate` in `group` pays at `now` without an
+    /// override, shared by sales and quotes
+    pub fn affiliate_rate(
+        &self,
+        affiliate: &AffiliateAccount,
+        group: Option<&AffiliateGroup>,
+        now: i64,
+    ) -> u16 {
+        // Policy rates replace the pool rate, so rate cut grace windows
+        // don't apply to them
+        let rate = self
+            .policy_rate(affiliate)
+            .unwrap_or_else(|| self.rate_for(affiliate, now));
+        // Idle affiliates lose part of their rate until they're back on a
+        // sale streak
+        let rate = self.decayed_rate(affiliate, rate, now);
+        // A group's modifier adjusts whatever rate its members get
+        match group {
+            Some(group) => group.modified_rate(rate),
+            None => rate,
+        }
+    }
+
+    pub fn commission_cap_mode(&self) -> CommissionCapMode {
+        if self.commission_cap_mode == CommissionCapMode::Clamp as u8 {
+            CommissionCapMode::Clamp
+        } else {
+            CommissionCapMode::Reject
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.is_active != 0
+    }
+
+    pub fn has_leaderboard(&self) -> bool {
+        self.has_leaderboard != 0
+    }
+
+    pub fn fee_exempt(&self) -> bool {
+        self.fee_exempt != 0
+    }
+
+    pub fn confidential_payouts(&self) -> bool {
+        self.confidential_payouts != 0
+    }
+
+    pub fn requires_payment_proof(&self) -> bool {
+        self.require_payment_proof != 0
+    }
+
+    pub fn guards_sales(&self) -> bool {
+        self.guard_sales != 0
+    }
+
+    /// Whether `process_sale_multi` can honour the pool's setup: it pays at
+    /// the flat pool rate straight from escrow, so holdbacks, attribution
+    /// windows, dynamic or decaying rates, store credit, escrowless funding
+    /// and the sale guard are left to `process_sale`
+    pub fn supports_split_sales(&self) -> bool {
+        self.holdback_secs == 0
+            && self.attribution_window_secs == 0
+            && self.rate_policy() == RatePolicy::Flat
+            && self.rate_decay_idle_days == 0
+            && self.reward_mode() == RewardMode::Token
+            && self.revenue_account().is_none()
+            && !self.guards_sales()
+    }
+
+    pub fn withdrawal_cosigner(&self) -> Option<Pubkey> {
+        optional_key(self.withdrawal_cosigner)
+    }
+
+    pub fn withdrawal_destination(&self) -> Option<Pubkey> {
+        optional_key(self.withdrawal_destination)
+    }
+
+    pub fn pending_withdrawal_destination(&self) -> Option<Pubkey> {
+        optional_key(self.pending_withdrawal_destination)
+    }
+
+    pub fn revenue_account(&self) -> Option<Pubkey> {
+        optional_key(self.revenue_account)
+    }
+
+    pub fn governance(&self) -> Option<Pubkey> {
+        optional_key(self.governance)
+    }
+
+    pub fn affiliate_tree(&self) -> Option<Pubkey> {
+        optional_key(self.affiliate_tree)
+    }
+
+    pub fn receipt_tree(&self) -> Option<Pubkey> {
+        optional_key(self.receipt_tree)
+    }
+
+    /// Base commission rate for `affiliate` at `now`: its snapshotted rate,
+    /// capped at the rate before the latest cut, while that cut's grace
+    /// window is open and the snapshot predates it; the pool rate otherwise
+    pub fn rate_for(&self, affiliate: &AffiliateAccount, now: i64) -> u16 {
+        let in_grace = self.rate_grace_period_secs > 0
+            && affiliate.rate_snapshot_at < self.rate_changed_at
+            && now
+                < self
+                    .rate_changed_at
+                    .saturating_add(self.rate_grace_period_secs);
+        if in_grace {
+            affiliate
+                .rate_snapshot_bps
+                .min(self.previous_commission_rate)
+                .max(self.commission_rate)
+        } else {
+            self.commission_rate
+        }
+    }
+
+    /// Whether sales and escrow operations may settle in `mint`
+    pub fn accepts_mint(&self, mint: &Pubkey) -> bool {
+        self.usdc_mint == *mint || self.settlement_mints().contains(mint)
+    }
+
+    /// Index of `mint` in the per-mint totals: zero for the primary mint,
+    /// then the settlement mints in the order they were added
+    pub fn mint_slot(&self, mint: &Pubkey) -> Option<usize> {
+        if self.usdc_mint == *mint {
+            return Some(0);
+        }
+        self.settlement_mints()
+            .iter()
+            .position(|settlement_mint| settlement_mint == mint)
+            .map(|index| index + 1)
+    }
+
+    /// Take a refund's volume and clawback out of `mint`'s totals
+    pub fn unrecord_mint_sale(&mut self, mint: &Pubkey, sale_amount: u64, commission: u64) {
+        if let Some(slot) = self.mint_slot(mint) {
+            self.mint_volumes[slot] = self.mint_volumes[slot].saturating_sub(sale_amount);
+            self.mint_commissions_paid[slot] =
+                self.mint_commissions_paid[slot].saturating_sub(commission);
+        }
+    }
+
+    /// Escrow balance of `mint` that is owed to affiliates and can't be spent
+    pub fn reserved_for(&self, mint: &Pubkey) -> u64 {
+        if self.usdc_mint == *mint {
+            self.total_claimable
+                .saturating_add(self.total_held)
+                .saturating_add(self.protocol_fees_accrued)
+        } else {
+            0
+        }
+    }
+
+    /// Whether `key` may act as this pool's sale processor
+    pub fn is_processor(&self, key: &Pubkey) -> bool {
+        self.processor == *key || self.merchant == *key
+    }
+
+    /// Require the co-signer, signing as `cosigner`, for `amount` leaving
+    /// escrow above `cosign_threshold`
+    pub fn require_cosigner(&self, amount: u64, cosigner: Option<Pubkey>) -> Result<()> {
+        if let Some(required) = self.withdrawal_cosigner() {
+            require!(
+                amount <= self.cosign_threshold || cosigner == Some(required),
+                ErrorCode::CosignerRequired
+            );
+        }
+        Ok(())
+    }
+
+    /// Count `amount` against the daily withdrawal limit, opening a new
+    /// window once the current one has run out
+    pub fn record_withdrawal(&mut self, amount: u64, now: i64) -> Result<()> {
+        if self.daily_withdrawal_limit == 0 {
+            return Ok(());
+        }
+        if now
+            >= self
+                .withdrawal_window_start
+                .saturating_add(WITHDRAWAL_WINDOW_SECS)
+        {
+            self.withdrawal_window_start = now;
+            self.withdrawn_in_window = 0;
+        }
+        let withdrawn = self
+            .withdrawn_in_window
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        require!(
+            withdrawn <= self.daily_withdrawal_limit,
+            ErrorCode::DailyWithdrawalLimitExceeded
+        );
+        self.withdrawn_in_window = withdrawn;
+        Ok(())
+    }
+
+    /// Primary-mint volume in the current `FEE_VOLUME_WINDOW_SECS` window,
+    /// opening a new one once the current one has run out
+    pub fn roll_monthly_volume(&mut self, now: i64) -> u64 {
+        if now
+            >= self
+                .monthly_volume_start
+                .saturating_add(FEE_VOLUME_WINDOW_SECS)
+        {
+            self.monthly_volume_start = now;
+            self.monthly_volume = 0;
+        }
+        self.monthly_volume
+    }
+
+    /// Protocol fee tier and rate of a primary-mint sale at `now`, after
+    /// rolling the monthly volume window; exempt pools pay nothing
+    pub fn protocol_fee_tier(&mut self, config: &ProgramConfig, now: i64) -> (u8, u16) {
+        self.roll_monthly_volume(now);
+        self.fee_tier_at(config, now)
+    }
+
+    /// `protocol_fee_tier` without rolling the window, for quotes: a
+    /// window that has run out counts as empty
+    pub fn fee_tier_at(&self, config: &ProgramConfig, now: i64) -> (u8, u16) {
+        let monthly_volume = if now
+            >= self
+                .monthly_volume_start
+                .saturating_add(FEE_VOLUME_WINDOW_SECS)
+        {
+            0
+        } else {
+            self.monthly_volume
+        };
+        match config.fee_tier_for(monthly_volume) {
+            Some(tier) if !self.fee_exempt() => tier,
+            _ => (0, 0),
+        }
+    }
+
+    /// Add a sale in `mint` to the lifetime, per-mint, period and, for the
+    /// primary mint, monthly totals
+    pub fn record_sale(&mut self, sale_amount: u64, commission: u64, mint: &Pubkey) -> Result<()> {
+        let slot = self.mint_slot(mint).ok_or(ErrorCode::MintMismatch)?;
+        self.mint_volumes[slot] = self.mint_volumes[slot]
+            .checked_add(sale_amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.mint_commissions_paid[slot] = self.mint_commissions_paid[slot]
+            .checked_add(commission)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.total_volume = self
+            .total_volume
+            .checked_add(sale_amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.total_commissions_paid = self
+            .total_commissions_paid
+            .checked_add(commission)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.period_volume = self
+            .period_volume
+            .checked_add(sale_amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.period_commissions_paid = self
+            .period_commissions_paid
+            .checked_add(commission)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        if slot == 0 {
+            self.monthly_volume = self
+                .monthly_volume
+                .checked_add(sale_amount)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+        }
+        Ok(())
+    }
+
+    /// Note a sale, deposit or withdrawal at `now`, which also withdraws
+    /// any abandonment notice
+    pub fn record_activity(&mut self, now: i64) {
+        self.last_activity_at = now;
+        self.abandonment_noticed_at = 0;
+    }
+
+    /// When the pool counts as abandoned without further activity
+    pub fn abandoned_at(&self) -> i64 {
+        self.last_activity_at
+            .max(self.created_at)
+            .saturating_add(ABANDONMENT_PERIOD_SECS)
+    }
+
+    /// Whether merchant withdrawals are frozen pending admin review
+    pub fn is_flagged(&self) -> bool {
+        self.flagged_at != 0
+    }
+
+    /// Whether affiliates must accept `terms_hash` before being paid
+    pub fn requires_terms(&self) -> bool {
+        self.terms_hash != [0; 32]
+    }
+
+    /// Apply `max_commission_per_sale` to a computed commission
+    pub fn cap_commission(&self, commission: u64) -> Result<u64> {
+        if self.max_commission_per_sale == 0 || commission <= self.max_commission_per_sale {
+            return Ok(commission);
+        }
+        match self.commission_cap_mode() {
+            CommissionCapMode::Clamp => Ok(self.max_commission_per_sale),
+            CommissionCapMode::Reject => err!(ErrorCode::CommissionCapExceeded),
+        }
+    }
+}
+
+/// `key` unless it is the default key standing in for `None`
+fn optional_key(key: Pubkey) -> Option<Pubkey> {
+    (key != Pubkey::default()).then_some(key)
+}
+
+/// The first `len` bytes of a fixed-size string field
+fn read_fixed(bytes: &[u8], len: u8) -> &str {
+    std::str::from_utf8(&bytes[..usize::from(len)]).unwrap_or_default()
+}
+
+/// Store `value`, already checked against the field's capacity, in a
+/// fixed-size string field, returning its length
+fn write_fixed<const N: usize>(field: &mut [u8; N], value: &str) -> u8 {
+    let len = value.len().min(N);
+    *field = [0; N];
+    field[..len].copy_from_slice(&value.as_bytes()[..len]);
+    len as u8
+}
+
+/// Top affiliates of a pool by sale volume, kept sorted in descending order.
+/// Each affiliate's volume is as of its latest sale; unused slots are zeroed
+#[account(zero_copy)]
+pub struct Leaderboard {
+    pub pool: Pubkey,
+    pub entries: [LeaderboardEntry; LEADERBOARD_SIZE],
+    pub bump: u8,
+    pub _padding: [u8; 7],
+}
+
+#[zero_copy]
+pub struct LeaderboardEntry {
+    pub affiliate: Pubkey,
+    pub volume: u64,
+}
+
+impl Leaderboard {
+    /// Move `affiliate` to its rank for `volume`, which only ever grows
+    pub fn record(&mut self, affiliate: Pubkey, volume: u64) {
+        let last = LEADERBOARD_SIZE - 1;
+        let mut rank = match self.entries.iter().position(|e| e.affiliate == affiliate) {
+            Some(rank) => rank,
+            None if volume > self.entries[last].volume => last,
+            None => return,
+        };
+        self.entries[rank] = LeaderboardEntry { affiliate, volume };
+        while rank > 0 && self.entries[rank - 1].volume < volume {
+            self.entries.swap(rank - 1, rank);
+            rank -= 1;
+        }
+    }
+}
+
+/// Per-merchant pool counter; pool `i` is listed in the `PoolEntry` at index `i`
+#[account]
+#[derive(InitSpace)]
+pub struct MerchantRegistry {
+    pub merchant: Pubkey,
+    pub pool_count: u64,
+    pub bump: u8,
+}
+
+/// A merchant's track record across all their pools, for affiliates to
+/// check before joining one
+#[account]
+#[derive(InitSpace)]
+pub struct MerchantReputation {
+    pub merchant: Pubkey,
+    pub pools_created: u64,
+    /// Commissions paid across pools, net of clawbacks, as of each pool's
+    /// last `sync_merchant_reputation`
+    pub total_paid: u64,
+    /// Flags the admin upheld on review
+    pub disputes_lost: u32,
+    /// Times affiliates flagged a pool, freezing its withdrawals
+    pub freezes: u32,
+    pub first_pool_at: i64,
+    pub updated_at: i64,
+    pub bump: u8,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct PoolEntry {
+    pub merchant: Pubkey,
+    pub index: u64,
+    pub pool: Pubkey,
+    pub bump: u8,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct AffiliateAccount {
+    pub pool: Pubkey,
+    pub wallet: Pubkey,
+    #[max_len(32)]
+    pub ref_id: String,
+    /// Position in the pool's affiliate listing, assigned on creation
+    pub affiliate_index: u64,
+    /// Terms hash the affiliate last accepted and when
+    pub accepted_terms_hash: [u8; 32],
+    pub terms_accepted_at: i64,
+    /// Commissions accrued in claim mode and not yet paid out
+    pub claimable: u64,
+    /// When the current unclaimed balance started accruing; zero when empty
+    pub claimable_since: i64,
+    /// Refund clawbacks not yet recovered, deducted from future commissions
+    pub commission_debt: u64,
+    /// Commissions in their holdback period, not yet released
+    pub total_held: u64,
+    /// Pool rate as of the affiliate's creation or latest sale at the pool
+    /// rate, kept through a rate cut's grace window
+    pub rate_snapshot_bps: u16,
+    pub rate_snapshot_at: i64,
+    pub total_earned: u64,
+    pub sales_count: u64,
+    /// Clicks reported by the processor through `record_click`
+    pub clicks: u64,
+    /// Activity since the last `generate_statement`
+    pub open_statement: StatementTotals,
+    /// Period of the latest statement; zero before the first
+    pub last_statement_period: u32,
+    /// Pool reporting period `period_volume` was accrued in
+    pub volume_period: u32,
+    /// Sale volume in `volume_period` and in the period before it
+    pub period_volume: u64,
+    pub previous_period_volume: u64,
+    /// Time of the affiliate's most recent sale; zero before the first
+    pub last_sale_at: i64,
+    /// Biggest single `sale_amount` attributed to the affiliate
+    pub largest_sale: u64,
+    /// Sale volume attributed to the affiliate, net of refunds
+    pub total_sale_volume: u64,
+    /// Push payouts are held back until this much is owed; zero disables
+    pub min_payout_amount: u64,
+    /// Minimum seconds between push payouts; zero disables
+    pub min_payout_interval_secs: i64,
+    /// Time of the last push payout; zero before the first
+    pub last_payout_at: i64,
+    /// Agency or manager paid `manager_share_bps` of each commission
+    pub manager: Option<Pubkey>,
+    pub manager_share_bps: u16,
+    /// Part of `total_earned` routed to the manager
+    pub manager_earned: u64,
+    /// Wallet of the affiliate that referred this one, if any
+    pub referred_by: Option<Pubkey>,
+    /// Whether the referrer has received the pool's `first_sale_bounty`
+    pub referral_bounty_paid: bool,
+    /// Token account primary-mint commissions are paid to instead of the
+    /// wallet's ATA
+    pub payout_account: Option<Pubkey>,
+    pub is_active: bool,
+    pub bump: u8,
+    pub created_at: i64,
+    /// `AffiliateGroup` the affiliate's sales roll up into
+    pub group: Option<Pubkey>,
+    /// Consecutive days, up to the latest sale, with sales
+    pub sale_streak_days: u16,
+    /// Whether the pool's rate decay applies, until a long enough streak
+    pub rate_decayed: bool,
+    /// Whether the wallet has signed `activate_self`, consenting to the
+    /// account the merchant created; sales don't pay it until then
+    pub activated_by_affiliate: bool,
+    /// Merchant-defined labels, one per bit (VIP, probation, region, ...)
+    pub tags: u32,
+    /// Token accounts primary-mint payouts are split between instead of
+    /// the payout account; empty pays it whole
+    #[max_len(MAX_PAYOUT_SPLITS)]
+    pub payout_splits: Vec<PayoutSplit>,
+    /// Credit earned in the pool's store-credit mode, not yet redeemed
+    pub store_credit: u64,
+    /// Whether primary-mint commissions accrue for `pay_out_compressed`
+    /// instead of going to a token account
+    pub compressed_payouts: bool,
+    /// ElGamal key of the wallet's confidential token account; while the
+    /// pool has confidential payouts on, primary-mint commissions accrue
+    /// for `pay_out_confidential`
+    pub elgamal_pubkey: Option<[u8; 32]>,
+    /// Like `commission_debt`, for refunds of sales in each of the pool's
+    /// settlement mints, recovered from later commissions in that mint
+    pub settlement_mint_debts: [u64; MAX_SETTLEMENT_MINTS],
+}
+
+impl AffiliateAccount {
+    /// Set up a newly created, active affiliate of `pool`
+    pub fn init(
+        &mut self,
+        pool_key: Pubkey,
+        pool: &MerchantPool,
+        wallet: Pubkey,
+        ref_id: String,
+        bump: u8,
+        created_at: i64,
+    ) {
+        self.pool = pool_key;
+        self.wallet = wallet;
+        self.ref_id = ref_id;
+        self.affiliate_index = pool.affiliate_count;
+        self.accepted_terms_hash = [0; 32];
+        self.terms_accepted_at = 0;
+        self.claimable = 0;
+        self.claimable_since = 0;
+        self.commission_debt = 0;
+        self.total_held = 0;
+        self.total_earned = 0;
+        self.sales_count = 0;
+        self.clicks = 0;
+        self.volume_period = pool.period_index;
+        self.period_volume = 0;
+        self.previous_period_volume = 0;
+        self.open_statement = StatementTotals::default();
+        self.last_statement_period = 0;
+        self.last_sale_at = 0;
+        self.largest_sale = 0;
+        self.total_sale_volume = 0;
+        self.min_payout_amount = 0;
+        self.min_payout_interval_secs = 0;
+        self.last_payout_at = 0;
+        self.manager = None;
+        self.manager_share_bps = 0;
+        self.manager_earned = 0;
+        self.referred_by = None;
+        self.referral_bounty_paid = false;
+        self.payout_account = None;
+        self.is_active = true;
+        self.bump = bump;
+        self.rate_snapshot_bps = pool.commission_rate;
+        self.rate_snapshot_at = created_at;
+        self.created_at = created_at;
+        self.group = None;
+        self.sale_streak_days = 0;
+        self.rate_decayed = false;
+        self.activated_by_affiliate = false;
+        self.tags = 0;
+        self.payout_splits = Vec::new();
+        self.store_credit = 0;
+        self.compressed_payouts = false;
+        self.elgamal_pubkey = None;
+        self.settlement_mint_debts = [0; MAX_SETTLEMENT_MINTS];
+    }
+
+    /// Outstanding clawbacks in the pool mint at `slot`, numbered as by
+    /// `MerchantPool::mint_slot`
+    pub fn debt(&self, slot: usize) -> u64 {
+        match slot.checked_sub(1) {
+            Some(index) => self.settlement_mint_debts[index],
+            None => self.commission_debt,
+        }
+    }
+
+    /// `debt`, for recovering or adding to it
+    pub fn debt_mut(&mut self, slot: usize) -> &mut u64 {
+        match slot.checked_sub(1) {
+            Some(index) => &mut self.settlement_mint_debts[index],
+            None => &mut self.commission_debt,
+        }
+    }
+
+    /// Whether `process_sale_multi` can pay the affiliate in `pool`: it
+    /// pays one commission token account per affiliate, so managers,
+    /// groups, payout splits, compressed and confidential payouts and
+    /// referral bounties still due are left to `process_sale`
+    pub fn supports_split_sales(&self, pool: &MerchantPool) -> bool {
+        self.manager.is_none()
+            && self.group.is_none()
+            && self.payout_splits.is_empty()
+            && !self.compressed_payouts
+            && self.elgamal_pubkey.is_none()
+            && (self.referred_by.is_none()
+                || self.referral_bounty_paid
+                || pool.first_sale_bounty == 0)
+    }
+
+    /// Whether the affiliate carries every bit of `tags`
+    pub fn has_tags(&self, tags: u32) -> bool {
+        self.tags & tags == tags
+    }
+
+    /// Whether a push payout of `payout` plus anything held back is due at `now`
+    pub fn payout_due(&self, payout: u64, now: i64) -> bool {
+        self.claimable.saturating_add(payout) >= self.min_payout_amount
+            && now
+                >= self
+                    .last_payout_at
+                    .saturating_add(self.min_payout_interval_secs)
+    }
+
+    /// Mean sale size net of refunds; zero before the first sale
+    pub fn average_sale(&self) -> u64 {
+        self.total_sale_volume
+            .checked_div(self.sales_count)
+            .unwrap_or(0)
+    }
+
+    /// Sales per recorded click in basis points, capped at 100%; zero
+    /// before the first click
+    pub fn conversion_rate_bps(&self) -> u16 {
+        let rate = (self.sales_count as u128 * 10000)
+            .checked_div(self.clicks as u128)
+            .unwrap_or(0);
+        rate.min(10000) as u16
+    }
+
+    /// Sale volume over the pool's current and previous reporting periods,
+    /// before refunds
+    pub fn trailing_volume(&self, period_index: u32) -> u64 {
+        if self.volume_period == period_index {
+            self.period_volume
+                .saturating_add(self.previous_period_volume)
+        } else if self.volume_period.checked_add(1) == Some(period_index) {
+            self.period_volume
+        } else {
+            0
+        }
+    }
+
+    /// Add `sale_amount` to the volume of period `period_index`, rolling
+    /// the counters over first if the pool has moved on
+    pub fn record_period_volume(&mut self, period_index: u32, sale_amount: u64) -> Result<()> {
+        if self.volume_period != period_index {
+            self.previous_period_volume = self.trailing_volume(period_index);
+            self.period_volume = 0;
+            self.volume_period = period_index;
+        }
+        self.period_volume = self
+            .period_volume
+            .checked_add(sale_amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(())
+    }
+}
+
+/// Limits on the sales a session key may sign
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub struct SessionScope {
+    /// Largest `sale_amount` of a single sale
+    pub max_sale_amount: u64,
+    /// Total commission the key's sales may pay over its lifetime
+    pub commission_budget: u64,
+}
+
+/// Short-lived grant letting `session` sign `process_sale` for the pool in
+/// the processor's place
+#[account]
+#[derive(InitSpace)]
+pub struct SessionKey {
+    pub pool: Pubkey,
+    pub session: Pubkey,
+    /// Merchant or processor key that issued the grant
+    pub issued_by: Pubkey,
+    pub scope: SessionScope,
+    /// Commission paid on sales signed with the key so far
+    pub commission_spent: u64,
+    pub expires_at: i64,
+    pub bump: u8,
+    pub created_at: i64,
+}
+
+/// Operator sign-offs on an affiliate added while its pool requires
+/// approval; kept after activation as the onboarding record
+#[account]
+#[derive(InitSpace)]
+pub struct PendingAffiliate {
+    pub pool: Pubkey,
+    pub affiliate: Pubkey,
+    #[max_len(MAX_APPROVERS)]
+    pub approvals: Vec<Pubkey>,
+    /// When the threshold was reached; zero while pending
+    pub approved_at: i64,
+    pub bump: u8,
+    pub created_at: i64,
+    /// Where the applicant describes itself; empty until it submits
+    #[max_len(MAX_METADATA_URI_LEN)]
+    pub profile_uri: String,
+    /// Hash of the contact details the applicant shared off-chain
+    pub contact_hash: [u8; 32],
+    pub submitted_at: i64,
+}
+
+/// An affiliate kept as a leaf of its pool's affiliate tree rather than an
+/// `AffiliateAccount`, so it costs no rent. The full record travels with
+/// each sale and must hash to the leaf the tree holds
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CompressedAffiliate {
+    pub wallet: Pubkey,
+    pub ref_id: String,
+    /// Position of the affiliate's leaf in the tree
+    pub leaf_index: u32,
+    pub total_earned: u64,
+    pub sales_count: u64,
+    pub total_sale_volume: u64,
+    pub last_sale_at: i64,
+    pub created_at: i64,
+}
+
+impl CompressedAffiliate {
+    /// Tree leaf committing to this record within `pool`
+    pub fn leaf(&self, pool: &Pubkey) -> [u8; 32] {
+        keccak::hashv(&[
+            pool.as_ref(),
+            self.wallet.as_ref(),
+            self.ref_id.as_bytes(),
+            &self.leaf_index.to_le_bytes(),
+            &self.total_earned.to_le_bytes(),
+            &self.sales_count.to_le_bytes(),
+            &self.total_sale_volume.to_le_bytes(),
+            &self.last_sale_at.to_le_bytes(),
+            &self.created_at.to_le_bytes(),
+        ])
+        .to_bytes()
+    }
+}
+
+/// A sale receipt kept as a leaf of its pool's receipt tree rather than a
+/// `SaleReceipt`; indexers keep the full record from `SaleReceiptCompressed`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CompressedReceipt {
+    pub pool: Pubkey,
+    /// The compressed affiliate's wallet
+    pub affiliate: Pubkey,
+    pub order_id: String,
+    pub mint: Pubkey,
+    pub sale_amount: u64,
+    pub commission: u64,
+    pub applied_rate_bps: u16,
+    /// Position of the receipt's leaf in the tree
+    pub leaf_index: u32,
+    pub created_at: i64,
+}
+
+impl CompressedReceipt {
+    /// Tree leaf committing to this receipt
+    pub fn leaf(&self) -> [u8; 32] {
+        keccak::hashv(&[
+            self.pool.as_ref(),
+            self.affiliate.as_ref(),
+            self.order_id.as_bytes(),
+            self.mint.as_ref(),
+            &self.sale_amount.to_le_bytes(),
+            &self.commission.to_le_bytes(),
+            &self.applied_rate_bps.to_le_bytes(),
+            &self.leaf_index.to_le_bytes(),
+            &self.created_at.to_le_bytes(),
+        ])
+        .to_bytes()
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct AffiliateEntry {
+    pub pool: Pubkey,
+    pub index: u64,
+    pub affiliate: Pubkey,
+    pub bump: u8,
+}
+
+/// Record of a processed sale, keyed by the merchant's order id
+#[account]
+#[derive(InitSpace)]
+pub struct SaleReceipt {
+    pub pool: Pubkey,
+    pub affiliate: Pubkey,
+    #[max_len(32)]
+    pub order_id: String,
+    /// Free-form link back to the merchant's order system; empty when unset
+    #[max_len(MAX_SALE_MEMO_LEN)]
+    pub memo: String,
+    /// Hash of off-chain order data; zero when unset
+    pub metadata_hash: [u8; 32],
+    pub mint: Pubkey,
+    pub sale_amount: u64,
+    pub commission: u64,
+    /// Commission rate the sale was paid at, including overrides
+    pub applied_rate_bps: u16,
+    pub refunded_amount: u64,
+    pub clawed_back: u64,
+    /// Commission held back until `releasable_at`; zero once released
+    pub held_amount: u64,
+    pub releasable_at: i64,
+    pub bump: u8,
+    pub created_at: i64,
+    /// Whether `process_sale_multi` split the commission between several
+    /// affiliates; `affiliate` is then only the first of them
+    pub split: bool,
+}
+
+/// Promotion within a pool with its own rate, commission budget and window,
+/// paid from the pool's escrow
 #[account]
 #[derive(InitSpace)]
-pub struct AffiliateAccount {
+pub struct Campaign {
+    pub pool: Pubkey,
+    #[max_len(32)]
+    pub campaign_id: String,
+    pub commission_rate: u16,
+    /// Most commission the campaign may pay in total
+    pub budget: u64,
+    pub spent: u64,
+    pub total_volume: u64,
+    pub sales_count: u64,
+    pub starts_at: i64,
+    /// Zero means the campaign runs until ended
+    pub ends_at: i64,
+    /// Tag bits an affiliate must all carry; zero lets anyone sell
+    pub required_tags: u32,
+    pub is_active: bool,
+    pub bump: u8,
+    pub created_at: i64,
+}
+
+impl Campaign {
+    /// Whether sales can be credited to the campaign at `now`
+    pub fn is_live(&self, now: i64) -> bool {
+        self.is_active && now >= self.starts_at && (self.ends_at == 0 || now < self.ends_at)
+    }
+}
+
+/// A market within a pool, such as a country, with its own commission
+/// budget and rate modifier, paid from the pool's shared escrow
+#[account]
+#[derive(InitSpace)]
+pub struct RegionPartition {
+    pub pool: Pubkey,
+    /// Two-letter code, e.g. `*b"DE"`
+    pub region: [u8; 2],
+    /// Added to the rate of sales in the region, clamped to 0-100%
+    pub rate_modifier_bps: i16,
+    /// Most commission the region may pay in total
+    pub budget: u64,
+    pub spent: u64,
+    pub total_volume: u64,
+    pub sales_count: u64,
+    pub bump: u8,
+    pub created_at: i64,
+}
+
+impl RegionPartition {
+    /// `rate_bps` with the region's modifier applied
+    pub fn modified_rate(&self, rate_bps: u16) -> u16 {
+        (i32::from(rate_bps) + i32::from(self.rate_modifier_bps)).clamp(0, 10000) as u16
+    }
+}
+
+/// Affiliates the merchant manages together, such as an agency's
+/// creators, with stats rolled up from their sales since joining
+#[account]
+#[derive(InitSpace)]
+pub struct AffiliateGroup {
+    pub pool: Pubkey,
+    #[max_len(32)]
+    pub group_id: String,
+    /// Added to members' rates in `process_sale`, clamped to 0-100%
+    pub rate_modifier_bps: i16,
+    pub member_count: u32,
+    pub total_sale_volume: u64,
+    pub total_earned: u64,
+    pub sales_count: u64,
+    pub bump: u8,
+    pub created_at: i64,
+    /// Receives members' commissions in place of their own accounts
+    pub payout_account: Option<Pubkey>,
+}
+
+impl AffiliateGroup {
+    /// `rate_bps` with the group's modifier applied
+    pub fn modified_rate(&self, rate_bps: u16) -> u16 {
+        (i32::from(rate_bps) + i32::from(self.rate_modifier_bps)).clamp(0, 10000) as u16
+    }
+
+    pub fn record_sale(&mut self, sale_amount: u64, commission: u64) -> Result<()> {
+        self.total_sale_volume = self
+            .total_sale_volume
+            .checked_add(sale_amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.total_earned = self
+            .total_earned
+            .checked_add(commission)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.sales_count = self
+            .sales_count
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(())
+    }
+}
+
+/// Maps a ref_id to its affiliate account so codes resolve without scans
+#[account]
+#[derive(InitSpace)]
+pub struct RefIdIndex {
+    pub pool: Pubkey,
+    pub affiliate: Pubkey,
+    /// Sales attributed by looking up this code
+    pub redemptions: u64,
+    /// Latest `register_click` on this code; zero before the first
+    pub click_registered_at: i64,
+    pub bump: u8,
+}
+
+/// Recurring escrow top-up pulled from a merchant token account that has
+/// approved this PDA as its delegate
+#[account]
+#[derive(InitSpace)]
+pub struct TopUpSchedule {
+    pub pool: Pubkey,
+    /// Merchant token account funds are pulled from
+    pub source: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub interval_secs: i64,
+    pub next_topup_at: i64,
+    pub bump: u8,
+}
+
+/// An affiliate's stats as of the end of a period, never modified after
+/// `snapshot_affiliate` writes it
+#[account]
+#[derive(InitSpace)]
+pub struct AffiliateSnapshot {
+    pub pool: Pubkey,
+    pub affiliate: Pubkey,
+    pub period: u32,
+    pub total_earned: u64,
+    pub manager_earned: u64,
+    pub sales_count: u64,
+    pub total_sale_volume: u64,
+    pub largest_sale: u64,
+    pub claimable: u64,
+    pub total_held: u64,
+    pub commission_debt: u64,
+    pub taken_at: i64,
+    pub bump: u8,
+}
+
+/// Affiliate activity over a statement's span
+#[derive(
+    AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq, InitSpace,
+)]
+pub struct StatementTotals {
+    /// Commissions earned, before manager fees and clawbacks
+    pub earned: u64,
+    /// Share of `earned` routed to the affiliate's manager
+    pub manager_fees: u64,
+    /// Commissions clawed back by refunds
+    pub clawed_back: u64,
+    pub sales_count: u64,
+    pub sale_volume: u64,
+    pub refunded_volume: u64,
+    /// Keccak chain over the sale receipts included, in order, starting
+    /// from zero
+    pub receipts_hash: [u8; 32],
+}
+
+impl StatementTotals {
+    pub fn record_sale(
+        &mut self,
+        receipt: &Pubkey,
+        sale_amount: u64,
+        commission: u64,
+        manager_fee: u64,
+    ) -> Result<()> {
+        self.earned = self
+            .earned
+            .checked_add(commission)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.manager_fees = self
+            .manager_fees
+            .checked_add(manager_fee)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.sales_count = self
+            .sales_count
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.sale_volume = self
+            .sale_volume
+            .checked_add(sale_amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.receipts_hash = keccak::hashv(&[&self.receipts_hash, receipt.as_ref()]).to_bytes();
+        Ok(())
+    }
+
+    pub fn record_refund(&mut self, refund_amount: u64, clawback: u64) -> Result<()> {
+        self.refunded_volume = self
+            .refunded_volume
+            .checked_add(refund_amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.clawed_back = self
+            .clawed_back
+            .checked_add(clawback)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(())
+    }
+}
+
+/// An affiliate's payout statement for a period, never modified after
+/// `generate_statement` writes it; the canonical record for accounting
+#[account]
+#[derive(InitSpace)]
+pub struct Statement {
+    pub pool: Pubkey,
+    pub affiliate: Pubkey,
+    pub period: u32,
+    /// Period of the statement before this one; zero for the first
+    pub previous_period: u32,
+    pub totals: StatementTotals,
+    /// Lifetime totals as of generation
+    pub total_earned: u64,
+    pub total_sale_volume: u64,
+    pub generated_at: i64,
+    pub bump: u8,
+}
+
+/// A closed reporting period of a pool, archived by `rotate_period`
+#[account]
+#[derive(InitSpace)]
+pub struct PoolPeriod {
+    pub pool: Pubkey,
+    pub period_index: u32,
+    pub volume: u64,
+    pub commissions_paid: u64,
+    /// Lifetime totals as of the period's end
+    pub total_volume: u64,
+    pub total_commissions_paid: u64,
+    pub started_at: i64,
+    pub ended_at: i64,
+    pub bump: u8,
+}
+
+#[derive(Accounts)]
+pub struct InitializeConfig<'info> {
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + ProgramConfig::INIT_SPACE,
+        seeds = [CONFIG_SEED],
+        bump
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    /// Must be the program's upgrade authority
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        constraint = program.programdata_address()? == Some(program_data.key())
+            @ ErrorCode::InvalidProgramData
+    )]
+    pub program: Program<'info, crate::program::RedioContract>,
+
+    #[account(
+        constraint = program_data.upgrade_authority_address == Some(admin.key()) @ ErrorCode::Unauthorized
+    )]
+    pub program_data: Account<'info, ProgramData>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetPaused<'info> {
+    #[account(
+        mut,
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        has_one = admin @ ErrorCode::Unauthorized
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(args: InitializePoolArgs)]
+pub struct InitializePool<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        init,
+        payer = rent_payer,
+        space = 8 + std::mem::size_of::<MerchantPool>(),
+        seeds = [
+            POOL_SEED,
+            merchant.key().as_ref(),
+            pda::pool_id_hash(&args.pool_id).as_ref()
+        ],
+        bump
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    /// PDA that owns the escrow token account and signs transfers out of it
+    #[account(
+        seeds = [ESCROW_AUTHORITY_SEED, merchant_pool.key().as_ref()],
+        bump
+    )]
+    pub escrow_authority: SystemAccount<'info>,
+
+    pub merchant: Signer<'info>,
+
+    /// Funds rent for the accounts created here, so the merchant only authorizes
+    #[account(mut)]
+    pub rent_payer: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = merchant_usdc.owner == merchant.key() @ ErrorCode::WrongMerchantTokenAccount,
+        constraint = merchant_usdc.mint == usdc_mint.key() @ ErrorCode::TokenAccountMintMismatch
+    )]
+    pub merchant_usdc: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        init,
+        payer = rent_payer,
+        associated_token::mint = usdc_mint,
+        associated_token::authority = escrow_authority,
+        associated_token::token_program = token_program,
+    )]
+    pub escrow_usdc: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = rent_payer,
+        space = 8 + MerchantRegistry::INIT_SPACE,
+        seeds = [REGISTRY_SEED, merchant.key().as_ref()],
+        bump
+    )]
+    pub merchant_registry: Account<'info, MerchantRegistry>,
+
+    #[account(
+        init_if_needed,
+        payer = rent_payer,
+        space = 8 + MerchantReputation::INIT_SPACE,
+        seeds = [REPUTATION_SEED, merchant.key().as_ref()],
+        bump
+    )]
+    pub merchant_reputation: Account<'info, MerchantReputation>,
+
+    #[account(
+        init,
+        payer = rent_payer,
+        space = 8 + PoolEntry::INIT_SPACE,
+        seeds = [
+            POOL_ENTRY_SEED,
+            merchant.key().as_ref(),
+            &merchant_registry.pool_count.to_le_bytes()
+        ],
+        bump
+    )]
+    pub pool_entry: Account<'info, PoolEntry>,
+
+    pub usdc_mint: InterfaceAccount<'info, Mint>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(new_pool_id: String)]
+pub struct ClonePool<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    /// Pool whose settings are copied
+    #[account(
+        seeds = [
+            POOL_SEED,
+            source_pool.load()?.creator.as_ref(),
+            source_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = source_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub source_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        init,
+        payer = rent_payer,
+        space = 8 + std::mem::size_of::<MerchantPool>(),
+        seeds = [
+            POOL_SEED,
+            merchant.key().as_ref(),
+            pda::pool_id_hash(&new_pool_id).as_ref()
+        ],
+        bump
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        seeds = [ESCROW_AUTHORITY_SEED, merchant_pool.key().as_ref()],
+        bump
+    )]
+    pub escrow_authority: SystemAccount<'info>,
+
+    pub merchant: Signer<'info>,
+
+    #[account(mut)]
+    pub rent_payer: Signer<'info>,
+
+    #[account(
+        init,
+        payer = rent_payer,
+        associated_token::mint = usdc_mint,
+        associated_token::authority = escrow_authority,
+        associated_token::token_program = token_program,
+    )]
+    pub escrow_usdc: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [REGISTRY_SEED, merchant.key().as_ref()],
+        bump = merchant_registry.bump
+    )]
+    pub merchant_registry: Account<'info, MerchantRegistry>,
+
+    #[account(
+        init_if_needed,
+        payer = rent_payer,
+        space = 8 + MerchantReputation::INIT_SPACE,
+        seeds = [REPUTATION_SEED, merchant.key().as_ref()],
+        bump
+    )]
+    pub merchant_reputation: Account<'info, MerchantReputation>,
+
+    #[account(
+        init,
+        payer = rent_payer,
+        space = 8 + PoolEntry::INIT_SPACE,
+        seeds = [
+            POOL_ENTRY_SEED,
+            merchant.key().as_ref(),
+            &merchant_registry.pool_count.to_le_bytes()
+        ],
+        bump
+    )]
+    pub pool_entry: Account<'info, PoolEntry>,
+
+    #[account(
+        constraint = usdc_mint.key() == source_pool.load()?.usdc_mint @ ErrorCode::MintMismatch
+    )]
+    pub usdc_mint: InterfaceAccount<'info, Mint>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdatePoolCommission<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    pub merchant: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetPoolGovernance<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    /// The governance native treasury when enabling DAO mode, signed by the
+    /// governance program as it executes a proposal
+    pub merchant: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetRateGracePeriod<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    pub merchant: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdatePoolMetadata<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    pub merchant: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetPoolTerms<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    pub merchant: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetRewardMode<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    pub merchant: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RedeemStoreCredit<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        mut,
+        seeds = [
+            AFFILIATE_SEED,
+            merchant_pool.key().as_ref(),
+            affiliate_wallet.key().as_ref()
+        ],
+        bump = affiliate_account.bump
+    )]
+    pub affiliate_account: Account<'info, AffiliateAccount>,
+
+    pub affiliate_wallet: Signer<'info>,
+
+    /// The pool's storefront program's PDA, signed for in its CPI
+    #[account(
+        constraint = merchant_pool.load()?.storefront_program != Pubkey::default() @ ErrorCode::Unauthorized,
+        constraint = storefront_authority.key()
+            == pda::find_storefront_authority(
+                &merchant_pool.key(),
+                &merchant_pool.load()?.storefront_program
+            ).0 @ ErrorCode::Unauthorized
+    )]
+    pub storefront_authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetPayoutMode<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    pub merchant: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetSaleGuard<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    pub merchant: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetPaymentProofRequired<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    pub merchant: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ConfigureConfidentialEscrow<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        seeds = [
+            ESCROW_AUTHORITY_SEED,
+            merchant_pool.key().as_ref(),
+            merchant_pool.load()?.escrow_version_seed()
+        ],
+        bump = merchant_pool.load()?.escrow_bump
+    )]
+    pub escrow_authority: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = escrow_usdc.owner == escrow_authority.key() @ ErrorCode::EscrowAuthorityMismatch,
+        constraint = escrow_usdc.mint == merchant_pool.load()?.usdc_mint @ ErrorCode::EscrowMintMismatch
+    )]
+    pub escrow_usdc: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        constraint = usdc_mint.key() == merchant_pool.load()?.usdc_mint @ ErrorCode::MintMismatch
+    )]
+    pub usdc_mint: InterfaceAccount<'info, Mint>,
+
+    /// CHECK: Context state account of the escrow ElGamal key's validity
+    /// proof, validated by Token-2022
+    pub proof_context: UncheckedAccount<'info>,
+
+    /// Pays for growing the escrow
+    #[account(mut)]
+    pub merchant: Signer<'info>,
+
+    pub token_program: Program<'info, Token2022>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetConfidentialPayouts<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        seeds = [
+            ESCROW_AUTHORITY_SEED,
+            merchant_pool.key().as_ref(),
+            merchant_pool.load()?.escrow_version_seed()
+        ],
+        bump = merchant_pool.load()?.escrow_bump
+    )]
+    pub escrow_authority: SystemAccount<'info>,
+
+    #[account(
+        constraint = escrow_usdc.owner == escrow_authority.key() @ ErrorCode::EscrowAuthorityMismatch,
+        constraint = escrow_usdc.mint == merchant_pool.load()?.usdc_mint @ ErrorCode::EscrowMintMismatch
+    )]
+    pub escrow_usdc: InterfaceAccount<'info, TokenAccount>,
+
+    pub merchant: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetPoolProcessor<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    pub merchant: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetCommissionOverrideBounds<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    pub merchant: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetClaimWindow<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    pub merchant: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetWithdrawalCosigner<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    pub merchant: Signer<'info>,
+
+    /// Required while the pool already has a co-signer
+    pub current_cosigner: Option<Signer<'info>>,
+}
+
+#[derive(Accounts)]
+pub struct SetRevenueAccount<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    pub merchant: Signer<'info>,
+
+    /// Omit to return the pool to escrow mode
+    #[account(
+        constraint = revenue_usdc.owner == merchant.key() @ ErrorCode::Unauthorized,
+        constraint = revenue_usdc.mint == merchant_pool.load()?.usdc_mint @ ErrorCode::MintMismatch
+    )]
+    pub revenue_usdc: Option<InterfaceAccount<'info, TokenAccount>>,
+}
+
+#[derive(Accounts)]
+pub struct SetWithdrawalDestination<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    pub merchant: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetDailyWithdrawalLimit<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    pub merchant: Signer<'info>,
+
+    /// The pool's `withdrawal_cosigner`, required to raise or remove the limit
+    pub cosigner: Option<Signer<'info>>,
+}
+
+#[derive(Accounts)]
+pub struct SetHoldbackPeriod<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    pub merchant: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ReleaseMaturedHoldbacks<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(mut)]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        seeds = [
+            ESCROW_AUTHORITY_SEED,
+            merchant_pool.key().as_ref(),
+            merchant_pool.load()?.escrow_version_seed()
+        ],
+        bump = merchant_pool.load()?.escrow_bump
+    )]
+    pub escrow_authority: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = escrow_usdc.owner == escrow_authority.key() @ ErrorCode::EscrowAuthorityMismatch,
+        constraint = escrow_usdc.mint == usdc_mint.key() @ ErrorCode::EscrowMintMismatch
+    )]
+    pub escrow_usdc: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        constraint = usdc_mint.key() == merchant_pool.load()?.usdc_mint @ ErrorCode::MintMismatch
+    )]
+    pub usdc_mint: InterfaceAccount<'info, Mint>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub memo_program: Program<'info, Memo>,
+}
+
+#[derive(Accounts)]
+pub struct SetFirstSaleBounty<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    pub merchant: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetCommissionCap<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    pub merchant: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct InitLeaderboard<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        init,
+        payer = rent_payer,
+        space = 8 + std::mem::size_of::<Leaderboard>(),
+        seeds = [LEADERBOARD_SEED, merchant_pool.key().as_ref()],
+        bump
+    )]
+    pub leaderboard: AccountLoader<'info, Leaderboard>,
+
+    pub merchant: Signer<'info>,
+
+    /// Funds rent for the accounts created here, so the merchant only authorizes
+    #[account(mut)]
+    pub rent_payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetInactivityPeriod<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    pub merchant: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetAttributionWindow<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    pub merchant: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(ref_id: String)]
+pub struct RegisterClick<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        constraint = merchant_pool.load()?.is_processor(&authority.key()) @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        mut,
+        seeds = [REF_ID_SEED, merchant_pool.key().as_ref(), ref_id.as_bytes()],
+        bump = ref_id_index.bump
+    )]
+    pub ref_id_index: Account<'info, RefIdIndex>,
+
+    /// The pool's merchant or processor
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetRatePolicy<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    pub merchant: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetRateDecay<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    pub merchant: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetAffiliateApprovers<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    pub merchant: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ApproveAffiliate<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        mut,
+        constraint = affiliate_account.pool == merchant_pool.key() @ ErrorCode::InvalidAffiliate
+    )]
+    pub affiliate_account: Account<'info, AffiliateAccount>,
+
+    #[account(
+        mut,
+        seeds = [PENDING_AFFILIATE_SEED, affiliate_account.key().as_ref()],
+        bump = pending_affiliate.bump
+    )]
+    pub pending_affiliate: Account<'info, PendingAffiliate>,
+
+    /// One of the pool's approvers
+    pub approver: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SubmitAffiliateApplication<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        seeds = [
+            AFFILIATE_SEED,
+            merchant_pool.key().as_ref(),
+            affiliate_wallet.key().as_ref()
+        ],
+        bump = affiliate_account.bump
+    )]
+    pub affiliate_account: Account<'info, AffiliateAccount>,
+
+    #[account(
+        mut,
+        seeds = [PENDING_AFFILIATE_SEED, affiliate_account.key().as_ref()],
+        bump = pending_affiliate.bump
+    )]
+    pub pending_affiliate: Account<'info, PendingAffiliate>,
+
+    pub affiliate_wallet: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(session: Pubkey)]
+pub struct CreateSessionKey<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        constraint = merchant_pool.load()?.is_processor(&authority.key()) @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + SessionKey::INIT_SPACE,
+        seeds = [SESSION_KEY_SEED, merchant_pool.key().as_ref(), session.as_ref()],
+        bump
+    )]
+    pub session_key: Account<'info, SessionKey>,
+
+    /// The pool's merchant or processor
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RevokeSessionKey<'info> {
+    #[account(
+        constraint = merchant_pool.load()?.is_processor(&authority.key()) @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        mut,
+        close = authority,
+        seeds = [
+            SESSION_KEY_SEED,
+            merchant_pool.key().as_ref(),
+            session_key.session.as_ref()
+        ],
+        bump = session_key.bump
+    )]
+    pub session_key: Account<'info, SessionKey>,
+
+    /// The pool's merchant or processor
+    #[account(mut)]
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RecordClick<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        constraint = merchant_pool.load()?.is_processor(&authority.key()) @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        mut,
+        constraint = affiliate_account.pool == merchant_pool.key() @ ErrorCode::InvalidAffiliate
+    )]
+    pub affiliate_account: Account<'info, AffiliateAccount>,
+
+    /// The pool's merchant or processor
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RotatePeriod<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        init,
+        payer = rent_payer,
+        space = 8 + PoolPeriod::INIT_SPACE,
+        seeds = [
+            POOL_PERIOD_SEED,
+            merchant_pool.key().as_ref(),
+            &merchant_pool.load()?.period_index.to_le_bytes()
+        ],
+        bump
+    )]
+    pub pool_period: Account<'info, PoolPeriod>,
+
+    pub merchant: Signer<'info>,
+
+    /// Funds rent for the accounts created here, so the merchant only authorizes
+    #[account(mut)]
+    pub rent_payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct DeactivatePool<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    pub merchant: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct FlagPool<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(mut)]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        mut,
+        seeds = [REPUTATION_SEED, merchant_pool.load()?.creator.as_ref()],
+        bump = merchant_reputation.bump
+    )]
+    pub merchant_reputation: Account<'info, MerchantReputation>,
+}
+
+#[derive(Accounts)]
+pub struct ClearPoolFlag<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        has_one = admin @ ErrorCode::Unauthorized
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    pub admin: Signer<'info>,
+
+    #[account(mut)]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        mut,
+        seeds = [REPUTATION_SEED, merchant_pool.load()?.creator.as_ref()],
+        bump = merchant_reputation.bump
+    )]
+    pub merchant_reputation: Account<'info, MerchantReputation>,
+}
+
+#[derive(Accounts)]
+pub struct SyncMerchantReputation<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(mut)]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        seeds = [REGISTRY_SEED, merchant_pool.load()?.creator.as_ref()],
+        bump = merchant_registry.bump
+    )]
+    pub merchant_registry: Account<'info, MerchantRegistry>,
+
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + MerchantReputation::INIT_SPACE,
+        seeds = [REPUTATION_SEED, merchant_pool.load()?.creator.as_ref()],
+        bump
+    )]
+    pub merchant_reputation: Account<'info, MerchantReputation>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetFeeTiers<'info> {
+    #[account(
+        mut,
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        has_one = admin @ ErrorCode::Unauthorized
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    pub admin: Signer<'info>,
+
+    /// CHECK: Only read for being empty; once the program has an upgrade
+    /// guard this change goes through `queue_config_change`
+    #[account(
+        seeds = [UPGRADE_GUARD_SEED],
+        bump,
+        constraint = upgrade_guard.data_is_empty() @ ErrorCode::ConfigChangeTimelocked
+    )]
+    pub upgrade_guard: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetCommissionRateBand<'info> {
+    #[account(
+        mut,
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        has_one = admin @ ErrorCode::Unauthorized
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    pub admin: Signer<'info>,
+
+    /// CHECK: Only read for being empty; once the program has an upgrade
+    /// guard this change goes through `queue_config_change`
+    #[account(
+        seeds = [UPGRADE_GUARD_SEED],
+        bump,
+        constraint = upgrade_guard.data_is_empty() @ ErrorCode::ConfigChangeTimelocked
+    )]
+    pub upgrade_guard: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetTreasury<'info> {
+    #[account(
+        mut,
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        has_one = admin @ ErrorCode::Unauthorized
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    pub admin: Signer<'info>,
+
+    /// CHECK: Only read for being empty; once the program has an upgrade
+    /// guard this change goes through `queue_config_change`
+    #[account(
+        seeds = [UPGRADE_GUARD_SEED],
+        bump,
+        constraint = upgrade_guard.data_is_empty() @ ErrorCode::ConfigChangeTimelocked
+    )]
+    pub upgrade_guard: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct InitUpgradeGuard<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        has_one = admin @ ErrorCode::Unauthorized
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + UpgradeGuard::INIT_SPACE,
+        seeds = [UPGRADE_GUARD_SEED],
+        bump
+    )]
+    pub upgrade_guard: Account<'info, UpgradeGuard>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct QueueConfigChange<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        has_one = admin @ ErrorCode::Unauthorized
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(mut, seeds = [UPGRADE_GUARD_SEED], bump = upgrade_guard.bump)]
+    pub upgrade_guard: Account<'info, UpgradeGuard>,
+
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteConfigChange<'info> {
+    #[account(
+        mut,
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        has_one = admin @ ErrorCode::Unauthorized
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(mut, seeds = [UPGRADE_GUARD_SEED], bump = upgrade_guard.bump)]
+    pub upgrade_guard: Account<'info, UpgradeGuard>,
+
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CancelConfigChange<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        has_one = admin @ ErrorCode::Unauthorized
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(mut, seeds = [UPGRADE_GUARD_SEED], bump = upgrade_guard.bump)]
+    pub upgrade_guard: Account<'info, UpgradeGuard>,
+
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CollectProtocolFees<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        has_one = admin @ ErrorCode::Unauthorized
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    pub admin: Signer<'info>,
+
+    #[account(mut)]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        seeds = [
+            ESCROW_AUTHORITY_SEED,
+            merchant_pool.key().as_ref(),
+            merchant_pool.load()?.escrow_version_seed()
+        ],
+        bump = merchant_pool.load()?.escrow_bump
+    )]
+    pub escrow_authority: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = escrow_usdc.owner == escrow_authority.key() @ ErrorCode::EscrowAuthorityMismatch,
+        constraint = escrow_usdc.mint == usdc_mint.key() @ ErrorCode::EscrowMintMismatch
+    )]
+    pub escrow_usdc: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = treasury_usdc.owner == config.treasury @ ErrorCode::WrongTreasuryAccount,
+        constraint = treasury_usdc.mint == usdc_mint.key() @ ErrorCode::TokenAccountMintMismatch
+    )]
+    pub treasury_usdc: InterfaceAccount<'info, TokenAccount>,
+
+    /// Fees accrue in the pool's primary mint only
+    #[account(
+        constraint = usdc_mint.key() == merchant_pool.load()?.usdc_mint @ ErrorCode::MintMismatch
+    )]
+    pub usdc_mint: InterfaceAccount<'info, Mint>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct SetFeeExempt<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        has_one = admin @ ErrorCode::Unauthorized
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    pub admin: Signer<'info>,
+
+    #[account(mut)]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+}
+
+#[derive(Accounts)]
+pub struct NoticeAbandonedPool<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        has_one = admin @ ErrorCode::Unauthorized
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    pub admin: Signer<'info>,
+
+    #[account(mut)]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+}
+
+#[derive(Accounts)]
+pub struct RecoverAbandonedPool<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        has_one = admin @ ErrorCode::Unauthorized
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    pub admin: Signer<'info>,
+
+    #[account(mut)]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    /// Owned by the pool's recorded merchant wallet
+    #[account(
+        mut,
+        constraint = merchant_usdc.owner == merchant_pool.load()?.merchant @ ErrorCode::Unauthorized,
+        constraint = merchant_usdc.mint == usdc_mint.key() @ ErrorCode::TokenAccountMintMismatch
+    )]
+    pub merchant_usdc: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        seeds = [
+            ESCROW_AUTHORITY_SEED,
+            merchant_pool.key().as_ref(),
+            merchant_pool.load()?.escrow_version_seed()
+        ],
+        bump = merchant_pool.load()?.escrow_bump
+    )]
+    pub escrow_authority: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = escrow_usdc.owner == escrow_authority.key() @ ErrorCode::EscrowAuthorityMismatch,
+        constraint = escrow_usdc.mint == usdc_mint.key() @ ErrorCode::EscrowMintMismatch
+    )]
+    pub escrow_usdc: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        constraint = merchant_pool.load()?.accepts_mint(&usdc_mint.key()) @ ErrorCode::MintMismatch
+    )]
+    pub usdc_mint: InterfaceAccount<'info, Mint>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub memo_program: Program<'info, Memo>,
+}
+
+#[derive(Accounts)]
+pub struct SetGuardians<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    pub merchant: Signer<'info>,
+}
+
+/// Guardians sign as remaining accounts
+#[derive(Accounts)]
+pub struct ProposeMerchantRecovery<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+}
+
+#[derive(Accounts)]
+pub struct VetoMerchantRecovery<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    pub merchant: Signer<'info>,
+}
+
+/// Guardians sign as remaining accounts
+#[derive(Accounts)]
+pub struct RecoverMerchant<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+}
+
+#[derive(Accounts)]
+#[instruction(ref_id: String)]
+pub struct AddAffiliate<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        init,
+        payer = rent_payer,
+        space = 8 + AffiliateAccount::INIT_SPACE,
+        seeds = [
+            AFFILIATE_SEED,
+            merchant_pool.key().as_ref(),
+            affiliate_wallet.key().as_ref()
+        ],
+        bump
+    )]
+    pub affiliate_account: Account<'info, AffiliateAccount>,
+
+    #[account(
+        init,
+        payer = rent_payer,
+        space = 8 + AffiliateEntry::INIT_SPACE,
+        seeds = [
+            AFFILIATE_ENTRY_SEED,
+            merchant_pool.key().as_ref(),
+            &merchant_pool.load()?.affiliate_count.to_le_bytes()
+        ],
+        bump
+    )]
+    pub affiliate_entry: Account<'info, AffiliateEntry>,
+
+    #[account(
+        init,
+        payer = rent_payer,
+        space = 8 + RefIdIndex::INIT_SPACE,
+        seeds = [REF_ID_SEED, merchant_pool.key().as_ref(), ref_id.as_bytes()],
+        bump
+    )]
+    pub ref_id_index: Account<'info, RefIdIndex>,
+
+    /// CHECK: Wallet that receives the affiliate's commissions. Only used as
+    /// the payout ATA's owner, so it may be off-curve (a multisig vault or
+    /// another program's treasury PDA).
+    pub affiliate_wallet: UncheckedAccount<'info>,
+
+    pub merchant: Signer<'info>,
+
+    /// Funds rent for the accounts created here, so the merchant only authorizes
+    #[account(mut)]
+    pub rent_payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    /// Existing affiliate of the pool that referred the new one
+    #[account(
+        constraint = referrer.pool == merchant_pool.key() @ ErrorCode::InvalidAffiliate
+    )]
+    pub referrer: Option<Account<'info, AffiliateAccount>>,
+
+    /// Required while the pool has an approval threshold
+    #[account(
+        init,
+        payer = rent_payer,
+        space = 8 + PendingAffiliate::INIT_SPACE,
+        seeds = [PENDING_AFFILIATE_SEED, affiliate_account.key().as_ref()],
+        bump
+    )]
+    pub pending_affiliate: Option<Account<'info, PendingAffiliate>>,
+}
+
+#[derive(Accounts)]
+pub struct AddAffiliatesBatch<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    pub merchant: Signer<'info>,
+
+    /// Funds rent for the accounts created here, so the merchant only authorizes
+    #[account(mut)]
+    pub rent_payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitAffiliateTree<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    pub merchant: Signer<'info>,
+
+    /// CHECK: Data-less PDA that owns the tree; only signs
+    #[account(seeds = [AFFILIATE_TREE_SEED, merchant_pool.key().as_ref()], bump)]
+    pub tree_authority: UncheckedAccount<'info>,
+
+    /// CHECK: Allocated by the caller for the compression program, which
+    /// checks its owner and size when initializing it
+    #[account(mut)]
+    pub merkle_tree: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Account Compression
+    #[account(address = compression::ACCOUNT_COMPRESSION_ID)]
+    pub compression_program: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Noop
+    #[account(address = compression::NOOP_ID)]
+    pub noop_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AddCompressedAffiliate<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    pub merchant: Signer<'info>,
+
+    /// CHECK: Wallet that receives the affiliate's commissions; may be off-curve
+    pub affiliate_wallet: UncheckedAccount<'info>,
+
+    /// CHECK: Data-less PDA that owns the tree; only signs
+    #[account(seeds = [AFFILIATE_TREE_SEED, merchant_pool.key().as_ref()], bump)]
+    pub tree_authority: UncheckedAccount<'info>,
+
+    /// CHECK: The pool's affiliate tree, validated by the compression program
+    #[account(
+        mut,
+        constraint = Some(merkle_tree.key()) == merchant_pool.load()?.affiliate_tree() @ ErrorCode::AffiliateTreeMismatch
+    )]
+    pub merkle_tree: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Account Compression
+    #[account(address = compression::ACCOUNT_COMPRESSION_ID)]
+    pub compression_program: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Noop
+    #[account(address = compression::NOOP_ID)]
+    pub noop_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(args: CompressedSaleArgs)]
+pub struct ProcessCompressedSale<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        constraint = merchant_pool.load()?.is_processor(&authority.key()) @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    /// CHECK: Data-less PDA that owns the tree; only signs
+    #[account(seeds = [AFFILIATE_TREE_SEED, merchant_pool.key().as_ref()], bump)]
+    pub tree_authority: UncheckedAccount<'info>,
+
+    /// CHECK: The pool's affiliate tree, validated by the compression program
+    #[account(
+        mut,
+        constraint = Some(merkle_tree.key()) == merchant_pool.load()?.affiliate_tree() @ ErrorCode::AffiliateTreeMismatch
+    )]
+    pub merkle_tree: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [
+            ESCROW_AUTHORITY_SEED,
+            merchant_pool.key().as_ref(),
+            merchant_pool.load()?.escrow_version_seed()
+        ],
+        bump = merchant_pool.load()?.escrow_bump
+    )]
+    pub escrow_authority: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = escrow_usdc.owner == escrow_authority.key() @ ErrorCode::EscrowAuthorityMismatch,
+        constraint = escrow_usdc.mint == usdc_mint.key() @ ErrorCode::EscrowMintMismatch
+    )]
+    pub escrow_usdc: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Owned by the compressed affiliate's wallet
+    #[account(
+        mut,
+        constraint = affiliate_usdc.mint == usdc_mint.key() @ ErrorCode::TokenAccountMintMismatch
+    )]
+    pub affiliate_usdc: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Required until the pool has a receipt tree
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + SaleReceipt::INIT_SPACE,
+        seeds = [RECEIPT_SEED, merchant_pool.key().as_ref(), args.order_id.as_bytes()],
+        bump
+    )]
+    pub sale_receipt: Option<Account<'info, SaleReceipt>>,
+
+    #[account(
+        constraint = usdc_mint.key() == merchant_pool.load()?.usdc_mint @ ErrorCode::MintMismatch
+    )]
+    pub usdc_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+    pub memo_program: Program<'info, Memo>,
+
+    /// CHECK: SPL Account Compression
+    #[account(address = compression::ACCOUNT_COMPRESSION_ID)]
+    pub compression_program: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Noop
+    #[account(address = compression::NOOP_ID)]
+    pub noop_program: UncheckedAccount<'info>,
+
+    /// CHECK: Data-less PDA that owns the receipt tree; only signs.
+    /// Required once the pool has a receipt tree
+    #[account(seeds = [RECEIPT_TREE_SEED, merchant_pool.key().as_ref()], bump)]
+    pub receipt_tree_authority: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: The pool's receipt tree, validated by the compression program
+    #[account(
+        mut,
+        constraint = Some(receipt_tree.key()) == merchant_pool.load()?.receipt_tree() @ ErrorCode::ReceiptTreeMismatch
+    )]
+    pub receipt_tree: Option<UncheckedAccount<'info>>,
+}
+
+#[derive(Accounts)]
+pub struct InitReceiptTree<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    pub merchant: Signer<'info>,
+
+    /// CHECK: Data-less PDA that owns the tree; only signs
+    #[account(seeds = [RECEIPT_TREE_SEED, merchant_pool.key().as_ref()], bump)]
+    pub tree_authority: UncheckedAccount<'info>,
+
+    /// CHECK: Allocated by the caller for the compression program, which
+    /// checks its owner and size when initializing it
+    #[account(mut)]
+    pub merkle_tree: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Account Compression
+    #[account(address = compression::ACCOUNT_COMPRESSION_ID)]
+    pub compression_program: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Noop
+    #[account(address = compression::NOOP_ID)]
+    pub noop_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct VerifySaleReceipt<'info> {
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    /// CHECK: The pool's receipt tree, validated by the compression program
+    #[account(
+        constraint = Some(receipt_tree.key()) == merchant_pool.load()?.receipt_tree() @ ErrorCode::ReceiptTreeMismatch
+    )]
+    pub receipt_tree: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Account Compression
+    #[account(address = compression::ACCOUNT_COMPRESSION_ID)]
+    pub compression_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(args: MultiSaleArgs)]
+pub struct ProcessSaleMulti<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        constraint = merchant_pool.load()?.is_processor(&authority.key()) @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        seeds = [
+            ESCROW_AUTHORITY_SEED,
+            merchant_pool.key().as_ref(),
+            merchant_pool.load()?.escrow_version_seed()
+        ],
+        bump = merchant_pool.load()?.escrow_bump
+    )]
+    pub escrow_authority: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = escrow_usdc.owner == escrow_authority.key() @ ErrorCode::EscrowAuthorityMismatch,
+        constraint = escrow_usdc.mint == usdc_mint.key() @ ErrorCode::EscrowMintMismatch
+    )]
+    pub escrow_usdc: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + SaleReceipt::INIT_SPACE,
+        seeds = [RECEIPT_SEED, merchant_pool.key().as_ref(), args.order_id.as_bytes()],
+        bump
+    )]
+    pub sale_receipt: Account<'info, SaleReceipt>,
+
+    /// Split sales settle in the pool's primary mint
+    #[account(
+        constraint = usdc_mint.key() == merchant_pool.load()?.usdc_mint @ ErrorCode::MintMismatch
+    )]
+    pub usdc_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+    pub memo_program: Program<'info, Memo>,
+
+    /// Required once the pool has a leaderboard
+    #[account(
+        mut,
+        seeds = [LEADERBOARD_SEED, merchant_pool.key().as_ref()],
+        bump = leaderboard.load()?.bump
+    )]
+    pub leaderboard: Option<AccountLoader<'info, Leaderboard>>,
+}
+
+/// Both `ProcessSaleArgs` and `CampaignSaleArgs` serialize these fields first
+#[derive(Accounts)]
+#[instruction(sale_amount: u64, order_id: String)]
+pub struct ProcessSale<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        constraint = merchant_pool.load()?.is_processor(&authority.key()) || session_key.is_some() @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        mut,
+        seeds = [
+            AFFILIATE_SEED,
+            merchant_pool.key().as_ref(),
+            affiliate_wallet.key().as_ref()
+        ],
+        bump = affiliate_account.bump,
+        constraint = affiliate_account.pool == merchant_pool.key() @ ErrorCode::InvalidAffiliate
+    )]
+    pub affiliate_account: Account<'info, AffiliateAccount>,
+
+    /// CHECK: Must match the affiliate account's wallet; may be off-curve
+    #[account(
+        constraint = affiliate_wallet.key() == affiliate_account.wallet @ ErrorCode::AffiliateWalletMismatch
+    )]
+    pub affiliate_wallet: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [
+            ESCROW_AUTHORITY_SEED,
+            merchant_pool.key().as_ref(),
+            merchant_pool.load()?.escrow_version_seed()
+        ],
+        bump = merchant_pool.load()?.escrow_bump
+    )]
+    pub escrow_authority: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = escrow_usdc.owner == escrow_authority.key() @ ErrorCode::EscrowAuthorityMismatch,
+        constraint = escrow_usdc.mint == usdc_mint.key() @ ErrorCode::EscrowMintMismatch
+    )]
+    pub escrow_usdc: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        init_if_needed,
+        payer = authority,
+        associated_token::mint = usdc_mint,
+        associated_token::authority = affiliate_wallet,
+        associated_token::token_program = token_program,
+    )]
+    pub affiliate_usdc: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Reused as-is when `order_id` was already processed
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + SaleReceipt::INIT_SPACE,
+        seeds = [RECEIPT_SEED, merchant_pool.key().as_ref(), order_id.as_bytes()],
+        bump
+    )]
+    pub sale_receipt: Account<'info, SaleReceipt>,
+
+    #[account(
+        constraint = merchant_pool.load()?.accepts_mint(&usdc_mint.key()) @ ErrorCode::MintMismatch
+    )]
+    pub usdc_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    /// The pool's processor or merchant, or a session key passing `session_key`
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+    pub memo_program: Program<'info, Memo>,
+
+    /// Required once the pool has a leaderboard
+    #[account(
+        mut,
+        seeds = [LEADERBOARD_SEED, merchant_pool.key().as_ref()],
+        bump = leaderboard.load()?.bump
+    )]
+    pub leaderboard: Option<AccountLoader<'info, Leaderboard>>,
+
+    /// Required while the affiliate has a manager
+    #[account(
+        mut,
+        constraint = Some(manager_usdc.owner) == affiliate_account.manager @ ErrorCode::ManagerMismatch,
+        constraint = manager_usdc.mint == usdc_mint.key() @ ErrorCode::TokenAccountMintMismatch
+    )]
+    pub manager_usdc: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    /// Required for the referred affiliate's first sale while a bounty is set
+    #[account(
+        mut,
+        constraint = Some(referrer_usdc.owner) == affiliate_account.referred_by @ ErrorCode::ReferrerMismatch,
+        constraint = referrer_usdc.mint == usdc_mint.key() @ ErrorCode::TokenAccountMintMismatch
+    )]
+    pub referrer_usdc: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    /// Required in escrowless mode
+    #[account(
+        mut,
+        constraint = Some(revenue_usdc.key()) == merchant_pool.load()?.revenue_account() @ ErrorCode::RevenueAccountMismatch
+    )]
+    pub revenue_usdc: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    /// Required while the affiliate or its group has a payout account
+    #[account(
+        mut,
+        constraint = Some(payout_usdc.key()) == payout_account_for(&affiliate_account, affiliate_group.as_deref()) @ ErrorCode::PayoutAccountMismatch
+    )]
+    pub payout_usdc: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    /// Passed when the sale was attributed by looking up the affiliate's
+    /// ref_id, counting a redemption of it
+    #[account(
+        mut,
+        constraint = ref_id_index.affiliate == affiliate_account.key() @ ErrorCode::InvalidAffiliate
+    )]
+    pub ref_id_index: Option<Account<'info, RefIdIndex>>,
+
+    /// Passed when `authority` is a session key, which then stands in for
+    /// the processor within its limits
+    #[account(
+        mut,
+        seeds = [SESSION_KEY_SEED, merchant_pool.key().as_ref(), authority.key().as_ref()],
+        bump = session_key.bump
+    )]
+    pub session_key: Option<Account<'info, SessionKey>>,
+
+    /// Required while the affiliate is in a group
+    #[account(
+        mut,
+        constraint = Some(affiliate_group.key()) == affiliate_account.group @ ErrorCode::AffiliateGroupMismatch
+    )]
+    pub affiliate_group: Option<Account<'info, AffiliateGroup>>,
+
+    /// CHECK: The instructions sysvar; required while the pool guards sales
+    #[account(address = instructions_sysvar::ID)]
+    pub instructions: Option<UncheckedAccount<'info>>,
+}
+
+#[derive(Accounts)]
+pub struct ProcessSaleForCampaign<'info> {
+    pub sale: ProcessSale<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            CAMPAIGN_SEED,
+            sale.merchant_pool.key().as_ref(),
+            campaign.campaign_id.as_bytes()
+        ],
+        bump = campaign.bump
+    )]
+    pub campaign: Account<'info, Campaign>,
+}
+
+#[derive(Accounts)]
+pub struct ProcessSaleInRegion<'info> {
+    pub sale: ProcessSale<'info>,
+
+    #[account(
+        mut,
+        seeds = [REGION_SEED, sale.merchant_pool.key().as_ref(), region.region.as_ref()],
+        bump = region.bump
+    )]
+    pub region: Account<'info, RegionPartition>,
+}
+
+#[derive(Accounts)]
+pub struct ProcessSaleWithPaymentProof<'info> {
+    pub sale: ProcessSale<'info>,
+
+    /// The merchant's token account the buyer paid into
+    #[account(
+        constraint = merchant_usdc.mint == sale.usdc_mint.key() @ ErrorCode::MintMismatch,
+        constraint = merchant_usdc.owner == sale.merchant_pool.load()?.merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_usdc: InterfaceAccount<'info, TokenAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(region_code: [u8; 2])]
+pub struct CreateRegion<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        init,
+        payer = rent_payer,
+        space = 8 + RegionPartition::INIT_SPACE,
+        seeds = [REGION_SEED, merchant_pool.key().as_ref(), region_code.as_ref()],
+        bump
+    )]
+    pub region: Account<'info, RegionPartition>,
+
+    pub merchant: Signer<'info>,
+
+    /// Funds rent for the accounts created here, so the merchant only authorizes
+    #[account(mut)]
+    pub rent_payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateRegion<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        mut,
+        seeds = [REGION_SEED, merchant_pool.key().as_ref(), region.region.as_ref()],
+        bump = region.bump
+    )]
+    pub region: Account<'info, RegionPartition>,
+
+    pub merchant: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(args: CreateCampaignArgs)]
+pub struct CreateCampaign<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        init,
+        payer = rent_payer,
+        space = 8 + Campaign::INIT_SPACE,
+        seeds = [CAMPAIGN_SEED, merchant_pool.key().as_ref(), args.campaign_id.as_bytes()],
+        bump
+    )]
+    pub campaign: Account<'info, Campaign>,
+
+    pub merchant: Signer<'info>,
+
+    /// Funds rent for the accounts created here, so the merchant only authorizes
+    #[account(mut)]
+    pub rent_payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(group_id: String)]
+pub struct CreateAffiliateGroup<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        init,
+        payer = rent_payer,
+        space = 8 + AffiliateGroup::INIT_SPACE,
+        seeds = [GROUP_SEED, merchant_pool.key().as_ref(), group_id.as_bytes()],
+        bump
+    )]
+    pub affiliate_group: Account<'info, AffiliateGroup>,
+
+    pub merchant: Signer<'info>,
+
+    /// Funds rent for the accounts created here, so the merchant only authorizes
+    #[account(mut)]
+    pub rent_payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateAffiliateGroup<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        mut,
+        seeds = [
+            GROUP_SEED,
+            merchant_pool.key().as_ref(),
+            affiliate_group.group_id.as_bytes()
+        ],
+        bump = affiliate_group.bump
+    )]
+    pub affiliate_group: Account<'info, AffiliateGroup>,
+
+    pub merchant: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetGroupPayoutAccount<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        mut,
+        seeds = [
+            GROUP_SEED,
+            merchant_pool.key().as_ref(),
+            affiliate_group.group_id.as_bytes()
+        ],
+        bump = affiliate_group.bump
+    )]
+    pub affiliate_group: Account<'info, AffiliateGroup>,
+
+    pub merchant: Signer<'info>,
+
+    #[account(
+        seeds = [
+            ESCROW_AUTHORITY_SEED,
+            merchant_pool.key().as_ref(),
+            merchant_pool.load()?.escrow_version_seed()
+        ],
+        bump = merchant_pool.load()?.escrow_bump
+    )]
+    pub escrow_authority: SystemAccount<'info>,
+
+    /// Omit to pay members directly again
+    #[account(
+        constraint = payout_usdc.mint == merchant_pool.load()?.usdc_mint @ ErrorCode::MintMismatch,
+        constraint = payout_usdc.owner != escrow_authority.key() @ ErrorCode::InvalidPayoutAccount
+    )]
+    pub payout_usdc: Option<InterfaceAccount<'info, TokenAccount>>,
+}
+
+#[derive(Accounts)]
+pub struct AssignAffiliateGroup<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        mut,
+        constraint = affiliate_account.pool == merchant_pool.key() @ ErrorCode::InvalidAffiliate
+    )]
+    pub affiliate_account: Account<'info, AffiliateAccount>,
+
+    #[account(
+        mut,
+        seeds = [
+            GROUP_SEED,
+            merchant_pool.key().as_ref(),
+            affiliate_group.group_id.as_bytes()
+        ],
+        bump = affiliate_group.bump
+    )]
+    pub affiliate_group: Account<'info, AffiliateGroup>,
+
+    pub merchant: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct EndCampaign<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        mut,
+        seeds = [
+            CAMPAIGN_SEED,
+            merchant_pool.key().as_ref(),
+            campaign.campaign_id.as_bytes()
+        ],
+        bump = campaign.bump
+    )]
+    pub campaign: Account<'info, Campaign>,
+
+    pub merchant: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct QuoteCommission<'info> {
+    /// Fee tiers the quote's protocol fee is charged from
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        constraint = affiliate_account.pool == merchant_pool.key() @ ErrorCode::InvalidAffiliate
+    )]
+    pub affiliate_account: Account<'info, AffiliateAccount>,
+
+    /// The pool's primary mint; pass it for interest-bearing mints so the
+    /// quote converts `sale_amount` like a sale would
+    #[account(
+        constraint = usdc_mint.key() == merchant_pool.load()?.usdc_mint @ ErrorCode::MintMismatch
+    )]
+    pub usdc_mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    /// Required while the affiliate is in a group, whose modifier the
+    /// quote applies
+    #[account(
+        constraint = Some(affiliate_group.key()) == affiliate_account.group @ ErrorCode::AffiliateGroupMismatch
+    )]
+    pub affiliate_group: Option<Account<'info, AffiliateGroup>>,
+}
+
+#[derive(Accounts)]
+pub struct SyncInterest<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(mut)]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        seeds = [
+            ESCROW_AUTHORITY_SEED,
+            merchant_pool.key().as_ref(),
+            merchant_pool.load()?.escrow_version_seed()
+        ],
+        bump = merchant_pool.load()?.escrow_bump
+    )]
+    pub escrow_authority: SystemAccount<'info>,
+
+    /// The primary mint's escrow
+    #[account(
+        constraint = escrow_usdc.owner == escrow_authority.key() @ ErrorCode::EscrowAuthorityMismatch,
+        constraint = escrow_usdc.mint == usdc_mint.key() @ ErrorCode::EscrowMintMismatch
+    )]
+    pub escrow_usdc: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        constraint = usdc_mint.key() == merchant_pool.load()?.usdc_mint @ ErrorCode::MintMismatch
+    )]
+    pub usdc_mint: Box<InterfaceAccount<'info, Mint>>,
+}
+
+#[derive(Accounts)]
+pub struct PingPool<'info> {
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        seeds = [
+            ESCROW_AUTHORITY_SEED,
+            merchant_pool.key().as_ref(),
+            merchant_pool.load()?.escrow_version_seed()
+        ],
+        bump = merchant_pool.load()?.escrow_bump
+    )]
+    pub escrow_authority: SystemAccount<'info>,
+
+    /// The primary mint's escrow
+    #[account(
+        constraint = escrow_usdc.owner == escrow_authority.key() @ ErrorCode::EscrowAuthorityMismatch,
+        constraint = escrow_usdc.mint == merchant_pool.load()?.usdc_mint @ ErrorCode::EscrowMintMismatch
+    )]
+    pub escrow_usdc: Box<InterfaceAccount<'info, TokenAccount>>,
+}
+
+#[derive(Accounts)]
+#[instruction(order_id: String)]
+pub struct RefundPartial<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        mut,
+        seeds = [RECEIPT_SEED, merchant_pool.key().as_ref(), order_id.as_bytes()],
+        bump = sale_receipt.bump
+    )]
+    pub sale_receipt: Account<'info, SaleReceipt>,
+
+    #[account(
+        mut,
+        constraint = affiliate_account.key() == sale_receipt.affiliate @ ErrorCode::InvalidAffiliate
+    )]
+    pub affiliate_account: Account<'info, AffiliateAccount>,
+
+    pub merchant: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(new_ref_id: String)]
+pub struct UpdateAffiliateRefId<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        mut,
+        seeds = [
+            AFFILIATE_SEED,
+            merchant_pool.key().as_ref(),
+            affiliate_account.wallet.as_ref()
+        ],
+        bump = affiliate_account.bump
+    )]
+    pub affiliate_account: Account<'info, AffiliateAccount>,
+
+    #[account(
+        mut,
+        close = rent_payer,
+        seeds = [
+            REF_ID_SEED,
+            merchant_pool.key().as_ref(),
+            affiliate_account.ref_id.as_bytes()
+        ],
+        bump = old_ref_id_index.bump,
+        constraint = old_ref_id_index.affiliate == affiliate_account.key() @ ErrorCode::InvalidAffiliate
+    )]
+    pub old_ref_id_index: Account<'info, RefIdIndex>,
+
+    #[account(
+        init,
+        payer = rent_payer,
+        space = 8 + RefIdIndex::INIT_SPACE,
+        seeds = [REF_ID_SEED, merchant_pool.key().as_ref(), new_ref_id.as_bytes()],
+        bump
+    )]
+    pub new_ref_id_index: Account<'info, RefIdIndex>,
+
+    pub merchant: Signer<'info>,
+
+    /// Funds rent for the accounts created here, so the merchant only authorizes
+    #[account(mut)]
+    pub rent_payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ActivateSelf<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        mut,
+        seeds = [
+            AFFILIATE_SEED,
+            merchant_pool.key().as_ref(),
+            affiliate_wallet.key().as_ref()
+        ],
+        bump = affiliate_account.bump
+    )]
+    pub affiliate_account: Account<'info, AffiliateAccount>,
+
+    pub affiliate_wallet: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetCompressedPayouts<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        mut,
+        seeds = [
+            AFFILIATE_SEED,
+            merchant_pool.key().as_ref(),
+            affiliate_wallet.key().as_ref()
+        ],
+        bump = affiliate_account.bump
+    )]
+    pub affiliate_account: Account<'info, AffiliateAccount>,
+
+    pub affiliate_wallet: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetConfidentialPayoutKey<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        mut,
+        seeds = [
+            AFFILIATE_SEED,
+            merchant_pool.key().as_ref(),
+            affiliate_wallet.key().as_ref()
+        ],
+        bump = affiliate_account.bump
+    )]
+    pub affiliate_account: Account<'info, AffiliateAccount>,
+
+    pub affiliate_wallet: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptTerms<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        mut,
+        seeds = [
+            AFFILIATE_SEED,
+            merchant_pool.key().as_ref(),
+            affiliate_wallet.key().as_ref()
+        ],
+        bump = affiliate_account.bump
+    )]
+    pub affiliate_account: Account<'info, AffiliateAccount>,
+
+    pub affiliate_wallet: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetPayoutThreshold<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        mut,
+        constraint = affiliate_account.pool == merchant_pool.key() @ ErrorCode::InvalidAffiliate
+    )]
+    pub affiliate_account: Account<'info, AffiliateAccount>,
+
+    /// The affiliate wallet or the pool's merchant
+    #[account(
+        constraint = authority.key() == affiliate_account.wallet
+            || authority.key() == merchant_pool.load()?.merchant @ ErrorCode::Unauthorized
+    )]
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(period: u32)]
+pub struct SnapshotAffiliate<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        constraint = affiliate_account.pool == merchant_pool.key() @ ErrorCode::InvalidAffiliate
+    )]
+    pub affiliate_account: Account<'info, AffiliateAccount>,
+
+    /// `init` rather than `init_if_needed`: a period's snapshot is written once
+    #[account(
+        init,
+        payer = rent_payer,
+        space = 8 + AffiliateSnapshot::INIT_SPACE,
+        seeds = [
+            AFFILIATE_SNAPSHOT_SEED,
+            affiliate_account.key().as_ref(),
+            &period.to_le_bytes()
+        ],
+        bump
+    )]
+    pub snapshot: Account<'info, AffiliateSnapshot>,
+
+    /// The pool's merchant or processor
+    #[account(
+        constraint = merchant_pool.load()?.is_processor(&authority.key()) @ ErrorCode::Unauthorized
+    )]
+    pub authority: Signer<'info>,
+
+    #[account(mut)]
+    pub rent_payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(period: u32)]
+pub struct GenerateStatement<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        mut,
+        constraint = affiliate_account.pool == merchant_pool.key() @ ErrorCode::InvalidAffiliate
+    )]
+    pub affiliate_account: Account<'info, AffiliateAccount>,
+
+    #[account(
+        init,
+        payer = rent_payer,
+        space = 8 + Statement::INIT_SPACE,
+        seeds = [
+            STATEMENT_SEED,
+            affiliate_account.key().as_ref(),
+            &period.to_le_bytes()
+        ],
+        bump
+    )]
+    pub statement: Account<'info, Statement>,
+
+    /// The pool's merchant or processor
+    #[account(
+        constraint = merchant_pool.load()?.is_processor(&authority.key()) @ ErrorCode::Unauthorized
+    )]
+    pub authority: Signer<'info>,
+
+    #[account(mut)]
+    pub rent_payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetManager<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        mut,
+        seeds = [
+            AFFILIATE_SEED,
+            merchant_pool.key().as_ref(),
+            affiliate_wallet.key().as_ref()
+        ],
+        bump = affiliate_account.bump
+    )]
+    pub affiliate_account: Account<'info, AffiliateAccount>,
+
+    pub affiliate_wallet: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetPayoutAccount<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        mut,
+        seeds = [
+            AFFILIATE_SEED,
+            merchant_pool.key().as_ref(),
+            affiliate_wallet.key().as_ref()
+        ],
+        bump = affiliate_account.bump
+    )]
+    pub affiliate_account: Account<'info, AffiliateAccount>,
+
+    pub affiliate_wallet: Signer<'info>,
+
+    #[account(
+        seeds = [
+            ESCROW_AUTHORITY_SEED,
+            merchant_pool.key().as_ref(),
+            merchant_pool.load()?.escrow_version_seed()
+        ],
+        bump = merchant_pool.load()?.escrow_bump
+    )]
+    pub escrow_authority: SystemAccount<'info>,
+
+    /// Omit to pay the wallet's ATA again
+    #[account(
+        constraint = payout_usdc.mint == merchant_pool.load()?.usdc_mint @ ErrorCode::MintMismatch,
+        constraint = payout_usdc.owner != escrow_authority.key() @ ErrorCode::InvalidPayoutAccount
+    )]
+    pub payout_usdc: Option<InterfaceAccount<'info, TokenAccount>>,
+}
+
+#[derive(Accounts)]
+pub struct SetPayoutSplits<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        mut,
+        seeds = [
+            AFFILIATE_SEED,
+            merchant_pool.key().as_ref(),
+            affiliate_wallet.key().as_ref()
+        ],
+        bump = affiliate_account.bump
+    )]
+    pub affiliate_account: Account<'info, AffiliateAccount>,
+
+    pub affiliate_wallet: Signer<'info>,
+
+    #[account(
+        seeds = [
+            ESCROW_AUTHORITY_SEED,
+            merchant_pool.key().as_ref(),
+            merchant_pool.load()?.escrow_version_seed()
+        ],
+        bump = merchant_pool.load()?.escrow_bump
+    )]
+    pub escrow_authority: SystemAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimCommissions<'info> {
+    #[account(mut)]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        mut,
+        seeds = [
+            AFFILIATE_SEED,
+            merchant_pool.key().as_ref(),
+            affiliate_wallet.key().as_ref()
+        ],
+        bump = affiliate_account.bump
+    )]
+    pub affiliate_account: Account<'info, AffiliateAccount>,
+
+    /// CHECK: Bound to the affiliate account through its PDA seeds; may be off-curve
+    pub affiliate_wallet: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [
+            ESCROW_AUTHORITY_SEED,
+            merchant_pool.key().as_ref(),
+            merchant_pool.load()?.escrow_version_seed()
+        ],
+        bump = merchant_pool.load()?.escrow_bump
+    )]
+    pub escrow_authority: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = escrow_usdc.owner == escrow_authority.key() @ ErrorCode::EscrowAuthorityMismatch,
+        constraint = escrow_usdc.mint == usdc_mint.key() @ ErrorCode::EscrowMintMismatch
+    )]
+    pub escrow_usdc: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = authority,
+        associated_token::mint = usdc_mint,
+        associated_token::authority = affiliate_wallet,
+        associated_token::token_program = token_program,
+    )]
+    pub affiliate_usdc: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        constraint = usdc_mint.key() == merchant_pool.load()?.usdc_mint @ ErrorCode::MintMismatch
+    )]
+    pub usdc_mint: InterfaceAccount<'info, Mint>,
+
+    /// The affiliate wallet, or the pool's merchant forcing settlement
+    #[account(
+        mut,
+        constraint = authority.key() == affiliate_account.wallet
+            || authority.key() == merchant_pool.load()?.merchant @ ErrorCode::Unauthorized
+    )]
+    pub authority: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+    pub memo_program: Program<'info, Memo>,
+
+    /// Required while the affiliate or its group has a payout account
+    #[account(
+        mut,
+        constraint = Some(payout_usdc.key()) == payout_account_for(&affiliate_account, affiliate_group.as_deref()) @ ErrorCode::PayoutAccountMismatch
+    )]
+    pub payout_usdc: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Required while the affiliate is in a group
+    #[account(
+        constraint = Some(affiliate_group.key()) == affiliate_account.group @ ErrorCode::AffiliateGroupMismatch
+    )]
+    pub affiliate_group: Option<Account<'info, AffiliateGroup>>,
+}
+
+#[derive(Accounts)]
+pub struct PayOutCompressed<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        constraint = merchant_pool.load()?.is_processor(&authority.key()) @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        seeds = [
+            ESCROW_AUTHORITY_SEED,
+            merchant_pool.key().as_ref(),
+            merchant_pool.load()?.escrow_version_seed()
+        ],
+        bump = merchant_pool.load()?.escrow_bump
+    )]
+    pub escrow_authority: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = escrow_usdc.owner == escrow_authority.key() @ ErrorCode::EscrowAuthorityMismatch,
+        constraint = escrow_usdc.mint == merchant_pool.load()?.usdc_mint @ ErrorCode::EscrowMintMismatch
+    )]
+    pub escrow_usdc: InterfaceAccount<'info, TokenAccount>,
+
+    /// The pool's processor or merchant
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    /// CHECK: Light compressed-token program
+    #[account(address = compressed_token::COMPRESSED_TOKEN_ID)]
+    pub compressed_token_program: UncheckedAccount<'info>,
+
+    /// CHECK: Validated by the compressed-token program
+    pub light_cpi_authority: UncheckedAccount<'info>,
+
+    /// CHECK: Light system program
+    #[account(address = compressed_token::LIGHT_SYSTEM_ID)]
+    pub light_system_program: UncheckedAccount<'info>,
+
+    /// CHECK: Validated by Light account compression
+    pub registered_program: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Noop
+    #[account(address = compression::NOOP_ID)]
+    pub noop_program: UncheckedAccount<'info>,
+
+    /// CHECK: Validated by the Light system program
+    pub account_compression_authority: UncheckedAccount<'info>,
+
+    /// CHECK: Light account compression
+    #[account(address = compressed_token::LIGHT_ACCOUNT_COMPRESSION_ID)]
+    pub account_compression_program: UncheckedAccount<'info>,
+
+    /// CHECK: The primary mint's compressed-token pool, validated by the
+    /// compressed-token program
+    #[account(mut)]
+    pub token_pool: UncheckedAccount<'info>,
+
+    /// CHECK: Light state tree the payouts are appended to, validated by
+    /// Light account compression
+    #[account(mut)]
+    pub output_state_tree: UncheckedAccount<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct PayOutConfidential<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        constraint = merchant_pool.load()?.is_processor(&authority.key()) @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        mut,
+        constraint = affiliate_account.pool == merchant_pool.key() @ ErrorCode::InvalidAffiliate
+    )]
+    pub affiliate_account: Account<'info, AffiliateAccount>,
+
+    /// The affiliate wallet's token account configured with its ElGamal key
+    #[account(
+        mut,
+        constraint = affiliate_usdc.owner == affiliate_account.wallet @ ErrorCode::ConfidentialDestinationMismatch,
+        constraint = affiliate_usdc.mint == merchant_pool.load()?.usdc_mint @ ErrorCode::ConfidentialDestinationMismatch
+    )]
+    pub affiliate_usdc: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        seeds = [
+            ESCROW_AUTHORITY_SEED,
+            merchant_pool.key().as_ref(),
+            merchant_pool.load()?.escrow_version_seed()
+        ],
+        bump = merchant_pool.load()?.escrow_bump
+    )]
+    pub escrow_authority: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = escrow_usdc.owner == escrow_authority.key() @ ErrorCode::EscrowAuthorityMismatch,
+        constraint = escrow_usdc.mint == merchant_pool.load()?.usdc_mint @ ErrorCode::EscrowMintMismatch
+    )]
+    pub escrow_usdc: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        constraint = usdc_mint.key() == merchant_pool.load()?.usdc_mint @ ErrorCode::MintMismatch
+    )]
+    pub usdc_mint: InterfaceAccount<'info, Mint>,
+
+    /// CHECK: Context state account of the transfer's equality proof,
+    /// validated by Token-2022
+    pub equality_proof: UncheckedAccount<'info>,
+
+    /// CHECK: Context state account of the transfer's ciphertext validity
+    /// proof, validated by Token-2022
+    pub ciphertext_validity_proof: UncheckedAccount<'info>,
+
+    /// CHECK: Context state account of the transfer's range proof,
+    /// validated by Token-2022
+    pub range_proof: UncheckedAccount<'info>,
+
+    /// The pool's processor or merchant
+    pub authority: Signer<'info>,
+
+    pub token_program: Program<'info, Token2022>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimCommissionsRelayed<'info> {
+    #[account(mut)]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        mut,
+        seeds = [
+            AFFILIATE_SEED,
+            merchant_pool.key().as_ref(),
+            affiliate_wallet.key().as_ref()
+        ],
+        bump = affiliate_account.bump
+    )]
+    pub affiliate_account: Account<'info, AffiliateAccount>,
+
+    /// The affiliate's signature authorizes the claim and the relayer fee
+    pub affiliate_wallet: Signer<'info>,
+
+    #[account(
+        seeds = [
+            ESCROW_AUTHORITY_SEED,
+            merchant_pool.key().as_ref(),
+            merchant_pool.load()?.escrow_version_seed()
+        ],
+        bump = merchant_pool.load()?.escrow_bump
+    )]
+    pub escrow_authority: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = escrow_usdc.owner == escrow_authority.key() @ ErrorCode::EscrowAuthorityMismatch,
+        constraint = escrow_usdc.mint == usdc_mint.key() @ ErrorCode::EscrowMintMismatch
+    )]
+    pub escrow_usdc: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = relayer,
+        associated_token::mint = usdc_mint,
+        associated_token::authority = affiliate_wallet,
+        associated_token::token_program = token_program,
+    )]
+    pub affiliate_usdc: InterfaceAccount<'info, TokenAccount>,
+
+    /// Receives the relayer fee
+    #[account(
+        mut,
+        constraint = relayer_usdc.mint == usdc_mint.key() @ ErrorCode::TokenAccountMintMismatch
+    )]
+    pub relayer_usdc: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        constraint = usdc_mint.key() == merchant_pool.load()?.usdc_mint @ ErrorCode::MintMismatch
+    )]
+    pub usdc_mint: InterfaceAccount<'info, Mint>,
+
+    /// Pays the network fees and any rent in exchange for the relayer fee
+    #[account(mut)]
+    pub relayer: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+    pub memo_program: Program<'info, Memo>,
+
+    /// Required while the affiliate or its group has a payout account
+    #[account(
+        mut,
+        constraint = Some(payout_usdc.key()) == payout_account_for(&affiliate_account, affiliate_group.as_deref()) @ ErrorCode::PayoutAccountMismatch
+    )]
+    pub payout_usdc: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Required while the affiliate is in a group
+    #[account(
+        constraint = Some(affiliate_group.key()) == affiliate_account.group @ ErrorCode::AffiliateGroupMismatch
+    )]
+    pub affiliate_group: Option<Account<'info, AffiliateGroup>>,
+}
+
+#[derive(Accounts)]
+pub struct SweepExpired<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(mut)]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        mut,
+        constraint = affiliate_account.pool == merchant_pool.key() @ ErrorCode::InvalidAffiliate
+    )]
+    pub affiliate_account: Account<'info, AffiliateAccount>,
+}
+
+#[derive(Accounts)]
+pub struct DeactivateStaleAffiliate<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        mut,
+        constraint = affiliate_account.pool == merchant_pool.key() @ ErrorCode::InvalidAffiliate
+    )]
+    pub affiliate_account: Account<'info, AffiliateAccount>,
+}
+
+#[derive(Accounts)]
+pub struct RemoveAffiliate<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        mut,
+        seeds = [
+            AFFILIATE_SEED,
+            merchant_pool.key().as_ref(),
+            affiliate_wallet.key().as_ref()
+        ],
+        bump = affiliate_account.bump
+    )]
+    pub affiliate_account: Account<'info, AffiliateAccount>,
+
+    /// CHECK: Must match the affiliate account's wallet; may be off-curve
+    #[account(
+        constraint = affiliate_wallet.key() == affiliate_account.wallet @ ErrorCode::AffiliateWalletMismatch
+    )]
+    pub affiliate_wallet: UncheckedAccount<'info>,
+
+    pub merchant: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetAffiliateTags<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        mut,
+        seeds = [
+            AFFILIATE_SEED,
+            merchant_pool.key().as_ref(),
+            affiliate_wallet.key().as_ref()
+        ],
+        bump = affiliate_account.bump
+    )]
+    pub affiliate_account: Account<'info, AffiliateAccount>,
+
+    /// CHECK: Must match the affiliate account's wallet; may be off-curve
+    #[account(
+        constraint = affiliate_wallet.key() == affiliate_account.wallet @ ErrorCode::AffiliateWalletMismatch
+    )]
+    pub affiliate_wallet: UncheckedAccount<'info>,
+
+    pub merchant: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct LeavePool<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        mut,
+        seeds = [
+            AFFILIATE_SEED,
+            merchant_pool.key().as_ref(),
+            affiliate_wallet.key().as_ref()
+        ],
+        bump = affiliate_account.bump
+    )]
+    pub affiliate_account: Account<'info, AffiliateAccount>,
+
+    pub affiliate_wallet: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            REF_ID_SEED,
+            merchant_pool.key().as_ref(),
+            affiliate_account.ref_id.as_bytes()
+        ],
+        bump = ref_id_index.bump,
+        constraint = ref_id_index.affiliate == affiliate_account.key() @ ErrorCode::InvalidAffiliate
+    )]
+    pub ref_id_index: Account<'info, RefIdIndex>,
+
+    /// CHECK: Receives the rent of closed accounts; must be the pool's merchant
+    #[account(
+        mut,
+        constraint = merchant.key() == merchant_pool.load()?.merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct DepositEscrow<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    pub merchant: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = merchant_usdc.owner == merchant.key() @ ErrorCode::WrongMerchantTokenAccount,
+        constraint = merchant_usdc.mint == usdc_mint.key() @ ErrorCode::TokenAccountMintMismatch
+    )]
+    pub merchant_usdc: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        seeds = [
+            ESCROW_AUTHORITY_SEED,
+            merchant_pool.key().as_ref(),
+            merchant_pool.load()?.escrow_version_seed()
+        ],
+        bump = merchant_pool.load()?.escrow_bump
+    )]
+    pub escrow_authority: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = escrow_usdc.owner == escrow_authority.key() @ ErrorCode::EscrowAuthorityMismatch,
+        constraint = escrow_usdc.mint == usdc_mint.key() @ ErrorCode::EscrowMintMismatch
+    )]
+    pub escrow_usdc: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        constraint = merchant_pool.load()?.accepts_mint(&usdc_mint.key()) @ ErrorCode::MintMismatch
+    )]
+    pub usdc_mint: InterfaceAccount<'info, Mint>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct DepositEscrowDelegated<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    /// Holder of the merchant's SPL `approve` allowance
+    pub delegate: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = merchant_usdc.owner == merchant_pool.load()?.merchant @ ErrorCode::Unauthorized,
+        constraint = merchant_usdc.delegate == COption::Some(delegate.key()) @ ErrorCode::DelegateMismatch,
+        constraint = merchant_usdc.mint == usdc_mint.key() @ ErrorCode::TokenAccountMintMismatch
+    )]
+    pub merchant_usdc: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        seeds = [
+            ESCROW_AUTHORITY_SEED,
+            merchant_pool.key().as_ref(),
+            merchant_pool.load()?.escrow_version_seed()
+        ],
+        bump = merchant_pool.load()?.escrow_bump
+    )]
+    pub escrow_authority: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = escrow_usdc.owner == escrow_authority.key() @ ErrorCode::EscrowAuthorityMismatch,
+        constraint = escrow_usdc.mint == usdc_mint.key() @ ErrorCode::EscrowMintMismatch
+    )]
+    pub escrow_usdc: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        constraint = merchant_pool.load()?.accepts_mint(&usdc_mint.key()) @ ErrorCode::MintMismatch
+    )]
+    pub usdc_mint: InterfaceAccount<'info, Mint>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct DonateToEscrow<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    pub donor: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = donor_usdc.mint == usdc_mint.key() @ ErrorCode::TokenAccountMintMismatch
+    )]
+    pub donor_usdc: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        seeds = [
+            ESCROW_AUTHORITY_SEED,
+            merchant_pool.key().as_ref(),
+            merchant_pool.load()?.escrow_version_seed()
+        ],
+        bump = merchant_pool.load()?.escrow_bump
+    )]
+    pub escrow_authority: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = escrow_usdc.owner == escrow_authority.key() @ ErrorCode::EscrowAuthorityMismatch,
+        constraint = escrow_usdc.mint == usdc_mint.key() @ ErrorCode::EscrowMintMismatch
+    )]
+    pub escrow_usdc: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        constraint = merchant_pool.load()?.accepts_mint(&usdc_mint.key()) @ ErrorCode::MintMismatch
+    )]
+    pub usdc_mint: InterfaceAccount<'info, Mint>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawEscrow<'info> {
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    pub merchant: Signer<'info>,
+
+    /// Owned by the pool's `withdrawal_destination` once one is set
+    #[account(
+        mut,
+        constraint = merchant_usdc.owner
+            == merchant_pool.load()?.withdrawal_destination().unwrap_or(merchant.key())
+            @ ErrorCode::WithdrawalDestinationMismatch,
+        constraint = merchant_usdc.mint == usdc_mint.key() @ ErrorCode::TokenAccountMintMismatch
+    )]
+    pub merchant_usdc: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        seeds = [
+            ESCROW_AUTHORITY_SEED,
+            merchant_pool.key().as_ref(),
+            merchant_pool.load()?.escrow_version_seed()
+        ],
+        bump = merchant_pool.load()?.escrow_bump
+    )]
+    pub escrow_authority: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = escrow_usdc.owner == escrow_authority.key() @ ErrorCode::EscrowAuthorityMismatch,
+        constraint = escrow_usdc.mint == usdc_mint.key() @ ErrorCode::EscrowMintMismatch
+    )]
+    pub escrow_usdc: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        constraint = merchant_pool.load()?.accepts_mint(&usdc_mint.key()) @ ErrorCode::MintMismatch
+    )]
+    pub usdc_mint: InterfaceAccount<'info, Mint>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub memo_program: Program<'info, Memo>,
+
+    /// The pool's `withdrawal_cosigner`, required above `cosign_threshold`
+    pub cosigner: Option<Signer<'info>>,
+}
+
+#[derive(Accounts)]
+pub struct TransferEscrow<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            from_pool.load()?.creator.as_ref(),
+            from_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = from_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub from_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        seeds = [
+            POOL_SEED,
+            to_pool.load()?.creator.as_ref(),
+            to_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = to_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub to_pool: AccountLoader<'info, MerchantPool>,
+
+    pub merchant: Signer<'info>,
+
+    #[account(
+        seeds = [
+            ESCROW_AUTHORITY_SEED,
+            from_pool.key().as_ref(),
+            from_pool.load()?.escrow_version_seed()
+        ],
+        bump = from_pool.load()?.escrow_bump
+    )]
+    pub from_escrow_authority: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = from_escrow.owner == from_escrow_authority.key() @ ErrorCode::EscrowAuthorityMismatch,
+        constraint = from_escrow.mint == mint.key() @ ErrorCode::EscrowMintMismatch
+    )]
+    pub from_escrow: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        seeds = [
+            ESCROW_AUTHORITY_SEED,
+            to_pool.key().as_ref(),
+            to_pool.load()?.escrow_version_seed()
+        ],
+        bump = to_pool.load()?.escrow_bump
+    )]
+    pub to_escrow_authority: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = to_escrow.owner == to_escrow_authority.key() @ ErrorCode::EscrowAuthorityMismatch,
+        constraint = to_escrow.mint == mint.key() @ ErrorCode::EscrowMintMismatch
+    )]
+    pub to_escrow: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        constraint = from_pool.load()?.accepts_mint(&mint.key()) @ ErrorCode::MintMismatch,
+        constraint = to_pool.load()?.accepts_mint(&mint.key()) @ ErrorCode::MintMismatch
+    )]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+
+    /// The source pool's `withdrawal_cosigner`, required above `cosign_threshold`
+    pub cosigner: Option<Signer<'info>>,
+}
+
+#[derive(Accounts)]
+#[instruction(new_version: u8)]
+pub struct MigrateEscrowAuthority<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    pub merchant: Signer<'info>,
+
+    /// Funds rent for the new escrow accounts, so the merchant only authorizes
+    #[account(mut)]
+    pub rent_payer: Signer<'info>,
+
+    #[account(
+        seeds = [
+            ESCROW_AUTHORITY_SEED,
+            merchant_pool.key().as_ref(),
+            merchant_pool.load()?.escrow_version_seed()
+        ],
+        bump = merchant_pool.load()?.escrow_bump
+    )]
+    pub escrow_authority: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = escrow_usdc.owner == escrow_authority.key() @ ErrorCode::EscrowAuthorityMismatch,
+        constraint = escrow_usdc.mint == usdc_mint.key() @ ErrorCode::EscrowMintMismatch
+    )]
+    pub escrow_usdc: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        seeds = [
+            ESCROW_AUTHORITY_SEED,
+            merchant_pool.key().as_ref(),
+            escrow_version_seed(new_version)
+        ],
+        bump
+    )]
+    pub new_escrow_authority: SystemAccount<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = rent_payer,
+        associated_token::mint = usdc_mint,
+        associated_token::authority = new_escrow_authority,
+        associated_token::token_program = token_program,
+    )]
+    pub new_escrow_usdc: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        constraint = usdc_mint.key() == merchant_pool.load()?.usdc_mint @ ErrorCode::MintMismatch
+    )]
+    pub usdc_mint: InterfaceAccount<'info, Mint>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AddPoolMint<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    pub merchant: Signer<'info>,
+
+    /// Funds rent for the accounts created here, so the merchant only authorizes
+    #[account(mut)]
+    pub rent_payer: Signer<'info>,
+
+    #[account(
+        seeds = [
+            ESCROW_AUTHORITY_SEED,
+            merchant_pool.key().as_ref(),
+            merchant_pool.load()?.escrow_version_seed()
+        ],
+        bump = merchant_pool.load()?.escrow_bump
+    )]
+    pub escrow_authority: SystemAccount<'info>,
+
+    /// Anyone can create this ATA ahead of time, so an existing one is reused
+    #[account(
+        init_if_needed,
+        payer = rent_payer,
+        associated_token::mint = mint,
+        associated_token::authority = escrow_authority,
+        associated_token::token_program = token_program,
+    )]
+    pub escrow_token: InterfaceAccount<'info, TokenAccount>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetTopUpSchedule<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        init_if_needed,
+        payer = rent_payer,
+        space = 8 + TopUpSchedule::INIT_SPACE,
+        seeds = [TOPUP_SEED, merchant_pool.key().as_ref()],
+        bump
+    )]
+    pub topup_schedule: Account<'info, TopUpSchedule>,
+
+    #[account(
+        constraint = source.owner == merchant.key() @ ErrorCode::Unauthorized,
+        constraint = merchant_pool.load()?.accepts_mint(&source.mint) @ ErrorCode::MintMismatch
+    )]
+    pub source: InterfaceAccount<'info, TokenAccount>,
+
+    pub merchant: Signer<'info>,
+
+    /// Funds rent for the accounts created here, so the merchant only authorizes
+    #[account(mut)]
+    pub rent_payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelTopUpSchedule<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        seeds = [
+            POOL_SEED,
+            merchant_pool.load()?.creator.as_ref(),
+            merchant_pool.load()?.pool_id_hash.as_ref()
+        ],
+        bump = merchant_pool.load()?.bump,
+        has_one = merchant @ ErrorCode::Unauthorized
+    )]
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        mut,
+        close = merchant,
+        seeds = [TOPUP_SEED, merchant_pool.key().as_ref()],
+        bump = topup_schedule.bump
+    )]
+    pub topup_schedule: Account<'info, TopUpSchedule>,
+
+    #[account(mut)]
+    pub merchant: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteTopUp<'info> {
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProgramPaused
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    pub merchant_pool: AccountLoader<'info, MerchantPool>,
+
+    #[account(
+        mut,
+        seeds = [TOPUP_SEED, merchant_pool.key().as_ref()],
+        bump = topup_schedule.bump
+    )]
+    pub topup_schedule: Account<'info, TopUpSchedule>,
+
+    #[account(
+        mut,
+        address = topup_schedule.source @ ErrorCode::InvalidTopUpSource,
+        constraint = source.delegate == COption::Some(topup_schedule.key()) @ ErrorCode::DelegateMismatch,
+        constraint = source.mint == mint.key() @ ErrorCode::TokenAccountMintMismatch
+    )]
+    pub source: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        seeds = [
+            ESCROW_AUTHORITY_SEED,
+            merchant_pool.key().as_ref(),
+            merchant_pool.load()?.escrow_version_seed()
+        ],
+        bump = merchant_pool.load()?.escrow_bump
+    )]
+    pub escrow_authority: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = escrow_authority,
+        associated_token::token_program = token_program,
+    )]
+    pub escrow_token: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        constraint = merchant_pool.load()?.accepts_mint(&mint.key()) @ ErrorCode::MintMismatch
+    )]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+/// Payout breakdown returned by `quote_commission`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct CommissionQuote {
+    pub sale_amount: u64,
+    pub commission_rate: u16,
+    pub commission: u64,
+    /// Paid to the affiliate right away, net of everything below
+    pub affiliate_payout: u64,
+    /// Recovered against the affiliate's outstanding clawbacks
+    pub debt_offset: u64,
+    /// Owed to the protocol on top of the commission
+    pub protocol_fee: u64,
+    pub manager_cut: u64,
+    /// Held in escrow for the pool's holdback period
+    pub holdback: u64,
+    /// Credited in kind instead of paid, in store-credit pools
+    pub store_credit: u64,
+}
+
+/// How a pool's commissions reach affiliates
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub enum PayoutMode {
+    /// Transferred to the affiliate on every sale
+    Push,
+    /// Accrued on the affiliate account and paid out by `claim_commissions`
+    Claim,
+}
+
+/// What a pool's primary-mint sales reward affiliates with
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub enum RewardMode {
+    /// Commissions in the sale's mint
+    Token,
+    /// `store_credit` on the affiliate account, redeemed by the pool's
+    /// storefront program
+    StoreCredit,
+}
+
+/// Metric a pool's dynamic rate schedule is keyed on
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub enum RatePolicy {
+    /// Every affiliate earns the pool rate
+    Flat,
+    /// The affiliate's sales per recorded click, in basis points
+    ConversionRate,
+    /// The affiliate's sale volume over the current and previous
+    /// reporting periods
+    TrailingVolume,
+}
+
+/// One affiliate's part of a split sale
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SaleSplit {
+    pub affiliate: Pubkey,
+    pub weight_bps: u16,
+    pub commission: u64,
+    /// Sent to the affiliate now, including anything it had accrued; zero
+    /// when the share was accrued instead
+    pub paid_out: u64,
+}
+
+/// Rate paid once an affiliate's metric reaches `threshold`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub struct RateStep {
+    pub threshold: u64,
+    pub rate_bps: u16,
+}
+
+/// What `process_sale` does when a commission exceeds `max_commission_per_sale`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub enum CommissionCapMode {
+    /// Fail the sale so the amount can be checked
+    Reject,
+    /// Pay the cap instead
+    Clamp,
+}
+
+#[event]
+pub struct PoolInitialized {
+    pub pool: Pubkey,
+    pub merchant: Pubkey,
+    pub pool_id: String,
+    pub commission_rate: u16,
+    pub initial_deposit: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PoolCloned {
+    pub pool: Pubkey,
+    pub merchant: Pubkey,
+    pub pool_id: String,
+    /// Pool the settings were copied from
+    pub source_pool: Pubkey,
+    pub commission_rate: u16,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PoolCommissionUpdated {
+    pub pool: Pubkey,
+    pub merchant: Pubkey,
+    pub pool_id: String,
+    pub old_rate: u16,
+    pub new_rate: u16,
+    /// End of the grace window earlier affiliates keep `old_rate` for;
+    /// zero when there is none
+    pub grace_ends_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PoolGovernanceUpdated {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub governance_program: Pubkey,
+    pub governance: Option<Pubkey>,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RateGracePeriodUpdated {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub rate_grace_period_secs: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AffiliateTreeInitialized {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub tree: Pubkey,
+    pub max_depth: u32,
+    pub max_buffer_size: u32,
+    pub timestamp: i64,
+}
+
+/// Carries the full record, since the tree only stores its hash
+#[event]
+pub struct CompressedAffiliateAdded {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub tree: Pubkey,
+    pub affiliate: CompressedAffiliate,
+    pub leaf: [u8; 32],
+    pub timestamp: i64,
+}
+
+/// `affiliate` is the record after the sale, which `leaf` commits to
+#[event]
+pub struct CompressedSaleProcessed {
+    pub pool: Pubkey,
+    pub pool_id_hash: [u8; 32],
+    pub tree: Pubkey,
+    pub order_id: String,
+    pub sale_amount: u64,
+    pub commission: u64,
+    pub protocol_fee: u64,
+    pub fee_tier: u8,
+    pub affiliate: CompressedAffiliate,
+    pub leaf: [u8; 32],
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ReceiptTreeInitialized {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub tree: Pubkey,
+    pub max_depth: u32,
+    pub max_buffer_size: u32,
+    pub timestamp: i64,
+}
+
+/// Carries the full receipt, since the tree only stores its hash
+#[event]
+pub struct SaleReceiptCompressed {
+    pub pool: Pubkey,
+    pub tree: Pubkey,
+    pub receipt: CompressedReceipt,
+    pub leaf: [u8; 32],
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PeriodRotated {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub period_index: u32,
+    pub volume: u64,
+    pub commissions_paid: u64,
+    pub started_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PoolDeactivated {
+    pub pool: Pubkey,
+    pub merchant: Pubkey,
+    pub pool_id: String,
+    pub timestamp: i64,
+}
+
+/// Periodic health summary; `reserved` is owed to affiliates and the
+/// protocol, `available` is what the merchant could still withdraw
+#[event]
+pub struct PoolHeartbeat {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub escrow_balance: u64,
+    pub reserved: u64,
+    pub available: u64,
+    pub total_claimable: u64,
+    pub total_held: u64,
+    pub protocol_fees_accrued: u64,
+    pub affiliate_count: u64,
+    pub total_volume: u64,
+    pub total_commissions_paid: u64,
+    pub period_index: u32,
+    pub period_volume: u64,
+    pub is_active: bool,
+    pub flagged: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct StatementGenerated {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub affiliate: Pubkey,
+    pub statement: Pubkey,
+    pub period: u32,
+    pub totals: StatementTotals,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SessionKeyCreated {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub session: Pubkey,
+    pub issued_by: Pubkey,
+    pub scope: SessionScope,
+    pub expires_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SessionKeyRevoked {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub session: Pubkey,
+    pub revoked_by: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AffiliateApproversUpdated {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub approvers: Vec<Pubkey>,
+    pub approval_threshold: u8,
+    pub timestamp: i64,
+}
+
+/// `activated` is set on the approval that reached the threshold
+#[event]
+pub struct AffiliateApproved {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub affiliate: Pubkey,
+    pub approver: Pubkey,
+    pub approvals: u8,
+    pub approval_threshold: u8,
+    pub activated: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AffiliateApplicationSubmitted {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub affiliate: Pubkey,
+    pub profile_uri: String,
+    pub contact_hash: [u8; 32],
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AffiliateAdded {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub affiliate: Pubkey,
+    pub wallet: Pubkey,
+    pub ref_id: String,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SplitSaleProcessed {
+    pub pool: Pubkey,
+    pub pool_id_hash: [u8; 32],
+    pub order_id: String,
+    pub sale_amount: u64,
+    pub commission: u64,
+    pub applied_rate_bps: u16,
+    pub protocol_fee: u64,
+    pub fee_tier: u8,
+    pub splits: Vec<SaleSplit>,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SaleProcessed {
+    pub pool: Pubkey,
+    /// SHA-256 of the pool id, fixed-size to keep the hot path cheap
+    pub pool_id_hash: [u8; 32],
+    pub affiliate: Pubkey,
+    pub affiliate_wallet: Pubkey,
+    pub order_id: String,
+    /// Mint the sale settled in, which `sale_amount` and `commission` are in
+    pub mint: Pubkey,
+    pub sale_amount: u64,
+    pub commission: u64,
+    pub applied_rate_bps: u16,
+    /// Protocol's cut of the sale, reserved in escrow for the treasury
+    pub protocol_fee: u64,
+    /// Index of the fee tier applied in the config's schedule; zero along
+    /// with `protocol_fee_bps` when no fee applied
+    pub fee_tier: u8,
+    pub protocol_fee_bps: u16,
+    /// Escrow balance in the sale's mint once the payout has left it
+    pub escrow_remaining: u64,
+    pub memo: String,
+    pub metadata_hash: [u8; 32],
+    /// Affiliate totals after this sale, for ranking without account reads
+    pub sales_count: u64,
+    pub largest_sale: u64,
+    pub average_sale: u64,
+    pub last_sale_at: i64,
+    /// Transferred to the affiliate by this sale, including any earlier
+    /// held-back commissions; zero when the commission was accrued
+    pub paid_out: u64,
+    /// Paid to the affiliate's manager out of the commission
+    pub manager_cut: u64,
+    /// Kept in escrow until the pool's holdback period has passed
+    pub held: u64,
+    /// Credited to the affiliate instead of a payout in store-credit mode
+    pub store_credit: u64,
+    /// Campaign the sale was credited to, if any, and its sales count
+    /// including this one
+    pub campaign: Option<Pubkey>,
+    pub campaign_redemptions: u64,
+    /// `RefIdIndex` of the affiliate's code when the sale was attributed by
+    /// looking it up, and its redemptions including this one; zero otherwise
+    pub ref_id_index: Option<Pubkey>,
+    pub ref_id_redemptions: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AffiliateRemoved {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub affiliate: Pubkey,
+    pub wallet: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PoolAbandonmentNoticed {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub merchant: Pubkey,
+    pub last_activity_at: i64,
+    pub recoverable_at: i64,
+    pub admin: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AbandonedPoolRecovered {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub merchant: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub admin: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct GuardiansUpdated {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub guardians: Vec<Pubkey>,
+    pub guardian_threshold: u8,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MerchantRecoveryProposed {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub merchant: Pubkey,
+    pub new_merchant: Pubkey,
+    /// When guardians can complete the recovery unless the merchant vetoes
+    pub recoverable_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MerchantRecoveryVetoed {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub merchant: Pubkey,
+    pub new_merchant: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MerchantRecovered {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub previous_merchant: Pubkey,
+    pub merchant: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AffiliateTagsSet {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub affiliate: Pubkey,
+    pub wallet: Pubkey,
+    pub tags: u32,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AffiliateLeft {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub affiliate: Pubkey,
+    pub wallet: Pubkey,
+    /// Whether the affiliate's accounts were closed
+    pub closed: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct EscrowDeposited {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    /// Signer that moved the funds: the merchant, its delegate or a donor
+    pub depositor: Pubkey,
+    /// Token account the funds came from
+    pub source: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct EscrowWithdrawn {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PoolMintAdded {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub mint: Pubkey,
+    pub escrow: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PoolMetadataUpdated {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub display_name: String,
+    pub metadata_uri: String,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PoolTermsUpdated {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub terms_hash: [u8; 32],
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AffiliateSelfActivated {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub affiliate: Pubkey,
+    pub wallet: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct TermsAccepted {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub affiliate: Pubkey,
+    pub wallet: Pubkey,
+    pub terms_hash: [u8; 32],
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AffiliateRefIdUpdated {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub affiliate: Pubkey,
+    pub old_ref_id: String,
+    pub new_ref_id: String,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PayoutModeUpdated {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub payout_mode: PayoutMode,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RewardModeUpdated {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub reward_mode: RewardMode,
+    pub store_credit_rate_bps: u16,
+    pub storefront_program: Pubkey,
+    pub timestamp: i64,
+}
+
+/// `store_credit` is the affiliate's balance left after the redemption
+#[event]
+pub struct StoreCreditRedeemed {
     pub pool: Pubkey,
+    pub pool_id: String,
+    pub affiliate: Pubkey,
     pub wallet: Pubkey,
-    #[max_len(32)]
+    pub amount: u64,
+    pub store_credit: u64,
+    pub storefront_program: Pubkey,
+    pub timestamp: i64,
+}
+
+/// `buyer` is the authority of the transfer that paid `amount` for the sale
+#[event]
+pub struct PaymentVerified {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub order_id: String,
+    pub reference: Pubkey,
+    pub nonce: u64,
+    pub buyer: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+/// `interest` is what the escrow gained since the previous sync and
+/// `interest_earned` the running total, both in the mint's UI amounts
+#[event]
+pub struct InterestSynced {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub escrow_balance: u64,
+    pub escrow_ui_balance: u64,
+    pub interest: u64,
+    pub interest_earned: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RateDecayUpdated {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub idle_days: u16,
+    pub restore_days: u16,
+    pub decay_bps: u16,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SaleGuardUpdated {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub enabled: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PaymentProofRequirementUpdated {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub required: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CommissionsClaimed {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub affiliate: Pubkey,
+    pub wallet: Pubkey,
+    pub amount: u64,
+    pub claimed_by: Pubkey,
+    /// Part of `amount` kept by the relayer of a relayed claim
+    pub relayer_fee: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SaleRefunded {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub affiliate: Pubkey,
+    pub order_id: String,
+    pub refund_amount: u64,
+    pub clawback: u64,
+    pub from_held: u64,
+    pub from_claimable: u64,
+    pub debt_added: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PoolProcessorUpdated {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub processor: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CommissionOverrideBoundsUpdated {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub min_override_bps: u16,
+    pub max_override_bps: u16,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ClaimWindowUpdated {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub claim_window_secs: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct UnclaimedSwept {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub affiliate: Pubkey,
+    pub wallet: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AttributionWindowUpdated {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub attribution_window_secs: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ClickRegistered {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub affiliate: Pubkey,
     pub ref_id: String,
-    pub total_earned: u64,
-    pub sales_count: u64,
-    pub is_active: bool,
-    pub bump: u8,
-    pub created_at: i64,
+    pub timestamp: i64,
 }
 
-#[derive(Accounts)]
-#[instruction(pool_id: String)]
-pub struct InitializePool<'info> {
-    #[account(
-        init,
-        payer = merchant,
-        space = 8 + MerchantPool::INIT_SPACE,
-        seeds = [
-            b"pool",
-            merchant.key().as_ref(),
-            pool_id.as_bytes()
-        ],
-        bump
-    )]
-    pub merchant_pool: Account<'info, MerchantPool>,
+#[event]
+pub struct RatePolicyUpdated {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub rate_policy: RatePolicy,
+    pub rate_steps: Vec<RateStep>,
+    pub timestamp: i64,
+}
 
-    #[account(
-        seeds = [b"escrow_authority", merchant_pool.key().as_ref()],
-        bump
-    )]
-    pub escrow_authority: UncheckedAccount<'info>,
+#[event]
+pub struct ClicksRecorded {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub affiliate: Pubkey,
+    pub count: u64,
+    pub clicks: u64,
+    pub conversion_rate_bps: u16,
+    pub timestamp: i64,
+}
 
-    #[account(mut)]
-    pub merchant: Signer<'info>,
+#[event]
+pub struct InactivityPeriodUpdated {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub inactivity_period_secs: i64,
+    pub timestamp: i64,
+}
 
-    #[account(
-        mut,
-        constraint = merchant_usdc.owner == merchant.key(),
-        constraint = merchant_usdc.mint == usdc_mint.key()
-    )]
-    pub merchant_usdc: InterfaceAccount<'info, TokenAccount>,
+#[event]
+pub struct StaleAffiliateDeactivated {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub affiliate: Pubkey,
+    pub wallet: Pubkey,
+    pub last_active_at: i64,
+    pub timestamp: i64,
+}
 
-/// CHECK: This is a PDA used as the authority for the escrow token account.
-    /// It doesn't hold data, only acts as a signer via seeds for token transfers.
-    #[account(
-        init,
-        payer = merchant,
-        associated_token::mint = usdc_mint,
-        associated_token::authority = escrow_authority,
-        associated_token::token_program = token_program,
-    )]
-    pub escrow_usdc: InterfaceAccount<'info, TokenAccount>,
+#[event]
+pub struct AffiliateSnapshotTaken {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub affiliate: Pubkey,
+    pub period: u32,
+    pub total_earned: u64,
+    pub sales_count: u64,
+    pub total_sale_volume: u64,
+    pub timestamp: i64,
+}
 
-    pub usdc_mint: InterfaceAccount<'info, Mint>,
+#[event]
+pub struct TopUpScheduleUpdated {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub source: Pubkey,
+    pub amount: u64,
+    pub interval_secs: i64,
+    pub timestamp: i64,
+}
 
-    pub token_program: Interface<'info, TokenInterface>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
-    pub system_program: Program<'info, System>,
+#[event]
+pub struct TopUpScheduleCancelled {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub timestamp: i64,
 }
 
-#[derive(Accounts)]
-pub struct UpdatePoolCommission<'info> {
-    #[account(
-        mut,
-        constraint = merchant_pool.merchant == merchant.key() @ ErrorCode::Unauthorized
-    )]
-    pub merchant_pool: Account<'info, MerchantPool>,
+#[event]
+pub struct TopUpExecuted {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub next_topup_at: i64,
+    pub timestamp: i64,
+}
 
-    pub merchant: Signer<'info>,
+#[event]
+pub struct CommissionCapUpdated {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub max_commission_per_sale: u64,
+    pub commission_cap_mode: CommissionCapMode,
+    pub timestamp: i64,
 }
 
-#[derive(Accounts)]
-pub struct DeactivatePool<'info> {
-    #[account(
-        mut,
-        constraint = merchant_pool.merchant == merchant.key() @ ErrorCode::Unauthorized
-    )]
-    pub merchant_pool: Account<'info, MerchantPool>,
+#[event]
+pub struct AlreadyProcessed {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub affiliate: Pubkey,
+    pub order_id: String,
+    pub timestamp: i64,
+}
 
-    pub merchant: Signer<'info>,
+#[event]
+pub struct LeaderboardInitialized {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub leaderboard: Pubkey,
+    pub timestamp: i64,
 }
 
-#[derive(Accounts)]
-#[instruction(ref_id: String)]
-pub struct AddAffiliate<'info> {
-    #[account(
-        constraint = merchant_pool.merchant == merchant.key() @ ErrorCode::Unauthorized
-    )]
-    pub merchant_pool: Account<'info, MerchantPool>,
+#[event]
+pub struct AffiliateGroupCreated {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub group: Pubkey,
+    pub group_id: String,
+    pub rate_modifier_bps: i16,
+    pub timestamp: i64,
+}
 
-    #[account(
-        init,
-        payer = merchant,
-        space = 8 + AffiliateAccount::INIT_SPACE,
-        seeds = [
-            b"affiliate",
-            merchant_pool.key().as_ref(),
-            affiliate_wallet.key().as_ref()
-        ],
-        bump
-    )]
-    pub affiliate_account: Account<'info, AffiliateAccount>,
+#[event]
+pub struct GroupRateModifierUpdated {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub group: Pubkey,
+    pub old_modifier_bps: i16,
+    pub new_modifier_bps: i16,
+    pub timestamp: i64,
+}
 
-/// CHECK: This account is used only as a reference for the affiliate's wallet address.
-    /// No data is read from or written to this account.
-    pub affiliate_wallet: UncheckedAccount<'info>,
+#[event]
+pub struct GroupPayoutAccountUpdated {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub group: Pubkey,
+    /// `None` when members are paid directly again
+    pub payout_account: Option<Pubkey>,
+    pub owner: Option<Pubkey>,
+    pub timestamp: i64,
+}
 
-    #[account(mut)]
-    pub merchant: Signer<'info>,
+/// `joined` is false when the affiliate left the group
+#[event]
+pub struct AffiliateGroupChanged {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub group: Pubkey,
+    pub affiliate: Pubkey,
+    pub joined: bool,
+    pub member_count: u32,
+    pub timestamp: i64,
+}
 
-    pub system_program: Program<'info, System>,
+#[event]
+pub struct RegionUpdated {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub region_account: Pubkey,
+    pub region: [u8; 2],
+    pub budget: u64,
+    pub rate_modifier_bps: i16,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CampaignCreated {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub campaign: Pubkey,
+    pub campaign_id: String,
+    pub commission_rate: u16,
+    pub budget: u64,
+    pub starts_at: i64,
+    pub ends_at: i64,
+    pub required_tags: u32,
+    pub timestamp: i64,
 }
 
-#[derive(Accounts)]
-pub struct ProcessSale<'info> {
-    #[account(mut)]
-    pub merchant_pool: Account<'info, MerchantPool>,
-
-    #[account(
-        mut,
-        seeds = [
-            b"affiliate",
-            merchant_pool.key().as_ref(),
-            affiliate_wallet.key().as_ref()
-        ],
-        bump = affiliate_account.bump,
-        constraint = affiliate_account.pool == merchant_pool.key() @ ErrorCode::InvalidAffiliate
-    )]
-    pub affiliate_account: Account<'info, AffiliateAccount>,
+#[event]
+pub struct CampaignEnded {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub campaign: Pubkey,
+    pub spent: u64,
+    pub total_volume: u64,
+    pub timestamp: i64,
+}
 
-    /// CHECK: This account is used only as a reference for the affiliate's wallet address.
-    /// It must match the affiliate_account.wallet field.
-    #[account(mut)]
-    pub affiliate_wallet: UncheckedAccount<'info>,
+#[event]
+pub struct EscrowAuthorityMigrated {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub escrow_authority: Pubkey,
+    pub new_escrow_authority: Pubkey,
+    pub escrow_version: u8,
+    pub timestamp: i64,
+}
 
-    /// CHECK: This is a PDA used as the authority for the escrow token account.
-    /// It acts as a signer via seeds for token transfers from escrow.
-    #[account(
-        seeds = [b"escrow_authority", merchant_pool.key().as_ref()],
-        bump = merchant_pool.escrow_bump
-    )]
-    pub escrow_authority: UncheckedAccount<'info>,
+#[event]
+pub struct EscrowTransferred {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub to_pool: Pubkey,
+    pub to_pool_id: String,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
 
-    #[account(
-        mut,
-        constraint = escrow_usdc.owner == escrow_authority.key(),
-        constraint = escrow_usdc.mint == usdc_mint.key()
-    )]
-    pub escrow_usdc: InterfaceAccount<'info, TokenAccount>,
+#[event]
+pub struct PayoutThresholdUpdated {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub affiliate: Pubkey,
+    pub min_payout_amount: u64,
+    pub min_payout_interval_secs: i64,
+    pub updated_by: Pubkey,
+    pub timestamp: i64,
+}
 
-    #[account(
-        init_if_needed,
-        payer = authority,
-        associated_token::mint = usdc_mint,
-        associated_token::authority = affiliate_wallet,
-        associated_token::token_program = token_program,
-    )]
-    pub affiliate_usdc: InterfaceAccount<'info, TokenAccount>,
+#[event]
+pub struct RevenueAccountUpdated {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    /// `None` when the pool went back to escrow mode
+    pub revenue_account: Option<Pubkey>,
+    pub timestamp: i64,
+}
 
-    pub usdc_mint: InterfaceAccount<'info, Mint>,
+#[event]
+pub struct WithdrawalDestinationProposed {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub destination: Pubkey,
+    pub effective_at: i64,
+    pub timestamp: i64,
+}
 
-    #[account(mut)]
-    pub authority: Signer<'info>,
+#[event]
+pub struct WithdrawalDestinationUpdated {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub destination: Pubkey,
+    pub timestamp: i64,
+}
 
-    pub token_program: Interface<'info, TokenInterface>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
-    pub system_program: Program<'info, System>,
+#[event]
+pub struct DailyWithdrawalLimitUpdated {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub daily_withdrawal_limit: u64,
+    pub timestamp: i64,
 }
 
-#[derive(Accounts)]
-pub struct RemoveAffiliate<'info> {
-    #[account(
-        constraint = merchant_pool.merchant == merchant.key() @ ErrorCode::Unauthorized
-    )]
-    pub merchant_pool: Account<'info, MerchantPool>,
+#[event]
+pub struct WithdrawalCosignerUpdated {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub cosigner: Option<Pubkey>,
+    pub cosign_threshold: u64,
+    pub timestamp: i64,
+}
 
-    #[account(
-        mut,
-        seeds = [
-            b"affiliate",
-            merchant_pool.key().as_ref(),
-            affiliate_wallet.key().as_ref()
-        ],
-        bump = affiliate_account.bump
-    )]
-    pub affiliate_account: Account<'info, AffiliateAccount>,
+#[event]
+pub struct PoolFlagged {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    /// Affiliates that co-signed the flag
+    pub affiliates: u32,
+    pub flagged_volume: u64,
+    pub total_volume: u64,
+    pub timestamp: i64,
+}
 
-    /// CHECK: This account is used only as a reference for the affiliate's wallet address.
-    /// It must match the affiliate_account.wallet field.
-    #[account(mut)]
-    pub affiliate_wallet: UncheckedAccount<'info>,
+#[event]
+pub struct PoolFlagCleared {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub admin: Pubkey,
+    pub upheld: bool,
+    pub timestamp: i64,
+}
 
-    pub merchant: Signer<'info>,
+#[event]
+pub struct MerchantReputationSynced {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub merchant: Pubkey,
+    pub pools_created: u64,
+    pub total_paid: u64,
+    pub disputes_lost: u32,
+    pub freezes: u32,
+    pub timestamp: i64,
 }
 
-#[derive(Accounts)]
-pub struct DepositEscrow<'info> {
-    #[account(
-        constraint = merchant_pool.merchant == merchant.key() @ ErrorCode::Unauthorized
-    )]
-    pub merchant_pool: Account<'info, MerchantPool>,
+#[event]
+pub struct FeeTiersUpdated {
+    pub fee_tiers: Vec<FeeTier>,
+    pub admin: Pubkey,
+    pub timestamp: i64,
+}
 
-    #[account(mut)]
-    pub merchant: Signer<'info>,
+#[event]
+pub struct TreasuryUpdated {
+    pub treasury: Pubkey,
+    pub admin: Pubkey,
+    pub timestamp: i64,
+}
 
-    #[account(
-        mut,
-        constraint = merchant_usdc.owner == merchant.key(),
-        constraint = merchant_usdc.mint == usdc_mint.key()
-    )]
-    pub merchant_usdc: InterfaceAccount<'info, TokenAccount>,
+#[event]
+pub struct UpgradeGuardInitialized {
+    pub program_hash: [u8; 32],
+    pub timelock_secs: i64,
+    pub admin: Pubkey,
+    pub timestamp: i64,
+}
 
-/// CHECK: This is a PDA used as the authority for the escrow token account.
-    /// It acts as a signer via seeds for receiving token deposits.
-    #[account(
-        seeds = [b"escrow_authority", merchant_pool.key().as_ref()],
-        bump = merchant_pool.escrow_bump
-    )]
-    pub escrow_authority: UncheckedAccount<'info>,
+#[event]
+pub struct ConfigChangeQueued {
+    pub change: ConfigChange,
+    pub executable_at: i64,
+    pub admin: Pubkey,
+    pub timestamp: i64,
+}
 
-    #[account(
-        mut,
-        constraint = escrow_usdc.owner == escrow_authority.key(),
-        constraint = escrow_usdc.mint == usdc_mint.key()
-    )]
-    pub escrow_usdc: InterfaceAccount<'info, TokenAccount>,
+#[event]
+pub struct ConfigChangeCancelled {
+    pub change: ConfigChange,
+    pub admin: Pubkey,
+    pub timestamp: i64,
+}
 
-    pub usdc_mint: InterfaceAccount<'info, Mint>,
+#[event]
+pub struct ProgramHashUpdated {
+    pub program_hash: [u8; 32],
+    pub admin: Pubkey,
+    pub timestamp: i64,
+}
 
-    pub token_program: Interface<'info, TokenInterface>,
+#[event]
+pub struct ConfigTimelockUpdated {
+    pub timelock_secs: i64,
+    pub admin: Pubkey,
+    pub timestamp: i64,
 }
 
-#[derive(Accounts)]
-pub struct WithdrawEscrow<'info> {
-    #[account(
-        constraint = merchant_pool.merchant == merchant.key() @ ErrorCode::Unauthorized
-    )]
-    pub merchant_pool: Account<'info, MerchantPool>,
+#[event]
+pub struct CommissionRateBandUpdated {
+    pub min_commission_rate_bps: u16,
+    pub max_commission_rate_bps: u16,
+    pub admin: Pubkey,
+    pub timestamp: i64,
+}
 
-    #[account(mut)]
-    pub merchant: Signer<'info>,
+#[event]
+pub struct ProtocolFeesCollected {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub amount: u64,
+    pub treasury_account: Pubkey,
+    pub timestamp: i64,
+}
 
-    #[account(
-        mut,
-        constraint = merchant_usdc.owner == merchant.key(),
-        constraint = merchant_usdc.mint == usdc_mint.key()
-    )]
-    pub merchant_usdc: InterfaceAccount<'info, TokenAccount>,
+#[event]
+pub struct FeeExemptionUpdated {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub fee_exempt: bool,
+    pub admin: Pubkey,
+    pub timestamp: i64,
+}
 
-/// CHECK: This is a PDA used as the authority for the escrow token account.
-    /// It acts as a signer via seeds for token withdrawals from escrow.
-    #[account(
-        seeds = [b"escrow_authority", merchant_pool.key().as_ref()],
-        bump = merchant_pool.escrow_bump
-    )]
-    pub escrow_authority: UncheckedAccount<'info>,
+#[event]
+pub struct ProgramPauseUpdated {
+    pub paused: bool,
+    pub admin: Pubkey,
+    pub timestamp: i64,
+}
 
-    #[account(
-        mut,
-        constraint = escrow_usdc.owner == escrow_authority.key(),
-        constraint = escrow_usdc.mint == usdc_mint.key()
-    )]
-    pub escrow_usdc: InterfaceAccount<'info, TokenAccount>,
+#[event]
+pub struct HoldbackPeriodUpdated {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub holdback_secs: i64,
+    pub timestamp: i64,
+}
 
-    pub usdc_mint: InterfaceAccount<'info, Mint>,
+#[event]
+pub struct HoldbackReleased {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub affiliate: Pubkey,
+    pub order_id: String,
+    pub amount: u64,
+    pub timestamp: i64,
+}
 
-    pub token_program: Interface<'info, TokenInterface>,
+#[event]
+pub struct FirstSaleBountyUpdated {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub amount: u64,
+    pub timestamp: i64,
 }
 
 #[event]
-pub struct PoolInitialized {
+pub struct ReferralBountyPaid {
     pub pool: Pubkey,
-    pub merchant: Pubkey,
     pub pool_id: String,
-    pub commission_rate: u16,
-    pub initial_deposit: u64,
+    /// The referred affiliate whose first sale triggered the bounty
+    pub affiliate: Pubkey,
+    /// Wallet of the referring affiliate
+    pub referrer: Pubkey,
+    pub amount: u64,
     pub timestamp: i64,
 }
 
 #[event]
-pub struct PoolCommissionUpdated {
+pub struct ManagerUpdated {
     pub pool: Pubkey,
-    pub merchant: Pubkey,
     pub pool_id: String,
-    pub old_rate: u16,
-    pub new_rate: u16,
+    pub affiliate: Pubkey,
+    pub manager: Option<Pubkey>,
+    pub manager_share_bps: u16,
     pub timestamp: i64,
 }
 
 #[event]
-pub struct PoolDeactivated {
+pub struct PayoutAccountUpdated {
     pub pool: Pubkey,
-    pub merchant: Pubkey,
     pub pool_id: String,
+    pub affiliate: Pubkey,
+    /// `None` when payouts went back to the wallet's ATA
+    pub payout_account: Option<Pubkey>,
+    /// Owner of the payout account, e.g. a custodian or exchange
+    pub owner: Option<Pubkey>,
     pub timestamp: i64,
 }
 
 #[event]
-pub struct AffiliateAdded {
+pub struct CompressedPayoutsUpdated {
     pub pool: Pubkey,
     pub pool_id: String,
     pub affiliate: Pubkey,
     pub wallet: Pubkey,
-    pub ref_id: String,
+    pub enabled: bool,
     pub timestamp: i64,
 }
 
+/// `amount` went to a compressed token account owned by `wallet`, appended
+/// to `output_state_tree`
 #[event]
-pub struct SaleProcessed {
+pub struct CompressedCommissionsPaid {
     pub pool: Pubkey,
     pub pool_id: String,
     pub affiliate: Pubkey,
-    pub affiliate_wallet: Pubkey,
-    pub sale_amount: u64,
-    pub commission: u64,
+    pub wallet: Pubkey,
+    pub amount: u64,
+    pub output_state_tree: Pubkey,
     pub timestamp: i64,
 }
 
 #[event]
-pub struct AffiliateRemoved {
+pub struct ConfidentialPayoutsUpdated {
+    pub pool: Pubkey,
+    pub pool_id: String,
+    pub enabled: bool,
+    pub timestamp: i64,
+}
+
+/// `elgamal_pubkey` is `None` when the affiliate opted out
+#[event]
+pub struct ConfidentialPayoutKeyUpdated {
     pub pool: Pubkey,
     pub pool_id: String,
     pub affiliate: Pubkey,
     pub wallet: Pubkey,
+    pub elgamal_pubkey: Option<[u8; 32]>,
     pub timestamp: i64,
 }
 
+/// `amount` went to `destination`'s encrypted pending balance
 #[event]
-pub struct EscrowDeposited {
+pub struct ConfidentialCommissionsPaid {
     pub pool: Pubkey,
     pub pool_id: String,
+    pub affiliate: Pubkey,
+    pub wallet: Pubkey,
+    pub destination: Pubkey,
     pub amount: u64,
     pub timestamp: i64,
 }
 
+/// `splits` is empty when payouts went back to the payout account whole
 #[event]
-pub struct EscrowWithdrawn {
+pub struct PayoutSplitsUpdated {
     pub pool: Pubkey,
     pub pool_id: String,
-    pub amount: u64,
+    pub affiliate: Pubkey,
+    pub splits: Vec<PayoutSplit>,
     pub timestamp: i64,
 }
 
@@ -681,7 +12783,7 @@ pub enum ErrorCode {
     InvalidCommissionRate,
     #[msg("Amount must be greater than 0")]
     InvalidAmount,
-    #[msg("Pool ID must be between 1-32 characters")]
+    #[msg("Pool ID must be between 1-128 characters")]
     InvalidPoolId,
     #[msg("Reference ID must be between 1-32 characters")]
     InvalidRefId,
@@ -699,4 +12801,325 @@ pub enum ErrorCode {
     Unauthorized,
     #[msg("Invalid affiliate account")]
     InvalidAffiliate,
+    #[msg("Mint does not match the pool's settlement mint")]
+    MintMismatch,
+    #[msg("Affiliate wallet does not match the affiliate account")]
+    AffiliateWalletMismatch,
+    #[msg("Escrow token account is not owned by the pool's escrow authority")]
+    EscrowAuthorityMismatch,
+    #[msg("Mint is already a settlement mint of this pool")]
+    MintAlreadyAdded,
+    #[msg("Pool already has the maximum number of settlement mints")]
+    TooManySettlementMints,
+    #[msg("Display name must be at most 64 characters")]
+    InvalidDisplayName,
+    #[msg("Metadata URI must be at most 200 characters")]
+    InvalidMetadataUri,
+    #[msg("Affiliate has not accepted the pool's current terms")]
+    TermsNotAccepted,
+    #[msg("Terms hash does not match the pool's current terms")]
+    TermsHashMismatch,
+    #[msg("Affiliate has unclaimed commissions; settle them first")]
+    PendingCommissions,
+    #[msg("No commissions to claim")]
+    NothingToClaim,
+    #[msg("Order ID must be between 1-32 characters")]
+    InvalidOrderId,
+    #[msg("Refund amount exceeds the sale's refundable amount")]
+    InvalidRefundAmount,
+    #[msg("Commission override is outside the pool's configured bounds")]
+    CommissionOverrideOutOfBounds,
+    #[msg("Sale memo must be at most 128 characters")]
+    InvalidMemo,
+    #[msg("Claim window must not be negative")]
+    InvalidClaimWindow,
+    #[msg("Commissions are still within the pool's claim window")]
+    ClaimWindowNotElapsed,
+    #[msg("Inactivity period must not be negative")]
+    InvalidInactivityPeriod,
+    #[msg("Affiliate has not been inactive for the pool's inactivity period")]
+    AffiliateNotStale,
+    #[msg("Signer is not the token account's approved delegate")]
+    DelegateMismatch,
+    #[msg("Top-up interval must be positive")]
+    InvalidTopUpInterval,
+    #[msg("Scheduled top-up is not due yet")]
+    TopUpNotDue,
+    #[msg("Token account is not the schedule's top-up source")]
+    InvalidTopUpSource,
+    #[msg("Commission exceeds the pool's per-sale cap")]
+    CommissionCapExceeded,
+    #[msg("A sale with this order_id was already processed")]
+    OrderAlreadyProcessed,
+    #[msg("This pool keeps a leaderboard that must be passed with each sale")]
+    LeaderboardRequired,
+    #[msg("Invalid campaign ID (must be 1-32 characters)")]
+    InvalidCampaignId,
+    #[msg("Campaign must end after it starts")]
+    InvalidCampaignWindow,
+    #[msg("Campaign is ended or outside its time window")]
+    CampaignNotLive,
+    #[msg("Commission exceeds the campaign's remaining budget")]
+    CampaignBudgetExceeded,
+    #[msg("Escrow transfers need two different pools")]
+    SameEscrowTransfer,
+    #[msg("Payout interval cannot be negative")]
+    InvalidPayoutInterval,
+    #[msg("Relayer fee exceeds the allowed share of the claim")]
+    RelayerFeeTooHigh,
+    #[msg("Manager share must be at most 10000 bps and needs a manager")]
+    InvalidManagerShare,
+    #[msg("The affiliate's manager token account is required")]
+    ManagerAccountRequired,
+    #[msg("Token account does not belong to the affiliate's manager")]
+    ManagerMismatch,
+    #[msg("The referrer's token account is required to pay the first-sale bounty")]
+    ReferrerAccountRequired,
+    #[msg("Token account does not belong to the affiliate's referrer")]
+    ReferrerMismatch,
+    #[msg("The program is paused")]
+    ProgramPaused,
+    #[msg("Pool is flagged; withdrawals are frozen pending review")]
+    PoolFlagged,
+    #[msg("Pool is not flagged")]
+    PoolNotFlagged,
+    #[msg("Flag accounts must be (affiliate account, signing wallet) pairs of the pool")]
+    InvalidFlagAccounts,
+    #[msg("Flagging affiliates don't hold enough of the pool's volume")]
+    InsufficientFlagVolume,
+    #[msg("The pool's withdrawal co-signer must sign")]
+    CosignerRequired,
+    #[msg("Withdrawal exceeds the pool's daily limit")]
+    DailyWithdrawalLimitExceeded,
+    #[msg("Withdrawals must go to the pool's withdrawal destination")]
+    WithdrawalDestinationMismatch,
+    #[msg("No withdrawal destination change is queued")]
+    NoPendingDestination,
+    #[msg("The withdrawal destination change is still timelocked")]
+    DestinationChangePending,
+    #[msg("The pool's revenue account is required in escrowless mode")]
+    RevenueAccountRequired,
+    #[msg("Token account is not the pool's revenue account")]
+    RevenueAccountMismatch,
+    #[msg("Holdback period cannot be negative")]
+    InvalidHoldbackPeriod,
+    #[msg("Holdback accounts must be (sale receipt, affiliate account, affiliate token account) triples of the pool")]
+    InvalidHoldbackAccounts,
+    #[msg("The affiliate's payout account is required")]
+    PayoutAccountRequired,
+    #[msg("Token account is not the affiliate's payout account")]
+    PayoutAccountMismatch,
+    #[msg("Payout account cannot be owned by the pool's escrow")]
+    InvalidPayoutAccount,
+    #[msg("Rate grace period cannot be negative")]
+    InvalidRateGracePeriod,
+    #[msg("Escrow token account is not in the expected mint")]
+    EscrowMintMismatch,
+    #[msg("Token account is not in the expected mint")]
+    TokenAccountMintMismatch,
+    #[msg("Token account does not belong to the merchant")]
+    WrongMerchantTokenAccount,
+    #[msg("Program data account does not belong to this program")]
+    InvalidProgramData,
+    #[msg("Pool ID may only contain ASCII letters, digits and '-'")]
+    InvalidPoolIdCharset,
+    #[msg("Reference ID may only contain ASCII letters, digits and '-'")]
+    InvalidRefIdCharset,
+    #[msg("Merchant is not the native treasury of this governance")]
+    NotGovernanceTreasury,
+    #[msg("Fee tiers must start at zero volume, ascend and charge at most MAX_PROTOCOL_FEE_BPS")]
+    InvalidFeeTiers,
+    #[msg("The pool has no protocol fees to collect")]
+    NoProtocolFees,
+    #[msg("Token account does not belong to the protocol treasury")]
+    WrongTreasuryAccount,
+    #[msg("The pool already has an affiliate tree")]
+    AffiliateTreeExists,
+    #[msg("Tree is not the pool's affiliate tree")]
+    AffiliateTreeMismatch,
+    #[msg("The pool already has a receipt tree")]
+    ReceiptTreeExists,
+    #[msg("Tree is not the pool's receipt tree")]
+    ReceiptTreeMismatch,
+    #[msg("The pool logs receipts to its receipt tree, which must be passed")]
+    ReceiptTreeRequired,
+    #[msg("A sale receipt account is required")]
+    SaleReceiptRequired,
+    #[msg("Attribution window must not be negative")]
+    InvalidAttributionWindow,
+    #[msg("The sale's ref_id has no registered click")]
+    ClickNotRegistered,
+    #[msg("The click was registered outside the attribution window")]
+    AttributionWindowExpired,
+    #[msg(
+        "Rate steps must ascend, rate at most 100% and be set exactly when the policy isn't flat"
+    )]
+    InvalidRateSteps,
+    #[msg("Approvers must be unique, at most MAX_APPROVERS, and cover a non-zero threshold exactly when set")]
+    InvalidApprovers,
+    #[msg("The pool requires approvals, so the pending affiliate account must be passed")]
+    PendingAffiliateRequired,
+    #[msg("The affiliate is already approved, or already approved by this approver")]
+    AffiliateAlreadyApproved,
+    #[msg("Session keys must expire in the future and within MAX_SESSION_KEY_SECS")]
+    InvalidSessionExpiry,
+    #[msg("Session key limits must be greater than 0")]
+    InvalidSessionScope,
+    #[msg("Session key has expired")]
+    SessionKeyExpired,
+    #[msg("Sale exceeds the session key's limits")]
+    SessionLimitExceeded,
+    #[msg("Statement period must come after the affiliate's last statement")]
+    StatementPeriodNotAfterLast,
+    #[msg("Commission rate band must have min <= max <= 10000 bps")]
+    InvalidCommissionRateBand,
+    #[msg("Commission rate is outside the program's allowed band")]
+    CommissionRateOutOfBand,
+    #[msg("Split weights must be non-zero, at most MAX_SALE_SPLITS and add up to 10000 bps")]
+    InvalidSplitWeights,
+    #[msg("Split accounts must be distinct affiliates of the pool, each with its commission token account")]
+    InvalidSplitAccounts,
+    #[msg("Invalid group ID (must be 1-32 characters)")]
+    InvalidGroupId,
+    #[msg("Group rate modifier must be within +/-10000 bps")]
+    InvalidGroupRateModifier,
+    #[msg("Affiliate is already in a group")]
+    AffiliateAlreadyGrouped,
+    #[msg("Group doesn't match the affiliate's group")]
+    AffiliateGroupMismatch,
+    #[msg("The affiliate is in a group, so the group account must be passed")]
+    AffiliateGroupRequired,
+    #[msg("Affiliate batch must hold 1 to MAX_AFFILIATE_BATCH affiliates")]
+    InvalidAffiliateBatch,
+    #[msg("Batch accounts must be each affiliate's uninitialized account, entry and ref_id index PDAs")]
+    InvalidAffiliateBatchAccounts,
+    #[msg("The pool guards sales, so the instructions sysvar must be passed")]
+    InstructionsSysvarRequired,
+    #[msg("The transaction has a token instruction on the sale's escrow or commission accounts")]
+    UnexpectedTokenInstruction,
+    #[msg("The transaction's expiry slot or valid-until time has passed")]
+    TransactionExpired,
+    #[msg("Rate decay needs restore days and a decay of 1 to 10000 bps")]
+    InvalidRateDecay,
+    #[msg("The pool's mint doesn't use the interest-bearing extension")]
+    MintNotInterestBearing,
+    #[msg("Escrow authorities can only migrate to a higher seed version")]
+    InvalidEscrowVersion,
+    #[msg("Migration accounts must be each settlement mint, its escrow and new escrow")]
+    InvalidEscrowMigrationAccounts,
+    #[msg("The upgrade guard timelocks this change; queue it instead")]
+    ConfigChangeTimelocked,
+    #[msg("This change needs the program's upgrade guard")]
+    UpgradeGuardRequired,
+    #[msg("Config change timelocks must be 1 second to 30 days")]
+    InvalidTimelock,
+    #[msg("Another config change is already pending")]
+    ConfigChangePending,
+    #[msg("No config change is pending")]
+    NoPendingConfigChange,
+    #[msg("The pending config change's timelock hasn't passed")]
+    TimelockNotElapsed,
+    #[msg("The affiliate wallet hasn't activated its account yet")]
+    AffiliateNotActivated,
+    #[msg("The affiliate wallet has already activated its account")]
+    AffiliateAlreadyActivated,
+    #[msg("The affiliate lacks tags the campaign requires")]
+    MissingAffiliateTags,
+    #[msg("Region codes are two uppercase ASCII letters")]
+    InvalidRegion,
+    #[msg("Sale would exceed the region's commission budget")]
+    RegionBudgetExceeded,
+    #[msg("The pool hasn't been inactive long enough, or given notice, to be recovered")]
+    PoolNotAbandoned,
+    #[msg("The pool's abandonment has already been noticed")]
+    AbandonmentAlreadyNoticed,
+    #[msg("The abandonment notice period hasn't passed")]
+    AbandonmentNoticePending,
+    #[msg("Guardians must be distinct, exclude the merchant and number at least the threshold, which is zero only without guardians")]
+    InvalidGuardians,
+    #[msg("Not enough of the pool's guardians signed")]
+    NotEnoughGuardians,
+    #[msg("A merchant recovery is already pending")]
+    MerchantRecoveryPending,
+    #[msg("No merchant recovery is pending")]
+    NoPendingMerchantRecovery,
+    #[msg("The recovery key must be a new key matching the pending proposal")]
+    InvalidRecoveryKey,
+    #[msg("The merchant recovery challenge window hasn't passed")]
+    MerchantRecoveryChallengeActive,
+    #[msg("Payout splits take up to 4 distinct token accounts with nonzero weights totalling 10000 bps")]
+    InvalidPayoutSplits,
+    #[msg("Pass the affiliate's payout split token accounts, in order, as remaining accounts")]
+    PayoutSplitAccountsMismatch,
+    #[msg("Store-credit mode needs a conversion rate and a storefront program")]
+    InvalidStoreCreditConfig,
+    #[msg("The affiliate doesn't have that much store credit")]
+    InsufficientStoreCredit,
+    #[msg("The instruction before the sale must be the buyer's checked token transfer")]
+    PaymentNotFound,
+    #[msg(
+        "The buyer's payment doesn't match the sale's amount, mint, merchant account or reference"
+    )]
+    PaymentMismatch,
+    #[msg("Compressed payouts go to the affiliate wallet, not a payout account, splits or group payout account")]
+    CompressedPayoutsRouted,
+    #[msg("The affiliate hasn't opted into compressed payouts")]
+    CompressedPayoutsDisabled,
+    #[msg("Pass 1 to 8 distinct affiliate accounts of the pool as remaining accounts")]
+    InvalidCompressedPayoutAccounts,
+    #[msg("Split sales don't support the pool's holdback, attribution window, rate policy or decay, store credit, escrowless mode or sale guard")]
+    PoolSplitSaleUnsupported,
+    #[msg("Split sales don't support affiliates with a manager, group, payout splits, compressed or confidential payouts or referral bounty due")]
+    AffiliateSplitSaleUnsupported,
+    #[msg("The pool's mint isn't a Token-2022 mint with confidential transfers")]
+    MintNotConfidential,
+    #[msg("The escrow isn't configured for confidential transfers")]
+    EscrowNotConfidential,
+    #[msg("Confidential payouts go to the affiliate's own token account, not a payout account, splits, group payout account or compressed payouts")]
+    ConfidentialPayoutsRouted,
+    #[msg("The pool or affiliate hasn't turned on confidential payouts")]
+    ConfidentialPayoutsDisabled,
+    #[msg("The token account isn't the affiliate's, in the pool's mint, configured with its ElGamal key")]
+    ConfidentialDestinationMismatch,
+    #[msg("The pool only pays commissions on sales processed with a payment proof")]
+    PaymentProofRequired,
+    #[msg("Split sales can't be partially refunded")]
+    SplitSaleRefundUnsupported,
+    #[msg("The buyer's payment doesn't carry the reference of the pool's current payment nonce")]
+    PaymentNonceMismatch,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `interest_growth` in floating point, as the extension computes it
+    fn float_growth(rate: i16, secs: i64) -> f64 {
+        (f64::from(rate) / 10_000.0 * secs as f64 / SECS_PER_INTEREST_YEAR as f64).exp()
+    }
+
+    #[test]
+    fn interest_growth_matches_continuous_compounding() {
+        for rate in [0, 1, 500, -500, 3_000, i16::MAX, i16::MIN] {
+            for secs in [
+                0,
+                1,
+                SECS_PER_DAY,
+                365 * SECS_PER_DAY,
+                10 * 365 * SECS_PER_DAY,
+            ] {
+                let growth = interest_growth(BasisPoints::from(rate), secs).unwrap();
+                let expected = float_growth(rate, secs) * INTEREST_GROWTH_ONE as f64;
+                assert!(
+                    (growth as f64 - expected).abs() <= expected * 1e-9 + 1.0,
+                    "rate {rate} over {secs}s: {growth} vs {expected}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn interest_growth_overflow_fails() {
+        assert!(interest_growth(BasisPoints::from(i16::MAX), i64::MAX).is_err());
+    }
 }
@@ -0,0 +1,267 @@
+//! PDA seeds and address derivation.
+//!
+//! These helpers are used by the program itself and compile for off-chain
+//! clients too, so integrators don't have to re-implement the seed layout.
+
+use anchor_lang::{prelude::Pubkey, solana_program::hash::hash};
+use anchor_spl::associated_token::get_associated_token_address_with_program_id;
+
+pub const POOL_SEED: &[u8] = b"pool";
+pub const AFFILIATE_SEED: &[u8] = b"affiliate";
+pub const ESCROW_AUTHORITY_SEED: &[u8] = b"escrow_authority";
+pub const REGISTRY_SEED: &[u8] = b"merchant_registry";
+pub const POOL_ENTRY_SEED: &[u8] = b"pool_entry";
+pub const AFFILIATE_ENTRY_SEED: &[u8] = b"affiliate_entry";
+pub const REF_ID_SEED: &[u8] = b"ref_id";
+pub const RECEIPT_SEED: &[u8] = b"receipt";
+pub const TOPUP_SEED: &[u8] = b"topup";
+pub const LEADERBOARD_SEED: &[u8] = b"leaderboard";
+pub const CAMPAIGN_SEED: &[u8] = b"campaign";
+pub const CONFIG_SEED: &[u8] = b"config";
+pub const AFFILIATE_SNAPSHOT_SEED: &[u8] = b"affiliate_snapshot";
+pub const POOL_PERIOD_SEED: &[u8] = b"pool_period";
+pub const AFFILIATE_TREE_SEED: &[u8] = b"affiliate_tree";
+pub const RECEIPT_TREE_SEED: &[u8] = b"receipt_tree";
+pub const PENDING_AFFILIATE_SEED: &[u8] = b"pending_affiliate";
+pub const SESSION_KEY_SEED: &[u8] = b"session_key";
+pub const STATEMENT_SEED: &[u8] = b"statement";
+pub const REPUTATION_SEED: &[u8] = b"merchant_reputation";
+pub const GROUP_SEED: &[u8] = b"affiliate_group";
+pub const UPGRADE_GUARD_SEED: &[u8] = b"upgrade_guard";
+pub const REGION_SEED: &[u8] = b"region";
+pub const PAYMENT_NONCE_SEED: &[u8] = b"payment_nonce";
+/// Seed of the authority a pool's storefront program signs store credit
+/// redemptions with, under that program
+pub const STOREFRONT_AUTHORITY_SEED: &[u8] = b"redio_storefront";
+/// Seed of an SPL Governance native treasury, under the governance program
+pub const GOVERNANCE_TREASURY_SEED: &[u8] = b"native-treasury";
+
+/// The program's single `ProgramConfig`
+pub fn find_program_config() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[CONFIG_SEED], &crate::ID)
+}
+
+/// The program's `UpgradeGuard`, once the admin has created it
+pub fn find_upgrade_guard() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[UPGRADE_GUARD_SEED], &crate::ID)
+}
+
+/// SHA-256 of `pool_id`, the form it takes in pool seeds
+pub fn pool_id_hash(pool_id: &str) -> [u8; 32] {
+    hash(pool_id.as_bytes()).to_bytes()
+}
+
+/// Merchant pool PDA for `pool_id` of the merchant that created it
+pub fn find_pool_address(merchant: &Pubkey, pool_id: &str) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[POOL_SEED, merchant.as_ref(), &pool_id_hash(pool_id)],
+        &crate::ID,
+    )
+}
+
+/// Affiliate account PDA for `wallet` within `pool`
+pub fn find_affiliate_address(pool: &Pubkey, wallet: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[AFFILIATE_SEED, pool.as_ref(), wallet.as_ref()],
+        &crate::ID,
+    )
+}
+
+/// PDA that signs for the pool's escrow token account, under the original
+/// escrow seed version every pool starts on
+pub fn find_escrow_authority(pool: &Pubkey) -> (Pubkey, u8) {
+    find_versioned_escrow_authority(pool, 0)
+}
+
+/// Escrow authority of `pool` under escrow seed `version`, which
+/// `migrate_escrow_authority` moves pools to
+pub fn find_versioned_escrow_authority(pool: &Pubkey, version: u8) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            ESCROW_AUTHORITY_SEED,
+            pool.as_ref(),
+            escrow_version_seed(version),
+        ],
+        &crate::ID,
+    )
+}
+
+/// Trailing escrow authority seed of `version`; empty for version 0, so the
+/// original authorities keep their address
+pub fn escrow_version_seed(version: u8) -> &'static [u8] {
+    const VERSIONS: [u8; 256] = {
+        let mut versions = [0; 256];
+        let mut version = 0;
+        while version < 256 {
+            versions[version] = version as u8;
+            version += 1;
+        }
+        versions
+    };
+    let version = usize::from(version);
+    &VERSIONS[version..version + usize::from(version > 0)]
+}
+
+/// Registry counting the pools created by `merchant`
+pub fn find_merchant_registry(merchant: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[REGISTRY_SEED, merchant.as_ref()], &crate::ID)
+}
+
+/// Cross-pool track record of `merchant`
+pub fn find_merchant_reputation(merchant: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[REPUTATION_SEED, merchant.as_ref()], &crate::ID)
+}
+
+/// Registry entry pointing at the `index`-th pool created by `merchant`
+pub fn find_pool_entry(merchant: &Pubkey, index: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[POOL_ENTRY_SEED, merchant.as_ref(), &index.to_le_bytes()],
+        &crate::ID,
+    )
+}
+
+/// Listing entry pointing at the `index`-th affiliate added to `pool`
+pub fn find_affiliate_entry(pool: &Pubkey, index: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[AFFILIATE_ENTRY_SEED, pool.as_ref(), &index.to_le_bytes()],
+        &crate::ID,
+    )
+}
+
+/// Index resolving `ref_id` to its affiliate account within `pool`
+pub fn find_ref_id_index(pool: &Pubkey, ref_id: &str) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[REF_ID_SEED, pool.as_ref(), ref_id.as_bytes()], &crate::ID)
+}
+
+/// Receipt of the sale recorded under `order_id` in `pool`
+pub fn find_sale_receipt(pool: &Pubkey, order_id: &str) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[RECEIPT_SEED, pool.as_ref(), order_id.as_bytes()],
+        &crate::ID,
+    )
+}
+
+/// Recurring top-up schedule for `pool`, also the delegate it pulls funds as
+pub fn find_topup_schedule(pool: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[TOPUP_SEED, pool.as_ref()], &crate::ID)
+}
+
+/// Top affiliates by volume for `pool`
+pub fn find_leaderboard(pool: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[LEADERBOARD_SEED, pool.as_ref()], &crate::ID)
+}
+
+/// Campaign `campaign_id` running within `pool`
+pub fn find_campaign(pool: &Pubkey, campaign_id: &str) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[CAMPAIGN_SEED, pool.as_ref(), campaign_id.as_bytes()],
+        &crate::ID,
+    )
+}
+
+/// Affiliate group `group_id` within `pool`
+pub fn find_affiliate_group(pool: &Pubkey, group_id: &str) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[GROUP_SEED, pool.as_ref(), group_id.as_bytes()],
+        &crate::ID,
+    )
+}
+
+/// Regional partition `region` of `pool`
+pub fn find_region(pool: &Pubkey, region: &[u8; 2]) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[REGION_SEED, pool.as_ref(), region], &crate::ID)
+}
+
+/// Reference a buyer's payment must carry to back `pool`'s `nonce`-th
+/// payment-proven sale. Never holds an account
+pub fn find_payment_nonce_reference(pool: &Pubkey, nonce: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[PAYMENT_NONCE_SEED, pool.as_ref(), &nonce.to_le_bytes()],
+        &crate::ID,
+    )
+}
+
+/// Archived reporting period `period_index` of `pool`
+pub fn find_pool_period(pool: &Pubkey, period_index: u32) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[POOL_PERIOD_SEED, pool.as_ref(), &period_index.to_le_bytes()],
+        &crate::ID,
+    )
+}
+
+/// PDA the pool's affiliate tree is created under and that signs its updates
+pub fn find_affiliate_tree_authority(pool: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[AFFILIATE_TREE_SEED, pool.as_ref()], &crate::ID)
+}
+
+/// Approval record of `affiliate` while its pool requires operator sign-off
+pub fn find_pending_affiliate(affiliate: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[PENDING_AFFILIATE_SEED, affiliate.as_ref()], &crate::ID)
+}
+
+/// Grant letting `session` process sales for `pool` until it expires
+pub fn find_session_key(pool: &Pubkey, session: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[SESSION_KEY_SEED, pool.as_ref(), session.as_ref()],
+        &crate::ID,
+    )
+}
+
+/// Signer over `pool`'s compressed sale receipt tree
+pub fn find_receipt_tree_authority(pool: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[RECEIPT_TREE_SEED, pool.as_ref()], &crate::ID)
+}
+
+/// Snapshot of `affiliate`'s stats for `period`
+pub fn find_affiliate_snapshot(affiliate: &Pubkey, period: u32) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            AFFILIATE_SNAPSHOT_SEED,
+            affiliate.as_ref(),
+            &period.to_le_bytes(),
+        ],
+        &crate::ID,
+    )
+}
+
+/// Payout statement of `affiliate` for `period`
+pub fn find_statement(affiliate: &Pubkey, period: u32) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[STATEMENT_SEED, affiliate.as_ref(), &period.to_le_bytes()],
+        &crate::ID,
+    )
+}
+
+/// Signer `storefront_program` redeems store credit of `pool`'s affiliates
+/// with, via CPI
+pub fn find_storefront_authority(pool: &Pubkey, storefront_program: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[STOREFRONT_AUTHORITY_SEED, pool.as_ref()],
+        storefront_program,
+    )
+}
+
+/// Native treasury of `governance` under an SPL Governance deployment;
+/// the merchant of a DAO-owned pool
+pub fn find_governance_treasury(governance_program: &Pubkey, governance: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[GOVERNANCE_TREASURY_SEED, governance.as_ref()],
+        governance_program,
+    )
+}
+
+/// Escrow token account (ATA of the escrow authority) for `pool` and `mint`
+pub fn get_escrow_token_address(pool: &Pubkey, mint: &Pubkey, token_program: &Pubkey) -> Pubkey {
+    get_versioned_escrow_token_address(pool, 0, mint, token_program)
+}
+
+/// Escrow token account for `pool` and `mint` under escrow seed `version`
+pub fn get_versioned_escrow_token_address(
+    pool: &Pubkey,
+    version: u8,
+    mint: &Pubkey,
+    token_program: &Pubkey,
+) -> Pubkey {
+    let (escrow_authority, _) = find_versioned_escrow_authority(pool, version);
+    get_associated_token_address_with_program_id(&escrow_authority, mint, token_program)
+}
@@ -0,0 +1,120 @@
+//! Resolution of the remaining accounts instructions take a variable number
+//! of.
+//!
+//! Each such instruction takes its remaining accounts as fixed-size groups,
+//! one per item and in item order, and fails with its own error when a group
+//! is missing or doesn't check out:
+//!
+//! | Instruction                 | Group                                                      | Error                             |
+//! |-----------------------------|------------------------------------------------------------|-----------------------------------|
+//! | `release_matured_holdbacks` | sale receipt, affiliate account, commission token account  | `InvalidHoldbackAccounts`         |
+//! | `flag_pool`                 | affiliate account, signing affiliate wallet                | `InvalidFlagAccounts`             |
+//! | `process_sale_multi`        | affiliate account, commission token account                | `InvalidSplitAccounts`            |
+//! | `add_affiliates_batch`      | affiliate account, affiliate entry, ref_id index (all new) | `InvalidAffiliateBatchAccounts`   |
+//! | `migrate_escrow_authority`  | settlement mint, escrow, new escrow ATA                    | `InvalidEscrowMigrationAccounts`  |
+//! | `set_payout_splits`         | payout split token account                                 | `InvalidPayoutSplits`             |
+//! | `pay_out_compressed`        | affiliate account                                          | `InvalidCompressedPayoutAccounts` |
+//!
+//! The commission token account is the affiliate account's `payout_account`
+//! when registered, otherwise a token account in the pool's primary mint
+//! owned by the affiliate wallet. Merkle proofs are passed whole, not
+//! grouped. Sales and claims paying an affiliate with payout splits take
+//! its split token accounts, in order, and guardians co-sign
+//! `propose_merchant_recovery` and `recover_merchant` as plain signing
+//! accounts. `redio-client` packs every layout in its `remaining` module.
+
+use anchor_lang::{prelude::*, system_program::CreateAccount, Space};
+use anchor_spl::token_interface::TokenAccount;
+
+use crate::{AffiliateAccount, ErrorCode};
+
+/// `accounts` as groups of `N`, failing with `error` unless they divide
+/// evenly into `count` groups, or into any number of them when `None`
+pub fn groups<'a, 'info, const N: usize>(
+    accounts: &'a [AccountInfo<'info>],
+    count: Option<usize>,
+    error: ErrorCode,
+) -> Result<Vec<&'a [AccountInfo<'info>; N]>> {
+    let complete = match count {
+        Some(count) => accounts.len() == count * N,
+        None => accounts.len().is_multiple_of(N),
+    };
+    if !complete {
+        return Err(error.into());
+    }
+    accounts
+        .chunks_exact(N)
+        .map(|group| group.try_into().map_err(|_| error.into()))
+        .collect()
+}
+
+/// Bump of `info` as the PDA of `seeds`, failing with `error` if it's
+/// another address
+pub fn expect_pda(info: &AccountInfo, seeds: &[&[u8]], error: ErrorCode) -> Result<u8> {
+    let (address, bump) = Pubkey::find_program_address(seeds, &crate::ID);
+    require_keys_eq!(info.key(), address, error);
+    Ok(bump)
+}
+
+/// A grouped affiliate account, checked to belong to `pool`
+pub fn affiliate_of<'info>(
+    info: &'info AccountInfo<'info>,
+    pool: &Pubkey,
+    error: ErrorCode,
+) -> Result<Account<'info, AffiliateAccount>> {
+    let affiliate = Account::<AffiliateAccount>::try_from(info)?;
+    require_keys_eq!(affiliate.pool, *pool, error);
+    Ok(affiliate)
+}
+
+/// A grouped commission token account, checked to be the one `affiliate`
+/// is paid to in `mint`
+pub fn commission_account_of<'info>(
+    info: &'info AccountInfo<'info>,
+    affiliate: &AffiliateAccount,
+    mint: &Pubkey,
+    error: ErrorCode,
+) -> Result<InterfaceAccount<'info, TokenAccount>> {
+    let token_account = InterfaceAccount::<TokenAccount>::try_from(info)?;
+    let is_payout_account = match affiliate.payout_account {
+        Some(payout_account) => token_account.key() == payout_account,
+        None => token_account.owner == affiliate.wallet,
+    };
+    if !is_payout_account || token_account.mint != *mint {
+        return Err(error.into());
+    }
+    Ok(token_account)
+}
+
+/// Create `info` as the program account at the PDA of `seeds`, sized for
+/// `T` and stamped with its discriminator so it loads as an `Account<T>`.
+/// Fails with `error` if `info` isn't that PDA; returns its bump
+pub fn create_pda_account<'info, T: Discriminator + Space>(
+    info: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    seeds: &[&[u8]],
+    error: ErrorCode,
+) -> Result<u8> {
+    let bump = expect_pda(info, seeds, error)?;
+    let bump_seed = [bump];
+    let mut signer_seeds = seeds.to_vec();
+    signer_seeds.push(&bump_seed);
+
+    let space = 8 + T::INIT_SPACE;
+    anchor_lang::system_program::create_account(
+        CpiContext::new_with_signer(
+            system_program.clone(),
+            CreateAccount {
+                from: payer.clone(),
+                to: info.clone(),
+            },
+            &[&signer_seeds[..]],
+        ),
+        Rent::get()?.minimum_balance(space),
+        space as u64,
+        &crate::ID,
+    )?;
+    info.try_borrow_mut_data()?[..8].copy_from_slice(T::DISCRIMINATOR);
+    Ok(bump)
+}
@@ -0,0 +1,26 @@
+#!/usr/bin/env bash
+# Dump the mainnet programs and accounts Anchor.toml loads into the test
+# validator: SPL Account Compression and Noop for the compressed affiliate
+# and receipt trees, and Light Protocol's programs and a Light state tree for
+# compressed payouts. Files already fetched are kept.
+set -euo pipefail
+cd "$(dirname "$0")"
+url=${FIXTURES_RPC_URL:-https://api.mainnet-beta.solana.com}
+
+program() {
+    [ -f "$2.so" ] || solana program dump --url "$url" "$1" "$2.so"
+}
+account() {
+    [ -f "$2.json" ] || solana account --url "$url" "$1" --output json-compact --output-file "$2.json"
+}
+
+program cmtDvXumGCrqC1Age74AVPhSRVXJMd8PJS91L8KbNCK spl_account_compression
+program noopb9bkMVfRPU8AsbpTUg8AQkHtKwMYZiFUjNRtMmV spl_noop
+program cTokenmWW8bLPjZEBAUgYy3zKxQZW6VKi7bqNFEVv3m light_compressed_token
+program SySTEM1eSU2p4BGQfQpimFEWWSC1XDFeun3Nqzz3rT7 light_system_program
+program compr6CUsB5m2jS4Y3831ztGSTnDpnKJTKS95d64XVq light_account_compression
+
+# The Light system program's registration with account compression, and the
+# state tree compressed payouts are appended to
+account 35hkDgaAKwMCaxRz2ocSZ6NaUrtKkyNqU6c4RV3tYJRh light_registered_program
+account smt1NamzXdq4AMqS2fS2F1i5KTYPZRhoHgWx38d8WsT light_state_tree
@@ -1,6 +1,17 @@
 import * as anchor from "@coral-xyz/anchor";
 import { Program } from "@coral-xyz/anchor";
-import { PublicKey, Keypair, SystemProgram, LAMPORTS_PER_SOL } from "@solana/web3.js";
+import {
+  PublicKey,
+  Keypair,
+  SystemProgram,
+  LAMPORTS_PER_SOL,
+  SYSVAR_INSTRUCTIONS_PUBKEY,
+  NONCE_ACCOUNT_LENGTH,
+  NonceAccount,
+  sendAndConfirmRawTransaction,
+  Transaction,
+  TransactionInstruction,
+} from "@solana/web3.js";
 import {
   TOKEN_PROGRAM_ID,
   ASSOCIATED_TOKEN_PROGRAM_ID,
@@ -9,8 +20,12 @@ import {
   getAccount,
   getOrCreateAssociatedTokenAccount,
   getAssociatedTokenAddressSync,
+  approve,
+  createTransferInstruction,
+  createTransferCheckedInstruction,
 } from "@solana/spl-token";
 import { expect } from "chai";
+import { createHash } from "crypto";
 import { RedioContract } from "../target/types/redio_contract";
 
 describe("USDC Affiliate Escrow - Multi Pool", () => {
@@ -27,18 +42,89 @@ describe("USDC Affiliate Escrow - Multi Pool", () => {
   let affiliateUsdc: PublicKey;
   let affiliate2Usdc: PublicKey;
 
-  const POOL_ID_1 = "standard_pool";
+  const POOL_ID_1 = "standard-pool";
   let merchantPoolPda1: PublicKey;
   let escrowAuthorityPda1: PublicKey;
   let escrowUsdc1: PublicKey;
   let affiliatePda1: PublicKey;
 
-  const POOL_ID_2 = "vip_pool";
+  const POOL_ID_2 = "vip-pool";
   let merchantPoolPda2: PublicKey;
   let escrowAuthorityPda2: PublicKey;
   let escrowUsdc2: PublicKey;
   let affiliatePda2: PublicKey;
 
+  let registryPda: PublicKey;
+  let reputationPda: PublicKey;
+  let configPda: PublicKey;
+  const poolEntryPda = (index: number) =>
+    PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("pool_entry"),
+        merchant.publicKey.toBuffer(),
+        new anchor.BN(index).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    )[0];
+  const affiliateEntryPda = (pool: PublicKey, index: number) =>
+    PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("affiliate_entry"),
+        pool.toBuffer(),
+        new anchor.BN(index).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    )[0];
+  const refIdPda = (pool: PublicKey, refId: string) =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("ref_id"), pool.toBuffer(), Buffer.from(refId)],
+      program.programId
+    )[0];
+
+  // Pool seeds carry the SHA-256 of the pool id rather than the id itself
+  const poolPda = (merchantKey: PublicKey, poolId: string) =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("pool"), merchantKey.toBuffer(), createHash("sha256").update(poolId).digest()],
+      program.programId
+    )[0];
+
+  // MerchantPool is zero-copy: ids are fixed-size byte arrays with a length
+  const fixedString = (bytes: number[], len: number) => Buffer.from(bytes.slice(0, len)).toString();
+
+  const receiptPda = (pool: PublicKey, orderId: string) =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("receipt"), pool.toBuffer(), Buffer.from(orderId)],
+      program.programId
+    )[0];
+
+  // ProcessSaleArgs / CampaignSaleArgs with no override, memo or hash
+  const campaignSaleArgs = (saleAmount: number, orderId: string, args: { idempotent?: boolean } = {}) => ({
+    saleAmount: new anchor.BN(saleAmount),
+    orderId,
+    memo: null,
+    metadataHash: null,
+    idempotent: false,
+    ...args,
+  });
+  const saleArgs = (saleAmount: number, orderId: string, args: Record<string, any> = {}) => ({
+    ...campaignSaleArgs(saleAmount, orderId),
+    commissionOverrideBps: null,
+    ...args,
+  });
+  const activateSelf = (pool: PublicKey, wallet: Keypair) =>
+    program.methods
+      .activateSelf()
+      .accounts({
+        merchantPool: pool,
+        affiliateAccount: PublicKey.findProgramAddressSync(
+          [Buffer.from("affiliate"), pool.toBuffer(), wallet.publicKey.toBuffer()],
+          program.programId
+        )[0],
+        affiliateWallet: wallet.publicKey,
+      })
+      .signers([wallet])
+      .rpc();
+
   const REF_ID = "AFF001";
   const REF_ID_2 = "VIP001";
   const COMMISSION_RATE_1 = 500;
@@ -111,10 +197,7 @@ describe("USDC Affiliate Escrow - Multi Pool", () => {
     console.log("✓ Minted 1000 USDC to merchant");
 
     // Derive PDAs for Pool 1
-    [merchantPoolPda1] = PublicKey.findProgramAddressSync(
-      [Buffer.from("pool"), merchant.publicKey.toBuffer(), Buffer.from(POOL_ID_1)],
-      program.programId
-    );
+    merchantPoolPda1 = poolPda(merchant.publicKey, POOL_ID_1);
 
     [escrowAuthorityPda1] = PublicKey.findProgramAddressSync(
       [Buffer.from("escrow_authority"), merchantPoolPda1.toBuffer()],
@@ -129,10 +212,7 @@ describe("USDC Affiliate Escrow - Multi Pool", () => {
     );
 
     // Derive PDAs for Pool 2
-    [merchantPoolPda2] = PublicKey.findProgramAddressSync(
-      [Buffer.from("pool"), merchant.publicKey.toBuffer(), Buffer.from(POOL_ID_2)],
-      program.programId
-    );
+    merchantPoolPda2 = poolPda(merchant.publicKey, POOL_ID_2);
 
     [escrowAuthorityPda2] = PublicKey.findProgramAddressSync(
       [Buffer.from("escrow_authority"), merchantPoolPda2.toBuffer()],
@@ -146,19 +226,55 @@ describe("USDC Affiliate Escrow - Multi Pool", () => {
       program.programId
     );
 
+    [registryPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("merchant_registry"), merchant.publicKey.toBuffer()],
+      program.programId
+    );
+
+    [reputationPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("merchant_reputation"), merchant.publicKey.toBuffer()],
+      program.programId
+    );
+
     console.log("✓ Derived PDAs for both pools");
+
+    [configPda] = PublicKey.findProgramAddressSync([Buffer.from("config")], program.programId);
+    const [programData] = PublicKey.findProgramAddressSync(
+      [program.programId.toBuffer()],
+      new PublicKey("BPFLoaderUpgradeab1e11111111111111111111111")
+    );
+    await program.methods
+      .initializeConfig()
+      .accounts({
+        config: configPda,
+        admin: provider.wallet.publicKey,
+        program: program.programId,
+        programData,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+
+    console.log("✓ Initialized program config");
   });
 
   describe("Initialize Multiple Pools", () => {
     it("Creates first merchant pool (standard)", async () => {
       await program.methods
-        .initializePool(POOL_ID_1, COMMISSION_RATE_1, new anchor.BN(INITIAL_DEPOSIT))
+        .initializePool({
+          poolId: POOL_ID_1,
+          commissionRate: COMMISSION_RATE_1,
+          initialDeposit: new anchor.BN(INITIAL_DEPOSIT),
+        })
         .accounts({
           merchantPool: merchantPoolPda1,
           merchant: merchant.publicKey,
+          rentPayer: merchant.publicKey,
           merchantUsdc: merchantUsdc,
           escrowAuthority: escrowAuthorityPda1,
           escrowUsdc: escrowUsdc1,
+          merchantRegistry: registryPda,
+          merchantReputation: reputationPda,
+          poolEntry: poolEntryPda(0),
           usdcMint: usdcMint,
           tokenProgram: TOKEN_PROGRAM_ID,
           associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
@@ -169,9 +285,9 @@ describe("USDC Affiliate Escrow - Multi Pool", () => {
 
       const poolAccount = await program.account.merchantPool.fetch(merchantPoolPda1);
       expect(poolAccount.merchant.toString()).to.equal(merchant.publicKey.toString());
-      expect(poolAccount.poolId).to.equal(POOL_ID_1);
+      expect(fixedString(poolAccount.poolId, poolAccount.poolIdLen)).to.equal(POOL_ID_1);
       expect(poolAccount.commissionRate).to.equal(COMMISSION_RATE_1);
-      expect(poolAccount.isActive).to.be.true;
+      expect(poolAccount.isActive).to.equal(1);
 
       const escrowAccount = await getAccount(provider.connection, escrowUsdc1);
       expect(Number(escrowAccount.amount)).to.equal(INITIAL_DEPOSIT);
@@ -180,13 +296,21 @@ describe("USDC Affiliate Escrow - Multi Pool", () => {
 
     it("Creates second merchant pool (VIP)", async () => {
       await program.methods
-        .initializePool(POOL_ID_2, COMMISSION_RATE_2, new anchor.BN(INITIAL_DEPOSIT))
+        .initializePool({
+          poolId: POOL_ID_2,
+          commissionRate: COMMISSION_RATE_2,
+          initialDeposit: new anchor.BN(INITIAL_DEPOSIT),
+        })
         .accounts({
           merchantPool: merchantPoolPda2,
           merchant: merchant.publicKey,
+          rentPayer: merchant.publicKey,
           merchantUsdc: merchantUsdc,
           escrowAuthority: escrowAuthorityPda2,
           escrowUsdc: escrowUsdc2,
+          merchantRegistry: registryPda,
+          merchantReputation: reputationPda,
+          poolEntry: poolEntryPda(1),
           usdcMint: usdcMint,
           tokenProgram: TOKEN_PROGRAM_ID,
           associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
@@ -197,21 +321,40 @@ describe("USDC Affiliate Escrow - Multi Pool", () => {
 
       const poolAccount = await program.account.merchantPool.fetch(merchantPoolPda2);
       expect(poolAccount.merchant.toString()).to.equal(merchant.publicKey.toString());
-      expect(poolAccount.poolId).to.equal(POOL_ID_2);
+      expect(fixedString(poolAccount.poolId, poolAccount.poolIdLen)).to.equal(POOL_ID_2);
       expect(poolAccount.commissionRate).to.equal(COMMISSION_RATE_2);
-      expect(poolAccount.isActive).to.be.true;
+      expect(poolAccount.isActive).to.equal(1);
 
       const escrowAccount = await getAccount(provider.connection, escrowUsdc2);
       expect(Number(escrowAccount.amount)).to.equal(INITIAL_DEPOSIT);
+
+      const registry = await program.account.merchantRegistry.fetch(registryPda);
+      expect(registry.poolCount.toNumber()).to.equal(2);
+      const entry = await program.account.poolEntry.fetch(poolEntryPda(1));
+      expect(entry.pool.toString()).to.equal(merchantPoolPda2.toString());
       console.log("✓ Pool 2 (VIP) initialized with 10% commission");
     });
 
+    it("Makes the backend the processor of both pools", async () => {
+      for (const pool of [merchantPoolPda1, merchantPoolPda2]) {
+        await program.methods
+          .setPoolProcessor(backend.publicKey)
+          .accounts({
+            merchantPool: pool,
+            merchant: merchant.publicKey,
+          })
+          .signers([merchant])
+          .rpc();
+
+        const poolAccount = await program.account.merchantPool.fetch(pool);
+        expect(poolAccount.processor.toString()).to.equal(backend.publicKey.toString());
+      }
+      console.log("✓ Backend set as processor of both pools");
+    });
+
     it("Fails to create pool with invalid pool ID", async () => {
       const INVALID_POOL_ID = ""; // Empty pool ID
-      const [invalidPoolPda] = PublicKey.findProgramAddressSync(
-        [Buffer.from("pool"), merchant.publicKey.toBuffer(), Buffer.from(INVALID_POOL_ID)],
-        program.programId
-      );
+      const invalidPoolPda = poolPda(merchant.publicKey, INVALID_POOL_ID);
 
       const [invalidEscrowAuthorityPda] = PublicKey.findProgramAddressSync(
         [Buffer.from("escrow_authority"), invalidPoolPda.toBuffer()],
@@ -222,13 +365,21 @@ describe("USDC Affiliate Escrow - Multi Pool", () => {
 
       try {
         await program.methods
-          .initializePool(INVALID_POOL_ID, COMMISSION_RATE_1, new anchor.BN(INITIAL_DEPOSIT))
+          .initializePool({
+            poolId: INVALID_POOL_ID,
+            commissionRate: COMMISSION_RATE_1,
+            initialDeposit: new anchor.BN(INITIAL_DEPOSIT),
+          })
           .accounts({
             merchantPool: invalidPoolPda,
             merchant: merchant.publicKey,
+            rentPayer: merchant.publicKey,
             merchantUsdc: merchantUsdc,
             escrowAuthority: invalidEscrowAuthorityPda,
             escrowUsdc: invalidEscrowUsdc,
+            merchantRegistry: registryPda,
+            merchantReputation: reputationPda,
+            poolEntry: poolEntryPda(2),
             usdcMint: usdcMint,
             tokenProgram: TOKEN_PROGRAM_ID,
             associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
@@ -243,6 +394,46 @@ describe("USDC Affiliate Escrow - Multi Pool", () => {
         console.log("✓ Rejected invalid pool ID");
       }
     });
+
+    it("Fails to create pool with a pool ID over 128 characters", async () => {
+      const LONG_POOL_ID = "sku-".repeat(32) + "x"; // 129 characters
+      const longPoolPda = poolPda(merchant.publicKey, LONG_POOL_ID);
+      const [longEscrowAuthorityPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("escrow_authority"), longPoolPda.toBuffer()],
+        program.programId
+      );
+
+      try {
+        await program.methods
+          .initializePool({
+            poolId: LONG_POOL_ID,
+            commissionRate: COMMISSION_RATE_1,
+            initialDeposit: new anchor.BN(INITIAL_DEPOSIT),
+          })
+          .accounts({
+            merchantPool: longPoolPda,
+            merchant: merchant.publicKey,
+            rentPayer: merchant.publicKey,
+            merchantUsdc: merchantUsdc,
+            escrowAuthority: longEscrowAuthorityPda,
+            escrowUsdc: getAssociatedTokenAddressSync(usdcMint, longEscrowAuthorityPda, true),
+            merchantRegistry: registryPda,
+            merchantReputation: reputationPda,
+            poolEntry: poolEntryPda(2),
+            usdcMint: usdcMint,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([merchant])
+          .rpc();
+
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("InvalidPoolId");
+        console.log("✓ Rejected over-long pool ID");
+      }
+    });
   });
 
   describe("Update Pool Commission", () => {
@@ -250,7 +441,7 @@ describe("USDC Affiliate Escrow - Multi Pool", () => {
       const NEW_RATE = 750;
 
       await program.methods
-        .updatePoolCommission(NEW_RATE)
+        .updatePoolCommission(NEW_RATE, null, null)
         .accounts({
           merchantPool: merchantPoolPda1,
           merchant: merchant.publicKey,
@@ -263,13 +454,102 @@ describe("USDC Affiliate Escrow - Multi Pool", () => {
       console.log("✓ Pool 1 commission updated to 7.5%");
 
       await program.methods
-        .updatePoolCommission(COMMISSION_RATE_1)
+        .updatePoolCommission(COMMISSION_RATE_1, null, null)
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          merchant: merchant.publicKey,
+        })
+        .signers([merchant])
+        .rpc();
+    });
+
+    it("Rejects rate changes approved with a lapsed valid-until time", async () => {
+      const updateUntil = (validUntil: number) =>
+        program.methods
+          .updatePoolCommission(COMMISSION_RATE_1, null, new anchor.BN(validUntil))
+          .accounts({
+            merchantPool: merchantPoolPda1,
+            merchant: merchant.publicKey,
+          })
+          .signers([merchant])
+          .rpc();
+      const now = Math.floor(Date.now() / 1000);
+
+      try {
+        await updateUntil(now - 3600);
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("TransactionExpired");
+      }
+
+      await updateUntil(now + 3600);
+      console.log("✓ Expired authorization rejected, current one accepted");
+    });
+
+    it("Rejects rates outside the program's band", async () => {
+      const setBand = (min: number, max: number) =>
+        program.methods
+          .setCommissionRateBand(min, max)
+          .accounts({ config: configPda, admin: provider.wallet.publicKey })
+          .rpc();
+
+      await setBand(100, 5000);
+      try {
+        await program.methods
+          .updatePoolCommission(6000, null, null)
+          .accounts({
+            merchantPool: merchantPoolPda1,
+            merchant: merchant.publicKey,
+          })
+          .signers([merchant])
+          .rpc();
+
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("CommissionRateOutOfBand");
+        console.log("✓ Rejected rate above the band");
+      } finally {
+        await setBand(0, 10000);
+      }
+    });
+  });
+
+  describe("Pool Metadata", () => {
+    it("Sets display name and metadata URI for pool 1", async () => {
+      const NAME = "Standard Partners";
+      const URI = "https://example.com/pools/standard.json";
+
+      await program.methods
+        .updatePoolMetadata(NAME, URI)
         .accounts({
           merchantPool: merchantPoolPda1,
           merchant: merchant.publicKey,
         })
         .signers([merchant])
         .rpc();
+
+      const poolAccount = await program.account.merchantPool.fetch(merchantPoolPda1);
+      expect(fixedString(poolAccount.displayName, poolAccount.displayNameLen)).to.equal(NAME);
+      expect(fixedString(poolAccount.metadataUri, poolAccount.metadataUriLen)).to.equal(URI);
+      console.log("✓ Pool 1 metadata updated");
+    });
+
+    it("Rejects a display name over 64 characters", async () => {
+      try {
+        await program.methods
+          .updatePoolMetadata("x".repeat(65), "")
+          .accounts({
+            merchantPool: merchantPoolPda1,
+            merchant: merchant.publicKey,
+          })
+          .signers([merchant])
+          .rpc();
+
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("InvalidDisplayName");
+        console.log("✓ Rejected oversized display name");
+      }
     });
   });
 
@@ -280,8 +560,11 @@ describe("USDC Affiliate Escrow - Multi Pool", () => {
         .accounts({
           merchantPool: merchantPoolPda1,
           affiliateAccount: affiliatePda1,
+          affiliateEntry: affiliateEntryPda(merchantPoolPda1, 0),
+          refIdIndex: refIdPda(merchantPoolPda1, REF_ID),
           affiliateWallet: affiliate.publicKey,
           merchant: merchant.publicKey,
+          rentPayer: merchant.publicKey,
           systemProgram: SystemProgram.programId,
         })
         .signers([merchant])
@@ -291,6 +574,12 @@ describe("USDC Affiliate Escrow - Multi Pool", () => {
       expect(affiliateAccount.refId).to.equal(REF_ID);
       expect(affiliateAccount.pool.toString()).to.equal(merchantPoolPda1.toString());
       expect(affiliateAccount.isActive).to.be.true;
+      expect(affiliateAccount.affiliateIndex.toNumber()).to.equal(0);
+
+      const pool = await program.account.merchantPool.fetch(merchantPoolPda1);
+      expect(pool.affiliateCount.toNumber()).to.equal(1);
+      const entry = await program.account.affiliateEntry.fetch(affiliateEntryPda(merchantPoolPda1, 0));
+      expect(entry.affiliate.toString()).to.equal(affiliatePda1.toString());
       console.log("✓ Affiliate added to Pool 1 (standard)");
     });
 
@@ -300,8 +589,11 @@ describe("USDC Affiliate Escrow - Multi Pool", () => {
         .accounts({
           merchantPool: merchantPoolPda2,
           affiliateAccount: affiliatePda2,
+          affiliateEntry: affiliateEntryPda(merchantPoolPda2, 0),
+          refIdIndex: refIdPda(merchantPoolPda2, REF_ID_2),
           affiliateWallet: affiliate2.publicKey,
           merchant: merchant.publicKey,
+          rentPayer: merchant.publicKey,
           systemProgram: SystemProgram.programId,
         })
         .signers([merchant])
@@ -313,17 +605,206 @@ describe("USDC Affiliate Escrow - Multi Pool", () => {
       expect(affiliateAccount.isActive).to.be.true;
       console.log("✓ Affiliate added to Pool 2 (VIP)");
     });
+
+    it("Holds payouts until each affiliate activates itself", async () => {
+      expect((await program.account.affiliateAccount.fetch(affiliatePda1)).activatedByAffiliate).to.be.false;
+      try {
+        await program.methods
+          .processSale(saleArgs(10_000_000, "unactivated-1"))
+          .accounts({
+            merchantPool: merchantPoolPda1,
+            affiliateAccount: affiliatePda1,
+            affiliateWallet: affiliate.publicKey,
+            escrowAuthority: escrowAuthorityPda1,
+            escrowUsdc: escrowUsdc1,
+            affiliateUsdc: affiliateUsdc,
+            saleReceipt: receiptPda(merchantPoolPda1, "unactivated-1"),
+            usdcMint: usdcMint,
+            authority: backend.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([backend])
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("AffiliateNotActivated");
+      }
+
+      await activateSelf(merchantPoolPda1, affiliate);
+      await activateSelf(merchantPoolPda2, affiliate2);
+      expect((await program.account.affiliateAccount.fetch(affiliatePda1)).activatedByAffiliate).to.be.true;
+      expect((await program.account.affiliateAccount.fetch(affiliatePda2)).activatedByAffiliate).to.be.true;
+
+      try {
+        await activateSelf(merchantPoolPda1, affiliate);
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("AffiliateAlreadyActivated");
+      }
+      console.log("✓ Affiliates consented before their first payout");
+    });
+
+    it("Rebrands the pool 2 affiliate's ref_id", async () => {
+      const NEW_REF_ID = "VIP2024";
+
+      await program.methods
+        .updateAffiliateRefId(NEW_REF_ID)
+        .accounts({
+          merchantPool: merchantPoolPda2,
+          affiliateAccount: affiliatePda2,
+          oldRefIdIndex: refIdPda(merchantPoolPda2, REF_ID_2),
+          newRefIdIndex: refIdPda(merchantPoolPda2, NEW_REF_ID),
+          merchant: merchant.publicKey,
+          rentPayer: merchant.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([merchant])
+        .rpc();
+
+      const affiliateAccount = await program.account.affiliateAccount.fetch(affiliatePda2);
+      expect(affiliateAccount.refId).to.equal(NEW_REF_ID);
+
+      const index = await program.account.refIdIndex.fetch(refIdPda(merchantPoolPda2, NEW_REF_ID));
+      expect(index.affiliate.toString()).to.equal(affiliatePda2.toString());
+      expect(await provider.connection.getAccountInfo(refIdPda(merchantPoolPda2, REF_ID_2))).to.be.null;
+      console.log("✓ Affiliate ref_id updated to", NEW_REF_ID);
+    });
+
+    it("Rejects a lookalike ref_id", async () => {
+      const SPOOFED_REF_ID = "\u0412IP2024"; // Cyrillic "В" posing as "VIP2024"
+
+      try {
+        await program.methods
+          .updateAffiliateRefId(SPOOFED_REF_ID)
+          .accounts({
+            merchantPool: merchantPoolPda2,
+            affiliateAccount: affiliatePda2,
+            oldRefIdIndex: refIdPda(merchantPoolPda2, "VIP2024"),
+            newRefIdIndex: refIdPda(merchantPoolPda2, SPOOFED_REF_ID),
+            merchant: merchant.publicKey,
+            rentPayer: merchant.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([merchant])
+          .rpc();
+
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("InvalidRefIdCharset");
+        console.log("✓ Rejected lookalike ref_id");
+      }
+    });
   });
 
   describe("Process Sales in Different Pools", () => {
     const SALE_AMOUNT = 100_000_000;
 
+    it("Quotes commission for pool 1 without paying out", async () => {
+      const quote = await program.methods
+        .quoteCommission(new anchor.BN(SALE_AMOUNT))
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          affiliateAccount: affiliatePda1,
+        })
+        .view();
+
+      expect(quote.commissionRate).to.equal(COMMISSION_RATE_1);
+      expect(quote.commission.toNumber()).to.equal(5_000_000);
+      expect(quote.affiliatePayout.toNumber()).to.equal(5_000_000);
+
+      const affiliateAccount = await program.account.affiliateAccount.fetch(affiliatePda1);
+      expect(affiliateAccount.salesCount.toNumber()).to.equal(0);
+      console.log("✓ Quoted commission for Pool 1:", quote.commission.toNumber() / 1_000_000, "USDC");
+    });
+
+    it("Rejects or clamps commissions above the per-sale cap", async () => {
+      const CAP = 1_000_000;
+      const setCap = (cap: number, mode: object) =>
+        program.methods
+          .setCommissionCap(new anchor.BN(cap), mode as any)
+          .accounts({
+            merchantPool: merchantPoolPda1,
+            merchant: merchant.publicKey,
+          })
+          .signers([merchant])
+          .rpc();
+
+      await setCap(CAP, { reject: {} });
+      try {
+        await program.methods
+          .processSale(saleArgs(SALE_AMOUNT, "capped-1"))
+          .accounts({
+            merchantPool: merchantPoolPda1,
+            affiliateAccount: affiliatePda1,
+            affiliateWallet: affiliate.publicKey,
+            escrowAuthority: escrowAuthorityPda1,
+            escrowUsdc: escrowUsdc1,
+            affiliateUsdc: affiliateUsdc,
+            saleReceipt: receiptPda(merchantPoolPda1, "capped-1"),
+            usdcMint: usdcMint,
+            authority: backend.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([backend])
+          .rpc();
+
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("CommissionCapExceeded");
+      }
+
+      await setCap(CAP, { clamp: {} });
+      const quote = await program.methods
+        .quoteCommission(new anchor.BN(SALE_AMOUNT))
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          affiliateAccount: affiliatePda1,
+        })
+        .view();
+      expect(quote.commission.toNumber()).to.equal(CAP);
+
+      await setCap(0, { reject: {} });
+      console.log("✓ Per-sale commission cap rejected and clamped");
+    });
+
+    it("Rejects sales signed by anyone but the processor", async () => {
+      try {
+        await program.methods
+          .processSale(saleArgs(SALE_AMOUNT, "unauthorized-1"))
+          .accounts({
+            merchantPool: merchantPoolPda1,
+            affiliateAccount: affiliatePda1,
+            affiliateWallet: affiliate.publicKey,
+            escrowAuthority: escrowAuthorityPda1,
+            escrowUsdc: escrowUsdc1,
+            affiliateUsdc: affiliateUsdc,
+            saleReceipt: receiptPda(merchantPoolPda1, "unauthorized-1"),
+            usdcMint: usdcMint,
+            authority: affiliate.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([affiliate])
+          .rpc();
+
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("Unauthorized");
+        console.log("✓ Rejected a sale signed by the affiliate");
+      }
+    });
+
     it("Processes sale in pool 1 (5% commission)", async () => {
       const EXPECTED_COMMISSION = 5_000_000;
       const affiliateBalanceBefore = (await getAccount(provider.connection, affiliateUsdc)).amount;
 
       await program.methods
-        .processSale(new anchor.BN(SALE_AMOUNT))
+        .processSale(saleArgs(SALE_AMOUNT, "order-1"))
         .accounts({
           merchantPool: merchantPoolPda1,
           affiliateAccount: affiliatePda1,
@@ -331,6 +812,7 @@ describe("USDC Affiliate Escrow - Multi Pool", () => {
           escrowAuthority: escrowAuthorityPda1,
           escrowUsdc: escrowUsdc1,
           affiliateUsdc: affiliateUsdc,
+          saleReceipt: receiptPda(merchantPoolPda1, "order-1"),
           usdcMint: usdcMint,
           authority: backend.publicKey,
           tokenProgram: TOKEN_PROGRAM_ID,
@@ -346,6 +828,9 @@ describe("USDC Affiliate Escrow - Multi Pool", () => {
       const affiliateAccount = await program.account.affiliateAccount.fetch(affiliatePda1);
       expect(affiliateAccount.totalEarned.toNumber()).to.equal(EXPECTED_COMMISSION);
       expect(affiliateAccount.salesCount.toNumber()).to.equal(1);
+      expect(affiliateAccount.largestSale.toNumber()).to.equal(SALE_AMOUNT);
+      expect(affiliateAccount.totalSaleVolume.toNumber()).to.equal(SALE_AMOUNT);
+      expect(affiliateAccount.lastSaleAt.toNumber()).to.be.greaterThan(0);
 
       const poolAccount = await program.account.merchantPool.fetch(merchantPoolPda1);
       expect(poolAccount.totalVolume.toNumber()).to.equal(SALE_AMOUNT);
@@ -359,7 +844,7 @@ describe("USDC Affiliate Escrow - Multi Pool", () => {
       const affiliateBalanceBefore = (await getAccount(provider.connection, affiliate2Usdc)).amount;
 
       await program.methods
-        .processSale(new anchor.BN(SALE_AMOUNT))
+        .processSale(saleArgs(SALE_AMOUNT, "order-2"))
         .accounts({
           merchantPool: merchantPoolPda2,
           affiliateAccount: affiliatePda2,
@@ -367,6 +852,7 @@ describe("USDC Affiliate Escrow - Multi Pool", () => {
           escrowAuthority: escrowAuthorityPda2,
           escrowUsdc: escrowUsdc2,
           affiliateUsdc: affiliate2Usdc,
+          saleReceipt: receiptPda(merchantPoolPda2, "order-2"),
           usdcMint: usdcMint,
           authority: backend.publicKey,
           tokenProgram: TOKEN_PROGRAM_ID,
@@ -389,145 +875,4465 @@ describe("USDC Affiliate Escrow - Multi Pool", () => {
 
       console.log("✓ Sale processed in Pool 2, commission paid:", EXPECTED_COMMISSION / 1_000_000, "USDC");
     });
-  });
-
-  describe("Pool-specific Escrow Management", () => {
-    it("Deposits to pool 1 escrow", async () => {
-      const DEPOSIT = 50_000_000;
-      const before = (await getAccount(provider.connection, escrowUsdc1)).amount;
 
-      await program.methods
+    it("Treats a replayed order_id as a no-op only when idempotent", async () => {
+      const replay = (idempotent: boolean) =>
+        program.methods
+          .processSale(saleArgs(SALE_AMOUNT, "order-1", { idempotent }))
+          .accounts({
+            merchantPool: merchantPoolPda1,
+            affiliateAccount: affiliatePda1,
+            affiliateWallet: affiliate.publicKey,
+            escrowAuthority: escrowAuthorityPda1,
+            escrowUsdc: escrowUsdc1,
+            affiliateUsdc: affiliateUsdc,
+            saleReceipt: receiptPda(merchantPoolPda1, "order-1"),
+            usdcMint: usdcMint,
+            authority: backend.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([backend])
+          .rpc();
+
+      try {
+        await replay(false);
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("OrderAlreadyProcessed");
+      }
+
+      const balanceBefore = (await getAccount(provider.connection, affiliateUsdc)).amount;
+      await replay(true);
+
+      const balanceAfter = (await getAccount(provider.connection, affiliateUsdc)).amount;
+      expect(balanceAfter).to.equal(balanceBefore);
+      const affiliateAccount = await program.account.affiliateAccount.fetch(affiliatePda1);
+      expect(affiliateAccount.salesCount.toNumber()).to.equal(1);
+      console.log("✓ Replayed order-1 acknowledged without paying twice");
+    });
+
+    it("Applies a processor's commission override within bounds", async () => {
+      await program.methods
+        .setCommissionOverrideBounds(300, 800)
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          merchant: merchant.publicKey,
+        })
+        .signers([merchant])
+        .rpc();
+
+      const overrideAccounts = (orderId: string) => ({
+        merchantPool: merchantPoolPda1,
+        affiliateAccount: affiliatePda1,
+        affiliateWallet: affiliate.publicKey,
+        escrowAuthority: escrowAuthorityPda1,
+        escrowUsdc: escrowUsdc1,
+        affiliateUsdc: affiliateUsdc,
+        saleReceipt: receiptPda(merchantPoolPda1, orderId),
+        usdcMint: usdcMint,
+        authority: backend.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      });
+
+      try {
+        await program.methods
+          .processSale(saleArgs(10_000_000, "override-0", { commissionOverrideBps: 900 }))
+          .accounts(overrideAccounts("override-0"))
+          .signers([backend])
+          .rpc();
+
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("CommissionOverrideOutOfBounds");
+      }
+
+      await program.methods
+        .processSale(
+          saleArgs(10_000_000, "override-1", {
+            commissionOverrideBps: 800,
+            memo: "shop#1001",
+            metadataHash: Array.from(Buffer.alloc(32, 1)),
+          })
+        )
+        .accounts(overrideAccounts("override-1"))
+        .signers([backend])
+        .rpc();
+
+      const receipt = await program.account.saleReceipt.fetch(receiptPda(merchantPoolPda1, "override-1"));
+      expect(receipt.appliedRateBps).to.equal(800);
+      expect(receipt.commission.toNumber()).to.equal(800_000);
+      expect(receipt.memo).to.equal("shop#1001");
+      expect(receipt.metadataHash).to.deep.equal(Array.from(Buffer.alloc(32, 1)));
+      console.log("✓ Override applied at 8% within bounds");
+    });
+  });
+
+  describe("Campaigns", () => {
+    const CAMPAIGN_ID = "spring";
+    let campaignPda: PublicKey;
+    const campaignSale = (orderId: string, amount: number, campaign = campaignPda) =>
+      program.methods
+        .processSaleForCampaign(campaignSaleArgs(amount, orderId))
+        .accounts({
+          sale: {
+            merchantPool: merchantPoolPda1,
+            affiliateAccount: affiliatePda1,
+            affiliateWallet: affiliate.publicKey,
+            escrowAuthority: escrowAuthorityPda1,
+            escrowUsdc: escrowUsdc1,
+            affiliateUsdc: affiliateUsdc,
+            saleReceipt: receiptPda(merchantPoolPda1, orderId),
+            usdcMint: usdcMint,
+            authority: backend.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+          },
+          campaign,
+        })
+        .signers([backend])
+        .rpc();
+
+    before(async () => {
+      [campaignPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("campaign"), merchantPoolPda1.toBuffer(), Buffer.from(CAMPAIGN_ID)],
+        program.programId
+      );
+    });
+
+    it("Pays a campaign sale at the campaign's rate", async () => {
+      await program.methods
+        .createCampaign({
+          campaignId: CAMPAIGN_ID,
+          commissionRate: 2000,
+          budget: new anchor.BN(3_000_000),
+          startsAt: new anchor.BN(0),
+          endsAt: new anchor.BN(0),
+          requiredTags: 0,
+        })
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          campaign: campaignPda,
+          merchant: merchant.publicKey,
+          rentPayer: merchant.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([merchant])
+        .rpc();
+
+      const before = (await getAccount(provider.connection, affiliateUsdc)).amount;
+      await campaignSale("spring-1", 10_000_000);
+
+      const after = (await getAccount(provider.connection, affiliateUsdc)).amount;
+      expect(Number(after - before)).to.equal(2_000_000);
+      const campaign = await program.account.campaign.fetch(campaignPda);
+      expect(campaign.spent.toNumber()).to.equal(2_000_000);
+      expect(campaign.totalVolume.toNumber()).to.equal(10_000_000);
+      console.log("✓ Campaign sale paid at 20%");
+    });
+
+    it("Rejects sales beyond the campaign budget", async () => {
+      try {
+        await campaignSale("spring-2", 10_000_000);
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("CampaignBudgetExceeded");
+        console.log("✓ Rejected sale over the campaign budget");
+      }
+    });
+
+    it("Rejects sales after the campaign ends", async () => {
+      await program.methods
+        .endCampaign()
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          campaign: campaignPda,
+          merchant: merchant.publicKey,
+        })
+        .signers([merchant])
+        .rpc();
+
+      try {
+        await campaignSale("spring-3", 1_000_000);
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("CampaignNotLive");
+        console.log("✓ Rejected sale for an ended campaign");
+      }
+    });
+
+    it("Limits a campaign to affiliates with its required tags", async () => {
+      const VIP = 0b1;
+      const [vipCampaignPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("campaign"), merchantPoolPda1.toBuffer(), Buffer.from("vip")],
+        program.programId
+      );
+      await program.methods
+        .createCampaign({
+          campaignId: "vip",
+          commissionRate: 1000,
+          budget: new anchor.BN(10_000_000),
+          startsAt: new anchor.BN(0),
+          endsAt: new anchor.BN(0),
+          requiredTags: VIP,
+        })
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          campaign: vipCampaignPda,
+          merchant: merchant.publicKey,
+          rentPayer: merchant.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([merchant])
+        .rpc();
+
+      try {
+        await campaignSale("vip-1", 1_000_000, vipCampaignPda);
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("MissingAffiliateTags");
+      }
+
+      await program.methods
+        .setAffiliateTags(VIP | 0b100)
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          affiliateAccount: affiliatePda1,
+          affiliateWallet: affiliate.publicKey,
+          merchant: merchant.publicKey,
+        })
+        .signers([merchant])
+        .rpc();
+      expect((await program.account.affiliateAccount.fetch(affiliatePda1)).tags).to.equal(0b101);
+
+      await campaignSale("vip-1", 1_000_000, vipCampaignPda);
+      const campaign = await program.account.campaign.fetch(vipCampaignPda);
+      expect(campaign.salesCount.toNumber()).to.equal(1);
+      console.log("✓ Tagged VIP affiliate sold through the VIP campaign");
+    });
+  });
+
+  describe("Affiliate Snapshots", () => {
+    const PERIOD = 202610;
+    const snapshotPda = () =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("affiliate_snapshot"), affiliatePda1.toBuffer(), new anchor.BN(PERIOD).toArrayLike(Buffer, "le", 4)],
+        program.programId
+      )[0];
+    const snapshot = (authority: Keypair) =>
+      program.methods
+        .snapshotAffiliate(PERIOD)
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          affiliateAccount: affiliatePda1,
+          snapshot: snapshotPda(),
+          authority: authority.publicKey,
+          rentPayer: authority.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([authority])
+        .rpc();
+
+    it("Only lets the merchant or processor take snapshots", async () => {
+      try {
+        await snapshot(affiliate);
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("Unauthorized");
+        console.log("✓ Affiliate cannot snapshot itself");
+      }
+    });
+
+    it("Copies the affiliate's stats once per period", async () => {
+      await snapshot(merchant);
+
+      const affiliateAccount = await program.account.affiliateAccount.fetch(affiliatePda1);
+      const taken = await program.account.affiliateSnapshot.fetch(snapshotPda());
+      expect(taken.period).to.equal(PERIOD);
+      expect(taken.totalEarned.toNumber()).to.equal(affiliateAccount.totalEarned.toNumber());
+      expect(taken.salesCount.toNumber()).to.equal(affiliateAccount.salesCount.toNumber());
+      expect(taken.totalSaleVolume.toNumber()).to.equal(affiliateAccount.totalSaleVolume.toNumber());
+
+      try {
+        await snapshot(merchant);
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.message).to.not.include("Should have thrown error");
+      }
+      console.log("✓ Snapshot written once for", PERIOD);
+    });
+  });
+
+  describe("Payout Statements", () => {
+    const statementPda = (period: number) =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("statement"), affiliatePda1.toBuffer(), new anchor.BN(period).toArrayLike(Buffer, "le", 4)],
+        program.programId
+      )[0];
+    const generate = (period: number) =>
+      program.methods
+        .generateStatement(period)
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          affiliateAccount: affiliatePda1,
+          statement: statementPda(period),
+          authority: merchant.publicKey,
+          rentPayer: merchant.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([merchant])
+        .rpc();
+
+    it("Closes the affiliate's activity into a statement", async () => {
+      const before = await program.account.affiliateAccount.fetch(affiliatePda1);
+
+      await generate(202610);
+
+      const statement = await program.account.statement.fetch(statementPda(202610));
+      expect(statement.period).to.equal(202610);
+      expect(statement.previousPeriod).to.equal(0);
+      expect(statement.totals.salesCount.toNumber()).to.equal(before.openStatement.salesCount.toNumber());
+      expect(statement.totals.earned.toNumber()).to.equal(before.openStatement.earned.toNumber());
+      expect(statement.totals.receiptsHash).to.deep.equal(before.openStatement.receiptsHash);
+
+      const after = await program.account.affiliateAccount.fetch(affiliatePda1);
+      expect(after.openStatement.salesCount.toNumber()).to.equal(0);
+      expect(after.lastStatementPeriod).to.equal(202610);
+      console.log("✓ Statement generated with", statement.totals.salesCount.toNumber(), "sales");
+    });
+
+    it("Rejects statements for earlier periods", async () => {
+      try {
+        await generate(202609);
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("StatementPeriodNotAfterLast");
+        console.log("✓ Rejected out-of-order statement");
+      }
+    });
+  });
+
+  describe("Pool Heartbeat", () => {
+    it("Lets anyone ping a pool without changing it", async () => {
+      const before = await program.account.merchantPool.fetch(merchantPoolPda1);
+
+      await program.methods
+        .pingPool()
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          escrowAuthority: escrowAuthorityPda1,
+          escrowUsdc: escrowUsdc1,
+        })
+        .rpc();
+
+      const after = await program.account.merchantPool.fetch(merchantPoolPda1);
+      expect(after.totalVolume.toNumber()).to.equal(before.totalVolume.toNumber());
+      expect(after.totalClaimable.toNumber()).to.equal(before.totalClaimable.toNumber());
+      console.log("✓ Heartbeat emitted");
+    });
+  });
+
+  describe("Payout Threshold", () => {
+    const sale = (orderId: string) =>
+      program.methods
+        .processSale(saleArgs(20_000_000, orderId))
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          affiliateAccount: affiliatePda1,
+          affiliateWallet: affiliate.publicKey,
+          escrowAuthority: escrowAuthorityPda1,
+          escrowUsdc: escrowUsdc1,
+          affiliateUsdc: affiliateUsdc,
+          saleReceipt: receiptPda(merchantPoolPda1, orderId),
+          usdcMint: usdcMint,
+          authority: backend.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([backend])
+        .rpc();
+    const setThreshold = (amount: number, signer: Keypair) =>
+      program.methods
+        .setPayoutThreshold(new anchor.BN(amount), new anchor.BN(0))
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          affiliateAccount: affiliatePda1,
+          authority: signer.publicKey,
+        })
+        .signers([signer])
+        .rpc();
+
+    it("Holds back push payouts below the affiliate's threshold", async () => {
+      await setThreshold(1_500_000, affiliate);
+      const before = (await getAccount(provider.connection, affiliateUsdc)).amount;
+
+      // 5% of 20 USDC stays below the 1.5 USDC threshold
+      await sale("batch-1");
+
+      const after = (await getAccount(provider.connection, affiliateUsdc)).amount;
+      expect(after).to.equal(before);
+      const affiliateAccount = await program.account.affiliateAccount.fetch(affiliatePda1);
+      expect(affiliateAccount.claimable.toNumber()).to.equal(1_000_000);
+      console.log("✓ Commission held back below threshold");
+    });
+
+    it("Pays out everything owed once the threshold is reached", async () => {
+      const before = (await getAccount(provider.connection, affiliateUsdc)).amount;
+
+      await sale("batch-2");
+
+      const after = (await getAccount(provider.connection, affiliateUsdc)).amount;
+      expect(Number(after - before)).to.equal(2_000_000);
+      const affiliateAccount = await program.account.affiliateAccount.fetch(affiliatePda1);
+      expect(affiliateAccount.claimable.toNumber()).to.equal(0);
+      expect(affiliateAccount.lastPayoutAt.toNumber()).to.be.greaterThan(0);
+
+      // The merchant may reset it too
+      await setThreshold(0, merchant);
+      console.log("✓ Held-back commission released with the next payout");
+    });
+  });
+
+  describe("Manager Split", () => {
+    const manager = Keypair.generate();
+    let managerUsdc: PublicKey;
+    const saleAccounts = (orderId: string) => ({
+      merchantPool: merchantPoolPda1,
+      affiliateAccount: affiliatePda1,
+      affiliateWallet: affiliate.publicKey,
+      escrowAuthority: escrowAuthorityPda1,
+      escrowUsdc: escrowUsdc1,
+      affiliateUsdc: affiliateUsdc,
+      saleReceipt: receiptPda(merchantPoolPda1, orderId),
+      usdcMint: usdcMint,
+      authority: backend.publicKey,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+    });
+    const setManager = (wallet: PublicKey | null, shareBps: number) =>
+      program.methods
+        .setManager(wallet, shareBps)
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          affiliateAccount: affiliatePda1,
+          affiliateWallet: affiliate.publicKey,
+        })
+        .signers([affiliate])
+        .rpc();
+
+    before(async () => {
+      managerUsdc = (
+        await getOrCreateAssociatedTokenAccount(provider.connection, merchant, usdcMint, manager.publicKey)
+      ).address;
+      await setManager(manager.publicKey, 2000);
+    });
+
+    it("Requires the manager's token account while a manager is set", async () => {
+      try {
+        await program.methods
+          .processSale(saleArgs(20_000_000, "managed-0"))
+          .accounts(saleAccounts("managed-0"))
+          .signers([backend])
+          .rpc();
+
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("ManagerAccountRequired");
+        console.log("✓ Rejected sale without the manager's account");
+      }
+    });
+
+    it("Pays the manager its share of each commission", async () => {
+      const affiliateBefore = (await getAccount(provider.connection, affiliateUsdc)).amount;
+      const earnedBefore = (await program.account.affiliateAccount.fetch(affiliatePda1)).totalEarned.toNumber();
+      const quote = await program.methods
+        .quoteCommission(new anchor.BN(20_000_000))
+        .accounts({ merchantPool: merchantPoolPda1, affiliateAccount: affiliatePda1 })
+        .view();
+
+      // 20% of the 1 USDC commission goes to the manager
+      await program.methods
+        .processSale(saleArgs(20_000_000, "managed-1"))
+        .accounts({ ...saleAccounts("managed-1"), managerUsdc })
+        .signers([backend])
+        .rpc();
+
+      const affiliateAfter = (await getAccount(provider.connection, affiliateUsdc)).amount;
+      const managerBalance = (await getAccount(provider.connection, managerUsdc)).amount;
+      expect(Number(affiliateAfter - affiliateBefore)).to.equal(800_000);
+      expect(Number(managerBalance)).to.equal(200_000);
+      expect(quote.managerCut.toNumber()).to.equal(200_000);
+      expect(quote.affiliatePayout.toNumber()).to.equal(800_000);
+
+      const affiliateAccount = await program.account.affiliateAccount.fetch(affiliatePda1);
+      expect(affiliateAccount.managerEarned.toNumber()).to.equal(200_000);
+      expect(affiliateAccount.totalEarned.toNumber() - earnedBefore).to.equal(1_000_000);
+
+      await setManager(null, 0);
+      console.log("✓ Commission split between affiliate and manager");
+    });
+  });
+
+  describe("Holdback", () => {
+    const setHoldback = (secs: number) =>
+      program.methods
+        .setHoldbackPeriod(new anchor.BN(secs))
+        .accounts({ merchantPool: merchantPoolPda1, merchant: merchant.publicKey })
+        .signers([merchant])
+        .rpc();
+    const release = (orderId: string) =>
+      program.methods
+        .releaseMaturedHoldbacks()
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          escrowAuthority: escrowAuthorityPda1,
+          escrowUsdc: escrowUsdc1,
+          usdcMint: usdcMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts([
+          { pubkey: receiptPda(merchantPoolPda1, orderId), isWritable: true, isSigner: false },
+          { pubkey: affiliatePda1, isWritable: true, isSigner: false },
+          { pubkey: affiliateUsdc, isWritable: true, isSigner: false },
+        ])
+        .rpc();
+
+    it("Keeps commissions in escrow until the holdback period passes", async () => {
+      await setHoldback(2);
+      const before = (await getAccount(provider.connection, affiliateUsdc)).amount;
+      const quote = await program.methods
+        .quoteCommission(new anchor.BN(20_000_000))
+        .accounts({ merchantPool: merchantPoolPda1, affiliateAccount: affiliatePda1 })
+        .view();
+
+      await program.methods
+        .processSale(saleArgs(20_000_000, "held-1"))
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          affiliateAccount: affiliatePda1,
+          affiliateWallet: affiliate.publicKey,
+          escrowAuthority: escrowAuthorityPda1,
+          escrowUsdc: escrowUsdc1,
+          affiliateUsdc: affiliateUsdc,
+          saleReceipt: receiptPda(merchantPoolPda1, "held-1"),
+          usdcMint: usdcMint,
+          authority: backend.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([backend])
+        .rpc();
+
+      // Not due yet, so the crank skips it
+      await release("held-1");
+
+      const after = (await getAccount(provider.connection, affiliateUsdc)).amount;
+      expect(after).to.equal(before);
+      const receipt = await program.account.saleReceipt.fetch(receiptPda(merchantPoolPda1, "held-1"));
+      expect(receipt.heldAmount.toNumber()).to.equal(1_000_000);
+      expect(quote.holdback.toNumber()).to.equal(receipt.heldAmount.toNumber());
+      expect(quote.affiliatePayout.toNumber()).to.equal(0);
+      const pool = await program.account.merchantPool.fetch(merchantPoolPda1);
+      expect(pool.totalHeld.toNumber()).to.equal(1_000_000);
+      console.log("✓ Commission held back in escrow");
+    });
+
+    it("Claws refunds back from the held commission first", async () => {
+      await program.methods
+        .refundPartial("held-1", new anchor.BN(10_000_000))
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          saleReceipt: receiptPda(merchantPoolPda1, "held-1"),
+          affiliateAccount: affiliatePda1,
+          merchant: merchant.publicKey,
+        })
+        .signers([merchant])
+        .rpc();
+
+      const receipt = await program.account.saleReceipt.fetch(receiptPda(merchantPoolPda1, "held-1"));
+      expect(receipt.heldAmount.toNumber()).to.equal(500_000);
+      const affiliateAccount = await program.account.affiliateAccount.fetch(affiliatePda1);
+      expect(affiliateAccount.commissionDebt.toNumber()).to.equal(0);
+      console.log("✓ Refund clawed back from the holdback");
+    });
+
+    it("Releases matured holdbacks to the affiliate", async () => {
+      const before = (await getAccount(provider.connection, affiliateUsdc)).amount;
+      await new Promise((resolve) => setTimeout(resolve, 3000));
+
+      const signature = await release("held-1");
+
+      const after = (await getAccount(provider.connection, affiliateUsdc)).amount;
+      expect(Number(after - before)).to.equal(500_000);
+      const logs = (
+        await provider.connection.getTransaction(signature, {
+          commitment: "confirmed",
+          maxSupportedTransactionVersion: 0,
+        })
+      )!.meta!.logMessages!;
+      expect(logs.filter((log) => log.includes("Memo (len"))).to.deep.equal([
+        'Program log: Memo (len 6): "held-1"',
+      ]);
+      const receipt = await program.account.saleReceipt.fetch(receiptPda(merchantPoolPda1, "held-1"));
+      expect(receipt.heldAmount.toNumber()).to.equal(0);
+      const pool = await program.account.merchantPool.fetch(merchantPoolPda1);
+      expect(pool.totalHeld.toNumber()).to.equal(0);
+
+      await setHoldback(0);
+      console.log("✓ Matured holdback released");
+    });
+  });
+
+  describe("Custom Payout Account", () => {
+    const exchange = Keypair.generate();
+    let depositUsdc: PublicKey;
+    const setPayoutAccount = (payoutUsdc: PublicKey | null) =>
+      program.methods
+        .setPayoutAccount()
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          affiliateAccount: affiliatePda1,
+          affiliateWallet: affiliate.publicKey,
+          escrowAuthority: escrowAuthorityPda1,
+          payoutUsdc,
+        })
+        .signers([affiliate])
+        .rpc();
+    const saleAccounts = (orderId: string) => ({
+      merchantPool: merchantPoolPda1,
+      affiliateAccount: affiliatePda1,
+      affiliateWallet: affiliate.publicKey,
+      escrowAuthority: escrowAuthorityPda1,
+      escrowUsdc: escrowUsdc1,
+      affiliateUsdc: affiliateUsdc,
+      saleReceipt: receiptPda(merchantPoolPda1, orderId),
+      usdcMint: usdcMint,
+      authority: backend.publicKey,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+    });
+
+    before(async () => {
+      depositUsdc = (
+        await getOrCreateAssociatedTokenAccount(provider.connection, merchant, usdcMint, exchange.publicKey)
+      ).address;
+    });
+
+    it("Rejects the pool's own escrow as a payout account", async () => {
+      try {
+        await setPayoutAccount(escrowUsdc1);
+
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("InvalidPayoutAccount");
+        console.log("✓ Rejected escrow-owned payout account");
+      }
+    });
+
+    it("Requires the payout account once one is registered", async () => {
+      await setPayoutAccount(depositUsdc);
+      const affiliateAccount = await program.account.affiliateAccount.fetch(affiliatePda1);
+      expect(affiliateAccount.payoutAccount.toBase58()).to.equal(depositUsdc.toBase58());
+
+      try {
+        await program.methods
+          .processSale(saleArgs(20_000_000, "deposit-0"))
+          .accounts(saleAccounts("deposit-0"))
+          .signers([backend])
+          .rpc();
+
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("PayoutAccountRequired");
+        console.log("✓ Rejected sale without the payout account");
+      }
+    });
+
+    it("Pays commissions to the registered payout account", async () => {
+      const affiliateBefore = (await getAccount(provider.connection, affiliateUsdc)).amount;
+
+      await program.methods
+        .processSale(saleArgs(20_000_000, "deposit-1"))
+        .accounts({ ...saleAccounts("deposit-1"), payoutUsdc: depositUsdc })
+        .signers([backend])
+        .rpc();
+
+      const affiliateAfter = (await getAccount(provider.connection, affiliateUsdc)).amount;
+      const deposited = (await getAccount(provider.connection, depositUsdc)).amount;
+      expect(affiliateAfter).to.equal(affiliateBefore);
+      expect(Number(deposited)).to.equal(1_000_000);
+
+      await setPayoutAccount(null);
+      const affiliateAccount = await program.account.affiliateAccount.fetch(affiliatePda1);
+      expect(affiliateAccount.payoutAccount).to.be.null;
+      console.log("✓ Commission paid to the exchange deposit account");
+    });
+  });
+
+  describe("Rate Grace Period", () => {
+    const sale = (orderId: string) =>
+      program.methods
+        .processSale(saleArgs(20_000_000, orderId))
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          affiliateAccount: affiliatePda1,
+          affiliateWallet: affiliate.publicKey,
+          escrowAuthority: escrowAuthorityPda1,
+          escrowUsdc: escrowUsdc1,
+          affiliateUsdc: affiliateUsdc,
+          saleReceipt: receiptPda(merchantPoolPda1, orderId),
+          usdcMint: usdcMint,
+          authority: backend.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([backend])
+        .rpc();
+    const setRate = (rate: number) =>
+      program.methods
+        .updatePoolCommission(rate, null, null)
+        .accounts({ merchantPool: merchantPoolPda1, merchant: merchant.publicKey })
+        .signers([merchant])
+        .rpc();
+    const setGracePeriod = (secs: number) =>
+      program.methods
+        .setRateGracePeriod(new anchor.BN(secs))
+        .accounts({ merchantPool: merchantPoolPda1, merchant: merchant.publicKey })
+        .signers([merchant])
+        .rpc();
+
+    before(async () => {
+      await setGracePeriod(2);
+      // The cut must land after the affiliate's last rate snapshot
+      await new Promise((resolve) => setTimeout(resolve, 1100));
+      await setRate(250);
+    });
+
+    it("Keeps paying existing affiliates the old rate during the grace period", async () => {
+      const before = (await getAccount(provider.connection, affiliateUsdc)).amount;
+
+      await sale("grace-1");
+
+      const after = (await getAccount(provider.connection, affiliateUsdc)).amount;
+      expect(Number(after - before)).to.equal(1_000_000);
+      const receipt = await program.account.saleReceipt.fetch(receiptPda(merchantPoolPda1, "grace-1"));
+      expect(receipt.appliedRateBps).to.equal(COMMISSION_RATE_1);
+      console.log("✓ Old rate kept during the grace period");
+    });
+
+    it("Applies the cut once the grace period ends", async () => {
+      await new Promise((resolve) => setTimeout(resolve, 2500));
+      const before = (await getAccount(provider.connection, affiliateUsdc)).amount;
+
+      await sale("grace-2");
+
+      const after = (await getAccount(provider.connection, affiliateUsdc)).amount;
+      expect(Number(after - before)).to.equal(500_000);
+      const affiliateAccount = await program.account.affiliateAccount.fetch(affiliatePda1);
+      expect(affiliateAccount.rateSnapshotBps).to.equal(250);
+
+      await setRate(COMMISSION_RATE_1);
+      await setGracePeriod(0);
+      console.log("✓ New rate applied after the grace period");
+    });
+  });
+
+  describe("DAO Governance", () => {
+    const GOVERNANCE_PROGRAM = new PublicKey("GovER5Lthms3bLBqWub97yVrMmEogzX7xNjdXpPPCVZw");
+
+    it("Rejects governance whose native treasury isn't the merchant", async () => {
+      try {
+        await program.methods
+          .setPoolGovernance(GOVERNANCE_PROGRAM, Keypair.generate().publicKey)
+          .accounts({ merchantPool: merchantPoolPda1, merchant: merchant.publicKey })
+          .signers([merchant])
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("NotGovernanceTreasury");
+      }
+
+      const pool = await program.account.merchantPool.fetch(merchantPoolPda1);
+      expect(pool.governance.equals(PublicKey.default)).to.be.true;
+      console.log("✓ Governance must own the merchant key");
+    });
+  });
+
+  describe("Reporting Periods", () => {
+    it("Archives the period's stats and starts a new one", async () => {
+      const before = await program.account.merchantPool.fetch(merchantPoolPda1);
+      const [periodPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("pool_period"), merchantPoolPda1.toBuffer(), new anchor.BN(before.periodIndex).toArrayLike(Buffer, "le", 4)],
+        program.programId
+      );
+
+      await program.methods
+        .rotatePeriod()
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          poolPeriod: periodPda,
+          merchant: merchant.publicKey,
+          rentPayer: merchant.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([merchant])
+        .rpc();
+
+      const period = await program.account.poolPeriod.fetch(periodPda);
+      expect(period.volume.toNumber()).to.equal(before.periodVolume.toNumber());
+      expect(period.commissionsPaid.toNumber()).to.equal(before.periodCommissionsPaid.toNumber());
+      expect(period.totalVolume.toNumber()).to.equal(before.totalVolume.toNumber());
+
+      const after = await program.account.merchantPool.fetch(merchantPoolPda1);
+      expect(after.periodIndex).to.equal(before.periodIndex + 1);
+      expect(after.periodVolume.toNumber()).to.equal(0);
+      expect(after.totalVolume.toNumber()).to.equal(before.totalVolume.toNumber());
+      console.log("✓ Period", before.periodIndex, "archived with volume", period.volume.toNumber());
+    });
+  });
+
+  describe("Ref ID Attribution", () => {
+    it("Counts sales attributed through a ref_id lookup", async () => {
+      const indexPda = refIdPda(merchantPoolPda1, REF_ID);
+      const before = (await program.account.refIdIndex.fetch(indexPda)).redemptions.toNumber();
+
+      await program.methods
+        .processSale(saleArgs(10_000_000, "ref-attributed-1"))
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          affiliateAccount: affiliatePda1,
+          affiliateWallet: affiliate.publicKey,
+          escrowAuthority: escrowAuthorityPda1,
+          escrowUsdc: escrowUsdc1,
+          affiliateUsdc: affiliateUsdc,
+          saleReceipt: receiptPda(merchantPoolPda1, "ref-attributed-1"),
+          usdcMint: usdcMint,
+          authority: backend.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          refIdIndex: indexPda,
+        })
+        .signers([backend])
+        .rpc();
+
+      const after = (await program.account.refIdIndex.fetch(indexPda)).redemptions.toNumber();
+      expect(after).to.equal(before + 1);
+      console.log("✓", REF_ID, "redeemed", after, "times");
+    });
+  });
+
+  describe("Off-curve Affiliate Wallets", () => {
+    it("Won't pay a PDA-owned wallet that never activated itself", async () => {
+      // Stand-in for a multisig vault: any off-curve address
+      const [vault] = PublicKey.findProgramAddressSync(
+        [Buffer.from("vault"), merchant.publicKey.toBuffer()],
+        program.programId
+      );
+      const [vaultAffiliatePda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("affiliate"), merchantPoolPda1.toBuffer(), vault.toBuffer()],
+        program.programId
+      );
+      const vaultUsdc = getAssociatedTokenAddressSync(usdcMint, vault, true);
+
+      // Rent is funded by the ops wallet; the merchant key only authorizes
+      const merchantLamportsBefore = await provider.connection.getBalance(merchant.publicKey);
+
+      await program.methods
+        .addAffiliate("DAO001")
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          affiliateAccount: vaultAffiliatePda,
+          affiliateEntry: affiliateEntryPda(merchantPoolPda1, 1),
+          refIdIndex: refIdPda(merchantPoolPda1, "DAO001"),
+          affiliateWallet: vault,
+          merchant: merchant.publicKey,
+          rentPayer: backend.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([merchant, backend])
+        .rpc();
+
+      expect(await provider.connection.getBalance(merchant.publicKey)).to.equal(merchantLamportsBefore);
+
+      // A real multisig would activate through its own transaction; this
+      // stand-in can't sign, so it never consents
+      try {
+        await program.methods
+          .processSale(saleArgs(10_000_000, "vault-1"))
+          .accounts({
+            merchantPool: merchantPoolPda1,
+            affiliateAccount: vaultAffiliatePda,
+            affiliateWallet: vault,
+            escrowAuthority: escrowAuthorityPda1,
+            escrowUsdc: escrowUsdc1,
+            affiliateUsdc: vaultUsdc,
+            saleReceipt: receiptPda(merchantPoolPda1, "vault-1"),
+            usdcMint: usdcMint,
+            authority: backend.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([backend])
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("AffiliateNotActivated");
+      }
+
+      expect(await provider.connection.getAccountInfo(vaultUsdc)).to.be.null;
+      console.log("✓ Off-curve vault not paid before activating");
+    });
+  });
+
+  describe("Referral Bounty", () => {
+    const BOUNTY = 250_000;
+    const referred = Keypair.generate();
+    const referredPda = () =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("affiliate"), merchantPoolPda1.toBuffer(), referred.publicKey.toBuffer()],
+        program.programId
+      )[0];
+    const saleAccounts = (orderId: string) => ({
+      merchantPool: merchantPoolPda1,
+      affiliateAccount: referredPda(),
+      affiliateWallet: referred.publicKey,
+      escrowAuthority: escrowAuthorityPda1,
+      escrowUsdc: escrowUsdc1,
+      affiliateUsdc: getAssociatedTokenAddressSync(usdcMint, referred.publicKey),
+      saleReceipt: receiptPda(merchantPoolPda1, orderId),
+      usdcMint: usdcMint,
+      authority: backend.publicKey,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+    });
+    const setBounty = (amount: number) =>
+      program.methods
+        .setFirstSaleBounty(new anchor.BN(amount))
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          merchant: merchant.publicKey,
+        })
+        .signers([merchant])
+        .rpc();
+
+    it("Records the referrer of a new affiliate", async () => {
+      await setBounty(BOUNTY);
+
+      await program.methods
+        .addAffiliate("REF001")
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          affiliateAccount: referredPda(),
+          affiliateEntry: affiliateEntryPda(merchantPoolPda1, 2),
+          refIdIndex: refIdPda(merchantPoolPda1, "REF001"),
+          affiliateWallet: referred.publicKey,
+          merchant: merchant.publicKey,
+          rentPayer: merchant.publicKey,
+          systemProgram: SystemProgram.programId,
+          referrer: affiliatePda1,
+        })
+        .signers([merchant])
+        .rpc();
+
+      await activateSelf(merchantPoolPda1, referred);
+
+      const referredAccount = await program.account.affiliateAccount.fetch(referredPda());
+      expect(referredAccount.referredBy.toString()).to.equal(affiliate.publicKey.toString());
+      expect(referredAccount.referralBountyPaid).to.be.false;
+      console.log("✓ Referred affiliate registered");
+    });
+
+    it("Pays the referrer a bounty on the first sale only", async () => {
+      const before = (await getAccount(provider.connection, affiliateUsdc)).amount;
+
+      await program.methods
+        .processSale(saleArgs(10_000_000, "referral-1"))
+        .accounts({ ...saleAccounts("referral-1"), referrerUsdc: affiliateUsdc })
+        .signers([backend])
+        .rpc();
+
+      const after = (await getAccount(provider.connection, affiliateUsdc)).amount;
+      expect(Number(after - before)).to.equal(BOUNTY);
+      const referredAccount = await program.account.affiliateAccount.fetch(referredPda());
+      expect(referredAccount.referralBountyPaid).to.be.true;
+
+      // Later sales no longer need the referrer's account
+      await program.methods
+        .processSale(saleArgs(10_000_000, "referral-2"))
+        .accounts(saleAccounts("referral-2"))
+        .signers([backend])
+        .rpc();
+      expect((await getAccount(provider.connection, affiliateUsdc)).amount).to.equal(after);
+
+      await setBounty(0);
+      console.log("✓ Referral bounty paid once");
+    });
+  });
+
+  describe("Escrowless Mode", () => {
+    const poolAccounts = () => ({ merchantPool: merchantPoolPda2, merchant: merchant.publicKey });
+
+    it("Funds payouts from the merchant's revenue account", async () => {
+      await approve(provider.connection, merchant, merchantUsdc, escrowAuthorityPda2, merchant, 10_000_000);
+      await program.methods
+        .setRevenueAccount()
+        .accounts({ ...poolAccounts(), revenueUsdc: merchantUsdc })
+        .signers([merchant])
+        .rpc();
+
+      const merchantBefore = (await getAccount(provider.connection, merchantUsdc)).amount;
+      const escrowBefore = (await getAccount(provider.connection, escrowUsdc2)).amount;
+      const affiliateBefore = (await getAccount(provider.connection, affiliate2Usdc)).amount;
+
+      await program.methods
+        .processSale(saleArgs(10_000_000, "escrowless-1"))
+        .accounts({
+          merchantPool: merchantPoolPda2,
+          affiliateAccount: affiliatePda2,
+          affiliateWallet: affiliate2.publicKey,
+          escrowAuthority: escrowAuthorityPda2,
+          escrowUsdc: escrowUsdc2,
+          affiliateUsdc: affiliate2Usdc,
+          saleReceipt: receiptPda(merchantPoolPda2, "escrowless-1"),
+          usdcMint: usdcMint,
+          authority: backend.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          revenueUsdc: merchantUsdc,
+        })
+        .signers([backend])
+        .rpc();
+
+      // 10% of 10 USDC moved straight from revenue to the affiliate
+      const merchantAfter = (await getAccount(provider.connection, merchantUsdc)).amount;
+      const escrowAfter = (await getAccount(provider.connection, escrowUsdc2)).amount;
+      const affiliateAfter = (await getAccount(provider.connection, affiliate2Usdc)).amount;
+      expect(Number(merchantBefore - merchantAfter)).to.equal(1_000_000);
+      expect(escrowAfter).to.equal(escrowBefore);
+      expect(Number(affiliateAfter - affiliateBefore)).to.equal(1_000_000);
+
+      await program.methods.setRevenueAccount().accounts(poolAccounts()).signers([merchant]).rpc();
+      const poolAccount = await program.account.merchantPool.fetch(merchantPoolPda2);
+      expect(poolAccount.revenueAccount.equals(PublicKey.default)).to.be.true;
+      console.log("✓ Escrowless sale funded from revenue");
+    });
+  });
+
+  describe("Terms of Service", () => {
+    const TERMS_HASH = Array.from(Buffer.alloc(32, 7));
+    const saleAccounts = () => ({
+      merchantPool: merchantPoolPda2,
+      affiliateAccount: affiliatePda2,
+      affiliateWallet: affiliate2.publicKey,
+      escrowAuthority: escrowAuthorityPda2,
+      escrowUsdc: escrowUsdc2,
+      affiliateUsdc: affiliate2Usdc,
+      usdcMint: usdcMint,
+      authority: backend.publicKey,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+    });
+
+    it("Blocks payouts until the affiliate accepts the pool's terms", async () => {
+      await program.methods
+        .setPoolTerms(TERMS_HASH)
+        .accounts({
+          merchantPool: merchantPoolPda2,
+          merchant: merchant.publicKey,
+        })
+        .signers([merchant])
+        .rpc();
+
+      try {
+        await program.methods
+          .processSale(saleArgs(10_000_000, "terms-1"))
+          .accounts({ ...saleAccounts(), saleReceipt: receiptPda(merchantPoolPda2, "terms-1") })
+          .signers([backend])
+          .rpc();
+
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("TermsNotAccepted");
+        console.log("✓ Rejected payout before terms acceptance");
+      }
+    });
+
+    it("Records acceptance and pays out afterwards", async () => {
+      await program.methods
+        .acceptTerms(TERMS_HASH)
+        .accounts({
+          merchantPool: merchantPoolPda2,
+          affiliateAccount: affiliatePda2,
+          affiliateWallet: affiliate2.publicKey,
+        })
+        .signers([affiliate2])
+        .rpc();
+
+      const affiliateAccount = await program.account.affiliateAccount.fetch(affiliatePda2);
+      expect(affiliateAccount.acceptedTermsHash).to.deep.equal(TERMS_HASH);
+      expect(affiliateAccount.termsAcceptedAt.toNumber()).to.be.greaterThan(0);
+
+      await program.methods
+        .processSale(saleArgs(10_000_000, "terms-2"))
+        .accounts({ ...saleAccounts(), saleReceipt: receiptPda(merchantPoolPda2, "terms-2") })
+        .signers([backend])
+        .rpc();
+      console.log("✓ Paid out after terms acceptance");
+    });
+  });
+
+  describe("Pool-specific Escrow Management", () => {
+    it("Deposits to pool 1 escrow", async () => {
+      const DEPOSIT = 50_000_000;
+      const before = (await getAccount(provider.connection, escrowUsdc1)).amount;
+
+      await program.methods
+        .depositEscrow(new anchor.BN(DEPOSIT))
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          merchant: merchant.publicKey,
+          merchantUsdc: merchantUsdc,
+          escrowAuthority: escrowAuthorityPda1,
+          escrowUsdc: escrowUsdc1,
+          usdcMint: usdcMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([merchant])
+        .rpc();
+
+      const after = (await getAccount(provider.connection, escrowUsdc1)).amount;
+      expect(Number(after - before)).to.equal(DEPOSIT);
+      console.log("✓ Deposited to Pool 1:", DEPOSIT / 1_000_000, "USDC");
+    });
+
+    it("Deposits to pool 2 escrow", async () => {
+      const DEPOSIT = 75_000_000;
+      const before = (await getAccount(provider.connection, escrowUsdc2)).amount;
+
+      await program.methods
         .depositEscrow(new anchor.BN(DEPOSIT))
         .accounts({
-          merchantPool: merchantPoolPda1,
+          merchantPool: merchantPoolPda2,
+          merchant: merchant.publicKey,
+          merchantUsdc: merchantUsdc,
+          escrowAuthority: escrowAuthorityPda2,
+          escrowUsdc: escrowUsdc2,
+          usdcMint: usdcMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([merchant])
+        .rpc();
+
+      const after = (await getAccount(provider.connection, escrowUsdc2)).amount;
+      expect(Number(after - before)).to.equal(DEPOSIT);
+      console.log("✓ Deposited to Pool 2:", DEPOSIT / 1_000_000, "USDC");
+    });
+
+    it("Names the failing constraint for a wrong token account", async () => {
+      try {
+        await program.methods
+          .depositEscrow(new anchor.BN(1_000_000))
+          .accounts({
+            merchantPool: merchantPoolPda1,
+            merchant: merchant.publicKey,
+            merchantUsdc: affiliateUsdc,
+            escrowAuthority: escrowAuthorityPda1,
+            escrowUsdc: escrowUsdc1,
+            usdcMint: usdcMint,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([merchant])
+          .rpc();
+
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("WrongMerchantTokenAccount");
+        console.log("✓ Rejected deposit from a token account the merchant doesn't own");
+      }
+    });
+
+    it("Deposits through a delegate approval without the merchant signing", async () => {
+      const DEPOSIT = 5_000_000;
+      await approve(
+        provider.connection,
+        merchant,
+        merchantUsdc,
+        backend.publicKey,
+        merchant,
+        DEPOSIT
+      );
+      const before = (await getAccount(provider.connection, escrowUsdc1)).amount;
+
+      await program.methods
+        .depositEscrowDelegated(new anchor.BN(DEPOSIT))
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          delegate: backend.publicKey,
+          merchantUsdc: merchantUsdc,
+          escrowAuthority: escrowAuthorityPda1,
+          escrowUsdc: escrowUsdc1,
+          usdcMint: usdcMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([backend])
+        .rpc();
+
+      const after = (await getAccount(provider.connection, escrowUsdc1)).amount;
+      expect(Number(after - before)).to.equal(DEPOSIT);
+      console.log("✓ Treasury bot topped up Pool 1 via delegate");
+    });
+
+    it("Rejects delegated deposit from a non-delegate", async () => {
+      try {
+        await program.methods
+          .depositEscrowDelegated(new anchor.BN(1_000_000))
+          .accounts({
+            merchantPool: merchantPoolPda1,
+            delegate: affiliate.publicKey,
+            merchantUsdc: merchantUsdc,
+            escrowAuthority: escrowAuthorityPda1,
+            escrowUsdc: escrowUsdc1,
+            usdcMint: usdcMint,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([affiliate])
+          .rpc();
+
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("DelegateMismatch");
+        console.log("✓ Rejected deposit from unapproved signer");
+      }
+    });
+
+    it("Accepts escrow donations from third parties", async () => {
+      const DONATION = 500_000;
+      const before = (await getAccount(provider.connection, escrowUsdc1)).amount;
+
+      await program.methods
+        .donateToEscrow(new anchor.BN(DONATION))
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          donor: affiliate.publicKey,
+          donorUsdc: affiliateUsdc,
+          escrowAuthority: escrowAuthorityPda1,
+          escrowUsdc: escrowUsdc1,
+          usdcMint: usdcMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([affiliate])
+        .rpc();
+
+      const after = (await getAccount(provider.connection, escrowUsdc1)).amount;
+      expect(Number(after - before)).to.equal(DONATION);
+      console.log("✓ Sponsor funded Pool 1 escrow");
+    });
+
+    it("Executes a scheduled top-up once due", async () => {
+      const TOPUP = 2_000_000;
+      const [topupSchedule] = PublicKey.findProgramAddressSync(
+        [Buffer.from("topup"), merchantPoolPda1.toBuffer()],
+        program.programId
+      );
+
+      await program.methods
+        .setTopupSchedule(new anchor.BN(TOPUP), new anchor.BN(86_400))
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          topupSchedule,
+          source: merchantUsdc,
+          merchant: merchant.publicKey,
+          rentPayer: merchant.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([merchant])
+        .rpc();
+      await approve(
+        provider.connection,
+        merchant,
+        merchantUsdc,
+        topupSchedule,
+        merchant,
+        TOPUP * 2
+      );
+
+      const topupAccounts = {
+        merchantPool: merchantPoolPda1,
+        topupSchedule,
+        source: merchantUsdc,
+        escrowAuthority: escrowAuthorityPda1,
+        escrowToken: escrowUsdc1,
+        mint: usdcMint,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      };
+      const before = (await getAccount(provider.connection, escrowUsdc1)).amount;
+
+      // Cranked by an unrelated key
+      await program.methods
+        .executeTopup()
+        .accounts(topupAccounts)
+        .signers([affiliate2])
+        .rpc();
+
+      const after = (await getAccount(provider.connection, escrowUsdc1)).amount;
+      expect(Number(after - before)).to.equal(TOPUP);
+
+      try {
+        await program.methods.executeTopup().accounts(topupAccounts).rpc();
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("TopUpNotDue");
+      }
+
+      await program.methods
+        .cancelTopupSchedule()
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          topupSchedule,
+          merchant: merchant.publicKey,
+        })
+        .signers([merchant])
+        .rpc();
+
+      expect(await provider.connection.getAccountInfo(topupSchedule)).to.be.null;
+      console.log("✓ Scheduled top-up executed, rejected early and cancelled");
+    });
+
+    it("Rejects deposit with a mint other than the pool's", async () => {
+      const otherMint = await createMint(
+        provider.connection,
+        merchant,
+        merchant.publicKey,
+        null,
+        6,
+        undefined,
+        undefined,
+        TOKEN_PROGRAM_ID
+      );
+      const merchantOther = await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        merchant,
+        otherMint,
+        merchant.publicKey
+      );
+      // Anyone can create an ATA of the foreign mint for the escrow authority
+      const escrowOther = await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        merchant,
+        otherMint,
+        escrowAuthorityPda1,
+        true
+      );
+
+      try {
+        await program.methods
+          .depositEscrow(new anchor.BN(1_000_000))
+          .accounts({
+            merchantPool: merchantPoolPda1,
+            merchant: merchant.publicKey,
+            merchantUsdc: merchantOther.address,
+            escrowAuthority: escrowAuthorityPda1,
+            escrowUsdc: escrowOther.address,
+            usdcMint: otherMint,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([merchant])
+          .rpc();
+
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("MintMismatch");
+        console.log("✓ Rejected deposit with foreign mint");
+      }
+    });
+
+    it("Withdraws from pool 1 escrow", async () => {
+      const WITHDRAW = 20_000_000;
+      const before = (await getAccount(provider.connection, merchantUsdc)).amount;
+
+      await program.methods
+        .withdrawEscrow(new anchor.BN(WITHDRAW), null, null)
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          merchant: merchant.publicKey,
+          merchantUsdc: merchantUsdc,
+          escrowAuthority: escrowAuthorityPda1,
+          escrowUsdc: escrowUsdc1,
+          usdcMint: usdcMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([merchant])
+        .rpc();
+
+      const after = (await getAccount(provider.connection, merchantUsdc)).amount;
+      expect(Number(after - before)).to.equal(WITHDRAW);
+      console.log("✓ Withdrawn from Pool 1:", WITHDRAW / 1_000_000, "USDC");
+    });
+
+    it("Requires the co-signer for withdrawals above the threshold", async () => {
+      const withdrawAccounts = {
+        merchantPool: merchantPoolPda1,
+        merchant: merchant.publicKey,
+        merchantUsdc: merchantUsdc,
+        escrowAuthority: escrowAuthorityPda1,
+        escrowUsdc: escrowUsdc1,
+        usdcMint: usdcMint,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      };
+      await program.methods
+        .setWithdrawalCosigner(backend.publicKey, new anchor.BN(5_000_000))
+        .accounts({ merchantPool: merchantPoolPda1, merchant: merchant.publicKey })
+        .signers([merchant])
+        .rpc();
+
+      try {
+        await program.methods
+          .withdrawEscrow(new anchor.BN(6_000_000), null, null)
+          .accounts(withdrawAccounts)
+          .signers([merchant])
+          .rpc();
+
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("CosignerRequired");
+      }
+
+      const before = (await getAccount(provider.connection, merchantUsdc)).amount;
+      await program.methods
+        .withdrawEscrow(new anchor.BN(6_000_000), null, null)
+        .accounts({ ...withdrawAccounts, cosigner: backend.publicKey })
+        .signers([merchant, backend])
+        .rpc();
+      await program.methods
+        .withdrawEscrow(new anchor.BN(1_000_000), null, null)
+        .accounts(withdrawAccounts)
+        .signers([merchant])
+        .rpc();
+      const after = (await getAccount(provider.connection, merchantUsdc)).amount;
+      expect(Number(after - before)).to.equal(7_000_000);
+
+      // Dropping the requirement needs the current co-signer
+      await program.methods
+        .setWithdrawalCosigner(null, new anchor.BN(0))
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          merchant: merchant.publicKey,
+          currentCosigner: backend.publicKey,
+        })
+        .signers([merchant, backend])
+        .rpc();
+      console.log("✓ Large withdrawal needed the co-signer");
+    });
+
+    it("Caps withdrawals per day", async () => {
+      const setLimit = (limit: number) =>
+        program.methods
+          .setDailyWithdrawalLimit(new anchor.BN(limit))
+          .accounts({ merchantPool: merchantPoolPda1, merchant: merchant.publicKey })
+          .signers([merchant])
+          .rpc();
+      const withdraw = (amount: number) =>
+        program.methods
+          .withdrawEscrow(new anchor.BN(amount), null, null)
+          .accounts({
+            merchantPool: merchantPoolPda1,
+            merchant: merchant.publicKey,
+            merchantUsdc: merchantUsdc,
+            escrowAuthority: escrowAuthorityPda1,
+            escrowUsdc: escrowUsdc1,
+            usdcMint: usdcMint,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([merchant])
+          .rpc();
+
+      await setLimit(3_000_000);
+      await withdraw(2_000_000);
+
+      try {
+        await withdraw(2_000_000);
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("DailyWithdrawalLimitExceeded");
+      }
+
+      const poolAccount = await program.account.merchantPool.fetch(merchantPoolPda1);
+      expect(poolAccount.withdrawnInWindow.toNumber()).to.equal(2_000_000);
+
+      await setLimit(0);
+      console.log("✓ Daily withdrawal limit enforced");
+    });
+
+    it("Executes offline-signed withdrawals against a durable nonce until they expire", async () => {
+      const withdrawAccounts = {
+        merchantPool: merchantPoolPda1,
+        merchant: merchant.publicKey,
+        merchantUsdc: merchantUsdc,
+        escrowAuthority: escrowAuthorityPda1,
+        escrowUsdc: escrowUsdc1,
+        usdcMint: usdcMint,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      };
+      const slot = await provider.connection.getSlot();
+
+      try {
+        await program.methods
+          .withdrawEscrow(new anchor.BN(1_000_000), new anchor.BN(slot - 1), null)
+          .accounts(withdrawAccounts)
+          .signers([merchant])
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("TransactionExpired");
+      }
+
+      const nonceAccount = Keypair.generate();
+      await provider.sendAndConfirm(
+        SystemProgram.createNonceAccount({
+          fromPubkey: merchant.publicKey,
+          noncePubkey: nonceAccount.publicKey,
+          authorizedPubkey: merchant.publicKey,
+          lamports: await provider.connection.getMinimumBalanceForRentExemption(NONCE_ACCOUNT_LENGTH),
+        }),
+        [merchant, nonceAccount]
+      );
+      const nonce = async () =>
+        NonceAccount.fromAccountData((await provider.connection.getAccountInfo(nonceAccount.publicKey))!.data)
+          .nonce;
+
+      // Signed against the nonce instead of a recent blockhash, then
+      // submitted separately as an offline signer's transaction would be
+      const signedNonce = await nonce();
+      const transaction = await program.methods
+        .withdrawEscrow(new anchor.BN(1_000_000), new anchor.BN(slot + 10_000), null)
+        .accounts(withdrawAccounts)
+        .preInstructions([
+          SystemProgram.nonceAdvance({
+            noncePubkey: nonceAccount.publicKey,
+            authorizedPubkey: merchant.publicKey,
+          }),
+        ])
+        .transaction();
+      transaction.feePayer = merchant.publicKey;
+      transaction.recentBlockhash = signedNonce;
+      transaction.sign(merchant);
+
+      const before = (await getAccount(provider.connection, merchantUsdc)).amount;
+      await sendAndConfirmRawTransaction(provider.connection, transaction.serialize());
+      const after = (await getAccount(provider.connection, merchantUsdc)).amount;
+      expect(Number(after - before)).to.equal(1_000_000);
+      expect(await nonce()).to.not.equal(signedNonce);
+      console.log("✓ Durable nonce withdrawal executed before its expiry slot");
+    });
+
+    it("Only withdraws to the registered destination", async () => {
+      const treasuryUsdc = (
+        await getOrCreateAssociatedTokenAccount(provider.connection, backend, usdcMint, backend.publicKey)
+      ).address;
+      const poolAccounts = { merchantPool: merchantPoolPda2, merchant: merchant.publicKey };
+      const withdraw = (destination: PublicKey) =>
+        program.methods
+          .withdrawEscrow(new anchor.BN(1_000_000), null, null)
+          .accounts({
+            ...poolAccounts,
+            merchantUsdc: destination,
+            escrowAuthority: escrowAuthorityPda2,
+            escrowUsdc: escrowUsdc2,
+            usdcMint: usdcMint,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([merchant])
+          .rpc();
+
+      await program.methods
+        .setWithdrawalDestination(backend.publicKey)
+        .accounts(poolAccounts)
+        .signers([merchant])
+        .rpc();
+
+      try {
+        await withdraw(merchantUsdc);
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("WithdrawalDestinationMismatch");
+      }
+
+      const before = (await getAccount(provider.connection, treasuryUsdc)).amount;
+      await withdraw(treasuryUsdc);
+      const after = (await getAccount(provider.connection, treasuryUsdc)).amount;
+      expect(Number(after - before)).to.equal(1_000_000);
+
+      // Switching back to the merchant is timelocked
+      await program.methods
+        .setWithdrawalDestination(merchant.publicKey)
+        .accounts(poolAccounts)
+        .signers([merchant])
+        .rpc();
+      try {
+        await program.methods.applyWithdrawalDestination().accounts(poolAccounts).signers([merchant]).rpc();
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("DestinationChangePending");
+      }
+      console.log("✓ Withdrawals locked to the treasury");
+    });
+
+    const transferAccounts = () => ({
+      fromPool: merchantPoolPda1,
+      toPool: merchantPoolPda2,
+      merchant: merchant.publicKey,
+      fromEscrowAuthority: escrowAuthorityPda1,
+      fromEscrow: escrowUsdc1,
+      toEscrowAuthority: escrowAuthorityPda2,
+      toEscrow: escrowUsdc2,
+      mint: usdcMint,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    });
+
+    it("Moves escrow from pool 1 to pool 2 directly", async () => {
+      const TRANSFER = 5_000_000;
+      const before1 = (await getAccount(provider.connection, escrowUsdc1)).amount;
+      const before2 = (await getAccount(provider.connection, escrowUsdc2)).amount;
+
+      await program.methods
+        .transferEscrow(new anchor.BN(TRANSFER), null)
+        .accounts(transferAccounts())
+        .signers([merchant])
+        .rpc();
+
+      const after1 = (await getAccount(provider.connection, escrowUsdc1)).amount;
+      const after2 = (await getAccount(provider.connection, escrowUsdc2)).amount;
+      expect(Number(before1 - after1)).to.equal(TRANSFER);
+      expect(Number(after2 - before2)).to.equal(TRANSFER);
+      console.log("✓ Rebalanced", TRANSFER / 1_000_000, "USDC from Pool 1 to Pool 2");
+    });
+
+    it("Requires the co-signer for transfers above the threshold", async () => {
+      const poolAccounts = { merchantPool: merchantPoolPda1, merchant: merchant.publicKey };
+      await program.methods
+        .setWithdrawalCosigner(backend.publicKey, new anchor.BN(1_000_000))
+        .accounts(poolAccounts)
+        .signers([merchant])
+        .rpc();
+
+      try {
+        await program.methods
+          .transferEscrow(new anchor.BN(2_000_000), null)
+          .accounts(transferAccounts())
+          .signers([merchant])
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("CosignerRequired");
+      }
+
+      await program.methods
+        .transferEscrow(new anchor.BN(2_000_000), null)
+        .accounts({ ...transferAccounts(), cosigner: backend.publicKey })
+        .signers([merchant, backend])
+        .rpc();
+
+      await program.methods
+        .setWithdrawalCosigner(null, new anchor.BN(0))
+        .accounts({ ...poolAccounts, currentCosigner: backend.publicKey })
+        .signers([merchant, backend])
+        .rpc();
+      console.log("✓ Large transfer needed the co-signer");
+    });
+
+    it("Counts transfers toward the daily withdrawal limit", async () => {
+      const setLimit = (limit: number) =>
+        program.methods
+          .setDailyWithdrawalLimit(new anchor.BN(limit))
+          .accounts({ merchantPool: merchantPoolPda1, merchant: merchant.publicKey })
+          .signers([merchant])
+          .rpc();
+      const transfer = (amount: number) =>
+        program.methods
+          .transferEscrow(new anchor.BN(amount), null)
+          .accounts(transferAccounts())
+          .signers([merchant])
+          .rpc();
+
+      await setLimit(3_000_000);
+      const { withdrawnInWindow } = await program.account.merchantPool.fetch(merchantPoolPda1);
+      await transfer(2_000_000);
+      const poolAccount = await program.account.merchantPool.fetch(merchantPoolPda1);
+      expect(poolAccount.withdrawnInWindow.sub(withdrawnInWindow).toNumber()).to.equal(2_000_000);
+
+      try {
+        await transfer(2_000_000);
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("DailyWithdrawalLimitExceeded");
+      }
+
+      await setLimit(0);
+      console.log("✓ Transfers counted toward the daily limit");
+    });
+
+    it("Keeps escrow locked to a destination out of other pools", async () => {
+      // Pool 2's escrow is locked to the treasury, which pool 1 isn't
+      try {
+        await program.methods
+          .transferEscrow(new anchor.BN(1_000_000), null)
+          .accounts({
+            ...transferAccounts(),
+            fromPool: merchantPoolPda2,
+            toPool: merchantPoolPda1,
+            fromEscrowAuthority: escrowAuthorityPda2,
+            fromEscrow: escrowUsdc2,
+            toEscrowAuthority: escrowAuthorityPda1,
+            toEscrow: escrowUsdc1,
+          })
+          .signers([merchant])
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("WithdrawalDestinationMismatch");
+      }
+      console.log("✓ Locked escrow stayed put");
+    });
+  });
+
+  describe("Multiple Settlement Mints", () => {
+    let eurcMint: PublicKey;
+    let merchantEurc: PublicKey;
+    let escrowEurc1: PublicKey;
+    let affiliateEurc: PublicKey;
+
+    before(async () => {
+      eurcMint = await createMint(
+        provider.connection,
+        merchant,
+        merchant.publicKey,
+        null,
+        6,
+        undefined,
+        undefined,
+        TOKEN_PROGRAM_ID
+      );
+      merchantEurc = (
+        await getOrCreateAssociatedTokenAccount(provider.connection, merchant, eurcMint, merchant.publicKey)
+      ).address;
+      await mintTo(provider.connection, merchant, eurcMint, merchantEurc, merchant, 100_000_000);
+
+      escrowEurc1 = getAssociatedTokenAddressSync(eurcMint, escrowAuthorityPda1, true);
+      affiliateEurc = getAssociatedTokenAddressSync(eurcMint, affiliate.publicKey);
+    });
+
+    it("Registers a second settlement mint on pool 1", async () => {
+      await program.methods
+        .addPoolMint()
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          merchant: merchant.publicKey,
+          rentPayer: merchant.publicKey,
+          escrowAuthority: escrowAuthorityPda1,
+          escrowToken: escrowEurc1,
+          mint: eurcMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([merchant])
+        .rpc();
+
+      const poolAccount = await program.account.merchantPool.fetch(merchantPoolPda1);
+      expect(
+        poolAccount.settlementMints.slice(0, poolAccount.settlementMintCount).map((m) => m.toString())
+      ).to.include(eurcMint.toString());
+      console.log("✓ Pool 1 accepts a second settlement mint");
+    });
+
+    it("Pays commission in the mint passed to process_sale", async () => {
+      await program.methods
+        .depositEscrow(new anchor.BN(50_000_000))
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          merchant: merchant.publicKey,
+          merchantUsdc: merchantEurc,
+          escrowAuthority: escrowAuthorityPda1,
+          escrowUsdc: escrowEurc1,
+          usdcMint: eurcMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([merchant])
+        .rpc();
+
+      await program.methods
+        .processSale(saleArgs(10_000_000, "order-3"))
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          affiliateAccount: affiliatePda1,
+          affiliateWallet: affiliate.publicKey,
+          escrowAuthority: escrowAuthorityPda1,
+          escrowUsdc: escrowEurc1,
+          affiliateUsdc: affiliateEurc,
+          saleReceipt: receiptPda(merchantPoolPda1, "order-3"),
+          usdcMint: eurcMint,
+          authority: backend.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([backend])
+        .rpc();
+
+      const affiliateBalance = (await getAccount(provider.connection, affiliateEurc)).amount;
+      expect(Number(affiliateBalance)).to.equal(500_000);
+      console.log("✓ Commission paid from the second mint's escrow");
+    });
+
+    it("Splits the pool's totals by mint", async () => {
+      const poolAccount = await program.account.merchantPool.fetch(merchantPoolPda1);
+      const slot =
+        1 + poolAccount.settlementMints.findIndex((mint: PublicKey) => mint.equals(eurcMint));
+      expect(poolAccount.mintVolumes[slot].toNumber()).to.equal(10_000_000);
+      expect(poolAccount.mintCommissionsPaid[slot].toNumber()).to.equal(500_000);
+
+      const sum = (values: anchor.BN[]) => values.reduce((total, value) => total + value.toNumber(), 0);
+      expect(sum(poolAccount.mintVolumes)).to.equal(poolAccount.totalVolume.toNumber());
+      expect(sum(poolAccount.mintCommissionsPaid)).to.equal(poolAccount.totalCommissionsPaid.toNumber());
+      console.log("✓ Volume and commissions tracked per mint");
+    });
+
+    it("Claws back a settlement-mint sale's commission in that mint", async () => {
+      const before = await program.account.affiliateAccount.fetch(affiliatePda1);
+      const poolAccount = await program.account.merchantPool.fetch(merchantPoolPda1);
+      const index = poolAccount.settlementMints.findIndex((mint: PublicKey) => mint.equals(eurcMint));
+
+      // order-3: 10 EURC sale that paid 0.5 EURC commission
+      await program.methods
+        .refundPartial("order-3", new anchor.BN(4_000_000))
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          saleReceipt: receiptPda(merchantPoolPda1, "order-3"),
+          affiliateAccount: affiliatePda1,
+          merchant: merchant.publicKey,
+        })
+        .signers([merchant])
+        .rpc();
+
+      // The primary-mint balances are left alone
+      const after = await program.account.affiliateAccount.fetch(affiliatePda1);
+      expect(after.claimable.toNumber()).to.equal(before.claimable.toNumber());
+      expect(after.totalHeld.toNumber()).to.equal(before.totalHeld.toNumber());
+      expect(after.commissionDebt.toNumber()).to.equal(before.commissionDebt.toNumber());
+      expect(after.settlementMintDebts[index].toNumber()).to.equal(200_000);
+
+      const balanceBefore = (await getAccount(provider.connection, affiliateEurc)).amount;
+      await program.methods
+        .processSale(saleArgs(10_000_000, "eurc-1"))
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          affiliateAccount: affiliatePda1,
+          affiliateWallet: affiliate.publicKey,
+          escrowAuthority: escrowAuthorityPda1,
+          escrowUsdc: escrowEurc1,
+          affiliateUsdc: affiliateEurc,
+          saleReceipt: receiptPda(merchantPoolPda1, "eurc-1"),
+          usdcMint: eurcMint,
+          authority: backend.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([backend])
+        .rpc();
+      const balanceAfter = (await getAccount(provider.connection, affiliateEurc)).amount;
+      expect(Number(balanceAfter - balanceBefore)).to.equal(300_000);
+
+      const recovered = await program.account.affiliateAccount.fetch(affiliatePda1);
+      expect(recovered.settlementMintDebts[index].toNumber()).to.equal(0);
+      console.log("✓ EURC clawback recovered from the next EURC commission");
+    });
+  });
+
+  describe("Claim Mode", () => {
+    const SALE_AMOUNT = 20_000_000;
+    const EXPECTED_COMMISSION = 2_000_000;
+    const claimAccounts = (authority: PublicKey) => ({
+      merchantPool: merchantPoolPda2,
+      affiliateAccount: affiliatePda2,
+      affiliateWallet: affiliate2.publicKey,
+      escrowAuthority: escrowAuthorityPda2,
+      escrowUsdc: escrowUsdc2,
+      affiliateUsdc: affiliate2Usdc,
+      usdcMint: usdcMint,
+      authority,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+    });
+
+    it("Accrues commission instead of paying out", async () => {
+      await program.methods
+        .setPayoutMode({ claim: {} })
+        .accounts({
+          merchantPool: merchantPoolPda2,
+          merchant: merchant.publicKey,
+        })
+        .signers([merchant])
+        .rpc();
+
+      const balanceBefore = (await getAccount(provider.connection, affiliate2Usdc)).amount;
+
+      await program.methods
+        .processSale(saleArgs(SALE_AMOUNT, "claim-1"))
+        .accounts({
+          ...claimAccounts(backend.publicKey),
+          saleReceipt: receiptPda(merchantPoolPda2, "claim-1"),
+        })
+        .signers([backend])
+        .rpc();
+
+      const balanceAfter = (await getAccount(provider.connection, affiliate2Usdc)).amount;
+      expect(Number(balanceAfter - balanceBefore)).to.equal(0);
+
+      const affiliateAccount = await program.account.affiliateAccount.fetch(affiliatePda2);
+      expect(affiliateAccount.claimable.toNumber()).to.equal(EXPECTED_COMMISSION);
+      const poolAccount = await program.account.merchantPool.fetch(merchantPoolPda2);
+      expect(poolAccount.totalClaimable.toNumber()).to.equal(EXPECTED_COMMISSION);
+      console.log("✓ Commission accrued for later claim");
+    });
+
+    it("Refuses to remove an affiliate with unclaimed commissions", async () => {
+      try {
+        await program.methods
+          .removeAffiliate()
+          .accounts({
+            merchantPool: merchantPoolPda2,
+            affiliateAccount: affiliatePda2,
+            affiliateWallet: affiliate2.publicKey,
+            merchant: merchant.publicKey,
+          })
+          .signers([merchant])
+          .rpc();
+
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("PendingCommissions");
+        console.log("✓ Rejected removal with pending balance");
+      }
+    });
+
+    it("Lets the affiliate claim accrued commissions", async () => {
+      const balanceBefore = (await getAccount(provider.connection, affiliate2Usdc)).amount;
+
+      await program.methods
+        .claimCommissions()
+        .accounts(claimAccounts(affiliate2.publicKey))
+        .signers([affiliate2])
+        .rpc();
+
+      const balanceAfter = (await getAccount(provider.connection, affiliate2Usdc)).amount;
+      expect(Number(balanceAfter - balanceBefore)).to.equal(EXPECTED_COMMISSION);
+
+      const affiliateAccount = await program.account.affiliateAccount.fetch(affiliatePda2);
+      expect(affiliateAccount.claimable.toNumber()).to.equal(0);
+      const poolAccount = await program.account.merchantPool.fetch(merchantPoolPda2);
+      expect(poolAccount.totalClaimable.toNumber()).to.equal(0);
+
+      await program.methods
+        .setPayoutMode({ push: {} })
+        .accounts({
+          merchantPool: merchantPoolPda2,
+          merchant: merchant.publicKey,
+        })
+        .signers([merchant])
+        .rpc();
+      console.log("✓ Affiliate claimed accrued commissions");
+    });
+
+    it("Lets a relayer claim for the affiliate in exchange for a capped fee", async () => {
+      const poolAccounts = {
+        merchantPool: merchantPoolPda2,
+        merchant: merchant.publicKey,
+      };
+      await program.methods.setPayoutMode({ claim: {} }).accounts(poolAccounts).signers([merchant]).rpc();
+
+      await program.methods
+        .processSale(saleArgs(SALE_AMOUNT, "relay-1"))
+        .accounts({
+          ...claimAccounts(backend.publicKey),
+          saleReceipt: receiptPda(merchantPoolPda2, "relay-1"),
+        })
+        .signers([backend])
+        .rpc();
+
+      const relayerUsdc = (
+        await getOrCreateAssociatedTokenAccount(provider.connection, backend, usdcMint, backend.publicKey)
+      ).address;
+      const { authority: _, ...claimBase } = claimAccounts(backend.publicKey);
+      const relayedClaim = (fee: number) =>
+        program.methods
+          .claimCommissionsRelayed(new anchor.BN(fee))
+          .accounts({
+            ...claimBase,
+            relayerUsdc,
+            relayer: backend.publicKey,
+          })
+          .signers([backend, affiliate2])
+          .rpc();
+
+      try {
+        // The cap is 5% of the 2 USDC claim
+        await relayedClaim(150_000);
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("RelayerFeeTooHigh");
+      }
+
+      const affiliateBefore = (await getAccount(provider.connection, affiliate2Usdc)).amount;
+      const relayerBefore = (await getAccount(provider.connection, relayerUsdc)).amount;
+
+      await relayedClaim(100_000);
+
+      const affiliateAfter = (await getAccount(provider.connection, affiliate2Usdc)).amount;
+      const relayerAfter = (await getAccount(provider.connection, relayerUsdc)).amount;
+      expect(Number(affiliateAfter - affiliateBefore)).to.equal(EXPECTED_COMMISSION - 100_000);
+      expect(Number(relayerAfter - relayerBefore)).to.equal(100_000);
+
+      const affiliateAccount = await program.account.affiliateAccount.fetch(affiliatePda2);
+      expect(affiliateAccount.claimable.toNumber()).to.equal(0);
+
+      await program.methods.setPayoutMode({ push: {} }).accounts(poolAccounts).signers([merchant]).rpc();
+      console.log("✓ Relayer claimed on the affiliate's behalf");
+    });
+
+    it("Sweeps commissions left unclaimed past the claim window", async () => {
+      const poolAccounts = {
+        merchantPool: merchantPoolPda2,
+        merchant: merchant.publicKey,
+      };
+      await program.methods.setPayoutMode({ claim: {} }).accounts(poolAccounts).signers([merchant]).rpc();
+      await program.methods.setClaimWindow(new anchor.BN(1)).accounts(poolAccounts).signers([merchant]).rpc();
+
+      await program.methods
+        .processSale(saleArgs(SALE_AMOUNT, "sweep-1"))
+        .accounts({
+          ...claimAccounts(backend.publicKey),
+          saleReceipt: receiptPda(merchantPoolPda2, "sweep-1"),
+        })
+        .signers([backend])
+        .rpc();
+
+      await new Promise((resolve) => setTimeout(resolve, 2000));
+
+      await program.methods
+        .sweepExpired()
+        .accounts({
+          merchantPool: merchantPoolPda2,
+          affiliateAccount: affiliatePda2,
+        })
+        .rpc();
+
+      const affiliateAccount = await program.account.affiliateAccount.fetch(affiliatePda2);
+      expect(affiliateAccount.claimable.toNumber()).to.equal(0);
+      const poolAccount = await program.account.merchantPool.fetch(merchantPoolPda2);
+      expect(poolAccount.totalClaimable.toNumber()).to.equal(0);
+
+      await program.methods.setClaimWindow(new anchor.BN(0)).accounts(poolAccounts).signers([merchant]).rpc();
+      await program.methods.setPayoutMode({ push: {} }).accounts(poolAccounts).signers([merchant]).rpc();
+      console.log("✓ Expired commissions swept back to escrow");
+    });
+  });
+
+  describe("Partial Refunds", () => {
+    it("Claws back a proportional share of a paid commission as debt", async () => {
+      // order-2: 100 USDC sale in pool 2 that paid 10 USDC commission
+      await program.methods
+        .refundPartial("order-2", new anchor.BN(25_000_000))
+        .accounts({
+          merchantPool: merchantPoolPda2,
+          saleReceipt: receiptPda(merchantPoolPda2, "order-2"),
+          affiliateAccount: affiliatePda2,
+          merchant: merchant.publicKey,
+        })
+        .signers([merchant])
+        .rpc();
+
+      const receipt = await program.account.saleReceipt.fetch(receiptPda(merchantPoolPda2, "order-2"));
+      expect(receipt.refundedAmount.toNumber()).to.equal(25_000_000);
+      expect(receipt.clawedBack.toNumber()).to.equal(2_500_000);
+
+      const affiliateAccount = await program.account.affiliateAccount.fetch(affiliatePda2);
+      expect(affiliateAccount.commissionDebt.toNumber()).to.equal(2_500_000);
+      console.log("✓ Clawback recorded as affiliate debt");
+    });
+
+    it("Nets outstanding debt against the next commission", async () => {
+      const balanceBefore = (await getAccount(provider.connection, affiliate2Usdc)).amount;
+
+      await program.methods
+        .processSale(saleArgs(50_000_000, "refund-1"))
+        .accounts({
+          merchantPool: merchantPoolPda2,
+          affiliateAccount: affiliatePda2,
+          affiliateWallet: affiliate2.publicKey,
+          escrowAuthority: escrowAuthorityPda2,
+          escrowUsdc: escrowUsdc2,
+          affiliateUsdc: affiliate2Usdc,
+          saleReceipt: receiptPda(merchantPoolPda2, "refund-1"),
+          usdcMint: usdcMint,
+          authority: backend.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([backend])
+        .rpc();
+
+      const balanceAfter = (await getAccount(provider.connection, affiliate2Usdc)).amount;
+      expect(Number(balanceAfter - balanceBefore)).to.equal(2_500_000);
+
+      const affiliateAccount = await program.account.affiliateAccount.fetch(affiliatePda2);
+      expect(affiliateAccount.commissionDebt.toNumber()).to.equal(0);
+      console.log("✓ Debt recovered from the next commission");
+    });
+
+    it("Rejects refunding more than the remaining sale amount", async () => {
+      try {
+        await program.methods
+          .refundPartial("order-2", new anchor.BN(75_000_001))
+          .accounts({
+            merchantPool: merchantPoolPda2,
+            saleReceipt: receiptPda(merchantPoolPda2, "order-2"),
+            affiliateAccount: affiliatePda2,
+            merchant: merchant.publicKey,
+          })
+          .signers([merchant])
+          .rpc();
+
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("InvalidRefundAmount");
+        console.log("✓ Rejected over-refund");
+      }
+    });
+  });
+
+  describe("Leaderboard", () => {
+    let leaderboardPda: PublicKey;
+    const saleAccounts = (orderId: string) => ({
+      merchantPool: merchantPoolPda2,
+      affiliateAccount: affiliatePda2,
+      affiliateWallet: affiliate2.publicKey,
+      escrowAuthority: escrowAuthorityPda2,
+      escrowUsdc: escrowUsdc2,
+      affiliateUsdc: affiliate2Usdc,
+      saleReceipt: receiptPda(merchantPoolPda2, orderId),
+      usdcMint: usdcMint,
+      authority: backend.publicKey,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+    });
+
+    before(async () => {
+      [leaderboardPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("leaderboard"), merchantPoolPda2.toBuffer()],
+        program.programId
+      );
+    });
+
+    it("Requires the leaderboard once a pool keeps one", async () => {
+      await program.methods
+        .initLeaderboard()
+        .accounts({
+          merchantPool: merchantPoolPda2,
+          leaderboard: leaderboardPda,
+          merchant: merchant.publicKey,
+          rentPayer: merchant.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([merchant])
+        .rpc();
+
+      try {
+        await program.methods
+          .processSale(saleArgs(10_000_000, "board-1"))
+          .accounts(saleAccounts("board-1"))
+          .signers([backend])
+          .rpc();
+
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("LeaderboardRequired");
+        console.log("✓ Rejected sale that skipped the leaderboard");
+      }
+    });
+
+    it("Ranks the affiliate by volume on each sale", async () => {
+      await program.methods
+        .processSale(saleArgs(10_000_000, "board-1"))
+        .accounts({ ...saleAccounts("board-1"), leaderboard: leaderboardPda })
+        .signers([backend])
+        .rpc();
+
+      const affiliateAccount = await program.account.affiliateAccount.fetch(affiliatePda2);
+      const leaderboard = await program.account.leaderboard.fetch(leaderboardPda);
+      expect(leaderboard.entries[0].affiliate.toString()).to.equal(affiliatePda2.toString());
+      expect(leaderboard.entries[0].volume.toNumber()).to.equal(
+        affiliateAccount.totalSaleVolume.toNumber()
+      );
+      expect(leaderboard.entries[1].volume.toNumber()).to.equal(0);
+      console.log("✓ Leaderboard updated with affiliate volume");
+    });
+  });
+
+  describe("Program Pause", () => {
+    const setPaused = (paused: boolean) =>
+      program.methods
+        .setPaused(paused)
+        .accounts({
+          config: configPda,
+          admin: provider.wallet.publicKey,
+        })
+        .rpc();
+
+    it("Only lets the admin pause the program", async () => {
+      try {
+        await program.methods
+          .setPaused(true)
+          .accounts({
+            config: configPda,
+            admin: merchant.publicKey,
+          })
+          .signers([merchant])
+          .rpc();
+
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("Unauthorized");
+        console.log("✓ Rejected pause from non-admin");
+      }
+    });
+
+    it("Halts sales but not withdrawals while paused", async () => {
+      await setPaused(true);
+
+      try {
+        await program.methods
+          .processSale(saleArgs(10_000_000, "paused-1"))
+          .accounts({
+            merchantPool: merchantPoolPda1,
+            affiliateAccount: affiliatePda1,
+            affiliateWallet: affiliate.publicKey,
+            escrowAuthority: escrowAuthorityPda1,
+            escrowUsdc: escrowUsdc1,
+            affiliateUsdc: affiliateUsdc,
+            saleReceipt: receiptPda(merchantPoolPda1, "paused-1"),
+            usdcMint: usdcMint,
+            authority: backend.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([backend])
+          .rpc();
+
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("ProgramPaused");
+      }
+
+      const before = (await getAccount(provider.connection, merchantUsdc)).amount;
+      await program.methods
+        .withdrawEscrow(new anchor.BN(1_000_000), null, null)
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          merchant: merchant.publicKey,
+          merchantUsdc: merchantUsdc,
+          escrowAuthority: escrowAuthorityPda1,
+          escrowUsdc: escrowUsdc1,
+          usdcMint: usdcMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([merchant])
+        .rpc();
+      const after = (await getAccount(provider.connection, merchantUsdc)).amount;
+      expect(Number(after - before)).to.equal(1_000_000);
+
+      await setPaused(false);
+      const config = await program.account.programConfig.fetch(configPda);
+      expect(config.paused).to.be.false;
+      console.log("✓ Pause blocked sales and allowed withdrawals");
+    });
+  });
+
+  describe("Affiliate Flag", () => {
+    const withdraw = () =>
+      program.methods
+        .withdrawEscrow(new anchor.BN(1_000_000), null, null)
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          merchant: merchant.publicKey,
+          merchantUsdc: merchantUsdc,
+          escrowAuthority: escrowAuthorityPda1,
+          escrowUsdc: escrowUsdc1,
+          usdcMint: usdcMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([merchant])
+        .rpc();
+
+    it("Requires co-signers holding most of the pool's volume", async () => {
+      try {
+        await program.methods
+          .flagPool()
+          .accounts({ config: configPda, merchantPool: merchantPoolPda1, merchantReputation: reputationPda })
+          .rpc();
+
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("InsufficientFlagVolume");
+        console.log("✓ Rejected flag without enough volume");
+      }
+    });
+
+    it("Freezes withdrawals until the admin clears the flag", async () => {
+      // Affiliate 1 drove most of pool 1's sales
+      await program.methods
+        .flagPool()
+        .accounts({ config: configPda, merchantPool: merchantPoolPda1, merchantReputation: reputationPda })
+        .remainingAccounts([
+          { pubkey: affiliatePda1, isSigner: false, isWritable: false },
+          { pubkey: affiliate.publicKey, isSigner: true, isWritable: false },
+        ])
+        .signers([affiliate])
+        .rpc();
+
+      try {
+        await withdraw();
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("PoolFlagged");
+      }
+
+      await program.methods
+        .clearPoolFlag(true)
+        .accounts({
+          config: configPda,
+          admin: provider.wallet.publicKey,
+          merchantPool: merchantPoolPda1,
+          merchantReputation: reputationPda,
+        })
+        .rpc();
+
+      await withdraw();
+      const poolAccount = await program.account.merchantPool.fetch(merchantPoolPda1);
+      expect(poolAccount.flaggedAt.toNumber()).to.equal(0);
+      const reputation = await program.account.merchantReputation.fetch(reputationPda);
+      expect(reputation.freezes).to.equal(1);
+      expect(reputation.disputesLost).to.equal(1);
+      console.log("✓ Flag froze withdrawals until cleared");
+    });
+
+    it("Syncs the merchant's reputation with pool payouts", async () => {
+      const sync = (pool: PublicKey) =>
+        program.methods
+          .syncMerchantReputation()
+          .accounts({
+            config: configPda,
+            merchantPool: pool,
+            merchantRegistry: registryPda,
+            merchantReputation: reputationPda,
+            payer: provider.wallet.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .rpc();
+
+      await sync(merchantPoolPda1);
+      await sync(merchantPoolPda2);
+      // A repeated sync only applies what changed since the last one
+      await sync(merchantPoolPda1);
+
+      const pool1 = await program.account.merchantPool.fetch(merchantPoolPda1);
+      const pool2 = await program.account.merchantPool.fetch(merchantPoolPda2);
+      const reputation = await program.account.merchantReputation.fetch(reputationPda);
+      const registry = await program.account.merchantRegistry.fetch(registryPda);
+      expect(reputation.totalPaid.toNumber()).to.equal(
+        pool1.totalCommissionsPaid.toNumber() + pool2.totalCommissionsPaid.toNumber()
+      );
+      expect(reputation.poolsCreated.toNumber()).to.equal(registry.poolCount.toNumber());
+      console.log("✓ Reputation shows", reputation.totalPaid.toNumber(), "paid across pools");
+    });
+  });
+
+  describe("Protocol Fees", () => {
+    const setFeeTiers = (feeTiers: { minMonthlyVolume: number; feeBps: number }[]) =>
+      program.methods
+        .setFeeTiers(
+          feeTiers.map(({ minMonthlyVolume, feeBps }) => ({
+            minMonthlyVolume: new anchor.BN(minMonthlyVolume),
+            feeBps,
+          }))
+        )
+        .accounts({ config: configPda, admin: provider.wallet.publicKey })
+        .rpc();
+    const sale = (orderId: string) =>
+      program.methods
+        .processSale(saleArgs(20_000_000, orderId))
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          affiliateAccount: affiliatePda1,
+          affiliateWallet: affiliate.publicKey,
+          escrowAuthority: escrowAuthorityPda1,
+          escrowUsdc: escrowUsdc1,
+          affiliateUsdc: affiliateUsdc,
+          saleReceipt: receiptPda(merchantPoolPda1, orderId),
+          usdcMint: usdcMint,
+          authority: backend.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([backend])
+        .rpc();
+    const feesAccrued = async () =>
+      (await program.account.merchantPool.fetch(merchantPoolPda1)).protocolFeesAccrued.toNumber();
+    const setFeeExempt = (feeExempt: boolean, admin: Keypair | null = null) =>
+      program.methods
+        .setFeeExempt(feeExempt)
+        .accounts({
+          config: configPda,
+          admin: admin ? admin.publicKey : provider.wallet.publicKey,
+          merchantPool: merchantPoolPda1,
+        })
+        .signers(admin ? [admin] : [])
+        .rpc();
+
+    it("Lets only the admin exempt a pool", async () => {
+      try {
+        await setFeeExempt(true, merchant);
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("Unauthorized");
+      }
+
+      await setFeeExempt(true);
+      expect((await program.account.merchantPool.fetch(merchantPoolPda1)).feeExempt).to.equal(1);
+
+      await setFeeExempt(false);
+      expect((await program.account.merchantPool.fetch(merchantPoolPda1)).feeExempt).to.equal(0);
+      console.log("✓ Admin toggled the pool's fee exemption");
+    });
+
+    it("Rejects a fee schedule that doesn't start at zero volume", async () => {
+      try {
+        await setFeeTiers([{ minMonthlyVolume: 1_000_000, feeBps: 100 }]);
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("InvalidFeeTiers");
+        console.log("✓ Rejected malformed fee schedule");
+      }
+    });
+
+    it("Charges the fee of the pool's monthly volume tier", async () => {
+      // Pool 1's monthly volume is already past 20 USDC, putting it in the second tier
+      await setFeeTiers([
+        { minMonthlyVolume: 0, feeBps: 100 },
+        { minMonthlyVolume: 20_000_000, feeBps: 50 },
+      ]);
+      const before = await feesAccrued();
+      const quote = await program.methods
+        .quoteCommission(new anchor.BN(20_000_000))
+        .accounts({ merchantPool: merchantPoolPda1, affiliateAccount: affiliatePda1 })
+        .view();
+
+      await sale("fee-1");
+
+      expect((await feesAccrued()) - before).to.equal(100_000);
+      expect(quote.protocolFee.toNumber()).to.equal(100_000);
+      console.log("✓ Second-tier fee reserved for the treasury");
+    });
+
+    it("Sweeps accrued fees to the treasury", async () => {
+      // The treasury defaults to the admin
+      const treasuryUsdc = (
+        await getOrCreateAssociatedTokenAccount(provider.connection, backend, usdcMint, provider.wallet.publicKey)
+      ).address;
+      const owed = await feesAccrued();
+      const before = (await getAccount(provider.connection, treasuryUsdc)).amount;
+
+      await program.methods
+        .collectProtocolFees()
+        .accounts({
+          config: configPda,
+          admin: provider.wallet.publicKey,
+          merchantPool: merchantPoolPda1,
+          escrowAuthority: escrowAuthorityPda1,
+          escrowUsdc: escrowUsdc1,
+          treasuryUsdc,
+          usdcMint: usdcMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+      const after = (await getAccount(provider.connection, treasuryUsdc)).amount;
+      expect(Number(after - before)).to.equal(owed);
+      expect(await feesAccrued()).to.equal(0);
+      console.log("✓ Protocol fees collected:", owed);
+    });
+
+    it("Skips the fee for exempt pools", async () => {
+      await setFeeExempt(true);
+      const before = await feesAccrued();
+
+      await sale("fee-2");
+
+      expect(await feesAccrued()).to.equal(before);
+      await setFeeExempt(false);
+      await setFeeTiers([]);
+      console.log("✓ Exempt pool paid no protocol fee");
+    });
+  });
+
+  describe("Deactivate Pool", () => {
+    it("Deactivates pool 2", async () => {
+      await program.methods
+        .deactivatePool()
+        .accounts({
+          merchantPool: merchantPoolPda2,
+          merchant: merchant.publicKey,
+        })
+        .signers([merchant])
+        .rpc();
+
+      const poolAccount = await program.account.merchantPool.fetch(merchantPoolPda2);
+      expect(poolAccount.isActive).to.equal(0);
+      console.log("✓ Pool 2 deactivated");
+    });
+
+    it("Cannot process sale in deactivated pool", async () => {
+      try {
+        await program.methods
+          .processSale(saleArgs(50_000_000, "order-4"))
+          .accounts({
+            merchantPool: merchantPoolPda2,
+            affiliateAccount: affiliatePda2,
+            affiliateWallet: affiliate2.publicKey,
+            escrowAuthority: escrowAuthorityPda2,
+            escrowUsdc: escrowUsdc2,
+            affiliateUsdc: affiliate2Usdc,
+            saleReceipt: receiptPda(merchantPoolPda2, "order-4"),
+            usdcMint: usdcMint,
+            authority: backend.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([backend])
+          .rpc();
+
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("PoolInactive");
+        console.log("✓ Cannot process sale in deactivated pool");
+      }
+    });
+
+    it("Deactivates an affiliate idle past the inactivity period", async () => {
+      await program.methods
+        .setInactivityPeriod(new anchor.BN(1))
+        .accounts({
+          merchantPool: merchantPoolPda2,
+          merchant: merchant.publicKey,
+        })
+        .signers([merchant])
+        .rpc();
+
+      await new Promise((resolve) => setTimeout(resolve, 2000));
+
+      await program.methods
+        .deactivateStaleAffiliate()
+        .accounts({
+          merchantPool: merchantPoolPda2,
+          affiliateAccount: affiliatePda2,
+        })
+        .rpc();
+
+      const affiliateAccount = await program.account.affiliateAccount.fetch(affiliatePda2);
+      expect(affiliateAccount.isActive).to.be.false;
+      expect(affiliateAccount.lastSaleAt.toNumber()).to.be.greaterThan(0);
+      console.log("✓ Stale affiliate deactivated by crank");
+    });
+  });
+
+  describe("Remove Affiliate", () => {
+    it("Deactivates affiliate in pool 1", async () => {
+      await program.methods
+        .removeAffiliate()
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          affiliateAccount: affiliatePda1,
+          affiliateWallet: affiliate.publicKey,
+          merchant: merchant.publicKey,
+        })
+        .signers([merchant])
+        .rpc();
+
+      const affiliateAccount = await program.account.affiliateAccount.fetch(affiliatePda1);
+      expect(affiliateAccount.isActive).to.be.false;
+      console.log("✓ Affiliate deactivated in Pool 1");
+    });
+
+    it("Cannot process sale for deactivated affiliate", async () => {
+      try {
+        await program.methods
+          .processSale(saleArgs(50_000_000, "order-5"))
+          .accounts({
+            merchantPool: merchantPoolPda1,
+            affiliateAccount: affiliatePda1,
+            affiliateWallet: affiliate.publicKey,
+            escrowAuthority: escrowAuthorityPda1,
+            escrowUsdc: escrowUsdc1,
+            affiliateUsdc: affiliateUsdc,
+            saleReceipt: receiptPda(merchantPoolPda1, "order-5"),
+            usdcMint: usdcMint,
+            authority: backend.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([backend])
+          .rpc();
+
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("AffiliateInactive");
+        console.log("✓ Cannot process sale for deactivated affiliate");
+      }
+    });
+  });
+
+  describe("Leave Pool", () => {
+    const leaver = Keypair.generate();
+    const leaverPda = () =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("affiliate"), merchantPoolPda1.toBuffer(), leaver.publicKey.toBuffer()],
+        program.programId
+      )[0];
+    const leave = (close: boolean) =>
+      program.methods
+        .leavePool(close)
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          affiliateAccount: leaverPda(),
+          affiliateWallet: leaver.publicKey,
+          refIdIndex: refIdPda(merchantPoolPda1, "LEAVE01"),
+          merchant: merchant.publicKey,
+        })
+        .signers([leaver])
+        .rpc();
+
+    before(async () => {
+      const pool = await program.account.merchantPool.fetch(merchantPoolPda1);
+      await program.methods
+        .addAffiliate("LEAVE01")
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          affiliateAccount: leaverPda(),
+          affiliateEntry: affiliateEntryPda(merchantPoolPda1, pool.affiliateCount.toNumber()),
+          refIdIndex: refIdPda(merchantPoolPda1, "LEAVE01"),
+          affiliateWallet: leaver.publicKey,
+          merchant: merchant.publicKey,
+          rentPayer: merchant.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([merchant])
+        .rpc();
+    });
+
+    it("Lets an affiliate deactivate itself", async () => {
+      await leave(false);
+
+      const affiliateAccount = await program.account.affiliateAccount.fetch(leaverPda());
+      expect(affiliateAccount.isActive).to.be.false;
+      console.log("✓ Affiliate left the pool");
+    });
+
+    it("Closes the affiliate's accounts and refunds the merchant's rent", async () => {
+      const merchantLamportsBefore = await provider.connection.getBalance(merchant.publicKey);
+
+      await leave(true);
+
+      expect(await program.account.affiliateAccount.fetchNullable(leaverPda())).to.be.null;
+      expect(await program.account.refIdIndex.fetchNullable(refIdPda(merchantPoolPda1, "LEAVE01"))).to.be.null;
+      expect(await provider.connection.getBalance(merchant.publicKey)).to.be.greaterThan(merchantLamportsBefore);
+      console.log("✓ Affiliate accounts closed on leaving");
+    });
+  });
+
+  describe("Session Keys", () => {
+    const session = Keypair.generate();
+    const sessionKeyPda = () =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("session_key"), merchantPoolPda1.toBuffer(), session.publicKey.toBuffer()],
+        program.programId
+      )[0];
+    const createSessionKey = (expiry: number) =>
+      program.methods
+        .createSessionKey(session.publicKey, new anchor.BN(expiry), {
+          maxSaleAmount: new anchor.BN(100_000_000),
+          commissionBudget: new anchor.BN(50_000_000),
+        })
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          sessionKey: sessionKeyPda(),
+          authority: backend.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([backend])
+        .rpc();
+
+    it("Rejects session keys that outlive the maximum duration", async () => {
+      try {
+        await createSessionKey(Math.floor(Date.now() / 1000) + 7 * 24 * 60 * 60);
+
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("InvalidSessionExpiry");
+        console.log("✓ Rejected long-lived session key");
+      }
+    });
+
+    it("Issues and revokes a session key", async () => {
+      await createSessionKey(Math.floor(Date.now() / 1000) + 60 * 60);
+
+      const sessionKey = await program.account.sessionKey.fetch(sessionKeyPda());
+      expect(sessionKey.session.toString()).to.equal(session.publicKey.toString());
+      expect(sessionKey.issuedBy.toString()).to.equal(backend.publicKey.toString());
+      expect(sessionKey.commissionSpent.toNumber()).to.equal(0);
+
+      await program.methods
+        .revokeSessionKey()
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          sessionKey: sessionKeyPda(),
+          authority: backend.publicKey,
+        })
+        .signers([backend])
+        .rpc();
+
+      expect(await program.account.sessionKey.fetchNullable(sessionKeyPda())).to.be.null;
+      console.log("✓ Session key issued and revoked");
+    });
+  });
+
+  describe("Affiliate Approvals", () => {
+    const approver = Keypair.generate();
+    const candidate = Keypair.generate();
+    const candidatePda = () =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("affiliate"), merchantPoolPda1.toBuffer(), candidate.publicKey.toBuffer()],
+        program.programId
+      )[0];
+    const pendingPda = () =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("pending_affiliate"), candidatePda().toBuffer()],
+        program.programId
+      )[0];
+    const setApprovers = (approvers: PublicKey[], threshold: number) =>
+      program.methods
+        .setAffiliateApprovers(approvers, threshold)
+        .accounts({ merchantPool: merchantPoolPda1, merchant: merchant.publicKey })
+        .signers([merchant])
+        .rpc();
+
+    it("Adds affiliates inactive until the approval threshold is met", async () => {
+      await setApprovers([approver.publicKey], 1);
+
+      const pool = await program.account.merchantPool.fetch(merchantPoolPda1);
+      await program.methods
+        .addAffiliate("APPROVE01")
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          affiliateAccount: candidatePda(),
+          affiliateEntry: affiliateEntryPda(merchantPoolPda1, pool.affiliateCount.toNumber()),
+          refIdIndex: refIdPda(merchantPoolPda1, "APPROVE01"),
+          affiliateWallet: candidate.publicKey,
+          merchant: merchant.publicKey,
+          rentPayer: merchant.publicKey,
+          systemProgram: SystemProgram.programId,
+          pendingAffiliate: pendingPda(),
+        })
+        .signers([merchant])
+        .rpc();
+      expect((await program.account.affiliateAccount.fetch(candidatePda())).isActive).to.be.false;
+      await activateSelf(merchantPoolPda1, candidate);
+
+      const contactHash = Array.from(createHash("sha256").update("candidate@example.com").digest());
+      await program.methods
+        .submitAffiliateApplication("https://example.com/candidate", contactHash)
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          affiliateAccount: candidatePda(),
+          pendingAffiliate: pendingPda(),
+          affiliateWallet: candidate.publicKey,
+        })
+        .signers([candidate])
+        .rpc();
+      const application = await program.account.pendingAffiliate.fetch(pendingPda());
+      expect(application.profileUri).to.equal("https://example.com/candidate");
+      expect(application.contactHash).to.deep.equal(contactHash);
+
+      await program.methods
+        .approveAffiliate()
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          affiliateAccount: candidatePda(),
+          pendingAffiliate: pendingPda(),
+          approver: approver.publicKey,
+        })
+        .signers([approver])
+        .rpc();
+
+      expect((await program.account.affiliateAccount.fetch(candidatePda())).isActive).to.be.true;
+      const pending = await program.account.pendingAffiliate.fetch(pendingPda());
+      expect(pending.approvals.map((key) => key.toString())).to.deep.equal([approver.publicKey.toString()]);
+      expect(pending.approvedAt.toNumber()).to.be.greaterThan(0);
+
+      await setApprovers([], 0);
+      console.log("✓ Affiliate activated by operator approval");
+    });
+
+    it("Splits a sale's commission between affiliates by weight", async () => {
+      const candidateUsdc = (
+        await getOrCreateAssociatedTokenAccount(provider.connection, merchant, usdcMint, candidate.publicKey)
+      ).address;
+      const splitSale = (orderId: string, weightsBps: number[]) =>
+        program.methods
+          .processSaleMulti({ saleAmount: new anchor.BN(10_000_000), orderId, weightsBps })
+          .accounts({
+            merchantPool: merchantPoolPda1,
+            escrowAuthority: escrowAuthorityPda1,
+            escrowUsdc: escrowUsdc1,
+            saleReceipt: receiptPda(merchantPoolPda1, orderId),
+            usdcMint: usdcMint,
+            authority: backend.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+          })
+          .remainingAccounts([
+            { pubkey: affiliatePda1, isSigner: false, isWritable: true },
+            { pubkey: affiliateUsdc, isSigner: false, isWritable: true },
+            { pubkey: candidatePda(), isSigner: false, isWritable: true },
+            { pubkey: candidateUsdc, isSigner: false, isWritable: true },
+          ])
+          .signers([backend])
+          .rpc();
+
+      try {
+        await splitSale("split-0", [6000, 3000]);
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("InvalidSplitWeights");
+      }
+
+      // Split sales can't hold commissions back, so they're refused rather
+      // than paid out right away
+      const setHoldback = (secs: number) =>
+        program.methods
+          .setHoldbackPeriod(new anchor.BN(secs))
+          .accounts({ merchantPool: merchantPoolPda1, merchant: merchant.publicKey })
+          .signers([merchant])
+          .rpc();
+      await setHoldback(60);
+      try {
+        await splitSale("split-held", [6000, 4000]);
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("PoolSplitSaleUnsupported");
+      } finally {
+        await setHoldback(0);
+      }
+
+      const leadBefore = await program.account.affiliateAccount.fetch(affiliatePda1);
+      const candidateBefore = await program.account.affiliateAccount.fetch(candidatePda());
+
+      const signature = await splitSale("split-1", [6000, 4000]);
+
+      // Every payout transfer comes right after a memo with the order id
+      const logs = (
+        await provider.connection.getTransaction(signature, {
+          commitment: "confirmed",
+          maxSupportedTransactionVersion: 0,
+        })
+      )!.meta!.logMessages!;
+      const memos = logs.filter((log) => log.includes("Memo (len"));
+      expect(memos.length).to.equal(logs.filter((log) => log.includes("Instruction: TransferChecked")).length);
+      expect(memos.every((log) => log.includes('"split-1"'))).to.be.true;
+
+      const receipt = await program.account.saleReceipt.fetch(receiptPda(merchantPoolPda1, "split-1"));
+      const commission = receipt.commission.toNumber();
+      const leadAfter = await program.account.affiliateAccount.fetch(affiliatePda1);
+      const candidateAfter = await program.account.affiliateAccount.fetch(candidatePda());
+      const leadShare = leadAfter.totalEarned.toNumber() - leadBefore.totalEarned.toNumber();
+      const candidateShare = candidateAfter.totalEarned.toNumber() - candidateBefore.totalEarned.toNumber();
+      expect(leadShare).to.equal((commission * 6000) / 10000);
+      expect(leadShare + candidateShare).to.equal(commission);
+      expect(receipt.affiliate.toBase58()).to.equal(affiliatePda1.toBase58());
+      expect(receipt.split).to.be.true;
+      console.log("✓ Split", commission, "as", leadShare, "/", candidateShare);
+
+      // The receipt can't say which affiliate to claw a refund back from
+      try {
+        await program.methods
+          .refundPartial("split-1", new anchor.BN(1_000_000))
+          .accounts({
+            merchantPool: merchantPoolPda1,
+            saleReceipt: receiptPda(merchantPoolPda1, "split-1"),
+            affiliateAccount: affiliatePda1,
+            merchant: merchant.publicKey,
+          })
+          .signers([merchant])
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("SplitSaleRefundUnsupported");
+      }
+      console.log("✓ Refund of a split sale refused");
+    });
+
+    it("Keeps split affiliates on their old rate during a cut's grace period", async () => {
+      const poolAccounts = { merchantPool: merchantPoolPda1, merchant: merchant.publicKey };
+      const candidateUsdc = getAssociatedTokenAddressSync(usdcMint, candidate.publicKey);
+      const preCut = await program.account.saleReceipt.fetch(receiptPda(merchantPoolPda1, "split-1"));
+      const { commissionRate } = await program.account.merchantPool.fetch(merchantPoolPda1);
+      await program.methods.setRateGracePeriod(new anchor.BN(60)).accounts(poolAccounts).signers([merchant]).rpc();
+      // The cut must land after both affiliates' last rate snapshot
+      await new Promise((resolve) => setTimeout(resolve, 1100));
+      await program.methods
+        .updatePoolCommission(Math.floor(commissionRate / 2), null, null)
+        .accounts(poolAccounts)
+        .signers([merchant])
+        .rpc();
+
+      await program.methods
+        .processSaleMulti({ saleAmount: new anchor.BN(10_000_000), orderId: "split-grace", weightsBps: [5000, 5000] })
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          escrowAuthority: escrowAuthorityPda1,
+          escrowUsdc: escrowUsdc1,
+          saleReceipt: receiptPda(merchantPoolPda1, "split-grace"),
+          usdcMint: usdcMint,
+          authority: backend.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .remainingAccounts([
+          { pubkey: affiliatePda1, isSigner: false, isWritable: true },
+          { pubkey: affiliateUsdc, isSigner: false, isWritable: true },
+          { pubkey: candidatePda(), isSigner: false, isWritable: true },
+          { pubkey: candidateUsdc, isSigner: false, isWritable: true },
+        ])
+        .signers([backend])
+        .rpc();
+
+      const receipt = await program.account.saleReceipt.fetch(receiptPda(merchantPoolPda1, "split-grace"));
+      expect(receipt.appliedRateBps).to.equal(preCut.appliedRateBps);
+      expect(receipt.commission.toNumber()).to.equal(preCut.commission.toNumber());
+
+      await program.methods
+        .updatePoolCommission(commissionRate, null, null)
+        .accounts(poolAccounts)
+        .signers([merchant])
+        .rpc();
+      await program.methods.setRateGracePeriod(new anchor.BN(0)).accounts(poolAccounts).signers([merchant]).rpc();
+      console.log("✓ Split sale paid at the pre-cut rate");
+    });
+  });
+
+  describe("Affiliate Groups", () => {
+    const GROUP_ID = "agency-1";
+    const groupPda = () =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("affiliate_group"), merchantPoolPda1.toBuffer(), Buffer.from(GROUP_ID)],
+        program.programId
+      )[0];
+    const groupAccounts = () => ({
+      merchantPool: merchantPoolPda1,
+      affiliateAccount: affiliatePda1,
+      affiliateGroup: groupPda(),
+      merchant: merchant.publicKey,
+    });
+    const sale = (orderId: string, affiliateGroup: PublicKey | null, payoutUsdc: PublicKey | null = null) =>
+      program.methods
+        .processSale(saleArgs(10_000_000, orderId))
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          affiliateAccount: affiliatePda1,
+          affiliateWallet: affiliate.publicKey,
+          escrowAuthority: escrowAuthorityPda1,
+          escrowUsdc: escrowUsdc1,
+          affiliateUsdc: affiliateUsdc,
+          saleReceipt: receiptPda(merchantPoolPda1, orderId),
+          usdcMint: usdcMint,
+          authority: backend.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          affiliateGroup,
+          payoutUsdc,
+        })
+        .signers([backend])
+        .rpc();
+
+    it("Rolls members' sales up into their group", async () => {
+      await program.methods
+        .createAffiliateGroup(GROUP_ID, 100)
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          affiliateGroup: groupPda(),
+          merchant: merchant.publicKey,
+          rentPayer: merchant.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([merchant])
+        .rpc();
+      await program.methods.assignAffiliateGroup().accounts(groupAccounts()).signers([merchant]).rpc();
+
+      try {
+        await sale("group-0", null);
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("AffiliateGroupRequired");
+      }
+
+      const quote = await program.methods
+        .quoteCommission(new anchor.BN(10_000_000))
+        .accounts({ merchantPool: merchantPoolPda1, affiliateAccount: affiliatePda1, affiliateGroup: groupPda() })
+        .view();
+      await sale("group-1", groupPda());
+
+      const receipt = await program.account.saleReceipt.fetch(receiptPda(merchantPoolPda1, "group-1"));
+      expect(quote.commissionRate).to.equal(receipt.appliedRateBps);
+      const group = await program.account.affiliateGroup.fetch(groupPda());
+      expect(group.memberCount).to.equal(1);
+      expect(group.salesCount.toNumber()).to.equal(1);
+      expect(group.totalSaleVolume.toNumber()).to.equal(10_000_000);
+      expect(group.totalEarned.toNumber()).to.equal(receipt.commission.toNumber());
+      console.log("✓ Group rolled up a sale at", receipt.appliedRateBps, "bps");
+    });
+
+    const agency = Keypair.generate();
+    const agencyUsdc = () => getAssociatedTokenAddressSync(usdcMint, agency.publicKey);
+    const setGroupPayoutAccount = (payoutUsdc: PublicKey | null) =>
+      program.methods
+        .setGroupPayoutAccount()
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          affiliateGroup: groupPda(),
+          merchant: merchant.publicKey,
+          escrowAuthority: escrowAuthorityPda1,
+          payoutUsdc,
+        })
+        .signers([merchant])
+        .rpc();
+
+    it("Routes members' commissions to the group's payout account", async () => {
+      await getOrCreateAssociatedTokenAccount(provider.connection, merchant, usdcMint, agency.publicKey);
+      await setGroupPayoutAccount(agencyUsdc());
+      const before = await program.account.affiliateAccount.fetch(affiliatePda1);
+      const affiliateBefore = (await getAccount(provider.connection, affiliateUsdc)).amount;
+
+      await sale("group-2", groupPda(), agencyUsdc());
+
+      const receipt = await program.account.saleReceipt.fetch(receiptPda(merchantPoolPda1, "group-2"));
+      const after = await program.account.affiliateAccount.fetch(affiliatePda1);
+      const affiliateAfter = (await getAccount(provider.connection, affiliateUsdc)).amount;
+      const agencyBalance = (await getAccount(provider.connection, agencyUsdc())).amount;
+      expect(affiliateAfter).to.equal(affiliateBefore);
+      expect(Number(agencyBalance)).to.equal(receipt.commission.toNumber());
+      expect(after.totalEarned.sub(before.totalEarned).toNumber()).to.equal(receipt.commission.toNumber());
+      console.log("✓ Member commission paid to the agency account");
+    });
+
+    it("Releases a member's matured holdback to claim by the group's payout account", async () => {
+      const setHoldback = (secs: number) =>
+        program.methods
+          .setHoldbackPeriod(new anchor.BN(secs))
+          .accounts({ merchantPool: merchantPoolPda1, merchant: merchant.publicKey })
+          .signers([merchant])
+          .rpc();
+      await setHoldback(1);
+      await sale("group-held", groupPda(), agencyUsdc());
+      await setHoldback(0);
+      const held = (await program.account.saleReceipt.fetch(receiptPda(merchantPoolPda1, "group-held"))).heldAmount;
+      expect(held.toNumber()).to.be.greaterThan(0);
+      await new Promise((resolve) => setTimeout(resolve, 2000));
+
+      const claimableBefore = (await program.account.affiliateAccount.fetch(affiliatePda1)).claimable;
+      const affiliateBefore = (await getAccount(provider.connection, affiliateUsdc)).amount;
+      await program.methods
+        .releaseMaturedHoldbacks()
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          escrowAuthority: escrowAuthorityPda1,
+          escrowUsdc: escrowUsdc1,
+          usdcMint: usdcMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts([
+          { pubkey: receiptPda(merchantPoolPda1, "group-held"), isWritable: true, isSigner: false },
+          { pubkey: affiliatePda1, isWritable: true, isSigner: false },
+          { pubkey: affiliateUsdc, isWritable: true, isSigner: false },
+        ])
+        .rpc();
+
+      // Nothing reaches the member's own wallet; the claim goes to the agency
+      expect((await getAccount(provider.connection, affiliateUsdc)).amount).to.equal(affiliateBefore);
+      const claimable = (await program.account.affiliateAccount.fetch(affiliatePda1)).claimable;
+      expect(claimable.sub(claimableBefore).toNumber()).to.equal(held.toNumber());
+
+      const agencyBefore = (await getAccount(provider.connection, agencyUsdc())).amount;
+      await program.methods
+        .claimCommissions()
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          affiliateAccount: affiliatePda1,
+          affiliateWallet: affiliate.publicKey,
+          escrowAuthority: escrowAuthorityPda1,
+          escrowUsdc: escrowUsdc1,
+          affiliateUsdc: affiliateUsdc,
+          usdcMint: usdcMint,
+          authority: affiliate.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          affiliateGroup: groupPda(),
+          payoutUsdc: agencyUsdc(),
+        })
+        .signers([affiliate])
+        .rpc();
+      const agencyAfter = (await getAccount(provider.connection, agencyUsdc())).amount;
+      expect(Number(agencyAfter - agencyBefore)).to.equal(claimable.toNumber());
+      expect((await getAccount(provider.connection, affiliateUsdc)).amount).to.equal(affiliateBefore);
+
+      await setGroupPayoutAccount(null);
+      expect((await program.account.affiliateGroup.fetch(groupPda())).payoutAccount).to.be.null;
+      await program.methods.removeAffiliateFromGroup().accounts(groupAccounts()).signers([merchant]).rpc();
+      expect((await program.account.affiliateAccount.fetch(affiliatePda1)).group).to.be.null;
+      console.log("✓ Member holdback released to the agency's claim");
+    });
+  });
+
+  describe("Clone Pool", () => {
+    it("Copies a pool's settings into a new, unfunded pool", async () => {
+      const poolId = "pool-1-november";
+      const clonePda = poolPda(merchant.publicKey, poolId);
+      const [cloneEscrowAuthority] = PublicKey.findProgramAddressSync(
+        [Buffer.from("escrow_authority"), clonePda.toBuffer()],
+        program.programId
+      );
+      const cloneEscrowUsdc = getAssociatedTokenAddressSync(usdcMint, cloneEscrowAuthority, true);
+      const poolCount = (await program.account.merchantRegistry.fetch(registryPda)).poolCount.toNumber();
+
+      await program.methods
+        .clonePool(poolId)
+        .accounts({
+          sourcePool: merchantPoolPda1,
+          merchantPool: clonePda,
+          escrowAuthority: cloneEscrowAuthority,
+          merchant: merchant.publicKey,
+          rentPayer: merchant.publicKey,
+          escrowUsdc: cloneEscrowUsdc,
+          merchantRegistry: registryPda,
+          merchantReputation: reputationPda,
+          poolEntry: poolEntryPda(poolCount),
+          usdcMint: usdcMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([merchant])
+        .rpc();
+
+      const source = await program.account.merchantPool.fetch(merchantPoolPda1);
+      const clone = await program.account.merchantPool.fetch(clonePda);
+      expect(fixedString(clone.poolId, clone.poolIdLen)).to.equal(poolId);
+      expect(clone.commissionRate).to.equal(source.commissionRate);
+      expect(clone.maxCommissionPerSale.toString()).to.equal(source.maxCommissionPerSale.toString());
+      expect(clone.processor.toBase58()).to.equal(source.processor.toBase58());
+      expect(clone.approverCount).to.equal(source.approverCount);
+      expect(clone.totalVolume.toNumber()).to.equal(0);
+      expect(clone.affiliateCount.toNumber()).to.equal(0);
+      expect(Number((await getAccount(provider.connection, cloneEscrowUsdc)).amount)).to.equal(0);
+
+      const entry = await program.account.poolEntry.fetch(poolEntryPda(poolCount));
+      expect(entry.pool.toBase58()).to.equal(clonePda.toBase58());
+      console.log("✓ Pool cloned without its escrow funds");
+    });
+  });
+
+  describe("Bulk Affiliate Import", () => {
+    const affiliatePda = (pool: PublicKey, wallet: PublicKey) =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("affiliate"), pool.toBuffer(), wallet.toBuffer()],
+        program.programId
+      )[0];
+
+    it("Adds a batch of affiliates in one instruction", async () => {
+      const pool = poolPda(merchant.publicKey, "pool-1-november");
+      const imports = [Keypair.generate(), Keypair.generate(), Keypair.generate()].map((wallet, i) => ({
+        wallet: wallet.publicKey,
+        refId: `IMPORTED${i}`,
+      }));
+      const remainingAccounts = imports.flatMap(({ wallet, refId }, i) =>
+        [affiliatePda(pool, wallet), affiliateEntryPda(pool, i), refIdPda(pool, refId)].map((pubkey) => ({
+          pubkey,
+          isSigner: false,
+          isWritable: true,
+        }))
+      );
+
+      try {
+        await program.methods
+          .addAffiliatesBatch({ affiliates: imports })
+          .accounts({ merchantPool: pool, merchant: merchant.publicKey, rentPayer: merchant.publicKey })
+          .remainingAccounts(remainingAccounts.slice(0, 6))
+          .signers([merchant])
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("InvalidAffiliateBatchAccounts");
+      }
+
+      await program.methods
+        .addAffiliatesBatch({ affiliates: imports })
+        .accounts({ merchantPool: pool, merchant: merchant.publicKey, rentPayer: merchant.publicKey })
+        .remainingAccounts(remainingAccounts)
+        .signers([merchant])
+        .rpc();
+
+      const poolAccount = await program.account.merchantPool.fetch(pool);
+      expect(poolAccount.affiliateCount.toNumber()).to.equal(3);
+      for (const [i, { wallet, refId }] of imports.entries()) {
+        const affiliateAccount = await program.account.affiliateAccount.fetch(affiliatePda(pool, wallet));
+        expect(affiliateAccount.refId).to.equal(refId);
+        expect(affiliateAccount.affiliateIndex.toNumber()).to.equal(i);
+        expect(affiliateAccount.isActive).to.be.true;
+        const index = await program.account.refIdIndex.fetch(refIdPda(pool, refId));
+        expect(index.affiliate.toBase58()).to.equal(affiliatePda(pool, wallet).toBase58());
+      }
+      console.log("✓ Imported 3 affiliates in one transaction");
+    });
+  });
+
+  describe("Sale Guard", () => {
+    const setSaleGuard = (enabled: boolean) =>
+      program.methods
+        .setSaleGuard(enabled)
+        .accounts({ merchantPool: merchantPoolPda1, merchant: merchant.publicKey })
+        .signers([merchant])
+        .rpc();
+    const sale = (orderId: string, instructions: PublicKey | null, sandwiched = false) =>
+      program.methods
+        .processSale(saleArgs(10_000_000, orderId))
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          affiliateAccount: affiliatePda1,
+          affiliateWallet: affiliate.publicKey,
+          escrowAuthority: escrowAuthorityPda1,
+          escrowUsdc: escrowUsdc1,
+          affiliateUsdc: affiliateUsdc,
+          saleReceipt: receiptPda(merchantPoolPda1, orderId),
+          usdcMint: usdcMint,
+          authority: backend.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          instructions,
+        })
+        .preInstructions(
+          sandwiched ? [createTransferInstruction(merchantUsdc, affiliateUsdc, merchant.publicKey, 1)] : []
+        )
+        .signers(sandwiched ? [backend, merchant] : [backend])
+        .rpc();
+
+    it("Rejects guarded sales composed with transfers on the sale's accounts", async () => {
+      await setSaleGuard(true);
+      expect((await program.account.merchantPool.fetch(merchantPoolPda1)).guardSales).to.equal(1);
+
+      try {
+        await sale("guarded-0", null);
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("InstructionsSysvarRequired");
+      }
+
+      try {
+        await sale("guarded-0", SYSVAR_INSTRUCTIONS_PUBKEY, true);
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("UnexpectedTokenInstruction");
+      }
+
+      await sale("guarded-0", SYSVAR_INSTRUCTIONS_PUBKEY);
+      const receipt = await program.account.saleReceipt.fetch(receiptPda(merchantPoolPda1, "guarded-0"));
+      expect(receipt.saleAmount.toNumber()).to.equal(10_000_000);
+
+      await setSaleGuard(false);
+      await sale("guarded-1", null, true);
+      console.log("✓ Sandwiched sale rejected while the guard was on");
+    });
+  });
+
+  describe("Click Counters", () => {
+    it("Adds batched clicks to the affiliate's counter", async () => {
+      const before = await program.account.affiliateAccount.fetch(affiliatePda1);
+
+      await program.methods
+        .recordClick(new anchor.BN(25))
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          affiliateAccount: affiliatePda1,
+          authority: backend.publicKey,
+        })
+        .signers([backend])
+        .rpc();
+
+      const after = await program.account.affiliateAccount.fetch(affiliatePda1);
+      expect(after.clicks.toNumber()).to.equal(before.clicks.toNumber() + 25);
+      console.log("✓ Clicks recorded:", after.clicks.toNumber());
+    });
+
+    it("Rejects clicks from anyone but the processor", async () => {
+      try {
+        await program.methods
+          .recordClick(new anchor.BN(1))
+          .accounts({
+            merchantPool: merchantPoolPda1,
+            affiliateAccount: affiliatePda1,
+            authority: affiliate.publicKey,
+          })
+          .signers([affiliate])
+          .rpc();
+
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("Unauthorized");
+        console.log("✓ Rejected clicks from a non-processor");
+      }
+    });
+  });
+
+  describe("Dynamic Rates", () => {
+    const setRatePolicy = (policy: any, steps: { threshold: number; rateBps: number }[]) =>
+      program.methods
+        .setRatePolicy(
+          policy,
+          steps.map((step) => ({ threshold: new anchor.BN(step.threshold), rateBps: step.rateBps }))
+        )
+        .accounts({ merchantPool: merchantPoolPda1, merchant: merchant.publicKey })
+        .signers([merchant])
+        .rpc();
+
+    it("Rejects rate steps that don't ascend", async () => {
+      try {
+        await setRatePolicy({ trailingVolume: {} }, [
+          { threshold: 1_000_000_000, rateBps: 1500 },
+          { threshold: 500_000_000, rateBps: 2000 },
+        ]);
+
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("InvalidRateSteps");
+        console.log("✓ Rejected descending rate steps");
+      }
+    });
+
+    it("Stores a trailing volume schedule and turns it off again", async () => {
+      await setRatePolicy({ trailingVolume: {} }, [
+        { threshold: 1_000_000_000, rateBps: 1500 },
+        { threshold: 5_000_000_000, rateBps: 2000 },
+      ]);
+
+      let pool = await program.account.merchantPool.fetch(merchantPoolPda1);
+      expect(pool.ratePolicy).to.equal(2);
+      expect(pool.rateStepCount).to.equal(2);
+      expect(pool.rateStepBps.slice(0, 2)).to.deep.equal([1500, 2000]);
+
+      await setRatePolicy({ flat: {} }, []);
+      pool = await program.account.merchantPool.fetch(merchantPoolPda1);
+      expect(pool.ratePolicy).to.equal(0);
+      expect(pool.rateStepCount).to.equal(0);
+      console.log("✓ Dynamic rate schedule stored and cleared");
+    });
+  });
+
+  describe("Rate Decay", () => {
+    const setRateDecay = (idleDays: number, restoreDays: number, decayBps: number) =>
+      program.methods
+        .setRateDecay(idleDays, restoreDays, decayBps)
+        .accounts({ merchantPool: merchantPoolPda1, merchant: merchant.publicKey })
+        .signers([merchant])
+        .rpc();
+    const quote = () =>
+      program.methods
+        .quoteCommission(new anchor.BN(10_000_000))
+        .accounts({ merchantPool: merchantPoolPda1, affiliateAccount: affiliatePda1 })
+        .view();
+
+    it("Rejects a decay schedule without restore days", async () => {
+      try {
+        await setRateDecay(30, 0, 5000);
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("InvalidRateDecay");
+      }
+    });
+
+    it("Keeps recently active affiliates at their full rate", async () => {
+      const fullRate = (await quote()).commissionRate;
+      await setRateDecay(30, 3, 5000);
+
+      const pool = await program.account.merchantPool.fetch(merchantPoolPda1);
+      expect(pool.rateDecayIdleDays).to.equal(30);
+      expect(pool.rateDecayRestoreDays).to.equal(3);
+      expect(pool.rateDecayBps).to.equal(5000);
+      expect((await quote()).commissionRate).to.equal(fullRate);
+      expect((await program.account.affiliateAccount.fetch(affiliatePda1)).rateDecayed).to.be.false;
+
+      await setRateDecay(0, 0, 0);
+      console.log("✓ Decay schedule stored; active affiliate kept", fullRate, "bps");
+    });
+  });
+
+  describe("Interest-bearing Mints", () => {
+    it("Quotes plain mints' sale amounts unconverted", async () => {
+      const quote = (usdcMint: PublicKey | null) =>
+        program.methods
+          .quoteCommission(new anchor.BN(10_000_000))
+          .accounts({ merchantPool: merchantPoolPda1, affiliateAccount: affiliatePda1, usdcMint })
+          .view();
+
+      const withMint = await quote(usdcMint);
+      const withoutMint = await quote(null);
+      expect(withMint.commission.toNumber()).to.equal(withoutMint.commission.toNumber());
+      console.log("✓ Plain mint quote unchanged by passing the mint");
+    });
+
+    it("Only syncs interest for interest-bearing mints", async () => {
+      try {
+        await program.methods
+          .syncInterest()
+          .accounts({
+            merchantPool: merchantPoolPda1,
+            escrowAuthority: escrowAuthorityPda1,
+            escrowUsdc: escrowUsdc1,
+            usdcMint: usdcMint,
+          })
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("MintNotInterestBearing");
+      }
+
+      const pool = await program.account.merchantPool.fetch(merchantPoolPda1);
+      expect(pool.syncedInterestScale.toNumber()).to.equal(0);
+      console.log("✓ Plain SPL mint rejected by sync_interest");
+    });
+  });
+
+  describe("Transfer Memos", () => {
+    const memoLogs = async (signature: string) => {
+      const transaction = await provider.connection.getTransaction(signature, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      return transaction!.meta!.logMessages!.filter((log) => log.includes("Memo (len"));
+    };
+
+    it("Attaches the order id to sale payouts and the pool id to withdrawals", async () => {
+      const saleSignature = await program.methods
+        .processSale(saleArgs(10_000_000, "memo-1"))
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          affiliateAccount: affiliatePda1,
+          affiliateWallet: affiliate.publicKey,
+          escrowAuthority: escrowAuthorityPda1,
+          escrowUsdc: escrowUsdc1,
+          affiliateUsdc: affiliateUsdc,
+          saleReceipt: receiptPda(merchantPoolPda1, "memo-1"),
+          usdcMint: usdcMint,
+          authority: backend.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([backend])
+        .rpc({ commitment: "confirmed" });
+      const saleMemos = await memoLogs(saleSignature);
+      expect(saleMemos).to.have.length.greaterThan(0);
+      expect(saleMemos.every((log) => log.includes('"memo-1"'))).to.be.true;
+
+      const withdrawSignature = await program.methods
+        .withdrawEscrow(new anchor.BN(1_000_000), null, null)
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          merchant: merchant.publicKey,
+          merchantUsdc: merchantUsdc,
+          escrowAuthority: escrowAuthorityPda1,
+          escrowUsdc: escrowUsdc1,
+          usdcMint: usdcMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([merchant])
+        .rpc({ commitment: "confirmed" });
+      expect(await memoLogs(withdrawSignature)).to.deep.equal([`Program log: Memo (len ${POOL_ID_1.length}): "${POOL_ID_1}"`]);
+      console.log("✓ Escrow transfers carried their memos");
+    });
+  });
+
+  describe("Escrow Authority Migration", () => {
+    const poolId = "migrating-pool";
+    let pool: PublicKey;
+    const escrowAuthority = (version: number) =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("escrow_authority"), pool.toBuffer(), ...(version > 0 ? [Buffer.from([version])] : [])],
+        program.programId
+      )[0];
+    const escrow = (version: number) => getAssociatedTokenAddressSync(usdcMint, escrowAuthority(version), true);
+    const migrate = (version: number, newVersion: number) =>
+      program.methods
+        .migrateEscrowAuthority(newVersion)
+        .accounts({
+          merchantPool: pool,
+          merchant: merchant.publicKey,
+          rentPayer: merchant.publicKey,
+          escrowAuthority: escrowAuthority(version),
+          escrowUsdc: escrow(version),
+          newEscrowAuthority: escrowAuthority(newVersion),
+          newEscrowUsdc: escrow(newVersion),
+          usdcMint: usdcMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([merchant])
+        .rpc();
+
+    before(async () => {
+      pool = poolPda(merchant.publicKey, poolId);
+      const poolCount = (await program.account.merchantRegistry.fetch(registryPda)).poolCount.toNumber();
+      await program.methods
+        .initializePool({ poolId, commissionRate: COMMISSION_RATE_1, initialDeposit: new anchor.BN(5_000_000) })
+        .accounts({
+          merchantPool: pool,
+          merchant: merchant.publicKey,
+          rentPayer: merchant.publicKey,
+          merchantUsdc: merchantUsdc,
+          escrowAuthority: escrowAuthority(0),
+          escrowUsdc: escrow(0),
+          merchantRegistry: registryPda,
+          merchantReputation: reputationPda,
+          poolEntry: poolEntryPda(poolCount),
+          usdcMint: usdcMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([merchant])
+        .rpc();
+    });
+
+    it("Moves the escrow to the authority of the next seed version", async () => {
+      await migrate(0, 1);
+
+      const poolAccount = await program.account.merchantPool.fetch(pool);
+      expect(poolAccount.escrowVersion).to.equal(1);
+      expect(Number((await getAccount(provider.connection, escrow(0))).amount)).to.equal(0);
+      expect(Number((await getAccount(provider.connection, escrow(1))).amount)).to.equal(5_000_000);
+
+      await program.methods
+        .withdrawEscrow(new anchor.BN(1_000_000), null, null)
+        .accounts({
+          merchantPool: pool,
           merchant: merchant.publicKey,
           merchantUsdc: merchantUsdc,
+          escrowAuthority: escrowAuthority(1),
+          escrowUsdc: escrow(1),
+          usdcMint: usdcMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([merchant])
+        .rpc();
+      expect(Number((await getAccount(provider.connection, escrow(1))).amount)).to.equal(4_000_000);
+      console.log("✓ Escrow migrated to seed version 1 and withdrawable there");
+    });
+
+    it("Only migrates to a higher seed version", async () => {
+      try {
+        await migrate(1, 1);
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("InvalidEscrowVersion");
+        console.log("✓ Rejected migration to the current seed version");
+      }
+    });
+
+    it("Rejects the retired escrow authority", async () => {
+      try {
+        await migrate(0, 2);
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("ConstraintSeeds");
+        console.log("✓ Retired escrow authority no longer signs for the pool");
+      }
+    });
+  });
+
+  describe("Attribution Window", () => {
+    const setWindow = (secs: number) =>
+      program.methods
+        .setAttributionWindow(new anchor.BN(secs))
+        .accounts({ merchantPool: merchantPoolPda1, merchant: merchant.publicKey })
+        .signers([merchant])
+        .rpc();
+
+    it("Registers clicks on a ref_id", async () => {
+      await program.methods
+        .registerClick(REF_ID)
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          refIdIndex: refIdPda(merchantPoolPda1, REF_ID),
+          authority: backend.publicKey,
+        })
+        .signers([backend])
+        .rpc();
+
+      const index = await program.account.refIdIndex.fetch(refIdPda(merchantPoolPda1, REF_ID));
+      expect(index.clickRegisteredAt.toNumber()).to.be.greaterThan(0);
+      console.log("✓ Click registered");
+    });
+
+    it("Rejects sales without a click while a window is set", async () => {
+      await setWindow(30 * 24 * 60 * 60);
+
+      try {
+        await program.methods
+          .processSale(saleArgs(10_000_000, "no-click-1"))
+          .accounts({
+            merchantPool: merchantPoolPda1,
+            affiliateAccount: affiliatePda1,
+            affiliateWallet: affiliate.publicKey,
+            escrowAuthority: escrowAuthorityPda1,
+            escrowUsdc: escrowUsdc1,
+            affiliateUsdc: affiliateUsdc,
+            saleReceipt: receiptPda(merchantPoolPda1, "no-click-1"),
+            usdcMint: usdcMint,
+            authority: backend.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([backend])
+          .rpc();
+
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("ClickNotRegistered");
+        console.log("✓ Rejected sale without a registered click");
+      } finally {
+        await setWindow(0);
+      }
+    });
+  });
+
+  describe("Compressed Affiliates", () => {
+    it("Rejects compressed affiliates on a pool without a tree", async () => {
+      const [treeAuthority] = PublicKey.findProgramAddressSync(
+        [Buffer.from("affiliate_tree"), merchantPoolPda1.toBuffer()],
+        program.programId
+      );
+
+      try {
+        await program.methods
+          .addCompressedAffiliate("TREE01")
+          .accounts({
+            config: configPda,
+            merchantPool: merchantPoolPda1,
+            merchant: merchant.publicKey,
+            affiliateWallet: Keypair.generate().publicKey,
+            treeAuthority,
+            merkleTree: Keypair.generate().publicKey,
+            compressionProgram: new PublicKey("cmtDvXumGCrqC1Age74AVPhSRVXJMd8PJS91L8KbNCK"),
+            noopProgram: new PublicKey("noopb9bkMVfRPU8AsbpTUg8AQkHtKwMYZiFUjNRtMmV"),
+          })
+          .signers([merchant])
+          .rpc();
+
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("AffiliateTreeMismatch");
+        console.log("✓ Rejected compressed affiliate without a tree");
+      }
+    });
+
+    it("Rejects receipt proofs on a pool without a receipt tree", async () => {
+      try {
+        await program.methods
+          .verifySaleReceipt(Array(32).fill(0), {
+            pool: merchantPoolPda1,
+            affiliate: affiliate.publicKey,
+            orderId: "tree-receipt-1",
+            mint: usdcMint,
+            saleAmount: new anchor.BN(10_000_000),
+            commission: new anchor.BN(1_000_000),
+            appliedRateBps: 1000,
+            leafIndex: 0,
+            createdAt: new anchor.BN(0),
+          })
+          .accounts({
+            merchantPool: merchantPoolPda1,
+            receiptTree: Keypair.generate().publicKey,
+            compressionProgram: new PublicKey("cmtDvXumGCrqC1Age74AVPhSRVXJMd8PJS91L8KbNCK"),
+          })
+          .rpc();
+
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("ReceiptTreeMismatch");
+        console.log("✓ Rejected receipt proof without a receipt tree");
+      }
+    });
+  });
+
+  describe("Compressed Payouts", () => {
+    const compressedTokenProgram = new PublicKey("cTokenmWW8bLPjZEBAUgYy3zKxQZW6VKi7bqNFEVv3m");
+    const lightSystemProgram = new PublicKey("SySTEM1eSU2p4BGQfQpimFEWWSC1XDFeun3Nqzz3rT7");
+    const accountCompressionProgram = new PublicKey("compr6CUsB5m2jS4Y3831ztGSTnDpnKJTKS95d64XVq");
+    const lightCpiAuthority = PublicKey.findProgramAddressSync([Buffer.from("cpi_authority")], compressedTokenProgram)[0];
+    const tokenPool = () =>
+      PublicKey.findProgramAddressSync([Buffer.from("pool"), usdcMint.toBuffer()], compressedTokenProgram)[0];
+    const sale = (orderId: string) =>
+      program.methods
+        .processSale(saleArgs(20_000_000, orderId))
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          affiliateAccount: affiliatePda1,
+          affiliateWallet: affiliate.publicKey,
+          escrowAuthority: escrowAuthorityPda1,
+          escrowUsdc: escrowUsdc1,
+          affiliateUsdc: affiliateUsdc,
+          saleReceipt: receiptPda(merchantPoolPda1, orderId),
+          usdcMint: usdcMint,
+          authority: backend.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([backend])
+        .rpc();
+    const setCompressedPayouts = (enabled: boolean) =>
+      program.methods
+        .setCompressedPayouts(enabled)
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          affiliateAccount: affiliatePda1,
+          affiliateWallet: affiliate.publicKey,
+        })
+        .signers([affiliate])
+        .rpc();
+    const payOutCompressed = (authority: Keypair, outputStateTree = Keypair.generate().publicKey) =>
+      program.methods
+        .payOutCompressed()
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          escrowAuthority: escrowAuthorityPda1,
+          escrowUsdc: escrowUsdc1,
+          authority: authority.publicKey,
+          compressedTokenProgram,
+          lightCpiAuthority,
+          lightSystemProgram,
+          registeredProgram: PublicKey.findProgramAddressSync(
+            [lightSystemProgram.toBuffer()],
+            accountCompressionProgram
+          )[0],
+          noopProgram: new PublicKey("noopb9bkMVfRPU8AsbpTUg8AQkHtKwMYZiFUjNRtMmV"),
+          accountCompressionAuthority: PublicKey.findProgramAddressSync(
+            [Buffer.from("cpi_authority")],
+            lightSystemProgram
+          )[0],
+          accountCompressionProgram,
+          tokenPool: tokenPool(),
+          outputStateTree,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .remainingAccounts([{ pubkey: affiliatePda1, isSigner: false, isWritable: true }])
+        .signers([authority])
+        .rpc();
+
+    it("Accrues an opted-in affiliate's commissions for compressed payout", async () => {
+      await setCompressedPayouts(true);
+
+      try {
+        await program.methods
+          .setPayoutAccount()
+          .accounts({
+            merchantPool: merchantPoolPda1,
+            affiliateAccount: affiliatePda1,
+            affiliateWallet: affiliate.publicKey,
+            escrowAuthority: escrowAuthorityPda1,
+            payoutUsdc: affiliateUsdc,
+          })
+          .signers([affiliate])
+          .rpc();
+
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("CompressedPayoutsRouted");
+      }
+
+      const balanceBefore = (await getAccount(provider.connection, affiliateUsdc)).amount;
+      const claimableBefore = (await program.account.affiliateAccount.fetch(affiliatePda1)).claimable.toNumber();
+      await sale("compressed-1");
+
+      const balanceAfter = (await getAccount(provider.connection, affiliateUsdc)).amount;
+      const affiliateAccount = await program.account.affiliateAccount.fetch(affiliatePda1);
+      expect(balanceAfter).to.equal(balanceBefore);
+      expect(affiliateAccount.claimable.toNumber()).to.be.greaterThan(claimableBefore);
+      console.log("✓ Commission accrued for compressed payout");
+    });
+
+    it("Only lets the processor pay out compressed commissions", async () => {
+      try {
+        await payOutCompressed(affiliate);
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("Unauthorized");
+        console.log("✓ Rejected compressed payout by the affiliate");
+      }
+    });
+
+    it("Rejects compressed payouts to affiliates that opted out", async () => {
+      await setCompressedPayouts(false);
+
+      try {
+        await payOutCompressed(backend);
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("CompressedPayoutsDisabled");
+      }
+
+      // The accrued commission is still the affiliate's to claim
+      await program.methods
+        .claimCommissions()
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          affiliateAccount: affiliatePda1,
+          affiliateWallet: affiliate.publicKey,
           escrowAuthority: escrowAuthorityPda1,
           escrowUsdc: escrowUsdc1,
+          affiliateUsdc: affiliateUsdc,
           usdcMint: usdcMint,
+          authority: affiliate.publicKey,
           tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([affiliate])
+        .rpc();
+      const affiliateAccount = await program.account.affiliateAccount.fetch(affiliatePda1);
+      expect(affiliateAccount.claimable.toNumber()).to.equal(0);
+      console.log("✓ Opted-out affiliate claimed its accrued commission instead");
+    });
+
+    it("Compresses accrued commissions into a Light state tree", async () => {
+      // Mainnet tree loaded by Anchor.toml, with the Light programs
+      const stateTree = new PublicKey("smt1NamzXdq4AMqS2fS2F1i5KTYPZRhoHgWx38d8WsT");
+      // Register the mint with the compressed-token program, creating the
+      // pool compressed commissions are escrowed in
+      await provider.sendAndConfirm(
+        new Transaction().add(
+          new TransactionInstruction({
+            programId: compressedTokenProgram,
+            keys: [
+              { pubkey: provider.wallet.publicKey, isSigner: true, isWritable: true },
+              { pubkey: tokenPool(), isSigner: false, isWritable: true },
+              { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+              { pubkey: usdcMint, isSigner: false, isWritable: true },
+              { pubkey: TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
+              { pubkey: lightCpiAuthority, isSigner: false, isWritable: false },
+            ],
+            data: createHash("sha256").update("global:create_token_pool").digest().subarray(0, 8),
+          })
+        )
+      );
+      await setCompressedPayouts(true);
+      await sale("compressed-2");
+
+      const claimable = (await program.account.affiliateAccount.fetch(affiliatePda1)).claimable.toNumber();
+      const escrowBefore = (await getAccount(provider.connection, escrowUsdc1)).amount;
+      const treeBefore = (await provider.connection.getAccountInfo(stateTree))!.data;
+
+      await payOutCompressed(backend, stateTree);
+
+      const escrowAfter = (await getAccount(provider.connection, escrowUsdc1)).amount;
+      expect(Number(escrowBefore - escrowAfter)).to.equal(claimable);
+      expect(Number((await getAccount(provider.connection, tokenPool())).amount)).to.equal(claimable);
+      expect((await program.account.affiliateAccount.fetch(affiliatePda1)).claimable.toNumber()).to.equal(0);
+      // The compressed token account was appended as a new leaf
+      const treeAfter = (await provider.connection.getAccountInfo(stateTree))!.data;
+      expect(treeAfter.equals(treeBefore)).to.be.false;
+
+      await setCompressedPayouts(false);
+      console.log("✓ Paid out", claimable, "as a compressed token account");
+    });
+  });
+
+  describe("Confidential Payouts", () => {
+    const setConfidentialPayoutKey = (elgamalPubkey: number[] | null) =>
+      program.methods
+        .setConfidentialPayoutKey(elgamalPubkey)
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          affiliateAccount: affiliatePda1,
+          affiliateWallet: affiliate.publicKey,
+        })
+        .signers([affiliate])
+        .rpc();
+
+    it("Only offers confidential payouts from a confidential escrow", async () => {
+      try {
+        await program.methods
+          .setConfidentialPayouts(true)
+          .accounts({
+            merchantPool: merchantPoolPda1,
+            escrowAuthority: escrowAuthorityPda1,
+            escrowUsdc: escrowUsdc1,
+            merchant: merchant.publicKey,
+          })
+          .signers([merchant])
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("EscrowNotConfidential");
+      }
+
+      const pool = await program.account.merchantPool.fetch(merchantPoolPda1);
+      expect(pool.confidentialPayouts).to.equal(0);
+      console.log("✓ Rejected confidential payouts from a plain SPL escrow");
+    });
+
+    it("Rejects affiliate opt-ins while the pool doesn't offer them", async () => {
+      try {
+        await setConfidentialPayoutKey(Array.from(Keypair.generate().publicKey.toBytes()));
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("ConfidentialPayoutsDisabled");
+      }
+
+      await setConfidentialPayoutKey(null);
+      const affiliateAccount = await program.account.affiliateAccount.fetch(affiliatePda1);
+      expect(affiliateAccount.elgamalPubkey).to.be.null;
+      console.log("✓ Opt-in refused; opting out is always allowed");
+    });
+  });
+
+  // The upgrade guard can't be removed, so it's created after every other
+  // test that changes the config directly
+  describe("Regional Partitions", () => {
+    const regionPda = () =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("region"), merchantPoolPda1.toBuffer(), Buffer.from("DE")],
+        program.programId
+      )[0];
+    const regionSale = (orderId: string) =>
+      program.methods
+        .processSaleInRegion(saleArgs(1_000_000, orderId))
+        .accounts({
+          sale: {
+            merchantPool: merchantPoolPda1,
+            affiliateAccount: affiliatePda1,
+            affiliateWallet: affiliate.publicKey,
+            escrowAuthority: escrowAuthorityPda1,
+            escrowUsdc: escrowUsdc1,
+            affiliateUsdc: affiliateUsdc,
+            saleReceipt: receiptPda(merchantPoolPda1, orderId),
+            usdcMint: usdcMint,
+            authority: backend.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+          },
+          region: regionPda(),
+        })
+        .signers([backend])
+        .rpc();
+    const updateRegion = (budget: number, rateModifierBps: number) =>
+      program.methods
+        .updateRegion(new anchor.BN(budget), rateModifierBps)
+        .accounts({ merchantPool: merchantPoolPda1, region: regionPda(), merchant: merchant.publicKey })
+        .signers([merchant])
+        .rpc();
+
+    it("Pays regional sales at the region's modified rate against its budget", async () => {
+      try {
+        await program.methods
+          .createRegion(Array.from(Buffer.from("de")), new anchor.BN(1_500_000), 10000)
+          .accounts({
+            merchantPool: merchantPoolPda1,
+            region: PublicKey.findProgramAddressSync(
+              [Buffer.from("region"), merchantPoolPda1.toBuffer(), Buffer.from("de")],
+              program.programId
+            )[0],
+            merchant: merchant.publicKey,
+            rentPayer: merchant.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([merchant])
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("InvalidRegion");
+      }
+
+      // A full-rate modifier clamps every regional sale to 100%
+      await program.methods
+        .createRegion(Array.from(Buffer.from("DE")), new anchor.BN(1_500_000), 10000)
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          region: regionPda(),
+          merchant: merchant.publicKey,
+          rentPayer: merchant.publicKey,
+          systemProgram: SystemProgram.programId,
         })
         .signers([merchant])
         .rpc();
 
-      const after = (await getAccount(provider.connection, escrowUsdc1)).amount;
-      expect(Number(after - before)).to.equal(DEPOSIT);
-      console.log("✓ Deposited to Pool 1:", DEPOSIT / 1_000_000, "USDC");
+      await regionSale("region-1");
+      const receipt = await program.account.saleReceipt.fetch(receiptPda(merchantPoolPda1, "region-1"));
+      expect(receipt.appliedRateBps).to.equal(10000);
+      const region = await program.account.regionPartition.fetch(regionPda());
+      expect(region.spent.toNumber()).to.equal(receipt.commission.toNumber());
+      expect(region.totalVolume.toNumber()).to.equal(1_000_000);
+      expect(region.salesCount.toNumber()).to.equal(1);
+      console.log("✓ Regional sale paid at the modified rate");
+    });
+
+    it("Rejects regional sales beyond the region's budget until it is raised", async () => {
+      try {
+        await regionSale("region-2");
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("RegionBudgetExceeded");
+      }
+
+      await updateRegion(3_000_000, 0);
+      await regionSale("region-2");
+      const region = await program.account.regionPartition.fetch(regionPda());
+      expect(region.budget.toNumber()).to.equal(3_000_000);
+      expect(region.rateModifierBps).to.equal(0);
+      expect(region.salesCount.toNumber()).to.equal(2);
+      console.log("✓ Region budget enforced and raised");
+    });
+  });
+
+  describe("Abandoned Pool Recovery", () => {
+    it("Tracks pool activity and refuses to recover an active pool", async () => {
+      const pool = await program.account.merchantPool.fetch(merchantPoolPda1);
+      expect(pool.lastActivityAt.toNumber()).to.be.greaterThan(0);
+      expect(pool.abandonmentNoticedAt.toNumber()).to.equal(0);
+
+      try {
+        await program.methods
+          .noticeAbandonedPool()
+          .accounts({ config: configPda, admin: merchant.publicKey, merchantPool: merchantPoolPda1 })
+          .signers([merchant])
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("Unauthorized");
+      }
+
+      try {
+        await program.methods
+          .noticeAbandonedPool()
+          .accounts({ config: configPda, admin: provider.wallet.publicKey, merchantPool: merchantPoolPda1 })
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("PoolNotAbandoned");
+      }
+
+      try {
+        await program.methods
+          .recoverAbandonedPool()
+          .accounts({
+            config: configPda,
+            admin: provider.wallet.publicKey,
+            merchantPool: merchantPoolPda1,
+            merchantUsdc: merchantUsdc,
+            escrowAuthority: escrowAuthorityPda1,
+            escrowUsdc: escrowUsdc1,
+            usdcMint: usdcMint,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("PoolNotAbandoned");
+      }
+      console.log("✓ Active pool can't be noticed or recovered");
     });
+  });
 
-    it("Deposits to pool 2 escrow", async () => {
-      const DEPOSIT = 75_000_000;
-      const before = (await getAccount(provider.connection, escrowUsdc2)).amount;
+  describe("Merchant Recovery", () => {
+    const guardians = [Keypair.generate(), Keypair.generate(), Keypair.generate()];
+    const newMerchant = Keypair.generate();
+    const guardianAccounts = (signers: Keypair[]) =>
+      signers.map((guardian) => ({ pubkey: guardian.publicKey, isSigner: true, isWritable: false }));
+    const propose = (signers: Keypair[]) =>
+      program.methods
+        .proposeMerchantRecovery(newMerchant.publicKey)
+        .accounts({ merchantPool: merchantPoolPda1 })
+        .remainingAccounts(guardianAccounts(signers))
+        .signers(signers)
+        .rpc();
+
+    it("Lets guardians propose a new merchant key that the merchant can veto", async () => {
+      let pool = await program.account.merchantPool.fetch(merchantPoolPda1);
+      expect(pool.creator.toString()).to.equal(merchant.publicKey.toString());
 
       await program.methods
-        .depositEscrow(new anchor.BN(DEPOSIT))
-        .accounts({
-          merchantPool: merchantPoolPda2,
-          merchant: merchant.publicKey,
-          merchantUsdc: merchantUsdc,
-          escrowAuthority: escrowAuthorityPda2,
-          escrowUsdc: escrowUsdc2,
-          usdcMint: usdcMint,
-          tokenProgram: TOKEN_PROGRAM_ID,
-        })
+        .setGuardians(guardians.map((guardian) => guardian.publicKey), 2)
+        .accounts({ merchantPool: merchantPoolPda1, merchant: merchant.publicKey })
         .signers([merchant])
         .rpc();
 
-      const after = (await getAccount(provider.connection, escrowUsdc2)).amount;
-      expect(Number(after - before)).to.equal(DEPOSIT);
-      console.log("✓ Deposited to Pool 2:", DEPOSIT / 1_000_000, "USDC");
-    });
+      try {
+        await propose([guardians[0]]);
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("NotEnoughGuardians");
+      }
 
-    it("Withdraws from pool 1 escrow", async () => {
-      const WITHDRAW = 20_000_000;
-      const before = (await getAccount(provider.connection, merchantUsdc)).amount;
+      await propose([guardians[0], guardians[2]]);
+      pool = await program.account.merchantPool.fetch(merchantPoolPda1);
+      expect(pool.pendingMerchant.toString()).to.equal(newMerchant.publicKey.toString());
+      expect(pool.merchantRecoveryAt.toNumber()).to.be.greaterThan(0);
+
+      try {
+        await program.methods
+          .recoverMerchant(newMerchant.publicKey)
+          .accounts({ merchantPool: merchantPoolPda1 })
+          .remainingAccounts(guardianAccounts([guardians[0], guardians[1]]))
+          .signers([guardians[0], guardians[1]])
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("MerchantRecoveryChallengeActive");
+      }
+
+      await program.methods
+        .vetoMerchantRecovery()
+        .accounts({ merchantPool: merchantPoolPda1, merchant: merchant.publicKey })
+        .signers([merchant])
+        .rpc();
+      pool = await program.account.merchantPool.fetch(merchantPoolPda1);
+      expect(pool.merchant.toString()).to.equal(merchant.publicKey.toString());
+      expect(pool.merchantRecoveryAt.toNumber()).to.equal(0);
 
       await program.methods
-        .withdrawEscrow(new anchor.BN(WITHDRAW))
+        .setGuardians([], 0)
+        .accounts({ merchantPool: merchantPoolPda1, merchant: merchant.publicKey })
+        .signers([merchant])
+        .rpc();
+      console.log("✓ Guardian recovery proposed, held by the challenge window and vetoed");
+    });
+  });
+
+  describe("Payout Splits", () => {
+    const charity = Keypair.generate();
+    let charityUsdc: PublicKey;
+    const setSplits = (weightsBps: number[], tokenAccounts: PublicKey[]) =>
+      program.methods
+        .setPayoutSplits(weightsBps)
         .accounts({
           merchantPool: merchantPoolPda1,
-          merchant: merchant.publicKey,
-          merchantUsdc: merchantUsdc,
+          affiliateAccount: affiliatePda1,
+          affiliateWallet: affiliate.publicKey,
+          escrowAuthority: escrowAuthorityPda1,
+        })
+        .remainingAccounts(tokenAccounts.map((pubkey) => ({ pubkey, isSigner: false, isWritable: false })))
+        .signers([affiliate])
+        .rpc();
+    const sale = (orderId: string, splitAccounts: PublicKey[]) =>
+      program.methods
+        .processSale(saleArgs(20_000_000, orderId))
+        .accounts({
+          merchantPool: merchantPoolPda1,
+          affiliateAccount: affiliatePda1,
+          affiliateWallet: affiliate.publicKey,
           escrowAuthority: escrowAuthorityPda1,
           escrowUsdc: escrowUsdc1,
+          affiliateUsdc: affiliateUsdc,
+          saleReceipt: receiptPda(merchantPoolPda1, orderId),
           usdcMint: usdcMint,
+          authority: backend.publicKey,
           tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
         })
-        .signers([merchant])
+        .remainingAccounts(splitAccounts.map((pubkey) => ({ pubkey, isSigner: false, isWritable: true })))
+        .signers([backend])
         .rpc();
 
-      const after = (await getAccount(provider.connection, merchantUsdc)).amount;
-      expect(Number(after - before)).to.equal(WITHDRAW);
-      console.log("✓ Withdrawn from Pool 1:", WITHDRAW / 1_000_000, "USDC");
+    before(async () => {
+      charityUsdc = (
+        await getOrCreateAssociatedTokenAccount(provider.connection, merchant, usdcMint, charity.publicKey)
+      ).address;
+    });
+
+    it("Rejects splits that don't total 10000 bps", async () => {
+      try {
+        await setSplits([8000, 1000], [affiliateUsdc, charityUsdc]);
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("InvalidPayoutSplits");
+      }
+
+      try {
+        await setSplits([8000, 2000], [affiliateUsdc, escrowUsdc1]);
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("InvalidPayoutAccount");
+      }
+      console.log("✓ Invalid payout splits rejected");
+    });
+
+    it("Splits push payouts between the affiliate's token accounts", async () => {
+      await setSplits([8000, 2000], [affiliateUsdc, charityUsdc]);
+      const affiliateAccount = await program.account.affiliateAccount.fetch(affiliatePda1);
+      expect(affiliateAccount.payoutSplits.map((split: any) => split.weightBps)).to.deep.equal([8000, 2000]);
+
+      try {
+        await sale("split-0", []);
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("PayoutSplitAccountsMismatch");
+      }
+
+      const affiliateBefore = Number((await getAccount(provider.connection, affiliateUsdc)).amount);
+      const charityBefore = Number((await getAccount(provider.connection, charityUsdc)).amount);
+      await sale("split-1", [affiliateUsdc, charityUsdc]);
+      const affiliateGain = Number((await getAccount(provider.connection, affiliateUsdc)).amount) - affiliateBefore;
+      const charityGain = Number((await getAccount(provider.connection, charityUsdc)).amount) - charityBefore;
+      const paid = affiliateGain + charityGain;
+      expect(paid).to.be.greaterThan(0);
+      expect(affiliateGain).to.equal(Math.floor((paid * 8000) / 10000));
+      expect(charityGain).to.equal(paid - affiliateGain);
+
+      await setSplits([], []);
+      expect((await program.account.affiliateAccount.fetch(affiliatePda1)).payoutSplits).to.be.empty;
+      console.log("✓ Payout split 80/20 between personal and charity accounts");
     });
   });
 
-  describe("Deactivate Pool", () => {
-    it("Deactivates pool 2", async () => {
+  describe("Store Credit", () => {
+    const storefrontProgram = Keypair.generate().publicKey;
+    const setRewardMode = (rewardMode: object, rateBps: number, storefront: PublicKey) =>
+      program.methods
+        .setRewardMode(rewardMode as any, rateBps, storefront)
+        .accounts({ merchantPool: merchantPoolPda1, merchant: merchant.publicKey })
+        .signers([merchant])
+        .rpc();
+
+    it("Credits store credit instead of paying commission in store-credit mode", async () => {
+      try {
+        await setRewardMode({ storeCredit: {} }, 0, storefrontProgram);
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("InvalidStoreCreditConfig");
+      }
+
+      // Credit worth 150% of the commission
+      await setRewardMode({ storeCredit: {} }, 15000, storefrontProgram);
+      const creditBefore = (await program.account.affiliateAccount.fetch(affiliatePda1)).storeCredit.toNumber();
+      const affiliateBefore = (await getAccount(provider.connection, affiliateUsdc)).amount;
+      const quote = await program.methods
+        .quoteCommission(new anchor.BN(20_000_000))
+        .accounts({ merchantPool: merchantPoolPda1, affiliateAccount: affiliatePda1 })
+        .view();
+
       await program.methods
-        .deactivatePool()
+        .processSale(saleArgs(20_000_000, "credit-1"))
         .accounts({
-          merchantPool: merchantPoolPda2,
-          merchant: merchant.publicKey,
+          merchantPool: merchantPoolPda1,
+          affiliateAccount: affiliatePda1,
+          affiliateWallet: affiliate.publicKey,
+          escrowAuthority: escrowAuthorityPda1,
+          escrowUsdc: escrowUsdc1,
+          affiliateUsdc: affiliateUsdc,
+          saleReceipt: receiptPda(merchantPoolPda1, "credit-1"),
+          usdcMint: usdcMint,
+          authority: backend.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
         })
-        .signers([merchant])
+        .signers([backend])
         .rpc();
 
-      const poolAccount = await program.account.merchantPool.fetch(merchantPoolPda2);
-      expect(poolAccount.isActive).to.be.false;
-      console.log("✓ Pool 2 deactivated");
+      const receipt = await program.account.saleReceipt.fetch(receiptPda(merchantPoolPda1, "credit-1"));
+      const credit = (await program.account.affiliateAccount.fetch(affiliatePda1)).storeCredit.toNumber();
+      expect(credit - creditBefore).to.equal(Math.floor((receipt.commission.toNumber() * 15000) / 10000));
+      expect(quote.storeCredit.toNumber()).to.equal(credit - creditBefore);
+      expect(quote.affiliatePayout.toNumber()).to.equal(0);
+      expect((await getAccount(provider.connection, affiliateUsdc)).amount).to.equal(affiliateBefore);
+      console.log("✓ Sale rewarded in store credit");
     });
 
-    it("Cannot process sale in deactivated pool", async () => {
+    it("Only lets the storefront program's authority redeem credit", async () => {
+      const impostor = Keypair.generate();
       try {
         await program.methods
-          .processSale(new anchor.BN(50_000_000))
+          .redeemStoreCredit(new anchor.BN(1))
           .accounts({
-            merchantPool: merchantPoolPda2,
-            affiliateAccount: affiliatePda2,
-            affiliateWallet: affiliate2.publicKey,
-            escrowAuthority: escrowAuthorityPda2,
-            escrowUsdc: escrowUsdc2,
-            affiliateUsdc: affiliate2Usdc,
-            usdcMint: usdcMint,
-            authority: backend.publicKey,
-            tokenProgram: TOKEN_PROGRAM_ID,
-            associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
-            systemProgram: SystemProgram.programId,
+            merchantPool: merchantPoolPda1,
+            affiliateAccount: affiliatePda1,
+            affiliateWallet: affiliate.publicKey,
+            storefrontAuthority: impostor.publicKey,
           })
-          .signers([backend])
+          .signers([affiliate, impostor])
           .rpc();
-
         expect.fail("Should have thrown error");
       } catch (error: any) {
-        expect(error.error.errorCode.code).to.equal("PoolInactive");
-        console.log("✓ Cannot process sale in deactivated pool");
+        expect(error.error.errorCode.code).to.equal("Unauthorized");
       }
+
+      await setRewardMode({ token: {} }, 0, PublicKey.default);
+      console.log("✓ Redemption outside the storefront rejected");
     });
   });
 
-  describe("Remove Affiliate", () => {
-    it("Deactivates affiliate in pool 1", async () => {
-      await program.methods
-        .removeAffiliate()
+  describe("Payment Proof", () => {
+    const buyer = Keypair.generate();
+    let buyerUsdc: PublicKey;
+    const nonceReference = (nonce: anchor.BN) =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("payment_nonce"), merchantPoolPda1.toBuffer(), nonce.toArrayLike(Buffer, "le", 8)],
+        program.programId
+      )[0];
+    const paymentNonce = async () => (await program.account.merchantPool.fetch(merchantPoolPda1)).paymentNonce;
+    const payment = (amount: number, reference: PublicKey, nonce: anchor.BN) => {
+      const transfer = createTransferCheckedInstruction(
+        buyerUsdc,
+        usdcMint,
+        merchantUsdc,
+        buyer.publicKey,
+        amount,
+        6
+      );
+      transfer.keys.push({ pubkey: reference, isSigner: false, isWritable: false });
+      transfer.keys.push({ pubkey: nonceReference(nonce), isSigner: false, isWritable: false });
+      return transfer;
+    };
+    const sale = async (orderId: string, reference: PublicKey, paid: number | null, nonce?: anchor.BN) =>
+      program.methods
+        .processSaleWithPaymentProof(saleArgs(5_000_000, orderId), reference)
         .accounts({
-          merchantPool: merchantPoolPda1,
-          affiliateAccount: affiliatePda1,
-          affiliateWallet: affiliate.publicKey,
-          merchant: merchant.publicKey,
+          sale: {
+            merchantPool: merchantPoolPda1,
+            affiliateAccount: affiliatePda1,
+            affiliateWallet: affiliate.publicKey,
+            escrowAuthority: escrowAuthorityPda1,
+            escrowUsdc: escrowUsdc1,
+            affiliateUsdc: affiliateUsdc,
+            saleReceipt: receiptPda(merchantPoolPda1, orderId),
+            usdcMint: usdcMint,
+            authority: backend.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+            instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+          },
+          merchantUsdc: merchantUsdc,
         })
-        .signers([merchant])
+        .preInstructions(paid === null ? [] : [payment(paid, reference, nonce ?? (await paymentNonce()))])
+        .signers(paid === null ? [backend] : [backend, buyer])
         .rpc();
 
-      const affiliateAccount = await program.account.affiliateAccount.fetch(affiliatePda1);
-      expect(affiliateAccount.isActive).to.be.false;
-      console.log("✓ Affiliate deactivated in Pool 1");
+    before(async () => {
+      buyerUsdc = (await getOrCreateAssociatedTokenAccount(provider.connection, merchant, usdcMint, buyer.publicKey))
+        .address;
+      await mintTo(provider.connection, merchant, usdcMint, buyerUsdc, merchant, 20_000_000);
     });
 
-    it("Cannot process sale for deactivated affiliate", async () => {
+    it("Pays commission once the buyer's tagged payment precedes the sale", async () => {
+      const reference = Keypair.generate().publicKey;
+      const merchantBefore = (await getAccount(provider.connection, merchantUsdc)).amount;
+
+      await sale("paid-1", reference, 5_000_000);
+
+      const receipt = await program.account.saleReceipt.fetch(receiptPda(merchantPoolPda1, "paid-1"));
+      expect(receipt.saleAmount.toNumber()).to.equal(5_000_000);
+      expect((await getAccount(provider.connection, merchantUsdc)).amount - merchantBefore).to.equal(BigInt(5_000_000));
+      console.log("✓ Sale settled against its Solana Pay payment");
+    });
+
+    it("Rejects sales without a matching payment", async () => {
+      try {
+        await sale("paid-2", Keypair.generate().publicKey, null);
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("PaymentNotFound");
+      }
+
+      try {
+        await sale("paid-2", Keypair.generate().publicKey, 4_000_000);
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("PaymentMismatch");
+      }
+      console.log("✓ Unpaid and underpaid sales rejected");
+    });
+
+    it("Rejects a payment tagged with a spent nonce", async () => {
+      const spent = (await paymentNonce()).subn(1);
+      try {
+        await sale("paid-2", Keypair.generate().publicKey, 5_000_000, spent);
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("PaymentNonceMismatch");
+      }
+
+      const nonce = await paymentNonce();
+      await sale("paid-2", Keypair.generate().publicKey, 5_000_000, nonce);
+      expect((await paymentNonce()).toNumber()).to.equal(nonce.toNumber() + 1);
+      console.log("✓ Payment replayed against a spent nonce rejected");
+    });
+
+    it("Refuses unproven sales while the pool requires payment proofs", async () => {
+      const setPaymentProofRequired = (required: boolean) =>
+        program.methods
+          .setPaymentProofRequired(required)
+          .accounts({ merchantPool: merchantPoolPda1, merchant: merchant.publicKey })
+          .signers([merchant])
+          .rpc();
+      await setPaymentProofRequired(true);
+
       try {
         await program.methods
-          .processSale(new anchor.BN(50_000_000))
+          .processSale(saleArgs(5_000_000, "paid-3"))
           .accounts({
             merchantPool: merchantPoolPda1,
             affiliateAccount: affiliatePda1,
@@ -535,6 +5341,7 @@ describe("USDC Affiliate Escrow - Multi Pool", () => {
             escrowAuthority: escrowAuthorityPda1,
             escrowUsdc: escrowUsdc1,
             affiliateUsdc: affiliateUsdc,
+            saleReceipt: receiptPda(merchantPoolPda1, "paid-3"),
             usdcMint: usdcMint,
             authority: backend.publicKey,
             tokenProgram: TOKEN_PROGRAM_ID,
@@ -543,11 +5350,93 @@ describe("USDC Affiliate Escrow - Multi Pool", () => {
           })
           .signers([backend])
           .rpc();
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("PaymentProofRequired");
+      }
+
+      await sale("paid-3", Keypair.generate().publicKey, 5_000_000);
+      const receipt = await program.account.saleReceipt.fetch(receiptPda(merchantPoolPda1, "paid-3"));
+      expect(receipt.saleAmount.toNumber()).to.equal(5_000_000);
 
+      await setPaymentProofRequired(false);
+      console.log("✓ Only the proven sale settled while proofs were required");
+    });
+  });
+
+  describe("Upgrade Guard", () => {
+    const [upgradeGuardPda] = PublicKey.findProgramAddressSync([Buffer.from("upgrade_guard")], program.programId);
+    const treasury = Keypair.generate().publicKey;
+    const adminAccounts = () => ({
+      config: configPda,
+      upgradeGuard: upgradeGuardPda,
+      admin: provider.wallet.publicKey,
+    });
+    const queue = (change: any) => program.methods.queueConfigChange(change).accounts(adminAccounts()).rpc();
+    const execute = () => program.methods.executeConfigChange().accounts(adminAccounts()).rpc();
+
+    it("Records the intended program hash and timelock", async () => {
+      const programHash = Array.from(createHash("sha256").update("redio-contract build").digest());
+      await program.methods
+        .initUpgradeGuard(programHash, new anchor.BN(2))
+        .accounts({ ...adminAccounts(), systemProgram: SystemProgram.programId })
+        .rpc();
+
+      const guard = await program.account.upgradeGuard.fetch(upgradeGuardPda);
+      expect(guard.programHash).to.deep.equal(programHash);
+      expect(guard.timelockSecs.toNumber()).to.equal(2);
+      expect(guard.pendingChange).to.be.null;
+      console.log("✓ Upgrade guard created with a 2s timelock");
+    });
+
+    it("Rejects direct config changes once guarded", async () => {
+      try {
+        await program.methods.setTreasury(treasury).accounts(adminAccounts()).rpc();
         expect.fail("Should have thrown error");
       } catch (error: any) {
-        expect(error.error.errorCode.code).to.equal("AffiliateInactive");
-        console.log("✓ Cannot process sale for deactivated affiliate");
+        expect(error.error.errorCode.code).to.equal("ConfigChangeTimelocked");
+        console.log("✓ Direct treasury change rejected");
+      }
+    });
+
+    it("Executes a queued change only after its timelock", async () => {
+      await queue({ treasury: { treasury } });
+      const guard = await program.account.upgradeGuard.fetch(upgradeGuardPda);
+      expect(guard.pendingChange.treasury.treasury.toBase58()).to.equal(treasury.toBase58());
+
+      try {
+        await queue({ commissionRateBand: { minCommissionRateBps: 0, maxCommissionRateBps: 10000 } });
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("ConfigChangePending");
+      }
+      try {
+        await execute();
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("TimelockNotElapsed");
+      }
+
+      await new Promise((resolve) => setTimeout(resolve, 3000));
+      await execute();
+      expect((await program.account.programConfig.fetch(configPda)).treasury.toBase58()).to.equal(treasury.toBase58());
+      expect((await program.account.upgradeGuard.fetch(upgradeGuardPda)).pendingChange).to.be.null;
+      console.log("✓ Treasury change executed after the timelock");
+    });
+
+    it("Cancels a queued change", async () => {
+      await queue({ timelock: { timelockSecs: new anchor.BN(60) } });
+      await program.methods.cancelConfigChange().accounts(adminAccounts()).rpc();
+
+      const guard = await program.account.upgradeGuard.fetch(upgradeGuardPda);
+      expect(guard.pendingChange).to.be.null;
+      expect(guard.timelockSecs.toNumber()).to.equal(2);
+      try {
+        await execute();
+        expect.fail("Should have thrown error");
+      } catch (error: any) {
+        expect(error.error.errorCode.code).to.equal("NoPendingConfigChange");
+        console.log("✓ Cancelled timelock change left nothing to execute");
       }
     });
   });